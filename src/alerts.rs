@@ -0,0 +1,155 @@
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::FilterStore;
+
+/// Saturation beyond which a filter is reported by a [`SaturationMonitor`]; either threshold may
+/// be unset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct AlertThresholds {
+    /// Share of the bits set, between 0 and 1; a filter sized for its items sets about half of
+    /// its bits once full.
+    pub fill_ratio: Option<f64>,
+    /// Probability that an item never inserted is reported present, given the bits set now.
+    pub false_positive_rate: Option<f64>,
+}
+
+/// A filter found beyond the [thresholds](AlertThresholds) of a [`SaturationMonitor`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SaturationAlert {
+    /// Namespace of the filter, or `None` for the default namespace.
+    pub namespace: Option<String>,
+    pub filter: String,
+    pub fill_ratio: f64,
+    pub false_positive_rate: f64,
+    /// The thresholds the filter was checked against.
+    pub thresholds: AlertThresholds,
+}
+
+/// Watches the saturation of the filters of a store, in all namespaces: overfull filters silently
+/// report more and more false positives.
+///
+/// Each filter is reported at most once per `cooldown`, for as long as it stays beyond the
+/// thresholds.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{AlertThresholds, FilterSpec, FilterStore, SaturationMonitor};
+/// use std::time::{Duration, Instant};
+///
+/// let store = FilterStore::new();
+/// let spec = FilterSpec {
+///     name: "login_attempts".to_string(),
+///     item_count: 10,
+///     false_positive_rate: Some(0.01),
+///     ..Default::default()
+/// };
+/// store.create(spec).unwrap();
+/// for i in 0..100 {
+///     store.insert("login_attempts", format!("user{i}").as_bytes()).unwrap();
+/// }
+///
+/// let thresholds = AlertThresholds {
+///     fill_ratio: Some(0.6),
+///     false_positive_rate: None,
+/// };
+/// let monitor = SaturationMonitor::new(thresholds, Duration::from_secs(3600));
+/// let now = Instant::now();
+/// let alerts = monitor.check(&store, now);
+/// assert_eq!(alerts[0].filter, "login_attempts");
+///
+/// // Not reported again until the cooldown elapses
+/// assert!(monitor.check(&store, now).is_empty());
+/// ```
+#[derive(Debug)]
+pub struct SaturationMonitor {
+    thresholds: RwLock<AlertThresholds>,
+    /// Time before a filter still beyond the thresholds is reported again.
+    pub cooldown: Duration,
+    reported: Mutex<HashMap<(Option<String>, String), Instant>>,
+}
+
+impl SaturationMonitor {
+    /// Creates a monitor of the given thresholds, reporting each filter at most once per
+    /// `cooldown`.
+    pub fn new(thresholds: AlertThresholds, cooldown: Duration) -> Self {
+        SaturationMonitor {
+            thresholds: RwLock::new(thresholds),
+            cooldown,
+            reported: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the current thresholds.
+    pub fn thresholds(&self) -> AlertThresholds {
+        *self.thresholds.read()
+    }
+
+    /// Changes the thresholds, e.g. on reload of the configuration.
+    pub fn set_thresholds(&self, thresholds: AlertThresholds) {
+        *self.thresholds.write() = thresholds;
+    }
+
+    /// Checks every filter of `store` and of its namespaces, returning those beyond the
+    /// thresholds and not reported within the cooldown.
+    ///
+    /// The filters are locked one at a time, as counting the bits set takes a while for large
+    /// filters.
+    pub fn check(&self, store: &FilterStore, now: Instant) -> Vec<SaturationAlert> {
+        let thresholds = self.thresholds();
+        if thresholds == AlertThresholds::default() {
+            return Vec::new();
+        }
+        let namespaces: Vec<_> = store
+            .namespaces
+            .read()
+            .iter()
+            .map(|(name, namespace)| (Some(name.clone()), namespace.clone()))
+            .collect();
+        let mut alerts = Vec::new();
+        let stores = std::iter::once((None, store)).chain(
+            namespaces
+                .iter()
+                .map(|(name, namespace)| (name.clone(), &**namespace)),
+        );
+        for (namespace, store) in stores {
+            // Evicted filters are not reloaded to be checked
+            for info in store.list().into_iter().filter(|info| !info.evicted) {
+                // The filter may have been deleted since it was listed
+                let Ok(stats) = store.filter_stats(&info.name) else {
+                    continue;
+                };
+                let beyond = thresholds.fill_ratio.is_some_and(|t| stats.fill_ratio > t)
+                    || thresholds
+                        .false_positive_rate
+                        .is_some_and(|t| stats.false_positive_rate > t);
+                if beyond {
+                    alerts.push(SaturationAlert {
+                        namespace: namespace.clone(),
+                        filter: info.name,
+                        fill_ratio: stats.fill_ratio,
+                        false_positive_rate: stats.false_positive_rate,
+                        thresholds,
+                    });
+                }
+            }
+        }
+
+        let mut reported = self.reported.lock();
+        reported.retain(|_, at| now.duration_since(*at) < self.cooldown);
+        alerts.retain(|alert| {
+            let key = (alert.namespace.clone(), alert.filter.clone());
+            if reported.contains_key(&key) {
+                return false;
+            }
+            reported.insert(key, now);
+            true
+        });
+        alerts
+    }
+}
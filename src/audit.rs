@@ -0,0 +1,116 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+/// Number of the most recent entries kept in memory, for [`AuditLog::entries`].
+const MAX_RECENT_ENTRIES: usize = 10_000;
+
+/// A change requested of the service, as audited.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct AuditEntry {
+    /// When the request completed, as an RFC 3339 timestamp in UTC.
+    pub timestamp: String,
+    /// Id of the request, as logged.
+    pub request_id: String,
+    /// Who made the request (see [`Access::caller`](crate::Access::caller)), or `None` if the
+    /// service requires no credentials, or the request had none valid.
+    pub caller: Option<String>,
+    pub method: String,
+    pub path: String,
+    /// Namespace of the request, or `None` for the default namespace.
+    pub namespace: Option<String>,
+    /// Names of the filters the request was on, if any.
+    pub filters: Vec<String>,
+    /// Status of the response; the change was made only if it is a success.
+    pub status: u16,
+}
+
+/// Audit log of the changes requested of the service: every create, insert (one entry per
+/// batch), clear, merge, delete and other change, made or refused.
+///
+/// The most recent entries are kept in memory; with [`AuditLog::open`], every entry is also
+/// appended to a file, as a JSON line.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{AuditEntry, AuditLog};
+///
+/// let audit = AuditLog::default();
+/// let entry = AuditEntry {
+///     timestamp: "2026-10-14T07:16:23.839Z".to_string(),
+///     request_id: "req-42".to_string(),
+///     caller: Some("admin".to_string()),
+///     method: "PUT".to_string(),
+///     path: "/filters/login_attempts/clear".to_string(),
+///     namespace: None,
+///     filters: vec!["login_attempts".to_string()],
+///     status: 200,
+/// };
+/// audit.record(entry.clone()).unwrap();
+///
+/// assert_eq!(audit.entries(Some("login_attempts"), None, 10), [entry]);
+/// assert!(audit.entries(Some("emails"), None, 10).is_empty());
+/// ```
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+    recent: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// Creates an audit log appending to the file at `path`, created if missing.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog {
+            file: Some(Mutex::new(file)),
+            recent: Mutex::default(),
+        })
+    }
+
+    /// Records an entry, failing if it cannot be appended to the file of the log; it is kept in
+    /// memory either way.
+    pub fn record(&self, entry: AuditEntry) -> io::Result<()> {
+        let written = match &self.file {
+            Some(file) => {
+                let mut line = serde_json::to_vec(&entry)?;
+                line.push(b'\n');
+                // One write per line, so that concurrent entries never interleave
+                file.lock().write_all(&line)
+            }
+            None => Ok(()),
+        };
+        let mut recent = self.recent.lock();
+        if recent.len() == MAX_RECENT_ENTRIES {
+            recent.pop_front();
+        }
+        recent.push_back(entry);
+        written
+    }
+
+    /// Returns the last `limit` entries kept in memory, oldest first, on the filter `filter` and
+    /// made by `caller`, if given.
+    pub fn entries(
+        &self,
+        filter: Option<&str>,
+        caller: Option<&str>,
+        limit: usize,
+    ) -> Vec<AuditEntry> {
+        let recent = self.recent.lock();
+        let mut entries: Vec<AuditEntry> = recent
+            .iter()
+            .rev()
+            .filter(|e| filter.is_none_or(|filter| e.filters.iter().any(|f| f == filter)))
+            .filter(|e| caller.is_none_or(|caller| e.caller.as_deref() == Some(caller)))
+            .take(limit)
+            .cloned()
+            .collect();
+        entries.reverse();
+        entries
+    }
+}
@@ -0,0 +1,365 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use uuid::Uuid;
+
+use crate::{store::new_id, wal::Operation, Error, FilterStore};
+
+/// Prefix of the secrets of API keys, telling them apart from other credentials.
+const SECRET_PREFIX: &str = "bsk_";
+
+/// What an API key allows; each scope allows everything the previous ones do.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::KeyScope;
+///
+/// assert!(KeyScope::ReadWrite.allows(KeyScope::Read));
+/// assert!(!KeyScope::Read.allows(KeyScope::ReadWrite));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "What an API key allows.")
+)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyScope {
+    /// Lookups, lists and downloads; nothing is changed.
+    Read,
+    /// Everything but the administration endpoints.
+    #[default]
+    ReadWrite,
+    /// Everything, including minting and revoking keys.
+    Admin,
+}
+
+impl fmt::Display for KeyScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyScope::Read => "read",
+            KeyScope::ReadWrite => "read_write",
+            KeyScope::Admin => "admin",
+        })
+    }
+}
+
+impl KeyScope {
+    /// Returns `true` if a key of this scope may make a request requiring `required`.
+    pub fn allows(self, required: KeyScope) -> bool {
+        self >= required
+    }
+}
+
+/// Who makes a request, and what it may reach: a scope, limited to some namespaces and filters.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{Access, KeyScope};
+///
+/// let access = Access {
+///     caller: "key:ingest".to_string(),
+///     scope: KeyScope::ReadWrite,
+///     namespaces: vec!["payments".to_string()],
+///     filters: vec!["cards_*".to_string()],
+/// };
+/// let filters = ["cards_eu".to_string()];
+/// assert!(access.check(KeyScope::ReadWrite, Some("payments"), Some(&filters)).is_ok());
+/// assert!(access.check(KeyScope::ReadWrite, None, Some(&filters)).is_err());
+/// assert!(access.check(KeyScope::Read, Some("payments"), None).is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Access {
+    /// Who the credentials belong to, as [audited](crate::AuditLog): `admin` for the admin key,
+    /// `key:<id>` for an API key, and `token:<subject>` for a bearer token.
+    pub caller: String,
+    pub scope: KeyScope,
+    /// Namespaces the requests may be made in; all, including the default one, if empty.
+    pub namespaces: Vec<String>,
+    /// Filters the requests may be made on, by name, or by prefix with a trailing `*`; all, as
+    /// well as the requests not on a filter, if empty.
+    pub filters: Vec<String>,
+}
+
+impl Access {
+    /// Access of `caller` to everything allowed by `scope`.
+    pub fn unrestricted(caller: &str, scope: KeyScope) -> Self {
+        Access {
+            caller: caller.to_string(),
+            scope,
+            namespaces: Vec::new(),
+            filters: Vec::new(),
+        }
+    }
+
+    /// Checks that a request requiring the scope `required` may be made in `namespace` (`None`
+    /// for the default namespace) on `filters`, given by name (`None` for a request not on given
+    /// filters, e.g. a list), failing with [`Error::Forbidden`] otherwise.
+    pub fn check(
+        &self,
+        required: KeyScope,
+        namespace: Option<&str>,
+        filters: Option<&[String]>,
+    ) -> Result<(), Error> {
+        if !self.scope.allows(required) {
+            return Err(Error::Forbidden(format!(
+                "The credentials do not allow this request, which requires the '{required}' scope"
+            )));
+        }
+        if !self.namespaces.is_empty() {
+            let allowed = namespace.is_some_and(|ns| self.namespaces.iter().any(|n| n == ns));
+            if !allowed {
+                let namespace = namespace.map_or("default".to_string(), |ns| format!("'{ns}'"));
+                return Err(Error::Forbidden(format!(
+                    "The credentials do not allow requests in the {namespace} namespace"
+                )));
+            }
+        }
+        match filters {
+            None if !self.filters.is_empty() => Err(Error::Forbidden(
+                "The credentials only allow requests on their filters".to_string(),
+            )),
+            None => Ok(()),
+            Some(filters) => self.check_filters(filters),
+        }
+    }
+
+    /// Checks that requests may be made on `filters`, given by name, e.g. those a request names
+    /// in its body or query besides its path, failing with [`Error::Forbidden`] otherwise.
+    pub fn check_filters(&self, filters: &[String]) -> Result<(), Error> {
+        let matches = |name: &str| {
+            self.filters.is_empty()
+                || self
+                    .filters
+                    .iter()
+                    .any(|pattern| match pattern.strip_suffix('*') {
+                        Some(prefix) => name.starts_with(prefix),
+                        None => name == pattern,
+                    })
+        };
+        match filters.iter().find(|name| !matches(name)) {
+            Some(name) => Err(Error::Forbidden(format!(
+                "The credentials do not allow requests on filter '{name}'"
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Specification of an API key to mint.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Specification of an API key to mint.")
+)]
+pub struct KeySpec {
+    #[serde(default)]
+    pub scope: KeyScope,
+    pub description: Option<String>,
+    /// Namespaces the key is limited to; see [`Access::namespaces`].
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    /// Filters the key is limited to; see [`Access::filters`].
+    #[serde(default)]
+    pub filters: Vec<String>,
+}
+
+/// An API key as stored: only a hash of its secret is kept.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub(crate) struct ApiKey {
+    pub(crate) id: String,
+    pub(crate) scope: KeyScope,
+    #[serde(default)]
+    pub(crate) description: Option<String>,
+    #[serde(default)]
+    pub(crate) namespaces: Vec<String>,
+    #[serde(default)]
+    pub(crate) filters: Vec<String>,
+    pub(crate) created_at: u64,
+    /// SHA-256 of the secret, in hexadecimal.
+    pub(crate) secret_hash: String,
+}
+
+impl ApiKey {
+    fn info(&self) -> ApiKeyInfo {
+        ApiKeyInfo {
+            id: self.id.clone(),
+            scope: self.scope,
+            description: self.description.clone(),
+            namespaces: self.namespaces.clone(),
+            filters: self.filters.clone(),
+            created_at: self.created_at,
+        }
+    }
+
+    fn access(&self) -> Access {
+        Access {
+            caller: format!("key:{}", self.id),
+            scope: self.scope,
+            namespaces: self.namespaces.clone(),
+            filters: self.filters.clone(),
+        }
+    }
+}
+
+/// Summary of an API key, as listed by [`FilterStore::list_api_keys`]; the secret cannot be
+/// retrieved after the key is created.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Summary of an API key.")
+)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub scope: KeyScope,
+    pub description: Option<String>,
+    pub namespaces: Vec<String>,
+    pub filters: Vec<String>,
+    /// When the key was created, in seconds since the Unix epoch.
+    pub created_at: u64,
+}
+
+impl FilterStore {
+    /// Mints an API key, returning its summary and its secret, which is not stored and cannot be
+    /// retrieved later.
+    ///
+    /// Keys are saved and logged with the filters. While a command of a cluster is served, the
+    /// secret is that generated by the node the command was sent to, which the secret returned,
+    /// empty, is left to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterStore, KeyScope, KeySpec};
+    ///
+    /// let store = FilterStore::default();
+    /// let (key, secret) = store
+    ///     .create_api_key(KeySpec {
+    ///         scope: KeyScope::Read,
+    ///         description: Some("dashboards".to_string()),
+    ///         ..Default::default()
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(store.authenticate(&secret).unwrap().scope, KeyScope::Read);
+    ///
+    /// store.revoke_api_key(&key.id).unwrap();
+    /// assert_eq!(store.authenticate(&secret), None);
+    /// ```
+    pub fn create_api_key(&self, spec: KeySpec) -> Result<(ApiKeyInfo, String), Error> {
+        let KeySpec {
+            scope,
+            description,
+            namespaces,
+            filters,
+        } = spec;
+        if let Some(pattern) = filters
+            .iter()
+            .find(|p| p.trim_end_matches('*').contains('*'))
+        {
+            return Err(Error::InvalidParameters(format!(
+                "Invalid filter pattern '{pattern}': only a trailing '*' is supported"
+            )));
+        }
+        // In a cluster, the node the request was sent to generates the secret, and commits its
+        // hash only, for no other node to see it
+        #[cfg(feature = "cluster")]
+        let committed = crate::cluster::committed_secret_hash();
+        #[cfg(not(feature = "cluster"))]
+        let committed = None;
+        let (secret, secret_hash) = match committed {
+            Some(hash) => (String::new(), hash),
+            None => {
+                let secret = new_secret();
+                let hash = hash_secret(&secret);
+                (secret, hash)
+            }
+        };
+        let key = ApiKey {
+            id: new_id().to_string(),
+            scope,
+            description,
+            namespaces,
+            filters,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            secret_hash,
+        };
+        let mut keys = self.api_keys.write();
+        self.journal.record(Operation::CreateKey {
+            name: &key.id,
+            key: encode_keys(std::slice::from_ref(&key)),
+        })?;
+        let info = key.info();
+        keys.insert(key.id.clone(), key);
+        Ok((info, secret))
+    }
+
+    /// Revokes an API key, given its id.
+    pub fn revoke_api_key(&self, id: &str) -> Result<(), Error> {
+        let mut keys = self.api_keys.write();
+        if !keys.contains_key(id) {
+            return Err(Error::ApiKeyNotFound(id.to_string()));
+        }
+        self.journal.record(Operation::RevokeKey { name: id })?;
+        keys.remove(id);
+        Ok(())
+    }
+
+    /// Lists all API keys, oldest first.
+    pub fn list_api_keys(&self) -> Vec<ApiKeyInfo> {
+        let mut keys: Vec<ApiKeyInfo> = self.api_keys.read().values().map(ApiKey::info).collect();
+        keys.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        keys
+    }
+
+    /// Returns what the API key with this secret may access, or `None` if there is no such key.
+    pub fn authenticate(&self, secret: &str) -> Option<Access> {
+        let hash = hash_secret(secret);
+        self.api_keys
+            .read()
+            .values()
+            .find(|key| constant_time_eq(key.secret_hash.as_bytes(), hash.as_bytes()))
+            .map(ApiKey::access)
+    }
+}
+
+/// Encodes API keys, as a JSON array.
+pub(crate) fn encode_keys(keys: &[ApiKey]) -> Vec<u8> {
+    serde_json::to_vec(keys).unwrap_or_default()
+}
+
+/// Decodes API keys encoded by [`encode_keys`].
+pub(crate) fn decode_keys(bytes: &[u8]) -> Result<Vec<ApiKey>, Error> {
+    serde_json::from_slice(bytes)
+        .map_err(|error| Error::InvalidParameters(format!("Invalid API keys: {error}")))
+}
+
+/// Compares two byte strings in a time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Generates the secret of a key, from the random generator of the operating system: never from
+/// the seed of the ids of a cluster command, from which the ids it derives, which are public,
+/// would tell the secret.
+pub(crate) fn new_secret() -> String {
+    let random = || Uuid::new_v4().simple().to_string();
+    format!("{SECRET_PREFIX}{}{}", random(), random())
+}
+
+/// Returns the SHA-256 of a secret, in hexadecimal.
+pub(crate) fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -0,0 +1,725 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    events::Change,
+    persist::{
+        file_name, files, metadata_path, snapshot_path, to_snapshot, write_metadata,
+        write_snapshot, FilterMetadata, FILTER_EXTENSION,
+    },
+    store::{forget_filter, FilterContainer, FilterEntry},
+    wal::Operation,
+    BloomFilter, Error, FilterInfo, FilterKind, FilterSnapshot, FilterStore, RecentFilter, Storage,
+};
+
+/// Number of evictions kept for [`FilterStore::memory_stats`].
+const RECENT_EVICTIONS: usize = 100;
+
+/// A limit on the memory used by the bits of the filters of a store.
+///
+/// Once the filters use more than `max_bytes`, the least recently used filters are evicted
+/// (deleted) until they fit again; those saved to `persist_dir` are reloaded when next used, in
+/// turn evicting others. Memory-mapped filters do not count towards the budget, as the
+/// kernel pages their bits in and out as needed.
+#[derive(Clone, Debug)]
+pub struct MemoryBudget {
+    pub max_bytes: usize,
+    /// Directory the evicted filters are saved to, as [filter files](FilterSnapshot::encode),
+    /// before they are removed, and reloaded from.
+    pub persist_dir: Option<PathBuf>,
+}
+
+/// Limits on what clients may allocate in a store, so that no client (e.g. the tenant of a
+/// namespace) can take the memory of the whole service.
+///
+/// Unlike the [`MemoryBudget`], which makes room by evicting filters, quotas reject the changes
+/// exceeding them: creating a filter too large, or one too many, fails, as do inserts into a
+/// scalable filter that has outgrown them. Filters loaded from a data directory, replayed
+/// from the write-ahead log or replicated from a primary are never rejected.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{FilterSpec, FilterStore, Quotas};
+///
+/// let store = FilterStore::new();
+/// let quotas = Quotas {
+///     max_filters: Some(1),
+///     max_item_count: Some(1_000_000),
+///     ..Default::default()
+/// };
+/// store.set_quotas(quotas, Default::default());
+///
+/// let spec = |name: &str, item_count| FilterSpec {
+///     name: name.to_string(),
+///     item_count,
+///     false_positive_rate: Some(0.01),
+///     ..Default::default()
+/// };
+/// let error = store.create(spec("huge", 10_000_000_000)).unwrap_err();
+/// assert_eq!(error.code(), "filter_too_large");
+/// store.create(spec("sessions", 1000)).unwrap();
+/// let error = store.create(spec("logins", 1000)).unwrap_err();
+/// assert_eq!(error.code(), "quota_exceeded");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quotas {
+    /// Maximum number of filters.
+    pub max_filters: Option<usize>,
+    /// Maximum number of items a filter may be sized for.
+    pub max_item_count: Option<usize>,
+    /// Maximum bytes a filter may take on the heap.
+    pub max_filter_bytes: Option<usize>,
+    /// Maximum bytes all the filters may take on the heap, together.
+    pub max_total_bytes: Option<usize>,
+}
+
+impl Quotas {
+    /// Fails if a filter sized for `item_count` items, taking `bytes` on the heap, is larger than
+    /// a filter may be.
+    fn check_filter(&self, name: &str, item_count: usize, bytes: usize) -> Result<(), Error> {
+        if let Some(max) = self.max_item_count.filter(|&max| item_count > max) {
+            return Err(Error::FilterTooLarge(format!(
+                "Filter '{name}' is sized for {item_count} items, more than the quota of {max} items"
+            )));
+        }
+        if let Some(max) = self.max_filter_bytes.filter(|&max| bytes > max) {
+            return Err(Error::FilterTooLarge(format!(
+                "Filter '{name}' needs {bytes} bytes, more than the quota of {max} bytes"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The scalable filters grown beyond the [`Quotas`], by name, with the quota in bytes they
+/// reached.
+pub(crate) type Outgrown = BTreeMap<String, usize>;
+
+/// Fails if the filter `name` is among the `outgrown` ones.
+pub(crate) fn check_outgrown(outgrown: &Outgrown, name: &str) -> Result<(), Error> {
+    match outgrown.get(name) {
+        Some(max) => Err(Error::QuotaExceeded(format!(
+            "Filter '{name}' cannot grow beyond the quota of {max} bytes"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// A filter evicted to keep the store within its [`MemoryBudget`].
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "A filter evicted to keep the store within its memory budget.")
+)]
+pub struct Eviction {
+    pub name: String,
+    pub memory_usage_bytes: usize,
+    /// Whether the filter was saved to the [persist directory](MemoryBudget::persist_dir).
+    pub persisted: bool,
+    /// When the filter was evicted, in seconds since the Unix epoch.
+    pub evicted_at: u64,
+}
+
+/// Memory used by the filters of a store, as reported by [`FilterStore::memory_stats`].
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Memory used by the filters of a store.")
+)]
+pub struct MemoryStats {
+    pub memory_usage_bytes: usize,
+    pub max_memory_bytes: Option<usize>,
+    /// Number of filters evicted since the store was created.
+    pub evictions: u64,
+    /// Number of evicted filters reloaded since the store was created.
+    pub reloads: u64,
+    /// The last evictions, most recent last.
+    pub recent_evictions: Vec<Eviction>,
+}
+
+/// Memory used by a filter, as reported by [`FilterStore::memory_report`].
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Memory used by a filter.")
+)]
+pub struct FilterMemory {
+    /// Namespace of the filter; none for the default namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    pub name: String,
+    pub storage: Storage,
+    /// Bytes of the bits and counters on the heap, as counted by the memory budget.
+    pub memory_usage_bytes: usize,
+    /// Bytes of the memory-mapped bits, which the kernel pages in and out as needed.
+    pub mapped_bytes: usize,
+}
+
+/// Memory used by a store and its namespaces, as reported by [`FilterStore::memory_report`].
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Memory used by a store and its namespaces.")
+)]
+pub struct MemoryReport {
+    /// Every filter, the largest first.
+    pub filters: Vec<FilterMemory>,
+    /// Bytes of the filters on the heap.
+    pub filter_bytes: usize,
+    /// Bytes of the memory-mapped filters.
+    pub mapped_bytes: usize,
+    /// Bytes of the rate limiters, recent filters, sketches and cardinality estimators.
+    pub structure_bytes: usize,
+    /// Resident memory of the whole process (Linux only): the data above, the mapped pages in
+    /// memory, and everything else the service allocates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resident_bytes: Option<u64>,
+}
+
+/// The evictions of a store.
+#[derive(Default)]
+pub(crate) struct Evictions {
+    total: u64,
+    reloads: u64,
+    recent: VecDeque<Eviction>,
+    /// The filters evicted to the persist directory, by name, to list them and find them by
+    /// their ids until they are reloaded.
+    evicted: BTreeMap<String, EvictedFilter>,
+}
+
+/// A filter evicted to the persist directory, as it was then.
+struct EvictedFilter {
+    info: FilterInfo,
+    /// When the filter expires, in seconds since the Unix epoch.
+    expires_at: Option<u64>,
+}
+
+impl EvictedFilter {
+    /// Indexes the filter of `container`, evicted at `evicted_at`.
+    fn new(container: &FilterContainer, evicted_at: u64) -> Self {
+        let mut info = container.info(Instant::now());
+        info.memory_usage_bytes = 0;
+        info.evicted = true;
+        EvictedFilter {
+            expires_at: info
+                .ttl_remaining_seconds
+                .map(|s| evicted_at.saturating_add(s)),
+            info,
+        }
+    }
+}
+
+impl FilterStore {
+    /// Returns the memory used by the filters, and the filters evicted to stay within the budget.
+    pub fn memory_stats(&self) -> MemoryStats {
+        let memory_usage_bytes = self
+            .all_entries()
+            .iter()
+            .map(|entry| entry.read().filter.memory_usage_bytes())
+            .sum();
+        let evictions = self.evictions.lock();
+        MemoryStats {
+            memory_usage_bytes,
+            max_memory_bytes: self.memory_budget.as_ref().map(|b| b.max_bytes),
+            evictions: evictions.total,
+            reloads: evictions.reloads,
+            recent_evictions: evictions.recent.iter().cloned().collect(),
+        }
+    }
+
+    /// Returns the memory used by each filter of the store and its namespaces, with the totals
+    /// of the store and of the process.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterSpec, FilterStore};
+    ///
+    /// let store = FilterStore::default();
+    /// let spec = FilterSpec {
+    ///     name: "seen".to_string(),
+    ///     item_count: 1000,
+    ///     false_positive_rate: Some(0.01),
+    ///     ..Default::default()
+    /// };
+    /// store.create(spec.clone()).unwrap();
+    /// store.create_namespace("payments").unwrap().create(spec).unwrap();
+    ///
+    /// let report = store.memory_report();
+    /// assert_eq!(report.filters.len(), 2);
+    /// assert_eq!(report.filter_bytes, 2 * report.filters[0].memory_usage_bytes);
+    /// ```
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut filters = self.filter_memory(None);
+        let mut structure_bytes = self.structure_bytes();
+        for (name, namespace) in self.namespaces.read().iter() {
+            filters.extend(namespace.filter_memory(Some(name)));
+            structure_bytes += namespace.structure_bytes();
+        }
+        filters.sort_by(|a, b| {
+            let size = |f: &FilterMemory| f.memory_usage_bytes + f.mapped_bytes;
+            size(b)
+                .cmp(&size(a))
+                .then_with(|| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)))
+        });
+        MemoryReport {
+            filter_bytes: filters.iter().map(|f| f.memory_usage_bytes).sum(),
+            mapped_bytes: filters.iter().map(|f| f.mapped_bytes).sum(),
+            filters,
+            structure_bytes,
+            resident_bytes: resident_bytes(),
+        }
+    }
+
+    /// Returns the memory used by each filter of the store itself.
+    fn filter_memory(&self, namespace: Option<&str>) -> Vec<FilterMemory> {
+        self.all_entries()
+            .iter()
+            .map(|entry| {
+                let container = entry.read();
+                FilterMemory {
+                    namespace: namespace.map(str::to_string),
+                    name: container.name.clone(),
+                    storage: container.filter.storage(),
+                    memory_usage_bytes: container.filter.memory_usage_bytes(),
+                    mapped_bytes: container.filter.mapped_bytes(),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the memory used by the structures of the store other than filters.
+    fn structure_bytes(&self) -> usize {
+        let limiters: usize = self
+            .list_limiters()
+            .iter()
+            .map(|l| l.memory_usage_bytes)
+            .sum();
+        let recent: usize = self
+            .recent
+            .read()
+            .values()
+            .map(RecentFilter::memory_usage_bytes)
+            .sum();
+        let sketches: usize = self
+            .list_sketches()
+            .iter()
+            .map(|s| s.memory_usage_bytes)
+            .sum();
+        let cardinality: usize = self
+            .list_cardinality()
+            .iter()
+            .map(|c| c.memory_usage_bytes)
+            .sum();
+        limiters + recent + sketches + cardinality
+    }
+
+    /// Sets the quotas of the store, and those of its namespaces: the namespaces listed in
+    /// `namespaces` have theirs, and the other namespaces those of the store.
+    pub fn set_quotas(&self, quotas: Quotas, namespaces: BTreeMap<String, Quotas>) {
+        for (name, namespace) in self.namespaces.read().iter() {
+            let quotas = namespaces.get(name).unwrap_or(&quotas);
+            *namespace.quotas.write() = quotas.clone();
+        }
+        *self.quotas.write() = quotas;
+        *self.namespace_quotas.write() = namespaces;
+    }
+
+    /// Returns the quotas of the store.
+    pub fn quotas(&self) -> Quotas {
+        self.quotas.read().clone()
+    }
+
+    /// Returns the quotas of a new namespace of the store.
+    pub(crate) fn quotas_of(&self, namespace: &str) -> Quotas {
+        match self.namespace_quotas.read().get(namespace) {
+            Some(quotas) => quotas.clone(),
+            None => self.quotas(),
+        }
+    }
+
+    /// Fails unless the filters `added`, by name, with the item count they are sized for and the
+    /// bytes they take on the heap, fit in the quotas along with the filters `db`, once those of
+    /// the same names and those `removed` are gone.
+    ///
+    /// The filters of `db` to be replaced or removed are not locked, as their callers may hold
+    /// their locks.
+    pub(crate) fn check_quotas(
+        &self,
+        db: &HashMap<String, FilterEntry>,
+        added: &[(&str, usize, usize)],
+        removed: &[&str],
+    ) -> Result<(), Error> {
+        let quotas = self.quotas.read().clone();
+        for &(name, item_count, bytes) in added {
+            quotas.check_filter(name, item_count, bytes)?;
+        }
+        let gone =
+            |name: &str| removed.contains(&name) || added.iter().any(|&(added, ..)| added == name);
+        if let Some(max) = quotas.max_filters {
+            let kept = db.keys().filter(|name| !gone(name)).count();
+            if kept + added.len() > max && added.iter().any(|(name, ..)| !db.contains_key(*name)) {
+                return Err(Error::QuotaExceeded(format!(
+                    "The quota of {max} filters is reached"
+                )));
+            }
+        }
+        if let Some(max) = quotas.max_total_bytes {
+            let kept: usize = db
+                .iter()
+                .filter(|(name, _)| !gone(name))
+                .map(|(_, entry)| entry.read().filter.memory_usage_bytes())
+                .sum();
+            let bytes = kept + added.iter().map(|&(_, _, bytes)| bytes).sum::<usize>();
+            if bytes > max {
+                return Err(Error::QuotaExceeded(format!(
+                    "The filters would take {bytes} bytes, more than the quota of {max} bytes"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the scalable filters of `entries` that have already grown beyond the quotas, so
+    /// that inserts do not grow them any further.
+    ///
+    /// Called before the filters are locked for the inserts, as the quotas look up the other
+    /// filters.
+    pub(crate) fn outgrown(&self, entries: &BTreeMap<String, FilterEntry>) -> Outgrown {
+        let quotas = self.quotas.read().clone();
+        let mut outgrown = Outgrown::new();
+        if quotas.max_filter_bytes.is_none() && quotas.max_total_bytes.is_none() {
+            return outgrown;
+        }
+        let scalable: Vec<(&String, usize)> = entries
+            .iter()
+            .filter_map(|(name, entry)| {
+                let container = entry.read();
+                (container.filter.kind() == FilterKind::Scalable)
+                    .then(|| (name, container.filter.memory_usage_bytes()))
+            })
+            .collect();
+        if scalable.is_empty() {
+            return outgrown;
+        }
+        let total: Option<usize> = quotas.max_total_bytes.map(|_| {
+            self.all_entries()
+                .iter()
+                .map(|entry| entry.read().filter.memory_usage_bytes())
+                .sum()
+        });
+        for (name, bytes) in scalable {
+            let reached = quotas
+                .max_filter_bytes
+                .filter(|&max| bytes >= max)
+                .or(quotas.max_total_bytes.filter(|&max| total >= Some(max)));
+            if let Some(max) = reached {
+                outgrown.insert(name.clone(), max);
+            }
+        }
+        outgrown
+    }
+
+    /// Returns the filters evicted to the persist directory of the memory budget, as they were
+    /// then but for the time to their expiry.
+    pub(crate) fn evicted_filters(&self) -> Vec<FilterInfo> {
+        let now = unix_now();
+        self.evictions
+            .lock()
+            .evicted
+            .values()
+            .map(|evicted| {
+                let mut info = evicted.info.clone();
+                info.ttl_remaining_seconds = evicted.expires_at.map(|at| at.saturating_sub(now));
+                info
+            })
+            .collect()
+    }
+
+    /// Returns the name of the filter of id `id` evicted to the persist directory, if any.
+    pub(crate) fn evicted_name(&self, id: &str) -> Option<String> {
+        self.evictions
+            .lock()
+            .evicted
+            .values()
+            .find(|evicted| evicted.info.id == id)
+            .map(|evicted| evicted.info.name.clone())
+    }
+
+    /// Indexes the filters found evicted in the persist directory of the memory budget, e.g. by
+    /// an earlier run of the service, to list them and find them by their ids. Files that cannot
+    /// be read are left for an upload, as when they are reloaded.
+    pub(crate) fn index_evicted(&self) {
+        let Some(dir) = self
+            .memory_budget
+            .as_ref()
+            .and_then(|budget| budget.persist_dir.as_ref())
+        else {
+            return;
+        };
+        if self.clustered.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(paths) = files(dir, FILTER_EXTENSION) else {
+            return;
+        };
+        let now = unix_now();
+        for path in paths {
+            let Some(name) = file_name(&path) else {
+                continue;
+            };
+            if self.name_policy.check(name).is_err() || self.resident(name).is_some() {
+                continue;
+            }
+            let Some(mut container) = fs::read(&path)
+                .ok()
+                .and_then(|bytes| FilterSnapshot::decode(&bytes).ok())
+                .and_then(|snapshot| FilterContainer::of(name, snapshot).ok())
+            else {
+                continue;
+            };
+            if let Some(metadata) = read_metadata(dir, name) {
+                let _ = metadata.apply(&mut container);
+            }
+            let evicted = EvictedFilter::new(&container, now);
+            self.evictions
+                .lock()
+                .evicted
+                .insert(name.to_string(), evicted);
+        }
+    }
+
+    /// Fails if a filter could not fit in the memory budget even if every other filter were evicted.
+    pub(crate) fn check_budget(&self, filter: &BloomFilter) -> Result<(), Error> {
+        match &self.memory_budget {
+            Some(budget) if filter.memory_usage_bytes() > budget.max_bytes => {
+                Err(Error::InvalidParameters(format!(
+                    "The filter needs {} bytes, more than the memory budget of {} bytes",
+                    filter.memory_usage_bytes(),
+                    budget.max_bytes
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Evicts the least recently used filters until the filters fit in the memory budget,
+    /// returning the evictions. A no-op without a budget.
+    ///
+    /// Evicted filters are removed like deleted ones (including from groups and as shadow
+    /// filters), after being saved to the persist directory of the budget, if any, with their
+    /// metadata and groups to be reloaded with; until then, they are still listed.
+    ///
+    /// A no-op in the store of a cluster node, whose leader commits the evictions instead.
+    pub fn enforce_memory_budget(&self) -> Vec<Eviction> {
+        if self.clustered.load(Ordering::Relaxed) {
+            return Vec::new();
+        }
+        self.evict(self.over_budget())
+    }
+
+    /// Returns the least recently used filters to evict for the filters to fit in the memory
+    /// budget, if any.
+    pub(crate) fn over_budget(&self) -> Vec<FilterEntry> {
+        let Some(budget) = &self.memory_budget else {
+            return Vec::new();
+        };
+        let mut usage = 0;
+        let mut candidates: Vec<(Instant, String, usize, FilterEntry)> = Vec::new();
+        for entry in self.all_entries() {
+            let container = entry.read();
+            let bytes = container.filter.memory_usage_bytes();
+            usage += bytes;
+            if bytes > 0 {
+                let accessed = *container.last_accessed.lock();
+                candidates.push((accessed, container.name.clone(), bytes, entry.clone()));
+            }
+        }
+        candidates.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        candidates
+            .into_iter()
+            .take_while(|&(_, _, bytes, _)| {
+                let over = usage > budget.max_bytes;
+                usage -= bytes;
+                over
+            })
+            .map(|(.., entry)| entry)
+            .collect()
+    }
+
+    /// Evicts the filters `entries`, whether or not they exceed the memory budget, returning the
+    /// evictions.
+    pub(crate) fn evict(&self, entries: Vec<FilterEntry>) -> Vec<Eviction> {
+        if entries.is_empty() {
+            return Vec::new();
+        }
+        let evicted = {
+            let mut groups = self.groups.write();
+            let mut evicted = Vec::new();
+            for entry in entries {
+                let name = entry.read().name.clone();
+                let member_of: Vec<String> = groups
+                    .values()
+                    .filter(|group| group.filters.contains(&name))
+                    .map(|group| group.name.clone())
+                    .collect();
+                let entries: Vec<FilterEntry> = {
+                    let mut db = self.filters.write();
+                    // Unless deleted (and maybe created again) since it was measured
+                    if !db.get(&name).is_some_and(|e| Arc::ptr_eq(e, &entry)) {
+                        continue;
+                    }
+                    // Eviction cannot fail: if the delete is not logged, it is repeated after a
+                    // recovery
+                    let _ = self.journal.record(Operation::Delete { name: &name });
+                    db.remove(&name);
+                    db.values().cloned().collect()
+                };
+                forget_filter(&mut groups, &entries, &name);
+                self.events.changed(Change::Deleted, &name);
+                evicted.push((entry, member_of));
+            }
+            evicted
+        };
+
+        // Saved without the locks of the store, as writing large filters takes a while
+        let evicted_at = unix_now();
+        // Not listed in the store of a cluster node, which does not reload them
+        let clustered = self.clustered.load(Ordering::Relaxed);
+        let evictions: Vec<Eviction> = evicted
+            .into_iter()
+            .map(|(entry, groups)| {
+                let container = entry.read();
+                let memory_usage_bytes = container.filter.memory_usage_bytes();
+                let name = container.name.clone();
+                let snapshot = to_snapshot(&container);
+                let metadata = FilterMetadata::of(&container, groups);
+                let indexed = EvictedFilter::new(&container, evicted_at);
+                drop(container);
+                let persisted = self
+                    .memory_budget
+                    .as_ref()
+                    .and_then(|budget| budget.persist_dir.as_ref())
+                    .is_some_and(|dir| {
+                        write_snapshot(dir, &name, &snapshot).is_ok()
+                            && write_metadata(dir, &name, &metadata).is_ok()
+                    });
+                if persisted && !clustered {
+                    self.evictions.lock().evicted.insert(name.clone(), indexed);
+                }
+                Eviction {
+                    name,
+                    memory_usage_bytes,
+                    persisted,
+                    evicted_at,
+                }
+            })
+            .collect();
+
+        let mut log = self.evictions.lock();
+        log.total += evictions.len() as u64;
+        for eviction in &evictions {
+            if log.recent.len() == RECENT_EVICTIONS {
+                log.recent.pop_front();
+            }
+            log.recent.push_back(eviction.clone());
+        }
+        evictions
+    }
+
+    /// Reloads the filter `name` from the persist directory of the memory budget, if it was evicted
+    /// there, removing its file; returns whether the filter is in memory. The least recently used
+    /// filters are evicted in turn, if need be.
+    ///
+    /// Reloaded as it was evicted, with its id, labels, transform, expiry, shadow filter and item
+    /// journal, joining its groups again, when those that still exist.
+    pub(crate) fn reload_evicted(&self, name: &str) -> bool {
+        let Some(dir) = self
+            .memory_budget
+            .as_ref()
+            .and_then(|budget| budget.persist_dir.as_ref())
+        else {
+            return false;
+        };
+        // Not to read files outside of the directory; nor in the store of a cluster node, whose
+        // filters change only through its consensus
+        if self.name_policy.check(name).is_err() || self.clustered.load(Ordering::Relaxed) {
+            return false;
+        }
+        let path = snapshot_path(dir, name);
+        let reloading = self.reloading.lock();
+        if self.filters.read().contains_key(name) {
+            return true;
+        }
+        let Ok(bytes) = fs::read(&path) else {
+            return false;
+        };
+        // A file that cannot be reloaded is left for an upload
+        let Ok(snapshot) = FilterSnapshot::decode(&bytes) else {
+            return false;
+        };
+        if self.restore(name, snapshot).is_err() {
+            return false;
+        }
+        if let Some(metadata) = read_metadata(dir, name) {
+            if let Some(entry) = self.resident(name) {
+                // Reloaded without a transform that no longer compiles, rather than not at all
+                let _ = metadata.apply(&mut entry.write());
+                metadata.apply_shadow(self, &entry);
+            }
+            let mut groups = self.groups.write();
+            for group in &metadata.groups {
+                if let Some(group) = groups.get_mut(group) {
+                    if !group.filters.iter().any(|filter| filter == name) {
+                        group.filters.push(name.to_string());
+                    }
+                }
+            }
+        }
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(metadata_path(dir, name));
+        self.evictions.lock().evicted.remove(name);
+        let _ = self.open_journal(name);
+        drop(reloading);
+        self.evictions.lock().reloads += 1;
+        true
+    }
+}
+
+/// Reads the metadata file of the filter `name` in `dir`, if any can be decoded.
+fn read_metadata(dir: &Path, name: &str) -> Option<FilterMetadata> {
+    let bytes = fs::read(metadata_path(dir, name)).ok()?;
+    FilterMetadata::decode(&bytes).ok()
+}
+
+/// Returns the time in seconds since the Unix epoch.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Returns the resident memory of the process, as counted by Linux.
+fn resident_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    filter::{fnv1a, mix},
+    Error,
+};
+
+/// Default precision of a HyperLogLog: 2^14 registers, for a standard error of 0.81%.
+const DEFAULT_PRECISION: u8 = 14;
+/// Identifies a HyperLogLog file.
+const HLL_MAGIC: &[u8; 8] = b"BLOOMHLL";
+/// Version of the HyperLogLog file format.
+const HLL_VERSION: u32 = 1;
+/// Size of the header of a HyperLogLog file, in bytes.
+const HLL_HEADER_LEN: usize = 16;
+
+/// Parameters of a HyperLogLog to be created.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::CardinalitySpec;
+///
+/// let spec = CardinalitySpec {
+///     name: "unique_visitors".to_string(),
+///     precision: Some(12),
+/// };
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Parameters of a HyperLogLog to be created.")
+)]
+pub struct CardinalitySpec {
+    pub name: String,
+    /// Base-2 logarithm of the number of registers, between 4 and 18 (default 14); each extra bit
+    /// doubles the memory and divides the error by about 1.4.
+    pub precision: Option<u8>,
+}
+
+/// Summary of a HyperLogLog, as listed by
+/// [`FilterStore::list_cardinality`](crate::FilterStore::list_cardinality).
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Summary of a HyperLogLog.")
+)]
+pub struct CardinalityInfo {
+    pub name: String,
+    pub precision: u8,
+    /// Estimated number of distinct items inserted.
+    pub estimate: u64,
+    /// Relative standard error of the estimate.
+    pub standard_error: f64,
+    pub memory_usage_bytes: usize,
+}
+
+/// A HyperLogLog, estimating the number of distinct items inserted in a few kilobytes.
+///
+/// Each item sets one of `2^precision` registers to the maximum of its value and the number of
+/// leading zeros of the hash of the item. Inserts and merges only ever raise registers, so that
+/// inserting an item twice, or merging the same HyperLogLog twice, does not change the estimate.
+/// Positions are derived from a stable hash of the item, so HyperLogLogs can be saved and loaded
+/// by other builds of the service.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{CardinalitySpec, HyperLogLog};
+///
+/// let spec = CardinalitySpec { name: "unique_visitors".to_string(), ..Default::default() };
+/// let mut visitors = HyperLogLog::new(&spec).unwrap();
+/// for i in 0..10_000 {
+///     visitors.insert(format!("user-{}", i % 1000).as_bytes());
+/// }
+/// let estimate = visitors.estimate();
+/// assert!((950..=1050).contains(&estimate));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct HyperLogLog {
+    pub name: String,
+    pub precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// Validates a specification and creates the corresponding, empty HyperLogLog.
+    pub fn new(spec: &CardinalitySpec) -> Result<Self, Error> {
+        let precision = spec.precision.unwrap_or(DEFAULT_PRECISION);
+        check_precision(precision)?;
+        Ok(HyperLogLog {
+            name: spec.name.clone(),
+            precision,
+            registers: vec![0; 1 << precision],
+        })
+    }
+
+    /// Returns the memory used by the registers, in bytes.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.registers.len()
+    }
+
+    /// Returns the relative standard error of the estimate, `1.04 / sqrt(2^precision)`.
+    pub fn standard_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+
+    /// Inserts an item, returning `true` if this raised a register (i.e. the item is new).
+    pub fn insert(&mut self, item: &[u8]) -> bool {
+        let hash = mix(fnv1a(item));
+        let precision = u32::from(self.precision);
+        let index = (hash >> (64 - precision)) as usize;
+        // The guard bit bounds the rank when the remaining bits are all zero
+        let rank = ((hash << precision) | (1 << (precision - 1))).leading_zeros() as u8 + 1;
+        let register = &mut self.registers[index];
+        let raised = rank > *register;
+        *register = (*register).max(rank);
+        raised
+    }
+
+    /// Estimates the number of distinct items inserted.
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&register| 2f64.powi(-i32::from(register)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+
+        // Small cardinalities are estimated more precisely by linear counting
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|&&register| register == 0)
+            .count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+
+    /// Adds the items of another HyperLogLog of the same precision.
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), Error> {
+        if self.precision != other.precision {
+            return Err(Error::InvalidParameters(format!(
+                "Cannot merge a HyperLogLog of precision {} into one of precision {}",
+                other.precision, self.precision
+            )));
+        }
+        for (register, &other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(other);
+        }
+        Ok(())
+    }
+
+    /// Returns the registers, one byte each.
+    pub(crate) fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Creates a HyperLogLog from its registers, as logged.
+    pub(crate) fn from_registers(name: &str, registers: Vec<u8>) -> Result<Self, Error> {
+        let precision = registers.len().trailing_zeros() as u8;
+        if !registers.len().is_power_of_two() {
+            return Err(Error::InvalidParameters(
+                "The registers of the HyperLogLog do not match a precision".to_string(),
+            ));
+        }
+        check_precision(precision)?;
+        Ok(HyperLogLog {
+            name: name.to_string(),
+            precision,
+            registers,
+        })
+    }
+
+    /// Encodes the HyperLogLog as a HyperLogLog file.
+    ///
+    /// The binary format is little-endian: a 16-byte header (the magic `BLOOMHLL`, the format
+    /// version and the precision), the registers in a byte each, and a 64-bit FNV-1a checksum of
+    /// everything before it.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HLL_HEADER_LEN + self.registers.len() + 8);
+        bytes.extend_from_slice(HLL_MAGIC);
+        bytes.extend_from_slice(&HLL_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&u32::from(self.precision).to_le_bytes());
+        bytes.extend_from_slice(&self.registers);
+        let checksum = fnv1a(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes a HyperLogLog file as the HyperLogLog `name`, verifying its checksum and size.
+    pub fn decode(name: &str, bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Err(Error::InvalidParameters(reason.to_string()));
+        if bytes.len() < HLL_HEADER_LEN + 8 || &bytes[..8] != HLL_MAGIC {
+            return invalid("Not a HyperLogLog file");
+        }
+        let (content, checksum) = bytes.split_at(bytes.len() - 8);
+        if fnv1a(content) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+            return invalid("The checksum of the HyperLogLog file does not match");
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(content[at..at + 4].try_into().unwrap());
+        if u32_at(8) != HLL_VERSION {
+            return invalid("Unsupported HyperLogLog file version");
+        }
+
+        let hll = Self::from_registers(name, content[HLL_HEADER_LEN..].to_vec())?;
+        if u32::from(hll.precision) != u32_at(12) {
+            return invalid("The registers of the HyperLogLog do not match its declared precision");
+        }
+        Ok(hll)
+    }
+}
+
+fn check_precision(precision: u8) -> Result<(), Error> {
+    if !(4..=18).contains(&precision) {
+        return Err(Error::InvalidParameters(
+            "precision must be between 4 and 18".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,497 @@
+//! A typed client of the REST API of the service, for Rust applications calling a running
+//! service rather than embedding a [`FilterStore`](crate::FilterStore).
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::{FilterSpec, ShardRing};
+
+/// Errors returned by the [`BloomClient`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientError {
+    /// The service could not be reached, or its response could not be read.
+    Request(String),
+    /// The service rejected the request, with the status, [code](crate::Error::code) and message
+    /// of its error.
+    Service {
+        status: u16,
+        code: String,
+        message: String,
+    },
+}
+
+impl ClientError {
+    /// Returns the machine-readable code of the error of the service, if it rejected the request,
+    /// e.g. `filter_not_found`.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ClientError::Request(_) => None,
+            ClientError::Service { code, .. } => Some(code),
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Request(reason) => write!(f, "Request failed: {reason}"),
+            ClientError::Service {
+                status, message, ..
+            } => write!(f, "{message} ({status})"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(error: reqwest::Error) -> Self {
+        ClientError::Request(error.to_string())
+    }
+}
+
+/// A filter created by the service.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct CreatedFilter {
+    pub id: String,
+    pub name: String,
+    pub bit_count: u64,
+    pub hash_count: u32,
+}
+
+/// A filter, as listed by the service.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct FilterSummary {
+    pub id: String,
+    pub name: String,
+    /// Number of items the filter was sized for.
+    pub item_count: usize,
+    pub memory_usage_bytes: usize,
+    /// Creation time, in seconds since the Unix epoch.
+    pub created_at: u64,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// The error body of the responses of the service.
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+    code: String,
+}
+
+/// A client of a running service, calling its REST API.
+///
+/// A client of a service with [replicas](BloomClient::with_replicas), or of a
+/// [shard ring](BloomClient::with_ring), sends each request to the nodes serving it, and tries the
+/// others when a node cannot be reached, or is unavailable.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bloomsrv::{client::BloomClient, FilterSpec};
+///
+/// # async fn run() -> Result<(), bloomsrv::client::ClientError> {
+/// let client = BloomClient::new("http://127.0.0.1:3000").with_key("change-me");
+/// client
+///     .create_filter(&FilterSpec {
+///         name: "login_attempts".to_string(),
+///         item_count: 1000,
+///         false_positive_rate: Some(0.01),
+///         ..Default::default()
+///     })
+///     .await?;
+///
+/// client.insert("login_attempts", "user@example.com").await?;
+/// assert!(client.contains("login_attempts", "user@example.com").await?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct BloomClient {
+    /// URL of the service, without a trailing slash.
+    url: String,
+    /// Path of the first version of the API, or of a namespace of it, on every node.
+    base: String,
+    key: Option<String>,
+    http: reqwest::Client,
+    topology: Topology,
+}
+
+/// The nodes serving the requests of a client.
+#[derive(Clone, Debug)]
+enum Topology {
+    /// The service serves every request.
+    Single,
+    /// The replicas of the service serve the lookups in turn, and the service the changes.
+    Replicated {
+        replicas: Arc<[String]>,
+        next: Arc<AtomicUsize>,
+    },
+    /// The node of the ring owning a filter serves its requests.
+    Sharded(Arc<ShardRing>),
+}
+
+/// What a request is for, choosing the nodes it is sent to.
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    /// A lookup, of a filter and an item of it, if given.
+    Read(Option<&'a str>, Option<&'a [u8]>),
+    /// A change of a filter, and of an item of it, if given.
+    Write(&'a str, Option<&'a [u8]>),
+}
+
+impl BloomClient {
+    /// Creates a client of the service at `url`, e.g. `http://127.0.0.1:3000`.
+    pub fn new(url: &str) -> Self {
+        BloomClient {
+            url: url.trim_end_matches('/').to_string(),
+            base: "/v1".to_string(),
+            key: None,
+            http: reqwest::Client::new(),
+            topology: Topology::Single,
+        }
+    }
+
+    /// Sends `key` with every request, as the API key (or bearer token) of the client.
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Spreads the lookups over the `replicas` of the service, e.g. `http://replica-1:3000`, in
+    /// turn; a lookup a replica cannot serve is sent to the service.
+    ///
+    /// Changes are sent to the service, and to the replicas in turn while it cannot be reached,
+    /// so that they reach the replica promoted in its place.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::client::BloomClient;
+    ///
+    /// let replicas = ["http://replica-1:3000".to_string(), "http://replica-2:3000".to_string()];
+    /// let client = BloomClient::new("http://primary:3000").with_replicas(&replicas);
+    /// ```
+    pub fn with_replicas(mut self, replicas: &[String]) -> Self {
+        let replicas: Vec<String> = replicas
+            .iter()
+            .map(|replica| replica.trim_end_matches('/').to_string())
+            .collect();
+        self.topology = if replicas.is_empty() {
+            Topology::Single
+        } else {
+            Topology::Replicated {
+                replicas: replicas.into(),
+                next: Arc::default(),
+            }
+        };
+        self
+    }
+
+    /// Sends the requests for each filter to the node of `ring` owning it, and those for the
+    /// items of a [partitioned](ShardRing::with_partitioned) filter to the owners of the items.
+    ///
+    /// The ring should have the nodes, and the partitioned filters, the nodes are given. A request
+    /// the owner cannot serve, e.g. as it left the ring, is sent to the other nodes in turn,
+    /// which proxy it to the owner they know.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{client::BloomClient, ShardRing};
+    ///
+    /// let nodes = ["http://node-2:3000".to_string(), "http://node-3:3000".to_string()];
+    /// let ring = ShardRing::new("http://node-1:3000", &nodes).unwrap();
+    /// let client = BloomClient::new("http://node-1:3000").with_ring(ring);
+    /// ```
+    pub fn with_ring(mut self, ring: ShardRing) -> Self {
+        self.topology = Topology::Sharded(Arc::new(ring));
+        self
+    }
+
+    /// Returns a client of the namespace `namespace` of the service, with the same key.
+    ///
+    /// Namespaces are neither replicated nor sharded, so the service serves all of its requests.
+    pub fn namespace(&self, namespace: &str) -> Self {
+        BloomClient {
+            base: format!("{}/namespaces/{namespace}", self.base),
+            topology: Topology::Single,
+            ..self.clone()
+        }
+    }
+
+    /// Creates a filter, failing with the code `filter_exists` if its name is in use.
+    pub async fn create_filter(&self, spec: &FilterSpec) -> Result<CreatedFilter, ClientError> {
+        let target = Target::Write(&spec.name, None);
+        self.send(target, reqwest::Method::POST, "/filters", |request| {
+            request.json(spec)
+        })
+        .await
+    }
+
+    /// Creates a filter unless it exists already with the same parameters, returning `true` if it
+    /// was created.
+    pub async fn ensure_filter(&self, spec: &FilterSpec) -> Result<bool, ClientError> {
+        let path = format!("/filters/{}", spec.name);
+        let target = Target::Write(&spec.name, None);
+        let response = self
+            .call(target, reqwest::Method::PUT, &path, |request| {
+                request.json(spec)
+            })
+            .await?;
+        Ok(response.status() == reqwest::StatusCode::CREATED)
+    }
+
+    /// Lists the filters; with a ring, those of the service only.
+    pub async fn list_filters(&self) -> Result<Vec<FilterSummary>, ClientError> {
+        let target = Target::Read(None, None);
+        self.send(target, reqwest::Method::GET, "/filters", |request| request)
+            .await
+    }
+
+    /// Deletes a filter.
+    pub async fn delete_filter(&self, name: &str) -> Result<(), ClientError> {
+        let path = format!("/filters/{name}");
+        let target = Target::Write(name, None);
+        self.call(target, reqwest::Method::DELETE, &path, |request| request)
+            .await
+            .map(drop)
+    }
+
+    /// Removes every item from a filter.
+    pub async fn clear_filter(&self, name: &str) -> Result<(), ClientError> {
+        let path = format!("/filters/{name}/clear");
+        let target = Target::Write(name, None);
+        self.call(target, reqwest::Method::PUT, &path, |request| request)
+            .await
+            .map(drop)
+    }
+
+    /// Inserts an item into a filter, returning `true` if it was certainly not in the filter
+    /// before.
+    pub async fn insert(&self, name: &str, item: impl AsRef<[u8]>) -> Result<bool, ClientError> {
+        #[derive(Deserialize)]
+        struct Inserted {
+            new: bool,
+        }
+        let path = format!("/filters/{name}/items");
+        let item = item.as_ref();
+        let target = Target::Write(name, Some(item));
+        let inserted: Inserted = self
+            .send(target, reqwest::Method::POST, &path, |request| {
+                request.body(item.to_vec())
+            })
+            .await?;
+        Ok(inserted.new)
+    }
+
+    /// Inserts items into a filter, returning for each whether it was certainly not in the filter
+    /// before, or `None` if the transform of the filter rejected it.
+    pub async fn insert_batch<I: AsRef<[u8]>>(
+        &self,
+        name: &str,
+        items: &[I],
+    ) -> Result<Vec<Option<bool>>, ClientError> {
+        #[derive(Deserialize)]
+        struct Inserted {
+            new: Vec<Option<bool>>,
+        }
+        let path = format!("/filters/{name}/items/batch");
+        let target = Target::Write(name, None);
+        let inserted: Inserted = self.batch(target, &path, items).await?;
+        Ok(inserted.new)
+    }
+
+    /// Tests whether an item may have been inserted into a filter.
+    pub async fn contains(&self, name: &str, item: impl AsRef<[u8]>) -> Result<bool, ClientError> {
+        #[derive(Deserialize)]
+        struct Lookup {
+            contains: bool,
+        }
+        let path = format!("/filters/{name}/items");
+        let item = item.as_ref();
+        let target = Target::Read(Some(name), Some(item));
+        let lookup: Lookup = self
+            .send(target, reqwest::Method::GET, &path, |request| {
+                request.body(item.to_vec())
+            })
+            .await?;
+        Ok(lookup.contains)
+    }
+
+    /// Tests whether each of the items may have been inserted into a filter.
+    pub async fn contains_many<I: AsRef<[u8]>>(
+        &self,
+        name: &str,
+        items: &[I],
+    ) -> Result<Vec<bool>, ClientError> {
+        #[derive(Deserialize)]
+        struct Lookup {
+            contains: Vec<bool>,
+        }
+        let path = format!("/filters/{name}/items/query");
+        let target = Target::Read(Some(name), None);
+        let lookup: Lookup = self.batch(target, &path, items).await?;
+        Ok(lookup.contains)
+    }
+
+    /// Inserts an item into a filter, returning `true` if it may have been in the filter before.
+    pub async fn check_and_insert(
+        &self,
+        name: &str,
+        item: impl AsRef<[u8]>,
+    ) -> Result<bool, ClientError> {
+        #[derive(Deserialize)]
+        struct Checked {
+            was_present: bool,
+        }
+        let path = format!("/filters/{name}/items/check-insert");
+        let item = item.as_ref();
+        let target = Target::Write(name, Some(item));
+        let checked: Checked = self
+            .send(target, reqwest::Method::POST, &path, |request| {
+                request.body(item.to_vec())
+            })
+            .await?;
+        Ok(checked.was_present)
+    }
+
+    /// Returns the nodes a request for `target` is sent to, in turn: the first serving it, and
+    /// the others while it cannot be served.
+    fn nodes(&self, target: Target<'_>) -> Vec<&str> {
+        match (&self.topology, target) {
+            (Topology::Single, _) => vec![&self.url],
+            (Topology::Replicated { replicas, next }, Target::Read(..)) => {
+                let replica = next.fetch_add(1, Ordering::Relaxed) % replicas.len();
+                vec![&replicas[replica], &self.url]
+            }
+            (Topology::Replicated { replicas, .. }, Target::Write(..)) => {
+                std::iter::once(self.url.as_str())
+                    .chain(replicas.iter().map(String::as_str))
+                    .collect()
+            }
+            (Topology::Sharded(ring), target) => {
+                let (name, item) = match target {
+                    Target::Read(name, item) => (name, item),
+                    Target::Write(name, item) => (Some(name), item),
+                };
+                let first = match (name, item) {
+                    (Some(name), Some(item)) if ring.is_partitioned(name) => ring.item_owner(item),
+                    (Some(name), _) if !ring.is_partitioned(name) => ring.owner(name),
+                    _ => &self.url,
+                };
+                let nodes = ring.nodes();
+                let start = nodes.iter().position(|node| node == first).unwrap_or(0);
+                let mut ordered: Vec<&str> = vec![first];
+                ordered.extend(
+                    nodes[start..]
+                        .iter()
+                        .chain(&nodes[..start])
+                        .map(String::as_str)
+                        .filter(|node| *node != first),
+                );
+                ordered
+            }
+        }
+    }
+
+    /// Sends a request for a batch of items, encoded in Base64 so that any bytes can be sent, and
+    /// reads the JSON body of its response.
+    async fn batch<T: DeserializeOwned, I: AsRef<[u8]>>(
+        &self,
+        target: Target<'_>,
+        path: &str,
+        items: &[I],
+    ) -> Result<T, ClientError> {
+        let items: Vec<String> = items.iter().map(|item| BASE64.encode(item)).collect();
+        self.send(target, reqwest::Method::POST, path, |request| {
+            request.query(&[("encoding", "base64")]).json(&items)
+        })
+        .await
+    }
+
+    /// Sends a request to `path` of the nodes of `target` in turn, with the key of the client and
+    /// as `build` completes it, until one serves it; fails with the error of the last node
+    /// otherwise.
+    ///
+    /// A request is sent again to the next node when a node cannot be reached, or answers
+    /// `503 Service Unavailable`; a change, also when a replica answers `403 Forbidden`, as it is
+    /// not promoted.
+    async fn call(
+        &self,
+        target: Target<'_>,
+        method: reqwest::Method,
+        path: &str,
+        build: impl Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, ClientError> {
+        let replicated = matches!(self.topology, Topology::Replicated { .. });
+        let change = matches!(target, Target::Write(..));
+        let mut failure = None;
+        for (index, node) in self.nodes(target).into_iter().enumerate() {
+            let mut request = self
+                .http
+                .request(method.clone(), format!("{node}{}{path}", self.base));
+            if let Some(key) = &self.key {
+                request = request.bearer_auth(key);
+            }
+            let response = match build(request).send().await {
+                Ok(response) => response,
+                Err(error) if error.is_connect() || error.is_timeout() => {
+                    failure = Some(error.into());
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+            match check(response).await {
+                Err(error @ ClientError::Service { status: 503, .. }) => failure = Some(error),
+                Err(error @ ClientError::Service { status: 403, .. })
+                    if replicated && change && index > 0 =>
+                {
+                    failure = Some(error)
+                }
+                result => return result,
+            }
+        }
+        Err(failure.unwrap_or_else(|| ClientError::Request("No node to send to".to_string())))
+    }
+
+    /// Sends a request as [`BloomClient::call`] does, and reads the JSON body of its response.
+    async fn send<T: DeserializeOwned>(
+        &self,
+        target: Target<'_>,
+        method: reqwest::Method,
+        path: &str,
+        build: impl Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+    ) -> Result<T, ClientError> {
+        Ok(self.call(target, method, path, build).await?.json().await?)
+    }
+}
+
+/// Fails with the error of the service if it rejected a request.
+async fn check(response: reqwest::Response) -> Result<reqwest::Response, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.bytes().await?;
+    Err(match serde_json::from_slice::<ErrorBody>(&body) {
+        Ok(error) => ClientError::Service {
+            status: status.as_u16(),
+            code: error.code,
+            message: error.error,
+        },
+        Err(_) => ClientError::Request(format!(
+            "Unexpected response ({status}): {}",
+            String::from_utf8_lossy(&body)
+        )),
+    })
+}
@@ -0,0 +1,1250 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use openraft::{
+    error::{
+        ClientWriteError, ForwardToLeader, InitializeError, InstallSnapshotError, NetworkError,
+        RPCError, RaftError, RemoteError, Unreachable,
+    },
+    network::{RPCOption, RaftNetwork, RaftNetworkFactory},
+    raft::{
+        AppendEntriesRequest, AppendEntriesResponse, InstallSnapshotRequest,
+        InstallSnapshotResponse, VoteRequest, VoteResponse,
+    },
+    storage::{LogFlushed, RaftLogStorage, RaftStateMachine},
+    BasicNode, ChangeMembers, Entry, EntryPayload, LogId, LogState, RaftLogReader,
+    RaftSnapshotBuilder, ServerState, Snapshot, SnapshotMeta, SnapshotPolicy, StorageError,
+    StorageIOError, StoredMembership, Vote,
+};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt, fs,
+    io::{self, BufRead, Cursor, Write},
+    ops::RangeBounds,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::{
+    auth::{hash_secret, new_secret},
+    server::MessageResponse,
+    store::FilterEntry,
+    wal::next_record,
+    Error, FilterStore, SharedState,
+};
+
+openraft::declare_raft_types!(
+    /// The types of the consensus of a cluster: the commands committed are requests, and their
+    /// outcomes the responses.
+    pub(crate) TypeConfig:
+        D = Command,
+        R = Outcome,
+);
+
+type NodeId = u64;
+type Raft = openraft::Raft<TypeConfig>;
+
+/// Interval between the heartbeats of the leader, in milliseconds.
+const HEARTBEAT_INTERVAL: u64 = 250;
+/// Bounds of the time without heartbeats after which a follower calls an election, in
+/// milliseconds.
+const ELECTION_TIMEOUT_MIN: u64 = 1000;
+const ELECTION_TIMEOUT_MAX: u64 = 2000;
+
+/// Entries committed between the snapshots of the filters, after which the log before a snapshot
+/// is purged, but for the last [`KEPT_ENTRIES`]: a node joining the cluster, or too far behind,
+/// is sent the last snapshot and the entries after it, so that it catches up in a time bounded by
+/// the size of the filters rather than the length of their history.
+const SNAPSHOT_ENTRIES: u64 = 5000;
+/// Entries kept in the log before the last snapshot, for the nodes slightly behind to catch up
+/// with the entries alone.
+const KEPT_ENTRIES: u64 = 1000;
+
+/// Longest time forwarding a change to the leader may take.
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Path of the command expiring and evicting filters, served by the nodes of a cluster only as
+/// committed by its leader.
+pub(crate) const EXPIRE_PATH: &str = "/admin/cluster/expire";
+
+/// Path of the request minting API keys.
+const KEYS_PATH: &str = "/admin/keys";
+
+/// Files of the consensus, in the directory of the cluster.
+const VOTE_FILE: &str = "vote.json";
+const LOG_FILE: &str = "log.jsonl";
+const SNAPSHOT_FILE: &str = "snapshot.bin";
+const SNAPSHOT_META_FILE: &str = "snapshot.json";
+
+tokio::task_local! {
+    /// State of the generator of the ids of what a command creates, while it is served.
+    static SEED: Cell<u64>;
+    /// Hash of the secret of the key a command mints, while it is served.
+    static SECRET_HASH: Option<String>;
+}
+
+/// Returns the hash of the secret of the key minted by the command served, generated by the node
+/// it was sent to; `None` outside of commands.
+pub(crate) fn committed_secret_hash() -> Option<String> {
+    SECRET_HASH.try_with(Clone::clone).ok().flatten()
+}
+
+/// Returns the next id generated while a command is served, derived from the seed of the
+/// command for every node to generate the same; `None` outside of commands.
+pub(crate) fn seeded_id() -> Option<Uuid> {
+    SEED.try_with(|seed| {
+        let mut bytes = [0; 16];
+        for half in bytes.chunks_mut(8) {
+            // SplitMix64
+            let state = seed.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+            seed.set(state);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            half.copy_from_slice(&(z ^ (z >> 31)).to_le_bytes());
+        }
+        uuid::Builder::from_random_bytes(bytes).into_uuid()
+    })
+    .ok()
+}
+
+/// A change requested of a cluster: a request, served by every node once committed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Command {
+    method: String,
+    /// Path and query of the request.
+    uri: String,
+    content_type: Option<String>,
+    #[serde(with = "base64_bytes")]
+    body: Vec<u8>,
+    /// Seed of the ids of what the request creates.
+    seed: u64,
+    /// Hash of the secret of the key the request mints, if it does: the secret itself is known
+    /// to the node the request was sent to only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret_hash: Option<String>,
+}
+
+impl Command {
+    /// A command of the request `method` of `uri`, carrying a `body` of `content_type`.
+    fn new(method: String, uri: String, content_type: Option<String>, body: Vec<u8>) -> Self {
+        Command {
+            method,
+            uri,
+            content_type,
+            body,
+            seed: u64::from_le_bytes(Uuid::new_v4().as_bytes()[..8].try_into().unwrap()),
+            secret_hash: None,
+        }
+    }
+}
+
+/// The filters of a store to expire and evict, selected by the leader of a cluster for every node
+/// to expire and evict the same, whichever are due on its own clock.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Expiries {
+    /// Namespace of the store, or `None` for the root store.
+    namespace: Option<String>,
+    removed: Vec<String>,
+    cleared: Vec<String>,
+    evicted: Vec<String>,
+}
+
+impl Expiries {
+    /// Expires and evicts the filters in the store of `state`, returning their number.
+    pub(crate) fn apply(&self, state: &SharedState) -> Result<usize, Error> {
+        let store = match &self.namespace {
+            Some(namespace) => state
+                .namespaces
+                .read()
+                .get(namespace)
+                .cloned()
+                .ok_or_else(|| Error::NamespaceNotFound(namespace.clone()))?,
+            None => state.clone(),
+        };
+        let entries = |names: &[String]| store.entries(names).into_values().collect();
+        let expired = store.expire_entries(
+            entries(&self.removed),
+            entries(&self.cleared),
+            Instant::now(),
+        );
+        Ok(expired + store.evict(entries(&self.evicted)).len())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.cleared.is_empty() && self.evicted.is_empty()
+    }
+}
+
+/// Returns the names of filters.
+fn names(entries: Vec<FilterEntry>) -> Vec<String> {
+    entries
+        .iter()
+        .map(|entry| entry.read().name.clone())
+        .collect()
+}
+
+/// The response to a [`Command`], as served by the node it was sent to.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Outcome {
+    status: u16,
+    content_type: Option<String>,
+    #[serde(with = "base64_bytes")]
+    body: Vec<u8>,
+}
+
+impl Outcome {
+    /// A successful outcome carrying a message, as answered by the membership endpoints.
+    fn message(text: String) -> Self {
+        Outcome {
+            status: StatusCode::OK.as_u16(),
+            content_type: Some("application/json".to_string()),
+            body: serde_json::to_vec(&MessageResponse { message: text }).unwrap(),
+        }
+    }
+
+    /// This outcome, answering the secret of the key minted, which the nodes serving the command
+    /// do not know.
+    fn with_secret(self, secret: &str) -> Self {
+        let minted = serde_json::from_slice::<serde_json::Value>(&self.body);
+        match minted {
+            Ok(mut minted) if self.status == StatusCode::CREATED.as_u16() => {
+                minted["api_key"] = secret.into();
+                Outcome {
+                    body: serde_json::to_vec(&minted).unwrap(),
+                    ..self
+                }
+            }
+            _ => self,
+        }
+    }
+
+    /// The outcome of a response, read whole.
+    async fn of(response: Response) -> Self {
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map(|body| body.to_vec())
+            .unwrap_or_default();
+        Outcome {
+            status,
+            content_type,
+            body,
+        }
+    }
+}
+
+impl IntoResponse for Outcome {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() =
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        if let Some(value) = self.content_type.and_then(|t| t.parse().ok()) {
+            response.headers_mut().insert(header::CONTENT_TYPE, value);
+        }
+        response
+    }
+}
+
+mod base64_bytes {
+    use super::*;
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64.encode(bytes))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A node of a cluster, as given to `POST /admin/cluster/nodes`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ClusterMember {
+    /// Id of the node, unique in the cluster.
+    pub id: u64,
+    /// URL the other nodes reach the node at, e.g. `http://node-2:3000`.
+    pub address: String,
+}
+
+/// The state of a cluster, as seen by one of its nodes.
+#[derive(Clone, Debug, Serialize, utoipa::ToSchema)]
+pub struct ClusterStatus {
+    /// Id of the node.
+    pub id: u64,
+    /// Role of the node: `leader`, `follower`, `candidate` or `learner`.
+    pub role: String,
+    /// Id of the leader, if known.
+    pub leader: Option<u64>,
+    /// Term of the consensus, incremented on each election.
+    pub term: u64,
+    /// Index of the last change in the log of the node, and of the last one applied to its
+    /// filters.
+    pub last_log_index: Option<u64>,
+    pub last_applied_index: Option<u64>,
+    /// Members of the cluster, voters and learners (still catching up).
+    pub nodes: Vec<ClusterNode>,
+}
+
+#[derive(Clone, Debug, Serialize, utoipa::ToSchema)]
+pub struct ClusterNode {
+    pub id: u64,
+    pub address: String,
+    pub voter: bool,
+}
+
+/// A node of a cluster of services keeping the same filters: the changes requested of any node
+/// are committed through Raft consensus (forwarded to the leader, and applied by every node once
+/// a majority has them), and any node serves lookups from its own filters.
+///
+/// The log of the consensus, the votes of the node and the snapshots of its filters are kept in
+/// a directory, for the node to rejoin the cluster with them after a restart.
+#[derive(Clone)]
+pub struct Cluster {
+    id: NodeId,
+    address: String,
+    raft: Raft,
+    client: reqwest::Client,
+    key: Option<String>,
+    /// The routes serving the commands once committed, set once the application is created.
+    routes: Arc<watch::Sender<Option<Router>>>,
+}
+
+impl fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cluster").field("id", &self.id).finish()
+    }
+}
+
+impl Cluster {
+    /// Starts the node `member` of a cluster, keeping the filters of `state` and its consensus in
+    /// `dir`, and calling the other nodes with the admin key `key`, if they require one.
+    ///
+    /// The node waits for an [initialization](Cluster::initialize), or to be
+    /// [added](Cluster::add_node) to a cluster, unless it is a member already.
+    pub async fn start(
+        state: SharedState,
+        member: ClusterMember,
+        dir: &Path,
+        key: Option<String>,
+    ) -> Result<Self, Error> {
+        let storage = |e: io::Error| {
+            Error::Storage(format!(
+                "Cannot read the cluster directory '{}': {e}",
+                dir.display()
+            ))
+        };
+        fs::create_dir_all(dir).map_err(storage)?;
+        // Only the leader expires and evicts filters, committing them for every node to
+        state.clustered.store(true, Ordering::Relaxed);
+        for namespace in state.namespaces.read().values() {
+            namespace.clustered.store(true, Ordering::Relaxed);
+        }
+        let log = LogStore::open(dir).map_err(storage)?;
+        let (routes, served) = watch::channel(None);
+        let machine = StateMachine::open(state, dir, served).map_err(storage)?;
+        let client = reqwest::Client::new();
+        let network = Network {
+            client: client.clone(),
+            key: key.clone(),
+        };
+        let config = openraft::Config {
+            cluster_name: "bloomsrv".to_string(),
+            heartbeat_interval: HEARTBEAT_INTERVAL,
+            election_timeout_min: ELECTION_TIMEOUT_MIN,
+            election_timeout_max: ELECTION_TIMEOUT_MAX,
+            snapshot_policy: SnapshotPolicy::LogsSinceLast(SNAPSHOT_ENTRIES),
+            max_in_snapshot_log_to_keep: KEPT_ENTRIES,
+            ..Default::default()
+        }
+        .validate()
+        .map_err(|e| Error::InvalidParameters(e.to_string()))?;
+        let raft = Raft::new(member.id, Arc::new(config), network, log, machine)
+            .await
+            .map_err(|e| Error::Unavailable(e.to_string()))?;
+        let cluster = Cluster {
+            id: member.id,
+            address: member.address.trim_end_matches('/').to_string(),
+            raft,
+            client,
+            key,
+            routes: Arc::new(routes),
+        };
+        Ok(cluster)
+    }
+
+    /// Serves the commands committed with `routes` from now on.
+    pub(crate) fn serve(&self, routes: Router) {
+        self.routes.send_replace(Some(routes));
+    }
+
+    /// Initializes a cluster of this node alone, which the other nodes are then added to.
+    ///
+    /// Fails with [`Error::Forbidden`] if the node is a member of a cluster already.
+    pub async fn initialize(&self) -> Result<(), Error> {
+        let members = BTreeMap::from([(self.id, BasicNode::new(&self.address))]);
+        match self.raft.initialize(members).await {
+            Ok(()) => Ok(()),
+            Err(RaftError::APIError(InitializeError::NotAllowed(_))) => Err(Error::Forbidden(
+                "This node is a member of a cluster already".to_string(),
+            )),
+            Err(error) => Err(Error::InvalidParameters(error.to_string())),
+        }
+    }
+
+    /// Stops taking part in the consensus, e.g. as the service stops; the node is still a member
+    /// of the cluster, and takes part again once started with the same directory.
+    pub async fn shutdown(&self) {
+        if let Err(error) = self.raft.shutdown().await {
+            tracing::warn!(%error, "Cannot leave the consensus of the cluster");
+        }
+    }
+
+    /// Returns the state of the cluster, as this node sees it.
+    pub fn status(&self) -> ClusterStatus {
+        let metrics = self.raft.metrics().borrow().clone();
+        let membership = metrics.membership_config.membership();
+        let voters: HashSet<NodeId> = membership.voter_ids().collect();
+        let role = match metrics.state {
+            ServerState::Leader => "leader",
+            ServerState::Follower => "follower",
+            ServerState::Candidate => "candidate",
+            ServerState::Learner => "learner",
+            ServerState::Shutdown => "shutdown",
+        };
+        ClusterStatus {
+            id: self.id,
+            role: role.to_string(),
+            leader: metrics.current_leader,
+            term: metrics.current_term,
+            last_log_index: metrics.last_log_index,
+            last_applied_index: metrics.last_applied.map(|log_id| log_id.index),
+            nodes: membership
+                .nodes()
+                .map(|(id, node)| ClusterNode {
+                    id: *id,
+                    address: node.addr.clone(),
+                    voter: voters.contains(id),
+                })
+                .collect(),
+        }
+    }
+
+    /// Commits a request through the consensus, and returns the response of this node once it
+    /// has served it; forwarded to the leader if this node is not.
+    pub(crate) async fn commit(&self, request: Request) -> Result<Outcome, Error> {
+        let (parts, body) = request.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .map_err(|e| Error::InvalidParameters(format!("Cannot read the request: {e}")))?;
+        let mut command = Command::new(
+            parts.method.to_string(),
+            parts
+                .uri
+                .path_and_query()
+                .map_or_else(|| parts.uri.path().to_string(), |uri| uri.to_string()),
+            parts
+                .headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            body.to_vec(),
+        );
+        // The secret of a key is generated here, and only its hash committed, for the log and
+        // the other nodes not to hold it
+        let secret = (parts.method == axum::http::Method::POST && parts.uri.path() == KEYS_PATH)
+            .then(new_secret);
+        command.secret_hash = secret.as_deref().map(hash_secret);
+        let outcome = match self.raft.client_write(command.clone()).await {
+            Ok(written) => written.data,
+            Err(error) => match leader(&error) {
+                Some(leader) => {
+                    let path = "/admin/cluster/raft/write";
+                    self.forward(&leader, reqwest::Method::POST, path, Some(&command))
+                        .await?
+                }
+                None => return Err(write_error(error)),
+            },
+        };
+        Ok(match secret {
+            Some(secret) => outcome.with_secret(&secret),
+            None => outcome,
+        })
+    }
+
+    /// Commits a command forwarded by another node, if this node leads the cluster.
+    pub(crate) async fn write(&self, command: Command) -> Result<Outcome, Error> {
+        self.raft
+            .client_write(command)
+            .await
+            .map(|written| written.data)
+            .map_err(write_error)
+    }
+
+    /// Spawns a background task that, every `interval` while this node leads the cluster, commits
+    /// the expiries of the filters of `state` and of its namespaces, and then the evictions
+    /// enforcing their memory budget, which every node applies alike.
+    ///
+    /// Takes the place of [`spawn_expiry_task`](crate::spawn_expiry_task) for the expiries and
+    /// evictions, which it leaves to the leader in a clustered store. Nothing expires in
+    /// read-only mode.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn_expiry_task(
+        &self,
+        state: SharedState,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let cluster = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if cluster.raft.metrics().borrow().state != ServerState::Leader {
+                    continue;
+                }
+                let mut stores = vec![(None, state.clone())];
+                stores.extend(
+                    state
+                        .namespaces
+                        .read()
+                        .iter()
+                        .map(|(name, store)| (Some(name.clone()), store.clone())),
+                );
+                let frozen = state.read_only_mode.load(Ordering::Relaxed);
+                for (namespace, store) in stores {
+                    if !frozen {
+                        let (removed, cleared) = store.expiring(Instant::now());
+                        let expiries = Expiries {
+                            namespace: namespace.clone(),
+                            removed: names(removed),
+                            cleared: names(cleared),
+                            ..Default::default()
+                        };
+                        cluster.commit_expiries(&expiries).await;
+                    }
+                    // Selected once the expired filters are gone, not to evict more than needed
+                    let expiries = Expiries {
+                        namespace,
+                        evicted: names(store.over_budget()),
+                        ..Default::default()
+                    };
+                    cluster.commit_expiries(&expiries).await;
+                }
+            }
+        })
+    }
+
+    /// Commits expiries and evictions, if any, logging the failure to.
+    async fn commit_expiries(&self, expiries: &Expiries) {
+        if expiries.is_empty() {
+            return;
+        }
+        let command = Command::new(
+            "POST".to_string(),
+            EXPIRE_PATH.to_string(),
+            Some("application/json".to_string()),
+            serde_json::to_vec(expiries).unwrap(),
+        );
+        if let Err(error) = self.write(command).await {
+            tracing::warn!(%error, "Cannot commit the expiry of the filters");
+        }
+    }
+
+    /// Adds a node to the cluster, as a voter once it has caught up with the log; forwarded to
+    /// the leader if this node is not.
+    pub(crate) async fn add_node(&self, member: ClusterMember) -> Result<Outcome, Error> {
+        let address = member.address.trim_end_matches('/');
+        if !address.starts_with("http://") && !address.starts_with("https://") {
+            return Err(Error::InvalidParameters(format!(
+                "Invalid address '{address}': expected an http:// URL"
+            )));
+        }
+        let added = async {
+            self.raft
+                .add_learner(member.id, BasicNode::new(address), true)
+                .await?;
+            let voters = BTreeSet::from([member.id]);
+            self.raft
+                .change_membership(ChangeMembers::AddVoterIds(voters), false)
+                .await
+        };
+        match added.await {
+            Ok(_) => Ok(Outcome::message(format!(
+                "Node {} added to the cluster",
+                member.id
+            ))),
+            Err(error) => match leader(&error) {
+                Some(leader) => {
+                    let path = "/admin/cluster/nodes";
+                    self.forward(&leader, reqwest::Method::POST, path, Some(&member))
+                        .await
+                }
+                None => Err(write_error(error)),
+            },
+        }
+    }
+
+    /// Removes a node from the cluster; forwarded to the leader if this node is not.
+    pub(crate) async fn remove_node(&self, id: u64) -> Result<Outcome, Error> {
+        let metrics = self.raft.metrics().borrow().clone();
+        let membership = metrics.membership_config.membership();
+        let change = if membership.voter_ids().any(|voter| voter == id) {
+            ChangeMembers::RemoveVoters(BTreeSet::from([id]))
+        } else if membership.nodes().any(|(node, _)| *node == id) {
+            ChangeMembers::RemoveNodes(BTreeSet::from([id]))
+        } else {
+            return Err(Error::InvalidParameters(format!(
+                "Node {id} is not a member of the cluster"
+            )));
+        };
+        match self.raft.change_membership(change, false).await {
+            Ok(_) => Ok(Outcome::message(format!(
+                "Node {id} removed from the cluster"
+            ))),
+            Err(error) => match leader(&error) {
+                Some(leader) => {
+                    let path = format!("/admin/cluster/nodes/{id}");
+                    self.forward(&leader, reqwest::Method::DELETE, &path, None::<&()>)
+                        .await
+                }
+                None => Err(write_error(error)),
+            },
+        }
+    }
+
+    /// Answers a call of the consensus from another node: `append` (entries of the log, or
+    /// heartbeats), `vote` or `snapshot` (a chunk of a snapshot of the filters).
+    pub(crate) async fn answer(&self, call: &str, body: &[u8]) -> Result<serde_json::Value, Error> {
+        fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, Error> {
+            serde_json::from_slice(body).map_err(|e| Error::InvalidParameters(e.to_string()))
+        }
+        let answer = match call {
+            "append" => serde_json::to_value(self.raft.append_entries(parse(body)?).await),
+            "vote" => serde_json::to_value(self.raft.vote(parse(body)?).await),
+            "snapshot" => serde_json::to_value(self.raft.install_snapshot(parse(body)?).await),
+            _ => {
+                return Err(Error::InvalidParameters(format!(
+                    "Unknown call of the consensus '{call}'"
+                )))
+            }
+        };
+        answer.map_err(|e| Error::Storage(e.to_string()))
+    }
+
+    /// Sends a request to the leader, returning its response.
+    async fn forward(
+        &self,
+        leader: &BasicNode,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&impl Serialize>,
+    ) -> Result<Outcome, Error> {
+        let mut request = self
+            .client
+            .request(method, format!("{}{path}", leader.addr))
+            .timeout(FORWARD_TIMEOUT);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        if let Some(key) = &self.key {
+            request = request.bearer_auth(key);
+        }
+        let unreachable = |e: reqwest::Error| {
+            Error::Unavailable(format!(
+                "Cannot reach the leader of the cluster at {}: {e}",
+                leader.addr
+            ))
+        };
+        let response = request.send().await.map_err(unreachable)?;
+        let status = response.status().as_u16();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response.bytes().await.map_err(unreachable)?.to_vec();
+        Ok(Outcome {
+            status,
+            content_type,
+            body,
+        })
+    }
+}
+
+/// Returns the leader a change must be forwarded to, if the error is that this node is not the
+/// leader, and the leader is known.
+fn leader(error: &RaftError<NodeId, ClientWriteError<NodeId, BasicNode>>) -> Option<BasicNode> {
+    match error.forward_to_leader() {
+        Some(ForwardToLeader {
+            leader_node: Some(leader),
+            ..
+        }) => Some(leader.clone()),
+        _ => None,
+    }
+}
+
+fn write_error(error: RaftError<NodeId, ClientWriteError<NodeId, BasicNode>>) -> Error {
+    match error {
+        RaftError::APIError(ClientWriteError::ForwardToLeader(_)) => {
+            Error::Unavailable("The cluster has no leader at the moment, retry later".to_string())
+        }
+        RaftError::APIError(ClientWriteError::ChangeMembershipError(error)) => {
+            Error::InvalidParameters(error.to_string())
+        }
+        RaftError::Fatal(error) => Error::Unavailable(error.to_string()),
+    }
+}
+
+// --- Log ---
+
+/// A line of the log file: an entry, or the last entry purged (once in a snapshot).
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LogLine {
+    Entry(Entry<TypeConfig>),
+    Purged(LogId<NodeId>),
+}
+
+#[derive(Default)]
+struct Log {
+    vote: Option<Vote<NodeId>>,
+    entries: BTreeMap<u64, Entry<TypeConfig>>,
+    last_purged: Option<LogId<NodeId>>,
+}
+
+/// The log of the consensus, kept in memory and appended to a file, which is written again
+/// whole when entries are truncated or purged.
+#[derive(Clone)]
+struct LogStore {
+    dir: PathBuf,
+    log: Arc<Mutex<Log>>,
+}
+
+impl LogStore {
+    fn open(dir: &Path) -> io::Result<Self> {
+        let mut log = Log::default();
+        match fs::read(dir.join(VOTE_FILE)) {
+            Ok(vote) => log.vote = Some(serde_json::from_slice(&vote)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        match fs::File::open(dir.join(LOG_FILE)) {
+            Ok(file) => {
+                for line in io::BufReader::new(file).lines() {
+                    match serde_json::from_str(&line?)? {
+                        LogLine::Entry(entry) => {
+                            log.entries.insert(entry.log_id.index, entry);
+                        }
+                        LogLine::Purged(log_id) => log.last_purged = Some(log_id),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(LogStore {
+            dir: dir.to_path_buf(),
+            log: Arc::new(Mutex::new(log)),
+        })
+    }
+
+    /// Writes the log file again, with the entries left.
+    fn rewrite(&self, log: &Log) -> io::Result<()> {
+        let mut lines = Vec::new();
+        if let Some(log_id) = log.last_purged {
+            serde_json::to_writer(&mut lines, &LogLine::Purged(log_id))?;
+            lines.push(b'\n');
+        }
+        for entry in log.entries.values() {
+            serde_json::to_writer(&mut lines, &LogLine::Entry(entry.clone()))?;
+            lines.push(b'\n');
+        }
+        write_file(&self.dir, LOG_FILE, &lines)
+    }
+}
+
+impl RaftLogReader<TypeConfig> for LogStore {
+    async fn try_get_log_entries<R: RangeBounds<u64> + Clone + fmt::Debug + Send>(
+        &mut self,
+        range: R,
+    ) -> Result<Vec<Entry<TypeConfig>>, StorageError<NodeId>> {
+        let log = self.log.lock();
+        Ok(log.entries.range(range).map(|(_, e)| e.clone()).collect())
+    }
+}
+
+impl RaftLogStorage<TypeConfig> for LogStore {
+    type LogReader = LogStore;
+
+    async fn get_log_state(&mut self) -> Result<LogState<TypeConfig>, StorageError<NodeId>> {
+        let log = self.log.lock();
+        let last = log.entries.values().next_back().map(|entry| entry.log_id);
+        Ok(LogState {
+            last_purged_log_id: log.last_purged,
+            last_log_id: last.or(log.last_purged),
+        })
+    }
+
+    async fn get_log_reader(&mut self) -> Self::LogReader {
+        self.clone()
+    }
+
+    async fn save_vote(&mut self, vote: &Vote<NodeId>) -> Result<(), StorageError<NodeId>> {
+        let json = serde_json::to_vec(vote).map_err(|e| StorageIOError::write_vote(&e))?;
+        write_file(&self.dir, VOTE_FILE, &json).map_err(|e| StorageIOError::write_vote(&e))?;
+        self.log.lock().vote = Some(*vote);
+        Ok(())
+    }
+
+    async fn read_vote(&mut self) -> Result<Option<Vote<NodeId>>, StorageError<NodeId>> {
+        Ok(self.log.lock().vote)
+    }
+
+    async fn append<I>(
+        &mut self,
+        entries: I,
+        callback: LogFlushed<TypeConfig>,
+    ) -> Result<(), StorageError<NodeId>>
+    where
+        I: IntoIterator<Item = Entry<TypeConfig>> + Send,
+        I::IntoIter: Send,
+    {
+        let mut log = self.log.lock();
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, &LogLine::Entry(entry.clone()))
+                .map_err(|e| StorageIOError::write_logs(&e))?;
+            lines.push(b'\n');
+            log.entries.insert(entry.log_id.index, entry);
+        }
+        let appended = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(LOG_FILE))
+            .and_then(|mut file| {
+                file.write_all(&lines)?;
+                file.sync_data()
+            });
+        callback.log_io_completed(appended.map_err(|e| io::Error::new(e.kind(), e.to_string())));
+        Ok(())
+    }
+
+    async fn truncate(&mut self, log_id: LogId<NodeId>) -> Result<(), StorageError<NodeId>> {
+        let mut log = self.log.lock();
+        log.entries.split_off(&log_id.index);
+        self.rewrite(&log)
+            .map_err(|e| StorageIOError::write_logs(&e).into())
+    }
+
+    async fn purge(&mut self, log_id: LogId<NodeId>) -> Result<(), StorageError<NodeId>> {
+        let mut log = self.log.lock();
+        log.entries = log.entries.split_off(&(log_id.index + 1));
+        log.last_purged = Some(log_id);
+        self.rewrite(&log)
+            .map_err(|e| StorageIOError::write_logs(&e).into())
+    }
+}
+
+// --- State machine ---
+
+/// A snapshot of the filters, as kept by a node.
+#[derive(Clone)]
+struct StoredSnapshot {
+    meta: SnapshotMeta<NodeId, BasicNode>,
+    data: Vec<u8>,
+}
+
+/// The filters of a node, changed by serving the commands committed.
+struct StateMachine {
+    state: SharedState,
+    dir: PathBuf,
+    routes: watch::Receiver<Option<Router>>,
+    last_applied: Option<LogId<NodeId>>,
+    membership: StoredMembership<NodeId, BasicNode>,
+    snapshot: Arc<Mutex<Option<StoredSnapshot>>>,
+}
+
+impl StateMachine {
+    /// Opens the state machine of `state`, restoring its filters from the last snapshot kept in
+    /// `dir`, if any; the commands committed since are served again.
+    fn open(
+        state: SharedState,
+        dir: &Path,
+        routes: watch::Receiver<Option<Router>>,
+    ) -> io::Result<Self> {
+        let mut machine = StateMachine {
+            state,
+            dir: dir.to_path_buf(),
+            routes,
+            last_applied: None,
+            membership: StoredMembership::default(),
+            snapshot: Arc::default(),
+        };
+        let meta = match fs::read(dir.join(SNAPSHOT_META_FILE)) {
+            Ok(meta) => serde_json::from_slice::<SnapshotMeta<NodeId, BasicNode>>(&meta)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(machine),
+            Err(e) => return Err(e),
+        };
+        let data = fs::read(dir.join(SNAPSHOT_FILE))?;
+        restore(&machine.state, &data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        machine.last_applied = meta.last_log_id;
+        machine.membership = meta.last_membership.clone();
+        *machine.snapshot.lock() = Some(StoredSnapshot { meta, data });
+        Ok(machine)
+    }
+
+    /// Serves a command with the routes of the application, as it was served by the node it was
+    /// sent to.
+    async fn serve(&mut self, command: Command) -> Outcome {
+        // Commands committed before the application is created wait for it
+        let routes = match self.routes.wait_for(Option::is_some).await {
+            Ok(routes) => routes.clone().unwrap_or_default(),
+            Err(_) => return Outcome::default(),
+        };
+        let mut request = Request::builder()
+            .method(command.method.as_str())
+            .uri(command.uri.as_str());
+        if let Some(content_type) = &command.content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        let Ok(request) = request.body(Body::from(command.body)) else {
+            return Outcome::default();
+        };
+        let served = SEED.scope(Cell::new(command.seed), routes.oneshot(request));
+        let served = SECRET_HASH.scope(command.secret_hash, served);
+        match served.await {
+            Ok(response) => Outcome::of(response).await,
+            Err(never) => match never {},
+        }
+    }
+
+    fn keep(&self, snapshot: StoredSnapshot) -> io::Result<()> {
+        keep_snapshot(&self.dir, &snapshot)?;
+        *self.snapshot.lock() = Some(snapshot);
+        Ok(())
+    }
+}
+
+impl RaftStateMachine<TypeConfig> for StateMachine {
+    type SnapshotBuilder = SnapshotBuilder;
+
+    async fn applied_state(
+        &mut self,
+    ) -> Result<(Option<LogId<NodeId>>, StoredMembership<NodeId, BasicNode>), StorageError<NodeId>>
+    {
+        Ok((self.last_applied, self.membership.clone()))
+    }
+
+    async fn apply<I>(&mut self, entries: I) -> Result<Vec<Outcome>, StorageError<NodeId>>
+    where
+        I: IntoIterator<Item = Entry<TypeConfig>> + Send,
+        I::IntoIter: Send,
+    {
+        let mut outcomes = Vec::new();
+        for entry in entries {
+            self.last_applied = Some(entry.log_id);
+            let outcome = match entry.payload {
+                EntryPayload::Blank => Outcome::default(),
+                EntryPayload::Normal(command) => self.serve(command).await,
+                EntryPayload::Membership(membership) => {
+                    self.membership = StoredMembership::new(Some(entry.log_id), membership);
+                    Outcome::default()
+                }
+            };
+            outcomes.push(outcome);
+        }
+        Ok(outcomes)
+    }
+
+    async fn get_snapshot_builder(&mut self) -> Self::SnapshotBuilder {
+        // Copied now, as the commands applied meanwhile would not be in it otherwise
+        let last_log_id = self.last_applied;
+        let snapshot_id = match last_log_id {
+            Some(log_id) => format!(
+                "{}-{}-{}",
+                log_id.leader_id.term,
+                log_id.index,
+                Uuid::new_v4()
+            ),
+            None => format!("0-0-{}", Uuid::new_v4()),
+        };
+        SnapshotBuilder {
+            snapshot: StoredSnapshot {
+                meta: SnapshotMeta {
+                    last_log_id,
+                    last_membership: self.membership.clone(),
+                    snapshot_id,
+                },
+                data: snapshot(&self.state),
+            },
+            dir: self.dir.clone(),
+            kept: self.snapshot.clone(),
+        }
+    }
+
+    async fn begin_receiving_snapshot(
+        &mut self,
+    ) -> Result<Box<Cursor<Vec<u8>>>, StorageError<NodeId>> {
+        Ok(Box::new(Cursor::new(Vec::new())))
+    }
+
+    async fn install_snapshot(
+        &mut self,
+        meta: &SnapshotMeta<NodeId, BasicNode>,
+        snapshot: Box<Cursor<Vec<u8>>>,
+    ) -> Result<(), StorageError<NodeId>> {
+        let data = snapshot.into_inner();
+        restore(&self.state, &data).map_err(|e| StorageIOError::write_snapshot(None, &e))?;
+        self.last_applied = meta.last_log_id;
+        self.membership = meta.last_membership.clone();
+        let meta = meta.clone();
+        self.keep(StoredSnapshot { meta, data })
+            .map_err(|e| StorageIOError::write_snapshot(None, &e).into())
+    }
+
+    async fn get_current_snapshot(
+        &mut self,
+    ) -> Result<Option<Snapshot<TypeConfig>>, StorageError<NodeId>> {
+        Ok(self.snapshot.lock().clone().map(|snapshot| Snapshot {
+            meta: snapshot.meta,
+            snapshot: Box::new(Cursor::new(snapshot.data)),
+        }))
+    }
+}
+
+/// Keeps a snapshot copied by the state machine, once the consensus asks for it.
+struct SnapshotBuilder {
+    snapshot: StoredSnapshot,
+    dir: PathBuf,
+    kept: Arc<Mutex<Option<StoredSnapshot>>>,
+}
+
+impl RaftSnapshotBuilder<TypeConfig> for SnapshotBuilder {
+    async fn build_snapshot(&mut self) -> Result<Snapshot<TypeConfig>, StorageError<NodeId>> {
+        let signature = self.snapshot.meta.signature();
+        keep_snapshot(&self.dir, &self.snapshot)
+            .map_err(|e| StorageIOError::write_snapshot(Some(signature), &e))?;
+        *self.kept.lock() = Some(self.snapshot.clone());
+        Ok(Snapshot {
+            meta: self.snapshot.meta.clone(),
+            snapshot: Box::new(Cursor::new(self.snapshot.data.clone())),
+        })
+    }
+}
+
+/// Writes a snapshot to `dir`, its filters before its metadata.
+fn keep_snapshot(dir: &Path, snapshot: &StoredSnapshot) -> io::Result<()> {
+    write_file(dir, SNAPSHOT_FILE, &snapshot.data)?;
+    write_file(
+        dir,
+        SNAPSHOT_META_FILE,
+        &serde_json::to_vec(&snapshot.meta)?,
+    )
+}
+
+/// Copies what the commands change in a store and its namespaces: a section for each, of the
+/// name of the namespace (empty for the default namespace) and the [records](crate::wal::encode_record) of
+/// the operations setting a copy of it, each prefixed with its length.
+fn snapshot(state: &FilterStore) -> Vec<u8> {
+    let namespaces: Vec<(String, SharedState)> = state
+        .namespaces
+        .read()
+        .iter()
+        .map(|(name, namespace)| (name.clone(), namespace.clone()))
+        .collect();
+    let mut data = Vec::new();
+    let stores = std::iter::once(("", state)).chain(
+        namespaces
+            .iter()
+            .map(|(name, namespace)| (name.as_str(), &**namespace)),
+    );
+    for (name, store) in stores {
+        let mut records = Vec::new();
+        store.replicate(|record| {
+            records.extend_from_slice(&record);
+            true
+        });
+        data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&(records.len() as u64).to_le_bytes());
+        data.extend_from_slice(&records);
+    }
+    data
+}
+
+/// Replaces what the commands change in a store and its namespaces with a [snapshot].
+fn restore(state: &FilterStore, mut data: &[u8]) -> Result<(), Error> {
+    let malformed = || Error::Storage("Malformed snapshot of the cluster".to_string());
+    let mut sections = Vec::new();
+    while !data.is_empty() {
+        let len = u32::from_le_bytes(data.get(..4).ok_or_else(malformed)?.try_into().unwrap());
+        let (name, rest) = data[4..]
+            .split_at_checked(len as usize)
+            .ok_or_else(malformed)?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| malformed())?;
+        let len = u64::from_le_bytes(rest.get(..8).ok_or_else(malformed)?.try_into().unwrap());
+        let (mut records, rest) = rest[8..]
+            .split_at_checked(len as usize)
+            .ok_or_else(malformed)?;
+        let copy = FilterStore::default();
+        while !records.is_empty() {
+            let (operation, rest) = next_record(records).ok_or_else(malformed)?;
+            copy.apply_replicated(operation);
+            records = rest;
+        }
+        sections.push((name, copy));
+        data = rest;
+    }
+
+    let names: HashSet<String> = sections.iter().map(|(name, _)| name.clone()).collect();
+    let deleted: Vec<String> = state
+        .namespaces
+        .read()
+        .keys()
+        .filter(|name| !names.contains(*name))
+        .cloned()
+        .collect();
+    for name in deleted {
+        state.delete_namespace(&name)?;
+    }
+    for (name, copy) in sections {
+        if name.is_empty() {
+            state.replace_with(copy);
+            state.notify_inserted();
+        } else {
+            let namespace = match state.namespace(&name) {
+                Ok(namespace) => namespace,
+                Err(_) => state.create_namespace(&name)?,
+            };
+            namespace.replace_with(copy);
+            namespace.notify_inserted();
+        }
+    }
+    Ok(())
+}
+
+/// Writes a file of the cluster directory, replacing it at once.
+fn write_file(dir: &Path, name: &str, bytes: &[u8]) -> io::Result<()> {
+    let path = dir.join(name);
+    let temporary = dir.join(format!("{name}.tmp"));
+    let mut file = fs::File::create(&temporary)?;
+    file.write_all(bytes)?;
+    file.sync_data()?;
+    fs::rename(temporary, path)
+}
+
+// --- Network ---
+
+/// Calls the other nodes over HTTP, at `/admin/cluster/raft/<call>`.
+struct Network {
+    client: reqwest::Client,
+    key: Option<String>,
+}
+
+impl RaftNetworkFactory<TypeConfig> for Network {
+    type Network = Peer;
+
+    async fn new_client(&mut self, target: NodeId, node: &BasicNode) -> Self::Network {
+        Peer {
+            client: self.client.clone(),
+            key: self.key.clone(),
+            target,
+            address: node.addr.clone(),
+        }
+    }
+}
+
+struct Peer {
+    client: reqwest::Client,
+    key: Option<String>,
+    target: NodeId,
+    address: String,
+}
+
+impl Peer {
+    async fn call<T, R, E>(
+        &self,
+        call: &str,
+        rpc: &T,
+        option: &RPCOption,
+    ) -> Result<R, RPCError<NodeId, BasicNode, E>>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+        E: std::error::Error + DeserializeOwned,
+    {
+        let url = format!("{}/admin/cluster/raft/{call}", self.address);
+        let mut request = self.client.post(url).timeout(option.hard_ttl()).json(rpc);
+        if let Some(key) = &self.key {
+            request = request.bearer_auth(key);
+        }
+        let response = request.send().await.map_err(|e| match e.is_connect() {
+            true => RPCError::Unreachable(Unreachable::new(&e)),
+            false => RPCError::Network(NetworkError::new(&e)),
+        })?;
+        if !response.status().is_success() {
+            let reason = io::Error::other(format!(
+                "Node {} answered with {}",
+                self.target,
+                response.status()
+            ));
+            return Err(RPCError::Network(NetworkError::new(&reason)));
+        }
+        let answer: Result<R, E> = response
+            .json()
+            .await
+            .map_err(|e| RPCError::Network(NetworkError::new(&e)))?;
+        answer.map_err(|e| RPCError::RemoteError(RemoteError::new(self.target, e)))
+    }
+}
+
+impl RaftNetwork<TypeConfig> for Peer {
+    async fn append_entries(
+        &mut self,
+        rpc: AppendEntriesRequest<TypeConfig>,
+        option: RPCOption,
+    ) -> Result<AppendEntriesResponse<NodeId>, RPCError<NodeId, BasicNode, RaftError<NodeId>>> {
+        self.call("append", &rpc, &option).await
+    }
+
+    async fn install_snapshot(
+        &mut self,
+        rpc: InstallSnapshotRequest<TypeConfig>,
+        option: RPCOption,
+    ) -> Result<
+        InstallSnapshotResponse<NodeId>,
+        RPCError<NodeId, BasicNode, RaftError<NodeId, InstallSnapshotError>>,
+    > {
+        self.call("snapshot", &rpc, &option).await
+    }
+
+    async fn vote(
+        &mut self,
+        rpc: VoteRequest<NodeId>,
+        option: RPCOption,
+    ) -> Result<VoteResponse<NodeId>, RPCError<NodeId, BasicNode, RaftError<NodeId>>> {
+        self.call("vote", &rpc, &option).await
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt;
+
+#[cfg(feature = "server")]
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+
+/// Errors returned by [`FilterStore`](crate::FilterStore) operations.
+///
+/// Every error has a stable, machine-readable [`code`](Error::code). With the `server` feature,
+/// errors also convert into HTTP responses with a matching status code, and a JSON body
+/// carrying both the message and the code.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::Error;
+///
+/// let error = Error::FilterNotFound("login_attempts".to_string());
+/// assert_eq!(error.code(), "filter_not_found");
+/// assert_eq!(error.to_string(), "Filter 'login_attempts' not found");
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A filter with this name already exists.
+    FilterExists(String),
+    /// No filter with this name (or id) exists.
+    FilterNotFound(String),
+    /// A group with this name already exists.
+    GroupExists(String),
+    /// No group with this name exists.
+    GroupNotFound(String),
+    /// A rate limiter with this name already exists.
+    LimiterExists(String),
+    /// No rate limiter with this name exists.
+    LimiterNotFound(String),
+    /// A recent filter with this name already exists.
+    RecentExists(String),
+    /// No recent filter with this name exists.
+    RecentNotFound(String),
+    /// A sketch with this name already exists.
+    SketchExists(String),
+    /// No sketch with this name exists.
+    SketchNotFound(String),
+    /// A HyperLogLog with this name already exists.
+    CardinalityExists(String),
+    /// No HyperLogLog with this name exists.
+    CardinalityNotFound(String),
+    /// A namespace with this name already exists.
+    NamespaceExists(String),
+    /// No namespace with this name exists.
+    NamespaceNotFound(String),
+    /// A group refers to a filter that does not exist.
+    GroupMemberNotFound { group: String, filter: String },
+    /// The filter has no shadow filter.
+    NoShadow(String),
+    /// The filter has no transform.
+    NoTransform(String),
+    /// The transform of the filter rejected the item.
+    ItemRejected(String),
+    /// The filter cannot remove items, as it is not a counting filter.
+    RemovalUnsupported(String),
+    /// The transform of the filter failed to process the item.
+    TransformFailed { filter: String, reason: String },
+    /// The name does not follow the [`NamePolicy`](crate::NamePolicy) of the store.
+    InvalidName { name: String, reason: String },
+    /// The request is invalid.
+    InvalidParameters(String),
+    /// The operation is not enabled on this server.
+    Forbidden(String),
+    /// The filters could not be read from or written to the disk (e.g. to the write-ahead log).
+    Storage(String),
+    /// The parameters of these existing filters cannot be changed.
+    ParameterConflict(Vec<String>),
+    /// The filter no longer has the entity tag a change was conditional on, as it was cleared,
+    /// replaced or created again since it was read.
+    PreconditionFailed(String),
+    /// The filter is larger than the [quotas](crate::Quotas) of the store allow a filter to be.
+    FilterTooLarge(String),
+    /// The change would take the store over its [quotas](crate::Quotas) on the number of filters
+    /// or their memory, or the memory of the filter cannot be allocated.
+    QuotaExceeded(String),
+    /// The service cannot serve requests yet, e.g. while it restores the saved filters.
+    Unavailable(String),
+    /// The request carries no valid API key.
+    Unauthorized(String),
+    /// No API key with this id exists.
+    ApiKeyNotFound(String),
+    /// No job with this id exists (any longer).
+    JobNotFound(String),
+    /// The client made too many requests, and may retry after this many seconds.
+    RateLimited { retry_after: u64 },
+    /// The body of the request is larger than this many bytes.
+    PayloadTooLarge { limit: usize },
+    /// The request did not complete within this many seconds.
+    Timeout { seconds: f64 },
+    /// The body of the request is compressed with an encoding the service does not accept.
+    UnsupportedEncoding(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::FilterExists(name) => {
+                write!(f, "Cannot create filter '{name}', name is already in use")
+            }
+            Error::FilterNotFound(name) => write!(f, "Filter '{name}' not found"),
+            Error::GroupExists(name) => {
+                write!(f, "Cannot create group '{name}', name is already in use")
+            }
+            Error::GroupNotFound(name) => write!(f, "Group '{name}' not found"),
+            Error::LimiterExists(name) => {
+                write!(f, "Cannot create limiter '{name}', name is already in use")
+            }
+            Error::LimiterNotFound(name) => write!(f, "Limiter '{name}' not found"),
+            Error::RecentExists(name) => {
+                write!(
+                    f,
+                    "Cannot create recent filter '{name}', name is already in use"
+                )
+            }
+            Error::RecentNotFound(name) => write!(f, "Recent filter '{name}' not found"),
+            Error::SketchExists(name) => {
+                write!(f, "Cannot create sketch '{name}', name is already in use")
+            }
+            Error::SketchNotFound(name) => write!(f, "Sketch '{name}' not found"),
+            Error::CardinalityExists(name) => {
+                write!(
+                    f,
+                    "Cannot create cardinality '{name}', name is already in use"
+                )
+            }
+            Error::CardinalityNotFound(name) => write!(f, "Cardinality '{name}' not found"),
+            Error::ApiKeyNotFound(id) => write!(f, "API key '{id}' not found"),
+            Error::JobNotFound(id) => write!(f, "Job '{id}' not found"),
+            Error::NamespaceExists(name) => {
+                write!(
+                    f,
+                    "Cannot create namespace '{name}', name is already in use"
+                )
+            }
+            Error::NamespaceNotFound(name) => write!(f, "Namespace '{name}' not found"),
+            Error::GroupMemberNotFound { group, filter } => {
+                write!(f, "Group '{group}': filter '{filter}' not found")
+            }
+            Error::NoShadow(name) => write!(f, "Filter '{name}' has no shadow filter"),
+            Error::NoTransform(name) => write!(f, "Filter '{name}' has no transform"),
+            Error::ItemRejected(name) => {
+                write!(f, "Item rejected by the transform of filter '{name}'")
+            }
+            Error::RemovalUnsupported(name) => write!(
+                f,
+                "Filter '{name}' cannot remove items, only counting filters can"
+            ),
+            Error::TransformFailed { filter, reason } => {
+                write!(f, "Transform of filter '{filter}' failed: {reason}")
+            }
+            Error::InvalidName { name, reason } => write!(f, "Invalid name '{name}': {reason}"),
+            Error::InvalidParameters(reason)
+            | Error::Forbidden(reason)
+            | Error::FilterTooLarge(reason)
+            | Error::QuotaExceeded(reason)
+            | Error::Unavailable(reason)
+            | Error::Unauthorized(reason) => write!(f, "{reason}"),
+            Error::Storage(reason) => write!(f, "Storage error: {reason}"),
+            Error::ParameterConflict(_) => {
+                write!(f, "The parameters of existing filters cannot be changed")
+            }
+            Error::PreconditionFailed(name) => {
+                write!(f, "Filter '{name}' has changed since it was read")
+            }
+            Error::RateLimited { retry_after } => {
+                write!(f, "Too many requests, retry in {retry_after} seconds")
+            }
+            Error::PayloadTooLarge { limit } => {
+                write!(f, "The body of the request is larger than {limit} bytes")
+            }
+            Error::Timeout { seconds } => {
+                write!(f, "The request did not complete within {seconds} seconds")
+            }
+            Error::UnsupportedEncoding(encoding) => {
+                write!(f, "Unsupported content encoding '{encoding}'")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+    /// Returns the machine-readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::FilterExists(_) => "filter_exists",
+            Error::FilterNotFound(_) => "filter_not_found",
+            Error::GroupExists(_) => "group_exists",
+            Error::GroupNotFound(_) => "group_not_found",
+            Error::LimiterExists(_) => "limiter_exists",
+            Error::LimiterNotFound(_) => "limiter_not_found",
+            Error::RecentExists(_) => "recent_exists",
+            Error::RecentNotFound(_) => "recent_not_found",
+            Error::SketchExists(_) => "sketch_exists",
+            Error::SketchNotFound(_) => "sketch_not_found",
+            Error::CardinalityExists(_) => "cardinality_exists",
+            Error::CardinalityNotFound(_) => "cardinality_not_found",
+            Error::NamespaceExists(_) => "namespace_exists",
+            Error::NamespaceNotFound(_) => "namespace_not_found",
+            Error::GroupMemberNotFound { .. } => "group_member_not_found",
+            Error::NoShadow(_) => "no_shadow",
+            Error::NoTransform(_) => "no_transform",
+            Error::ItemRejected(_) => "item_rejected",
+            Error::RemovalUnsupported(_) => "removal_unsupported",
+            Error::TransformFailed { .. } => "transform_failed",
+            Error::InvalidName { .. } => "invalid_name",
+            Error::InvalidParameters(_) => "invalid_parameters",
+            Error::Forbidden(_) => "forbidden",
+            Error::Storage(_) => "storage_error",
+            Error::ParameterConflict(_) => "parameter_conflict",
+            Error::PreconditionFailed(_) => "precondition_failed",
+            Error::FilterTooLarge(_) => "filter_too_large",
+            Error::QuotaExceeded(_) => "quota_exceeded",
+            Error::Unavailable(_) => "unavailable",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::ApiKeyNotFound(_) => "api_key_not_found",
+            Error::JobNotFound(_) => "job_not_found",
+            Error::RateLimited { .. } => "rate_limited",
+            Error::PayloadTooLarge { .. } => "payload_too_large",
+            Error::Timeout { .. } => "timeout",
+            Error::UnsupportedEncoding(_) => "unsupported_encoding",
+        }
+    }
+
+    /// Returns the HTTP status code corresponding to the error.
+    #[cfg(feature = "server")]
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::FilterExists(_)
+            | Error::GroupExists(_)
+            | Error::LimiterExists(_)
+            | Error::RecentExists(_)
+            | Error::SketchExists(_)
+            | Error::CardinalityExists(_)
+            | Error::NamespaceExists(_)
+            | Error::ParameterConflict(_) => StatusCode::CONFLICT,
+            Error::FilterNotFound(_)
+            | Error::GroupNotFound(_)
+            | Error::LimiterNotFound(_)
+            | Error::RecentNotFound(_)
+            | Error::SketchNotFound(_)
+            | Error::CardinalityNotFound(_)
+            | Error::NamespaceNotFound(_)
+            | Error::ApiKeyNotFound(_)
+            | Error::JobNotFound(_)
+            | Error::GroupMemberNotFound { .. }
+            | Error::NoShadow(_)
+            | Error::NoTransform(_) => StatusCode::NOT_FOUND,
+            Error::InvalidName { .. }
+            | Error::InvalidParameters(_)
+            | Error::RemovalUnsupported(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            Error::Timeout { .. } => StatusCode::REQUEST_TIMEOUT,
+            Error::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::UnsupportedEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Error::ItemRejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Error::TransformFailed { .. } | Error::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::FilterTooLarge(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::QuotaExceeded(_) => StatusCode::INSUFFICIENT_STORAGE,
+            Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// The JSON body of the error responses of the service.
+///
+/// The service adds the `request_id` of the request to it once answered.
+#[cfg(feature = "server")]
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub(crate) struct ErrorBody {
+    error: String,
+    /// The machine-readable [code](Error::code) of the error, e.g. `filter_not_found`.
+    code: &'static str,
+    /// The filters whose parameters conflict, for a `parameter_conflict`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conflicts: Option<Vec<String>>,
+}
+
+#[cfg(feature = "server")]
+impl From<&Error> for ErrorBody {
+    fn from(error: &Error) -> Self {
+        ErrorBody {
+            error: error.to_string(),
+            code: error.code(),
+            conflicts: match error {
+                Error::ParameterConflict(conflicts) => Some(conflicts.clone()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Responds with the status code of the error, and a JSON body carrying its message and code.
+///
+/// A [`Error::ParameterConflict`] additionally lists the conflicting filters, and a
+/// [`Error::RateLimited`] sets the `Retry-After` header.
+#[cfg(feature = "server")]
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let mut response = (self.status(), Json(ErrorBody::from(&self))).into_response();
+        if let Error::RateLimited { retry_after } = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
@@ -0,0 +1,177 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Serialize, Serializer};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tokio::sync::broadcast;
+
+use crate::SaturationAlert;
+
+/// Number of events kept for each subscriber; slower subscribers miss the older ones.
+const EVENT_CAPACITY: usize = 1024;
+
+/// A change in the lifecycle of a filter, as broadcast to the subscribers of a
+/// [store](crate::FilterStore::subscribe).
+///
+/// Filters removed on expiry or eviction are reported as deleted, and filters emptied on expiry as
+/// cleared. Inserts are only reported if [sampled](crate::FilterStore::sample_insert_events).
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{FilterEvent, FilterSpec, FilterStore};
+///
+/// let store = FilterStore::new();
+/// let mut events = store.subscribe();
+/// let spec = FilterSpec {
+///     name: "login_attempts".to_string(),
+///     item_count: 1000,
+///     false_positive_rate: Some(0.01),
+///     ..Default::default()
+/// };
+/// store.create(spec).unwrap();
+/// store.delete("login_attempts").unwrap();
+///
+/// let created = FilterEvent::Created {
+///     namespace: None,
+///     filter: "login_attempts".to_string(),
+/// };
+/// assert_eq!(events.try_recv().unwrap(), created);
+/// assert_eq!(events.try_recv().unwrap().kind(), "deleted");
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterEvent {
+    Created {
+        /// Namespace of the filter, or `None` for the default namespace.
+        namespace: Option<String>,
+        filter: String,
+    },
+    Deleted {
+        namespace: Option<String>,
+        filter: String,
+    },
+    Cleared {
+        namespace: Option<String>,
+        filter: String,
+    },
+    Inserted {
+        namespace: Option<String>,
+        filter: String,
+        /// The item as given, before the transform of the filter; serialized in base64.
+        #[serde(serialize_with = "as_base64")]
+        item: Vec<u8>,
+    },
+    /// The filter is filled beyond the thresholds of a [`SaturationMonitor`](crate::SaturationMonitor).
+    Saturated(SaturationAlert),
+}
+
+impl FilterEvent {
+    /// Returns the kind of the event, as serialized in its `type` field.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FilterEvent::Created { .. } => "created",
+            FilterEvent::Deleted { .. } => "deleted",
+            FilterEvent::Cleared { .. } => "cleared",
+            FilterEvent::Inserted { .. } => "inserted",
+            FilterEvent::Saturated(_) => "saturated",
+        }
+    }
+}
+
+fn as_base64<S: Serializer>(item: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&BASE64.encode(item))
+}
+
+/// The changes of a filter reported by its store itself.
+#[derive(Clone, Copy)]
+pub(crate) enum Change {
+    Created,
+    Deleted,
+    Cleared,
+}
+
+/// Broadcasts the events of the filters of a store, shared with its namespaces.
+#[derive(Debug)]
+pub(crate) struct Events {
+    sender: broadcast::Sender<FilterEvent>,
+    /// Namespace of the store, set in the events of its filters.
+    namespace: Option<String>,
+    sampling: Arc<Sampling>,
+}
+
+/// Share of the inserts reported, shared with the namespaces.
+#[derive(Debug, Default)]
+struct Sampling {
+    /// The share, as the bits of an `f64`; none if 0.
+    ratio: AtomicU64,
+    /// Inserts made since the share was set.
+    inserts: AtomicU64,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events {
+            sender: broadcast::channel(EVENT_CAPACITY).0,
+            namespace: None,
+            sampling: Arc::default(),
+        }
+    }
+}
+
+impl Events {
+    /// The events of the store of the namespace `name`, broadcast to the same subscribers.
+    pub(crate) fn for_namespace(&self, name: &str) -> Self {
+        Events {
+            sender: self.sender.clone(),
+            namespace: Some(name.to_string()),
+            sampling: self.sampling.clone(),
+        }
+    }
+
+    /// Reports `ratio` of the inserts from now on, between 0 and 1.
+    pub(crate) fn sample_inserts(&self, ratio: f64) {
+        self.sampling.inserts.store(0, Ordering::Relaxed);
+        let ratio = ratio.clamp(0.0, 1.0);
+        self.sampling
+            .ratio
+            .store(ratio.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Broadcasts the insert of `item` into the filter `filter` of the store, if sampled; the
+    /// inserts reported are spread evenly, e.g. one in ten for a share of 0.1.
+    pub(crate) fn inserted(&self, filter: &str, item: &[u8]) {
+        let ratio = f64::from_bits(self.sampling.ratio.load(Ordering::Relaxed));
+        if ratio == 0.0 || self.sender.receiver_count() == 0 {
+            return;
+        }
+        let count = self.sampling.inserts.fetch_add(1, Ordering::Relaxed) as f64;
+        if ((count + 1.0) * ratio).floor() > (count * ratio).floor() {
+            self.publish(FilterEvent::Inserted {
+                namespace: self.namespace.clone(),
+                filter: filter.to_string(),
+                item: item.to_vec(),
+            });
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<FilterEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Broadcasts an event; it is dropped if nobody subscribes.
+    pub(crate) fn publish(&self, event: FilterEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Broadcasts a change of the filter `filter` of the store.
+    pub(crate) fn changed(&self, change: Change, filter: &str) {
+        let (namespace, filter) = (self.namespace.clone(), filter.to_string());
+        self.publish(match change {
+            Change::Created => FilterEvent::Created { namespace, filter },
+            Change::Deleted => FilterEvent::Deleted { namespace, filter },
+            Change::Cleared => FilterEvent::Cleared { namespace, filter },
+        });
+    }
+}
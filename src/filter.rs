@@ -0,0 +1,2149 @@
+use serde::{Deserialize, Serialize};
+use siphasher::sip128::{Hasher128, SipHasher24};
+use std::{
+    borrow::Cow,
+    fmt,
+    hash::Hasher,
+    io,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use xxhash_rust::xxh3::xxh3_128_with_seed;
+
+#[cfg(unix)]
+use crate::mmap::MappedWords;
+use crate::{
+    interop::{murmur3_128, pybloom_indices},
+    CreationMode, Error,
+};
+
+/// Identifies a filter snapshot.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"BLOOMSRV";
+/// Version of the snapshot format.
+const SNAPSHOT_VERSION: u32 = 1;
+/// Version of the snapshot format of counting filters, whose counters follow the bits.
+const COUNTING_SNAPSHOT_VERSION: u32 = 2;
+/// Version of the snapshot format of scalable filters, whose further slices follow the bits.
+const SCALABLE_SNAPSHOT_VERSION: u32 = 3;
+/// Version of the snapshot format of stable filters, whose cells and decrements follow the bits.
+const STABLE_SNAPSHOT_VERSION: u32 = 4;
+/// Size of the decrements of a stable filter in a snapshot, in bytes.
+const DECAY_LEN: usize = 16;
+/// Size of the snapshot header, in bytes.
+const SNAPSHOT_HEADER_LEN: usize = 48;
+/// Flag of the hash scheme in the header of the files of seeded filters, whose seed follows the
+/// header.
+const SEEDED_SCHEME: u32 = 1 << 31;
+/// Flag of the hash scheme in the header of the snapshots of filters with atomic storage.
+const ATOMIC_SCHEME: u32 = 1 << 30;
+/// Flag of the hash scheme in the header of the snapshots of rotating filters, whose number of
+/// rotations, and previous generation once rotated, follow the bits.
+const ROTATING_SCHEME: u32 = 1 << 29;
+/// Size of the seed following the header of the files of seeded filters, in bytes.
+const SEED_LEN: usize = 8;
+/// Largest number of hash functions of a filter; a false positive rate never needs more.
+pub(crate) const MAX_HASH_COUNT: u32 = 1024;
+/// Largest number of bits of a filter (or slice), 128 GiB.
+pub(crate) const MAX_BIT_COUNT: u64 = 1 << 40;
+/// Fill ratio of the newest slice of a scalable filter past which the filter adds a slice.
+const SCALABLE_FILL_RATIO: f64 = 0.5;
+/// Fill ratio of the current generation of a rotating filter past which the filter rotates.
+const ROTATION_FILL_RATIO: f64 = 0.5;
+/// Maximum number of slices of a scalable filter; the last one keeps filling up.
+const MAX_SLICES: usize = 32;
+/// Identifies a memory-mapped filter file (a snapshot header followed by the words, without a
+/// checksum, as the words change in place).
+const MAPPED_MAGIC: &[u8; 8] = b"BLOOMMAP";
+
+/// How the positions of an item in a filter are derived from its bytes.
+///
+/// Besides Guava's, the schemes hash an item into two 64-bit hashes `h1` and `h2`, and set the
+/// bits `h1 + i * h2` (modulo the bit count) for `i` from 0 to the hash count, so that filters of
+/// the same size, hash count, scheme and seed built by other tools or nodes have the same bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema, clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum HashScheme {
+    /// The stable hash of `bloomsrv` (FNV-1a, scrambled into two hashes for double hashing);
+    /// the seed is mixed into the offset basis of FNV-1a.
+    #[default]
+    Native,
+    /// The `MURMUR128_MITZ_64` strategy of Guava's `BloomFilter`, for filters imported from
+    /// (and exported to) JVM pipelines. Takes no seed.
+    Guava,
+    /// The 128-bit MurmurHash3 (x64 variant), whose halves are `h1` and `h2`; the seed must fit
+    /// in 32 bits.
+    Murmur3,
+    /// The 128-bit XXH3, whose low and high halves are `h1` and `h2`.
+    Xxhash,
+    /// The 128-bit SipHash-2-4, keyed with the seed and zero, whose halves are `h1` and `h2`.
+    Siphash,
+    /// The slices of Python's `pybloom_live.BloomFilter`, for filters imported from (and
+    /// exported to) Python: slice `i` holds the position `i` of an item, a chunk of the digest
+    /// (MD5, SHA-1 or SHA-2, by the size of the filter) of the item salted by its index. The seed
+    /// is the number of bits of each slice; a seed of zero is as many as fit. Not for scalable
+    /// filters.
+    Pybloom,
+}
+
+impl HashScheme {
+    /// Returns the code of the scheme in filter files and the write-ahead log.
+    pub(crate) fn code(self) -> u32 {
+        match self {
+            HashScheme::Native => 0,
+            HashScheme::Guava => 1,
+            HashScheme::Murmur3 => 2,
+            HashScheme::Xxhash => 3,
+            HashScheme::Siphash => 4,
+            HashScheme::Pybloom => 5,
+        }
+    }
+
+    /// Returns the scheme of a code returned by [`HashScheme::code`].
+    pub(crate) fn from_code(code: u32) -> Option<Self> {
+        Some(match code {
+            0 => HashScheme::Native,
+            1 => HashScheme::Guava,
+            2 => HashScheme::Murmur3,
+            3 => HashScheme::Xxhash,
+            4 => HashScheme::Siphash,
+            5 => HashScheme::Pybloom,
+            _ => return None,
+        })
+    }
+
+    /// Checks that the scheme takes `seed`.
+    pub(crate) fn check_seed(self, seed: u64) -> Result<(), Error> {
+        match self {
+            HashScheme::Guava if seed != 0 => Err(Error::InvalidParameters(
+                "The guava hash scheme takes no seed".to_string(),
+            )),
+            HashScheme::Murmur3 if seed > u64::from(u32::MAX) => Err(Error::InvalidParameters(
+                "The seed of the murmur3 hash scheme must fit in 32 bits".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolves the seed of a filter of `bit_count` bits and `hash_count` hashes: with the
+    /// pybloom scheme, a seed of zero is as many bits per slice as fit.
+    pub(crate) fn resolve_seed(self, seed: u64, bit_count: u64, hash_count: u32) -> u64 {
+        match self {
+            HashScheme::Pybloom if seed == 0 => bit_count / u64::from(hash_count.max(1)),
+            _ => seed,
+        }
+    }
+}
+
+/// Where the bits of a filter are kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Storage {
+    /// On the heap (and saved to the data directory by snapshots, if enabled).
+    #[default]
+    Memory,
+    /// In a file of the data directory mapped into memory (Unix only): the kernel pages the bits
+    /// in and out as needed, and writes them back to the file, so restarts do not load them.
+    Mmap,
+    /// On the heap, in atomic words (standard filters only): inserts and lookups set and read
+    /// the bits without taking the lock of the filter, so that many run at once.
+    Atomic,
+}
+
+/// How a filter keeps its items: whether it can remove them, grow, or forget them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    /// A plain Bloom filter: items can be inserted, but never removed.
+    #[default]
+    Standard,
+    /// A counting Bloom filter, keeping an 8-bit counter of the items setting each bit, so that
+    /// items can be removed. Takes nine times the memory of a standard filter.
+    Counting,
+    /// A scalable Bloom filter, adding slices as it fills up, so that it keeps its false positive
+    /// rate however many items are inserted. Each slice is twice as large as the one before.
+    Scalable,
+    /// A stable Bloom filter, for unbounded streams: each insert first decrements a few cells
+    /// (8-bit counters, one per bit), so that old items are gradually forgotten and the false
+    /// positive rate settles instead of growing. Items may thus be reported as not seen.
+    Stable,
+}
+
+/// Parameters of a [stable](FilterKind::Stable) filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Parameters of a stable filter.")
+)]
+pub struct StableParams {
+    /// Value the cells of an item are set to on insert; the larger, the longer items are kept.
+    #[serde(default = "default_stable_max")]
+    pub max: u8,
+    /// Number of cells decremented on each insert. By default, the number for which the false
+    /// positive rate settles at the rate the filter is created for.
+    #[serde(default)]
+    pub decrements: Option<u32>,
+}
+
+impl Default for StableParams {
+    fn default() -> Self {
+        StableParams {
+            max: default_stable_max(),
+            decrements: None,
+        }
+    }
+}
+
+fn default_stable_max() -> u8 {
+    3
+}
+
+/// Parameters and guarantees of a stable filter, as reported by [`BloomFilter::stable_info`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Parameters and guarantees of a stable filter.")
+)]
+pub struct StableInfo {
+    pub max: u8,
+    pub decrements: u32,
+    /// The false positive rate the filter settles at, however many items are inserted.
+    pub false_positive_rate: f64,
+}
+
+/// The state of the decrements of a stable filter.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "The state of the decrements of a stable filter.")
+)]
+pub(crate) struct Decay {
+    pub(crate) max: u8,
+    pub(crate) decrements: u32,
+    /// Number of inserts so far, which seeds the cells to decrement, so that replaying the same
+    /// inserts yields the same filter.
+    pub(crate) step: u64,
+}
+
+/// The words of a filter, on the heap, in atomic words on the heap, or in a memory-mapped file.
+enum Bits {
+    Heap(Vec<u64>),
+    Atomic(Vec<AtomicU64>),
+    #[cfg(unix)]
+    Mapped(MappedWords),
+}
+
+impl Bits {
+    /// Returns the number of words.
+    fn len(&self) -> usize {
+        match self {
+            Bits::Heap(words) => words.len(),
+            Bits::Atomic(words) => words.len(),
+            #[cfg(unix)]
+            Bits::Mapped(words) => words.len(),
+        }
+    }
+
+    /// Returns the word at `index`.
+    fn word(&self, index: usize) -> u64 {
+        match self {
+            Bits::Heap(words) => words[index],
+            Bits::Atomic(words) => words[index].load(Ordering::Relaxed),
+            #[cfg(unix)]
+            Bits::Mapped(words) => words[index],
+        }
+    }
+
+    /// Returns the words, copied out of atomic words.
+    fn words(&self) -> Cow<'_, [u64]> {
+        match self {
+            Bits::Heap(words) => Cow::Borrowed(words),
+            Bits::Atomic(words) => Cow::Owned(
+                words
+                    .iter()
+                    .map(|word| word.load(Ordering::Relaxed))
+                    .collect(),
+            ),
+            #[cfg(unix)]
+            Bits::Mapped(words) => Cow::Borrowed(words),
+        }
+    }
+
+    /// Returns the words, to change them.
+    fn words_mut(&mut self) -> &mut [u64] {
+        match self {
+            Bits::Heap(words) => words,
+            Bits::Atomic(words) => {
+                let words: &mut [AtomicU64] = words;
+                // SAFETY: `AtomicU64` has the same size and bit validity as `u64`, and at least
+                // its alignment; the exclusive borrow rules out atomic accesses meanwhile.
+                unsafe { &mut *(words as *mut [AtomicU64] as *mut [u64]) }
+            }
+            #[cfg(unix)]
+            Bits::Mapped(words) => words,
+        }
+    }
+}
+
+/// Clones of memory-mapped words are on the heap.
+impl Clone for Bits {
+    fn clone(&self) -> Self {
+        match self {
+            Bits::Atomic(words) => Bits::Atomic(
+                words
+                    .iter()
+                    .map(|word| AtomicU64::new(word.load(Ordering::Relaxed)))
+                    .collect(),
+            ),
+            bits => Bits::Heap(bits.words().into_owned()),
+        }
+    }
+}
+
+impl PartialEq for Bits {
+    fn eq(&self, other: &Self) -> bool {
+        self.words() == other.words()
+    }
+}
+
+impl fmt::Debug for Bits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.words().fmt(f)
+    }
+}
+
+/// A Bloom filter over byte items.
+///
+/// The filter is sized like [`bloomlib`](https://docs.rs/bloomlib): for `n` expected items and a
+/// false positive rate `p`, it uses `m = -n ln(p) / ln(2)^2` bits and `k = (m / n) ln(2)` hash
+/// functions; for a fixed `k`, it uses `m = k n / ln(2)` bits. Unlike `bloomlib`, the positions of
+/// an item are derived from a stable hash of its bytes, so filters can be built offline, saved,
+/// and loaded again by other builds of the service (see [`FilterSnapshot`]).
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{BloomFilter, CreationMode};
+///
+/// let mut filter = BloomFilter::new(1000, CreationMode::FalsePositiveRate(0.01)).unwrap();
+/// assert!(filter.insert(b"user@example.com"));
+/// assert!(!filter.insert(b"user@example.com"));
+/// assert!(filter.contains(b"user@example.com"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct BloomFilter {
+    bits: Bits,
+    /// One counter per bit, in counting filters.
+    counters: Option<Vec<u8>>,
+    /// The further slices of a scalable filter (whose first slice is the filter itself).
+    growth: Option<Box<Growth>>,
+    /// The decrements of a stable filter, whose cells are in `counters`.
+    decay: Option<Decay>,
+    /// The previous generation of a filter rotating once full.
+    rotation: Option<Box<Rotation>>,
+    bit_count: u64,
+    hash_count: u32,
+    scheme: HashScheme,
+    seed: u64,
+}
+
+/// The slices a scalable filter added as it filled up.
+#[derive(Clone, Debug, PartialEq)]
+struct Growth {
+    /// The expected number of items and the false positive rate the filter was created for.
+    item_count: usize,
+    rate: f64,
+    /// The slices after the first one, oldest first.
+    slices: Vec<BloomFilter>,
+    /// Number of bits set in the newest slice.
+    newest_set_bits: u64,
+}
+
+/// The generation a rotating filter rotated out when its bits filled up, kept for lookups.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Rotation {
+    /// The bits of the previous generation; none until the first rotation.
+    previous: Option<Vec<u64>>,
+    /// Number of bits set in the current generation.
+    set_bits: u64,
+    rotations: u64,
+}
+
+impl Growth {
+    /// Creates the next, empty slice, hashing items like the first one.
+    fn next_slice(&self, scheme: HashScheme, seed: u64) -> Result<BloomFilter, Error> {
+        let (bit_count, hash_count) =
+            slice_dimensions(self.item_count, self.rate, self.slices.len() + 1)?;
+        let mut slice = BloomFilter::from_words(zeroed(bit_count / 64)?, hash_count, scheme);
+        slice.seed = seed;
+        Ok(slice)
+    }
+}
+
+impl BloomFilter {
+    /// Creates an empty filter for `item_count` expected items.
+    pub fn new(item_count: usize, mode: CreationMode) -> Result<Self, Error> {
+        let (bit_count, hash_count) = Self::dimensions(item_count, mode)?;
+        Ok(BloomFilter {
+            bits: Bits::Heap(zeroed(bit_count / 64)?),
+            counters: None,
+            growth: None,
+            decay: None,
+            rotation: None,
+            bit_count,
+            hash_count,
+            scheme: HashScheme::Native,
+            seed: 0,
+        })
+    }
+
+    /// Creates a filter from its bits, as loaded from a file.
+    pub(crate) fn from_words(bits: Vec<u64>, hash_count: u32, scheme: HashScheme) -> Self {
+        BloomFilter {
+            bit_count: bits.len() as u64 * 64,
+            bits: Bits::Heap(bits),
+            counters: None,
+            growth: None,
+            decay: None,
+            rotation: None,
+            hash_count,
+            scheme,
+            seed: 0,
+        }
+    }
+
+    /// Creates an empty [counting](FilterKind::Counting) filter for `item_count` expected items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{BloomFilter, CreationMode};
+    ///
+    /// let mut filter = BloomFilter::counting(1000, CreationMode::FalsePositiveRate(0.01)).unwrap();
+    /// filter.insert(b"user@example.com");
+    /// assert!(filter.remove(b"user@example.com"));
+    /// assert!(!filter.contains(b"user@example.com"));
+    /// ```
+    pub fn counting(item_count: usize, mode: CreationMode) -> Result<Self, Error> {
+        let mut filter = Self::new(item_count, mode)?;
+        filter.counters = Some(zeroed(filter.bit_count)?);
+        Ok(filter)
+    }
+
+    /// Creates an empty [stable](FilterKind::Stable) filter for `item_count` items at a time.
+    ///
+    /// Without explicit `decrements`, `mode` must be a false positive rate, at which the false
+    /// positive rate of the filter then settles.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{BloomFilter, CreationMode, StableParams};
+    ///
+    /// let mode = CreationMode::FalsePositiveRate(0.01);
+    /// let mut filter = BloomFilter::stable(1000, mode, StableParams::default()).unwrap();
+    /// for i in 0..100_000 {
+    ///     filter.insert(format!("event-{i}").as_bytes());
+    /// }
+    /// assert!(filter.contains(b"event-99999"));
+    /// assert!(filter.stable_info().unwrap().false_positive_rate <= 0.01);
+    /// ```
+    pub fn stable(
+        item_count: usize,
+        mode: CreationMode,
+        params: StableParams,
+    ) -> Result<Self, Error> {
+        let invalid = |reason: &str| Err(Error::InvalidParameters(reason.to_string()));
+        let mut filter = Self::new(item_count, mode)?;
+        if params.max == 0 {
+            return invalid("The max of a stable filter must be at least 1");
+        }
+        let decrements = match (params.decrements, mode) {
+            (Some(decrements), _) => decrements,
+            (None, CreationMode::FalsePositiveRate(rate)) => {
+                stable_decrements(rate, filter.hash_count, filter.bit_count, params.max)
+            }
+            (None, CreationMode::HashCount(_)) => {
+                return invalid("Stable filters created with a hash_count need decrements")
+            }
+        };
+        if decrements == 0 || u64::from(decrements) > filter.bit_count {
+            return invalid("A stable filter must decrement between 1 and bit_count cells");
+        }
+        filter.counters = Some(zeroed(filter.bit_count)?);
+        filter.decay = Some(Decay {
+            max: params.max,
+            decrements,
+            step: 0,
+        });
+        Ok(filter)
+    }
+
+    /// Creates an empty [scalable](FilterKind::Scalable) filter, starting with room for
+    /// `item_count` items, and keeping the false positive rate of `mode` (which must be a rate)
+    /// as it grows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{BloomFilter, CreationMode};
+    ///
+    /// let mut filter = BloomFilter::scalable(100, CreationMode::FalsePositiveRate(0.01)).unwrap();
+    /// for i in 0..1000 {
+    ///     filter.insert(format!("user-{i}").as_bytes());
+    /// }
+    /// assert!(filter.slice_count() > 1);
+    /// assert!(filter.contains(b"user-0"));
+    /// ```
+    pub fn scalable(item_count: usize, mode: CreationMode) -> Result<Self, Error> {
+        let rate = scalable_rate(mode)?;
+        let (bit_count, hash_count) = slice_dimensions(item_count, rate, 0)?;
+        let mut filter = Self::from_words(zeroed(bit_count / 64)?, hash_count, HashScheme::Native);
+        filter.growth = Some(Box::new(Growth {
+            item_count,
+            rate,
+            slices: Vec::new(),
+            newest_set_bits: 0,
+        }));
+        Ok(filter)
+    }
+
+    /// Returns the bytes the bits (and counters) of an empty filter of `kind` would take on the
+    /// heap, without allocating them, e.g. to check a quota before creating it.
+    pub(crate) fn heap_bytes(
+        item_count: usize,
+        mode: CreationMode,
+        kind: FilterKind,
+    ) -> Result<usize, Error> {
+        let bit_count = match kind {
+            FilterKind::Scalable => slice_dimensions(item_count, scalable_rate(mode)?, 0)?.0,
+            _ => Self::dimensions(item_count, mode)?.0,
+        };
+        let bytes = usize::try_from(bit_count / 8).unwrap_or(usize::MAX);
+        Ok(match kind {
+            FilterKind::Counting | FilterKind::Stable => bytes.saturating_mul(9),
+            FilterKind::Standard | FilterKind::Scalable => bytes,
+        })
+    }
+
+    /// Resolves the bit count and hash count of a filter for `item_count` expected items.
+    pub fn dimensions(item_count: usize, mode: CreationMode) -> Result<(u64, u32), Error> {
+        let invalid = |reason: &str| Err(Error::InvalidParameters(reason.to_string()));
+        if item_count == 0 {
+            return invalid("item_count must be greater than 0");
+        }
+
+        let n = item_count as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let (m, k) = match mode {
+            CreationMode::FalsePositiveRate(rate) => {
+                if !(rate > 0.0 && rate < 1.0) {
+                    return invalid("false_positive_rate must be between 0 and 1");
+                }
+                let m = (-n * rate.ln() / (ln2 * ln2)).ceil() as u64;
+                let k = ((m as f64 / n) * ln2).ceil() as u32;
+                // Bounded as a hash count given, which rates below 2^-1024 would exceed
+                if k > MAX_HASH_COUNT {
+                    return Err(Error::InvalidParameters(format!(
+                        "A false positive rate of {rate} would need {k} hash functions, more than the maximum of {MAX_HASH_COUNT}"
+                    )));
+                }
+                (m, k)
+            }
+            CreationMode::HashCount(k) => {
+                if k == 0 || k > MAX_HASH_COUNT {
+                    return invalid("hash_count must be between 1 and 1024");
+                }
+                (((k as f64 * n) / ln2).ceil() as u64, k)
+            }
+        };
+
+        if m > MAX_BIT_COUNT {
+            return Err(Error::FilterTooLarge(format!(
+                "A filter of {item_count} items would need {m} bits, more than the maximum of {MAX_BIT_COUNT}"
+            )));
+        }
+        // Whole words only, so the bit count is a multiple of 64
+        Ok((m.max(1).div_ceil(64) * 64, k))
+    }
+
+    /// Returns the size of the filter, in bits.
+    pub fn bit_count(&self) -> u64 {
+        self.bit_count
+    }
+
+    /// Returns the number of hash functions.
+    pub fn hash_count(&self) -> u32 {
+        self.hash_count
+    }
+
+    /// Returns how the positions of items are derived from their bytes.
+    pub fn scheme(&self) -> HashScheme {
+        self.scheme
+    }
+
+    /// Returns the seed of the hashes of the [scheme](BloomFilter::scheme).
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Derives the positions of items with `scheme`, seeded with `seed`, e.g. to lay out the bits
+    /// of an empty filter like another tool or node does; the bits are left as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{BloomFilter, CreationMode, HashScheme};
+    ///
+    /// let build = || {
+    ///     let filter = BloomFilter::new(1000, CreationMode::FalsePositiveRate(0.01)).unwrap();
+    ///     filter.with_hashing(HashScheme::Xxhash, 42).unwrap()
+    /// };
+    /// let (mut a, mut b) = (build(), build());
+    /// a.insert(b"alice");
+    /// b.insert(b"bob");
+    ///
+    /// a.union(&b).unwrap();
+    /// assert!(a.contains(b"bob"));
+    /// assert!(BloomFilter::new(10, CreationMode::HashCount(3))
+    ///     .unwrap()
+    ///     .with_hashing(HashScheme::Guava, 42)
+    ///     .is_err());
+    /// ```
+    pub fn with_hashing(mut self, scheme: HashScheme, seed: u64) -> Result<Self, Error> {
+        scheme.check_seed(seed)?;
+        if scheme == HashScheme::Guava && self.growth.is_some() {
+            return Err(Error::InvalidParameters(
+                "Scalable filters cannot use the guava hash scheme".to_string(),
+            ));
+        }
+        if scheme == HashScheme::Pybloom && self.growth.is_some() {
+            return Err(Error::InvalidParameters(
+                "Scalable filters cannot use the pybloom hash scheme".to_string(),
+            ));
+        }
+        let seed = scheme.resolve_seed(seed, self.bit_count, self.hash_count);
+        if scheme == HashScheme::Pybloom
+            && (seed == 0
+                || seed
+                    .checked_mul(u64::from(self.hash_count))
+                    .is_none_or(|bits| bits > self.bit_count))
+        {
+            return Err(Error::InvalidParameters(format!(
+                "{} slices of {seed} bits do not fit in the {} bits of the filter",
+                self.hash_count, self.bit_count
+            )));
+        }
+        (self.scheme, self.seed) = (scheme, seed);
+        if let Some(growth) = &mut self.growth {
+            for slice in &mut growth.slices {
+                (slice.scheme, slice.seed) = (scheme, seed);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns how the filter keeps its items.
+    pub fn kind(&self) -> FilterKind {
+        match (&self.counters, &self.growth, &self.decay) {
+            (Some(_), _, Some(_)) => FilterKind::Stable,
+            (Some(_), _, None) => FilterKind::Counting,
+            (None, Some(_), _) => FilterKind::Scalable,
+            (None, None, _) => FilterKind::Standard,
+        }
+    }
+
+    /// Returns the parameters of a stable filter, and the false positive rate it settles at.
+    pub fn stable_info(&self) -> Option<StableInfo> {
+        self.decay.map(|decay| StableInfo {
+            max: decay.max,
+            decrements: decay.decrements,
+            false_positive_rate: stable_rate(
+                decay.decrements,
+                self.hash_count,
+                self.bit_count,
+                decay.max,
+            ),
+        })
+    }
+
+    /// Returns the parameters of a stable filter, with the decrements resolved.
+    pub(crate) fn stable_params(&self) -> Option<StableParams> {
+        self.decay.map(|decay| StableParams {
+            max: decay.max,
+            decrements: Some(decay.decrements),
+        })
+    }
+
+    /// Returns the state of the decrements of a stable filter.
+    #[cfg(feature = "server")]
+    pub(crate) fn decay(&self) -> Option<Decay> {
+        self.decay
+    }
+
+    /// Makes a standard filter on the heap rotate once its bits fill up (at half of them set, as
+    /// at its expected number of items): the bits move to a previous generation, still looked
+    /// up but no longer inserted into, and the filter starts over, replacing the generation
+    /// rotated out before. Items inserted since the last rotation are always found.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{BloomFilter, CreationMode};
+    ///
+    /// let mut filter = BloomFilter::new(1000, CreationMode::FalsePositiveRate(0.01))
+    ///     .unwrap()
+    ///     .with_rotation()
+    ///     .unwrap();
+    /// for i in 0..5000 {
+    ///     filter.insert(format!("user-{i}").as_bytes());
+    /// }
+    /// assert!(filter.rotations() >= Some(4));
+    /// assert!(filter.contains(b"user-4999"));
+    /// assert!(filter.stats().false_positive_rate < 0.05);
+    /// ```
+    pub fn with_rotation(mut self) -> Result<Self, Error> {
+        if self.kind() != FilterKind::Standard || self.storage() != Storage::Memory {
+            return Err(Error::InvalidParameters(
+                "Only standard filters kept in memory can rotate".to_string(),
+            ));
+        }
+        if self.rotation.is_none() {
+            self.rotation = Some(Box::new(Rotation {
+                set_bits: count_ones(&self.bits.words()),
+                ..Default::default()
+            }));
+        }
+        Ok(self)
+    }
+
+    /// Returns the number of times a rotating filter rotated; `None` for other filters.
+    pub fn rotations(&self) -> Option<u64> {
+        self.rotation.as_ref().map(|rotation| rotation.rotations)
+    }
+
+    /// Returns the bits of the previous generation of a rotating filter, once it rotated.
+    pub(crate) fn previous_generation(&self) -> Option<&[u64]> {
+        self.rotation.as_ref()?.previous.as_deref()
+    }
+
+    /// Restores the rotations and previous generation of a rotating filter, as saved.
+    pub(crate) fn restore_rotation(
+        mut self,
+        rotations: u64,
+        previous: Option<Vec<u64>>,
+    ) -> Result<Self, Error> {
+        if previous
+            .as_ref()
+            .is_some_and(|words| words.len() != self.bits.len())
+        {
+            return Err(Error::InvalidParameters(
+                "The previous generation of the filter does not match its declared size"
+                    .to_string(),
+            ));
+        }
+        self = self.with_rotation()?;
+        let set_bits = count_ones(&self.bits.words());
+        if let Some(rotation) = &mut self.rotation {
+            rotation.set_bits = set_bits;
+            rotation.previous = previous;
+            rotation.rotations = rotations;
+        }
+        Ok(self)
+    }
+
+    /// Returns the number of slices: more than one only for scalable filters that grew.
+    pub fn slice_count(&self) -> usize {
+        1 + self.slices().len()
+    }
+
+    /// Returns the slices of a scalable filter after the first one.
+    pub(crate) fn slices(&self) -> &[BloomFilter] {
+        self.growth.as_ref().map_or(&[], |growth| &growth.slices)
+    }
+
+    /// Returns where the bits are kept.
+    pub fn storage(&self) -> Storage {
+        match self.bits {
+            Bits::Heap(_) => Storage::Memory,
+            Bits::Atomic(_) => Storage::Atomic,
+            #[cfg(unix)]
+            Bits::Mapped(_) => Storage::Mmap,
+        }
+    }
+
+    /// Writes the bits of a memory-mapped filter back to its file; a no-op on the heap.
+    pub(crate) fn flush(&self) -> io::Result<()> {
+        match &self.bits {
+            Bits::Heap(_) | Bits::Atomic(_) => Ok(()),
+            #[cfg(unix)]
+            Bits::Mapped(words) => words.flush(),
+        }
+    }
+
+    /// Sets the bits to those of another filter, keeping a memory-mapped filter in its file
+    /// (which requires the other filter to have the same parameters), and atomic storage atomic
+    /// (which requires the other filter to be a standard one).
+    pub(crate) fn assign(&mut self, other: BloomFilter) -> Result<(), Error> {
+        let other = match self.rotation {
+            Some(_) => other.with_rotation()?,
+            None => other,
+        };
+        match self.bits {
+            Bits::Heap(_) => *self = other,
+            Bits::Atomic(_) => *self = other.into_atomic()?,
+            #[cfg(unix)]
+            Bits::Mapped(_) => {
+                self.check_compatible(&other)?;
+                self.bits.words_mut().copy_from_slice(&other.bits.words());
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the bits of a standard filter into atomic words, which
+    /// [`insert_shared`](BloomFilter::insert_shared) sets through shared references, so that
+    /// many threads insert into the filter at once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{BloomFilter, CreationMode, Storage};
+    ///
+    /// let filter = BloomFilter::new(1000, CreationMode::FalsePositiveRate(0.01))
+    ///     .unwrap()
+    ///     .into_atomic()
+    ///     .unwrap();
+    /// assert_eq!(filter.storage(), Storage::Atomic);
+    ///
+    /// std::thread::scope(|scope| {
+    ///     scope.spawn(|| filter.insert_shared(b"alice"));
+    ///     scope.spawn(|| filter.insert_shared(b"bob"));
+    /// });
+    /// assert!(filter.contains(b"alice") && filter.contains(b"bob"));
+    /// ```
+    pub fn into_atomic(mut self) -> Result<Self, Error> {
+        if self.kind() != FilterKind::Standard || self.rotation.is_some() {
+            return Err(Error::InvalidParameters(
+                "Only standard filters can have atomic storage".to_string(),
+            ));
+        }
+        if !matches!(self.bits, Bits::Atomic(_)) {
+            let words = self
+                .bits
+                .words()
+                .iter()
+                .map(|&word| AtomicU64::new(word))
+                .collect();
+            self.bits = Bits::Atomic(words);
+        }
+        Ok(self)
+    }
+
+    /// Returns the memory used by the bits, in bytes; none for memory-mapped bits, which the
+    /// kernel pages in and out as needed.
+    pub fn memory_usage_bytes(&self) -> usize {
+        let previous = self.previous_generation().map_or(0, <[u64]>::len) * 8;
+        let counters = self.counters.as_ref().map_or(0, Vec::len)
+            + previous
+            + self
+                .slices()
+                .iter()
+                .map(BloomFilter::memory_usage_bytes)
+                .sum::<usize>();
+        match self.bits {
+            Bits::Heap(_) | Bits::Atomic(_) => {
+                self.bits.len() * std::mem::size_of::<u64>() + counters
+            }
+            #[cfg(unix)]
+            Bits::Mapped(_) => counters,
+        }
+    }
+
+    /// Returns the size of the memory-mapped bits, in bytes, which the kernel pages in and out of
+    /// memory as needed; none for bits on the heap.
+    pub fn mapped_bytes(&self) -> usize {
+        let slices: usize = self.slices().iter().map(BloomFilter::mapped_bytes).sum();
+        match self.bits {
+            Bits::Heap(_) | Bits::Atomic(_) => slices,
+            #[cfg(unix)]
+            Bits::Mapped(_) => self.bits.len() * std::mem::size_of::<u64>() + slices,
+        }
+    }
+
+    /// Returns the bits of the filter (of its first slice, for scalable filters), in words of
+    /// 64 bits (copied out of atomic storage).
+    pub fn bits(&self) -> Cow<'_, [u64]> {
+        self.bits.words()
+    }
+
+    /// Returns the counter of each bit, in counting filters (or the cells of stable filters).
+    pub fn counters(&self) -> Option<&[u8]> {
+        self.counters.as_deref()
+    }
+
+    /// Inserts an item, returning `true` if this changed any bits (i.e. the item is definitely new).
+    pub fn insert(&mut self, item: &[u8]) -> bool {
+        if self.growth.is_some() {
+            return self.insert_scaling(item);
+        }
+        if self.rotation.is_some() {
+            return self.insert_rotating(item);
+        }
+        self.decrement();
+        self.set(item) > 0
+    }
+
+    /// Inserts an item into a filter with atomic storage through a shared reference, returning
+    /// `true` if this changed any bits, as [`insert`](BloomFilter::insert) does; `None` for other
+    /// storage, which inserts need exclusive access to.
+    pub fn insert_shared(&self, item: &[u8]) -> Option<bool> {
+        let Bits::Atomic(words) = &self.bits else {
+            return None;
+        };
+        let mut changed = false;
+        for (word, mask) in self.positions(item) {
+            changed |= words[word].fetch_or(mask, Ordering::Relaxed) & mask == 0;
+        }
+        Some(changed)
+    }
+
+    /// Decrements the cells of a stable filter before an insert, starting at a pseudo-random
+    /// cell, clearing the bits of the cells reaching zero.
+    fn decrement(&mut self) {
+        let (Some(decay), Some(cells)) = (&mut self.decay, &mut self.counters) else {
+            return;
+        };
+        // The SplitMix64 sequence
+        let start = mix(decay.step.wrapping_mul(0x9E37_79B9_7F4A_7C15)) % self.bit_count;
+        decay.step += 1;
+        for offset in 0..u64::from(decay.decrements) {
+            let cell = (start + offset) % self.bit_count;
+            let value = &mut cells[cell as usize];
+            if *value > 0 {
+                *value -= 1;
+                if *value == 0 {
+                    let (word, mask) = position(cell);
+                    self.bits.words_mut()[word] &= !mask;
+                }
+            }
+        }
+    }
+
+    /// Sets the bits of an item (and counts it, in a counting filter), returning the number of
+    /// bits that were not set yet.
+    fn set(&mut self, item: &[u8]) -> u64 {
+        let mut newly_set = 0;
+        for bit in self.indices(item) {
+            let (word, mask) = position(bit);
+            let words = self.bits.words_mut();
+            if words[word] & mask == 0 {
+                newly_set += 1;
+            }
+            words[word] |= mask;
+            if let Some(counters) = &mut self.counters {
+                let counter = &mut counters[bit as usize];
+                *counter = match self.decay {
+                    Some(decay) => decay.max,
+                    None => counter.saturating_add(1),
+                };
+            }
+        }
+        newly_set
+    }
+
+    /// Inserts an item into the newest slice of a scalable filter, unless any slice may have it
+    /// already, adding a slice once the newest one is full.
+    fn insert_scaling(&mut self, item: &[u8]) -> bool {
+        if self.contains(item) {
+            return false;
+        }
+        let newly_set = match self.growth.as_mut().and_then(|g| g.slices.last_mut()) {
+            Some(newest) => newest.set(item),
+            None => self.set(item),
+        };
+
+        let (first_bit_count, scheme, seed) = (self.bit_count, self.scheme, self.seed);
+        if let Some(growth) = self.growth.as_mut() {
+            growth.newest_set_bits += newly_set;
+            let newest_bit_count = growth
+                .slices
+                .last()
+                .map_or(first_bit_count, |s| s.bit_count);
+            let full =
+                growth.newest_set_bits as f64 >= newest_bit_count as f64 * SCALABLE_FILL_RATIO;
+            if full && growth.slices.len() + 1 < MAX_SLICES {
+                // A slice too large to size stays the newest, and keeps filling up
+                if let Ok(slice) = growth.next_slice(scheme, seed) {
+                    growth.slices.push(slice);
+                    growth.newest_set_bits = 0;
+                }
+            }
+        }
+        true
+    }
+
+    /// Inserts an item into the current generation of a rotating filter, rotating it out once
+    /// full. Items of the previous generation are inserted again, to be kept once it is replaced.
+    fn insert_rotating(&mut self, item: &[u8]) -> bool {
+        let seen = self.previous_contains(item);
+        let newly_set = self.set(item);
+        let bit_count = self.bit_count;
+        if let Some(rotation) = self.rotation.as_mut() {
+            rotation.set_bits += newly_set;
+            if rotation.set_bits as f64 >= bit_count as f64 * ROTATION_FILL_RATIO {
+                if let Bits::Heap(words) = &mut self.bits {
+                    let fresh = vec![0; words.len()];
+                    rotation.previous = Some(std::mem::replace(words, fresh));
+                }
+                rotation.set_bits = 0;
+                rotation.rotations += 1;
+            }
+        }
+        newly_set > 0 && !seen
+    }
+
+    /// Returns `true` if the previous generation of a rotating filter may have the item.
+    fn previous_contains(&self, item: &[u8]) -> bool {
+        self.previous_generation().is_some_and(|previous| {
+            self.positions(item)
+                .all(|(word, mask)| previous[word] & mask != 0)
+        })
+    }
+
+    /// Removes an item from a counting filter, returning `false` if it definitely was not in it
+    /// (in which case nothing changes). Other filters cannot remove items, and return `false`.
+    ///
+    /// Only remove items that were inserted: removing a false positive also removes some other
+    /// items. Counters that reached 255 are never decremented, as their true count is unknown.
+    pub fn remove(&mut self, item: &[u8]) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+        let indices = self.indices(item);
+        let (Some(counters), None) = (&mut self.counters, self.decay) else {
+            return false;
+        };
+        for bit in indices {
+            let counter = &mut counters[bit as usize];
+            if *counter < u8::MAX {
+                *counter = counter.saturating_sub(1);
+            }
+            if *counter == 0 {
+                let (word, mask) = position(bit);
+                self.bits.words_mut()[word] &= !mask;
+            }
+        }
+        true
+    }
+
+    /// Returns `true` if the item may have been inserted, and `false` if it definitely was not.
+    pub fn contains(&self, item: &[u8]) -> bool {
+        self.positions(item)
+            .all(|(word, mask)| self.bits.word(word) & mask != 0)
+            || self.slices().iter().any(|slice| slice.contains(item))
+            || self.previous_contains(item)
+    }
+
+    /// Clears all bits.
+    pub fn clear(&mut self) {
+        self.bits.words_mut().fill(0);
+        if let Some(counters) = &mut self.counters {
+            counters.fill(0);
+        }
+        // A scalable filter shrinks back to its first slice
+        if let Some(growth) = &mut self.growth {
+            growth.slices.clear();
+            growth.newest_set_bits = 0;
+        }
+        // And a rotating filter forgets its previous generation
+        if let Some(rotation) = &mut self.rotation {
+            rotation.previous = None;
+            rotation.set_bits = 0;
+        }
+    }
+
+    /// Returns the number of bits set (in all slices).
+    pub fn set_bits(&self) -> u64 {
+        count_ones(&self.bits.words())
+            + self.slices().iter().map(BloomFilter::set_bits).sum::<u64>()
+    }
+
+    /// Estimates the number of distinct items inserted, from the number of bits set.
+    pub fn estimated_items(&self) -> u64 {
+        estimate_items(
+            count_ones(&self.bits.words()),
+            self.bit_count,
+            self.hash_count,
+        ) + self
+            .slices()
+            .iter()
+            .map(BloomFilter::estimated_items)
+            .sum::<u64>()
+    }
+
+    /// Reports how saturated the filter is: the bits set, and the false positive rate they yield.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{BloomFilter, CreationMode};
+    ///
+    /// let mut filter = BloomFilter::new(1000, CreationMode::FalsePositiveRate(0.01)).unwrap();
+    /// for i in 0..1000 {
+    ///     filter.insert(format!("user-{i}").as_bytes());
+    /// }
+    /// let stats = filter.stats();
+    /// assert!((0.45..0.55).contains(&stats.fill_ratio));
+    /// assert!((0.005..0.02).contains(&stats.false_positive_rate));
+    /// ```
+    pub fn stats(&self) -> FilterStats {
+        let set_bits = count_ones(&self.bits.words());
+        let fill_ratio = set_bits as f64 / self.bit_count as f64;
+        let mut stats = FilterStats {
+            set_bits,
+            bit_count: self.bit_count,
+            fill_ratio,
+            estimated_items: estimate_items(set_bits, self.bit_count, self.hash_count),
+            false_positive_rate: fill_ratio.powi(self.hash_count as i32),
+        };
+        // An item is a false positive of a scalable filter if it is one of any of its slices
+        for slice in self.slices().iter().map(BloomFilter::stats) {
+            stats.set_bits += slice.set_bits;
+            stats.bit_count += slice.bit_count;
+            stats.estimated_items = stats.estimated_items.saturating_add(slice.estimated_items);
+            stats.false_positive_rate =
+                1.0 - (1.0 - stats.false_positive_rate) * (1.0 - slice.false_positive_rate);
+        }
+        stats.fill_ratio = stats.set_bits as f64 / stats.bit_count as f64;
+        // Or of the previous generation of a rotating filter, whose bits are not counted
+        if let Some(previous) = self.previous_generation() {
+            let fill_ratio = count_ones(previous) as f64 / self.bit_count as f64;
+            let rate = fill_ratio.powi(self.hash_count as i32);
+            stats.false_positive_rate = 1.0 - (1.0 - stats.false_positive_rate) * (1.0 - rate);
+        }
+        stats
+    }
+
+    /// Adds all items of another filter with the same parameters to this one.
+    pub fn union(&mut self, other: &BloomFilter) -> Result<(), Error> {
+        self.check_compatible(other)?;
+        self.check_not_rotating(other)?;
+        for (word, other) in self
+            .bits
+            .words_mut()
+            .iter_mut()
+            .zip(other.bits.words().iter())
+        {
+            *word |= other;
+        }
+        if let (Some(counters), Some(other)) = (&mut self.counters, &other.counters) {
+            // The cells of stable filters hold how recently an item was seen, not how often
+            let stable = self.decay.is_some();
+            for (counter, &other) in counters.iter_mut().zip(other) {
+                *counter = match stable {
+                    true => (*counter).max(other),
+                    false => counter.saturating_add(other),
+                };
+            }
+        }
+        // Slices of the same position have the same size, whichever filter added them
+        let first_set_bits = count_ones(&self.bits.words());
+        if let Some(growth) = &mut self.growth {
+            let others = other.slices();
+            for (slice, other) in growth.slices.iter_mut().zip(others) {
+                slice.union(other)?;
+            }
+            growth
+                .slices
+                .extend_from_slice(others.get(growth.slices.len()..).unwrap_or_default());
+            growth.newest_set_bits = growth
+                .slices
+                .last()
+                .map_or(first_set_bits, BloomFilter::set_bits);
+        }
+        Ok(())
+    }
+
+    /// Keeps only the bits (and the smaller counters) this filter shares with another filter with
+    /// the same parameters, approximating the items inserted into both.
+    ///
+    /// Items inserted into both filters are always kept; the false positive rate is higher than
+    /// that of a filter of the common items only, but at most that of the sparser filter.
+    /// Scalable filters, whose slices fill up in insertion order, cannot be
+    /// intersected.
+    pub fn intersect(&mut self, other: &BloomFilter) -> Result<(), Error> {
+        self.check_compatible(other)?;
+        self.check_not_rotating(other)?;
+        if self.growth.is_some() {
+            return Err(Error::InvalidParameters(
+                "Scalable filters cannot be intersected".to_string(),
+            ));
+        }
+        for (word, other) in self
+            .bits
+            .words_mut()
+            .iter_mut()
+            .zip(other.bits.words().iter())
+        {
+            *word &= other;
+        }
+        if let (Some(counters), Some(other)) = (&mut self.counters, &other.counters) {
+            for (counter, &other) in counters.iter_mut().zip(other) {
+                *counter = (*counter).min(other);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares this filter with a later state of it (e.g. a snapshot with the live filter).
+    pub fn diff(&self, later: &BloomFilter) -> Result<FilterDiff, Error> {
+        self.check_compatible(later)?;
+        let mut diff = diff_words(
+            &self.bits.words(),
+            &later.bits.words(),
+            self.bit_count,
+            self.hash_count,
+        );
+
+        // The slices of scalable filters are compared one by one, a missing slice being empty
+        let (before, after) = (self.slices(), later.slices());
+        for index in 0..before.len().max(after.len()) {
+            let slice = before.get(index).or(after.get(index)).unwrap();
+            let empty = vec![0; slice.bits.len()];
+            let words_before = before
+                .get(index)
+                .map_or(Cow::Borrowed(&empty[..]), |s| s.bits.words());
+            let words_after = after
+                .get(index)
+                .map_or(Cow::Borrowed(&empty[..]), |s| s.bits.words());
+            diff.add(diff_words(
+                &words_before,
+                &words_after,
+                slice.bit_count,
+                slice.hash_count,
+            ));
+        }
+        Ok(diff)
+    }
+
+    /// Estimates how many items this filter and another one with the same parameters have in
+    /// common, from the bits set in each of them and in their union.
+    ///
+    /// Scalable filters, whose slices fill up in insertion order, cannot be compared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{BloomFilter, CreationMode};
+    ///
+    /// let mode = CreationMode::FalsePositiveRate(0.01);
+    /// let (mut a, mut b) = (BloomFilter::new(1000, mode).unwrap(), BloomFilter::new(1000, mode).unwrap());
+    /// for i in 0..100 {
+    ///     a.insert(format!("user-{i}").as_bytes());
+    ///     b.insert(format!("user-{}", i + 50).as_bytes());
+    /// }
+    /// let overlap = a.overlap(&b).unwrap();
+    /// assert!((45..=55).contains(&overlap.estimated_intersection));
+    /// assert!((overlap.jaccard - 1.0 / 3.0).abs() < 0.05);
+    /// ```
+    pub fn overlap(&self, other: &BloomFilter) -> Result<FilterOverlap, Error> {
+        self.check_compatible(other)?;
+        if self.growth.is_some() {
+            return Err(Error::InvalidParameters(
+                "Scalable filters cannot be compared".to_string(),
+            ));
+        }
+        let estimate = |set| estimate_items(set, self.bit_count, self.hash_count);
+        let union_set: u64 = self
+            .bits
+            .words()
+            .iter()
+            .zip(other.bits.words().iter())
+            .map(|(a, b)| u64::from((a | b).count_ones()))
+            .sum();
+        let (a, b) = (
+            estimate(count_ones(&self.bits.words())),
+            estimate(count_ones(&other.bits.words())),
+        );
+        let union = estimate(union_set);
+        // Inclusion-exclusion, bounded by the smaller filter as the estimates are noisy
+        let intersection = a.saturating_add(b).saturating_sub(union).min(a.min(b));
+        Ok(FilterOverlap {
+            estimated_items_a: a,
+            estimated_items_b: b,
+            estimated_union: union,
+            estimated_intersection: intersection,
+            jaccard: match union {
+                0 => 1.0,
+                union => intersection as f64 / union as f64,
+            },
+        })
+    }
+
+    /// Returns the hashes and bit positions of an item, and which of these bits are set.
+    ///
+    /// This is meant for diagnosis, e.g. to compare with the positions another system computes.
+    /// Only the first slice of a scalable filter is traced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{BloomFilter, CreationMode};
+    ///
+    /// let mut filter = BloomFilter::new(1000, CreationMode::HashCount(3)).unwrap();
+    /// filter.insert(b"alice");
+    ///
+    /// let trace = filter.trace(b"alice");
+    /// assert_eq!(trace.bit_indices.len(), 3);
+    /// assert!(trace.bits_set.iter().all(|&set| set));
+    /// ```
+    pub fn trace(&self, item: &[u8]) -> HashTrace {
+        let (hash1, hash2) = self.hashes(item);
+        let bit_indices: Vec<u64> = self.indices(item).collect();
+        let bits_set = bit_indices
+            .iter()
+            .map(|&bit| self.bits.word((bit / 64) as usize) & 1 << (bit % 64) != 0)
+            .collect();
+        HashTrace {
+            scheme: self.scheme,
+            hash1,
+            hash2,
+            bit_indices,
+            bits_set,
+        }
+    }
+
+    /// Checks that another filter has the same size and hash functions as this one.
+    fn check_compatible(&self, other: &BloomFilter) -> Result<(), Error> {
+        if self.kind() != other.kind() {
+            return Err(Error::InvalidParameters(format!(
+                "Cannot combine a {:?} filter with a {:?} one",
+                self.kind(),
+                other.kind()
+            )));
+        }
+        if let (Some(decay), Some(other)) = (self.decay, other.decay) {
+            if (decay.max, decay.decrements) != (other.max, other.decrements) {
+                return Err(Error::InvalidParameters(format!(
+                    "Cannot combine a stable filter of max {} and {} decrements with one of max {} and {} decrements",
+                    decay.max, decay.decrements, other.max, other.decrements
+                )));
+            }
+        }
+        if self.scheme != other.scheme {
+            return Err(Error::InvalidParameters(format!(
+                "Cannot combine a filter using the {:?} hash scheme with one using the {:?} scheme",
+                self.scheme, other.scheme
+            )));
+        }
+        if self.seed != other.seed {
+            return Err(Error::InvalidParameters(format!(
+                "Cannot combine a filter hashed with the seed {} with one hashed with the seed {}",
+                self.seed, other.seed
+            )));
+        }
+        if (self.bit_count, self.hash_count) != (other.bit_count, other.hash_count) {
+            return Err(Error::InvalidParameters(format!(
+                "Cannot combine a filter of {} bits and {} hashes with one of {} bits and {} hashes",
+                self.bit_count, self.hash_count, other.bit_count, other.hash_count
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fails if either filter rotates, as their generations rotate at different times.
+    fn check_not_rotating(&self, other: &BloomFilter) -> Result<(), Error> {
+        if self.rotation.is_some() || other.rotation.is_some() {
+            return Err(Error::InvalidParameters(
+                "Rotating filters cannot be combined".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the two hashes of an item combined by double hashing.
+    fn hashes(&self, item: &[u8]) -> (u64, u64) {
+        match self.scheme {
+            HashScheme::Native => {
+                let hash = fnv1a_from(FNV_OFFSET_BASIS ^ self.seed, item);
+                (mix(hash), mix(hash ^ 0x9E37_79B9_7F4A_7C15) | 1)
+            }
+            HashScheme::Guava => murmur3_128(item, 0),
+            HashScheme::Murmur3 => murmur3_128(item, self.seed as u32),
+            HashScheme::Xxhash => {
+                let hash = xxh3_128_with_seed(item, self.seed);
+                (hash as u64, (hash >> 64) as u64)
+            }
+            HashScheme::Siphash => {
+                let mut hasher = SipHasher24::new_with_keys(self.seed, 0);
+                hasher.write(item);
+                let hash = hasher.finish128();
+                (hash.h1, hash.h2)
+            }
+            // Its positions are not combined from two hashes
+            HashScheme::Pybloom => (0, 0),
+        }
+    }
+
+    /// Returns the bit positions of an item.
+    fn indices(&self, item: &[u8]) -> Indices {
+        if self.scheme == HashScheme::Pybloom {
+            return Indices::Sliced(pybloom_indices(item, self.hash_count, self.seed).into_iter());
+        }
+        let (h1, h2) = self.hashes(item);
+        // Guava drops the sign bit of its (signed) combined hashes
+        let mask = match self.scheme {
+            HashScheme::Guava => i64::MAX as u64,
+            _ => u64::MAX,
+        };
+        Indices::Hashed {
+            h1,
+            h2,
+            mask,
+            bit_count: self.bit_count,
+            hashes: 0..self.hash_count,
+        }
+    }
+
+    /// Returns the word index and bit mask of each position of an item.
+    fn positions(&self, item: &[u8]) -> impl Iterator<Item = (usize, u64)> {
+        self.indices(item).map(position)
+    }
+}
+
+/// The bit positions of an item, as returned by [`BloomFilter::indices`].
+enum Indices {
+    /// Double hashing: position `i` is `h1 + i * h2`, masked, modulo the bit count.
+    Hashed {
+        h1: u64,
+        h2: u64,
+        mask: u64,
+        bit_count: u64,
+        hashes: std::ops::Range<u32>,
+    },
+    /// Positions computed at once, e.g. one in each slice of the filter.
+    Sliced(std::vec::IntoIter<u64>),
+}
+
+impl Iterator for Indices {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        match self {
+            Indices::Hashed {
+                h1,
+                h2,
+                mask,
+                bit_count,
+                hashes,
+            } => {
+                let i = u64::from(hashes.next()?);
+                Some((h1.wrapping_add(h2.wrapping_mul(i)) & *mask) % *bit_count)
+            }
+            Indices::Sliced(indices) => indices.next(),
+        }
+    }
+}
+
+/// Compares two states of the words of a filter of `bit_count` bits and `hash_count` hashes.
+fn diff_words(before: &[u64], after: &[u64], bit_count: u64, hash_count: u32) -> FilterDiff {
+    let count = |f: fn(u64, u64) -> u64| -> u64 {
+        before
+            .iter()
+            .zip(after)
+            .map(|(&before, &after)| u64::from(f(before, after).count_ones()))
+            .sum()
+    };
+    let estimate = |bits| estimate_items(bits, bit_count, hash_count);
+
+    // Items added are those of the later filter not explained by the bits both filters share
+    // (which are all the bits of the earlier one, unless the filter was cleared in between)
+    let kept = count(|before, after| before & after);
+    let (bits_set_before, bits_set_after) = (count_ones(before), count_ones(after));
+    FilterDiff {
+        bits_set_before,
+        bits_set_after,
+        bits_added: count(|before, after| !before & after),
+        bits_removed: count(|before, after| before & !after),
+        estimated_items_before: estimate(bits_set_before),
+        estimated_items_after: estimate(bits_set_after),
+        estimated_items_added: estimate(bits_set_after).saturating_sub(estimate(kept)),
+    }
+}
+
+/// Returns the number of bits set in some words.
+fn count_ones(words: &[u64]) -> u64 {
+    words.iter().map(|word| u64::from(word.count_ones())).sum()
+}
+
+/// Resolves the number of cells a stable filter of `bit_count` cells and `hash_count` hashes,
+/// whose cells are set to `max`, decrements on each insert for its false positive rate to settle
+/// at `rate` (Deng and Rafiei, "Approximately detecting duplicates for streaming data using
+/// stable Bloom filters", 2006).
+fn stable_decrements(rate: f64, hash_count: u32, bit_count: u64, max: u8) -> u32 {
+    // The fraction of cells at zero at which the rate is reached, and the decrements giving it
+    let zeros = 1.0 - rate.powf(1.0 / f64::from(hash_count));
+    let per_decrement = 1.0 / f64::from(hash_count) - 1.0 / bit_count as f64;
+    let decrements = 1.0 / ((zeros.powf(-1.0 / f64::from(max)) - 1.0) * per_decrement);
+    // Saturates to the bounds for extreme rates
+    (decrements.ceil() as u32).clamp(1, u32::try_from(bit_count).unwrap_or(u32::MAX))
+}
+
+/// Returns the false positive rate a stable filter settles at.
+fn stable_rate(decrements: u32, hash_count: u32, bit_count: u64, max: u8) -> f64 {
+    let per_decrement = 1.0 / f64::from(hash_count) - 1.0 / bit_count as f64;
+    let zeros = (1.0 / (1.0 + 1.0 / (f64::from(decrements) * per_decrement))).powi(i32::from(max));
+    (1.0 - zeros).powi(hash_count as i32)
+}
+
+/// Returns the false positive rate a scalable filter keeps, which is how it must be created.
+fn scalable_rate(mode: CreationMode) -> Result<f64, Error> {
+    match mode {
+        CreationMode::FalsePositiveRate(rate) => Ok(rate),
+        CreationMode::HashCount(_) => Err(Error::InvalidParameters(
+            "Scalable filters need a false_positive_rate, not a hash_count".to_string(),
+        )),
+    }
+}
+
+/// Resolves the size of slice `index` of a scalable filter created for `item_count` items and a
+/// false positive rate of `rate`.
+///
+/// Each slice has room for twice the items of the one before, and half its false positive rate,
+/// so that the false positive rates of all slices add up to less than `rate`.
+fn slice_dimensions(item_count: usize, rate: f64, index: usize) -> Result<(u64, u32), Error> {
+    let items = 1usize
+        .checked_shl(index as u32)
+        .and_then(|growth| item_count.checked_mul(growth))
+        .ok_or_else(|| {
+            Error::InvalidParameters("The filter cannot grow any further".to_string())
+        })?;
+    let rate = rate / 2f64.powi(index as i32 + 1);
+    BloomFilter::dimensions(items, CreationMode::FalsePositiveRate(rate))
+}
+
+/// Allocates `len` zeroed words (or counters), failing rather than aborting the process if the
+/// memory cannot be had.
+fn zeroed<T: Clone + Default>(len: u64) -> Result<Vec<T>, Error> {
+    let failed = || {
+        let bytes = len.saturating_mul(std::mem::size_of::<T>() as u64);
+        Error::QuotaExceeded(format!("Cannot allocate the {bytes} bytes of the filter"))
+    };
+    let len = usize::try_from(len).map_err(|_| failed())?;
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(len).map_err(|_| failed())?;
+    vec.resize(len, T::default());
+    Ok(vec)
+}
+
+/// Returns the word index and bit mask of a bit position.
+fn position(bit: u64) -> (usize, u64) {
+    ((bit / 64) as usize, 1 << (bit % 64))
+}
+
+/// The hashes and bit positions of an item in a filter, as reported by [`BloomFilter::trace`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "The hashes and bit positions of an item in a filter.")
+)]
+pub struct HashTrace {
+    pub scheme: HashScheme,
+    /// The two hashes of the item; position `i` is `hash1 + i * hash2`, modulo the bit count.
+    /// Both are zero with the pybloom scheme, whose positions are not combined from two hashes.
+    pub hash1: u64,
+    pub hash2: u64,
+    pub bit_indices: Vec<u64>,
+    /// Whether each of the bits in `bit_indices` is set.
+    pub bits_set: Vec<bool>,
+}
+
+/// The estimated overlap of the items of two filters, `a` and `b`, as reported by
+/// [`BloomFilter::overlap`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "The estimated overlap of the items of two filters, `a` and `b`.")
+)]
+pub struct FilterOverlap {
+    pub estimated_items_a: u64,
+    pub estimated_items_b: u64,
+    /// Estimated number of distinct items inserted into either filter.
+    pub estimated_union: u64,
+    /// Estimated number of distinct items inserted into both filters.
+    pub estimated_intersection: u64,
+    /// Estimated Jaccard similarity, the intersection over the union; 1 if both are empty.
+    pub jaccard: f64,
+}
+
+/// How saturated a filter is, as reported by [`BloomFilter::stats`].
+///
+/// The counts of scalable filters are summed over their slices.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(
+        description = "How saturated a filter is; the counts of scalable filters are summed over their slices."
+    )
+)]
+pub struct FilterStats {
+    pub set_bits: u64,
+    pub bit_count: u64,
+    /// Share of the bits set, between 0 and 1.
+    pub fill_ratio: f64,
+    /// Estimated number of distinct items inserted, from the bits set.
+    pub estimated_items: u64,
+    /// Probability that an item never inserted is reported present, given the bits set now.
+    pub false_positive_rate: f64,
+}
+
+/// The changes between two states of a filter, as reported by [`BloomFilter::diff`].
+///
+/// The counts of scalable filters are summed over their slices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "The changes between two states of a filter.")
+)]
+pub struct FilterDiff {
+    pub bits_set_before: u64,
+    pub bits_set_after: u64,
+    /// Bits set later but not before.
+    pub bits_added: u64,
+    /// Bits set before but not later; only non-zero if the filter was cleared in between.
+    pub bits_removed: u64,
+    pub estimated_items_before: u64,
+    pub estimated_items_after: u64,
+    /// Estimated number of distinct items inserted in between.
+    pub estimated_items_added: u64,
+}
+
+impl FilterDiff {
+    /// Adds the changes of another slice.
+    fn add(&mut self, other: FilterDiff) {
+        self.bits_set_before += other.bits_set_before;
+        self.bits_set_after += other.bits_set_after;
+        self.bits_added += other.bits_added;
+        self.bits_removed += other.bits_removed;
+        self.estimated_items_before += other.estimated_items_before;
+        self.estimated_items_after += other.estimated_items_after;
+        self.estimated_items_added += other.estimated_items_added;
+    }
+}
+
+/// A filter together with the parameters it was created from, as saved to and loaded from files.
+///
+/// The binary format is little-endian: a 48-byte header (the magic `BLOOMSRV`, the format
+/// version, the creation mode and its parameter, the item count, the bit count, the hash
+/// count and the hash scheme), the bits in words of 64 bits, and a 64-bit FNV-1a checksum of
+/// everything before it. Counting filters use version 2 of the format, in which the bits are
+/// followed by one 8-bit counter per bit. Scalable filters use version 3, in which the bits (of
+/// the first slice) are followed by the bits of each further slice, whose sizes follow from the
+/// parameters of the filter. Stable filters use version 4, in which the bits are followed by one
+/// 8-bit cell per bit, the number of cells decremented on each insert (32 bits), the value the
+/// cells of an item are set to (32 bits), and the number of inserts so far (64 bits).
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{BloomFilter, CreationMode, FilterSnapshot};
+///
+/// let creation_mode = CreationMode::FalsePositiveRate(0.01);
+/// let mut filter = BloomFilter::new(1000, creation_mode).unwrap();
+/// filter.insert(b"user@example.com");
+///
+/// let snapshot = FilterSnapshot { item_count: 1000, creation_mode, filter };
+/// let loaded = FilterSnapshot::decode(&snapshot.encode()).unwrap();
+/// assert!(loaded.filter.contains(b"user@example.com"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterSnapshot {
+    pub item_count: usize,
+    pub creation_mode: CreationMode,
+    pub filter: BloomFilter,
+}
+
+impl FilterSnapshot {
+    /// Encodes the snapshot in the binary format.
+    pub fn encode(&self) -> Vec<u8> {
+        let counters = self.filter.counters().unwrap_or_default();
+        let mut bytes = Vec::with_capacity(
+            self.header_len() + self.filter.memory_usage_bytes() + counters.len() + 8,
+        );
+        bytes.extend_from_slice(&self.header(SNAPSHOT_MAGIC));
+        let slices = self.filter.slices().iter().map(|slice| slice.bits.words());
+        for words in std::iter::once(self.filter.bits.words()).chain(slices) {
+            for word in words.iter() {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(counters);
+        if let Some(decay) = self.filter.decay {
+            bytes.extend_from_slice(&decay.decrements.to_le_bytes());
+            bytes.extend_from_slice(&u32::from(decay.max).to_le_bytes());
+            bytes.extend_from_slice(&decay.step.to_le_bytes());
+        }
+        if let Some(rotation) = &self.filter.rotation {
+            bytes.extend_from_slice(&rotation.rotations.to_le_bytes());
+            for word in rotation.previous.iter().flatten() {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        let checksum = fnv1a(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Encodes the header of a filter file starting with `magic`.
+    fn header(&self, magic: &[u8; 8]) -> Vec<u8> {
+        let (mode, parameter) = encode_creation_mode(self.creation_mode);
+        let mut bytes = Vec::with_capacity(self.header_len());
+        let version = match self.filter.kind() {
+            FilterKind::Standard => SNAPSHOT_VERSION,
+            FilterKind::Counting => COUNTING_SNAPSHOT_VERSION,
+            FilterKind::Scalable => SCALABLE_SNAPSHOT_VERSION,
+            FilterKind::Stable => STABLE_SNAPSHOT_VERSION,
+        };
+        bytes.extend_from_slice(magic);
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&mode.to_le_bytes());
+        bytes.extend_from_slice(&parameter.to_le_bytes());
+        bytes.extend_from_slice(&(self.item_count as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.filter.bit_count.to_le_bytes());
+        bytes.extend_from_slice(&self.filter.hash_count.to_le_bytes());
+        let seed = self.filter.seed;
+        let mut scheme = self.filter.scheme.code() | if seed != 0 { SEEDED_SCHEME } else { 0 };
+        if self.filter.storage() == Storage::Atomic {
+            scheme |= ATOMIC_SCHEME;
+        }
+        if self.filter.rotation.is_some() {
+            scheme |= ROTATING_SCHEME;
+        }
+        bytes.extend_from_slice(&scheme.to_le_bytes());
+        if seed != 0 {
+            bytes.extend_from_slice(&seed.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Returns the size of the header of the files of the filter: longer for seeded filters,
+    /// followed by their seed, so that the files of the others read as they always did.
+    fn header_len(&self) -> usize {
+        match self.filter.seed {
+            0 => SNAPSHOT_HEADER_LEN,
+            _ => SNAPSHOT_HEADER_LEN + SEED_LEN,
+        }
+    }
+
+    /// Decodes the header of a filter file starting with `magic`, into a snapshot without words,
+    /// the declared bit count, and the kind of filter.
+    fn decode_header(bytes: &[u8], magic: &[u8; 8]) -> Result<(Self, u64, FilterKind), Error> {
+        let invalid = |reason: &str| Error::InvalidParameters(reason.to_string());
+        if bytes.len() < SNAPSHOT_HEADER_LEN || &bytes[..8] != magic {
+            return Err(invalid("Not a filter snapshot"));
+        }
+
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let kind = match u32_at(8) {
+            SNAPSHOT_VERSION => FilterKind::Standard,
+            COUNTING_SNAPSHOT_VERSION => FilterKind::Counting,
+            SCALABLE_SNAPSHOT_VERSION => FilterKind::Scalable,
+            STABLE_SNAPSHOT_VERSION => FilterKind::Stable,
+            _ => return Err(invalid("Unsupported filter snapshot version")),
+        };
+        let creation_mode = decode_creation_mode(u32_at(12), u64_at(16))?;
+        let item_count = usize::try_from(u64_at(24)).map_err(|_| invalid("Invalid item count"))?;
+        let flags = SEEDED_SCHEME | ATOMIC_SCHEME | ROTATING_SCHEME;
+        let scheme = HashScheme::from_code(u32_at(44) & !flags)
+            .ok_or_else(|| invalid("Unknown hash scheme in filter snapshot"))?;
+        let seed = match u32_at(44) & SEEDED_SCHEME {
+            0 => 0,
+            _ if bytes.len() < SNAPSHOT_HEADER_LEN + SEED_LEN => {
+                return Err(invalid("The filter snapshot is truncated"))
+            }
+            _ => u64_at(SNAPSHOT_HEADER_LEN),
+        };
+        let mut filter = BloomFilter::from_words(Vec::new(), u32_at(40), scheme);
+        filter.seed = seed;
+        if u32_at(44) & ATOMIC_SCHEME != 0 {
+            filter.bits = Bits::Atomic(Vec::new());
+        }
+        if u32_at(44) & ROTATING_SCHEME != 0 {
+            filter.rotation = Some(Box::default());
+        }
+        let snapshot = FilterSnapshot {
+            item_count,
+            creation_mode,
+            filter,
+        };
+        Ok((snapshot, u64_at(32), kind))
+    }
+
+    /// Decodes a snapshot, verifying its checksum and that its size matches its parameters.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidParameters(reason.to_string());
+        if bytes.len() < SNAPSHOT_HEADER_LEN + 8 {
+            return Err(invalid("Not a filter snapshot"));
+        }
+        let (header, bit_count, kind) = Self::decode_header(bytes, SNAPSHOT_MAGIC)?;
+        let (content, checksum) = bytes.split_at(bytes.len() - 8);
+        if fnv1a(content) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err(invalid(
+                "The checksum of the filter snapshot does not match",
+            ));
+        }
+
+        let body = content
+            .get(header.header_len()..)
+            .ok_or_else(|| invalid("The filter snapshot is truncated"))?;
+        let truncated = || invalid("The filter snapshot is truncated");
+        // Splits the words of a slice of `bit_count` bits off the body
+        let split = |body: &[u8], bit_count: u64| -> Result<(Vec<u64>, usize), Error> {
+            let len = usize::try_from(bit_count / 8)
+                .ok()
+                .filter(|&len| len <= body.len())
+                .ok_or_else(truncated)?;
+            Ok((to_words(&body[..len]), len))
+        };
+        // The rotations and any previous generation of a rotating filter follow its bits
+        let mut rotation = None;
+        let (words, extra) = match kind {
+            FilterKind::Standard if header.filter.rotation.is_some() => {
+                let (words, len) = split(body, bit_count)?;
+                let rotations = body.get(len..len + 8).ok_or_else(truncated)?;
+                let previous = match &body[len + 8..] {
+                    [] => None,
+                    previous if previous.len() == len => Some(to_words(previous)),
+                    _ => return Err(truncated()),
+                };
+                rotation = Some((u64::from_le_bytes(rotations.try_into().unwrap()), previous));
+                (words, KindData::None)
+            }
+            FilterKind::Standard => {
+                if body.len() % 8 != 0 {
+                    return Err(truncated());
+                }
+                (to_words(body), KindData::None)
+            }
+            FilterKind::Counting => {
+                let (words, len) = split(body, bit_count)?;
+                (words, KindData::Counters(body[len..].to_vec()))
+            }
+            FilterKind::Scalable => {
+                let rate = scalable_rate(header.creation_mode)?;
+                let (words, mut offset) = split(body, bit_count)?;
+                let mut slices = Vec::new();
+                while offset < body.len() && slices.len() + 1 < MAX_SLICES {
+                    let (slice_bits, _) =
+                        slice_dimensions(header.item_count, rate, slices.len() + 1)?;
+                    let (slice, len) = split(&body[offset..], slice_bits)?;
+                    slices.push(slice);
+                    offset += len;
+                }
+                if offset != body.len() {
+                    return Err(truncated());
+                }
+                (words, KindData::Slices(slices))
+            }
+            FilterKind::Stable => {
+                let (words, len) = split(body, bit_count)?;
+                let cells_end = body.len().checked_sub(DECAY_LEN).ok_or_else(truncated)?;
+                let (cells, decay) = body
+                    .get(len..)
+                    .ok_or_else(truncated)?
+                    .split_at(cells_end.checked_sub(len).ok_or_else(truncated)?);
+                let u32_at = |at: usize| u32::from_le_bytes(decay[at..at + 4].try_into().unwrap());
+                let decay = Decay {
+                    decrements: u32_at(0),
+                    max: u8::try_from(u32_at(4)).map_err(|_| invalid("Invalid stable max"))?,
+                    step: u64::from_le_bytes(decay[8..].try_into().unwrap()),
+                };
+                (words, KindData::Stable(cells.to_vec(), decay))
+            }
+        };
+        let mut snapshot = Self::from_parts(
+            header.item_count,
+            header.creation_mode,
+            (bit_count, header.filter.hash_count),
+            (header.filter.scheme, header.filter.seed),
+            words,
+            extra,
+        )?;
+        if header.filter.storage() == Storage::Atomic {
+            snapshot.filter = snapshot.filter.into_atomic()?;
+        }
+        if let Some((rotations, previous)) = rotation {
+            snapshot.filter = snapshot.filter.restore_rotation(rotations, previous)?;
+        }
+        Ok(snapshot)
+    }
+
+    /// Creates an empty filter whose bits are kept in a memory-mapped file at `path`, hashing
+    /// items with `scheme` and `seed`.
+    pub(crate) fn create_mapped(
+        path: &Path,
+        item_count: usize,
+        creation_mode: CreationMode,
+        (scheme, seed): (HashScheme, u64),
+    ) -> Result<Self, Error> {
+        let (bit_count, hash_count) = BloomFilter::dimensions(item_count, creation_mode)?;
+        let mut filter = BloomFilter::from_words(Vec::new(), hash_count, HashScheme::Native);
+        // Before the hashing is set, as the slices of its scheme depend on the size
+        filter.bit_count = bit_count;
+        let mut snapshot = FilterSnapshot {
+            item_count,
+            creation_mode,
+            filter: filter.with_hashing(scheme, seed)?,
+        };
+        #[cfg(unix)]
+        {
+            let header = snapshot.header(MAPPED_MAGIC);
+            let words = MappedWords::create(path, &header, (bit_count / 64) as usize)
+                .map_err(|e| Error::Storage(format!("{}: {e}", path.display())))?;
+            snapshot.filter.bits = Bits::Mapped(words);
+            Ok(snapshot)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (path, snapshot);
+            Err(Error::InvalidParameters(
+                "Memory-mapped filters are only supported on Unix".to_string(),
+            ))
+        }
+    }
+
+    /// Maps a filter file created by [`FilterSnapshot::create_mapped`] into memory.
+    pub(crate) fn open_mapped(path: &Path) -> Result<Self, Error> {
+        #[cfg(unix)]
+        {
+            let mut declared = None;
+            let storage_error = |e: io::Error| Error::Storage(format!("{}: {e}", path.display()));
+            let header_len = mapped_header_len(path).map_err(storage_error)?;
+            let words = MappedWords::open(path, header_len, |header| {
+                let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidData, reason);
+                let (snapshot, bit_count, kind) = Self::decode_header(header, MAPPED_MAGIC)
+                    .map_err(|e| invalid(e.to_string()))?;
+                if kind != FilterKind::Standard {
+                    return Err(invalid(
+                        "Only standard filters can be memory-mapped".to_string(),
+                    ));
+                }
+                declared = Some((snapshot, bit_count));
+                Ok((bit_count / 64) as usize)
+            })
+            .map_err(storage_error)?;
+            let (mut snapshot, bit_count) = declared.unwrap();
+
+            snapshot.filter.bit_count = bit_count;
+            snapshot
+                .check_declared_size()
+                .map_err(|e| Error::Storage(format!("{}: {e}", path.display())))?;
+            snapshot.filter.bits = Bits::Mapped(words);
+            Ok(snapshot)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = path;
+            Err(Error::InvalidParameters(
+                "Memory-mapped filters are only supported on Unix".to_string(),
+            ))
+        }
+    }
+
+    /// Assembles a snapshot from its declared parameters, its bits, and the data specific to its
+    /// kind, verifying that they agree with each other.
+    pub(crate) fn from_parts(
+        item_count: usize,
+        creation_mode: CreationMode,
+        (bit_count, hash_count): (u64, u32),
+        (scheme, seed): (HashScheme, u64),
+        words: Vec<u64>,
+        extra: KindData,
+    ) -> Result<Self, Error> {
+        let invalid = |reason: &str| Err(Error::InvalidParameters(reason.to_string()));
+        if words.len() as u64 != bit_count / 64 {
+            return invalid("The bits of the filter do not match its declared size");
+        }
+        let mut filter = BloomFilter::from_words(words, hash_count, scheme);
+        match extra {
+            KindData::None => {}
+            KindData::Counters(counters) => {
+                if counters.len() as u64 != bit_count {
+                    return invalid("The counters of the filter do not match its declared size");
+                }
+                filter.counters = Some(counters);
+            }
+            KindData::Stable(cells, decay) => {
+                if cells.len() as u64 != bit_count {
+                    return invalid("The cells of the filter do not match its declared size");
+                }
+                if decay.max == 0
+                    || decay.decrements == 0
+                    || u64::from(decay.decrements) > bit_count
+                {
+                    return invalid("A stable filter must decrement between 1 and bit_count cells");
+                }
+                filter.counters = Some(cells);
+                filter.decay = Some(decay);
+            }
+            KindData::Slices(slices) => {
+                let rate = scalable_rate(creation_mode)?;
+                if slices.len() >= MAX_SLICES {
+                    return invalid("The slices of the filter do not match its parameters");
+                }
+                let mut growth = Growth {
+                    item_count,
+                    rate,
+                    slices: Vec::with_capacity(slices.len()),
+                    newest_set_bits: 0,
+                };
+                for words in slices {
+                    let (bit_count, hash_count) =
+                        slice_dimensions(item_count, rate, growth.slices.len() + 1)?;
+                    if words.len() as u64 != bit_count / 64 {
+                        return invalid("The slices of the filter do not match its parameters");
+                    }
+                    growth
+                        .slices
+                        .push(BloomFilter::from_words(words, hash_count, scheme));
+                }
+                growth.newest_set_bits = growth
+                    .slices
+                    .last()
+                    .map_or(count_ones(&filter.bits.words()), BloomFilter::set_bits);
+                filter.growth = Some(Box::new(growth));
+            }
+        }
+        let filter = filter.with_hashing(scheme, seed)?;
+        let snapshot = FilterSnapshot {
+            item_count,
+            creation_mode,
+            filter,
+        };
+        snapshot.check_declared_size()?;
+        Ok(snapshot)
+    }
+
+    /// Checks that the size of the filter agrees with the parameters of the snapshot.
+    fn check_declared_size(&self) -> Result<(), Error> {
+        let filter = &self.filter;
+        // Filters must be exactly the size their parameters resolve to, but those imported from
+        // Guava, which keep the size chosen by the library they were built with
+        let declared_size_matches = match filter.scheme {
+            HashScheme::Guava => {
+                filter.bit_count > 0 && filter.bit_count.is_multiple_of(64) && filter.hash_count > 0
+            }
+            // Its slices were checked to fit in the filter as its scheme was set
+            HashScheme::Pybloom => filter.bit_count > 0 && filter.hash_count > 0,
+            _ if filter.growth.is_some() => {
+                let rate = scalable_rate(self.creation_mode)?;
+                slice_dimensions(self.item_count, rate, 0)? == (filter.bit_count, filter.hash_count)
+            }
+            _ => {
+                BloomFilter::dimensions(self.item_count, self.creation_mode)?
+                    == (filter.bit_count, filter.hash_count)
+            }
+        };
+        if !declared_size_matches {
+            return Err(Error::InvalidParameters(
+                "The declared size of the filter does not match its parameters".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the size of the header of the filter file at `path`, longer if the flag of its hash
+/// scheme tells that its seed follows.
+#[cfg(unix)]
+fn mapped_header_len(path: &Path) -> io::Result<usize> {
+    use io::Read;
+
+    let mut header = [0; SNAPSHOT_HEADER_LEN];
+    std::fs::File::open(path)?.read_exact(&mut header)?;
+    let scheme = u32::from_le_bytes(header[44..48].try_into().unwrap());
+    Ok(match scheme & SEEDED_SCHEME {
+        0 => SNAPSHOT_HEADER_LEN,
+        _ => SNAPSHOT_HEADER_LEN + SEED_LEN,
+    })
+}
+
+/// The data of a filter specific to its kind, besides its bits.
+pub(crate) enum KindData {
+    None,
+    /// The counter of each bit of a counting filter.
+    Counters(Vec<u8>),
+    /// The words of the further slices of a scalable filter, oldest first.
+    Slices(Vec<Vec<u64>>),
+    /// The cells and decrements of a stable filter.
+    Stable(Vec<u8>, Decay),
+}
+
+/// Reads little-endian 64-bit words; the length of `bytes` must be a multiple of 8.
+fn to_words(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks_exact(8)
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+        .collect()
+}
+
+/// Encodes a creation mode as a mode number and a parameter, as stored in filter files.
+pub(crate) fn encode_creation_mode(creation_mode: CreationMode) -> (u32, u64) {
+    match creation_mode {
+        CreationMode::FalsePositiveRate(rate) => (0, rate.to_bits()),
+        CreationMode::HashCount(count) => (1, u64::from(count)),
+    }
+}
+
+/// Decodes a creation mode encoded by [`encode_creation_mode`].
+pub(crate) fn decode_creation_mode(mode: u32, parameter: u64) -> Result<CreationMode, Error> {
+    let invalid = |reason: &str| Error::InvalidParameters(reason.to_string());
+    match (mode, parameter) {
+        (0, rate) => Ok(CreationMode::FalsePositiveRate(f64::from_bits(rate))),
+        (1, count) => Ok(CreationMode::HashCount(
+            u32::try_from(count).map_err(|_| invalid("Invalid hash count"))?,
+        )),
+        _ => Err(invalid("Unknown creation mode")),
+    }
+}
+
+/// Estimates the number of distinct items in a filter with `set` of its `bits` set, using
+/// `n = -(m / k) ln(1 - X / m)`.
+fn estimate_items(set: u64, bits: u64, hashes: u32) -> u64 {
+    let (set, bits) = (set as f64, bits as f64);
+    // A full filter yields infinity, which saturates to u64::MAX
+    (-(bits / hashes as f64) * (1.0 - set / bits).ln()).round() as u64
+}
+
+/// The offset basis of the 64-bit FNV-1a hash.
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+
+/// The 64-bit FNV-1a hash, stable across platforms and releases.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_from(FNV_OFFSET_BASIS, bytes)
+}
+
+/// The 64-bit FNV-1a hash, starting from `basis` rather than its offset basis.
+fn fnv1a_from(basis: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(basis, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Scrambles the bits of a hash (the SplitMix64 finalizer).
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
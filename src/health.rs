@@ -0,0 +1,54 @@
+use std::{fs, path::Path, sync::atomic::Ordering};
+
+use crate::FilterStore;
+
+/// File written and removed again to check that a directory is writable.
+const PROBE_FILE: &str = ".bloomsrv-probe";
+
+impl FilterStore {
+    /// Returns why the store is not ready to serve requests, if it is not: the saved filters are
+    /// still being [restored](FilterStore::restoring), the store is a [replica](FilterStore::read_only)
+    /// not yet synced with its primary, or the data directory or write-ahead log directory cannot
+    /// be written to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::FilterStore;
+    /// use std::sync::atomic::Ordering;
+    ///
+    /// let store = FilterStore::default();
+    /// assert!(store.readiness().is_empty());
+    ///
+    /// store.restoring.store(true, Ordering::Relaxed);
+    /// assert_eq!(store.readiness(), ["The saved filters are being restored"]);
+    /// ```
+    pub fn readiness(&self) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if self.restoring.load(Ordering::Relaxed) {
+            reasons.push("The saved filters are being restored".to_string());
+        }
+        if self.read_only.load(Ordering::Relaxed) && !self.replica_synced.load(Ordering::Relaxed) {
+            reasons.push("The replica has not synced with its primary yet".to_string());
+        }
+        let log_dir = self.journal.settings().map(|(dir, _)| dir);
+        let mut dirs: Vec<&Path> = self.data_dir.iter().chain(&log_dir).map(|d| &**d).collect();
+        dirs.dedup();
+        for dir in dirs {
+            if let Err(error) = probe(dir) {
+                reasons.push(format!(
+                    "Directory '{}' is not writable: {error}",
+                    dir.display()
+                ));
+            }
+        }
+        reasons
+    }
+}
+
+/// Checks that a file can be created in `dir`.
+fn probe(dir: &Path) -> std::io::Result<()> {
+    let path = dir.join(PROBE_FILE);
+    fs::write(&path, b"")?;
+    fs::remove_file(&path)
+}
@@ -0,0 +1,419 @@
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::{
+    filter::{MAX_BIT_COUNT, MAX_HASH_COUNT},
+    BloomFilter, CreationMode, Error, FilterSnapshot, HashScheme,
+};
+
+/// Ordinal of Guava's `BloomFilterStrategies.MURMUR128_MITZ_64` (the default since Guava 12).
+const GUAVA_MURMUR128_MITZ_64: u8 = 1;
+/// Size of the header of Guava's serialized form: strategy, hash count, and number of words.
+const GUAVA_HEADER_LEN: usize = 6;
+/// Size of the header of the files of `pybloom_live.BloomFilter.tofile` (`<dQQQQ`): the false
+/// positive rate, the number of slices, the bits of each slice, the capacity and the count.
+const PYBLOOM_HEADER_LEN: usize = 40;
+
+impl FilterSnapshot {
+    /// Loads a filter serialized by Guava's `BloomFilter.writeTo`.
+    ///
+    /// The filter must use the default strategy (`MURMUR128_MITZ_64`), and must have been built
+    /// with `Funnels.byteArrayFunnel()` or `Funnels.stringFunnel(UTF_8)`, so that items are
+    /// hashed as their bytes. Guava does not record the expected number of items, so the
+    /// snapshot gets the item count for which its hash count is optimal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterSnapshot, HashScheme};
+    ///
+    /// // An empty Guava filter of 64 bits and 3 hash functions
+    /// let mut bytes = vec![1, 3, 0, 0, 0, 1];
+    /// bytes.extend_from_slice(&[0; 8]);
+    ///
+    /// let snapshot = FilterSnapshot::from_guava(&bytes).unwrap();
+    /// assert_eq!(snapshot.filter.scheme(), HashScheme::Guava);
+    /// assert_eq!(snapshot.to_guava().unwrap(), bytes);
+    /// ```
+    pub fn from_guava(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidParameters(reason.to_string());
+        if bytes.len() < GUAVA_HEADER_LEN {
+            return Err(invalid("Not a Guava Bloom filter"));
+        }
+        if bytes[0] != GUAVA_MURMUR128_MITZ_64 {
+            return Err(invalid(
+                "Only Guava Bloom filters using the MURMUR128_MITZ_64 strategy are supported",
+            ));
+        }
+        let hash_count = u32::from(bytes[1]);
+        let word_count = i32::from_be_bytes(bytes[2..6].try_into().unwrap());
+        let words = &bytes[GUAVA_HEADER_LEN..];
+        if hash_count == 0 || word_count <= 0 || words.len() != word_count as usize * 8 {
+            return Err(invalid("The Guava Bloom filter is truncated or corrupted"));
+        }
+
+        let words: Vec<u64> = words
+            .chunks_exact(8)
+            .map(|word| u64::from_be_bytes(word.try_into().unwrap()))
+            .collect();
+        let filter = BloomFilter::from_words(words, hash_count, HashScheme::Guava);
+        let item_count = (filter.bit_count() as f64 * std::f64::consts::LN_2 / hash_count as f64)
+            .max(1.0) as usize;
+        Ok(FilterSnapshot {
+            item_count,
+            creation_mode: CreationMode::HashCount(hash_count),
+            filter,
+        })
+    }
+
+    /// Serializes the filter in the form read by Guava's `BloomFilter.readFrom`.
+    ///
+    /// Only filters using the Guava hash scheme (i.e. imported from Guava, or created with its
+    /// scheme) can be exported, as Guava cannot reproduce the positions of items in other filters.
+    pub fn to_guava(&self) -> Result<Vec<u8>, Error> {
+        let filter = &self.filter;
+        if filter.scheme() != HashScheme::Guava {
+            return Err(Error::InvalidParameters(
+                "Only filters using the guava hash scheme can be exported to Guava".to_string(),
+            ));
+        }
+        let hash_count = u8::try_from(filter.hash_count()).map_err(|_| {
+            Error::InvalidParameters("Guava supports at most 255 hash functions".to_string())
+        })?;
+        let word_count = i32::try_from(filter.bits().len()).map_err(|_| {
+            Error::InvalidParameters("The filter is too large for Guava".to_string())
+        })?;
+
+        let mut bytes = Vec::with_capacity(GUAVA_HEADER_LEN + filter.bits().len() * 8);
+        bytes.push(GUAVA_MURMUR128_MITZ_64);
+        bytes.push(hash_count);
+        bytes.extend_from_slice(&word_count.to_be_bytes());
+        for word in filter.bits().iter() {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+
+    /// Loads a filter written by Python's `pybloom_live.BloomFilter.tofile` (or `pybloom`'s).
+    ///
+    /// The items must have been added as strings, which `pybloom_live` hashes as their UTF-8
+    /// bytes. The snapshot gets the capacity of the filter as its item count, and its error rate
+    /// as its false positive rate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterSnapshot, HashScheme};
+    ///
+    /// // An empty pybloom filter of 3 slices of 100 bits, for 50 items at a rate of 0.125
+    /// let mut bytes = 0.125f64.to_le_bytes().to_vec();
+    /// for field in [3u64, 100, 50, 0] {
+    ///     bytes.extend_from_slice(&field.to_le_bytes());
+    /// }
+    /// bytes.extend_from_slice(&[0; 38]);
+    ///
+    /// let snapshot = FilterSnapshot::from_pybloom(&bytes).unwrap();
+    /// assert_eq!(snapshot.filter.scheme(), HashScheme::Pybloom);
+    /// assert_eq!(snapshot.filter.seed(), 100);
+    /// assert_eq!(snapshot.to_pybloom().unwrap(), bytes);
+    /// ```
+    pub fn from_pybloom(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidParameters(reason.to_string());
+        if bytes.len() < PYBLOOM_HEADER_LEN {
+            return Err(invalid("Not a pybloom Bloom filter"));
+        }
+        let field = |index: usize| {
+            let at = 8 + index * 8;
+            u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+        };
+        let rate = f64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let (slice_count, slice_bits, capacity) = (field(0), field(1), field(2));
+        if slice_count == 0 || slice_count > u64::from(MAX_HASH_COUNT) || slice_bits == 0 {
+            return Err(invalid(
+                "The pybloom Bloom filter must have between 1 and 1024 slices of bits",
+            ));
+        }
+        let bit_count = slice_count
+            .checked_mul(slice_bits)
+            .filter(|&bits| bits <= MAX_BIT_COUNT)
+            .ok_or_else(|| Error::FilterTooLarge("The pybloom Bloom filter is too large".into()))?;
+        let data = &bytes[PYBLOOM_HEADER_LEN..];
+        if data.len() as u64 != bit_count.div_ceil(8) {
+            return Err(invalid(
+                "The pybloom Bloom filter is truncated or corrupted",
+            ));
+        }
+
+        let mut words = vec![0u64; bit_count.div_ceil(64) as usize];
+        for (index, &byte) in data.iter().enumerate() {
+            words[index / 8] |= u64::from(byte) << (index % 8 * 8);
+        }
+        let filter = BloomFilter::from_words(words, slice_count as u32, HashScheme::Native)
+            .with_hashing(HashScheme::Pybloom, slice_bits)?;
+        let creation_mode = match rate > 0.0 && rate < 1.0 {
+            true => CreationMode::FalsePositiveRate(rate),
+            false => CreationMode::HashCount(slice_count as u32),
+        };
+        Ok(FilterSnapshot {
+            item_count: usize::try_from(capacity).unwrap_or(usize::MAX).max(1),
+            creation_mode,
+            filter,
+        })
+    }
+
+    /// Serializes the filter in the form read by Python's `pybloom_live.BloomFilter.fromfile`.
+    ///
+    /// Only filters using the pybloom hash scheme can be exported, as `pybloom_live` cannot
+    /// reproduce the positions of items in other filters. The file holds the item count of the
+    /// snapshot as the capacity, its false positive rate as the error rate (`2^-k` for `k` hash
+    /// functions given), and the number of items estimated from its bits, at most its capacity,
+    /// as the count.
+    pub fn to_pybloom(&self) -> Result<Vec<u8>, Error> {
+        let filter = &self.filter;
+        if filter.scheme() != HashScheme::Pybloom {
+            return Err(Error::InvalidParameters(
+                "Only filters using the pybloom hash scheme can be exported to pybloom".to_string(),
+            ));
+        }
+        let rate = match self.creation_mode {
+            CreationMode::FalsePositiveRate(rate) => rate,
+            CreationMode::HashCount(count) => 0.5f64.powi(count as i32),
+        };
+        let (slice_count, slice_bits) = (u64::from(filter.hash_count()), filter.seed());
+        let capacity = self.item_count as u64;
+        let count = filter.estimated_items().min(capacity);
+        let bit_count = slice_count * slice_bits;
+
+        let mut bytes = Vec::with_capacity(PYBLOOM_HEADER_LEN + bit_count.div_ceil(8) as usize);
+        bytes.extend_from_slice(&rate.to_le_bytes());
+        for field in [slice_count, slice_bits, capacity, count] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        // The bits of pybloom's little-endian bit array, in whole bytes
+        let words = filter.bits();
+        let data = words.iter().flat_map(|word| word.to_le_bytes());
+        bytes.extend(data.take(bit_count.div_ceil(8) as usize));
+        Ok(bytes)
+    }
+}
+
+/// Returns the bit positions of an item in a filter of `slice_count` slices of `slice_bits` bits
+/// each, as `pybloom_live.BloomFilter` sets them: the position in slice `i` is the chunk `i` of
+/// the digests of the item salted by `0`, `1` and so on, modulo the bits of a slice.
+///
+/// The chunks are of 16 bits for slices under 2^15 bits, of 32 bits under 2^31, and of 64 bits
+/// beyond; the digest is the smallest of MD5, SHA-1, SHA-256, SHA-384 and SHA-512 holding all the
+/// chunks, or SHA-512 (with as many salts as needed) if none does.
+pub(crate) fn pybloom_indices(item: &[u8], slice_count: u32, slice_bits: u64) -> Vec<u64> {
+    let chunk = match slice_bits {
+        bits if bits >= 1 << 31 => 8,
+        bits if bits >= 1 << 15 => 4,
+        _ => 2,
+    };
+    let digest: fn(u32, &[u8]) -> Vec<u8> = match 8 * chunk * slice_count as usize {
+        385.. => salted::<Sha512>,
+        257..=384 => salted::<Sha384>,
+        161..=256 => salted::<Sha256>,
+        129..=160 => salted::<Sha1>,
+        _ => |salt, item| md5(&[&md5(&salt.to_le_bytes())[..], item].concat()).to_vec(),
+    };
+
+    let slices = slice_count as usize;
+    let mut indices = Vec::with_capacity(slices);
+    for salt in 0.. {
+        for chunk in digest(salt, item).chunks_exact(chunk) {
+            if indices.len() == slices {
+                return indices;
+            }
+            let value = chunk
+                .iter()
+                .rev()
+                .fold(0u64, |value, &byte| value << 8 | u64::from(byte));
+            indices.push(indices.len() as u64 * slice_bits + value % slice_bits);
+        }
+        if indices.len() == slices {
+            break;
+        }
+    }
+    indices
+}
+
+/// Returns the digest of `item` following the digest of its 32-bit little-endian `salt`, as
+/// `pybloom_live` salts its digests.
+fn salted<D: Digest>(salt: u32, item: &[u8]) -> Vec<u8> {
+    let mut hasher = D::new();
+    hasher.update(D::digest(salt.to_le_bytes()));
+    hasher.update(item);
+    hasher.finalize().to_vec()
+}
+
+/// The MD5 digest (RFC 1321), for the positions of the smallest pybloom filters only.
+fn md5(bytes: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    // The integer parts of abs(sin(i + 1)) * 2^32
+    const K: [u32; 64] = [
+        0xd76a_a478,
+        0xe8c7_b756,
+        0x2420_70db,
+        0xc1bd_ceee,
+        0xf57c_0faf,
+        0x4787_c62a,
+        0xa830_4613,
+        0xfd46_9501,
+        0x6980_98d8,
+        0x8b44_f7af,
+        0xffff_5bb1,
+        0x895c_d7be,
+        0x6b90_1122,
+        0xfd98_7193,
+        0xa679_438e,
+        0x49b4_0821,
+        0xf61e_2562,
+        0xc040_b340,
+        0x265e_5a51,
+        0xe9b6_c7aa,
+        0xd62f_105d,
+        0x0244_1453,
+        0xd8a1_e681,
+        0xe7d3_fbc8,
+        0x21e1_cde6,
+        0xc337_07d6,
+        0xf4d5_0d87,
+        0x455a_14ed,
+        0xa9e3_e905,
+        0xfcef_a3f8,
+        0x676f_02d9,
+        0x8d2a_4c8a,
+        0xfffa_3942,
+        0x8771_f681,
+        0x6d9d_6122,
+        0xfde5_380c,
+        0xa4be_ea44,
+        0x4bde_cfa9,
+        0xf6bb_4b60,
+        0xbebf_bc70,
+        0x289b_7ec6,
+        0xeaa1_27fa,
+        0xd4ef_3085,
+        0x0488_1d05,
+        0xd9d4_d039,
+        0xe6db_99e5,
+        0x1fa2_7cf8,
+        0xc4ac_5665,
+        0xf429_2244,
+        0x432a_ff97,
+        0xab94_23a7,
+        0xfc93_a039,
+        0x655b_59c3,
+        0x8f0c_cc92,
+        0xffef_f47d,
+        0x8584_5dd1,
+        0x6fa8_7e4f,
+        0xfe2c_e6e0,
+        0xa301_4314,
+        0x4e08_11a1,
+        0xf753_7e82,
+        0xbd3a_f235,
+        0x2ad7_d2bb,
+        0xeb86_d391,
+    ];
+
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(bytes.len() as u64).wrapping_mul(8).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    for block in message.chunks_exact(64) {
+        let words: Vec<u32> = block
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(words[g]);
+            (a, d, c) = (d, c, b);
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16 * 4 + i % 4]));
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+/// The 128-bit MurmurHash3 (x64 variant), as used by Guava's `Hashing.murmur3_128(seed)`.
+///
+/// Returns the two 64-bit halves of the hash, in the order Guava calls them `hash1` and `hash2`.
+pub(crate) fn murmur3_128(bytes: &[u8], seed: u32) -> (u64, u64) {
+    const C1: u64 = 0x87C3_7B91_1142_53D5;
+    const C2: u64 = 0x4CF5_AD43_2745_937F;
+    let mix_k1 = |k1: u64| k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix_k2 = |k2: u64| k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+
+    let (mut h1, mut h2) = (u64::from(seed), u64::from(seed));
+    let mut blocks = bytes.chunks_exact(16);
+    for block in &mut blocks {
+        let k1 = u64::from_le_bytes(block[..8].try_into().unwrap());
+        let k2 = u64::from_le_bytes(block[8..].try_into().unwrap());
+
+        h1 ^= mix_k1(k1);
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52DC_E729);
+        h2 ^= mix_k2(k2);
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x3849_5AB5);
+    }
+
+    let tail = blocks.remainder();
+    let little_endian = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .rev()
+            .fold(0u64, |value, &byte| value << 8 | u64::from(byte))
+    };
+    if tail.len() > 8 {
+        h2 ^= mix_k2(little_endian(&tail[8..]));
+    }
+    if !tail.is_empty() {
+        h1 ^= mix_k1(little_endian(&tail[..tail.len().min(8)]));
+    }
+
+    h1 ^= bytes.len() as u64;
+    h2 ^= bytes.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    (h1, h2)
+}
+
+/// The finalization mix of MurmurHash3.
+fn fmix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xC4CE_B9FE_1A85_EC53);
+    k ^ (k >> 33)
+}
@@ -0,0 +1,238 @@
+//! Background jobs, e.g. loading a filter from a file or URL on the side of the service, or
+//! rebuilding one from its item journal, whose progress clients poll at `GET /jobs/:id`.
+
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::{Component, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    server::{IngestStats, LineIngest},
+    store::{new_id, unix_seconds},
+    CreationMode, Error, SharedState,
+};
+
+/// How long finished jobs are kept, for their clients to poll their outcome.
+const JOB_RETENTION: Duration = Duration::from_secs(3600);
+
+/// Size of the chunks a file is read in.
+const READ_CHUNK_BYTES: usize = 1 << 16;
+
+/// The stage of a job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// A job loading a filter, as served at `GET /jobs/:id`.
+#[derive(Clone, Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct JobInfo {
+    pub(crate) id: String,
+    pub(crate) filter: String,
+    pub(crate) source: String,
+    pub(crate) status: JobStatus,
+    /// Size of the source in bytes, if known up front, to tell the progress from `bytes` of the
+    /// progress.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) total_bytes: Option<u64>,
+    pub(crate) progress: IngestStats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    /// Start time, in seconds since the Unix epoch.
+    pub(crate) started_at: u64,
+    /// End time, in seconds since the Unix epoch, once the job is over.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) finished_at: Option<u64>,
+}
+
+/// The jobs of a store, running or finished in the last hour.
+#[derive(Debug, Default)]
+pub(crate) struct Jobs {
+    jobs: Mutex<HashMap<String, (JobInfo, Option<Instant>)>>,
+}
+
+impl Jobs {
+    /// Records a new running job, forgetting the jobs finished too long ago.
+    fn start(&self, filter: &str, source: &str, total_bytes: Option<u64>) -> JobInfo {
+        let info = JobInfo {
+            id: new_id().to_string(),
+            filter: filter.to_string(),
+            source: source.to_string(),
+            status: JobStatus::Running,
+            total_bytes,
+            progress: IngestStats::default(),
+            error: None,
+            started_at: unix_seconds(SystemTime::now()),
+            finished_at: None,
+        };
+        let mut jobs = self.jobs.lock();
+        jobs.retain(|_, (_, finished)| finished.is_none_or(|at| at.elapsed() < JOB_RETENTION));
+        jobs.insert(info.id.clone(), (info.clone(), None));
+        info
+    }
+
+    /// Records the progress of a job, and its outcome once it is over.
+    fn update(&self, id: &str, progress: &IngestStats, outcome: Option<Result<(), Error>>) {
+        if let Some((info, finished)) = self.jobs.lock().get_mut(id) {
+            info.progress = progress.clone();
+            match outcome {
+                None => return,
+                Some(Ok(())) => info.status = JobStatus::Completed,
+                Some(Err(error)) => {
+                    info.status = JobStatus::Failed;
+                    info.error = Some(error.to_string());
+                }
+            }
+            info.finished_at = Some(unix_seconds(SystemTime::now()));
+            *finished = Some(Instant::now());
+        }
+    }
+
+    /// Returns a job by its id.
+    pub(crate) fn get(&self, id: &str) -> Result<JobInfo, Error> {
+        self.jobs
+            .lock()
+            .get(id)
+            .map(|(info, _)| info.clone())
+            .ok_or_else(|| Error::JobNotFound(id.to_string()))
+    }
+}
+
+/// A source of items to load into a filter, one per line.
+enum Source {
+    File(tokio::fs::File),
+    Url(reqwest::Response),
+}
+
+impl Source {
+    /// Opens a `file://` or `http(s)://` source, which must start with one of the `allowed`
+    /// prefixes.
+    async fn open(source: &str, allowed: &[String]) -> Result<Self, Error> {
+        if !allowed
+            .iter()
+            .any(|prefix| source.starts_with(prefix.as_str()))
+        {
+            return Err(Error::Forbidden(format!(
+                "Loading from '{source}' is not allowed on this server"
+            )));
+        }
+        let unreadable = |e: &dyn std::fmt::Display| {
+            Error::InvalidParameters(format!("Cannot read '{source}': {e}"))
+        };
+        if let Some(path) = source.strip_prefix("file://") {
+            let path = PathBuf::from(path);
+            // Prefixes only confine paths without parent components
+            if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+                return Err(Error::InvalidParameters(format!(
+                    "Invalid source '{source}': expected an absolute path, without '..'"
+                )));
+            }
+            let file = tokio::fs::File::open(&path)
+                .await
+                .map_err(|e| unreadable(&e))?;
+            Ok(Source::File(file))
+        } else if source.starts_with("http://") || source.starts_with("https://") {
+            let response = reqwest::get(source)
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(|e| unreadable(&e))?;
+            Ok(Source::Url(response))
+        } else {
+            Err(Error::InvalidParameters(format!(
+                "Invalid source '{source}': expected a file:// or https:// URL"
+            )))
+        }
+    }
+
+    /// Returns the size of the source in bytes, if known.
+    async fn len(&self) -> Option<u64> {
+        match self {
+            Source::File(file) => file.metadata().await.ok().map(|m| m.len()),
+            Source::Url(response) => response.content_length(),
+        }
+    }
+
+    /// Inserts the items of the source, recording the progress of the job after each chunk.
+    async fn load(self, ingest: &mut LineIngest, jobs: &Jobs, id: &str) -> Result<(), Error> {
+        let failed = |e: &dyn std::fmt::Display| {
+            Error::InvalidParameters(format!("Failed to read the source: {e}"))
+        };
+        match self {
+            Source::File(mut file) => {
+                let mut chunk = vec![0; READ_CHUNK_BYTES];
+                loop {
+                    let len = file.read(&mut chunk).await.map_err(|e| failed(&e))?;
+                    if len == 0 {
+                        break;
+                    }
+                    ingest.feed(&chunk[..len])?;
+                    jobs.update(id, &ingest.stats, None);
+                }
+            }
+            Source::Url(response) => {
+                let mut chunks = response.bytes_stream();
+                while let Some(chunk) = chunks.next().await {
+                    ingest.feed(&chunk.map_err(|e| failed(&e))?)?;
+                    jobs.update(id, &ingest.stats, None);
+                }
+            }
+        }
+        ingest.finish()
+    }
+}
+
+/// Starts a job inserting the items of `source` into the filter of `ingest`, one per line,
+/// failing up front if the source cannot be opened.
+pub(crate) async fn start_load(
+    state: SharedState,
+    mut ingest: LineIngest,
+    source: &str,
+    allowed: &[String],
+) -> Result<JobInfo, Error> {
+    let opened = Source::open(source, allowed).await?;
+    let job = state.jobs.start(ingest.name(), source, opened.len().await);
+    let id = job.id.clone();
+    tokio::spawn(async move {
+        let loaded = opened.load(&mut ingest, &state.jobs, &id).await;
+        if let Err(error) = &loaded {
+            tracing::warn!(%error, job = id, filter = ingest.name(), "Loading the filter failed");
+        }
+        state.jobs.update(&id, &ingest.stats, Some(loaded));
+    });
+    Ok(job)
+}
+
+/// Starts a job rebuilding the filter `name` from its item journal, for `item_count` items or with
+/// `creation_mode`, failing up front if the filter keeps no journal.
+pub(crate) fn start_rebuild(
+    state: SharedState,
+    name: String,
+    item_count: Option<usize>,
+    creation_mode: Option<CreationMode>,
+) -> Result<JobInfo, Error> {
+    let journal = state.item_journal(&name)?;
+    let job = state.jobs.start(&name, "journal", Some(journal.len()));
+    let id = job.id.clone();
+    // Replayed on a thread of its own, as reading the journal blocks
+    tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let mut stats = IngestStats::default();
+        let rebuilt = state.rebuild_from_journal(&name, item_count, creation_mode, |progress| {
+            stats = IngestStats::replayed(progress, started);
+            state.jobs.update(&id, &stats, None);
+        });
+        if let Err(error) = &rebuilt {
+            tracing::warn!(%error, job = id, filter = name, "Rebuilding the filter failed");
+        }
+        state.jobs.update(&id, &stats, Some(rebuilt.map(|_| ())));
+    });
+    Ok(job)
+}
@@ -0,0 +1,303 @@
+//! Journals of the items of the filters keeping one, from which they are rebuilt with other
+//! parameters: a Bloom filter cannot enumerate its items.
+
+use parking_lot::Mutex;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    filter::fnv1a,
+    store::{rebuild_spec, FilterContainer},
+    wal::encode_record,
+    CreationMode, Error, FilterInfo, FilterStore,
+};
+
+/// Extension of the journals, in the data directory of the store.
+pub(crate) const JOURNAL_EXTENSION: &str = "items";
+
+/// Bytes read from a journal at a time, while its filter is rebuilt.
+const READ_CHUNK_BYTES: u64 = 1 << 20;
+
+/// An item journaled as inserted into, or removed from, a filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ItemChange {
+    Insert,
+    Remove,
+}
+
+/// An item read from a journal, with how it changed the filter.
+type JournaledItem = (ItemChange, Vec<u8>);
+
+/// Progress of a filter rebuilt from its item journal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// Items replayed, inserted or removed.
+    pub items: usize,
+    /// Items inserted that were definitely new to the rebuilt filter.
+    pub new: usize,
+    /// Bytes of the journal replayed.
+    pub bytes: u64,
+    /// Size of the journal, growing as items are inserted during the rebuild.
+    pub total_bytes: u64,
+}
+
+/// The journal of the items of a filter, appended to as they are inserted or removed, after the
+/// transform of the filter, and emptied as it is cleared.
+///
+/// Items are written while the filter is locked, so that a reader holding the filter locked for
+/// writing has every item of the filter in the journal.
+pub(crate) struct ItemJournal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl ItemJournal {
+    /// Creates an empty journal at `path`, replacing any journal there.
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        File::create(path)?;
+        Self::open(path)
+    }
+
+    /// Opens the journal at `path`, appending to its items, e.g. as the filter is loaded again.
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(ItemJournal {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Records an item inserted into, or removed from, the filter.
+    pub(crate) fn record(&self, change: ItemChange, item: &[u8]) -> Result<(), Error> {
+        let mut payload = Vec::with_capacity(item.len() + 1);
+        payload.push(change as u8);
+        payload.extend_from_slice(item);
+        // A single write, so that a crash leaves at most one torn record at the end
+        self.file
+            .lock()
+            .write_all(&encode_record(&payload))
+            .map_err(|e| self.failed(e))
+    }
+
+    /// Forgets every item, as the filter is cleared.
+    pub(crate) fn clear(&self) -> Result<(), Error> {
+        self.file.lock().set_len(0).map_err(|e| self.failed(e))
+    }
+
+    /// Replaces the items with `items`, inserted, e.g. as the filter is rebuilt from them.
+    pub(crate) fn replace<'a>(&self, items: impl Iterator<Item = &'a [u8]>) -> Result<(), Error> {
+        let mut file = self.file.lock();
+        file.set_len(0).map_err(|e| self.failed(e))?;
+        let mut records = Vec::new();
+        for item in items {
+            let mut payload = Vec::with_capacity(item.len() + 1);
+            payload.push(ItemChange::Insert as u8);
+            payload.extend_from_slice(item);
+            records.extend_from_slice(&encode_record(&payload));
+        }
+        file.write_all(&records).map_err(|e| self.failed(e))
+    }
+
+    /// Returns the size of the journal, in bytes.
+    pub(crate) fn len(&self) -> u64 {
+        self.file.lock().metadata().map_or(0, |m| m.len())
+    }
+
+    /// Reads the items recorded from `offset`, about a chunk of them, returning them and the
+    /// offset after the last; a record being written, or torn by a crash, is left for later.
+    pub(crate) fn read(&self, offset: u64) -> Result<(Vec<JournaledItem>, u64), Error> {
+        let mut file = File::open(&self.path).map_err(|e| self.failed(e))?;
+        let mut bytes = Vec::new();
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| (&mut file).take(READ_CHUNK_BYTES).read_to_end(&mut bytes))
+            .map_err(|e| self.failed(e))?;
+        // A record larger than a chunk is read whole
+        if let Some(len) = bytes.get(..4) {
+            let len = u32::from_le_bytes(len.try_into().unwrap_or_default()) as u64 + 12;
+            if len > bytes.len() as u64 {
+                bytes.clear();
+                file.seek(SeekFrom::Start(offset))
+                    .and_then(|_| (&mut file).take(len).read_to_end(&mut bytes))
+                    .map_err(|e| self.failed(e))?;
+            }
+        }
+        let mut items = Vec::new();
+        let mut records = bytes.as_slice();
+        while let Some((change, item, rest)) = next_item(records) {
+            items.push((change, item.to_vec()));
+            records = rest;
+        }
+        Ok((items, offset + (bytes.len() - records.len()) as u64))
+    }
+
+    /// Removes the journal, as its filter is deleted.
+    pub(crate) fn delete(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+
+    fn failed(&self, error: io::Error) -> Error {
+        Error::Storage(format!(
+            "Cannot use the item journal '{}': {error}",
+            self.path.display()
+        ))
+    }
+}
+
+/// Returns the path of the item journal of a filter.
+pub(crate) fn journal_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.{JOURNAL_EXTENSION}"))
+}
+
+/// Splits the next valid record off the records of a journal.
+fn next_item(records: &[u8]) -> Option<(ItemChange, &[u8], &[u8])> {
+    let len = u32::from_le_bytes(records.get(..4)?.try_into().ok()?) as usize;
+    let payload = records.get(4..4 + len)?;
+    let checksum = u64::from_le_bytes(records.get(4 + len..12 + len)?.try_into().ok()?);
+    if fnv1a(payload) != checksum {
+        return None;
+    }
+    let change = match payload.first()? {
+        0 => ItemChange::Insert,
+        1 => ItemChange::Remove,
+        _ => return None,
+    };
+    Some((change, &payload[1..], &records[12 + len..]))
+}
+
+impl FilterStore {
+    /// Rebuilds the filter `name` from its item journal, sized for `item_count` items, or created
+    /// with `creation_mode`, reporting the `progress` after each chunk of the journal; returns the
+    /// summary of the rebuilt filter. Parameters not given are those of the filter.
+    ///
+    /// The filter is served as it is while the items are replayed into a new one, which replaces
+    /// it atomically once done, with the items inserted meanwhile, keeping its id, expiry and
+    /// shadow. Fails with [`Error::PreconditionFailed`] if the filter is cleared or replaced
+    /// meanwhile, as the items replayed would no longer be its own.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterSpec, FilterStore};
+    ///
+    /// let dir = std::env::temp_dir().join(format!("bloomsrv-journal-doc-{}", std::process::id()));
+    /// let mut store = FilterStore::new();
+    /// store.data_dir = Some(dir.clone());
+    /// let spec = FilterSpec {
+    ///     name: "emails".to_string(),
+    ///     item_count: 100,
+    ///     false_positive_rate: Some(0.01),
+    ///     journal: true,
+    ///     ..Default::default()
+    /// };
+    /// store.create(spec).unwrap();
+    /// store.insert("emails", "user@example.com").unwrap();
+    ///
+    /// let info = store
+    ///     .rebuild_from_journal("emails", Some(1_000_000), None, |_| {})
+    ///     .unwrap();
+    /// assert_eq!(info.capacity, 1_000_000);
+    /// assert!(store.contains("emails", "user@example.com").unwrap());
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn rebuild_from_journal(
+        &self,
+        name: &str,
+        item_count: Option<usize>,
+        creation_mode: Option<CreationMode>,
+        mut progress: impl FnMut(&ReplayProgress),
+    ) -> Result<FilterInfo, Error> {
+        let entry = self.entry(name)?;
+        let (journal, generation, mut rebuilt) = {
+            let container = entry.read();
+            self.check_rebuild(name, &container, item_count, creation_mode)?;
+            let rebuilt = rebuild_spec(&container, item_count, creation_mode).build(None)?;
+            (self.item_journal(name)?, container.generation, rebuilt)
+        };
+        let mut replayed = ReplayProgress::default();
+        let mut replay = |offset: u64, rebuilt: &mut FilterContainer| {
+            let (items, next) = journal.read(offset)?;
+            for (change, item) in items {
+                match change {
+                    ItemChange::Insert => replayed.new += usize::from(rebuilt.filter.insert(&item)),
+                    ItemChange::Remove => {
+                        rebuilt.filter.remove(&item);
+                    }
+                }
+                replayed.items += 1;
+            }
+            replayed.bytes = next;
+            replayed.total_bytes = journal.len();
+            Ok::<_, Error>((next, replayed))
+        };
+
+        // Without locking the filter, until the journal is read to its end...
+        let mut offset = 0;
+        loop {
+            let (next, replayed) = replay(offset, &mut rebuilt)?;
+            progress(&replayed);
+            if next == offset || next >= replayed.total_bytes {
+                offset = next;
+                break;
+            }
+            offset = next;
+        }
+
+        // ...and then with the filter locked, for the items inserted meanwhile: none can be
+        // inserted until it is replaced
+        let mut container = entry.write();
+        if container.generation != generation
+            || !self.resident(name).is_some_and(|e| Arc::ptr_eq(&e, &entry))
+        {
+            return Err(Error::PreconditionFailed(name.to_string()));
+        }
+        loop {
+            let (next, replayed) = replay(offset, &mut rebuilt)?;
+            // A record torn by a crash ends the journal
+            if next == offset {
+                progress(&replayed);
+                break;
+            }
+            offset = next;
+        }
+        let info = self.assign_rebuilt(name, &mut container, rebuilt)?;
+        drop(container);
+        self.notify_inserted();
+        self.enforce_memory_budget();
+        Ok(info)
+    }
+
+    /// Returns the item journal of the filter `name`, failing if it keeps none.
+    pub(crate) fn item_journal(&self, name: &str) -> Result<Arc<ItemJournal>, Error> {
+        self.entry(name)?.read().journal.clone().ok_or_else(|| {
+            Error::InvalidParameters(format!("Filter '{name}' keeps no journal of its items"))
+        })
+    }
+
+    /// Opens the item journal of the filter `name` kept in the data directory, if any, e.g. as the
+    /// filter is loaded again.
+    pub(crate) fn open_journal(&self, name: &str) -> io::Result<()> {
+        let Some(dir) = &self.data_dir else {
+            return Ok(());
+        };
+        let path = journal_path(dir, name);
+        if let (Some(entry), true) = (self.resident(name), path.exists()) {
+            entry.write().journal = Some(Arc::new(ItemJournal::open(&path)?));
+        }
+        Ok(())
+    }
+
+    /// Removes the item journal left in the data directory for the filter `name`, e.g. by a filter
+    /// of the same name evicted, as a filter keeping none is created.
+    pub(crate) fn discard_journal(&self, name: &str) {
+        if let Some(dir) = &self.data_dir {
+            let _ = fs::remove_file(journal_path(dir, name));
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use parking_lot::RwLock;
+use rsa::{traits::PublicKeyParts, BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Access, Error, KeyScope};
+
+/// Seconds by which the clocks of the identity provider and the service may differ.
+const CLOCK_LEEWAY: u64 = 60;
+/// Length of the shortest RSA modulus accepted, in bits (NIST SP 800-131A).
+const MIN_MODULUS_BITS: usize = 2048;
+
+/// Validates the bearer tokens of an OpenID Connect identity provider: JSON Web Tokens signed
+/// with RS256 by one of the keys of its JWKS, and maps their roles to [scopes](KeyScope).
+///
+/// A token is accepted if its signature is valid, its `iss` claim is the issuer, its `aud` claim
+/// is (or includes) the audience, and it has not expired; its scope is the widest of the scopes
+/// its roles map to.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{Error, JwtValidator, KeyScope};
+///
+/// let mut validator = JwtValidator::new("https://id.example.com", "bloomsrv");
+/// validator.roles.insert("fraud-team".to_string(), KeyScope::ReadWrite);
+/// assert_eq!(validator.set_keys(br#"{ "keys": [] }"#).unwrap(), 0);
+///
+/// assert!(matches!(
+///     validator.validate("not.a.token"),
+///     Err(Error::Unauthorized(_))
+/// ));
+/// ```
+#[derive(Debug)]
+pub struct JwtValidator {
+    /// Expected `iss` claim of the tokens.
+    pub issuer: String,
+    /// Expected `aud` claim of the tokens, the service itself.
+    pub audience: String,
+    /// Claim holding the roles of a token: an array of strings, or a string of space-separated
+    /// roles (like the standard `scope` claim).
+    pub role_claim: String,
+    /// Scopes of the roles; by default, the names of the scopes (`read`, `read_write` and
+    /// `admin`) are roles of the same scope.
+    pub roles: HashMap<String, KeyScope>,
+    keys: RwLock<Vec<RsaKey>>,
+}
+
+/// A public RSA key of a JWKS.
+#[derive(Debug)]
+struct RsaKey {
+    id: Option<String>,
+    key: RsaPublicKey,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    #[serde(rename = "use")]
+    usage: Option<String>,
+    alg: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+impl JwtValidator {
+    /// Creates a validator of the tokens of `issuer` meant for `audience`, without keys yet; see
+    /// [`JwtValidator::set_keys`].
+    pub fn new(issuer: &str, audience: &str) -> Self {
+        let roles = [KeyScope::Read, KeyScope::ReadWrite, KeyScope::Admin]
+            .into_iter()
+            .map(|scope| (scope.to_string(), scope))
+            .collect();
+        JwtValidator {
+            issuer: issuer.to_string(),
+            audience: audience.to_string(),
+            role_claim: "roles".to_string(),
+            roles,
+            keys: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Replaces the signing keys by the RSA keys of a JWKS document (RFC 7517), as served at the
+    /// `jwks_uri` of the identity provider. Keys for other uses or algorithms are skipped.
+    ///
+    /// Returns the number of keys kept.
+    pub fn set_keys(&self, jwks: &[u8]) -> Result<usize, Error> {
+        let invalid = |reason: String| Error::InvalidParameters(format!("Invalid JWKS: {reason}"));
+        let jwks: Jwks = serde_json::from_slice(jwks).map_err(|e| invalid(e.to_string()))?;
+        let mut keys = Vec::new();
+        for jwk in jwks.keys {
+            let signs = jwk.usage.as_deref().unwrap_or("sig") == "sig";
+            let rs256 = jwk.alg.as_deref().unwrap_or("RS256") == "RS256";
+            if jwk.kty != "RSA" || !signs || !rs256 {
+                continue;
+            }
+            let (Some(n), Some(e)) = (&jwk.n, &jwk.e) else {
+                return Err(invalid(
+                    "an RSA key lacks its modulus or exponent".to_string(),
+                ));
+            };
+            let modulus = BigUint::from_bytes_be(&decode(n).map_err(invalid)?);
+            let exponent = BigUint::from_bytes_be(&decode(e).map_err(invalid)?);
+            let key = RsaPublicKey::new(modulus, exponent)
+                .map_err(|e| invalid(format!("an RSA key is invalid: {e}")))?;
+            if key.n().bits() < MIN_MODULUS_BITS {
+                return Err(invalid(format!(
+                    "an RSA key is shorter than {MIN_MODULUS_BITS} bits"
+                )));
+            }
+            keys.push(RsaKey { id: jwk.kid, key });
+        }
+        let count = keys.len();
+        *self.keys.write() = keys;
+        Ok(count)
+    }
+
+    /// Validates a token, returning the access of its subject (its `sub` claim), with the widest
+    /// scope of its roles.
+    ///
+    /// Fails with [`Error::Unauthorized`] if the token is malformed, not signed by a known key,
+    /// expired, or meant for another issuer or audience, and with [`Error::Forbidden`] if none of
+    /// its roles has a scope.
+    pub fn validate(&self, token: &str) -> Result<Access, Error> {
+        let unauthorized = |reason: &str| Error::Unauthorized(format!("Invalid token: {reason}"));
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(unauthorized("not a JSON Web Token"));
+        };
+        let decode_json = |part: &str| {
+            decode(part)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+                .ok_or_else(|| unauthorized("not a JSON Web Token"))
+        };
+        let header: Header = serde_json::from_value(decode_json(header)?)
+            .map_err(|_| unauthorized("not a JSON Web Token"))?;
+        if header.alg != "RS256" {
+            return Err(unauthorized(&format!(
+                "unsupported algorithm '{}'",
+                header.alg
+            )));
+        }
+        // The signature covers the header and payload, as encoded
+        let signed = &token[..token.len() - signature.len() - 1];
+        let signature = decode(signature).map_err(|_| unauthorized("malformed signature"))?;
+        {
+            let keys = self.keys.read();
+            let key = match &header.kid {
+                Some(kid) => keys.iter().find(|key| key.id.as_ref() == Some(kid)),
+                None if keys.len() == 1 => keys.first(),
+                None => None,
+            }
+            .ok_or_else(|| unauthorized("signed by an unknown key"))?;
+            if !key.verify(signed.as_bytes(), &signature) {
+                return Err(unauthorized("bad signature"));
+            }
+        }
+
+        let claims = decode_json(payload)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        match claims["exp"].as_u64() {
+            Some(exp) if exp.saturating_add(CLOCK_LEEWAY) > now => {}
+            Some(_) => return Err(unauthorized("expired")),
+            None => return Err(unauthorized("no expiry")),
+        }
+        if claims["nbf"]
+            .as_u64()
+            .is_some_and(|nbf| nbf > now.saturating_add(CLOCK_LEEWAY))
+        {
+            return Err(unauthorized("not valid yet"));
+        }
+        if claims["iss"].as_str() != Some(self.issuer.as_str()) {
+            return Err(unauthorized("issued by another issuer"));
+        }
+        let audience_matches = match &claims["aud"] {
+            Value::String(aud) => *aud == self.audience,
+            Value::Array(auds) => auds.iter().any(|aud| *aud == *self.audience),
+            _ => false,
+        };
+        if !audience_matches {
+            return Err(unauthorized("meant for another audience"));
+        }
+
+        let roles: Vec<&str> = match &claims[&self.role_claim] {
+            Value::String(roles) => roles.split_whitespace().collect(),
+            Value::Array(roles) => roles.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let scope = roles
+            .into_iter()
+            .filter_map(|role| self.roles.get(role).copied())
+            .max()
+            .ok_or_else(|| {
+                Error::Forbidden(format!(
+                    "The token has no role of this service in its '{}' claim",
+                    self.role_claim
+                ))
+            })?;
+        let subject = claims["sub"].as_str().unwrap_or_default();
+        Ok(Access::unrestricted(&format!("token:{subject}"), scope))
+    }
+}
+
+impl RsaKey {
+    /// Verifies an RSASSA-PKCS1-v1_5 signature with SHA-256 (RFC 8017, section 8.2.2).
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        let digest = Sha256::digest(message);
+        self.key
+            .verify(Pkcs1v15Sign::new::<Sha256>(), &digest, signature)
+            .is_ok()
+    }
+}
+
+/// Decodes base64url, with or without padding.
+fn decode(text: &str) -> Result<Vec<u8>, String> {
+    BASE64_URL
+        .decode(text.trim_end_matches('='))
+        .map_err(|e| e.to_string())
+}
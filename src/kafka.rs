@@ -0,0 +1,199 @@
+use kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
+use std::{str::FromStr, sync::atomic::Ordering, thread, time::Duration};
+
+use crate::{Error, SharedState};
+
+/// Time to wait before consuming again after a failure, e.g. of the brokers.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Longest time a poll of the brokers waits for messages.
+const FETCH_MAX_WAIT: Duration = Duration::from_millis(500);
+
+/// A Kafka topic, and the filter its messages are inserted into.
+///
+/// Parsed from `<topic>=<filter>`, or `<topic>=<namespace>/<filter>` for a filter of a namespace.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::TopicMapping;
+///
+/// let mapping: TopicMapping = "signups=payments/emails".parse().unwrap();
+/// assert_eq!(mapping.topic, "signups");
+/// assert_eq!(mapping.namespace.as_deref(), Some("payments"));
+/// assert_eq!(mapping.filter, "emails");
+///
+/// assert!("signups".parse::<TopicMapping>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopicMapping {
+    pub topic: String,
+    /// Namespace of the filter, or `None` for the default namespace.
+    pub namespace: Option<String>,
+    pub filter: String,
+}
+
+impl FromStr for TopicMapping {
+    type Err = Error;
+
+    fn from_str(mapping: &str) -> Result<Self, Error> {
+        let invalid = || {
+            Error::InvalidParameters(format!(
+                "Invalid topic mapping '{mapping}', expected <topic>=[<namespace>/]<filter>"
+            ))
+        };
+        let (topic, target) = mapping.split_once('=').ok_or_else(invalid)?;
+        let (namespace, filter) = match target.split_once('/') {
+            Some((namespace, filter)) => (Some(namespace.to_string()), filter),
+            None => (None, target),
+        };
+        if topic.is_empty() || filter.is_empty() || namespace.as_deref() == Some("") {
+            return Err(invalid());
+        }
+        Ok(TopicMapping {
+            topic: topic.to_string(),
+            namespace,
+            filter: filter.to_string(),
+        })
+    }
+}
+
+/// Consumes Kafka topics into filters: the value of each message is an item, inserted into the
+/// filter its topic is mapped to.
+#[derive(Clone, Debug)]
+pub struct KafkaIngest {
+    /// Brokers to bootstrap from, as `<host>:<port>`.
+    pub brokers: Vec<String>,
+    /// Consumer group, under which the offsets consumed are committed to Kafka.
+    pub group: String,
+    pub topics: Vec<TopicMapping>,
+}
+
+impl KafkaIngest {
+    /// Consumes the topics into the filters of `state`, forever, committing the offsets only once
+    /// their messages are inserted.
+    ///
+    /// Blocks the calling thread. Failures, e.g. of the brokers or a filter missing, are logged,
+    /// and the topics are consumed again from the last committed offsets after a delay, so that
+    /// no message is lost (though some may be inserted twice, which changes nothing). Waits for
+    /// the saved filters to be restored before consuming, and pauses in
+    /// [read-only mode](crate::FilterStore::read_only_mode).
+    pub fn run(&self, state: &SharedState) {
+        while state.restoring.load(Ordering::Relaxed) {
+            thread::sleep(FETCH_MAX_WAIT);
+        }
+        loop {
+            if let Err(error) = self.consume(state) {
+                tracing::error!(%error, "Cannot consume the Kafka topics, retrying");
+            }
+            thread::sleep(RETRY_DELAY);
+        }
+    }
+
+    /// Consumes the topics until a failure.
+    fn consume(&self, state: &SharedState) -> Result<(), String> {
+        let mut consumer = self
+            .topics
+            .iter()
+            .fold(
+                Consumer::from_hosts(self.brokers.clone()),
+                |builder, mapping| builder.with_topic(mapping.topic.clone()),
+            )
+            .with_group(self.group.clone())
+            .with_fallback_offset(FetchOffset::Earliest)
+            .with_offset_storage(Some(GroupOffsetStorage::Kafka))
+            .with_fetch_max_wait_time(FETCH_MAX_WAIT)
+            .create()
+            .map_err(|e| e.to_string())?;
+        tracing::info!(
+            brokers = self.brokers.join(","),
+            group = self.group,
+            topics = self.topics.len(),
+            "Consuming the Kafka topics"
+        );
+
+        loop {
+            // Nothing is consumed in read-only mode, to be consumed once it is turned off
+            if state.read_only_mode.load(Ordering::Relaxed) {
+                thread::sleep(FETCH_MAX_WAIT);
+                continue;
+            }
+            let sets = consumer.poll().map_err(|e| e.to_string())?;
+            if sets.is_empty() {
+                continue;
+            }
+            for set in sets.iter() {
+                let messages = set.messages().iter().map(|message| message.value);
+                let inserted = self.insert(state, set.topic(), messages).map_err(|error| {
+                    format!(
+                        "Cannot insert the messages of topic '{}': {error}",
+                        set.topic()
+                    )
+                })?;
+                tracing::debug!(
+                    topic = set.topic(),
+                    partition = set.partition(),
+                    messages = set.messages().len(),
+                    inserted,
+                    "Inserted the Kafka messages"
+                );
+                consumer
+                    .consume_messageset(set)
+                    .map_err(|e| e.to_string())?;
+            }
+            consumer.commit_consumed().map_err(|e| e.to_string())?;
+        }
+    }
+
+    /// Inserts the values of a batch of messages of `topic` into the filter of `state` the topic
+    /// is mapped to, returning the number inserted; empty values, and items rejected by the
+    /// transform of the filter, are skipped.
+    ///
+    /// The offsets of the messages are committed only once this succeeds, so that the messages
+    /// of a failed batch, e.g. for a filter missing, are consumed again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterSpec, KafkaIngest, SharedState};
+    ///
+    /// let state = SharedState::default();
+    /// let spec = FilterSpec {
+    ///     name: "emails".to_string(),
+    ///     item_count: 1000,
+    ///     false_positive_rate: Some(0.01),
+    ///     ..Default::default()
+    /// };
+    /// state.create(spec).unwrap();
+    /// let ingest = KafkaIngest {
+    ///     brokers: vec!["localhost:9092".to_string()],
+    ///     group: "bloomsrv".to_string(),
+    ///     topics: vec!["signups=emails".parse().unwrap()],
+    /// };
+    ///
+    /// let messages: [&[u8]; 2] = [b"user@example.com", b""];
+    /// assert_eq!(ingest.insert(&state, "signups", messages).unwrap(), 1);
+    /// assert!(state.contains("emails", "user@example.com").unwrap());
+    /// ```
+    pub fn insert<'a>(
+        &self,
+        state: &SharedState,
+        topic: &str,
+        messages: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Result<usize, Error> {
+        let mapping = self
+            .topics
+            .iter()
+            .find(|mapping| mapping.topic == topic)
+            .ok_or_else(|| {
+                Error::InvalidParameters(format!("Topic '{topic}' is mapped to no filter"))
+            })?;
+        let store = match &mapping.namespace {
+            Some(namespace) => state.namespace(namespace)?,
+            None => state.clone(),
+        };
+        let items = messages.into_iter().filter(|value| !value.is_empty());
+        let inserted = store.insert_batch(&mapping.filter, items)?;
+        Ok(inserted.iter().flatten().count())
+    }
+}
@@ -0,0 +1,219 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+use crate::Error;
+
+/// Default number of counters per row of a Count-Min sketch.
+const DEFAULT_WIDTH: usize = 2048;
+/// Default number of rows (hash functions) of a Count-Min sketch.
+const DEFAULT_DEPTH: usize = 4;
+/// Default number of sub-windows the sliding window is divided into.
+const DEFAULT_SLOTS: usize = 10;
+
+/// Parameters of a rate limiter to be created.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::LimiterSpec;
+///
+/// // At most 100 requests per key and minute
+/// let spec = LimiterSpec {
+///     name: "api_calls".to_string(),
+///     limit: 100,
+///     window_seconds: 60,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Parameters of a rate limiter to be created.")
+)]
+pub struct LimiterSpec {
+    pub name: String,
+    /// Maximum count allowed per key within the window.
+    pub limit: u64,
+    pub window_seconds: u64,
+    /// Counters per row of each sketch (default 2048); wider sketches overcount less.
+    pub width: Option<usize>,
+    /// Rows of each sketch (default 4); deeper sketches overcount less often.
+    pub depth: Option<usize>,
+    /// Sub-windows the window is divided into (default 10); more slots slide more smoothly.
+    pub slots: Option<usize>,
+}
+
+/// Outcome of [`RateLimiter::check_and_increment`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Outcome of checking a key against a rate limiter.")
+)]
+pub struct LimiterDecision {
+    /// Whether the request is allowed (and has been counted).
+    pub allowed: bool,
+    /// The (approximate) count of the key within the window, including this request if allowed.
+    pub count: u64,
+    pub limit: u64,
+    /// The count still allowed within the window.
+    pub remaining: u64,
+}
+
+/// An approximate sliding-window rate limiter.
+///
+/// The window is divided into slots, each counting keys in a Count-Min sketch. The count of a key
+/// is the sum of its estimates over the slots within the window. Count-Min sketches never
+/// undercount, so the limiter never allows more than the limit, but it may deny requests early
+/// when many keys collide.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{LimiterSpec, RateLimiter};
+/// use std::time::Instant;
+///
+/// let limiter = RateLimiter::new(&LimiterSpec {
+///     name: "api_calls".to_string(),
+///     limit: 2,
+///     window_seconds: 60,
+///     ..Default::default()
+/// })
+/// .unwrap();
+///
+/// let now = Instant::now();
+/// assert!(limiter.check_and_increment(b"alice", 1, now).allowed);
+/// assert!(limiter.check_and_increment(b"alice", 1, now).allowed);
+/// assert!(!limiter.check_and_increment(b"alice", 1, now).allowed);
+/// ```
+pub struct RateLimiter {
+    pub name: String,
+    pub limit: u64,
+    pub window: Duration,
+    pub width: usize,
+    pub depth: usize,
+    pub slots: usize,
+    created_at: Instant,
+    windows: Mutex<Vec<Slot>>,
+}
+
+/// The counts of one sub-window.
+struct Slot {
+    /// Index of the sub-window (since the creation of the limiter) counted by this slot.
+    epoch: u64,
+    counters: Vec<u32>,
+}
+
+impl RateLimiter {
+    /// Validates a specification and creates the corresponding limiter.
+    pub fn new(spec: &LimiterSpec) -> Result<Self, Error> {
+        let invalid = |reason: &str| Err(Error::InvalidParameters(reason.to_string()));
+        let width = spec.width.unwrap_or(DEFAULT_WIDTH);
+        let depth = spec.depth.unwrap_or(DEFAULT_DEPTH);
+        let slots = spec.slots.unwrap_or(DEFAULT_SLOTS);
+
+        if spec.limit == 0 {
+            return invalid("limit must be greater than 0");
+        }
+        if spec.window_seconds == 0 {
+            return invalid("window_seconds must be greater than 0");
+        }
+        if !(1..=1 << 20).contains(&width) {
+            return invalid("width must be between 1 and 1048576");
+        }
+        if !(1..=16).contains(&depth) {
+            return invalid("depth must be between 1 and 16");
+        }
+        if !(1..=100).contains(&slots) {
+            return invalid("slots must be between 1 and 100");
+        }
+
+        Ok(RateLimiter {
+            name: spec.name.clone(),
+            limit: spec.limit,
+            window: Duration::from_secs(spec.window_seconds),
+            width,
+            depth,
+            slots,
+            created_at: Instant::now(),
+            windows: Mutex::new(
+                (0..slots)
+                    .map(|_| Slot {
+                        epoch: 0,
+                        counters: vec![0; width * depth],
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Returns the memory used by the counters, in bytes.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.slots * self.width * self.depth * std::mem::size_of::<u32>()
+    }
+
+    /// Counts `cost` for a key at `now`, unless this would exceed the limit within the window.
+    pub fn check_and_increment(&self, key: &[u8], cost: u64, now: Instant) -> LimiterDecision {
+        let slot_length = self.window.as_nanos() / self.slots as u128;
+        let current =
+            (now.saturating_duration_since(self.created_at).as_nanos() / slot_length.max(1)) as u64;
+        let cells = self.cells(key);
+
+        let mut windows = self.windows.lock();
+        let count: u64 = windows
+            .iter()
+            .filter(|slot| slot.epoch + self.slots as u64 > current)
+            .map(|slot| {
+                cells
+                    .iter()
+                    .map(|&cell| u64::from(slot.counters[cell]))
+                    .min()
+                    .unwrap_or(0)
+            })
+            .sum();
+
+        let allowed = count.saturating_add(cost) <= self.limit;
+        let count = if allowed {
+            let slot = &mut windows[(current % self.slots as u64) as usize];
+            if slot.epoch != current {
+                slot.epoch = current;
+                slot.counters.fill(0);
+            }
+            let cost = u32::try_from(cost).unwrap_or(u32::MAX);
+            for &cell in &cells {
+                slot.counters[cell] = slot.counters[cell].saturating_add(cost);
+            }
+            count + u64::from(cost)
+        } else {
+            count
+        };
+
+        LimiterDecision {
+            allowed,
+            count,
+            limit: self.limit,
+            remaining: self.limit.saturating_sub(count),
+        }
+    }
+
+    /// Returns the index of the counter of a key in each row.
+    fn cells(&self, key: &[u8]) -> Vec<usize> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        // Double hashing: the rows use h1 + i * h2 for independent-enough positions
+        let (h1, h2) = (hash as u32 as u64, (hash >> 32) | 1);
+        (0..self.depth)
+            .map(|row| {
+                let column = h1.wrapping_add((row as u64).wrapping_mul(h2)) % self.width as u64;
+                row * self.width + column as usize
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,204 @@
+use serde_json::{Map, Value};
+use std::{
+    fmt::{self, Write as _},
+    io::Write as _,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Metadata, Subscriber,
+};
+
+/// The most verbose level logged, as set by [`init_logging`] and [`set_log_level`].
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(level_index(Level::INFO));
+
+/// How log lines are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines, e.g. `2026-01-02T03:04:05.678Z  INFO request method=GET status=200`.
+    #[default]
+    Pretty,
+    /// One JSON object per line, with the fields of the event as keys.
+    Json,
+}
+
+/// Installs a subscriber writing the events of `level` and more severe to the standard output,
+/// in the given format, for the whole process.
+///
+/// Fails if a subscriber is installed already.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{init_logging, LogFormat};
+///
+/// init_logging(LogFormat::Json, tracing::Level::INFO).unwrap();
+/// tracing::info!(filters = 3, "Loaded the filters");
+/// ```
+pub fn init_logging(format: LogFormat, level: Level) -> Result<(), String> {
+    LOG_LEVEL.store(level_index(level), Ordering::Relaxed);
+    tracing::subscriber::set_global_default(LineSubscriber {
+        format,
+        next_span: AtomicU64::new(1),
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Changes the most verbose level logged by the subscriber of [`init_logging`], e.g. to debug a
+/// running service.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{init_logging, set_log_level, LogFormat};
+/// use tracing::Level;
+///
+/// init_logging(LogFormat::Pretty, Level::INFO).unwrap();
+/// set_log_level(Level::DEBUG);
+/// tracing::debug!("Logged from now on");
+/// ```
+pub fn set_log_level(level: Level) {
+    LOG_LEVEL.store(level_index(level), Ordering::Relaxed);
+    // Callsites cache whether they are enabled
+    tracing::callsite::rebuild_interest_cache();
+}
+
+/// Ranks a level by verbosity, from 0 for errors to 4 for traces.
+const fn level_index(level: Level) -> u8 {
+    match level {
+        Level::ERROR => 0,
+        Level::WARN => 1,
+        Level::INFO => 2,
+        Level::DEBUG => 3,
+        Level::TRACE => 4,
+    }
+}
+
+/// Writes each event as a line; spans are not recorded.
+struct LineSubscriber {
+    format: LogFormat,
+    next_span: AtomicU64,
+}
+
+impl Subscriber for LineSubscriber {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        level_index(*metadata.level()) <= LOG_LEVEL.load(Ordering::Relaxed)
+    }
+
+    fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(self.next_span.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let metadata = event.metadata();
+        let timestamp = timestamp(SystemTime::now());
+        let line = match self.format {
+            LogFormat::Json => {
+                let mut line = Map::new();
+                line.insert("timestamp".to_string(), timestamp.into());
+                line.insert("level".to_string(), metadata.level().as_str().into());
+                line.insert("target".to_string(), metadata.target().into());
+                line.insert("message".to_string(), fields.message.into());
+                line.extend(fields.values);
+                Value::Object(line).to_string()
+            }
+            LogFormat::Pretty => {
+                let mut line = format!("{timestamp} {:>5} {}", metadata.level(), fields.message);
+                for (key, value) in &fields.values {
+                    match value {
+                        Value::String(value) => write!(line, " {key}={value}"),
+                        value => write!(line, " {key}={value}"),
+                    }
+                    .unwrap();
+                }
+                line
+            }
+        };
+        // A log line that cannot be written is dropped rather than failing the request
+        let _ = writeln!(std::io::stdout().lock(), "{line}");
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+/// The message and other fields of an event.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    values: Map<String, Value>,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let value = format!("{value:?}");
+        if field.name() == "message" {
+            self.message = value;
+        } else {
+            self.values.insert(field.name().to_string(), value.into());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.values.insert(field.name().to_string(), value.into());
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.values.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.values.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.values.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.values.insert(field.name().to_string(), value.into());
+    }
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC, to the millisecond.
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date of a day count, after Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
@@ -1,16 +1,77 @@
-use clap::Parser;
-use std::net::{IpAddr, SocketAddr};
+use clap::{Args, Parser, Subcommand};
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{atomic::Ordering, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
 // Use the logic from lib.rs
 // Assuming your library crate is named "bloomsrv" in Cargo.toml
-use bloomsrv::{create_app, SharedState};
+use bloomsrv::{
+    client::BloomClient, create_app_with_shared_config, init_logging, serve_resp, set_log_level,
+    spawn_expiry_task, AlertThresholds, AppConfig, AuditLog, BloomFilter, Compression, CorsPolicy,
+    CreationMode, Error, EventSink, FilterEvent, FilterSnapshot, FilterSpec, FilterStore,
+    HashScheme, JwtValidator, KeyScope, LogFormat, MemoryBudget, NamePolicy, Quotas, Reloader,
+    RequestLimits, SaturationMonitor, ShardPeers, ShardRing, SharedConfig, SharedState, Throttle,
+};
 
 const DEFAULT_HOST: &str = "127.0.0.1";
 const DEFAULT_PORT: u16 = 3000;
+const DEFAULT_EXPIRY_INTERVAL: u64 = 1;
+const DEFAULT_SNAPSHOT_INTERVAL: u64 = 60;
+const DEFAULT_WAL_SEGMENT_MB: u64 = 64;
+const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
+const DEFAULT_URL: &str = "http://127.0.0.1:3000";
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(600);
+const DEFAULT_ALERT_INTERVAL: u64 = 60;
+const DEFAULT_ALERT_COOLDOWN: u64 = 3600;
+#[cfg(feature = "kafka")]
+const DEFAULT_KAFKA_GROUP: &str = "bloomsrv";
+const DEFAULT_PUBLISH_CHANNEL: &str = "bloomsrv.events";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Simple Bloom Filter Daemon
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(flatten)]
+    serve: ServeArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the service (the default, when no subcommand is given)
+    Serve(Box<ServeArgs>),
+    /// Create a filter in a running service
+    Create(CreateArgs),
+    /// Insert items into a filter of a running service
+    Insert(ItemArgs),
+    /// Test for items in a filter of a running service, exiting with 1 unless all may have been
+    /// inserted
+    Check(ItemArgs),
+    /// List the filters of a running service
+    List(ListArgs),
+    /// Reconcile a running service towards the filters and groups described in a manifest
+    Apply(ApplyArgs),
+    /// Build a filter file from a dataset of items locally, without going through the service
+    Build(BuildArgs),
+    /// Compare two filter files, e.g. backups, reporting the bits changed and items added
+    Diff(DiffArgs),
+}
+
+/// Options of the service itself (used when no subcommand, or `serve`, is given)
+#[derive(Args, Debug)]
+struct ServeArgs {
     /// Host to listen on
     #[arg(long, env = "BLOOMSRV_HOST", default_value = DEFAULT_HOST)]
     host: IpAddr,
@@ -18,21 +79,1743 @@ struct Args {
     /// Port to listen on
     #[arg(short, long, env = "BLOOMSRV_PORT", default_value_t = DEFAULT_PORT)]
     port: u16,
+
+    /// Port to also serve the Redis protocol on, with the commands of RedisBloom (`BF.ADD`,
+    /// `BF.EXISTS`, ...), for the applications using a Redis Bloom client
+    #[arg(long, env = "BLOOMSRV_RESP_PORT")]
+    resp_port: Option<u16>,
+
+    /// URL of a primary to follow as a read-only replica, e.g. `http://primary:3000`: the filters
+    /// of its default namespace are synced, and then changed as it changes them
+    #[arg(long, env = "BLOOMSRV_REPLICA_OF")]
+    replica_of: Option<String>,
+
+    /// Admin key (or API key of the admin scope) of the primary given by `--replica-of`
+    #[arg(long, env = "BLOOMSRV_REPLICA_KEY", requires = "replica_of")]
+    replica_key: Option<String>,
+
+    /// URL the other nodes of a shard ring reach this node at, e.g. `http://node-1:3000`: the
+    /// node owns only the filters the ring assigns to it, and proxies the requests for the others
+    #[arg(long, env = "BLOOMSRV_SHARD_ADDRESS", conflicts_with = "replica_of")]
+    shard_address: Option<String>,
+
+    /// URL of another node of the shard ring (repeatable, or comma-separated); every node must be
+    /// given the same nodes, unless they are discovered; replaced by the `shard_nodes` of the
+    /// configuration file, if any
+    #[arg(
+        long = "shard-node",
+        env = "BLOOMSRV_SHARD_NODES",
+        value_delimiter = ',',
+        requires = "shard_address"
+    )]
+    shard_nodes: Vec<String>,
+
+    /// Name of a filter partitioned over every node of the shard ring by the hashes of its items
+    /// (repeatable, or comma-separated), rather than owned by one; every node must be given the
+    /// same names
+    #[arg(
+        long = "shard-partition",
+        env = "BLOOMSRV_SHARD_PARTITIONS",
+        value_delimiter = ',',
+        requires = "shard_address"
+    )]
+    shard_partitions: Vec<String>,
+
+    /// Host name and port resolving to the addresses of the nodes of the shard ring, e.g. the
+    /// `bloomsrv.default.svc.cluster.local:3000` of a Kubernetes headless service; the nodes
+    /// discovered join those given
+    #[arg(long, env = "BLOOMSRV_SHARD_DISCOVER", requires = "shard_address")]
+    shard_discover: Option<String>,
+
+    /// Seconds between the resolutions of the name the nodes of the shard ring are discovered by
+    #[arg(
+        long,
+        env = "BLOOMSRV_SHARD_DISCOVER_INTERVAL",
+        default_value_t = 30,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    shard_discover_interval: u64,
+
+    /// Id of this node in a cluster, unique in it: the changes requested of any node are
+    /// committed through Raft consensus, and applied by every node
+    #[cfg(feature = "cluster")]
+    #[arg(
+        long,
+        env = "BLOOMSRV_CLUSTER_NODE_ID",
+        requires_all = ["cluster_address", "cluster_dir"],
+        conflicts_with_all = ["replica_of", "data_dir", "shard_address"]
+    )]
+    cluster_node_id: Option<u64>,
+
+    /// URL the other nodes of the cluster reach this node at, e.g. `http://node-1:3000`
+    #[cfg(feature = "cluster")]
+    #[arg(long, env = "BLOOMSRV_CLUSTER_ADDRESS", requires = "cluster_node_id")]
+    cluster_address: Option<String>,
+
+    /// Directory the log of the consensus, and the snapshots of the filters of the node, are kept
+    /// in, for the node to rejoin the cluster after a restart
+    #[cfg(feature = "cluster")]
+    #[arg(long, env = "BLOOMSRV_CLUSTER_DIR", requires = "cluster_node_id")]
+    cluster_dir: Option<PathBuf>,
+
+    /// Initialize a cluster of this node alone on startup, unless it is a member of one already;
+    /// the other nodes are then added at `POST /admin/cluster/nodes`
+    #[cfg(feature = "cluster")]
+    #[arg(long, env = "BLOOMSRV_CLUSTER_INIT", requires = "cluster_node_id")]
+    cluster_init: bool,
+
+    /// Configuration file (TOML) of the settings that can change while the service runs, read
+    /// again on SIGHUP or `POST /admin/reload`; its settings take precedence over the options
+    #[arg(short, long, env = "BLOOMSRV_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Interval in seconds between checks for expired filters
+    #[arg(
+        long,
+        env = "BLOOMSRV_EXPIRY_INTERVAL",
+        default_value_t = DEFAULT_EXPIRY_INTERVAL,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    expiry_interval: u64,
+
+    /// Directory the filters are saved to periodically, and loaded from on startup; also holds
+    /// the files of memory-mapped filters
+    #[arg(long, env = "BLOOMSRV_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Interval in seconds between saves of the filters to the data directory
+    #[arg(
+        long,
+        env = "BLOOMSRV_SNAPSHOT_INTERVAL",
+        default_value_t = DEFAULT_SNAPSHOT_INTERVAL,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    snapshot_interval: u64,
+
+    /// Also record every change to the filters in a write-ahead log in the data directory,
+    /// so that changes since the last snapshot survive a crash
+    #[arg(long, env = "BLOOMSRV_WAL", requires = "data_dir")]
+    wal: bool,
+
+    /// Size in MiB after which the write-ahead log starts a new segment
+    #[arg(
+        long,
+        env = "BLOOMSRV_WAL_SEGMENT_MB",
+        default_value_t = DEFAULT_WAL_SEGMENT_MB,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    wal_segment_mb: u64,
+
+    /// Memory budget of the filters in MiB; beyond it, the least recently used filters are evicted
+    #[arg(
+        long,
+        env = "BLOOMSRV_MAX_MEMORY",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_memory: Option<u64>,
+
+    /// Save evicted filters to the `evicted` directory of the data directory before removing them
+    #[arg(
+        long,
+        env = "BLOOMSRV_PERSIST_EVICTED",
+        requires_all = ["data_dir", "max_memory"]
+    )]
+    persist_evicted: bool,
+
+    /// Maximum number of filters clients may create, in the store and in each namespace
+    #[arg(long, env = "BLOOMSRV_QUOTA_FILTERS")]
+    quota_filters: Option<usize>,
+
+    /// Maximum number of items a filter may be sized for
+    #[arg(
+        long,
+        env = "BLOOMSRV_QUOTA_ITEM_COUNT",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    quota_item_count: Option<u64>,
+
+    /// Maximum memory of a filter in MiB, beyond which it cannot be created, nor scale
+    #[arg(
+        long,
+        env = "BLOOMSRV_QUOTA_FILTER_MEMORY",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    quota_filter_memory: Option<u64>,
+
+    /// Maximum memory of all the filters in MiB, in the store and in each namespace, beyond which
+    /// no filter can be created, nor scale
+    #[arg(
+        long,
+        env = "BLOOMSRV_QUOTA_MEMORY",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    quota_memory: Option<u64>,
+
+    /// Time in seconds given to the requests in flight to complete on shutdown, before their
+    /// connections are dropped and the filters saved
+    #[arg(
+        long,
+        env = "BLOOMSRV_SHUTDOWN_TIMEOUT",
+        default_value_t = DEFAULT_SHUTDOWN_TIMEOUT
+    )]
+    shutdown_timeout: u64,
+
+    /// Start in read-only mode, rejecting changes with 403 Forbidden while serving lookups and
+    /// lists, e.g. to serve restored filters that must not drift; turned off at
+    /// `POST /admin/readonly`
+    #[arg(long, env = "BLOOMSRV_READ_ONLY")]
+    read_only: bool,
+
+    /// Expose the development endpoints (e.g. synthetic filters for load tests)
+    #[arg(long, env = "BLOOMSRV_DEV_ENDPOINTS")]
+    dev_endpoints: bool,
+
+    /// Allow `?debug=1` on inserts and lookups, returning the hashes and bit positions of items
+    #[arg(long, env = "BLOOMSRV_DEBUG_TRACES")]
+    debug_traces: bool,
+
+    /// Require an API key on every request but the health probes; this key is the admin key,
+    /// which mints and revokes the others
+    #[arg(long, env = "BLOOMSRV_ADMIN_KEY", hide_env_values = true)]
+    admin_key: Option<String>,
+
+    /// Also accept the JSON Web Tokens of this OpenID Connect issuer as bearer tokens
+    #[arg(long, env = "BLOOMSRV_JWT_ISSUER", requires = "jwt_audience")]
+    jwt_issuer: Option<String>,
+
+    /// Audience the tokens must be meant for, i.e. their `aud` claim
+    #[arg(long, env = "BLOOMSRV_JWT_AUDIENCE", requires = "jwt_issuer")]
+    jwt_audience: Option<String>,
+
+    /// URL of the signing keys of the issuer (by default, the `jwks_uri` of its discovery
+    /// document, at `<issuer>/.well-known/openid-configuration`)
+    #[arg(long, env = "BLOOMSRV_JWKS_URL", requires = "jwt_issuer")]
+    jwks_url: Option<String>,
+
+    /// File holding the signing keys of the issuer, instead of fetching them
+    #[arg(
+        long,
+        env = "BLOOMSRV_JWKS_FILE",
+        requires = "jwt_issuer",
+        conflicts_with = "jwks_url"
+    )]
+    jwks_file: Option<PathBuf>,
+
+    /// Claim holding the roles of a token
+    #[arg(long, env = "BLOOMSRV_JWT_ROLE_CLAIM", default_value = "roles")]
+    jwt_role_claim: String,
+
+    /// Scope of a role of the tokens, as `<role>=<read|read_write|admin>` (repeatable, or
+    /// comma-separated); roles named after a scope have that scope
+    #[arg(
+        long = "jwt-role",
+        env = "BLOOMSRV_JWT_ROLES",
+        value_delimiter = ',',
+        value_parser = parse_role
+    )]
+    jwt_roles: Vec<(String, KeyScope)>,
+
+    /// Requests per second allowed to each client, told apart by its API key or token, or else
+    /// by its IP address; requests beyond fail with 429 Too Many Requests
+    #[arg(long, env = "BLOOMSRV_RATE_LIMIT", value_parser = parse_rate)]
+    rate_limit: Option<f64>,
+
+    /// Requests a client may make at once, beyond the rate limit (by default, the rate limit
+    /// rounded up)
+    #[arg(
+        long,
+        env = "BLOOMSRV_RATE_LIMIT_BURST",
+        requires = "rate_limit",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    rate_limit_burst: Option<u32>,
+
+    /// Maximum size in KiB of the body of most requests, e.g. an item inserted or looked up;
+    /// larger bodies fail with 413 Payload Too Large
+    #[arg(
+        long,
+        env = "BLOOMSRV_MAX_BODY_KIB",
+        default_value_t = RequestLimits::default().max_body_bytes >> 10,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_body_kib: usize,
+
+    /// Maximum size in MiB of the body of batches of items and uploads of filters
+    #[arg(
+        long,
+        env = "BLOOMSRV_MAX_UPLOAD_MIB",
+        default_value_t = RequestLimits::default().max_upload_bytes >> 20,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_upload_mib: usize,
+
+    /// Time in seconds given to most requests to complete, including receiving their body;
+    /// slower requests fail with 408 Request Timeout
+    #[arg(
+        long,
+        env = "BLOOMSRV_REQUEST_TIMEOUT",
+        default_value_t = RequestLimits::default().timeout.as_secs(),
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    request_timeout: u64,
+
+    /// Time in seconds given to batches of items, streams and uploads of filters to complete
+    #[arg(
+        long,
+        env = "BLOOMSRV_UPLOAD_TIMEOUT",
+        default_value_t = RequestLimits::default().upload_timeout.as_secs(),
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    upload_timeout: u64,
+
+    /// Algorithm compressing the responses to the clients accepting it, and decompressing the
+    /// requests compressed with it: gzip or zstd (repeatable, or comma-separated)
+    #[arg(long, env = "BLOOMSRV_COMPRESSION", value_enum, value_delimiter = ',')]
+    compression: Vec<Compression>,
+
+    /// Origin whose web pages may call the service from a browser, e.g.
+    /// https://dashboard.example.com, or * for any (repeatable, or comma-separated)
+    #[arg(
+        long = "cors-origin",
+        env = "BLOOMSRV_CORS_ORIGINS",
+        value_delimiter = ','
+    )]
+    cors_origins: Vec<String>,
+
+    /// Method allowed from the origins of --cors-origin (repeatable, or comma-separated; by
+    /// default GET, HEAD, POST, PUT, PATCH and DELETE)
+    #[arg(
+        long = "cors-method",
+        env = "BLOOMSRV_CORS_METHODS",
+        value_delimiter = ',',
+        requires = "cors_origins"
+    )]
+    cors_methods: Vec<String>,
+
+    /// Header allowed from the origins of --cors-origin (repeatable, or comma-separated; by
+    /// default Authorization, Content-Type, X-Api-Key and X-Request-Id)
+    #[arg(
+        long = "cors-header",
+        env = "BLOOMSRV_CORS_HEADERS",
+        value_delimiter = ',',
+        requires = "cors_origins"
+    )]
+    cors_headers: Vec<String>,
+
+    /// Prefix of the sources filters may be loaded from by POST /filters/:name/load, e.g.
+    /// file:///var/lib/keys/ or https://downloads.example.com/ (repeatable, or comma-separated;
+    /// none by default)
+    #[arg(
+        long = "load-source",
+        env = "BLOOMSRV_LOAD_SOURCES",
+        value_delimiter = ','
+    )]
+    load_sources: Vec<String>,
+
+    /// Report the filters with more than this share of their bits set, between 0 and 1, in a
+    /// warning and to the --alert-webhook
+    #[arg(long, env = "BLOOMSRV_ALERT_FILL_RATIO", value_parser = parse_ratio)]
+    alert_fill_ratio: Option<f64>,
+
+    /// Report the filters whose false positive rate, as estimated from the bits set, exceeds
+    /// this, between 0 and 1
+    #[arg(long, env = "BLOOMSRV_ALERT_FALSE_POSITIVE_RATE", value_parser = parse_ratio)]
+    alert_false_positive_rate: Option<f64>,
+
+    /// URL the saturation alerts are posted to, as JSON
+    #[arg(long, env = "BLOOMSRV_ALERT_WEBHOOK")]
+    alert_webhook: Option<String>,
+
+    /// Interval in seconds between checks of the saturation of the filters
+    #[arg(
+        long,
+        env = "BLOOMSRV_ALERT_INTERVAL",
+        default_value_t = DEFAULT_ALERT_INTERVAL,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    alert_interval: u64,
+
+    /// Time in seconds before a filter still saturated is reported again
+    #[arg(
+        long,
+        env = "BLOOMSRV_ALERT_COOLDOWN",
+        default_value_t = DEFAULT_ALERT_COOLDOWN
+    )]
+    alert_cooldown: u64,
+
+    /// Kafka brokers to consume the topics given by `--kafka-topic` from, as `<host>:<port>`
+    #[cfg(feature = "kafka")]
+    #[arg(long, env = "BLOOMSRV_KAFKA_BROKERS", value_delimiter = ',')]
+    kafka_brokers: Vec<String>,
+
+    /// Kafka topic to insert the messages of into a filter, as `<topic>=[<namespace>/]<filter>`;
+    /// may be repeated
+    #[cfg(feature = "kafka")]
+    #[arg(
+        long,
+        env = "BLOOMSRV_KAFKA_TOPIC",
+        value_delimiter = ',',
+        requires = "kafka_brokers",
+        conflicts_with = "replica_of"
+    )]
+    kafka_topic: Vec<bloomsrv::TopicMapping>,
+
+    /// Kafka consumer group, under which the offsets consumed are committed
+    #[cfg(feature = "kafka")]
+    #[arg(long, env = "BLOOMSRV_KAFKA_GROUP", default_value = DEFAULT_KAFKA_GROUP)]
+    kafka_group: String,
+
+    /// Broker the events of the filters are published to, as JSON, for replicas and caches to
+    /// mirror the changes: `nats://[<token>@]<host>[:<port>]` or
+    /// `redis://[:<password>@]<host>[:<port>]`
+    #[arg(long, env = "BLOOMSRV_PUBLISH_URL")]
+    publish_url: Option<String>,
+
+    /// NATS subject or Redis channel the events are published to
+    #[arg(long, env = "BLOOMSRV_PUBLISH_CHANNEL", default_value = DEFAULT_PUBLISH_CHANNEL)]
+    publish_channel: String,
+
+    /// Share of the inserts published as events too, between 0 and 1; none by default
+    #[arg(long, env = "BLOOMSRV_PUBLISH_INSERTS", value_parser = parse_ratio)]
+    publish_inserts: Option<f64>,
+
+    /// File the audit log of the changes requested of the service is appended to, as JSON lines;
+    /// the most recent changes are served at `/admin/audit` either way
+    #[arg(long, env = "BLOOMSRV_AUDIT_LOG")]
+    audit_log: Option<PathBuf>,
+
+    /// Maximum length of the names of filters, groups, limiters and recent filters
+    #[arg(
+        long,
+        env = "BLOOMSRV_MAX_NAME_LENGTH",
+        default_value_t = NamePolicy::default().max_length,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_name_length: usize,
+
+    /// Characters allowed in names besides ASCII letters and digits
+    #[arg(long, env = "BLOOMSRV_NAME_PUNCTUATION", default_value_t = NamePolicy::default().punctuation)]
+    name_punctuation: String,
+
+    /// Name prefix reserved for internal use (repeatable, or comma-separated)
+    #[arg(
+        long = "reserved-name-prefix",
+        env = "BLOOMSRV_RESERVED_NAME_PREFIXES",
+        value_delimiter = ','
+    )]
+    reserved_name_prefixes: Vec<String>,
+
+    /// Format of the log lines written to the standard output
+    #[arg(long, env = "BLOOMSRV_LOG_FORMAT", value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// Least severe level logged: error, warn, info, debug or trace
+    #[arg(long, env = "BLOOMSRV_LOG_LEVEL", default_value_t = tracing::Level::INFO)]
+    log_level: tracing::Level,
+}
+
+/// Options of the subcommands calling a running service
+#[derive(Args, Debug)]
+struct ServiceArgs {
+    /// URL of the running service
+    #[arg(long, env = "BLOOMSRV_URL", default_value = DEFAULT_URL)]
+    url: String,
+
+    /// API key of the service, if it requires one
+    #[arg(long, env = "BLOOMSRV_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+}
+
+impl ServiceArgs {
+    fn client(&self) -> BloomClient {
+        let client = BloomClient::new(&self.url);
+        match &self.api_key {
+            Some(key) => client.with_key(key),
+            None => client,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct CreateArgs {
+    /// Name of the filter
+    name: String,
+
+    /// Expected number of items
+    #[arg(long)]
+    item_count: usize,
+
+    /// Target false positive rate
+    #[arg(long = "fp", required_unless_present = "hash_count")]
+    false_positive_rate: Option<f64>,
+
+    /// Fixed number of hash functions, instead of a false positive rate
+    #[arg(long, conflicts_with = "false_positive_rate")]
+    hash_count: Option<u32>,
+
+    /// Time in seconds after which the filter expires
+    #[arg(long)]
+    ttl_seconds: Option<u64>,
+
+    /// How the positions of items are derived from their bytes
+    #[arg(long, value_enum, default_value_t = HashScheme::Native)]
+    scheme: HashScheme,
+
+    /// Seed of the hashes of the scheme
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Label of the filter, as `<key>=<value>` (repeatable)
+    #[arg(long = "label", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
+
+    #[command(flatten)]
+    service: ServiceArgs,
+}
+
+#[derive(Args, Debug)]
+struct ItemArgs {
+    /// Name of the filter
+    filter: String,
+
+    /// Items (by default, one per line of the standard input, empty lines skipped)
+    items: Vec<String>,
+
+    #[command(flatten)]
+    service: ServiceArgs,
+}
+
+#[derive(Args, Debug)]
+struct ListArgs {
+    #[command(flatten)]
+    service: ServiceArgs,
+}
+
+#[derive(Args, Debug)]
+struct ApplyArgs {
+    /// Manifest file (YAML or JSON) describing the desired filters and groups
+    #[arg(short = 'f', long = "file")]
+    file: PathBuf,
+
+    /// URL of the running service
+    #[arg(long, env = "BLOOMSRV_URL", default_value = DEFAULT_URL)]
+    url: String,
+
+    /// API key of the service, if it requires one; applying takes an admin key
+    #[arg(long, env = "BLOOMSRV_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// Delete filters and groups that are not in the manifest
+    #[arg(long)]
+    prune: bool,
+
+    /// Report the changes without applying them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+struct BuildArgs {
+    /// Dataset with one item per line (empty lines are skipped)
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Filter file to write
+    #[arg(short, long)]
+    out: PathBuf,
+
+    /// Target false positive rate
+    #[arg(long = "fp", required_unless_present = "hash_count")]
+    false_positive_rate: Option<f64>,
+
+    /// Fixed number of hash functions, instead of a false positive rate
+    #[arg(long, conflicts_with = "false_positive_rate")]
+    hash_count: Option<u32>,
+
+    /// Expected number of items (by default, the number of items in the dataset)
+    #[arg(long)]
+    item_count: Option<usize>,
+
+    /// How the positions of items are derived from their bytes
+    #[arg(long, value_enum, default_value_t = HashScheme::Native)]
+    scheme: HashScheme,
+
+    /// Seed of the hashes of the scheme
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Number of threads reading the dataset (by default, one per CPU)
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    threads: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// The earlier filter file
+    before: PathBuf,
+
+    /// The later filter file
+    after: PathBuf,
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     // Parse command line arguments (and environment variables)
-    let args = Args::parse();
+    let cli = Cli::parse();
+
+    match cli.command {
+        None => exit_code(serve(cli.serve).await),
+        Some(Command::Serve(args)) => exit_code(serve(*args).await),
+        Some(Command::Create(args)) => exit_code(create(args).await),
+        Some(Command::Insert(args)) => exit_code(insert(args).await),
+        Some(Command::Check(args)) => match check(args).await {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::from(1),
+            // Told apart from items not found, for scripts
+            Err(error) => {
+                eprintln!("Error: {error}");
+                ExitCode::from(2)
+            }
+        },
+        Some(Command::List(args)) => exit_code(list(args).await),
+        Some(Command::Apply(args)) => exit_code(apply(args).await),
+        Some(Command::Build(args)) => exit_code(build(args)),
+        Some(Command::Diff(args)) => exit_code(diff(args)),
+    }
+}
 
-    let state = SharedState::default();
+fn exit_code(result: Result<(), String>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn serve(args: ServeArgs) -> Result<(), String> {
+    let file = match &args.config {
+        Some(path) => ConfigFile::read(path)?,
+        None => ConfigFile::default(),
+    };
+    init_logging(args.log_format, file.log_level.unwrap_or(args.log_level))?;
+    // From the start, as restoring the saved filters may take longer than the watchdog timeout
+    spawn_watchdog_task();
+    let jwt = jwt_validator(&args).await?;
+    let audit = match &args.audit_log {
+        Some(path) => AuditLog::open(path)
+            .map_err(|e| format!("Cannot open the audit log '{}': {e}", path.display()))?,
+        None => AuditLog::default(),
+    };
+    let cors = cors_policy(&args);
+    let mut store = FilterStore::with_name_policy(NamePolicy {
+        max_length: args.max_name_length,
+        punctuation: args.name_punctuation,
+        reserved_prefixes: args.reserved_name_prefixes,
+    });
+    store.data_dir = args.data_dir.clone();
+    store.memory_budget = args.max_memory.map(|mb| MemoryBudget {
+        max_bytes: usize::try_from(mb << 20).unwrap_or(usize::MAX),
+        persist_dir: args
+            .data_dir
+            .as_ref()
+            .filter(|_| args.persist_evicted)
+            .map(|dir| dir.join("evicted")),
+    });
+    let quotas = QuotaSettings {
+        filters: args.quota_filters,
+        item_count: args.quota_item_count,
+        filter_memory: args.quota_filter_memory,
+        memory: args.quota_memory,
+    };
+    let (store_quotas, namespace_quotas) = file.quotas(&quotas);
+    store.set_quotas(store_quotas, namespace_quotas);
+    let state = SharedState::new(store);
+    if file.shard_nodes.is_some() && args.shard_address.is_none() {
+        return Err("The nodes of the shard ring need the address of this node".to_string());
+    }
+
+    // Join the cluster before serving, so that no change escapes its consensus
+    #[cfg(feature = "cluster")]
+    let cluster = match args.cluster_node_id {
+        #[cfg(feature = "kafka")]
+        Some(_) if !args.kafka_topic.is_empty() => {
+            return Err("Kafka topics cannot be consumed by the nodes of a cluster".to_string());
+        }
+        Some(id) => {
+            let member = bloomsrv::ClusterMember {
+                id,
+                address: args.cluster_address.clone().unwrap_or_default(),
+            };
+            let dir = args.cluster_dir.clone().unwrap_or_default();
+            // The nodes call each other with the admin key, as the other requests need
+            let key = file.admin_key.clone().or_else(|| args.admin_key.clone());
+            let cluster = bloomsrv::Cluster::start(state.clone(), member, &dir, key)
+                .await
+                .map_err(|e| e.to_string())?;
+            Some(cluster)
+        }
+        None => None,
+    };
 
     // We use the public function from lib.rs
-    let app = create_app(state);
+    let options = AppConfig {
+        dev_endpoints: args.dev_endpoints,
+        debug_traces: args.debug_traces,
+        admin_key: args.admin_key.clone(),
+        jwt,
+        audit: Some(Arc::new(audit)),
+        throttle: args.rate_limit.map(|rate| {
+            let burst = args.rate_limit_burst.unwrap_or(rate.ceil() as u32);
+            Arc::new(Throttle::new(rate, burst))
+        }),
+        limits: RequestLimits {
+            max_body_bytes: args.max_body_kib.saturating_mul(1 << 10),
+            max_upload_bytes: args.max_upload_mib.saturating_mul(1 << 20),
+            timeout: Duration::from_secs(args.request_timeout),
+            upload_timeout: Duration::from_secs(args.upload_timeout),
+        },
+        compression: args.compression.clone(),
+        cors,
+        reload: None,
+        #[cfg(feature = "cluster")]
+        cluster: cluster.clone(),
+        shards: None,
+        load_sources: args.load_sources.clone(),
+    };
+    let config = SharedConfig::new(configure(&options, &file, None));
+    // The ring is replaced as the nodes given by the configuration file or discovered change
+    let peers = match &args.shard_address {
+        Some(address) => {
+            let seeds = file.shard_nodes.as_ref().unwrap_or(&args.shard_nodes);
+            let peers = ShardPeers::new(config.clone(), address, seeds, &args.shard_partitions)
+                .map_err(|e| e.to_string())?;
+            Some(Arc::new(peers))
+        }
+        None => None,
+    };
+    if let (Some(peers), Some(name)) = (&peers, &args.shard_discover) {
+        // Before serving, so that the filters are created on the nodes owning them
+        if let Err(error) = peers.discover(name).await {
+            tracing::warn!(name, %error, "Cannot discover the nodes of the shard ring");
+        }
+        peers.spawn_discovery(
+            name.clone(),
+            Duration::from_secs(args.shard_discover_interval),
+        );
+    }
+    if let Some(peers) = &peers {
+        // Once discovered, before the partitions of the configuration file are created
+        peers.protect(state.clone());
+    }
+    let thresholds = AlertThresholds {
+        fill_ratio: args.alert_fill_ratio,
+        false_positive_rate: args.alert_false_positive_rate,
+    };
+    let monitor = Arc::new(SaturationMonitor::new(
+        file.alert_thresholds(thresholds),
+        Duration::from_secs(args.alert_cooldown),
+    ));
+    if let Some(path) = args.config.clone() {
+        let target = Reloadable {
+            options,
+            quotas,
+            log_level: args.log_level,
+            thresholds,
+            config: config.clone(),
+            state: state.clone(),
+            monitor: monitor.clone(),
+            peers: peers.clone(),
+            shard_nodes: args.shard_nodes.clone(),
+        };
+        let reloader = config_reloader(path, target);
+        let mut reloadable = config.get();
+        reloadable.reload = Some(reloader.clone());
+        config.replace(reloadable);
+        spawn_reload_task(reloader)?;
+    }
+    let app = create_app_with_shared_config(state.clone(), config.clone());
+
+    // Socket activation: the socket named "resp" serves the Redis protocol, the first other one
+    // the HTTP API
+    let mut activated = activated_listeners()?;
+    let activated_resp = activated
+        .iter()
+        .position(|(name, _)| name == "resp")
+        .map(|index| activated.remove(index).1);
+    let listener = match activated.into_iter().next() {
+        Some((name, listener)) => {
+            let listener = tokio::net::TcpListener::from_std(listener)
+                .map_err(|e| format!("Cannot listen on the socket '{name}': {e}"))?;
+            let addr = listener.local_addr().map_err(|e| e.to_string())?;
+            tracing::info!(address = %addr, "Bloom Daemon listening on the socket passed by systemd");
+            listener
+        }
+        None => {
+            let addr = SocketAddr::from((args.host, args.port));
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .map_err(|e| format!("Cannot listen on {addr}: {e}"))?;
+            tracing::info!(address = %addr, "Bloom Daemon listening");
+            listener
+        }
+    };
+    let sink = args
+        .publish_url
+        .as_deref()
+        .map(|url| EventSink::parse(url, &args.publish_channel))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let resp_listener = match (activated_resp, args.resp_port) {
+        (Some(listener), _) => {
+            let listener = tokio::net::TcpListener::from_std(listener)
+                .map_err(|e| format!("Cannot listen on the socket 'resp': {e}"))?;
+            tracing::info!("Serving the Redis protocol on the socket passed by systemd");
+            Some(listener)
+        }
+        (None, Some(port)) => {
+            let addr = SocketAddr::from((args.host, port));
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .map_err(|e| format!("Cannot listen on {addr}: {e}"))?;
+            tracing::info!(address = %addr, "Serving the Redis protocol");
+            Some(listener)
+        }
+        (None, None) => None,
+    };
+
+    // Answer the health probes while the saved filters are restored, rejecting other requests
+    state
+        .restoring
+        .store(args.data_dir.is_some(), Ordering::Relaxed);
+    // Reject the changes requested of a replica from the start
+    state
+        .read_only
+        .store(args.replica_of.is_some(), Ordering::Relaxed);
+    state
+        .read_only_mode
+        .store(args.read_only, Ordering::Relaxed);
+    let shutdown = Arc::new(Notify::new());
+    let stopping = shutdown.clone();
+    let mut server = tokio::spawn(async move {
+        // With the addresses of the clients, for throttling them
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(async move { stopping.notified().await })
+        .await
+    });
+    if let Some(listener) = resp_listener {
+        let (state, config) = (state.clone(), config.clone());
+        tokio::spawn(async move {
+            if let Err(error) = serve_resp(listener, state, config).await {
+                tracing::error!(%error, "Stopped serving the Redis protocol");
+            }
+        });
+    }
+
+    #[cfg(feature = "cluster")]
+    if let Some(cluster) = cluster.as_ref().filter(|_| args.cluster_init) {
+        match cluster.initialize().await {
+            Ok(()) => tracing::info!("Initialized a cluster of this node"),
+            Err(Error::Forbidden(_)) => tracing::info!("Rejoined the cluster"),
+            Err(error) => return Err(format!("Cannot initialize the cluster: {error}")),
+        }
+    }
+
+    // Reload the filters saved by a previous run (replaying its log), and save them periodically
+    // from now on
+    if let Some(dir) = &args.data_dir {
+        let (restored, path) = (state.clone(), dir.clone());
+        let loaded = tokio::task::spawn_blocking(move || restored.load(&path))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("Cannot load filters from '{}': {e}", dir.display()))?;
+        tracing::info!(filters = loaded, dir = %dir.display(), "Loaded the saved filters");
+        if args.wal {
+            state
+                .start_log(dir, args.wal_segment_mb << 20)
+                .map_err(|e| format!("Cannot start the write-ahead log: {e}"))?;
+        }
+        spawn_snapshot_task(
+            state.clone(),
+            dir.clone(),
+            Duration::from_secs(args.snapshot_interval),
+        );
+    }
+    // Before serving, so that clients never miss the filters of the configuration file
+    if !args.read_only {
+        create_filters(&state, &file.filters, config.get().shards.as_deref())
+            .map_err(|e| format!("Cannot create the filters of the configuration file: {e}"))?;
+    }
+    state.restoring.store(false, Ordering::Relaxed);
+    notify_systemd("READY=1");
+
+    // Insert the messages of the Kafka topics into their filters, in a thread of its own as the
+    // consumer blocks
+    #[cfg(feature = "kafka")]
+    if !args.kafka_topic.is_empty() {
+        let ingest = bloomsrv::KafkaIngest {
+            brokers: args.kafka_brokers.clone(),
+            group: args.kafka_group.clone(),
+            topics: args.kafka_topic.clone(),
+        };
+        let state = state.clone();
+        thread::spawn(move || ingest.run(&state));
+    }
+
+    // Follow the primary, replacing the filters restored with its own, until promoted
+    if let Some(primary) = &args.replica_of {
+        let replication = bloomsrv::Replication {
+            primary: primary.clone(),
+            key: args.replica_key.clone(),
+        };
+        let state = state.clone();
+        tokio::spawn(async move { replication.follow(state).await });
+    }
+
+    // Publish the changes of the filters from now on, reconnecting to the broker as needed
+    if let Some(sink) = sink {
+        if let Some(ratio) = args.publish_inserts {
+            state.sample_insert_events(ratio);
+        }
+        let state = state.clone();
+        tokio::spawn(async move { sink.publish(state).await });
+    }
+
+    // Remove (or clear) filters whose time-to-live has elapsed
+    spawn_expiry_task(state.clone(), Duration::from_secs(args.expiry_interval));
+    #[cfg(feature = "cluster")]
+    if let Some(cluster) = &cluster {
+        cluster.spawn_expiry_task(state.clone(), Duration::from_secs(args.expiry_interval));
+    }
+
+    // Report the filters saturated beyond the thresholds, if any
+    spawn_alert_task(
+        state.clone(),
+        monitor,
+        args.alert_webhook.clone(),
+        Duration::from_secs(args.alert_interval),
+    );
+
+    // Stop accepting connections on SIGINT or SIGTERM, and let the requests in flight complete
+    let served = tokio::select! {
+        served = &mut server => served,
+        () = shutdown_signal() => {
+            notify_systemd("STOPPING=1");
+            tracing::info!(
+                timeout_seconds = args.shutdown_timeout,
+                "Shutting down, draining the connections"
+            );
+            shutdown.notify_one();
+            let drain = Duration::from_secs(args.shutdown_timeout);
+            match tokio::time::timeout(drain, &mut server).await {
+                Ok(served) => served,
+                Err(_) => {
+                    tracing::warn!("Connections still open after the shutdown timeout, dropping them");
+                    server.abort();
+                    Ok(Ok(()))
+                }
+            }
+        }
+    };
+    served
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Cannot serve requests: {e}"))?;
+
+    // Leave the consensus, for the other nodes to elect a leader if this node led the cluster
+    #[cfg(feature = "cluster")]
+    if let Some(cluster) = &cluster {
+        cluster.shutdown().await;
+    }
+
+    // Save the inserts made since the last snapshot
+    if let Some(dir) = &args.data_dir {
+        let saved = state
+            .save(dir)
+            .map_err(|e| format!("Cannot save filters to '{}': {e}", dir.display()))?;
+        tracing::info!(filters = saved, dir = %dir.display(), "Saved the filters");
+    }
+    Ok(())
+}
+
+/// Parses the requests per second of `--rate-limit`.
+fn parse_rate(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err("expected a positive number of requests per second".to_string()),
+    }
+}
+
+/// Parses the ratio of `--alert-fill-ratio`, `--alert-false-positive-rate` and
+/// `--publish-inserts`.
+fn parse_ratio(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(ratio) if ratio > 0.0 && ratio <= 1.0 => Ok(ratio),
+        _ => Err("expected a number greater than 0, and at most 1".to_string()),
+    }
+}
+
+/// Settings of the configuration file given by `--config`, taking precedence over the options of
+/// the same name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default, deserialize_with = "deserialize_level")]
+    log_level: Option<tracing::Level>,
+    admin_key: Option<String>,
+    rate_limit: Option<f64>,
+    rate_limit_burst: Option<u32>,
+    alert_fill_ratio: Option<f64>,
+    alert_false_positive_rate: Option<f64>,
+    /// Filters created on startup, and on reload, unless they exist already.
+    #[serde(default)]
+    filters: Vec<FilterSpec>,
+    #[serde(default)]
+    quotas: QuotaSettings,
+    /// Quotas of the namespaces, overriding those of the store.
+    #[serde(default)]
+    namespace_quotas: BTreeMap<String, QuotaSettings>,
+    /// Other nodes of the shard ring, replacing those of the options.
+    shard_nodes: Option<Vec<String>>,
+}
+
+/// Quotas, as given by the options or the `[quotas]` settings of the configuration file, with
+/// memory in MiB.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuotaSettings {
+    filters: Option<usize>,
+    item_count: Option<u64>,
+    filter_memory: Option<u64>,
+    memory: Option<u64>,
+}
 
-    let addr = SocketAddr::from((args.host, args.port));
-    println!("Bloom Daemon listening on http://{}", addr);
+impl QuotaSettings {
+    /// Returns these settings, with those not set taken from `defaults`.
+    fn or(&self, defaults: &QuotaSettings) -> QuotaSettings {
+        QuotaSettings {
+            filters: self.filters.or(defaults.filters),
+            item_count: self.item_count.or(defaults.item_count),
+            filter_memory: self.filter_memory.or(defaults.filter_memory),
+            memory: self.memory.or(defaults.memory),
+        }
+    }
+
+    /// Returns the quotas of these settings, with memory in bytes.
+    fn quotas(&self) -> Quotas {
+        let saturating = |n: u64| usize::try_from(n).unwrap_or(usize::MAX);
+        let bytes = |mb: u64| saturating(mb.saturating_mul(1 << 20));
+        Quotas {
+            max_filters: self.filters,
+            max_item_count: self.item_count.map(saturating),
+            max_filter_bytes: self.filter_memory.map(bytes),
+            max_total_bytes: self.memory.map(bytes),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// Reads the configuration file at `path`.
+    fn read(path: &Path) -> Result<Self, String> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            format!("Invalid configuration file '{}': {e}", path.display())
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        let file: ConfigFile = toml::from_str(&text).map_err(|e| invalid(&e))?;
+        if let Some(rate) = file.rate_limit {
+            parse_rate(&rate.to_string()).map_err(|e| invalid(&format!("rate_limit: {e}")))?;
+        }
+        if file.rate_limit_burst == Some(0) {
+            return Err(invalid(&"rate_limit_burst: expected at least 1 request"));
+        }
+        let ratios = [
+            ("alert_fill_ratio", file.alert_fill_ratio),
+            ("alert_false_positive_rate", file.alert_false_positive_rate),
+        ];
+        for (setting, ratio) in ratios {
+            if let Some(ratio) = ratio {
+                parse_ratio(&ratio.to_string()).map_err(|e| invalid(&format!("{setting}: {e}")))?;
+            }
+        }
+        let mut names = std::collections::HashSet::new();
+        for filter in &file.filters {
+            if filter.name.is_empty() {
+                return Err(invalid(&"filters: every filter needs a name"));
+            }
+            if !names.insert(&filter.name) {
+                let duplicate = format!("filters: '{}' is listed more than once", filter.name);
+                return Err(invalid(&duplicate));
+            }
+        }
+        Ok(file)
+    }
+
+    /// Returns the quotas of the store and of its namespaces, those of the `options` overridden by
+    /// the settings of the file, and those of the store by those of each namespace.
+    fn quotas(&self, options: &QuotaSettings) -> (Quotas, BTreeMap<String, Quotas>) {
+        let store = self.quotas.or(options);
+        let namespaces = self
+            .namespace_quotas
+            .iter()
+            .map(|(name, settings)| (name.clone(), settings.or(&store).quotas()))
+            .collect();
+        (store.quotas(), namespaces)
+    }
+
+    /// Returns the thresholds of the saturation alerts, those of the options overridden by the
+    /// settings of the file.
+    fn alert_thresholds(&self, options: AlertThresholds) -> AlertThresholds {
+        AlertThresholds {
+            fill_ratio: self.alert_fill_ratio.or(options.fill_ratio),
+            false_positive_rate: self
+                .alert_false_positive_rate
+                .or(options.false_positive_rate),
+        }
+    }
+}
+
+/// Creates the filters of the configuration file that do not exist yet, returning how many were
+/// created; with a shard ring, only those this node owns, and its partitions of the partitioned
+/// filters.
+///
+/// Existing filters are left as they are; those with other parameters than in the file are
+/// reported, as changing them would lose their items.
+fn create_filters(
+    state: &SharedState,
+    filters: &[FilterSpec],
+    shards: Option<&ShardRing>,
+) -> Result<usize, Error> {
+    let report = match shards {
+        Some(ring) => state.bootstrap(ring.owned_filters(filters))?,
+        None => state.bootstrap(filters.iter().cloned())?,
+    };
+    for name in &report.created {
+        tracing::info!(filter = name, "Created a filter of the configuration file");
+    }
+    for name in &report.conflicting {
+        tracing::warn!(
+            filter = name,
+            "The filter exists with other parameters than in the configuration file"
+        );
+    }
+    Ok(report.created.len())
+}
+
+/// Parses a level of the logs, e.g. `debug`.
+fn deserialize_level<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<tracing::Level>, D::Error> {
+    let level = String::deserialize(deserializer)?;
+    level.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Applies the settings of the configuration file to the configuration given by the options.
+///
+/// The throttle of the `current` configuration is kept if its limits are unchanged, and so are
+/// the requests it counted.
+fn configure(options: &AppConfig, file: &ConfigFile, current: Option<&AppConfig>) -> AppConfig {
+    let mut config = options.clone();
+    if let Some(key) = &file.admin_key {
+        config.admin_key = Some(key.clone());
+    }
+    let limits = match (file.rate_limit, &options.throttle) {
+        (Some(rate), _) => Some((rate, file.rate_limit_burst.unwrap_or(rate.ceil() as u32))),
+        (None, Some(throttle)) => Some((
+            throttle.rate,
+            file.rate_limit_burst.unwrap_or(throttle.burst),
+        )),
+        (None, None) => None,
+    };
+    config.throttle =
+        limits.map(
+            |(rate, burst)| match current.and_then(|current| current.throttle.clone()) {
+                Some(throttle) if throttle.rate == rate && throttle.burst == burst => throttle,
+                _ => Arc::new(Throttle::new(rate, burst)),
+            },
+        );
+    config
+}
+
+/// What the settings of the configuration file apply to, and the options they take precedence
+/// over.
+struct Reloadable {
+    options: AppConfig,
+    quotas: QuotaSettings,
+    log_level: tracing::Level,
+    thresholds: AlertThresholds,
+    config: SharedConfig,
+    state: SharedState,
+    monitor: Arc<SaturationMonitor>,
+    peers: Option<Arc<ShardPeers>>,
+    shard_nodes: Vec<String>,
+}
+
+/// Creates the reloader of the configuration file at `path`, replacing the configuration of the
+/// service with the options overridden by the settings of the file, as read again.
+fn config_reloader(path: PathBuf, target: Reloadable) -> Reloader {
+    let reloading = Mutex::new(());
+    Reloader::new(move || {
+        // One reload at a time, so that none is lost
+        let _reloading = reloading.lock().unwrap_or_else(|e| e.into_inner());
+        let file = ConfigFile::read(&path).map_err(Error::InvalidParameters)?;
+        if let Some(peers) = &target.peers {
+            peers.set_seeds(file.shard_nodes.as_ref().unwrap_or(&target.shard_nodes))?;
+        }
+        let current = target.config.get();
+        // Nothing is created in read-only mode, until the next reload after it is turned off
+        let created = match target.state.read_only_mode.load(Ordering::Relaxed) {
+            true => 0,
+            false => create_filters(&target.state, &file.filters, current.shards.as_deref())?,
+        };
+        let mut reloaded = configure(&target.options, &file, Some(&current));
+        reloaded.reload = current.reload;
+        reloaded.shards = current.shards;
+        target.config.replace(reloaded);
+        let (quotas, namespace_quotas) = file.quotas(&target.quotas);
+        target.state.set_quotas(quotas, namespace_quotas);
+        set_log_level(file.log_level.unwrap_or(target.log_level));
+        target
+            .monitor
+            .set_thresholds(file.alert_thresholds(target.thresholds));
+        Ok(format!(
+            "Reloaded the configuration from '{}', creating {created} filters",
+            path.display()
+        ))
+    })
+}
+
+/// Spawns a background task checking the saturation of the filters every `interval`, logging a
+/// warning for each filter beyond the thresholds of the monitor, publishing it as an event, and
+/// posting it to `webhook`, if any.
+fn spawn_alert_task(
+    state: SharedState,
+    monitor: Arc<SaturationMonitor>,
+    webhook: Option<String>,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let (store, checked) = (state.clone(), monitor.clone());
+            // Counting the bits set of large filters takes a while
+            let Ok(alerts) =
+                tokio::task::spawn_blocking(move || checked.check(&store, Instant::now())).await
+            else {
+                continue;
+            };
+            for alert in alerts {
+                tracing::warn!(
+                    namespace = alert.namespace.as_deref().unwrap_or_default(),
+                    filter = alert.filter,
+                    fill_ratio = alert.fill_ratio,
+                    false_positive_rate = alert.false_positive_rate,
+                    "Filter is saturated"
+                );
+                state.publish(FilterEvent::Saturated(alert.clone()));
+                let Some(url) = &webhook else {
+                    continue;
+                };
+                let posted = client
+                    .post(url)
+                    .timeout(WEBHOOK_TIMEOUT)
+                    .json(&alert)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(error) = posted {
+                    tracing::warn!(%error, url, "Cannot post the saturation alert");
+                }
+            }
+        }
+    });
+}
+
+/// Spawns a background task reloading the configuration on SIGHUP.
+#[cfg(unix)]
+fn spawn_reload_task(reloader: Reloader) -> Result<(), String> {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .map_err(|e| format!("Cannot listen for SIGHUP: {e}"))?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match reloader.reload() {
+                Ok(reloaded) => tracing::info!("{reloaded}"),
+                Err(error) => tracing::warn!(%error, "Cannot reload the configuration"),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Reloading on SIGHUP needs Unix signals; `POST /admin/reload` reloads everywhere.
+#[cfg(not(unix))]
+fn spawn_reload_task(_: Reloader) -> Result<(), String> {
+    Ok(())
+}
+
+/// Builds the CORS policy of the origins given by `--cors-origin`, if any.
+fn cors_policy(args: &ServeArgs) -> Option<CorsPolicy> {
+    if args.cors_origins.is_empty() {
+        return None;
+    }
+    let mut cors = CorsPolicy::new(args.cors_origins.clone());
+    if !args.cors_methods.is_empty() {
+        cors.methods = args.cors_methods.clone();
+    }
+    if !args.cors_headers.is_empty() {
+        cors.headers = args.cors_headers.clone();
+    }
+    Some(cors)
+}
+
+/// Parses the `<role>=<scope>` of a `--jwt-role` option.
+fn parse_role(text: &str) -> Result<(String, KeyScope), String> {
+    let (role, scope) = text
+        .split_once('=')
+        .ok_or("expected <role>=<read|read_write|admin>")?;
+    let scope = serde_json::from_value(serde_json::Value::String(scope.to_string()))
+        .map_err(|_| format!("unknown scope '{scope}', expected read, read_write or admin"))?;
+    Ok((role.to_string(), scope))
+}
+
+/// Parses the `<key>=<value>` of a `--label` option.
+fn parse_label(text: &str) -> Result<(String, String), String> {
+    let (key, value) = text.split_once('=').ok_or("expected <key>=<value>")?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Creates the validator of the tokens of the issuer given by `--jwt-issuer`, if any, with its
+/// signing keys, and spawns a background task refreshing fetched keys, as issuers rotate them.
+async fn jwt_validator(args: &ServeArgs) -> Result<Option<Arc<JwtValidator>>, String> {
+    let (Some(issuer), Some(audience)) = (&args.jwt_issuer, &args.jwt_audience) else {
+        return Ok(None);
+    };
+    let mut validator = JwtValidator::new(issuer, audience);
+    validator.role_claim = args.jwt_role_claim.clone();
+    validator.roles.extend(args.jwt_roles.iter().cloned());
+    let validator = Arc::new(validator);
+
+    if let Some(file) = &args.jwks_file {
+        let jwks =
+            std::fs::read(file).map_err(|e| format!("Cannot read '{}': {e}", file.display()))?;
+        validator
+            .set_keys(&jwks)
+            .map_err(|e| format!("{}: {e}", file.display()))?;
+        return Ok(Some(validator));
+    }
+    let client = reqwest::Client::new();
+    let url = match &args.jwks_url {
+        Some(url) => url.clone(),
+        None => discover_jwks_url(&client, issuer).await?,
+    };
+    let keys = fetch_jwks(&client, &url, &validator).await?;
+    tracing::info!(issuer, keys, url, "Loaded the signing keys of the issuer");
+
+    let refreshed = validator.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(JWKS_REFRESH_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            // The previous keys are kept if the issuer cannot be reached
+            if let Err(error) = fetch_jwks(&client, &url, &refreshed).await {
+                tracing::warn!(%error, "Cannot refresh the signing keys of the issuer");
+            }
+        }
+    });
+    Ok(Some(validator))
+}
+
+/// Returns the `jwks_uri` of the OpenID Connect discovery document of an issuer.
+async fn discover_jwks_url(client: &reqwest::Client, issuer: &str) -> Result<String, String> {
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    );
+    let document: serde_json::Value = client
+        .get(&url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| format!("Request to {url} failed: {e}"))?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid response from {url}: {e}"))?;
+    document["jwks_uri"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("The discovery document at {url} has no jwks_uri"))
+}
+
+/// Fetches the signing keys at `url` into the validator, returning their number.
+async fn fetch_jwks(
+    client: &reqwest::Client,
+    url: &str,
+    validator: &JwtValidator,
+) -> Result<usize, String> {
+    let jwks = client
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| format!("Request to {url} failed: {e}"))?
+        .bytes()
+        .await
+        .map_err(|e| format!("Invalid response from {url}: {e}"))?;
+    validator.set_keys(&jwks).map_err(|e| format!("{url}: {e}"))
+}
+
+/// Takes the sockets passed by systemd socket activation, by name.
+#[cfg(unix)]
+fn activated_listeners() -> Result<Vec<(String, std::net::TcpListener)>, String> {
+    bloomsrv::activated_listeners()
+        .map_err(|e| format!("Cannot take the sockets passed by systemd: {e}"))
+}
+
+/// Socket activation is specific to systemd.
+#[cfg(not(unix))]
+fn activated_listeners() -> Result<Vec<(String, std::net::TcpListener)>, String> {
+    Ok(Vec::new())
+}
+
+/// Tells systemd the state of the service, e.g. `READY=1`, if it started the service and waits
+/// for notifications.
+#[cfg(unix)]
+fn notify_systemd(state: &str) {
+    if let Some(systemd) = bloomsrv::SystemdNotifier::from_env() {
+        if let Err(error) = systemd.notify(state) {
+            tracing::warn!(%error, state, "Cannot notify systemd");
+        }
+    }
+}
+
+/// Notifications are specific to systemd.
+#[cfg(not(unix))]
+fn notify_systemd(_: &str) {}
+
+/// Spawns a background task pinging the watchdog of systemd at half its timeout, if it watches
+/// the service, so that systemd restarts the service once it hangs.
+#[cfg(unix)]
+fn spawn_watchdog_task() {
+    let Some(systemd) = bloomsrv::SystemdNotifier::from_env() else {
+        return;
+    };
+    let Some(timeout) = systemd.watchdog() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(timeout / 2);
+        loop {
+            ticker.tick().await;
+            if let Err(error) = systemd.notify("WATCHDOG=1") {
+                tracing::warn!(%error, "Cannot ping the watchdog of systemd");
+            }
+        }
+    });
+}
+
+/// The watchdog is specific to systemd.
+#[cfg(not(unix))]
+fn spawn_watchdog_task() {}
+
+/// Completes when the service is asked to stop, with `Ctrl+C` (SIGINT) or, on Unix, SIGTERM (as
+/// sent by container runtimes).
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c().await.ok();
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        () = interrupt => {}
+        () = terminate => {}
+    }
+}
+
+/// Spawns a background task that saves all filters to `dir` every `interval`.
+///
+/// Failures are reported, and retried at the next interval.
+fn spawn_snapshot_task(state: SharedState, dir: PathBuf, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately, and there is nothing new to save yet
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let (state, dir) = (state.clone(), dir.clone());
+            let result = tokio::task::spawn_blocking(move || state.save(&dir)).await;
+            if let Ok(Err(error)) = result {
+                tracing::error!(%error, "Cannot save filters");
+            }
+        }
+    });
+}
+
+async fn apply(args: ApplyArgs) -> Result<(), String> {
+    let content = std::fs::read_to_string(&args.file)
+        .map_err(|e| format!("Cannot read '{}': {e}", args.file.display()))?;
+    // YAML is a superset of JSON, so both formats are accepted
+    let manifest: serde_json::Value = serde_yaml::from_str(&content)
+        .map_err(|e| format!("Cannot parse '{}': {e}", args.file.display()))?;
+
+    let url = format!("{}/v1/admin/apply", args.url.trim_end_matches('/'));
+    let mut request = reqwest::Client::new()
+        .post(url)
+        .query(&[("prune", args.prune), ("dry_run", args.dry_run)])
+        .json(&manifest);
+    if let Some(key) = &args.api_key {
+        request = request.bearer_auth(key);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Request to {} failed: {e}", args.url))?;
+
+    let status = response.status();
+    let body: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid response from {}: {e}", args.url))?;
+    println!("{}", serde_json::to_string_pretty(&body).unwrap());
+
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(format!("The manifest was rejected ({status})"))
+    }
+}
+
+/// Number of items sent by the `insert` and `check` subcommands in each request.
+const CLIENT_BATCH_ITEMS: usize = 10_000;
+
+async fn create(args: CreateArgs) -> Result<(), String> {
+    let spec = FilterSpec {
+        name: args.name,
+        item_count: args.item_count,
+        false_positive_rate: args.false_positive_rate,
+        hash_count: args.hash_count,
+        ttl_seconds: args.ttl_seconds,
+        scheme: args.scheme,
+        seed: args.seed,
+        labels: args.labels.into_iter().collect(),
+        ..Default::default()
+    };
+    let created = args
+        .service
+        .client()
+        .create_filter(&spec)
+        .await
+        .map_err(|e| e.to_string())?;
+    println!(
+        "Filter '{}' created ({} bits, {} hash functions)",
+        created.name, created.bit_count, created.hash_count
+    );
+    Ok(())
+}
+
+async fn insert(args: ItemArgs) -> Result<(), String> {
+    let client = args.service.client();
+    let items = items(args.items)?;
+    let mut new = 0;
+    for batch in items.chunks(CLIENT_BATCH_ITEMS) {
+        let inserted = client
+            .insert_batch(&args.filter, batch)
+            .await
+            .map_err(|e| e.to_string())?;
+        new += inserted.into_iter().flatten().filter(|&new| new).count();
+    }
+    println!(
+        "Inserted {} items into filter '{}', {new} of them new",
+        items.len(),
+        args.filter
+    );
+    Ok(())
+}
+
+/// Prints each item with whether it may have been inserted, returning `true` if all may have.
+async fn check(args: ItemArgs) -> Result<bool, String> {
+    let client = args.service.client();
+    let items = items(args.items)?;
+    let mut all = true;
+    for batch in items.chunks(CLIENT_BATCH_ITEMS) {
+        let contains = client
+            .contains_many(&args.filter, batch)
+            .await
+            .map_err(|e| e.to_string())?;
+        for (item, contains) in batch.iter().zip(contains) {
+            println!("{item}\t{contains}");
+            all &= contains;
+        }
+    }
+    Ok(all)
+}
+
+async fn list(args: ListArgs) -> Result<(), String> {
+    let filters = args
+        .service
+        .client()
+        .list_filters()
+        .await
+        .map_err(|e| e.to_string())?;
+    for filter in filters {
+        println!("{}", filter.name);
+    }
+    Ok(())
+}
+
+/// Returns the items given, or else those read from the standard input, one per line.
+fn items(given: Vec<String>) -> Result<Vec<String>, String> {
+    if !given.is_empty() {
+        return Ok(given);
+    }
+    let mut items = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.map_err(|e| format!("Cannot read the standard input: {e}"))?;
+        let item = line.strip_suffix('\r').unwrap_or(&line);
+        if !item.is_empty() {
+            items.push(item.to_string());
+        }
+    }
+    Ok(items)
+}
+
+fn build(args: BuildArgs) -> Result<(), String> {
+    let creation_mode = match (args.false_positive_rate, args.hash_count) {
+        (Some(rate), _) => CreationMode::FalsePositiveRate(rate),
+        (None, Some(count)) => CreationMode::HashCount(count),
+        (None, None) => unreachable!("clap requires one of --fp and --hash-count"),
+    };
+    let length = std::fs::metadata(&args.input)
+        .map_err(|e| format!("Cannot read '{}': {e}", args.input.display()))?
+        .len();
+
+    // Each thread reads the lines starting within its share of the bytes of the dataset
+    let threads = args
+        .threads
+        .or_else(|| thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(1) as u64;
+    let chunks: Vec<(u64, u64)> = (0..threads)
+        .map(|i| (length * i / threads, length * (i + 1) / threads))
+        .collect();
+
+    let item_count = match args.item_count {
+        Some(count) => count,
+        None => in_parallel(&chunks, |start, end| {
+            let mut count = 0;
+            for_each_line(&args.input, start, end, |_| count += 1)?;
+            Ok(count)
+        })?
+        .into_iter()
+        .sum(),
+    };
+    let empty = BloomFilter::new(item_count, creation_mode)
+        .and_then(|filter| filter.with_hashing(args.scheme, args.seed))
+        .map_err(|e| e.to_string())?;
+
+    let mut filters = in_parallel(&chunks, |start, end| {
+        let mut filter = empty.clone();
+        for_each_line(&args.input, start, end, |item| {
+            filter.insert(item);
+        })?;
+        Ok(filter)
+    })?
+    .into_iter();
+    let mut filter = filters.next().unwrap_or(empty);
+    for other in filters {
+        filter.union(&other).map_err(|e| e.to_string())?;
+    }
+
+    let snapshot = FilterSnapshot {
+        item_count,
+        creation_mode,
+        filter,
+    };
+    std::fs::write(&args.out, snapshot.encode())
+        .map_err(|e| format!("Cannot write '{}': {e}", args.out.display()))?;
+    println!(
+        "Built '{}' for {item_count} items: {} bits, {} hash functions",
+        args.out.display(),
+        snapshot.filter.bit_count(),
+        snapshot.filter.hash_count()
+    );
+    Ok(())
+}
+
+/// Runs `task` on every byte range of the dataset in its own thread, collecting the results.
+fn in_parallel<T: Send>(
+    chunks: &[(u64, u64)],
+    task: impl Fn(u64, u64) -> std::io::Result<T> + Sync,
+) -> Result<Vec<T>, String> {
+    let task = &task;
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .iter()
+            .map(|&(start, end)| scope.spawn(move || task(start, end)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("dataset reader panicked"))
+            .collect::<std::io::Result<Vec<T>>>()
+    })
+    .map_err(|e| format!("Cannot read the dataset: {e}"))
+}
+
+/// Calls `f` with every non-empty line of a file that starts within the byte range `start..end`.
+fn for_each_line(
+    path: &Path,
+    start: u64,
+    end: u64,
+    mut f: impl FnMut(&[u8]),
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut line = Vec::new();
+    let mut position = start;
+    if start > 0 {
+        // Skip the rest of a line starting before the range, it belongs to the previous one
+        reader.seek(SeekFrom::Start(start - 1))?;
+        position = start - 1 + reader.read_until(b'\n', &mut line)? as u64;
+    }
+
+    while position < end {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        position += read as u64;
+
+        let item = line.strip_suffix(b"\n").unwrap_or(&line);
+        let item = item.strip_suffix(b"\r").unwrap_or(item);
+        if !item.is_empty() {
+            f(item);
+        }
+    }
+    Ok(())
+}
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+fn diff(args: DiffArgs) -> Result<(), String> {
+    let read = |path: &Path| {
+        let bytes =
+            std::fs::read(path).map_err(|e| format!("Cannot read '{}': {e}", path.display()))?;
+        FilterSnapshot::decode(&bytes).map_err(|e| format!("Cannot load '{}': {e}", path.display()))
+    };
+    let diff = read(&args.before)?
+        .filter
+        .diff(&read(&args.after)?.filter)
+        .map_err(|e| e.to_string())?;
+    println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+    Ok(())
 }
@@ -0,0 +1,137 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    ops::{Deref, DerefMut},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    ptr::NonNull,
+};
+
+/// Words of a filter stored in a file mapped into memory, after a header of `header_len` bytes.
+///
+/// Writes go to the page cache, and the kernel writes them back to the file (at the latest on
+/// [`MappedWords::flush`]), so the filter survives restarts without being saved or loaded.
+pub(crate) struct MappedWords {
+    base: NonNull<u8>,
+    map_len: usize,
+    header_len: usize,
+    words: usize,
+    path: PathBuf,
+}
+
+// The mapping is owned like a `Vec<u64>`: shared access is read-only, writes need `&mut self`
+unsafe impl Send for MappedWords {}
+unsafe impl Sync for MappedWords {}
+
+impl MappedWords {
+    /// Creates a file holding `header` and `words` zero words, and maps it.
+    ///
+    /// The file is sparse, so creating even a very large filter is immediate.
+    pub(crate) fn create(path: &Path, header: &[u8], words: usize) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(header)?;
+        file.set_len((header.len() + words * 8) as u64)?;
+        Self::map(&file, path, header.len(), words)
+    }
+
+    /// Maps a file created by [`MappedWords::create`].
+    ///
+    /// `words` decodes the header, returning the number of words it declares.
+    pub(crate) fn open(
+        path: &Path,
+        header_len: usize,
+        words: impl FnOnce(&[u8]) -> io::Result<usize>,
+    ) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut header = vec![0; header_len];
+        file.read_exact(&mut header)?;
+        let words = words(&header)?;
+        if file.metadata()?.len() != (header_len + words * 8) as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the size of the file does not match its header",
+            ));
+        }
+        Self::map(&file, path, header_len, words)
+    }
+
+    fn map(file: &File, path: &Path, header_len: usize, words: usize) -> io::Result<Self> {
+        let map_len = header_len + words * 8;
+        // SAFETY: the file is at least `map_len` bytes long, and the mapping is released on drop
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                map_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(MappedWords {
+            base: NonNull::new(base.cast()).expect("mmap returned a null pointer"),
+            map_len,
+            header_len,
+            words,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Writes the changed pages back to the file, and waits until they are.
+    pub(crate) fn flush(&self) -> io::Result<()> {
+        // SAFETY: the range is the mapping itself
+        if unsafe { libc::msync(self.base.as_ptr().cast(), self.map_len, libc::MS_SYNC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Deref for MappedWords {
+    type Target = [u64];
+
+    fn deref(&self) -> &[u64] {
+        // SAFETY: the header is a multiple of 8 bytes long, and mappings are page-aligned
+        unsafe {
+            std::slice::from_raw_parts(self.base.as_ptr().add(self.header_len).cast(), self.words)
+        }
+    }
+}
+
+impl DerefMut for MappedWords {
+    fn deref_mut(&mut self) -> &mut [u64] {
+        // SAFETY: as for `deref`, and the mapping is writable
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.base.as_ptr().add(self.header_len).cast(),
+                self.words,
+            )
+        }
+    }
+}
+
+impl Drop for MappedWords {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is not used after this
+        unsafe {
+            libc::munmap(self.base.as_ptr().cast(), self.map_len);
+        }
+    }
+}
+
+impl std::fmt::Debug for MappedWords {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MappedWords")
+            .field("path", &self.path)
+            .field("words", &self.words)
+            .finish()
+    }
+}
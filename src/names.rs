@@ -0,0 +1,89 @@
+use crate::Error;
+
+/// Default maximum length of a name, in characters.
+const DEFAULT_MAX_LENGTH: usize = 64;
+/// Default characters allowed in names besides ASCII letters and digits.
+const DEFAULT_PUNCTUATION: &str = "_-.";
+
+/// Rules the names of filters, groups, limiters and recent filters must follow.
+///
+/// A valid name is non-empty, at most `max_length` characters long, starts with an ASCII letter
+/// or digit, and otherwise consists of ASCII letters, digits and the characters in
+/// `punctuation`. It must not start with any of the `reserved_prefixes`.
+///
+/// The default policy allows names such as `login_attempts` or `tier-1.eu`, but not names that
+/// would break URL routing (`a/b`, `a?b`) or file paths (`..`).
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::NamePolicy;
+///
+/// let policy = NamePolicy {
+///     reserved_prefixes: vec!["system_".to_string()],
+///     ..Default::default()
+/// };
+///
+/// assert!(policy.check("login_attempts").is_ok());
+/// assert!(policy.check("a/b").is_err());
+/// assert!(policy.check("system_users").is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct NamePolicy {
+    pub max_length: usize,
+    /// Characters allowed besides ASCII letters and digits (but not as the first character).
+    pub punctuation: String,
+    pub reserved_prefixes: Vec<String>,
+}
+
+impl Default for NamePolicy {
+    fn default() -> Self {
+        NamePolicy {
+            max_length: DEFAULT_MAX_LENGTH,
+            punctuation: DEFAULT_PUNCTUATION.to_string(),
+            reserved_prefixes: Vec::new(),
+        }
+    }
+}
+
+impl NamePolicy {
+    /// Checks a name against the policy.
+    pub fn check(&self, name: &str) -> Result<(), Error> {
+        let invalid = |reason: String| {
+            Err(Error::InvalidName {
+                name: name.to_string(),
+                reason,
+            })
+        };
+
+        let Some(first) = name.chars().next() else {
+            return invalid("names must not be empty".to_string());
+        };
+        if name.chars().count() > self.max_length {
+            return invalid(format!(
+                "names must be at most {} characters long",
+                self.max_length
+            ));
+        }
+        if !first.is_ascii_alphanumeric() {
+            return invalid("names must start with a letter or a digit".to_string());
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !self.punctuation.contains(*c))
+        {
+            return invalid(format!(
+                "'{c}' is not allowed, names may only contain letters, digits and '{}'",
+                self.punctuation
+            ));
+        }
+        if let Some(prefix) = self
+            .reserved_prefixes
+            .iter()
+            .find(|prefix| name.starts_with(prefix.as_str()))
+        {
+            return invalid(format!("the prefix '{prefix}' is reserved"));
+        }
+        Ok(())
+    }
+}
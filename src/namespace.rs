@@ -0,0 +1,195 @@
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{events::Change, Error, FilterStore, MemoryBudget, SharedState};
+
+/// Directory holding the namespaces, one subdirectory each, in a data directory.
+const NAMESPACES_DIR: &str = "namespaces";
+
+/// Summary of a namespace, as listed by [`FilterStore::list_namespaces`].
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Summary of a namespace.")
+)]
+pub struct NamespaceInfo {
+    pub name: String,
+    pub filters: usize,
+    pub memory_usage_bytes: usize,
+}
+
+impl FilterStore {
+    /// Creates an empty namespace: a store of its own, isolated from the other namespaces, so that
+    /// several applications can share a service without their names colliding.
+    ///
+    /// The namespace follows the name policy, memory budget and quotas (unless it has quotas of
+    /// its own) of this store, and keeps its memory-mapped filters, snapshots and write-ahead log
+    /// in `namespaces/<name>` under the directories of this store.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterSpec, FilterStore};
+    ///
+    /// let store = FilterStore::default();
+    /// let payments = store.create_namespace("payments").unwrap();
+    /// let spec = FilterSpec {
+    ///     name: "seen".to_string(),
+    ///     item_count: 1000,
+    ///     false_positive_rate: Some(0.01),
+    ///     ..Default::default()
+    /// };
+    /// payments.create(spec.clone()).unwrap();
+    /// store.create(spec).unwrap();
+    ///
+    /// payments.insert("seen", "alice").unwrap();
+    /// assert!(!store.contains("seen", "alice").unwrap());
+    /// ```
+    pub fn create_namespace(&self, name: &str) -> Result<SharedState, Error> {
+        self.name_policy.check(name)?;
+        let mut namespaces = self.namespaces.write();
+        if namespaces.contains_key(name) {
+            return Err(Error::NamespaceExists(name.to_string()));
+        }
+        let namespace = SharedState::new(self.new_namespace(name));
+        if let Some((dir, segment_bytes)) = self.journal.settings() {
+            namespace
+                .start_log(&namespace_dir(&dir, name), segment_bytes)
+                .map_err(|e| Error::Storage(e.to_string()))?;
+        }
+        namespaces.insert(name.to_string(), namespace.clone());
+        Ok(namespace)
+    }
+
+    /// Returns the namespace `name`.
+    pub fn namespace(&self, name: &str) -> Result<SharedState, Error> {
+        self.namespaces
+            .read()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::NamespaceNotFound(name.to_string()))
+    }
+
+    /// Lists all namespaces.
+    pub fn list_namespaces(&self) -> Vec<NamespaceInfo> {
+        self.namespaces
+            .read()
+            .iter()
+            .map(|(name, namespace)| NamespaceInfo {
+                name: name.clone(),
+                filters: namespace.filters.read().len(),
+                memory_usage_bytes: namespace.memory_stats().memory_usage_bytes,
+            })
+            .collect()
+    }
+
+    /// Deletes a namespace and everything in it, including its write-ahead log.
+    pub fn delete_namespace(&self, name: &str) -> Result<(), Error> {
+        let namespace = self
+            .namespaces
+            .write()
+            .remove(name)
+            .ok_or_else(|| Error::NamespaceNotFound(name.to_string()))?;
+        // Otherwise its log would bring it back on the next start
+        if let Some((dir, _)) = namespace.journal.settings() {
+            namespace.journal.stop();
+            fs::remove_dir_all(dir).map_err(|e| Error::Storage(e.to_string()))?;
+        }
+        for filter in namespace.filters.read().keys() {
+            namespace.events.changed(Change::Deleted, filter);
+        }
+        Ok(())
+    }
+
+    /// Saves every namespace to its subdirectory of `dir`, removing those of deleted namespaces;
+    /// returns the number of filters saved.
+    pub(crate) fn save_namespaces(&self, dir: &Path) -> io::Result<usize> {
+        let namespaces: Vec<(String, SharedState)> = self
+            .namespaces
+            .read()
+            .iter()
+            .map(|(name, namespace)| (name.clone(), namespace.clone()))
+            .collect();
+        let mut saved = 0;
+        for (name, namespace) in &namespaces {
+            saved += namespace.save(&namespace_dir(dir, name))?;
+        }
+        let root = dir.join(NAMESPACES_DIR);
+        if root.exists() {
+            for entry in fs::read_dir(&root)? {
+                let path = entry?.path();
+                let live = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| self.namespaces.read().contains_key(name));
+                if path.is_dir() && !live {
+                    fs::remove_dir_all(&path)?;
+                }
+            }
+        }
+        Ok(saved)
+    }
+
+    /// Loads the namespaces saved to `dir` by [`FilterStore::save_namespaces`], returning the
+    /// number of their filters.
+    pub(crate) fn load_namespaces(&self, dir: &Path) -> io::Result<usize> {
+        let root = dir.join(NAMESPACES_DIR);
+        if !root.exists() {
+            return Ok(0);
+        }
+        let mut loaded = 0;
+        for entry in fs::read_dir(&root)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !path.is_dir() || self.name_policy.check(name).is_err() {
+                continue;
+            }
+            let namespace = self
+                .namespaces
+                .write()
+                .entry(name.to_string())
+                .or_insert_with(|| SharedState::new(self.new_namespace(name)))
+                .clone();
+            loaded += namespace.load(&path)?;
+        }
+        Ok(loaded)
+    }
+
+    /// Starts the write-ahead logs of the namespaces, in their subdirectories of `dir`.
+    pub(crate) fn start_namespace_logs(&self, dir: &Path, segment_bytes: u64) -> io::Result<()> {
+        for (name, namespace) in self.namespaces.read().iter() {
+            namespace.start_log(&namespace_dir(dir, name), segment_bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Creates the empty store of the namespace `name`, with the settings of this store.
+    fn new_namespace(&self, name: &str) -> FilterStore {
+        let mut namespace = FilterStore::with_name_policy(self.name_policy.clone());
+        namespace.events = self.events.for_namespace(name);
+        namespace.quotas = RwLock::new(self.quotas_of(name));
+        namespace.data_dir = self.data_dir.as_ref().map(|dir| namespace_dir(dir, name));
+        namespace.clustered = AtomicBool::new(self.clustered.load(Ordering::Relaxed));
+        namespace.memory_budget = self.memory_budget.as_ref().map(|budget| MemoryBudget {
+            max_bytes: budget.max_bytes,
+            persist_dir: budget
+                .persist_dir
+                .as_ref()
+                .map(|dir| namespace_dir(dir, name)),
+        });
+        namespace
+    }
+}
+
+/// Returns the directory of the namespace `name` under `dir`.
+fn namespace_dir(dir: &Path, name: &str) -> PathBuf {
+    dir.join(NAMESPACES_DIR).join(name)
+}
@@ -0,0 +1,521 @@
+#[cfg(feature = "wasm")]
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use parking_lot::RwLockReadGuard;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    auth::{decode_keys, encode_keys},
+    journal::JOURNAL_EXTENSION,
+    store::FilterEntry,
+    wal::decode_labels,
+    CountMinSketch, Error, Expiry, ExpiryAction, FilterContainer, FilterGroup, FilterKind,
+    FilterSnapshot, FilterStore, HyperLogLog, Shadow, Storage,
+};
+
+/// Extension of the filter files in a data directory.
+pub(crate) const FILTER_EXTENSION: &str = "bloom";
+/// Extension of the files of memory-mapped filters.
+const MAPPED_EXTENSION: &str = "mmap";
+/// Extension of the sketch files in a data directory.
+const SKETCH_EXTENSION: &str = "cms";
+/// Extension of the HyperLogLog files in a data directory.
+const CARDINALITY_EXTENSION: &str = "hll";
+/// Extension of the files holding the labels of a filter, as a JSON object, as saved before the
+/// labels were saved with the rest of its [metadata](FilterMetadata).
+const LABELS_EXTENSION: &str = "labels";
+/// Extension of the files holding the [metadata](FilterMetadata) of a filter.
+pub(crate) const METADATA_EXTENSION: &str = "meta";
+/// Name and extension of the file holding the API keys, as a JSON array.
+const API_KEYS_FILE: (&str, &str) = ("api_keys", "json");
+/// Name and extension of the file holding the groups, as a JSON object of their members.
+const GROUPS_FILE: (&str, &str) = ("groups", "json");
+
+/// What a filter file leaves out of a filter, saved next to it as a JSON object: its identity,
+/// expiry, shadow, labels and transform, and, once it is evicted, the groups it was a member of.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct FilterMetadata {
+    id: String,
+    created_at: u64,
+    generation: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expiry: Option<SavedExpiry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    idle_timeout_seconds: Option<u64>,
+    /// Name of the shadow filter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shadow: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    labels: BTreeMap<String, String>,
+    /// WASM module of the transform, in base64.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transform: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) groups: Vec<String>,
+}
+
+/// The expiry of a filter, with the time it elapses at in seconds since the Unix epoch, as the
+/// [`Instant`] of an [`Expiry`] is only meaningful to the process.
+#[derive(Debug, Deserialize, Serialize)]
+struct SavedExpiry {
+    ttl_seconds: u64,
+    action: ExpiryAction,
+    expires_at: u64,
+}
+
+impl FilterMetadata {
+    /// Copies the metadata of a filter, a member of `groups`.
+    pub(crate) fn of(container: &FilterContainer, groups: Vec<String>) -> Self {
+        let (now, unix_now) = (Instant::now(), unix_time(SystemTime::now()));
+        let expiry = container.expiry.map(|expiry| SavedExpiry {
+            ttl_seconds: expiry.ttl.as_secs(),
+            action: expiry.action,
+            expires_at: unix_now + expiry.expires_at.saturating_duration_since(now).as_secs(),
+        });
+        #[cfg(feature = "wasm")]
+        let transform = container
+            .transform
+            .as_ref()
+            .map(|transform| BASE64.encode(transform.wasm()));
+        #[cfg(not(feature = "wasm"))]
+        let transform = None;
+        FilterMetadata {
+            id: container.id.clone(),
+            created_at: container.created_at,
+            generation: container.generation,
+            expiry,
+            idle_timeout_seconds: container.idle_timeout.map(|timeout| timeout.as_secs()),
+            shadow: container
+                .shadow
+                .as_ref()
+                .map(|shadow| shadow.filter.clone()),
+            labels: container.labels.clone(),
+            transform,
+            groups,
+        }
+    }
+
+    /// Sets the metadata of a filter; the shadow and groups are left to the caller, as the filters
+    /// they name may not be loaded yet.
+    pub(crate) fn apply(&self, container: &mut FilterContainer) -> Result<(), Error> {
+        container.id.clone_from(&self.id);
+        container.created_at = self.created_at;
+        container.generation = self.generation;
+        container.expiry = match &self.expiry {
+            Some(saved) => {
+                let (now, unix_now) = (Instant::now(), unix_time(SystemTime::now()));
+                let remaining = Duration::from_secs(saved.expires_at.saturating_sub(unix_now));
+                Some(Expiry {
+                    ttl: Duration::from_secs(saved.ttl_seconds),
+                    action: saved.action,
+                    expires_at: now.checked_add(remaining).unwrap_or(now),
+                })
+            }
+            None => None,
+        };
+        container.idle_timeout = self.idle_timeout_seconds.map(Duration::from_secs);
+        container.labels.clone_from(&self.labels);
+        #[cfg(feature = "wasm")]
+        {
+            container.transform = match &self.transform {
+                Some(wasm) => {
+                    let invalid = |reason: String| {
+                        Error::InvalidParameters(format!("Invalid transform module: {reason}"))
+                    };
+                    let wasm = BASE64.decode(wasm).map_err(|e| invalid(e.to_string()))?;
+                    Some(crate::Transform::new(&wasm).map_err(invalid)?)
+                }
+                None => None,
+            };
+        }
+        Ok(())
+    }
+
+    /// Sets the shadow filter of the filter of `entry`, unless it is missing.
+    pub(crate) fn apply_shadow(&self, store: &FilterStore, entry: &FilterEntry) {
+        if let Some(shadow) = self.shadow.as_ref().filter(|s| store.resident(s).is_some()) {
+            entry.write().shadow = Some(Shadow::new(shadow.clone()));
+        }
+    }
+
+    /// Encodes the metadata as a JSON object.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+
+    /// Decodes metadata encoded by [`FilterMetadata::encode`].
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes)
+            .map_err(|error| Error::InvalidParameters(format!("Invalid filter metadata: {error}")))
+    }
+}
+
+/// Returns a time in seconds since the Unix epoch.
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl FilterStore {
+    /// Saves every filter to `dir`, as one [filter file](FilterSnapshot::encode) per filter,
+    /// named after the filter (e.g. `login_attempts.bloom`).
+    ///
+    /// Each file is written to a temporary file first and then renamed, so a crash never leaves a
+    /// partially written filter behind. Files of filters no longer in the store are removed, and
+    /// so are the segments of the [write-ahead log](FilterStore::start_log) the files supersede.
+    ///
+    /// Memory-mapped filters are not copied: their files in the
+    /// [data directory](FilterStore::data_dir) are flushed instead. Sketches are saved alongside,
+    /// as one [sketch file](CountMinSketch::encode) per sketch (e.g. `page_views.cms`), and so are
+    /// HyperLogLogs (e.g. `unique_visitors.hll`). The rest of a filter (its id, creation time,
+    /// generation, expiry, shadow, labels and transform) is saved next to it (e.g.
+    /// `login_attempts.meta`), the groups to `groups.json`, and the
+    /// [API keys](FilterStore::create_api_key) to `api_keys.json`. Each [namespace](FilterStore::create_namespace) is
+    /// saved the same way, to `namespaces/<name>`.
+    ///
+    /// Returns the number of filters saved, in all namespaces.
+    pub fn save(&self, dir: &Path) -> io::Result<usize> {
+        fs::create_dir_all(dir)?;
+        // Every operation logged before the new segment is reflected in the files written below.
+        // Counting and stable filters are copied at the cut itself, as replaying an insert or a
+        // removal they already reflect would skew their counters; so are all sketches and
+        // HyperLogLogs.
+        let (segment, mut counting, sketches, hlls, keys) = {
+            let db = self.filters.read();
+            let locked = lock_counting(&db);
+            let sketches = self.sketches.read();
+            let hlls = self.cardinality.read();
+            let keys = self.api_keys.read();
+            let segment = self.journal.rotate()?;
+            let sketches: Vec<CountMinSketch> = sketches.values().cloned().collect();
+            let hlls: Vec<HyperLogLog> = hlls.values().cloned().collect();
+            let keys: Vec<_> = keys.values().cloned().collect();
+            let counting: HashMap<String, FilterSnapshot> = match segment {
+                Some(_) => locked
+                    .iter()
+                    .map(|(name, c)| (name.to_string(), to_snapshot(c)))
+                    .collect(),
+                None => HashMap::new(),
+            };
+            (segment, counting, sketches, hlls, keys)
+        };
+
+        let mut saved = HashSet::new();
+        let mut mapped = 0;
+        let mut metadata = Vec::new();
+        for info in self.list() {
+            // The filter may have been deleted since it was listed
+            let Some(entry) = self.resident(&info.name) else {
+                continue;
+            };
+            let container = entry.read();
+            let filter_metadata = FilterMetadata::of(&container, Vec::new());
+            metadata.push((filter_metadata.encode(), info.name.clone()));
+            let snapshot = match counting.remove(&info.name) {
+                Some(snapshot) => snapshot,
+                None if container.filter.storage() == Storage::Mmap => {
+                    container.filter.flush()?;
+                    mapped += 1;
+                    continue;
+                }
+                None => to_snapshot(&container),
+            };
+            drop(container);
+            write_snapshot(dir, &info.name, &snapshot)?;
+            saved.insert(info.name);
+        }
+
+        for path in files(dir, FILTER_EXTENSION)? {
+            if !file_name(&path).is_some_and(|name| saved.contains(name)) {
+                fs::remove_file(&path)?;
+            }
+        }
+        let sketches = sketches
+            .into_iter()
+            .map(|sketch| (sketch.encode(), sketch.name));
+        write_files(dir, SKETCH_EXTENSION, sketches)?;
+        let hlls = hlls.into_iter().map(|hll| (hll.encode(), hll.name));
+        write_files(dir, CARDINALITY_EXTENSION, hlls)?;
+        write_files(dir, METADATA_EXTENSION, metadata.into_iter())?;
+        write_files(dir, LABELS_EXTENSION, std::iter::empty())?;
+        let (keys_name, keys_extension) = API_KEYS_FILE;
+        if !keys.is_empty() {
+            write_file(dir, keys_name, keys_extension, &encode_keys(&keys))?;
+        } else if keys_path(dir).exists() {
+            fs::remove_file(keys_path(dir))?;
+        }
+        let groups: BTreeMap<String, Vec<String>> = self
+            .list_groups()
+            .into_iter()
+            .map(|group| (group.name, group.filters))
+            .collect();
+        let (groups_name, groups_extension) = GROUPS_FILE;
+        if !groups.is_empty() {
+            let bytes = serde_json::to_vec(&groups).unwrap_or_default();
+            write_file(dir, groups_name, groups_extension, &bytes)?;
+        } else if groups_path(dir).exists() {
+            fs::remove_file(groups_path(dir))?;
+        }
+        if let Some(data_dir) = &self.data_dir {
+            // Under the lock, so that the file of a filter being created is not taken as stale
+            let db = self.filters.read();
+            for path in files(data_dir, MAPPED_EXTENSION)? {
+                let live = file_name(&path)
+                    .and_then(|name| db.get(name))
+                    .is_some_and(|c| c.read().filter.storage() == Storage::Mmap);
+                if !live {
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+        if let Some(segment) = segment {
+            self.journal.compact(segment)?;
+        }
+        Ok(saved.len() + mapped + self.save_namespaces(dir)?)
+    }
+
+    /// Loads the filters, sketches and HyperLogLogs saved to `dir` by [`FilterStore::save`], replacing those of
+    /// the same name, maps the memory-mapped filters of the [data directory](FilterStore::data_dir), and
+    /// replays the operations of the write-ahead log since. A missing directory holds no filters.
+    ///
+    /// Returns the number of filters after recovery, in all namespaces. Fails on the first file that cannot be read
+    /// or decoded, so that a damaged data directory is noticed rather than silently dropped.
+    pub fn load(&self, dir: &Path) -> io::Result<usize> {
+        if !dir.exists() {
+            return Ok(0);
+        }
+
+        let snapshots = files(dir, FILTER_EXTENSION)?.into_iter().map(|path| {
+            let snapshot = FilterSnapshot::decode(&fs::read(&path)?);
+            Ok::<_, io::Error>((path, snapshot))
+        });
+        // Mapped last: they are never older than a snapshot of the same name
+        let mapped = match &self.data_dir {
+            Some(data_dir) if data_dir.exists() => files(data_dir, MAPPED_EXTENSION)?,
+            _ => Vec::new(),
+        }
+        .into_iter()
+        .map(|path| {
+            let snapshot = FilterSnapshot::open_mapped(&path);
+            Ok((path, snapshot))
+        });
+
+        for loaded in snapshots.chain(mapped) {
+            let (path, snapshot) = loaded?;
+            let invalid = |error: crate::Error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {error}", path.display()),
+                )
+            };
+            let Some(name) = file_name(&path) else {
+                continue;
+            };
+            self.restore(name, snapshot.map_err(invalid)?)
+                .map_err(invalid)?;
+        }
+        for (name, labels) in read_files(dir, LABELS_EXTENSION, |_, bytes| decode_labels(bytes))? {
+            if let Some(entry) = self.resident(&name) {
+                entry.write().labels = labels;
+            }
+        }
+        let metadata = read_files(dir, METADATA_EXTENSION, |name, bytes| {
+            let metadata = FilterMetadata::decode(bytes)?;
+            if let Some(entry) = self.resident(name) {
+                metadata.apply(&mut entry.write())?;
+            }
+            Ok(metadata)
+        })?;
+        // Once every filter is loaded, as the shadow of a filter is another
+        for (name, metadata) in metadata {
+            if let Some(entry) = self.resident(&name) {
+                metadata.apply_shadow(self, &entry);
+            }
+        }
+        let groups_path = groups_path(dir);
+        if groups_path.exists() {
+            let groups: BTreeMap<String, Vec<String>> =
+                serde_json::from_slice(&fs::read(&groups_path)?).map_err(|error| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: {error}", groups_path.display()),
+                    )
+                })?;
+            let mut loaded = self.groups.write();
+            for (name, mut filters) in groups {
+                filters.retain(|filter| self.resident(filter).is_some());
+                loaded.insert(name.clone(), FilterGroup { name, filters });
+            }
+        }
+        for (name, sketch) in read_files(dir, SKETCH_EXTENSION, CountMinSketch::decode)? {
+            self.sketches.write().insert(name, sketch);
+        }
+        for (name, hll) in read_files(dir, CARDINALITY_EXTENSION, HyperLogLog::decode)? {
+            self.cardinality.write().insert(name, hll);
+        }
+        let keys_path = keys_path(dir);
+        if keys_path.exists() {
+            let keys = decode_keys(&fs::read(&keys_path)?).map_err(|error| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {error}", keys_path.display()),
+                )
+            })?;
+            let mut loaded = self.api_keys.write();
+            for key in keys {
+                loaded.insert(key.id.clone(), key);
+            }
+        }
+        self.replay(dir)?;
+        self.index_evicted();
+        // The journals of the items of the filters keeping one
+        if let Some(data_dir) = self.data_dir.as_ref().filter(|dir| dir.exists()) {
+            for path in files(data_dir, JOURNAL_EXTENSION)? {
+                if let Some(name) = file_name(&path) {
+                    self.open_journal(name)?;
+                }
+            }
+        }
+        self.enforce_memory_budget();
+        Ok(self.filters.read().len() + self.load_namespaces(dir)?)
+    }
+}
+
+/// Locks the counting and stable filters for reading, in the order of their names, so that their
+/// copies reflect exactly the operations logged while they are locked: replaying an operation
+/// twice would skew their counters.
+pub(crate) fn lock_counting(
+    filters: &HashMap<String, FilterEntry>,
+) -> Vec<(&str, RwLockReadGuard<'_, FilterContainer>)> {
+    let mut names: Vec<&String> = filters.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let container = filters[name].read();
+            matches!(
+                container.filter.kind(),
+                FilterKind::Counting | FilterKind::Stable
+            )
+            .then_some((name.as_str(), container))
+        })
+        .collect()
+}
+
+/// Copies a filter for saving it.
+pub(crate) fn to_snapshot(container: &FilterContainer) -> FilterSnapshot {
+    FilterSnapshot {
+        item_count: container.capacity,
+        creation_mode: container.creation_mode,
+        filter: container.filter.clone(),
+    }
+}
+
+/// Writes a filter file named after the filter, through a temporary file.
+pub(crate) fn write_snapshot(dir: &Path, name: &str, snapshot: &FilterSnapshot) -> io::Result<()> {
+    write_file(dir, name, FILTER_EXTENSION, &snapshot.encode())
+}
+
+/// Writes the metadata file of a filter, through a temporary file.
+pub(crate) fn write_metadata(dir: &Path, name: &str, metadata: &FilterMetadata) -> io::Result<()> {
+    write_file(dir, name, METADATA_EXTENSION, &metadata.encode())
+}
+
+/// Writes the file `name.extension`, through a temporary file.
+fn write_file(dir: &Path, name: &str, extension: &str, bytes: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{name}.{extension}"));
+    let temporary = path.with_extension(format!("{extension}.tmp"));
+    fs::write(&temporary, bytes)?;
+    fs::rename(&temporary, &path)
+}
+
+/// Writes the files `name.extension` of the given contents, and removes the other files with the
+/// extension.
+fn write_files(
+    dir: &Path,
+    extension: &str,
+    contents: impl Iterator<Item = (Vec<u8>, String)>,
+) -> io::Result<()> {
+    let mut saved = HashSet::new();
+    for (bytes, name) in contents {
+        write_file(dir, &name, extension, &bytes)?;
+        saved.insert(name);
+    }
+    for path in files(dir, extension)? {
+        if !file_name(&path).is_some_and(|name| saved.contains(name)) {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the files with the given extension, named after what they hold.
+fn read_files<T>(
+    dir: &Path,
+    extension: &str,
+    decode: impl Fn(&str, &[u8]) -> Result<T, crate::Error>,
+) -> io::Result<Vec<(String, T)>> {
+    let mut decoded = Vec::new();
+    for path in files(dir, extension)? {
+        let Some(name) = file_name(&path) else {
+            continue;
+        };
+        let value = decode(name, &fs::read(&path)?).map_err(|error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {error}", path.display()),
+            )
+        })?;
+        decoded.push((name.to_string(), value));
+    }
+    Ok(decoded)
+}
+
+/// Returns the path of the file holding the API keys.
+fn keys_path(dir: &Path) -> PathBuf {
+    let (name, extension) = API_KEYS_FILE;
+    dir.join(format!("{name}.{extension}"))
+}
+
+/// Returns the path of the file holding the groups.
+fn groups_path(dir: &Path) -> PathBuf {
+    let (name, extension) = GROUPS_FILE;
+    dir.join(format!("{name}.{extension}"))
+}
+
+/// Returns the path of the filter file of a filter.
+pub(crate) fn snapshot_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.{FILTER_EXTENSION}"))
+}
+
+/// Returns the path of the metadata file of a filter.
+pub(crate) fn metadata_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.{METADATA_EXTENSION}"))
+}
+
+/// Returns the path of the file of a memory-mapped filter.
+pub(crate) fn mapped_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.{MAPPED_EXTENSION}"))
+}
+
+/// Returns the name of the filter saved in a filter file.
+pub(crate) fn file_name(path: &Path) -> Option<&str> {
+    path.file_stem().and_then(|stem| stem.to_str())
+}
+
+/// Lists the files with the given extension in a data directory.
+pub(crate) fn files(dir: &Path, extension: &str) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == extension) {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
@@ -0,0 +1,128 @@
+use parking_lot::Mutex;
+use wasmi::{Config, Engine, Linker, Memory, Module, Store, TypedFunc};
+
+/// Fuel available to a single call of a transform, bounding the work a module can do per item.
+const TRANSFORM_FUEL: u64 = 10_000_000;
+
+/// A WASM module transforming (or rejecting) items before they reach a filter.
+///
+/// The module must export:
+///
+/// * `memory` - its linear memory,
+/// * `alloc(len: i32) -> i32` - returning the address of `len` writable bytes for the input item,
+/// * `transform(ptr: i32, len: i32) -> i64` - returning the transformed item as `ptr << 32 | len`,
+///   or a negative value to reject the item.
+///
+/// Modules may be given either in the binary or in the text format. Each call runs with a bounded
+/// amount of fuel, so a misbehaving module fails the call instead of blocking the service.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::Transform;
+///
+/// // Accepts every item unchanged
+/// let transform = Transform::new(br#"(module
+///     (memory (export "memory") 1)
+///     (func (export "alloc") (param i32) (result i32) i32.const 0)
+///     (func (export "transform") (param i32 i32) (result i64)
+///         local.get 1
+///         i64.extend_i32_u))"#)
+/// .unwrap();
+///
+/// assert_eq!(transform.apply(b"item").unwrap().as_deref(), Some(&b"item"[..]));
+/// ```
+pub struct Transform {
+    /// The module, as given, to save it with the filter.
+    wasm: Vec<u8>,
+    instance: Mutex<TransformInstance>,
+}
+
+struct TransformInstance {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    transform: TypedFunc<(i32, i32), i64>,
+}
+
+impl Transform {
+    /// Compiles and instantiates a transform module.
+    pub fn new(wasm: &[u8]) -> Result<Self, String> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+
+        let module = Module::new(&engine, wasm).map_err(|e| e.to_string())?;
+        let mut store = Store::new(&engine, ());
+        store.set_fuel(TRANSFORM_FUEL).map_err(|e| e.to_string())?;
+        let instance = Linker::new(&engine)
+            .instantiate_and_start(&mut store, &module)
+            .map_err(|e| e.to_string())?;
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or("The module does not export 'memory'")?;
+        let alloc = instance
+            .get_typed_func(&store, "alloc")
+            .map_err(|e| format!("Invalid export 'alloc': {e}"))?;
+        let transform = instance
+            .get_typed_func(&store, "transform")
+            .map_err(|e| format!("Invalid export 'transform': {e}"))?;
+
+        Ok(Transform {
+            wasm: wasm.to_vec(),
+            instance: Mutex::new(TransformInstance {
+                store,
+                memory,
+                alloc,
+                transform,
+            }),
+        })
+    }
+
+    /// Returns the size of the module the transform was created from.
+    pub fn size_bytes(&self) -> usize {
+        self.wasm.len()
+    }
+
+    /// Returns the module the transform was created from, as given.
+    pub fn wasm(&self) -> &[u8] {
+        &self.wasm
+    }
+
+    /// Transforms an item, returning `None` if the module rejects it.
+    pub fn apply(&self, item: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let mut guard = self.instance.lock();
+        let instance = &mut *guard;
+        instance
+            .store
+            .set_fuel(TRANSFORM_FUEL)
+            .map_err(|e| e.to_string())?;
+
+        let len = i32::try_from(item.len()).map_err(|_| "The item is too large".to_string())?;
+        let ptr = instance
+            .alloc
+            .call(&mut instance.store, len)
+            .map_err(|e| e.to_string())?;
+        instance
+            .memory
+            .write(&mut instance.store, ptr as u32 as usize, item)
+            .map_err(|e| e.to_string())?;
+
+        let result = instance
+            .transform
+            .call(&mut instance.store, (ptr, len))
+            .map_err(|e| e.to_string())?;
+        if result < 0 {
+            return Ok(None);
+        }
+
+        let (ptr, len) = ((result >> 32) as usize, (result & 0xFFFF_FFFF) as usize);
+        let output = instance
+            .memory
+            .data(&instance.store)
+            .get(ptr..ptr + len)
+            .ok_or("The transformed item is out of bounds")?;
+        Ok(Some(output.to_vec()))
+    }
+}
@@ -0,0 +1,264 @@
+use std::{io, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{tcp::OwnedWriteHalf, TcpStream},
+    sync::{broadcast::error::RecvError, mpsc},
+};
+
+use crate::{Error, FilterEvent, SharedState};
+
+/// Delay before the first attempt to reconnect, doubled after each failed attempt.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest time connecting to the broker (and authenticating) may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A message broker the [events](FilterEvent) of the filters are published to, as JSON.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::EventSink;
+///
+/// let sink = EventSink::parse("nats://s3cr3t@nats.internal", "bloomsrv.events").unwrap();
+/// assert_eq!(
+///     sink,
+///     EventSink::Nats {
+///         address: "nats.internal:4222".to_string(),
+///         subject: "bloomsrv.events".to_string(),
+///         token: Some("s3cr3t".to_string()),
+///     }
+/// );
+/// assert!(EventSink::parse("amqp://rabbit.internal", "bloomsrv").is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventSink {
+    /// A NATS server, publishing to a subject, authenticated by a token if any.
+    Nats {
+        address: String,
+        subject: String,
+        token: Option<String>,
+    },
+    /// A Redis server, publishing to a pub/sub channel, authenticated by a password if any.
+    Redis {
+        address: String,
+        channel: String,
+        password: Option<String>,
+    },
+}
+
+impl EventSink {
+    /// Parses the URL of a broker, `nats://[<token>@]<host>[:<port>]` or
+    /// `redis://[[<username>]:<password>@]<host>[:<port>]`, publishing to the subject or channel
+    /// `channel`.
+    pub fn parse(url: &str, channel: &str) -> Result<Self, Error> {
+        let invalid = |reason: &str| {
+            Error::InvalidParameters(format!("Invalid broker URL '{url}': {reason}"))
+        };
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| invalid("expected nats:// or redis://"))?;
+        let rest = rest.trim_end_matches('/');
+        let (credentials, host) = match rest.rsplit_once('@') {
+            Some((credentials, host)) => (Some(credentials), host),
+            None => (None, rest),
+        };
+        if host.is_empty() {
+            return Err(invalid("no host"));
+        }
+        if channel.is_empty() || channel.contains(char::is_whitespace) {
+            return Err(Error::InvalidParameters(format!(
+                "Invalid subject or channel '{channel}'"
+            )));
+        }
+        let address = |port: u16| match host.contains(':') {
+            true => host.to_string(),
+            false => format!("{host}:{port}"),
+        };
+        match scheme {
+            "nats" => Ok(EventSink::Nats {
+                address: address(4222),
+                subject: channel.to_string(),
+                token: credentials.map(str::to_string),
+            }),
+            "redis" => Ok(EventSink::Redis {
+                address: address(6379),
+                channel: channel.to_string(),
+                password: credentials
+                    .map(|c| c.split_once(':').map_or(c, |(_, password)| password))
+                    .map(str::to_string),
+            }),
+            _ => Err(invalid("expected nats:// or redis://")),
+        }
+    }
+
+    fn address(&self) -> &str {
+        match self {
+            EventSink::Nats { address, .. } | EventSink::Redis { address, .. } => address,
+        }
+    }
+
+    /// Publishes the events of the filters of `state` (and of its namespaces), forever.
+    ///
+    /// Connections lost are logged, and made again after a delay, doubling up to 30 seconds.
+    /// While disconnected, the last events are kept for when the connection is made again; as
+    /// many events as [subscribers](crate::FilterStore::subscribe) keep, after which the oldest
+    /// are dropped, the number dropped being logged. The broker is thus never waited for by the
+    /// requests changing the filters.
+    pub async fn publish(&self, state: SharedState) {
+        let mut events = state.subscribe();
+        let mut delay = MIN_RECONNECT_DELAY;
+        loop {
+            match tokio::time::timeout(CONNECT_TIMEOUT, self.connect()).await {
+                Ok(Ok(connection)) => {
+                    tracing::info!(broker = self.address(), "Publishing the filter events");
+                    delay = MIN_RECONNECT_DELAY;
+                    let error = self.forward(connection, &mut events).await;
+                    tracing::warn!(broker = self.address(), %error, "Lost the connection to the broker");
+                }
+                Ok(Err(error)) => {
+                    tracing::warn!(broker = self.address(), %error, "Cannot connect to the broker");
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        broker = self.address(),
+                        "Timed out connecting to the broker"
+                    );
+                }
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    /// Connects and authenticates to the broker, returning the lines it sends from now on, and
+    /// the half of the connection to write to.
+    async fn connect(&self) -> io::Result<(mpsc::Receiver<String>, OwnedWriteHalf)> {
+        let stream = TcpStream::connect(self.address()).await?;
+        stream.set_nodelay(true)?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        match self {
+            EventSink::Nats { token, .. } => {
+                // The server greets with its INFO, and answers the PING once connected
+                reader.read_line(&mut line).await?;
+                if !line.starts_with("INFO") {
+                    return Err(protocol_error(&line));
+                }
+                let mut options = serde_json::json!({
+                    "verbose": false,
+                    "pedantic": false,
+                    "name": "bloomsrv",
+                    "lang": "rust",
+                    "version": env!("CARGO_PKG_VERSION"),
+                });
+                if let Some(token) = token {
+                    options["auth_token"] = token.clone().into();
+                }
+                writer
+                    .write_all(format!("CONNECT {options}\r\nPING\r\n").as_bytes())
+                    .await?;
+                line.clear();
+                reader.read_line(&mut line).await?;
+                if !line.starts_with("PONG") {
+                    return Err(protocol_error(&line));
+                }
+            }
+            EventSink::Redis { password, .. } => {
+                if let Some(password) = password {
+                    writer
+                        .write_all(&resp_command(&[b"AUTH", password.as_bytes()]))
+                        .await?;
+                    reader.read_line(&mut line).await?;
+                    if !line.starts_with("+OK") {
+                        return Err(protocol_error(&line));
+                    }
+                }
+            }
+        }
+
+        // Read by a task of its own, as reading a line cannot be interrupted by an event
+        let (lines, received) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                let mut line = String::new();
+                match reader.read_line(&mut line).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) if lines.send(line).await.is_err() => break,
+                    Ok(_) => {}
+                }
+            }
+        });
+        Ok((received, writer))
+    }
+
+    /// Publishes the events received until the connection fails, returning why.
+    async fn forward(
+        &self,
+        (mut lines, mut writer): (mpsc::Receiver<String>, OwnedWriteHalf),
+        events: &mut tokio::sync::broadcast::Receiver<FilterEvent>,
+    ) -> io::Error {
+        loop {
+            let frame = tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => self.frame(&event),
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed, "Dropped filter events, the broker cannot keep up");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return io::ErrorKind::BrokenPipe.into(),
+                },
+                line = lines.recv() => match line {
+                    // Keep-alives of NATS
+                    Some(line) if line.starts_with("PING") => b"PONG\r\n".to_vec(),
+                    Some(line) if line.starts_with('-') => return protocol_error(&line),
+                    // Acknowledgements, e.g. the number of subscribers of a Redis channel
+                    Some(_) => continue,
+                    None => return io::ErrorKind::ConnectionReset.into(),
+                },
+            };
+            if let Err(error) = writer.write_all(&frame).await {
+                return error;
+            }
+        }
+    }
+
+    /// Encodes the command publishing an event.
+    fn frame(&self, event: &FilterEvent) -> Vec<u8> {
+        let payload = serde_json::to_vec(event).unwrap_or_default();
+        match self {
+            EventSink::Nats { subject, .. } => {
+                let mut frame = format!("PUB {subject} {}\r\n", payload.len()).into_bytes();
+                frame.extend_from_slice(&payload);
+                frame.extend_from_slice(b"\r\n");
+                frame
+            }
+            EventSink::Redis { channel, .. } => {
+                resp_command(&[b"PUBLISH", channel.as_bytes(), &payload])
+            }
+        }
+    }
+}
+
+/// Encodes a Redis command, as an array of bulk strings.
+fn resp_command(arguments: &[&[u8]]) -> Vec<u8> {
+    let mut command = format!("*{}\r\n", arguments.len()).into_bytes();
+    for argument in arguments {
+        command.extend_from_slice(format!("${}\r\n", argument.len()).as_bytes());
+        command.extend_from_slice(argument);
+        command.extend_from_slice(b"\r\n");
+    }
+    command
+}
+
+fn protocol_error(line: &str) -> io::Error {
+    let line = line.trim_end();
+    let reason = if line.is_empty() {
+        "the broker closed the connection"
+    } else {
+        line
+    };
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
@@ -0,0 +1,239 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::{BloomFilter, CreationMode, Error};
+
+/// Default false positive rate of a recent filter, over its whole window.
+const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+/// Default number of generations the window is divided into.
+const DEFAULT_SLOTS: usize = 10;
+
+/// Parameters of a recent filter to be created.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::RecentSpec;
+///
+/// // Card numbers seen within the last 10 minutes
+/// let spec = RecentSpec {
+///     name: "cards".to_string(),
+///     window_seconds: 600,
+///     item_count: 100_000,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Parameters of a recent filter to be created.")
+)]
+pub struct RecentSpec {
+    pub name: String,
+    /// Length of the window; may be omitted if `rotation_seconds` is given.
+    #[serde(default)]
+    pub window_seconds: u64,
+    /// Interval between rotations, i.e. the length of one generation (default
+    /// `window_seconds / slots`); the window then spans `slots` rotations.
+    pub rotation_seconds: Option<u64>,
+    /// Expected number of distinct items within one window.
+    pub item_count: usize,
+    /// False positive rate over the whole window (default 0.01).
+    pub false_positive_rate: Option<f64>,
+    /// Generations the window is divided into (default 10).
+    pub slots: Option<usize>,
+}
+
+/// Summary of a recent filter, as listed by [`FilterStore::list_recent`](crate::FilterStore::list_recent).
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Summary of a recent filter.")
+)]
+pub struct RecentInfo {
+    pub name: String,
+    pub window_seconds: u64,
+    pub item_count: usize,
+    pub false_positive_rate: f64,
+    pub slots: usize,
+    /// Interval between rotations.
+    pub rotation_seconds: f64,
+}
+
+/// A filter answering whether an item has been seen within a sliding time window.
+///
+/// The window is divided into generations, each recording the items seen during its part of the
+/// window in its own Bloom filter; the oldest generation is cleared and reused as time passes.
+/// Items are recorded in the newest generation and looked up in all of them. An item seen within
+/// the window is always remembered, and forgotten at most one generation after it leaves the
+/// window.
+///
+/// Generations rotate lazily, as items are seen; [`RecentFilter::rotate`] clears those that have
+/// left the window ahead of time, so that filters receiving few items do not keep stale bits.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{RecentFilter, RecentSpec};
+/// use std::time::{Duration, Instant};
+///
+/// let recent = RecentFilter::new(&RecentSpec {
+///     name: "cards".to_string(),
+///     window_seconds: 60,
+///     item_count: 1000,
+///     ..Default::default()
+/// })
+/// .unwrap();
+///
+/// let now = Instant::now();
+/// assert!(!recent.seen(b"4111", now));
+/// assert!(recent.seen(b"4111", now + Duration::from_secs(30)));
+/// assert!(!recent.contains(b"4111", now + Duration::from_secs(120)));
+/// ```
+pub struct RecentFilter {
+    pub name: String,
+    pub window: Duration,
+    pub item_count: usize,
+    pub false_positive_rate: f64,
+    pub slots: usize,
+    created_at: Instant,
+    generations: Mutex<Vec<Generation>>,
+}
+
+/// The items seen during one part of the window.
+struct Generation {
+    /// Index of the part of the window (since the creation of the filter) recorded by this generation.
+    epoch: u64,
+    filter: BloomFilter,
+}
+
+impl RecentFilter {
+    /// Validates a specification and creates the corresponding, empty recent filter.
+    pub fn new(spec: &RecentSpec) -> Result<Self, Error> {
+        let invalid = |reason: &str| Err(Error::InvalidParameters(reason.to_string()));
+        let false_positive_rate = spec
+            .false_positive_rate
+            .unwrap_or(DEFAULT_FALSE_POSITIVE_RATE);
+        let slots = spec.slots.unwrap_or(DEFAULT_SLOTS);
+
+        if !(1..=100).contains(&slots) {
+            return invalid("slots must be between 1 and 100");
+        }
+        let window_seconds = match spec.rotation_seconds {
+            Some(0) => return invalid("rotation_seconds must be greater than 0"),
+            Some(rotation) => {
+                let window = rotation.saturating_mul(slots as u64);
+                if spec.window_seconds != 0 && spec.window_seconds != window {
+                    return invalid("window_seconds must be rotation_seconds times slots");
+                }
+                window
+            }
+            None => spec.window_seconds,
+        };
+        if window_seconds == 0 {
+            return invalid("window_seconds must be greater than 0");
+        }
+        if spec.item_count == 0 {
+            return invalid("item_count must be greater than 0");
+        }
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return invalid("false_positive_rate must be between 0 and 1");
+        }
+
+        // A lookup asks every generation, so their false positive rates add up
+        let generation_count = slots + 1;
+        let generation_rate = false_positive_rate / generation_count as f64;
+        let generation_items = spec.item_count.div_ceil(slots);
+        let generation_filter = BloomFilter::new(
+            generation_items,
+            CreationMode::FalsePositiveRate(generation_rate),
+        )?;
+
+        Ok(RecentFilter {
+            name: spec.name.clone(),
+            window: Duration::from_secs(window_seconds),
+            item_count: spec.item_count,
+            false_positive_rate,
+            slots,
+            created_at: Instant::now(),
+            generations: Mutex::new(
+                (0..generation_count)
+                    .map(|_| Generation {
+                        epoch: 0,
+                        filter: generation_filter.clone(),
+                    })
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Returns `true` if the item may have been seen within the window before `now`.
+    pub fn contains(&self, item: &[u8], now: Instant) -> bool {
+        let current = self.epoch(now);
+        self.generations
+            .lock()
+            .iter()
+            .any(|g| self.is_live(g, current) && g.filter.contains(item))
+    }
+
+    /// Records the item as seen at `now`, returning `true` if it may have been seen within the
+    /// window before.
+    pub fn seen(&self, item: &[u8], now: Instant) -> bool {
+        let current = self.epoch(now);
+        let mut generations = self.generations.lock();
+        let seen = generations
+            .iter()
+            .any(|g| self.is_live(g, current) && g.filter.contains(item));
+
+        let index = (current % generations.len() as u64) as usize;
+        let generation = &mut generations[index];
+        if generation.epoch != current {
+            generation.epoch = current;
+            generation.filter.clear();
+        }
+        generation.filter.insert(item);
+        seen
+    }
+
+    /// Clears the generations that no longer record part of the window ending at `now`,
+    /// returning how many were cleared.
+    pub fn rotate(&self, now: Instant) -> usize {
+        let current = self.epoch(now);
+        let mut cleared = 0;
+        for generation in self.generations.lock().iter_mut() {
+            if !self.is_live(generation, current) && generation.filter.set_bits() > 0 {
+                generation.filter.clear();
+                cleared += 1;
+            }
+        }
+        cleared
+    }
+
+    /// Returns the memory used by the bits of the generations, in bytes.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.generations
+            .lock()
+            .iter()
+            .map(|generation| generation.filter.memory_usage_bytes())
+            .sum()
+    }
+
+    /// Returns the interval between rotations, i.e. the length of one generation.
+    pub fn rotation(&self) -> Duration {
+        self.window / self.slots as u32
+    }
+
+    /// Returns the index of the generation recording the items seen at `now`.
+    fn epoch(&self, now: Instant) -> u64 {
+        let generation_length = (self.window.as_nanos() / self.slots as u128).max(1);
+        (now.saturating_duration_since(self.created_at).as_nanos() / generation_length) as u64
+    }
+
+    /// Returns `true` if a generation still records part of the window ending at epoch `current`.
+    fn is_live(&self, generation: &Generation, current: u64) -> bool {
+        generation.epoch + self.slots as u64 >= current
+    }
+}
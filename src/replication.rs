@@ -0,0 +1,360 @@
+use axum::body::{Body, Bytes};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+
+use crate::{
+    auth::encode_keys,
+    persist::{lock_counting, to_snapshot},
+    wal::{encode_record, next_record, Operation},
+    FilterSnapshot, FilterStore, SharedState, Storage,
+};
+
+/// Identifies a replication stream.
+const STREAM_MAGIC: &[u8; 8] = b"BLOOMREP";
+/// Version of the stream format.
+const STREAM_VERSION: u32 = 1;
+/// Size of the stream header, in bytes.
+const STREAM_HEADER_LEN: usize = 12;
+
+/// Interval between the keep-alives of a stream, sent while no operation is.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// Time without anything received after which a replica takes its primary as lost.
+const PRIMARY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Delay before the first attempt to reconnect, doubled after each failed attempt.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Largest batch of operations sent in one chunk of a stream, in bytes.
+const MAX_CHUNK_BYTES: usize = 1 << 16;
+
+/// An empty record: the first ends the copy of the store, and the later ones are keep-alives.
+const EMPTY_RECORD: [u8; 12] = [0; 12];
+
+impl FilterStore {
+    /// Sends the records of the operations setting a copy of the filters (with their labels),
+    /// sketches, HyperLogLogs and API keys of the store to `send`, and returns the operations
+    /// recorded from the start of the copy on; `None` if `send` fails.
+    ///
+    /// As for [`FilterStore::save`], the operations recorded during the copy may be reflected in
+    /// it already, and replaying them changes nothing, but for counting and stable filters,
+    /// sketches, HyperLogLogs and keys, which are copied as the operations start being recorded.
+    pub(crate) fn replicate(
+        &self,
+        mut send: impl FnMut(Vec<u8>) -> bool,
+    ) -> Option<broadcast::Receiver<Arc<[u8]>>> {
+        let (feed, counting, sketches, hlls, keys) = {
+            let db = self.filters.read();
+            let locked = lock_counting(&db);
+            let sketches = self.sketches.read();
+            let hlls = self.cardinality.read();
+            let keys = self.api_keys.read();
+            let counting: HashMap<String, FilterSnapshot> = locked
+                .iter()
+                .map(|(name, c)| (name.to_string(), to_snapshot(c)))
+                .collect();
+            (
+                self.journal.subscribe(),
+                counting,
+                sketches.values().cloned().collect::<Vec<_>>(),
+                hlls.values().cloned().collect::<Vec<_>>(),
+                keys.values().cloned().collect::<Vec<_>>(),
+            )
+        };
+
+        let record = |operation: Operation<'_>| encode_record(&operation.encode());
+        let mut counting = counting;
+        for info in self.list() {
+            let snapshot = match counting.remove(&info.name) {
+                Some(snapshot) => snapshot,
+                None => {
+                    // The filter may have been deleted since it was listed
+                    let Some(entry) = self.resident(&info.name) else {
+                        continue;
+                    };
+                    let snapshot = to_snapshot(&entry.read());
+                    snapshot
+                }
+            };
+            let name = &info.name;
+            let snapshot = snapshot.encode();
+            if !send(record(Operation::Put { name, snapshot })) {
+                return None;
+            }
+            if !info.labels.is_empty() {
+                let labels = info.labels.clone();
+                if !send(record(Operation::Label { name, labels })) {
+                    return None;
+                }
+            }
+        }
+        for sketch in &sketches {
+            let name = &sketch.name;
+            if !send(record(Operation::PutSketch {
+                name,
+                sketch: sketch.encode(),
+            })) {
+                return None;
+            }
+        }
+        for hll in &hlls {
+            let registers = hll.registers();
+            if !send(record(Operation::PutCardinality {
+                name: &hll.name,
+                registers,
+            })) {
+                return None;
+            }
+        }
+        if !keys.is_empty()
+            && !send(record(Operation::CreateKey {
+                name: "",
+                key: encode_keys(&keys),
+            }))
+        {
+            return None;
+        }
+        Some(feed)
+    }
+
+    /// Applies an operation received from the primary. Memory-mapped filters are kept in memory,
+    /// as the files of the filters being synced would clash with those of the filters they
+    /// replace.
+    pub(crate) fn apply_replicated(&self, operation: Operation<'_>) {
+        let operation = match operation {
+            Operation::Create {
+                name,
+                item_count,
+                creation_mode,
+                kind,
+                rotating,
+                stable,
+                storage,
+                scheme,
+                seed,
+            } => Operation::Create {
+                name,
+                item_count,
+                creation_mode,
+                storage: match storage {
+                    Storage::Mmap => Storage::Memory,
+                    storage => storage,
+                },
+                kind,
+                rotating,
+                stable,
+                scheme,
+                seed,
+            },
+            operation => operation,
+        };
+        self.apply_operation(operation);
+    }
+
+    /// Replaces everything replicated by the store with what `synced` holds.
+    pub(crate) fn replace_with(&self, synced: FilterStore) {
+        *self.filters.write() = synced.filters.into_inner();
+        *self.sketches.write() = synced.sketches.into_inner();
+        *self.cardinality.write() = synced.cardinality.into_inner();
+        *self.api_keys.write() = synced.api_keys.into_inner();
+    }
+}
+
+/// Streams the changes of the default namespace of `state` to a replica: a copy of everything
+/// replicated, then the operations as they are recorded, as [records](encode_record) after a
+/// header.
+///
+/// The stream ends if the replica falls too far behind, so that it reconnects and syncs again.
+pub(crate) fn replication_stream(state: SharedState) -> Body {
+    let (chunks, received) = mpsc::channel::<Bytes>(16);
+    tokio::spawn(async move {
+        let mut header = STREAM_MAGIC.to_vec();
+        header.extend_from_slice(&STREAM_VERSION.to_le_bytes());
+        if chunks.send(header.into()).await.is_err() {
+            return;
+        }
+        // Copied in a blocking task, as encoding large filters takes a while
+        let copying = chunks.clone();
+        let copy = tokio::task::spawn_blocking(move || {
+            state.replicate(|record| copying.blocking_send(record.into()).is_ok())
+        });
+        let Ok(Some(mut feed)) = copy.await else {
+            return;
+        };
+        let mut chunk = EMPTY_RECORD.to_vec();
+        loop {
+            if chunks
+                .send(std::mem::take(&mut chunk).into())
+                .await
+                .is_err()
+            {
+                return;
+            }
+            let operation = match tokio::time::timeout(KEEP_ALIVE_INTERVAL, feed.recv()).await {
+                Ok(Ok(operation)) => operation,
+                Ok(Err(RecvError::Lagged(missed))) => {
+                    tracing::warn!(
+                        missed,
+                        "A replica fell behind, ending its stream to sync it again"
+                    );
+                    return;
+                }
+                Ok(Err(RecvError::Closed)) => return,
+                Err(_) => {
+                    chunk = EMPTY_RECORD.to_vec();
+                    continue;
+                }
+            };
+            // Batched with the operations recorded meanwhile
+            chunk = encode_record(&operation);
+            while chunk.len() < MAX_CHUNK_BYTES {
+                match feed.try_recv() {
+                    Ok(operation) => chunk.extend_from_slice(&encode_record(&operation)),
+                    Err(_) => break,
+                }
+            }
+        }
+    });
+    let chunks = futures_util::stream::unfold(received, |mut received| async move {
+        let chunk = received.recv().await?;
+        Some((Ok::<_, io::Error>(chunk), received))
+    });
+    Body::from_stream(chunks)
+}
+
+/// Follows a primary: syncs the default namespace of a store with that of the primary, and then
+/// applies its changes as they are made.
+#[derive(Clone, Debug)]
+pub struct Replication {
+    /// URL of the primary, e.g. `http://primary:3000`.
+    pub primary: String,
+    /// Admin key (or API key of the admin scope) of the primary, if it requires credentials.
+    pub key: Option<String>,
+}
+
+impl Replication {
+    /// Follows the primary until the store is promoted, i.e. until it is no longer
+    /// [read-only](FilterStore::read_only).
+    ///
+    /// The store is synced again each time it connects, after a delay doubling up to 30 seconds
+    /// when connecting fails; the primary is taken as lost when it sends nothing for 60 seconds.
+    pub async fn follow(&self, state: SharedState) {
+        let client = reqwest::Client::new();
+        let url = format!("{}/admin/replication", self.primary.trim_end_matches('/'));
+        let mut delay = MIN_RECONNECT_DELAY;
+        while state.read_only.load(Ordering::Relaxed) {
+            match self.sync(&client, &url, &state).await {
+                Ok(true) => {
+                    tracing::info!(
+                        primary = self.primary,
+                        "Promoted, no longer following the primary"
+                    );
+                    return;
+                }
+                // Synced before the connection was lost, so reconnect soon
+                Ok(false) => delay = MIN_RECONNECT_DELAY,
+                Err(error) => {
+                    tracing::warn!(primary = self.primary, %error, "Cannot follow the primary");
+                }
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    /// Syncs with the primary and applies its changes, until the connection is lost (returning
+    /// `false`) or the store is promoted (returning `true`).
+    async fn sync(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        state: &SharedState,
+    ) -> Result<bool, String> {
+        let mut request = client.get(url);
+        if let Some(key) = &self.key {
+            request = request.bearer_auth(key);
+        }
+        let mut response = request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| e.to_string())?;
+
+        let mut buffer = Vec::new();
+        let mut synced: Option<FilterStore> = Some(FilterStore::default());
+        let mut header = false;
+        loop {
+            let chunk = tokio::time::timeout(PRIMARY_TIMEOUT, response.chunk())
+                .await
+                .map_err(|_| "The primary sent nothing for 60 seconds".to_string())?
+                .map_err(|e| e.to_string())?;
+            let Some(chunk) = chunk else {
+                return match synced {
+                    Some(_) => Err("The primary closed the stream while syncing".to_string()),
+                    None => Ok(false),
+                };
+            };
+            if !state.read_only.load(Ordering::Relaxed) {
+                return Ok(true);
+            }
+            buffer.extend_from_slice(&chunk);
+            if !header {
+                if buffer.len() < STREAM_HEADER_LEN {
+                    continue;
+                }
+                let version = u32::from_le_bytes(buffer[8..12].try_into().unwrap());
+                if &buffer[..8] != STREAM_MAGIC || version != STREAM_VERSION {
+                    return Err(format!(
+                        "{url} is not a replication stream of version {STREAM_VERSION}"
+                    ));
+                }
+                buffer.drain(..STREAM_HEADER_LEN);
+                header = true;
+            }
+
+            let mut records = buffer.as_slice();
+            let mut inserted = false;
+            while let Some(len) = records.get(..4) {
+                let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+                if records.len() < len + 12 {
+                    break;
+                }
+                if records[..12] == EMPTY_RECORD {
+                    if let Some(copy) = synced.take() {
+                        state.replace_with(copy);
+                        state.replica_synced.store(true, Ordering::Relaxed);
+                        state.notify_inserted();
+                        tracing::info!(
+                            primary = self.primary,
+                            filters = state.filters.read().len(),
+                            "Synced with the primary"
+                        );
+                    }
+                    records = &records[12..];
+                    continue;
+                }
+                let (operation, rest) = next_record(records)
+                    .ok_or_else(|| "Received a malformed operation".to_string())?;
+                inserted |= matches!(operation, Operation::Insert { .. } | Operation::Put { .. });
+                match &synced {
+                    Some(copy) => copy.apply_replicated(operation),
+                    None => state.apply_replicated(operation),
+                }
+                records = rest;
+            }
+            let consumed = buffer.len() - records.len();
+            buffer.drain(..consumed);
+            if inserted && synced.is_none() {
+                state.notify_inserted();
+            }
+        }
+    }
+}
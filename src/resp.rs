@@ -0,0 +1,456 @@
+use std::{io, net::SocketAddr, sync::atomic::Ordering};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    server::{cluster_error, read_only_error},
+    Access, Error, FilterKind, FilterSpec, KeyScope, SharedConfig, SharedState,
+};
+
+/// Number of elements beyond which a command is rejected, e.g. the items of a `BF.MADD`.
+const MAX_ARGUMENTS: usize = 1 << 20;
+
+/// Capacity of the filters created by `BF.ADD` and `BF.MADD`, as by RedisBloom.
+const DEFAULT_CAPACITY: usize = 100;
+
+/// False positive rate of the filters created by `BF.ADD` and `BF.MADD`, as by RedisBloom.
+const DEFAULT_ERROR_RATE: f64 = 0.01;
+
+/// Growth of the scalable filters, which is fixed: each slice is twice as large as the one
+/// before.
+const EXPANSION: i64 = 2;
+
+/// Serves the filters of `state` over the Redis protocol (RESP2), implementing the commands of
+/// RedisBloom, so that applications using a Redis Bloom client can use the service unchanged:
+/// `BF.RESERVE`, `BF.ADD`, `BF.MADD`, `BF.EXISTS` and `BF.MEXISTS`.
+///
+/// The keys are the names of the filters of the default namespace. The credentials, throttling
+/// and limits of `config` apply as they do to the HTTP API; clients send their API key (or
+/// bearer token) with `AUTH`.
+///
+/// Runs until accepting a connection fails.
+pub async fn serve_resp(
+    listener: TcpListener,
+    state: SharedState,
+    config: SharedConfig,
+) -> io::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let (state, config) = (state.clone(), config.clone());
+        tokio::spawn(async move {
+            if let Err(error) = serve_connection(stream, peer, state, config).await {
+                tracing::debug!(peer = %peer, %error, "RESP connection closed");
+            }
+        });
+    }
+}
+
+/// A reply to a command.
+#[derive(Debug)]
+enum Reply {
+    Status(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Reply::Status(status) => out.extend_from_slice(format!("+{status}\r\n").as_bytes()),
+            // Errors are a single line, starting with their kind (e.g. `ERR`)
+            Reply::Error(error) => {
+                let line = error.replace(['\r', '\n'], " ");
+                out.extend_from_slice(format!("-{line}\r\n").as_bytes());
+            }
+            Reply::Integer(value) => out.extend_from_slice(format!(":{value}\r\n").as_bytes()),
+            Reply::Bulk(value) => {
+                out.extend_from_slice(format!("${}\r\n", value.len()).as_bytes());
+                out.extend_from_slice(value);
+                out.extend_from_slice(b"\r\n");
+            }
+            Reply::Array(replies) => {
+                out.extend_from_slice(format!("*{}\r\n", replies.len()).as_bytes());
+                for reply in replies {
+                    reply.write(out);
+                }
+            }
+        }
+    }
+
+    fn error(error: &Error) -> Reply {
+        let kind = match error {
+            Error::Unauthorized(_) => "WRONGPASS",
+            Error::Forbidden(_) => "NOPERM",
+            Error::Unavailable(_) => "LOADING",
+            _ => "ERR",
+        };
+        Reply::Error(format!("{kind} {error}"))
+    }
+}
+
+/// State of a connection: who is authenticated on it, if anyone.
+struct Connection {
+    peer: SocketAddr,
+    state: SharedState,
+    config: SharedConfig,
+    access: Option<Access>,
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    state: SharedState,
+    config: SharedConfig,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut connection = Connection {
+        peer,
+        state,
+        config,
+        access: None,
+    };
+    let mut out = Vec::new();
+    loop {
+        // The limits may change on reload of the configuration
+        let max_length = connection.config.get().limits.max_body_bytes;
+        let command = match read_command(&mut reader, max_length).await {
+            Ok(Some(command)) => command,
+            Ok(None) => return Ok(()),
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                Reply::Error(format!("ERR Protocol error: {error}")).write(&mut out);
+                writer.write_all(&out).await?;
+                return Err(error);
+            }
+            Err(error) => return Err(error),
+        };
+        if command.is_empty() {
+            continue;
+        }
+        let quit = command[0].eq_ignore_ascii_case(b"QUIT");
+        connection.execute(&command).write(&mut out);
+        // Pipelined commands are answered together
+        if reader.buffer().is_empty() || quit {
+            writer.write_all(&out).await?;
+            out.clear();
+        }
+        if quit {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads a command, either an array of bulk strings or an inline command (words separated by
+/// spaces), returning `None` at the end of the stream.
+///
+/// Arguments longer than `max_length` bytes are rejected, as malformed commands are, with an
+/// error of kind [`io::ErrorKind::InvalidData`].
+async fn read_command<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_length: usize,
+) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = read_line(reader, max_length).await? else {
+        return Ok(None);
+    };
+    let Some(count) = line.strip_prefix(b"*") else {
+        let words = line
+            .split(|byte| byte.is_ascii_whitespace())
+            .filter(|word| !word.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect();
+        return Ok(Some(words));
+    };
+    let count = parse_length(count, MAX_ARGUMENTS, "multibulk length")?;
+    let mut arguments = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        let line = read_line(reader, max_length)
+            .await?
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        let Some(length) = line.strip_prefix(b"$") else {
+            return Err(invalid_data(format!(
+                "expected '$', got '{}'",
+                String::from_utf8_lossy(&line[..line.len().min(1)])
+            )));
+        };
+        let length = parse_length(length, max_length, "bulk length")?;
+        let mut argument = vec![0; length + 2];
+        reader.read_exact(&mut argument).await?;
+        if !argument.ends_with(b"\r\n") {
+            return Err(invalid_data(
+                "bulk string not terminated by CRLF".to_string(),
+            ));
+        }
+        argument.truncate(length);
+        arguments.push(argument);
+    }
+    Ok(Some(arguments))
+}
+
+/// Reads a line terminated by CRLF (or LF), without its terminator.
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    max_length: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    let read = (&mut *reader)
+        .take(max_length as u64 + 2)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(invalid_data("too big inline request".to_string()));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_length(digits: &[u8], max: usize, what: &str) -> io::Result<usize> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse::<usize>().ok())
+        .filter(|length| *length <= max)
+        .ok_or_else(|| invalid_data(format!("invalid {what}")))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Connection {
+    fn execute(&mut self, command: &[Vec<u8>]) -> Reply {
+        let name = String::from_utf8_lossy(&command[0]).to_ascii_lowercase();
+        let arguments = &command[1..];
+        let config = self.config.get();
+
+        // Commands answered before authentication
+        match name.as_str() {
+            "auth" => return self.auth(arguments),
+            "quit" => return Reply::Status("OK"),
+            _ => {}
+        }
+        if config.requires_credentials() && self.access.is_none() {
+            return Reply::Error("NOAUTH Authentication required.".to_string());
+        }
+        if let Some(throttle) = &config.throttle {
+            let client = match &self.access {
+                Some(access) => access.caller.clone(),
+                None => self.peer.ip().to_string(),
+            };
+            if let Err(error) = throttle.acquire(&client) {
+                return Reply::error(&error);
+            }
+        }
+
+        let arity = |min: usize, exact: bool| {
+            let valid = if exact {
+                arguments.len() == min
+            } else {
+                arguments.len() >= min
+            };
+            valid.then_some(()).ok_or_else(|| {
+                Reply::Error(format!(
+                    "ERR wrong number of arguments for '{name}' command"
+                ))
+            })
+        };
+        let result = match name.as_str() {
+            "ping" => match arguments {
+                [] => Ok(Reply::Status("PONG")),
+                [message] => Ok(Reply::Bulk(message.clone())),
+                _ => arity(1, true).map(|()| Reply::Status("PONG")),
+            },
+            "echo" => arity(1, true).map(|()| Reply::Bulk(arguments[0].clone())),
+            "select" => arity(1, true).map(|()| match arguments[0].as_slice() {
+                b"0" => Reply::Status("OK"),
+                _ => Reply::Error("ERR DB index is out of range".to_string()),
+            }),
+            // Client names and library versions are accepted, and ignored
+            "client" => arity(1, false).map(|()| Reply::Status("OK")),
+            "command" => Ok(Reply::Array(Vec::new())),
+            "bf.reserve" => arity(3, false).and_then(|()| self.reserve(arguments)),
+            "bf.add" => arity(2, true).and_then(|()| self.add(arguments).map(single)),
+            "bf.madd" => arity(2, false).and_then(|()| self.add(arguments).map(Reply::Array)),
+            "bf.exists" => arity(2, true).and_then(|()| self.exists(arguments).map(single)),
+            "bf.mexists" => arity(2, false).and_then(|()| self.exists(arguments).map(Reply::Array)),
+            _ => Err(Reply::Error(format!(
+                "ERR unknown command '{}', with args beginning with: {}",
+                String::from_utf8_lossy(&command[0]),
+                arguments
+                    .iter()
+                    .map(|argument| format!("'{}' ", String::from_utf8_lossy(argument)))
+                    .collect::<String>()
+            ))),
+        };
+        result.unwrap_or_else(|error| error)
+    }
+
+    /// `AUTH [username] key`: the username, if any, is ignored.
+    fn auth(&mut self, arguments: &[Vec<u8>]) -> Reply {
+        let key = match arguments {
+            [key] | [_, key] => String::from_utf8_lossy(key).into_owned(),
+            _ => {
+                return Reply::Error("ERR wrong number of arguments for 'auth' command".to_string())
+            }
+        };
+        let config = self.config.get();
+        if !config.requires_credentials() {
+            return Reply::Error(
+                "ERR AUTH called without any password configured for the default user".to_string(),
+            );
+        }
+        match config.authenticate(&self.state, Some(&key)) {
+            Ok(access) => {
+                self.access = Some(access);
+                Reply::Status("OK")
+            }
+            Err(_) => {
+                self.access = None;
+                Reply::Error(
+                    "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+                )
+            }
+        }
+    }
+
+    /// Checks that the connection may make a request requiring `required` on the filter `key`,
+    /// returning its name.
+    fn check(&self, required: KeyScope, key: &[u8]) -> Result<String, Reply> {
+        if self.state.restoring.load(Ordering::Relaxed) {
+            return Err(Reply::error(&Error::Unavailable(
+                "The saved filters are being restored".to_string(),
+            )));
+        }
+        if required == KeyScope::ReadWrite && self.state.read_only.load(Ordering::Relaxed) {
+            return Err(Reply::Error(
+                "READONLY You can't write against a read only replica.".to_string(),
+            ));
+        }
+        if required == KeyScope::ReadWrite && self.state.read_only_mode.load(Ordering::Relaxed) {
+            return Err(Reply::error(&read_only_error()));
+        }
+        if required == KeyScope::ReadWrite && self.config.get().is_clustered() {
+            return Err(Reply::error(&cluster_error()));
+        }
+        let name = String::from_utf8(key.to_vec())
+            .map_err(|_| Reply::Error("ERR the key is not valid UTF-8".to_string()))?;
+        if let Some(access) = &self.access {
+            access
+                .check(required, None, Some(std::slice::from_ref(&name)))
+                .map_err(|error| Reply::error(&error))?;
+        }
+        Ok(name)
+    }
+
+    /// `BF.RESERVE key error_rate capacity [EXPANSION expansion] [NONSCALING]`
+    fn reserve(&self, arguments: &[Vec<u8>]) -> Result<Reply, Reply> {
+        let name = self.check(KeyScope::ReadWrite, &arguments[0])?;
+        let error_rate = std::str::from_utf8(&arguments[1])
+            .ok()
+            .and_then(|rate| rate.parse::<f64>().ok())
+            .filter(|rate| *rate > 0.0 && *rate < 1.0)
+            .ok_or_else(|| Reply::Error("ERR (0 < error rate range < 1)".to_string()))?;
+        let capacity = std::str::from_utf8(&arguments[2])
+            .ok()
+            .and_then(|capacity| capacity.parse::<usize>().ok())
+            .filter(|capacity| *capacity > 0)
+            .ok_or_else(|| Reply::Error("ERR (capacity should be larger than 0)".to_string()))?;
+        let mut kind = FilterKind::Scalable;
+        let mut options = arguments[3..].iter();
+        while let Some(option) = options.next() {
+            if option.eq_ignore_ascii_case(b"NONSCALING") {
+                kind = FilterKind::Standard;
+            } else if option.eq_ignore_ascii_case(b"EXPANSION") {
+                let expansion = options
+                    .next()
+                    .and_then(|value| std::str::from_utf8(value).ok())
+                    .and_then(|value| value.parse::<i64>().ok())
+                    .ok_or_else(|| Reply::Error("ERR bad expansion".to_string()))?;
+                if expansion != EXPANSION {
+                    return Err(Reply::Error(format!(
+                        "ERR only an expansion of {EXPANSION} is supported"
+                    )));
+                }
+            } else {
+                return Err(Reply::Error("ERR syntax error".to_string()));
+            }
+        }
+        let spec = FilterSpec {
+            name,
+            item_count: capacity,
+            false_positive_rate: Some(error_rate),
+            kind,
+            ..Default::default()
+        };
+        match self.state.create(spec) {
+            Ok(_) => Ok(Reply::Status("OK")),
+            Err(Error::FilterExists(_)) => Err(Reply::Error("ERR item exists".to_string())),
+            Err(error) => Err(Reply::error(&error)),
+        }
+    }
+
+    /// `BF.ADD key item` and `BF.MADD key item...`, creating a missing filter with the defaults
+    /// of RedisBloom: answers, for each item, 1 if it is definitely new, and 0 otherwise.
+    fn add(&self, arguments: &[Vec<u8>]) -> Result<Vec<Reply>, Reply> {
+        let (key, items) = (&arguments[0], &arguments[1..]);
+        let name = self.check(KeyScope::ReadWrite, key)?;
+        let inserted = match self.state.insert_batch(&name, items) {
+            Err(Error::FilterNotFound(_)) => {
+                let spec = FilterSpec {
+                    name: name.clone(),
+                    item_count: DEFAULT_CAPACITY,
+                    false_positive_rate: Some(DEFAULT_ERROR_RATE),
+                    kind: FilterKind::Scalable,
+                    ..Default::default()
+                };
+                match self.state.create(spec) {
+                    // Created concurrently since inserted
+                    Ok(_) | Err(Error::FilterExists(_)) => self.state.insert_batch(&name, items),
+                    Err(error) => Err(error),
+                }
+            }
+            inserted => inserted,
+        };
+        let replies = inserted
+            .map_err(|error| Reply::error(&error))?
+            .into_iter()
+            .map(|new| match new {
+                Some(new) => Reply::Integer(i64::from(new)),
+                None => Reply::Error(
+                    "ERR the item is rejected by the transform of the filter".to_string(),
+                ),
+            })
+            .collect();
+        Ok(replies)
+    }
+
+    /// `BF.EXISTS key item` and `BF.MEXISTS key item...`: answers, for each item, 1 if it may
+    /// have been inserted, and 0 otherwise; items of a missing filter are absent.
+    fn exists(&self, arguments: &[Vec<u8>]) -> Result<Vec<Reply>, Reply> {
+        let (key, items) = (&arguments[0], &arguments[1..]);
+        let name = self.check(KeyScope::Read, key)?;
+        let found = match self.state.contains_many(&name, items) {
+            Err(Error::FilterNotFound(_)) => vec![false; items.len()],
+            found => found.map_err(|error| Reply::error(&error))?,
+        };
+        Ok(found
+            .into_iter()
+            .map(|found| Reply::Integer(i64::from(found)))
+            .collect())
+    }
+}
+
+/// The reply of a command on a single item, e.g. `BF.ADD`, out of those of its multi-item form.
+fn single(replies: Vec<Reply>) -> Reply {
+    replies
+        .into_iter()
+        .next()
+        .unwrap_or(Reply::Array(Vec::new()))
+}
@@ -0,0 +1,5245 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, DefaultBodyLimit, Extension, FromRequest, FromRequestParts, Path, Query,
+        Request, State,
+    },
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{any, delete, get, post, put},
+    Router,
+};
+use http_body_util::BodyExt;
+use parking_lot::{Mutex, RwLock};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::broadcast::error::RecvError;
+use tower::ServiceExt;
+use tower_http::{compression::CompressionLayer, decompression::RequestDecompressionLayer};
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    IntoParams, OpenApi, ToSchema,
+};
+use uuid::Uuid;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+#[cfg(feature = "cluster")]
+use crate::{
+    cluster::{Command, Expiries},
+    Cluster, ClusterMember, ClusterStatus,
+};
+use crate::{
+    error::ErrorBody,
+    filter::{Decay, KindData},
+    jobs::{start_load, start_rebuild, JobInfo},
+    logging::timestamp,
+    replication::replication_stream,
+    shard::{ShardStatus, FORWARDED_HEADER},
+    Access, ApiKeyInfo, ApplyOptions, ApplyReport, AuditEntry, AuditLog, CardinalityInfo,
+    CardinalitySpec, CreationMode, Error, FilterDiff, FilterInfo, FilterKind, FilterOverlap,
+    FilterSnapshot, FilterSpec, FilterStats, FilterUsage, FullAction, GroupLookupMode, GroupSpec,
+    HashScheme, HashTrace, JwtValidator, KeyScope, KeySpec, LimiterDecision, LimiterInfo,
+    LimiterSpec, Manifest, MemoryReport, MemoryStats, NamespaceInfo, RecentInfo, RecentSpec,
+    ReplayProgress, ShadowReport, ShardRing, SharedState, SketchInfo, SketchSpec, StableInfo,
+    Storage, Throttle, UploadMode,
+};
+
+// --- API Request/Response Models ---
+
+#[derive(Serialize, ToSchema)]
+struct FilterResponse {
+    id: String,
+    name: String,
+    bit_count: u64,
+    hash_count: u32,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct MessageResponse {
+    pub(crate) message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct InsertResponse {
+    response: String,
+    /// Whether the item was certainly not in the filter before.
+    new: bool,
+    /// The hashes and bit positions of the item, if asked for with `debug`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debug: Option<HashTrace>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct LookupResponse {
+    /// Whether the item may have been inserted; `false` is certain.
+    contains: bool,
+    message: String,
+    /// The hashes and bit positions of the item, if asked for with `debug`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debug: Option<HashTrace>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CheckInsertResponse {
+    /// Whether the item may have been in the filter before it was inserted.
+    was_present: bool,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BatchInsertResponse {
+    /// Number of items inserted, i.e. not rejected by the transform of the filter.
+    inserted: usize,
+    /// For each item, whether it was certainly not in the filter before, or `null` if rejected.
+    new: Vec<Option<bool>>,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BatchLookupResponse {
+    /// For each item, whether it may have been inserted.
+    contains: Vec<bool>,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RemoveResponse {
+    /// Whether the item was in the filter, and is removed.
+    removed: bool,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct InsertIfAbsentResponse {
+    inserted: bool,
+    message: String,
+    /// The filter that may have seen the item, if it is not inserted.
+    found_in: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct IngestResponse {
+    message: String,
+    stats: IngestStats,
+}
+
+#[derive(Serialize, ToSchema)]
+struct WatchResponse {
+    contains: bool,
+    /// Whether the item did not appear before the timeout.
+    timed_out: bool,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct GroupLookupResponse {
+    /// The combined answer of the member filters.
+    contains: bool,
+    /// The answer of each member filter.
+    filters: BTreeMap<String, bool>,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SeenResponse {
+    /// Whether the item may have been seen within the window.
+    seen: bool,
+    message: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CardinalityInsertResponse {
+    /// Whether the item changed the estimate, i.e. was certainly not inserted before.
+    new: bool,
+    message: String,
+}
+
+/// The response of the estimates of sketches and cardinalities.
+#[derive(Serialize, ToSchema)]
+struct EstimateResponse {
+    estimate: u64,
+    message: String,
+}
+
+/// The data of the `lagged` event, sent to subscribers missing events.
+#[derive(Serialize)]
+struct LaggedEvent {
+    missed: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ProbeResponse {
+    /// `live`, `ready` or `not_ready`.
+    status: &'static str,
+    /// Why the service is not ready.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reasons: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct GroupListItem {
+    name: String,
+    filters: Vec<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GroupDeleteParams {
+    #[serde(default)]
+    keep_filters: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GroupLookupParams {
+    #[serde(default)]
+    mode: GroupLookupMode,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ShadowRequest {
+    filter: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LimiterCheckParams {
+    cost: Option<u64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SketchIncrementParams {
+    count: Option<u64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct NamespaceRequest {
+    name: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct FilterMergeRequest {
+    /// Names or ids of the filters to merge.
+    sources: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct FilterIntersectRequest {
+    /// Names or ids of the filters to intersect with.
+    sources: Vec<String>,
+    /// Name of a new filter to create from the intersection, instead of replacing the target.
+    into: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct FilterUpdateRequest {
+    /// Labels to set, or to remove when `null`.
+    #[serde(default)]
+    labels: BTreeMap<String, Option<String>>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct FilterRebuildRequest {
+    /// New expected number of items; that of the filter if absent.
+    item_count: Option<usize>,
+    false_positive_rate: Option<f64>,
+    hash_count: Option<u32>,
+    /// Items to insert into the rebuilt filter.
+    #[serde(default)]
+    items: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct FilterRebuildJobRequest {
+    /// New expected number of items; that of the filter if absent.
+    item_count: Option<usize>,
+    false_positive_rate: Option<f64>,
+    hash_count: Option<u32>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CardinalityMergeRequest {
+    sources: Vec<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UploadParams {
+    #[serde(default)]
+    mode: UploadMode,
+    #[serde(default)]
+    format: BitsFormat,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DownloadParams {
+    #[serde(default)]
+    format: BitsFormat,
+}
+
+#[derive(Clone, Copy, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct StreamParams {
+    #[serde(default)]
+    format: StreamFormat,
+    #[serde(default)]
+    encoding: ItemEncoding,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct EncodingParams {
+    #[serde(default)]
+    encoding: ItemEncoding,
+}
+
+/// Encoding of the items given as text, i.e. in JSON payloads, streams and query parameters.
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum ItemEncoding {
+    /// Items are their UTF-8 encoding.
+    #[default]
+    Utf8,
+    /// Items are base64-encoded bytes, for binary items.
+    Base64,
+}
+
+impl ItemEncoding {
+    /// Decodes the item at `index` (from 0) of a request.
+    fn decode(self, item: &[u8], index: usize) -> Result<Vec<u8>, Error> {
+        match self {
+            ItemEncoding::Utf8 => Ok(item.to_vec()),
+            ItemEncoding::Base64 => BASE64.decode(item).map_err(|_| {
+                Error::InvalidParameters(format!("Item {} is not valid base64", index + 1))
+            }),
+        }
+    }
+
+    /// Decodes the items of a JSON payload.
+    fn decode_all(self, items: &[String]) -> Result<Vec<Vec<u8>>, Error> {
+        items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| self.decode(item.as_bytes(), index))
+            .collect()
+    }
+
+    /// Decodes the items of a batch; those given as bytes are taken as they are.
+    fn decode_batch(self, items: Vec<BatchItem>) -> Result<Vec<Vec<u8>>, Error> {
+        items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| match (self, item) {
+                (_, BatchItem::Bytes(bytes)) | (ItemEncoding::Utf8, BatchItem::Text(bytes)) => {
+                    Ok(bytes)
+                }
+                (_, BatchItem::Text(text)) => self.decode(&text, index),
+            })
+            .collect()
+    }
+}
+
+/// An item of a batch: a string, decoded as given by the `encoding` parameter, or the bytes of a
+/// MessagePack `bin`.
+enum BatchItem {
+    Text(Vec<u8>),
+    Bytes(Vec<u8>),
+}
+
+impl<'de> Deserialize<'de> for BatchItem {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = BatchItem;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a string, or bytes")
+            }
+
+            fn visit_str<E>(self, text: &str) -> Result<BatchItem, E> {
+                Ok(BatchItem::Text(text.as_bytes().to_vec()))
+            }
+
+            fn visit_string<E>(self, text: String) -> Result<BatchItem, E> {
+                Ok(BatchItem::Text(text.into_bytes()))
+            }
+
+            fn visit_bytes<E>(self, bytes: &[u8]) -> Result<BatchItem, E> {
+                Ok(BatchItem::Bytes(bytes.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<BatchItem, E> {
+                Ok(BatchItem::Bytes(bytes))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Encoding of the items of a streaming ingest, one per line.
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum StreamFormat {
+    /// Each line is an item, as its raw bytes.
+    #[default]
+    Lines,
+    /// Each line is a JSON string.
+    Ndjson,
+}
+
+/// Statistics of a streaming ingest, as returned by `POST /filters/:name/items/stream`.
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub(crate) struct IngestStats {
+    /// Items read, i.e. non-empty lines.
+    items: usize,
+    /// Items that were definitely new to the filter.
+    new: usize,
+    /// Items rejected by the transform of the filter.
+    rejected: usize,
+    bytes: usize,
+    /// Times the filters were locked to insert a batch of items.
+    batches: usize,
+    elapsed_seconds: f64,
+}
+
+impl IngestStats {
+    /// Describes the progress of a filter rebuilt from its item journal since `started`.
+    pub(crate) fn replayed(progress: &ReplayProgress, started: Instant) -> Self {
+        IngestStats {
+            items: progress.items,
+            new: progress.new,
+            bytes: progress.bytes as usize,
+            elapsed_seconds: started.elapsed().as_secs_f64(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Serialization of the bits of a filter.
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum BitsFormat {
+    /// The filter files of `bloomsrv` (see [`FilterSnapshot`]).
+    #[default]
+    Native,
+    /// The serialized form of Guava's `BloomFilter`.
+    Guava,
+    /// The files of Python's `pybloom_live.BloomFilter`.
+    Pybloom,
+}
+
+/// A filter with its parameters, as returned by `GET /filters/:name/dump` (in JSON).
+#[derive(Serialize, Deserialize, ToSchema)]
+struct FilterDump {
+    name: String,
+    item_count: usize,
+    /// Absent for filters created with a fixed hash count.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    false_positive_rate: Option<f64>,
+    hash_count: u32,
+    bit_count: u64,
+    #[serde(default)]
+    scheme: HashScheme,
+    /// The seed of the hashes, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    /// The bits, in 64-bit little-endian words, encoded in base64.
+    bits: String,
+    /// The 8-bit counter of each bit of a counting filter, encoded in base64.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    counters: Option<String>,
+    /// The bits of each further slice of a scalable filter, like `bits`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    slices: Option<Vec<String>>,
+    /// The decrements of a stable filter, whose cells are in `counters`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stable: Option<Decay>,
+    /// The number of rotations of a rotating filter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rotations: Option<u64>,
+    /// The bits of the previous generation of a rotating filter, like `bits`, once it rotated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    previous: Option<String>,
+}
+
+impl FilterDump {
+    fn new(name: String, snapshot: FilterSnapshot) -> Self {
+        let filter = &snapshot.filter;
+        let false_positive_rate = match snapshot.creation_mode {
+            CreationMode::FalsePositiveRate(rate) => Some(rate),
+            CreationMode::HashCount(_) => None,
+        };
+        let encode = |words: &[u64]| {
+            let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+            BASE64.encode(bytes)
+        };
+        let slices = (filter.kind() == FilterKind::Scalable)
+            .then(|| filter.slices().iter().map(|s| encode(&s.bits())).collect());
+        FilterDump {
+            name,
+            item_count: snapshot.item_count,
+            false_positive_rate,
+            hash_count: filter.hash_count(),
+            bit_count: filter.bit_count(),
+            scheme: filter.scheme(),
+            seed: Some(filter.seed()).filter(|&seed| seed != 0),
+            bits: encode(&filter.bits()),
+            counters: filter.counters().map(|counters| BASE64.encode(counters)),
+            slices,
+            stable: filter.decay(),
+            rotations: filter.rotations(),
+            previous: filter.previous_generation().map(encode),
+        }
+    }
+
+    /// Rebuilds the snapshot, verifying that the parameters match each other and the bits.
+    fn into_snapshot(self) -> Result<FilterSnapshot, Error> {
+        let invalid = |reason: &str| Error::InvalidParameters(reason.to_string());
+        let creation_mode = match self.false_positive_rate {
+            Some(rate) => CreationMode::FalsePositiveRate(rate),
+            None => CreationMode::HashCount(self.hash_count),
+        };
+        let decode = |bits: &str| -> Result<Vec<u64>, Error> {
+            let bits = BASE64
+                .decode(bits)
+                .map_err(|_| invalid("The bits of the dump are not valid base64"))?;
+            if bits.len() % 8 != 0 {
+                return Err(invalid(
+                    "The bits of the filter do not match its declared size",
+                ));
+            }
+            Ok(bits
+                .chunks_exact(8)
+                .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+                .collect())
+        };
+        let words = decode(&self.bits)?;
+        let counters = self
+            .counters
+            .map(|counters| BASE64.decode(counters))
+            .transpose()
+            .map_err(|_| invalid("The counters of the dump are not valid base64"))?;
+        let extra = match (counters, self.slices, self.stable) {
+            (None, None, None) => KindData::None,
+            (Some(counters), None, None) => KindData::Counters(counters),
+            (Some(cells), None, Some(decay)) => KindData::Stable(cells, decay),
+            (None, Some(slices), None) => KindData::Slices(
+                slices
+                    .iter()
+                    .map(|bits| decode(bits))
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => return Err(invalid(
+                "The counters, slices and decrements of the dump do not match any kind of filter",
+            )),
+        };
+        let mut snapshot = FilterSnapshot::from_parts(
+            self.item_count,
+            creation_mode,
+            (self.bit_count, self.hash_count),
+            (self.scheme, self.seed.unwrap_or(0)),
+            words,
+            extra,
+        )?;
+        if let Some(rotations) = self.rotations {
+            let previous = self.previous.as_deref().map(decode).transpose()?;
+            snapshot.filter = snapshot.filter.restore_rotation(rotations, previous)?;
+        }
+        Ok(snapshot)
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum DumpFormat {
+    /// The filter and its parameters, with the bits in base64.
+    #[default]
+    Json,
+    /// The filter file format, as for `/filters/:name/bits`.
+    Binary,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DumpParams {
+    #[serde(default)]
+    format: DumpFormat,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ImportParams {
+    /// The name of the imported filter; required for binary dumps, which do not carry one.
+    name: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct InsertIfAbsentParams {
+    /// Comma-separated names of the filters checked besides the target filter.
+    #[serde(default)]
+    check: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DebugParams {
+    /// Include the hashes and bit positions of the item in the response.
+    #[serde(default, deserialize_with = "flag")]
+    debug: bool,
+}
+
+#[derive(Deserialize)]
+struct LookupParams {
+    /// The item to look up, instead of the request body.
+    item: Option<String>,
+    /// Encoding of `item`.
+    #[serde(default)]
+    encoding: ItemEncoding,
+    #[serde(flatten)]
+    debug: DebugParams,
+}
+
+/// Deserializes a query flag given as `1`/`0` or `true`/`false`.
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    match String::deserialize(deserializer)?.as_str() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        other => Err(serde::de::Error::custom(format!(
+            "expected 1, 0, true or false, found '{other}'"
+        ))),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListParams {
+    /// Lists only the filters whose name starts with this prefix.
+    name_prefix: Option<String>,
+    /// Lists only the filters with this label, given as `key=value`, or as `key` for any value.
+    label: Option<String>,
+    #[serde(default)]
+    sort: ListSort,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+/// Order of the filters in a list.
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum ListSort {
+    #[default]
+    Name,
+    /// Oldest first, then by name.
+    CreatedAt,
+}
+
+/// Header holding the number of filters matching a list, before pagination.
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct WatchParams {
+    timeout_seconds: Option<u64>,
+}
+
+/// A response sent over the WebSocket of a filter, as a text frame: the results of a request, as
+/// for its operation, or the error it failed with.
+#[derive(Serialize)]
+struct SocketResponse {
+    id: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new: Option<Vec<Option<bool>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contains: Option<Vec<Option<bool>>>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorBody>,
+}
+
+impl SocketResponse {
+    fn new(id: serde_json::Value) -> Self {
+        SocketResponse {
+            id,
+            new: None,
+            contains: None,
+            error: None,
+        }
+    }
+}
+
+/// A request sent over the WebSocket of a filter, as a text frame.
+#[derive(Deserialize)]
+struct SocketRequest {
+    /// Echoed in the response, to match responses to requests.
+    #[serde(default)]
+    id: serde_json::Value,
+    op: SocketOperation,
+    items: Vec<String>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SocketOperation {
+    Insert,
+    Contains,
+}
+
+impl SocketOperation {
+    /// Opcode of the operation in binary frames.
+    fn from_opcode(opcode: u8) -> Option<Self> {
+        match opcode {
+            1 => Some(SocketOperation::Insert),
+            2 => Some(SocketOperation::Contains),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct ListItem {
+    id: String,
+    name: String,
+    item_count: usize,
+    config: String,
+    storage: Storage,
+    kind: FilterKind,
+    scheme: HashScheme,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    slices: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stable: Option<StableInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on_full: Option<FullAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rotations: Option<u64>,
+    memory_usage_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl_remaining_seconds: Option<u64>,
+    created_at: u64,
+    generation: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    labels: BTreeMap<String, String>,
+    /// Whether the filter is evicted to disk by the memory budget, until it is next used.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    evicted: bool,
+}
+
+impl From<FilterInfo> for ListItem {
+    fn from(info: FilterInfo) -> Self {
+        let config = match info.creation_mode {
+            CreationMode::FalsePositiveRate(r) => format!("False positive rate: {}", r),
+            CreationMode::HashCount(h) => format!("Hash count: {}", h),
+        };
+        ListItem {
+            id: info.id,
+            name: info.name,
+            item_count: info.capacity,
+            config,
+            storage: info.storage,
+            kind: info.kind,
+            scheme: info.scheme,
+            seed: Some(info.seed).filter(|&seed| seed != 0),
+            slices: info.slice_count,
+            stable: info.stable,
+            on_full: info.on_full,
+            rotations: info.rotations,
+            memory_usage_bytes: info.memory_usage_bytes,
+            ttl_remaining_seconds: info.ttl_remaining_seconds,
+            created_at: info.created_at,
+            generation: info.generation,
+            labels: info.labels,
+            evicted: info.evicted,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct FilterDetails {
+    #[serde(flatten)]
+    filter: ListItem,
+    stats: FilterStats,
+    usage: FilterUsage,
+}
+
+#[derive(Serialize, ToSchema)]
+struct StatsResponse {
+    #[serde(flatten)]
+    stats: FilterStats,
+    /// Bytes of the bits and counters of the filter on the heap.
+    memory_usage_bytes: usize,
+    /// Bytes of the bits of the filter mapped from a file.
+    mapped_bytes: usize,
+    usage: FilterUsage,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct FixtureRequest {
+    #[serde(flatten)]
+    filter: FilterSpec,
+    items: usize,
+    #[serde(default)]
+    seed: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct FixtureResponse {
+    id: String,
+    name: String,
+    items: usize,
+    seed: u64,
+    sample: Vec<String>,
+    message: String,
+}
+
+// --- The App Factory ---
+
+/// Options of the REST API.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::AppConfig;
+///
+/// let config = AppConfig {
+///     dev_endpoints: true,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AppConfig {
+    /// Expose the development endpoints under `/dev` (e.g. synthetic filters for load tests).
+    pub dev_endpoints: bool,
+    /// Allow `?debug=1` on inserts and lookups, returning the hashes and bit positions of items.
+    pub debug_traces: bool,
+    /// Require an API key on every request but the health probes. This key has the
+    /// [admin](KeyScope::Admin) scope, and can mint the others.
+    pub admin_key: Option<String>,
+    /// Also accept the bearer tokens of an identity provider, as validated by it; requires a
+    /// credential on every request but the health probes, like [`AppConfig::admin_key`].
+    pub jwt: Option<Arc<JwtValidator>>,
+    /// Record every change requested of the service, with who requested it, and serve the
+    /// records at `GET /admin/audit`.
+    pub audit: Option<Arc<AuditLog>>,
+    /// Limit the requests of each client, identified by its credentials or else its IP address,
+    /// rejecting those beyond the limit with `429 Too Many Requests`.
+    pub throttle: Option<Arc<Throttle>>,
+    /// Limits on the size of the bodies of requests, and on their duration.
+    pub limits: RequestLimits,
+    /// Compress the responses to the clients accepting one of these algorithms, and decompress
+    /// the requests compressed with one of them; none by default.
+    pub compression: Vec<Compression>,
+    /// Allow browsers to call the service from the origins of the policy.
+    pub cors: Option<CorsPolicy>,
+    /// Reload the configuration on `POST /admin/reload`, e.g. from a configuration file.
+    pub reload: Option<Reloader>,
+    /// Commit the changes requested through the consensus of a cluster, which every node then
+    /// applies, and serve its membership under `/admin/cluster`.
+    #[cfg(feature = "cluster")]
+    pub cluster: Option<Cluster>,
+    /// Own only the filters of the default namespace the ring assigns to this node, proxying the
+    /// requests for the others to the nodes owning them.
+    pub shards: Option<Arc<ShardRing>>,
+    /// Allow filters to be loaded by the service from the sources starting with one of these
+    /// prefixes, e.g. `file:///var/lib/keys/` or `https://downloads.example.com/`; none by
+    /// default.
+    pub load_sources: Vec<String>,
+}
+
+/// Reloads the configuration of a running service, e.g. by reading its configuration file again
+/// and [replacing](SharedConfig::replace) the configuration of the application, and describes
+/// what was reloaded.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::Reloader;
+///
+/// let reload = Reloader::new(|| Ok("Nothing to reload".to_string()));
+/// assert_eq!(reload.reload().unwrap(), "Nothing to reload");
+/// ```
+#[derive(Clone)]
+pub struct Reloader(Arc<dyn Fn() -> Result<String, Error> + Send + Sync>);
+
+impl Reloader {
+    /// Creates a reloader calling `reload`.
+    pub fn new(reload: impl Fn() -> Result<String, Error> + Send + Sync + 'static) -> Self {
+        Reloader(Arc::new(reload))
+    }
+
+    /// Reloads the configuration.
+    pub fn reload(&self) -> Result<String, Error> {
+        (self.0)()
+    }
+}
+
+impl std::fmt::Debug for Reloader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Reloader")
+    }
+}
+
+/// The configuration of an application, which can be replaced while it serves requests: each
+/// request is served with the configuration as it was when the request arrived.
+///
+/// The routes, and the limits on the size of the bodies of requests, are those of the
+/// configuration the application was created with.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{create_app_with_shared_config, AppConfig, SharedConfig, SharedState};
+///
+/// let config = SharedConfig::new(AppConfig::default());
+/// let app = create_app_with_shared_config(SharedState::default(), config.clone());
+///
+/// // Require credentials from now on
+/// let mut changed = config.get();
+/// changed.admin_key = Some("change-me".to_string());
+/// config.replace(changed);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SharedConfig(Arc<RwLock<AppConfig>>);
+
+impl SharedConfig {
+    /// Shares the configuration `config`.
+    pub fn new(config: AppConfig) -> Self {
+        SharedConfig(Arc::new(RwLock::new(config)))
+    }
+
+    /// Returns the current configuration.
+    pub fn get(&self) -> AppConfig {
+        self.0.read().clone()
+    }
+
+    /// Replaces the configuration, for the requests arriving from now on.
+    pub fn replace(&self, config: AppConfig) {
+        *self.0.write() = config;
+    }
+}
+
+impl AppConfig {
+    /// Returns `true` if the changes are committed through a cluster.
+    pub(crate) fn is_clustered(&self) -> bool {
+        #[cfg(feature = "cluster")]
+        {
+            self.cluster.is_some()
+        }
+        #[cfg(not(feature = "cluster"))]
+        {
+            false
+        }
+    }
+
+    /// Returns `true` if requests must carry an API key or a bearer token.
+    pub(crate) fn requires_credentials(&self) -> bool {
+        self.admin_key.is_some() || self.jwt.is_some()
+    }
+
+    /// Resolves the credentials of a request: the admin key, an API key of `state`, or a bearer
+    /// token, failing with [`Error::Unauthorized`] if they are missing or invalid.
+    pub(crate) fn authenticate(
+        &self,
+        state: &SharedState,
+        key: Option<&str>,
+    ) -> Result<Access, Error> {
+        match key {
+            Some(key) if self.admin_key.as_deref() == Some(key) => {
+                Ok(Access::unrestricted("admin", KeyScope::Admin))
+            }
+            Some(key) => match (state.authenticate(key), &self.jwt) {
+                (Some(access), _) => Ok(access),
+                // API keys have no dots, and JSON Web Tokens two
+                (None, Some(jwt)) if key.contains('.') => jwt.validate(key),
+                (None, _) => Err(Error::Unauthorized(
+                    "Missing or invalid API key".to_string(),
+                )),
+            },
+            None => Err(Error::Unauthorized(
+                "Missing or invalid API key".to_string(),
+            )),
+        }
+    }
+}
+
+/// Limits on the size of the bodies of requests, and on their duration: requests beyond fail with
+/// `413 Payload Too Large` or `408 Request Timeout`.
+///
+/// Batch requests, uploads of filters and streams have limits of their own, larger than those of
+/// the other requests.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{AppConfig, RequestLimits};
+/// use std::time::Duration;
+///
+/// let config = AppConfig {
+///     limits: RequestLimits {
+///         max_body_bytes: 64 << 10,
+///         timeout: Duration::from_secs(5),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct RequestLimits {
+    /// Maximum size in bytes of the body of most requests, e.g. an item inserted or looked up
+    /// (2 MiB by default).
+    pub max_body_bytes: usize,
+    /// Maximum size in bytes of the body of batches of items, and of uploaded filters (1 GiB by
+    /// default). Streams of items have no limit, as they are never held in memory as a whole.
+    pub max_upload_bytes: usize,
+    /// Time given to most requests to complete, including receiving their body (30 seconds by
+    /// default). Requests watching for an item are bounded by their own timeout instead.
+    pub timeout: Duration,
+    /// Time given to batches of items, streams and uploads of filters to complete (10 minutes by
+    /// default).
+    pub upload_timeout: Duration,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_body_bytes: 2 << 20,
+            max_upload_bytes: 1 << 30,
+            timeout: Duration::from_secs(30),
+            upload_timeout: Duration::from_secs(600),
+        }
+    }
+}
+
+impl RequestLimits {
+    /// Returns the maximum size of the body of requests on `path`, and their timeout, if any.
+    fn of(&self, path: &str) -> (usize, Option<Duration>) {
+        if path.ends_with("/items/watch") {
+            (self.max_body_bytes, None)
+        } else if path.ends_with("/items/stream") {
+            (usize::MAX, Some(self.upload_timeout))
+        } else if is_upload(path) {
+            (self.max_upload_bytes, Some(self.upload_timeout))
+        } else {
+            (self.max_body_bytes, Some(self.timeout))
+        }
+    }
+}
+
+/// Returns `true` if requests on `path` carry batches of items or filters, and have the
+/// [upload limits](RequestLimits::max_upload_bytes).
+fn is_upload(path: &str) -> bool {
+    const UPLOADS: [&str; 6] = [
+        "/items/query",
+        "/items/batch",
+        "/rebuild",
+        "/bits",
+        "/bits/diff",
+        "/filters/import",
+    ];
+    UPLOADS.iter().any(|suffix| path.ends_with(suffix))
+}
+
+/// Algorithms compressing the bodies of responses and requests, e.g. large batches of items and
+/// lists of filters.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{AppConfig, Compression};
+///
+/// let config = AppConfig {
+///     compression: vec![Compression::Zstd, Compression::Gzip],
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    /// `gzip`, understood by nearly every client.
+    Gzip,
+    /// `zstd`, faster than gzip at a similar ratio.
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the `Content-Encoding` of the bodies compressed with the algorithm.
+    fn encoding(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+}
+
+/// Cross-origin resource sharing: the origins whose web pages may call the service from a browser,
+/// and with which methods and headers.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{AppConfig, CorsPolicy};
+///
+/// let config = AppConfig {
+///     cors: Some(CorsPolicy::new(vec!["https://dashboard.example.com".to_string()])),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug)]
+pub struct CorsPolicy {
+    /// Origins allowed, e.g. `https://dashboard.example.com`; `*` allows any.
+    pub origins: Vec<String>,
+    /// Methods allowed (by default, `GET`, `HEAD`, `POST`, `PUT`, `PATCH` and `DELETE`).
+    pub methods: Vec<String>,
+    /// Headers allowed (by default, those of the credentials, `Content-Type`, `If-Match` and
+    /// `X-Request-Id`).
+    pub headers: Vec<String>,
+    /// How long browsers may cache the answer to a preflight request (10 minutes by default).
+    pub max_age: Duration,
+}
+
+/// Headers of the responses that the scripts of allowed origins may read.
+const CORS_EXPOSED_HEADERS: &str = "x-request-id, retry-after, etag";
+
+impl CorsPolicy {
+    /// Creates a policy allowing the given origins, with the default methods and headers.
+    pub fn new(origins: Vec<String>) -> Self {
+        let methods = ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"];
+        let headers = [
+            "authorization",
+            "content-type",
+            "x-api-key",
+            "if-match",
+            REQUEST_ID_HEADER,
+        ];
+        CorsPolicy {
+            origins,
+            methods: methods.map(str::to_string).to_vec(),
+            headers: headers.map(str::to_string).to_vec(),
+            max_age: Duration::from_secs(600),
+        }
+    }
+
+    /// Returns `true` if the policy allows the origin `origin`.
+    fn allows(&self, origin: &str) -> bool {
+        self.origins.iter().any(|allowed| {
+            allowed == "*" || allowed.trim_end_matches('/').eq_ignore_ascii_case(origin)
+        })
+    }
+}
+
+/// Creates the main Axum application router with the defined routes.
+///
+/// This function is the entry point for both the `main` binary and
+/// integration tests.
+///
+/// # Arguments
+///
+/// * `state` - The shared state (`Arc<FilterStore>`) holding the filters and groups.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{create_app, SharedState};
+///
+/// // Initialize the empty state
+/// let state = SharedState::default();
+///
+/// // Create the router
+/// let app = create_app(state);
+///
+/// // The app is now ready to be passed to axum::serve or used in tests
+/// ```
+pub fn create_app(state: SharedState) -> Router {
+    create_app_with_config(state, AppConfig::default())
+}
+
+/// Creates the application router, with the options given in `config`.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{create_app_with_config, AppConfig, SharedState};
+///
+/// let config = AppConfig {
+///     dev_endpoints: true,
+///     debug_traces: false,
+///     admin_key: Some("change-me".to_string()),
+///     jwt: None,
+///     audit: None,
+///     throttle: None,
+///     limits: Default::default(),
+///     cors: None,
+///     reload: None,
+///     ..Default::default()
+/// };
+/// let app = create_app_with_config(SharedState::default(), config);
+/// ```
+pub fn create_app_with_config(state: SharedState, config: AppConfig) -> Router {
+    create_app_with_shared_config(state, SharedConfig::new(config))
+}
+
+/// Creates the application router, with the configuration shared by `config`, which can be
+/// replaced while the application runs.
+pub fn create_app_with_shared_config(state: SharedState, config: SharedConfig) -> Router {
+    #[cfg(feature = "cluster")]
+    if let Some(cluster) = &config.get().cluster {
+        cluster.serve(command_routes(state.clone(), config.clone()));
+    }
+
+    // Each version of the API is served under its own prefix, so that a breaking change lands in
+    // a new version beside the others. The unversioned paths are aliases of the first version,
+    // kept for the clients predating the versions
+    let options = config.get();
+    let v1 = v1_routes(&state, &options);
+    let router = Router::new()
+        .nest("/v1", v1.clone())
+        .merge(v1)
+        .route("/livez", get(livez))
+        .route("/readyz", get(readyz))
+        .route("/healthz", get(healthz))
+        .route("/openapi.json", get(openapi_document))
+        .route("/docs", get(docs))
+        .route("/ui", get(dashboard))
+        .layer(Extension(NamespaceRouters::default()))
+        .layer(middleware::from_fn(allow_origins))
+        .layer(middleware::from_fn(
+            move |mut request: Request, next: Next| {
+                request.extensions_mut().insert(config.get());
+                next.run(request)
+            },
+        ));
+    compress(router, &options.compression)
+        .layer(middleware::from_fn(access_log))
+        .with_state(state)
+}
+
+/// Adds the layers compressing the responses, and decompressing the requests, with the
+/// algorithms of `compression`; without any, the bodies are served and read as they are.
+///
+/// Request bodies are decompressed as they are read, so that streams of items are never held in
+/// memory as a whole, and the [limits](RequestLimits) apply to their decompressed size.
+fn compress(router: Router<SharedState>, compression: &[Compression]) -> Router<SharedState> {
+    if compression.is_empty() {
+        return router;
+    }
+    let gzip = compression.contains(&Compression::Gzip);
+    let zstd = compression.contains(&Compression::Zstd);
+    let accepted = compression.to_vec();
+    router
+        .layer(RequestDecompressionLayer::new().gzip(gzip).zstd(zstd))
+        .layer(CompressionLayer::new().gzip(gzip).zstd(zstd))
+        .layer(middleware::from_fn(move |request: Request, next: Next| {
+            let rejected = match request.headers().get(header::CONTENT_ENCODING) {
+                Some(encoding)
+                    if encoding != "identity"
+                        && !accepted
+                            .iter()
+                            .any(|algorithm| algorithm.encoding() == encoding) =>
+                {
+                    let encoding = String::from_utf8_lossy(encoding.as_bytes()).into_owned();
+                    Some(Error::UnsupportedEncoding(encoding))
+                }
+                _ => None,
+            };
+            async move {
+                match rejected {
+                    Some(error) => error.into_response(),
+                    None => next.run(request).await,
+                }
+            }
+        }))
+}
+
+/// Builds the routes of the first version of the API, served under `/v1`, with the middleware
+/// checking and recording their requests.
+fn v1_routes(state: &SharedState, config: &AppConfig) -> Router<SharedState> {
+    let router = store_routes(config)
+        .route("/namespaces", post(namespaces_create))
+        .route("/namespaces", get(namespaces_list))
+        .route("/namespaces/:namespace", delete(namespaces_delete))
+        .route("/namespaces/:namespace/*path", any(namespace_route))
+        .route("/events", get(events_stream))
+        .route("/admin/keys", post(keys_create))
+        .route("/admin/keys", get(keys_list))
+        .route("/admin/keys/:id", delete(keys_delete))
+        .route("/admin/audit", get(audit_list))
+        .route("/admin/reload", post(admin_reload))
+        .route("/admin/replication", get(admin_replication))
+        .route("/admin/promote", post(admin_promote))
+        .route("/admin/readonly", post(admin_read_only))
+        .route("/admin/shards", get(admin_shards))
+        .route("/admin/memory", get(admin_memory));
+
+    #[cfg(feature = "cluster")]
+    let router = router
+        .route("/admin/cluster", get(cluster_status))
+        .route("/admin/cluster/init", post(cluster_init))
+        .route("/admin/cluster/nodes", post(cluster_nodes_add))
+        .route("/admin/cluster/nodes/:id", delete(cluster_nodes_delete))
+        .route("/admin/cluster/raft/write", post(cluster_write))
+        .route("/admin/cluster/raft/:call", post(cluster_call))
+        .layer(middleware::from_fn(commit_changes));
+
+    router
+        .layer(middleware::from_fn(proxy_to_owner))
+        .layer(middleware::from_fn(limit_requests))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            reject_read_only_changes,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            reject_while_restoring,
+        ))
+        .layer(middleware::from_fn(throttle_clients))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_api_key,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), audit_changes))
+}
+
+/// Builds the routes of everything in a store, served for the default namespace at the root of a
+/// version, and for each namespace under its `/namespaces/<namespace>`.
+fn store_routes(config: &AppConfig) -> Router<SharedState> {
+    let upload_limit = DefaultBodyLimit::max(config.limits.max_upload_bytes);
+    let router = Router::new()
+        .route("/filters", post(filters_create))
+        .route("/filters", get(filters_list))
+        .route(
+            "/filters/:name",
+            get(filters_get).patch(filters_update).put(filters_put),
+        )
+        .route("/filters/:name", delete(filters_delete))
+        .route("/filters/:name/items", post(filter_insert))
+        .route("/filters/:name/items", get(filter_lookup))
+        .route("/filters/:name/items", delete(filter_remove))
+        .route("/filters/:name/items/watch", get(filter_watch))
+        .route("/filters/:name/ws", get(filter_socket))
+        .route("/filters/:name/items/:item", get(filter_lookup_path))
+        .route(
+            "/filters/:name/items/if-absent",
+            post(filter_insert_if_absent),
+        )
+        .route(
+            "/filters/:name/items/query",
+            post(filter_lookup_batch).layer(upload_limit),
+        )
+        .route("/filters/:name/items/stream", post(filter_insert_stream))
+        .route("/filters/:name/load", post(filter_load))
+        .route("/jobs/:id", get(job_get))
+        .route(
+            "/filters/:name/items/check-insert",
+            post(filter_check_insert),
+        )
+        .route(
+            "/filters/:name/items/batch",
+            post(filter_insert_batch).layer(upload_limit),
+        )
+        .route("/filters/:name/clear", put(filter_clear))
+        .route("/filters/:name/merge", post(filter_merge))
+        .route("/filters/:name/intersect", post(filter_intersect))
+        .route(
+            "/filters/:name/rebuild",
+            post(filter_rebuild).layer(upload_limit),
+        )
+        .route("/filters/:name/rebuild/journal", post(filter_rebuild_job))
+        .route("/filters/:name/compare/:other", get(filter_compare))
+        .route("/filters/:name/stats", get(filter_stats))
+        .route(
+            "/filters/:name/bits",
+            put(filter_upload).get(filter_download).layer(upload_limit),
+        )
+        .route("/filters/:name/dump", get(filter_dump))
+        .route("/filters/import", post(filters_import).layer(upload_limit))
+        .route(
+            "/filters/:name/bits/diff",
+            post(filter_diff).layer(upload_limit),
+        )
+        .route("/filters/:name/shadow", put(filter_shadow_set))
+        .route("/filters/:name/shadow", get(filter_shadow_get))
+        .route("/filters/:name/shadow", delete(filter_shadow_delete))
+        .route("/groups", post(groups_create))
+        .route("/groups", get(groups_list))
+        .route("/groups/:name", delete(groups_delete))
+        .route("/groups/:name/items", post(group_insert))
+        .route("/groups/:name/items", get(group_lookup))
+        .route("/groups/:name/clear", put(group_clear))
+        .route("/limiters", post(limiters_create))
+        .route("/limiters", get(limiters_list))
+        .route("/limiters/:name", delete(limiters_delete))
+        .route("/limiters/:name/check", post(limiter_check))
+        .route("/recent", post(recent_create))
+        .route("/recent", get(recent_list))
+        .route("/recent/:name", delete(recent_delete))
+        .route("/recent/:name/seen", post(recent_seen))
+        .route("/recent/:name/seen", get(recent_lookup))
+        .route("/sketches", post(sketches_create))
+        .route("/sketches", get(sketches_list))
+        .route("/sketches/:name", delete(sketches_delete))
+        .route("/sketches/:name/items", post(sketch_increment))
+        .route("/sketches/:name/items", get(sketch_estimate))
+        .route("/cardinality", post(cardinality_create))
+        .route("/cardinality", get(cardinality_list))
+        .route("/cardinality/:name", delete(cardinality_delete))
+        .route("/cardinality/:name/items", post(cardinality_insert))
+        .route("/cardinality/:name/estimate", get(cardinality_estimate))
+        .route("/cardinality/:name/merge", post(cardinality_merge))
+        .route("/admin/apply", post(admin_apply))
+        .route("/stats", get(stats));
+
+    #[cfg(feature = "wasm")]
+    let router = router
+        .route("/filters/:name/transform", put(filter_transform_set))
+        .route("/filters/:name/transform", delete(filter_transform_delete));
+
+    let router = if config.dev_endpoints {
+        router.route("/dev/filters", post(dev_filters_create))
+    } else {
+        router
+    };
+    // The limits of the routes above take precedence
+    router.layer(DefaultBodyLimit::max(config.limits.max_body_bytes))
+}
+
+/// Builds the routes serving the changes committed through a cluster: those of the application
+/// changing something, without the middleware, which the node the changes were requested of
+/// applied already, and the expiries and evictions committed by the leader.
+#[cfg(feature = "cluster")]
+fn command_routes(state: SharedState, config: SharedConfig) -> Router {
+    store_routes(&config.get())
+        .route("/namespaces", post(namespaces_create))
+        .route("/namespaces/:namespace", delete(namespaces_delete))
+        .route("/namespaces/:namespace/*path", any(namespace_route))
+        .route("/admin/keys", post(keys_create))
+        .route("/admin/keys/:id", delete(keys_delete))
+        .route(crate::cluster::EXPIRE_PATH, post(cluster_expire))
+        .layer(Extension(NamespaceRouters::default()))
+        .layer(middleware::from_fn(
+            move |mut request: Request, next: Next| {
+                request.extensions_mut().insert(config.get());
+                next.run(request)
+            },
+        ))
+        .with_state(state)
+}
+
+/// Returns the entity tags listed by the `If-Match` header of a request, which a change to a
+/// filter is conditional on; none without the header, or with `*`.
+fn if_match(headers: &HeaderMap) -> Result<Option<Vec<String>>, Error> {
+    let mut tags = Vec::new();
+    for value in headers.get_all(header::IF_MATCH) {
+        let value = value
+            .to_str()
+            .map_err(|_| Error::InvalidParameters("Invalid If-Match header".to_string()))?;
+        for tag in value
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+        {
+            if tag == "*" {
+                return Ok(None);
+            }
+            tags.push(tag.to_string());
+        }
+    }
+    Ok((!tags.is_empty()).then_some(tags))
+}
+
+/// Builds the JSON body of a successful response carrying a message.
+fn message(text: String) -> Json<MessageResponse> {
+    Json(MessageResponse { message: text })
+}
+
+// --- Content Negotiation ---
+
+/// Media type of MessagePack, as exchanged by the batch and statistics endpoints.
+const MSGPACK: &str = "application/msgpack";
+
+/// Returns whether a media type (or range, of an `Accept` header) is that of MessagePack, with or
+/// without the `x-` prefix, and not refused by a quality of 0.
+fn is_msgpack(media_type: &str) -> bool {
+    let mut parts = media_type.split(';').map(str::trim);
+    let media_type = parts.next().unwrap_or_default();
+    let refused =
+        parts.any(|part| part.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0));
+    (media_type.eq_ignore_ascii_case(MSGPACK)
+        || media_type.eq_ignore_ascii_case("application/x-msgpack"))
+        && !refused
+}
+
+/// A request body in JSON or, if sent with the `Content-Type` `application/msgpack`, in
+/// MessagePack.
+struct Payload<T>(T);
+
+#[axum::async_trait]
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for Payload<T> {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let msgpack = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(is_msgpack);
+        if !msgpack {
+            return match Json::<T>::from_request(request, state).await {
+                Ok(Json(value)) => Ok(Payload(value)),
+                Err(rejection) => Err(rejection.into_response()),
+            };
+        }
+        let body = Bytes::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        rmp_serde::from_slice(&body).map(Payload).map_err(|error| {
+            Error::InvalidParameters(format!("Invalid MessagePack body: {error}")).into_response()
+        })
+    }
+}
+
+/// The format of a response body: MessagePack if the `Accept` header of the request lists
+/// `application/msgpack`, JSON otherwise.
+#[derive(Clone, Copy)]
+enum Format {
+    Json,
+    MessagePack,
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let msgpack = parts
+            .headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(is_msgpack);
+        Ok(if msgpack {
+            Format::MessagePack
+        } else {
+            Format::Json
+        })
+    }
+}
+
+impl Format {
+    /// Responds with `body`, in this format.
+    fn respond<T: Serialize>(self, body: T) -> Response {
+        match self {
+            Format::Json => Json(body).into_response(),
+            // With the names of the fields, as in JSON
+            Format::MessagePack => match rmp_serde::to_vec_named(&body) {
+                Ok(bytes) => ([(header::CONTENT_TYPE, MSGPACK)], bytes).into_response(),
+                Err(error) => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+                }
+            },
+        }
+    }
+}
+
+// --- Access Log ---
+
+/// Header carrying the id of a request: the one sent by the client, or one generated otherwise.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Tags every request with an id, returned in the `x-request-id` header and in the body of error
+/// responses, and logs the request once answered: its method, path, status and latency.
+async fn access_log(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| !id.is_empty())
+        .map_or_else(|| Uuid::new_v4().to_string(), str::to_string);
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+
+    let mut response = next.run(request).await;
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        response = with_request_id(response, &request_id).await;
+    }
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let status = status.as_u16();
+    // Probes come every few seconds, and would drown the other requests
+    if PROBE_PATHS.contains(&path.as_str()) {
+        tracing::debug!(request_id, method = %method, path, status, latency_ms, "request");
+    } else {
+        tracing::info!(request_id, method = %method, path, status, latency_ms, "request");
+    }
+    response
+}
+
+/// Id of a request, as logged.
+#[derive(Clone)]
+struct RequestId(String);
+
+// --- CORS ---
+
+/// Answers the preflight requests of browsers, and allows the allowed origins to read the
+/// responses, according to the [`CorsPolicy`], if any.
+///
+/// Preflights carry no credentials, so they are answered before any are required.
+async fn allow_origins(
+    Extension(config): Extension<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let origin = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+        .map(str::to_string);
+    let (Some(cors), Some(origin)) = (&config.cors, origin) else {
+        return next.run(request).await;
+    };
+    let preflight = request.method() == axum::http::Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    if !cors.allows(&origin) {
+        if preflight {
+            let reason = format!("Requests from the origin '{origin}' are not allowed");
+            return Error::Forbidden(reason).into_response();
+        }
+        return next.run(request).await;
+    }
+
+    let mut response = if preflight {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        let allowed = [
+            (
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                cors.methods.join(", "),
+            ),
+            (
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                cors.headers.join(", "),
+            ),
+            (
+                header::ACCESS_CONTROL_MAX_AGE,
+                cors.max_age.as_secs().to_string(),
+            ),
+        ];
+        for (name, value) in allowed {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+        response
+    } else {
+        next.run(request).await
+    };
+    let headers = response.headers_mut();
+    if let Ok(origin) = HeaderValue::from_str(&origin) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+    headers.insert(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static(CORS_EXPOSED_HEADERS),
+    );
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+    response
+}
+
+// --- Authentication ---
+
+/// Who made a request, as given by [`Access::caller`]; an extension of the request and of its
+/// response.
+#[derive(Clone)]
+struct Caller(String);
+
+#[derive(Serialize, ToSchema)]
+struct KeyResponse {
+    #[serde(flatten)]
+    key: ApiKeyInfo,
+    /// The secret of the key, returned only once.
+    api_key: String,
+    message: String,
+}
+
+/// Header carrying an API key, as an alternative to `Authorization: Bearer <key>`.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Rejects the requests without an API key or bearer token allowing them, if the server requires
+/// credentials.
+///
+/// Lookups, lists and downloads take a [read](KeyScope::Read) key, and the administration
+/// endpoints an [admin](KeyScope::Admin) one; everything else a [read-write](KeyScope::ReadWrite)
+/// key. The scope of a token is that of its roles. Keys limited to some namespaces or filters are
+/// also checked against those the request is in and on.
+async fn require_api_key(
+    State(state): State<SharedState>,
+    Extension(config): Extension<AppConfig>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if !config.requires_credentials() {
+        return next.run(request).await;
+    }
+    let headers = request.headers();
+    let key = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+        });
+    let access = config.authenticate(&state, key);
+    let path = request.uri().path();
+    let required = required_scope(request.method(), path);
+    let (namespace, filters) = requested_resources(&state, path);
+    let access = match access {
+        Ok(access) => access,
+        Err(error) => return error.into_response(),
+    };
+    let caller = Caller(access.caller.clone());
+    let checked = access.check(required, namespace, filters.as_deref());
+    request.extensions_mut().insert(caller.clone());
+    request.extensions_mut().insert(access);
+    let mut response = match checked {
+        Ok(()) => next.run(request).await,
+        Err(error) => error.into_response(),
+    };
+    response.extensions_mut().insert(caller);
+    response
+}
+
+/// Returns the namespace a request is in (`None` for the default namespace), and the names of the
+/// filters it is on, if it is on given filters only.
+///
+/// Merges and intersections are taken as not on given filters, as their body names filters too.
+fn requested_resources<'a>(
+    state: &SharedState,
+    path: &'a str,
+) -> (Option<&'a str>, Option<Vec<String>>) {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let (namespace, route) = match segments.as_slice() {
+        ["namespaces", namespace, route @ ..] if !route.is_empty() => (Some(*namespace), route),
+        route => (None, route),
+    };
+    let names = match route {
+        ["filters", "import", ..] => return (namespace, None),
+        ["filters", _, "merge" | "intersect", ..] => return (namespace, None),
+        ["filters", name, "compare", other, ..] => vec![*name, *other],
+        ["filters", name, ..] => vec![*name],
+        _ => return (namespace, None),
+    };
+    // Filters may be given by id, and keys are limited to filters by name
+    let store = match namespace {
+        Some(namespace) => state.namespace(namespace).ok(),
+        None => Some(state.clone()),
+    };
+    let names = names
+        .into_iter()
+        .map(|name| {
+            store
+                .as_ref()
+                .map_or(name.to_string(), |s| s.filter_name(name))
+        })
+        .collect();
+    (namespace, Some(names))
+}
+
+/// Returns the namespace of a change requested on `uri`, and the filters it touches: those of
+/// [`requested_resources`], or the members of the group it is on, followed by the shadow filters
+/// of those it inserts into, removes from or clears, which it is written to as well.
+fn audited_resources(state: &SharedState, uri: &axum::http::Uri) -> (Option<String>, Vec<String>) {
+    let path = uri.path();
+    let (namespace, filters) = requested_resources(state, path);
+    let filters = filters.unwrap_or_default();
+    let store = match namespace {
+        Some(namespace) => state.namespace(namespace).ok(),
+        None => Some(state.clone()),
+    };
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let route = match segments.as_slice() {
+        ["namespaces", _, route @ ..] if !route.is_empty() => route,
+        route => route,
+    };
+    let filters = match (store, route) {
+        (Some(store), ["filters", _, "items" | "load" | "clear", ..]) => {
+            store.written_filters(&filters)
+        }
+        (Some(store), ["groups", group, "items" | "clear"]) => {
+            store.written_filters(&store.group_filters(group))
+        }
+        (Some(store), ["groups", group]) => {
+            let keep_filters = Query::<GroupDeleteParams>::try_from_uri(uri)
+                .is_ok_and(|Query(params)| params.keep_filters);
+            if keep_filters {
+                filters
+            } else {
+                store.group_filters(group)
+            }
+        }
+        _ => filters,
+    };
+    (namespace.map(str::to_string), filters)
+}
+
+/// Checks that the credentials of a request allow it on the `filters` it names in its body or
+/// query, besides those of its path checked by [`require_api_key`].
+fn check_named_filters(access: Option<Extension<Access>>, filters: &[String]) -> Result<(), Error> {
+    access.map_or(Ok(()), |Extension(access)| access.check_filters(filters))
+}
+
+/// Returns the scope an API key needs for a request.
+fn required_scope(method: &axum::http::Method, path: &str) -> KeyScope {
+    // The routes of a namespace are those of the default namespace, under a prefix
+    let mut segments = path.trim_start_matches('/').split('/');
+    let mut first = segments.next();
+    if first == Some("namespaces") && segments.next().is_some() {
+        first = segments.next();
+    }
+    if first == Some("admin") {
+        KeyScope::Admin
+    } else if is_lookup(method, path) {
+        KeyScope::Read
+    } else {
+        KeyScope::ReadWrite
+    }
+}
+
+/// Returns `true` if a request changes nothing.
+fn is_lookup(method: &axum::http::Method, path: &str) -> bool {
+    // Lookups of many items, or against a snapshot, post their items
+    let posted_lookup = path.ends_with("/items/query") || path.ends_with("/bits/diff");
+    method == axum::http::Method::GET || method == axum::http::Method::HEAD || posted_lookup
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/keys",
+    tag = "keys",
+    request_body = KeySpec,
+    responses(
+        (status = 201, description = "The key is created; its secret is returned only once", body = KeyResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 403, description = "The server is started without credentials", body = ErrorBody),
+    )
+)]
+async fn keys_create(
+    State(state): State<SharedState>,
+    Extension(config): Extension<AppConfig>,
+    Json(spec): Json<KeySpec>,
+) -> Result<impl IntoResponse, Error> {
+    if !config.requires_credentials() {
+        return Err(Error::Forbidden(
+            "API keys are disabled on this server, which is started without credentials"
+                .to_string(),
+        ));
+    }
+    let (key, api_key) = state.create_api_key(spec)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(KeyResponse {
+            message: format!("API key '{}' created", key.id),
+            key,
+            api_key,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/keys",
+    tag = "keys",
+    responses((status = 200, description = "The keys, without their secrets", body = Vec<ApiKeyInfo>))
+)]
+async fn keys_list(State(state): State<SharedState>) -> impl IntoResponse {
+    Json(state.list_api_keys())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/keys/{id}",
+    tag = "keys",
+    params(("id" = String, Path, description = "Id of the key")),
+    responses(
+        (status = 200, description = "The key is revoked", body = MessageResponse),
+        (status = 404, description = "The key does not exist", body = ErrorBody),
+    )
+)]
+async fn keys_delete(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    state.revoke_api_key(&id)?;
+    Ok(message(format!("API key '{id}' has been revoked")))
+}
+
+// --- Throttling ---
+
+/// Rejects the requests of clients beyond the limit of the [`Throttle`], if any.
+///
+/// Clients are told apart by their credentials, or else by their IP address.
+async fn throttle_clients(
+    Extension(config): Extension<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(throttle) = &config.throttle else {
+        return next.run(request).await;
+    };
+    let client = match request.extensions().get::<Caller>() {
+        Some(Caller(caller)) => caller.clone(),
+        None => request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or_else(|| "unknown".to_string(), |info| info.0.ip().to_string()),
+    };
+    match throttle.acquire(&client) {
+        Ok(()) => next.run(request).await,
+        Err(error) => error.into_response(),
+    }
+}
+
+// --- Limits ---
+
+/// Rejects the requests with a body larger than the [limits](RequestLimits) allow, and those
+/// taking longer than their timeout.
+async fn limit_requests(
+    Extension(config): Extension<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (limit, timeout) = config.limits.of(request.uri().path());
+    let length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+    if length.is_some_and(|length| length > limit as u64) {
+        return Error::PayloadTooLarge { limit }.into_response();
+    }
+    let response = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, next.run(request)).await {
+            Ok(response) => response,
+            Err(_) => {
+                let seconds = timeout.as_secs_f64();
+                return Error::Timeout { seconds }.into_response();
+            }
+        },
+        None => next.run(request).await,
+    };
+    // Bodies of unknown length are cut at the limit as they are read, and rejected in plain text
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return Error::PayloadTooLarge { limit }.into_response();
+    }
+    response
+}
+
+// --- Audit ---
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AuditParams {
+    filter: Option<String>,
+    caller: Option<String>,
+    #[serde(default = "default_audit_limit")]
+    limit: usize,
+}
+
+fn default_audit_limit() -> usize {
+    100
+}
+
+/// Records the requests changing something in the audit log, if any, made or refused.
+async fn audit_changes(
+    State(state): State<SharedState>,
+    Extension(config): Extension<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    // The calls of the consensus between the nodes of a cluster change nothing themselves
+    let Some(audit) = config
+        .audit
+        .filter(|_| !path.starts_with("/admin/cluster/raft/"))
+    else {
+        return next.run(request).await;
+    };
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    if is_lookup(request.method(), path) {
+        // The inserts over a WebSocket are recorded by the socket, a batch at a time
+        let mut request = request;
+        let path = request.uri().path().to_string();
+        if path.ends_with("/ws") {
+            let (namespace, _) = requested_resources(&state, &path);
+            let trail = AuditTrail {
+                audit,
+                request_id,
+                namespace: namespace.map(str::to_string),
+                path,
+            };
+            request.extensions_mut().insert(trail);
+        }
+        return next.run(request).await;
+    }
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    // Before the request, as the filters it deletes can no longer be resolved after it
+    let (namespace, filters) = audited_resources(&state, request.uri());
+
+    let response = next.run(request).await;
+    let entry = AuditEntry {
+        timestamp: timestamp(SystemTime::now()),
+        request_id,
+        caller: response.extensions().get::<Caller>().map(|c| c.0.clone()),
+        method,
+        path,
+        namespace,
+        filters,
+        status: response.status().as_u16(),
+    };
+    if let Err(error) = audit.record(entry) {
+        tracing::error!(%error, "Cannot write to the audit log");
+    }
+    response
+}
+
+/// Where the inserts over the WebSocket of a filter are recorded, with the request opening it.
+#[derive(Clone)]
+struct AuditTrail {
+    audit: Arc<AuditLog>,
+    request_id: String,
+    namespace: Option<String>,
+    path: String,
+}
+
+impl AuditTrail {
+    /// Records a batch of inserts over the socket into `filters` by `caller`, with the outcome.
+    fn record(&self, caller: Option<&str>, filters: Vec<String>, outcome: Result<(), &Error>) {
+        let entry = AuditEntry {
+            timestamp: timestamp(SystemTime::now()),
+            request_id: self.request_id.clone(),
+            caller: caller.map(str::to_string),
+            method: "WS".to_string(),
+            path: self.path.clone(),
+            namespace: self.namespace.clone(),
+            filters,
+            status: outcome
+                .map_or_else(|error| error.status(), |()| StatusCode::OK)
+                .as_u16(),
+        };
+        if let Err(error) = self.audit.record(entry) {
+            tracing::error!(%error, "Cannot write to the audit log");
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    params(AuditParams),
+    responses(
+        (status = 200, description = "The latest entries of the audit log, newest first", body = Vec<AuditEntry>),
+        (status = 403, description = "The audit log is disabled", body = ErrorBody),
+    )
+)]
+async fn audit_list(
+    Query(params): Query<AuditParams>,
+    Extension(config): Extension<AppConfig>,
+) -> Result<impl IntoResponse, Error> {
+    let audit = config
+        .audit
+        .ok_or_else(|| Error::Forbidden("The audit log is disabled on this server".to_string()))?;
+    Ok(Json(audit.entries(
+        params.filter.as_deref(),
+        params.caller.as_deref(),
+        params.limit,
+    )))
+}
+
+// --- Events ---
+
+/// Streams the events of the filters of all namespaces, as server-sent events, until the client
+/// disconnects.
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "events",
+    responses((status = 200, description = "The events of the filters, as server-sent events", content_type = "text/event-stream"))
+)]
+async fn events_stream(State(state): State<SharedState>) -> impl IntoResponse {
+    let events = futures_util::stream::unfold(state.subscribe(), |mut events| async move {
+        let event = match events.recv().await {
+            Ok(event) => Event::default().event(event.kind()).json_data(&event),
+            // Subscribers too slow to keep up miss the oldest events
+            Err(RecvError::Lagged(missed)) => Event::default()
+                .event("lagged")
+                .json_data(LaggedEvent { missed }),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((event, events))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+// --- Reload ---
+
+#[utoipa::path(
+    post,
+    path = "/admin/reload",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The configuration file is reloaded", body = MessageResponse),
+        (status = 400, description = "The configuration file is invalid", body = ErrorBody),
+        (status = 403, description = "The server is started without a configuration file", body = ErrorBody),
+    )
+)]
+async fn admin_reload(Extension(config): Extension<AppConfig>) -> Result<impl IntoResponse, Error> {
+    let reload = config.reload.ok_or_else(|| {
+        Error::Forbidden(
+            "Reloading is disabled on this server, which is started without a configuration file"
+                .to_string(),
+        )
+    })?;
+    let reloaded = tokio::task::spawn_blocking(move || reload.reload())
+        .await
+        .map_err(|e| Error::Storage(e.to_string()))??;
+    tracing::info!("{reloaded}");
+    Ok(message(reloaded))
+}
+
+// --- Replication ---
+
+/// Streams the changes of the default namespace to a replica, from a copy of everything in it.
+#[utoipa::path(
+    get,
+    path = "/admin/replication",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The stream of the changes, from a copy of everything", content_type = "application/octet-stream"),
+        (status = 403, description = "The server is a replica", body = ErrorBody),
+    )
+)]
+async fn admin_replication(State(state): State<SharedState>) -> Result<impl IntoResponse, Error> {
+    if state.read_only.load(std::sync::atomic::Ordering::Relaxed) {
+        return Err(Error::Forbidden(
+            "This server is a replica; replicate its primary instead".to_string(),
+        ));
+    }
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        replication_stream(state),
+    ))
+}
+
+/// Stops following the primary, and accepts changes from now on.
+#[utoipa::path(
+    post,
+    path = "/admin/promote",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The replica is promoted to primary", body = MessageResponse),
+        (status = 403, description = "The server is not a replica", body = ErrorBody),
+    )
+)]
+async fn admin_promote(State(state): State<SharedState>) -> Result<impl IntoResponse, Error> {
+    if !state
+        .read_only
+        .swap(false, std::sync::atomic::Ordering::Relaxed)
+    {
+        return Err(Error::Forbidden("This server is not a replica".to_string()));
+    }
+    tracing::info!("Promoted to primary");
+    Ok(message("Promoted to primary".to_string()))
+}
+
+/// Paths served on a replica, and in read-only mode, even though they are not lookups, as they
+/// change no filters.
+const REPLICA_PATHS: [&str; 3] = ["/admin/promote", "/admin/reload", "/admin/readonly"];
+
+/// Rejects the requests changing something on a replica, whose filters only change as those of
+/// its primary do, and in read-only mode.
+async fn reject_read_only_changes(
+    State(state): State<SharedState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if !is_lookup(request.method(), path) && !REPLICA_PATHS.contains(&path) {
+        if state.read_only.load(std::sync::atomic::Ordering::Relaxed) {
+            return replica_error().into_response();
+        }
+        if state
+            .read_only_mode
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            return read_only_error().into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// The error of the changes requested of a replica.
+fn replica_error() -> Error {
+    Error::Forbidden("This server is a read-only replica; send changes to its primary".to_string())
+}
+
+// --- Read-only Mode ---
+
+#[derive(Deserialize, ToSchema)]
+struct ReadOnlyRequest {
+    enabled: bool,
+}
+
+/// Turns the read-only mode on or off.
+#[utoipa::path(
+    post,
+    path = "/admin/readonly",
+    tag = "admin",
+    request_body = ReadOnlyRequest,
+    responses((status = 200, description = "The read-only mode is turned on or off", body = MessageResponse))
+)]
+async fn admin_read_only(
+    State(state): State<SharedState>,
+    Json(request): Json<ReadOnlyRequest>,
+) -> impl IntoResponse {
+    state
+        .read_only_mode
+        .store(request.enabled, std::sync::atomic::Ordering::Relaxed);
+    let text = match request.enabled {
+        true => "Read-only mode turned on",
+        false => "Read-only mode turned off",
+    };
+    tracing::info!("{text}");
+    message(text.to_string())
+}
+
+/// The error of the changes requested in read-only mode.
+pub(crate) fn read_only_error() -> Error {
+    Error::Forbidden(
+        "This server is in read-only mode; changes are rejected until it is turned off".to_string(),
+    )
+}
+
+// --- Clustering ---
+
+/// Returns the cluster of the server, failing if it is not clustered.
+#[cfg(feature = "cluster")]
+fn cluster(config: &AppConfig) -> Result<&Cluster, Error> {
+    config.cluster.as_ref().ok_or_else(|| {
+        Error::Forbidden(
+            "Clustering is disabled on this server, which is started without a cluster node id"
+                .to_string(),
+        )
+    })
+}
+
+#[cfg(feature = "cluster")]
+#[utoipa::path(
+    get,
+    path = "/admin/cluster",
+    tag = "cluster",
+    responses(
+        (status = 200, description = "The state of this node, and the members of the cluster", body = ClusterStatus),
+        (status = 403, description = "The server is not clustered", body = ErrorBody),
+    )
+)]
+async fn cluster_status(
+    Extension(config): Extension<AppConfig>,
+) -> Result<impl IntoResponse, Error> {
+    Ok(Json(cluster(&config)?.status()))
+}
+
+#[cfg(feature = "cluster")]
+#[utoipa::path(
+    post,
+    path = "/admin/cluster/init",
+    tag = "cluster",
+    responses(
+        (status = 200, description = "A cluster of this node alone is initialized", body = MessageResponse),
+        (status = 403, description = "The node is a member of a cluster already, or not clustered", body = ErrorBody),
+    )
+)]
+async fn cluster_init(Extension(config): Extension<AppConfig>) -> Result<impl IntoResponse, Error> {
+    cluster(&config)?.initialize().await?;
+    Ok(message("Cluster initialized".to_string()))
+}
+
+#[cfg(feature = "cluster")]
+#[utoipa::path(
+    post,
+    path = "/admin/cluster/nodes",
+    tag = "cluster",
+    request_body = ClusterMember,
+    responses(
+        (status = 200, description = "The node is added to the cluster", body = MessageResponse),
+        (status = 403, description = "The server is not clustered", body = ErrorBody),
+        (status = 503, description = "The cluster has no leader", body = ErrorBody),
+    )
+)]
+async fn cluster_nodes_add(
+    Extension(config): Extension<AppConfig>,
+    Json(member): Json<ClusterMember>,
+) -> Result<impl IntoResponse, Error> {
+    cluster(&config)?.add_node(member).await
+}
+
+#[cfg(feature = "cluster")]
+#[utoipa::path(
+    delete,
+    path = "/admin/cluster/nodes/{id}",
+    tag = "cluster",
+    params(("id" = u64, Path, description = "Id of the node")),
+    responses(
+        (status = 200, description = "The node is removed from the cluster", body = MessageResponse),
+        (status = 403, description = "The server is not clustered", body = ErrorBody),
+        (status = 503, description = "The cluster has no leader", body = ErrorBody),
+    )
+)]
+async fn cluster_nodes_delete(
+    Path(id): Path<u64>,
+    Extension(config): Extension<AppConfig>,
+) -> Result<impl IntoResponse, Error> {
+    cluster(&config)?.remove_node(id).await
+}
+
+/// Commits a change forwarded by another node of the cluster.
+#[cfg(feature = "cluster")]
+#[utoipa::path(
+    post,
+    path = "/admin/cluster/raft/write",
+    tag = "cluster",
+    request_body(content = serde_json::Value, description = "A change forwarded by another node"),
+    responses(
+        (status = 200, description = "The change is committed; the response of this node to it", body = serde_json::Value),
+        (status = 503, description = "This node does not lead the cluster", body = ErrorBody),
+    )
+)]
+async fn cluster_write(
+    Extension(config): Extension<AppConfig>,
+    Json(command): Json<Command>,
+) -> Result<impl IntoResponse, Error> {
+    cluster(&config)?.write(command).await
+}
+
+/// Expires and evicts the filters selected by the leader of the cluster, as committed by it.
+#[cfg(feature = "cluster")]
+async fn cluster_expire(
+    State(state): State<SharedState>,
+    Json(expiries): Json<Expiries>,
+) -> Result<impl IntoResponse, Error> {
+    let expired = expiries.apply(&state)?;
+    Ok(message(format!("Expired or evicted {expired} filters")))
+}
+
+/// Answers the calls of the consensus made by the other nodes of the cluster.
+#[cfg(feature = "cluster")]
+#[utoipa::path(
+    post,
+    path = "/admin/cluster/raft/{call}",
+    tag = "cluster",
+    params(("call" = String, Path, description = "The call of the consensus")),
+    request_body(content = serde_json::Value, description = "The call of another node"),
+    responses(
+        (status = 200, description = "The answer of this node", body = serde_json::Value),
+        (status = 400, description = "The call is unknown", body = ErrorBody),
+    )
+)]
+async fn cluster_call(
+    Path(call): Path<String>,
+    Extension(config): Extension<AppConfig>,
+    body: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    Ok(Json(cluster(&config)?.answer(&call, &body).await?))
+}
+
+/// Returns `true` if requests on `path` are served by the node they are sent to, rather than
+/// committed through the cluster.
+#[cfg(feature = "cluster")]
+fn is_local(path: &str) -> bool {
+    path == "/admin/cluster" || path.starts_with("/admin/cluster/") || REPLICA_PATHS.contains(&path)
+}
+
+/// Commits the requests changing something through the cluster, if any, answering them once the
+/// node has applied them.
+#[cfg(feature = "cluster")]
+async fn commit_changes(
+    Extension(config): Extension<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    match &config.cluster {
+        Some(cluster) if !is_lookup(request.method(), path) && !is_local(path) => {
+            // Replayed on every node, the commands cannot depend on the generations of a node
+            if request.headers().contains_key(header::IF_MATCH) {
+                let reason =
+                    "Changes conditional on If-Match are not supported by clustered servers";
+                return Error::InvalidParameters(reason.to_string()).into_response();
+            }
+            match cluster.commit(request).await {
+                Ok(outcome) => outcome.into_response(),
+                Err(error) => error.into_response(),
+            }
+        }
+        _ => next.run(request).await,
+    }
+}
+
+/// The error of the changes requested of a cluster other than over HTTP, as only requests are
+/// committed.
+pub(crate) fn cluster_error() -> Error {
+    Error::Forbidden(
+        "This server is clustered; send changes to the REST API, which commits them".to_string(),
+    )
+}
+
+// --- Sharding ---
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ShardQuery {
+    filter: Option<String>,
+}
+
+/// Describes the ring of the server, and the node owning a filter, if asked.
+#[utoipa::path(
+    get,
+    path = "/admin/shards",
+    tag = "admin",
+    params(ShardQuery),
+    responses(
+        (status = 200, description = "The nodes of the ring, and the owner of the filter", body = ShardStatus),
+        (status = 403, description = "The server is started without shard nodes", body = ErrorBody),
+    )
+)]
+async fn admin_shards(
+    Query(query): Query<ShardQuery>,
+    Extension(config): Extension<AppConfig>,
+) -> Result<impl IntoResponse, Error> {
+    let ring = config.shards.as_ref().ok_or_else(|| {
+        Error::Forbidden(
+            "Sharding is disabled on this server, which is started without shard nodes".to_string(),
+        )
+    })?;
+    Ok(Json(ring.status(query.filter.as_deref())))
+}
+
+/// Proxies the requests for the filters owned by another node of the ring, if any, to that node.
+async fn proxy_to_owner(
+    Extension(config): Extension<AppConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(ring) = &config.shards else {
+        return next.run(request).await;
+    };
+    if request.headers().contains_key(FORWARDED_HEADER) {
+        return next.run(request).await;
+    }
+    let limit = config.limits.max_body_bytes;
+    let (request, name) = match sharded_filter(request, limit).await {
+        Ok(sharded) => sharded,
+        Err(error) => return error.into_response(),
+    };
+    if let Some(name) = name.as_deref().filter(|name| ring.is_partitioned(name)) {
+        return match route_partitioned(ring, name, request, next, limit).await {
+            Ok(response) => response,
+            Err(error) => error.into_response(),
+        };
+    }
+    let Some(name) = name.filter(|name| !ring.owns(name)) else {
+        return next.run(request).await;
+    };
+    let owner = ring.owner(&name);
+    if request.uri().path().ends_with("/ws") {
+        return Error::InvalidParameters(format!(
+            "Filter '{name}' is owned by the node {owner}; connect to it instead"
+        ))
+        .into_response();
+    }
+    match ring.forward(owner, request).await {
+        Ok(response) => response,
+        Err(error) => error.into_response(),
+    }
+}
+
+/// The ring of a partitioned filter, and the credentials of the request for a batch of its items,
+/// which its handler splits between the nodes owning them.
+#[derive(Clone)]
+struct Partition {
+    ring: Arc<ShardRing>,
+    path: String,
+    headers: HeaderMap,
+}
+
+impl Partition {
+    /// Inserts or looks up the items of a batch by their owners, returning the `field` of the
+    /// responses for each item, with `local` for the items of this node.
+    async fn scatter<T: DeserializeOwned>(
+        &self,
+        field: &str,
+        items: Vec<Vec<u8>>,
+        local: impl FnOnce(Vec<Vec<u8>>) -> Result<Vec<T>, Error>,
+    ) -> Result<Vec<T>, Error> {
+        self.ring
+            .scatter(&self.path, &self.headers, field, items, local)
+            .await
+    }
+}
+
+/// Serves a request for a filter partitioned over the nodes of the ring: the requests for an item
+/// are sent to its owner, the batches of items split between their owners, the changes of the
+/// filter made by every node, and the other reads served by this node, from its partition.
+async fn route_partitioned(
+    ring: &Arc<ShardRing>,
+    name: &str,
+    request: Request,
+    next: Next,
+    limit: usize,
+) -> Result<Response, Error> {
+    use axum::http::Method;
+
+    let route = request
+        .extensions()
+        .get::<axum::extract::MatchedPath>()
+        .map(|path| path.as_str().trim_start_matches("/v1").to_string())
+        .unwrap_or_default();
+    let route = route.strip_prefix("/filters/:name").unwrap_or(&route);
+    let method = request.method().clone();
+    match (&method, route) {
+        (&Method::POST | &Method::GET | &Method::DELETE, "/items")
+        | (&Method::POST, "/items/check-insert" | "/items/if-absent") => {
+            let (parts, body) = read_body(request, limit).await?;
+            let item = match Query::<LookupParams>::try_from_uri(&parts.uri) {
+                Ok(Query(LookupParams {
+                    item: Some(item),
+                    encoding,
+                    ..
+                })) if method == Method::GET => encoding.decode(item.as_bytes(), 0)?,
+                _ => body.to_vec(),
+            };
+            let request = Request::from_parts(parts, Body::from(body));
+            forward_item(ring, &item, request, next).await
+        }
+        (&Method::GET, "/items/:item") => {
+            let (mut parts, body) = request.into_parts();
+            let Path((_, item)) = Path::<(String, String)>::from_request_parts(&mut parts, &())
+                .await
+                .map_err(|e| Error::InvalidParameters(e.body_text()))?;
+            let request = Request::from_parts(parts, body);
+            forward_item(ring, item.as_bytes(), request, next).await
+        }
+        (&Method::POST, "/items/batch" | "/items/query") => {
+            let (mut parts, body) = request.into_parts();
+            parts.extensions.insert(Partition {
+                ring: ring.clone(),
+                path: parts.uri.path().to_string(),
+                headers: parts.headers.clone(),
+            });
+            Ok(next.run(Request::from_parts(parts, body)).await)
+        }
+        (&Method::POST, "/filters")
+        | (&Method::PUT | &Method::PATCH | &Method::DELETE, "")
+        | (&Method::PUT, "/clear") => {
+            let (parts, mut body) = read_body(request, limit).await?;
+            if route != "/clear" && method != Method::PATCH && method != Method::DELETE {
+                body = partition_spec(ring, body);
+            }
+            let response = next
+                .run(Request::from_parts(parts.clone(), Body::from(body.clone())))
+                .await;
+            if !response.status().is_success() {
+                return Ok(response);
+            }
+            Ok(ring.broadcast(&parts, &body).await?.unwrap_or(response))
+        }
+        (&Method::GET, "/ws" | "/items/watch") => Err(Error::InvalidParameters(format!(
+            "Filter '{name}' is partitioned over the nodes of the ring, which serve its items \
+             by requests"
+        ))),
+        (&Method::GET, _) => Ok(next.run(request).await),
+        _ => Err(Error::InvalidParameters(format!(
+            "Filter '{name}' is partitioned over the nodes of the ring, which cannot serve \
+             {method} {route} for it"
+        ))),
+    }
+}
+
+/// Reads the body of a request whole, up to `limit` bytes.
+async fn read_body(request: Request, limit: usize) -> Result<(Parts, Bytes), Error> {
+    let (parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, limit)
+        .await
+        .map_err(|_| Error::PayloadTooLarge { limit })?;
+    Ok((parts, body))
+}
+
+/// Sends a request for an item of a partitioned filter to the node owning the item, or serves it
+/// if it is this node.
+async fn forward_item(
+    ring: &ShardRing,
+    item: &[u8],
+    request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    let owner = ring.item_owner(item);
+    if owner == ring.address() {
+        return Ok(next.run(request).await);
+    }
+    ring.forward(owner, request).await
+}
+
+/// Sizes the filter of a specification for the share of the items of its partition.
+fn partition_spec(ring: &ShardRing, body: Bytes) -> Bytes {
+    let Ok(mut spec) = serde_json::from_slice::<serde_json::Value>(&body) else {
+        return body;
+    };
+    let Some(item_count) = spec["item_count"].as_u64() else {
+        return body;
+    };
+    let item_count = ring.partition_item_count(item_count as usize);
+    spec["item_count"] = item_count.into();
+    serde_json::to_vec(&spec).map_or(body, Bytes::from)
+}
+
+/// Returns the name of the filter of the default namespace a request is for, if any, read from
+/// the body of the requests creating filters. Filters given by their id are served by the node
+/// the request is sent to.
+async fn sharded_filter(
+    request: Request,
+    limit: usize,
+) -> Result<(Request, Option<String>), Error> {
+    let path = request.uri().path();
+    if path == "/filters" && request.method() == axum::http::Method::POST {
+        let (parts, body) = request.into_parts();
+        let body = axum::body::to_bytes(body, limit)
+            .await
+            .map_err(|_| Error::PayloadTooLarge { limit })?;
+        let name = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|spec| Some(spec.get("name")?.as_str()?.to_string()));
+        return Ok((Request::from_parts(parts, Body::from(body)), name));
+    }
+    let name = path
+        .strip_prefix("/filters/")
+        .and_then(|rest| rest.split('/').next())
+        .filter(|name| !name.is_empty() && *name != "import" && Uuid::parse_str(name).is_err())
+        .map(str::to_string);
+    Ok((request, name))
+}
+
+// --- Health Probes ---
+
+/// Paths of the health probes, served even while the service is not ready.
+const PROBE_PATHS: [&str; 3] = ["/livez", "/readyz", "/healthz"];
+
+/// Rejects the requests other than the health probes while the saved filters are restored, as
+/// they would see (and change) filters about to be replaced.
+async fn reject_while_restoring(
+    State(state): State<SharedState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.restoring.load(std::sync::atomic::Ordering::Relaxed) {
+        return Error::Unavailable("The saved filters are being restored".to_string())
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Answers as long as the service is running, whether or not it is ready.
+#[utoipa::path(
+    get,
+    path = "/livez",
+    tag = "probes",
+    security(()),
+    responses((status = 200, description = "The service is running", body = ProbeResponse))
+)]
+async fn livez() -> impl IntoResponse {
+    Json(ProbeResponse {
+        status: "live",
+        reasons: Vec::new(),
+    })
+}
+
+/// Answers whether the service is ready to serve requests, and why not if it is not.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "probes",
+    security(()),
+    responses(
+        (status = 200, description = "The service is ready", body = ProbeResponse),
+        (status = 503, description = "The service is not ready", body = ProbeResponse),
+    )
+)]
+async fn readyz(State(state): State<SharedState>) -> impl IntoResponse {
+    let reasons = state.readiness();
+    if reasons.is_empty() {
+        (
+            StatusCode::OK,
+            Json(ProbeResponse {
+                status: "ready",
+                reasons,
+            }),
+        )
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ProbeResponse {
+                status: "not_ready",
+                reasons,
+            }),
+        )
+    }
+}
+
+/// Answers whether the service is ready, as an alias of `/readyz` for the probes expecting it.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "probes",
+    security(()),
+    responses(
+        (status = 200, description = "The service is ready", body = ProbeResponse),
+        (status = 503, description = "The service is not ready", body = ProbeResponse),
+    )
+)]
+async fn healthz(state: State<SharedState>) -> impl IntoResponse {
+    readyz(state).await
+}
+
+/// Adds the id of the request to a JSON error body, e.g. for clients to quote in bug reports.
+async fn with_request_id(response: Response, request_id: &str) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if !is_json {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = body.collect().await.map(|body| body.to_bytes()) else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut error)) => {
+            error.insert("request_id".to_string(), request_id.into());
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(serde_json::Value::Object(error).to_string())
+        }
+        _ => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
+
+// --- OpenAPI ---
+
+/// The OpenAPI document of the API: its first version, the health probes and the documentation.
+#[derive(OpenApi)]
+#[openapi(
+    paths(livez, readyz, healthz, openapi_document, docs, dashboard),
+    nest((path = "/v1", api = FilterApiV1)),
+    modifiers(&ApiKeySchemes),
+    security(("bearer" = []), ("api_key" = [])),
+    tags(
+        (name = "filters", description = "Bloom filters, and the items inserted into them"),
+        (name = "jobs", description = "Jobs loading items into filters on the side of the service"),
+        (name = "groups", description = "Groups of filters operated on together"),
+        (name = "limiters", description = "Rate limiters, counting keys within a sliding window"),
+        (name = "recent", description = "Filters of the items seen within a sliding window"),
+        (name = "sketches", description = "Count-Min sketches, estimating the counts of items"),
+        (name = "cardinality", description = "HyperLogLogs, estimating the number of distinct items"),
+        (name = "namespaces", description = "Namespaces, isolating the names of applications"),
+        (name = "events", description = "Lifecycle events of the filters"),
+        (name = "keys", description = "API keys, created and revoked with the admin key"),
+        (name = "admin", description = "Administration of the service"),
+        (name = "cluster", description = "Membership and consensus of a cluster (`cluster` feature)"),
+        (name = "dev", description = "Development endpoints, served only if enabled"),
+        (name = "probes", description = "Liveness and readiness probes, served without a key"),
+        (name = "docs", description = "This document, and the pages browsing the service"),
+    )
+)]
+struct ApiDoc;
+
+/// The first version of the API.
+///
+/// Every path of the filters, groups, limiters, recent filters, sketches and cardinalities, and
+/// `/admin/apply` and `/stats`, is also served under `/namespaces/{namespace}`, for those of a
+/// namespace.
+#[derive(OpenApi)]
+#[openapi(paths(
+    filters_create,
+    filters_list,
+    filters_get,
+    filters_put,
+    filters_update,
+    filters_delete,
+    filter_insert,
+    filter_lookup,
+    filter_remove,
+    filter_watch,
+    filter_socket,
+    filter_lookup_path,
+    filter_insert_if_absent,
+    filter_lookup_batch,
+    filter_insert_stream,
+    filter_load,
+    job_get,
+    filter_check_insert,
+    filter_insert_batch,
+    filter_clear,
+    filter_merge,
+    filter_intersect,
+    filter_rebuild,
+    filter_rebuild_job,
+    filter_compare,
+    filter_stats,
+    filter_upload,
+    filter_download,
+    filter_dump,
+    filters_import,
+    filter_diff,
+    filter_shadow_set,
+    filter_shadow_get,
+    filter_shadow_delete,
+    groups_create,
+    groups_list,
+    groups_delete,
+    group_insert,
+    group_lookup,
+    group_clear,
+    limiters_create,
+    limiters_list,
+    limiters_delete,
+    limiter_check,
+    recent_create,
+    recent_list,
+    recent_delete,
+    recent_seen,
+    recent_lookup,
+    sketches_create,
+    sketches_list,
+    sketches_delete,
+    sketch_increment,
+    sketch_estimate,
+    cardinality_create,
+    cardinality_list,
+    cardinality_delete,
+    cardinality_insert,
+    cardinality_estimate,
+    cardinality_merge,
+    admin_apply,
+    stats,
+    dev_filters_create,
+    namespaces_create,
+    namespaces_list,
+    namespaces_delete,
+    events_stream,
+    keys_create,
+    keys_list,
+    keys_delete,
+    audit_list,
+    admin_reload,
+    admin_replication,
+    admin_promote,
+    admin_read_only,
+    admin_shards,
+    admin_memory,
+))]
+struct FilterApiV1;
+
+/// The transforms of the filters, in the first version of the API (`wasm` feature).
+#[cfg(feature = "wasm")]
+#[derive(OpenApi)]
+#[openapi(paths(filter_transform_set, filter_transform_delete))]
+struct TransformApiV1;
+
+/// The membership and consensus of a cluster, in the first version of the API (`cluster`
+/// feature).
+#[cfg(feature = "cluster")]
+#[derive(OpenApi)]
+#[openapi(paths(
+    cluster_status,
+    cluster_init,
+    cluster_nodes_add,
+    cluster_nodes_delete,
+    cluster_write,
+    cluster_call,
+))]
+struct ClusterApiV1;
+
+/// Adds the schemes clients send their API keys (or JWTs) with, when the server requires them.
+struct ApiKeySchemes;
+
+impl utoipa::Modify for ApiKeySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+    }
+}
+
+/// Serves the OpenAPI document of the API.
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "docs",
+    security(()),
+    responses((status = 200, description = "This document", content_type = "application/json"))
+)]
+async fn openapi_document() -> impl IntoResponse {
+    Json(openapi())
+}
+
+/// Returns the OpenAPI document of the API, with the paths of the features it is built with.
+fn openapi() -> utoipa::openapi::OpenApi {
+    let document = ApiDoc::openapi();
+    #[cfg(feature = "wasm")]
+    let document = document.nest("/v1", TransformApiV1::openapi());
+    #[cfg(feature = "cluster")]
+    let document = document.nest("/v1", ClusterApiV1::openapi());
+    document
+}
+
+/// The page of the Swagger UI, browsing the OpenAPI document; its scripts and styles are loaded
+/// from a CDN.
+const DOCS_PAGE: &str = include_str!("docs.html");
+
+/// Serves the Swagger UI.
+#[utoipa::path(
+    get,
+    path = "/docs",
+    tag = "docs",
+    security(()),
+    responses((status = 200, description = "The Swagger UI, browsing this document", content_type = "text/html"))
+)]
+async fn docs() -> impl IntoResponse {
+    axum::response::Html(DOCS_PAGE)
+}
+
+/// The page of the dashboard, listing the filters from the JSON endpoints with the key entered
+/// in it; its scripts and styles are embedded, so it needs nothing but the service.
+const DASHBOARD_PAGE: &str = include_str!("ui.html");
+
+/// Serves the dashboard.
+#[utoipa::path(
+    get,
+    path = "/ui",
+    tag = "docs",
+    security(()),
+    responses((status = 200, description = "The dashboard of the filters", content_type = "text/html"))
+)]
+async fn dashboard() -> impl IntoResponse {
+    axum::response::Html(DASHBOARD_PAGE)
+}
+
+// --- Request Handlers ---
+
+#[utoipa::path(
+    post,
+    path = "/filters",
+    tag = "filters",
+    request_body = FilterSpec,
+    responses(
+        (status = 201, description = "The filter is created", body = FilterResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 409, description = "A filter of the name exists", body = ErrorBody),
+    )
+)]
+async fn filters_create(
+    State(state): State<SharedState>,
+    Json(spec): Json<FilterSpec>,
+) -> Result<impl IntoResponse, Error> {
+    let info = state.create(spec)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(FilterResponse {
+            id: info.id,
+            message: format!("Filter '{}' created", info.name),
+            name: info.name,
+            bit_count: info.bit_count,
+            hash_count: info.hash_count,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/filters/{name}",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name of the filter")),
+    request_body = FilterSpec,
+    responses(
+        (status = 201, description = "The filter is created", body = FilterResponse),
+        (status = 200, description = "The filter exists with the same parameters", body = FilterResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 409, description = "The filter exists with other parameters", body = ErrorBody),
+    )
+)]
+async fn filters_put(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    Json(mut spec): Json<FilterSpec>,
+) -> Result<impl IntoResponse, Error> {
+    if spec.name.is_empty() {
+        spec.name = name;
+    } else if spec.name != name {
+        return Err(Error::InvalidParameters(format!(
+            "The name '{}' does not match the name '{name}' of the path",
+            spec.name
+        )));
+    }
+    let (info, created) = state.get_or_create(spec)?;
+    let (status, message) = if created {
+        (
+            StatusCode::CREATED,
+            format!("Filter '{}' created", info.name),
+        )
+    } else {
+        (
+            StatusCode::OK,
+            format!("Filter '{}' already exists", info.name),
+        )
+    };
+    Ok((
+        status,
+        Json(FilterResponse {
+            id: info.id,
+            message,
+            name: info.name,
+            bit_count: info.bit_count,
+            hash_count: info.hash_count,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/filters/{name}",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body = FilterUpdateRequest,
+    responses(
+        (status = 200, description = "The labels are updated", body = ListItem),
+        (status = 400, description = "The labels are invalid", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filters_update(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    Json(request): Json<FilterUpdateRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let info = state.update_labels(&name, request.labels)?;
+    Ok(Json(ListItem::from(info)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/filters/{name}",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    responses(
+        (status = 200, description = "The filter is deleted", body = MessageResponse),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+        (status = 412, description = "The filter changed since it was read", body = ErrorBody),
+    )
+)]
+async fn filters_delete(
+    Path(id_or_name): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.delete_if_match(&id_or_name, if_match(&headers)?.as_deref())?;
+    Ok(message(format!("Filter '{name}' has been deleted")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters",
+    tag = "filters",
+    params(ListParams),
+    responses(
+        (
+            status = 200,
+            description = "The filters, in order",
+            body = Vec<ListItem>,
+            headers(("x-total-count" = usize, description = "Number of filters matching, before pagination")),
+        ),
+    )
+)]
+async fn filters_list(
+    Query(params): Query<ListParams>,
+    State(state): State<SharedState>,
+) -> impl IntoResponse {
+    let mut filters = state.list();
+    if let Some(prefix) = &params.name_prefix {
+        filters.retain(|info| info.name.starts_with(prefix.as_str()));
+    }
+    if let Some(label) = &params.label {
+        filters.retain(|info| match label.split_once('=') {
+            Some((key, value)) => info.labels.get(key).is_some_and(|v| v == value),
+            None => info.labels.contains_key(label),
+        });
+    }
+    match params.sort {
+        ListSort::Name => filters.sort_by(|a, b| a.name.cmp(&b.name)),
+        ListSort::CreatedAt => {
+            filters.sort_by(|a, b| (a.created_at, &a.name).cmp(&(b.created_at, &b.name)))
+        }
+    }
+    let total = filters.len();
+    let list: Vec<ListItem> = filters
+        .into_iter()
+        .skip(params.offset)
+        .take(params.limit.unwrap_or(usize::MAX))
+        .map(ListItem::from)
+        .collect();
+    ([(TOTAL_COUNT_HEADER, total.to_string())], Json(list))
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    responses(
+        (status = 200, description = "The filter, its saturation and usage", body = FilterDetails),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filters_get(
+    Path(id_or_name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    let (info, stats) = state.describe(&id_or_name)?;
+    let etag = [(header::ETAG, info.etag())];
+    Ok((
+        etag,
+        Json(FilterDetails {
+            usage: info.usage,
+            filter: info.into(),
+            stats,
+        }),
+    ))
+}
+
+/// Traces an item in a filter if the request asks for it, and the server allows it.
+fn debug_trace(
+    state: &SharedState,
+    config: &AppConfig,
+    params: &DebugParams,
+    name: &str,
+    item: &[u8],
+) -> Result<Option<HashTrace>, Error> {
+    if !params.debug {
+        return Ok(None);
+    }
+    if !config.debug_traces {
+        return Err(Error::Forbidden(
+            "Debug traces are disabled on this server".to_string(),
+        ));
+    }
+    state.trace(name, item).map(Some)
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/items",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), DebugParams),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "The item is inserted", body = InsertResponse),
+        (status = 422, description = "The transform of the filter rejects the item", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_insert(
+    Path(name): Path<String>,
+    Query(params): Query<DebugParams>,
+    State(state): State<SharedState>,
+    Extension(config): Extension<AppConfig>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    // Traced before the insert, so the bits show whether the item may have been inserted before
+    let trace = debug_trace(&state, &config, &params, &name, &item)?;
+    let new = state.insert(&name, &item)?;
+    let item = String::from_utf8_lossy(&item);
+    let response = if new {
+        format!("Item '{item}' inserted into filter '{name}'")
+    } else {
+        format!("Item '{item}' inserted into filter '{name}', and may have been inserted before")
+    };
+    Ok(Json(InsertResponse {
+        response,
+        new,
+        debug: trace,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/items/check-insert",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "The item is inserted", body = CheckInsertResponse),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_check_insert(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let was_present = state.check_and_insert(&name, &item)?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(CheckInsertResponse {
+        was_present,
+        message: if was_present {
+            format!("Item '{item}' may already have been in filter '{name}', and is now inserted")
+        } else {
+            format!("Item '{item}' was not in filter '{name}', and is now inserted")
+        },
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/items/batch",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), EncodingParams),
+    request_body(
+        content((Vec<String> = "application/json"), (Vec<String> = "application/msgpack")),
+        description = "The items, encoded as given by `encoding`; in MessagePack, also as bytes"
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The items are inserted",
+            content(
+                (BatchInsertResponse = "application/json"),
+                (BatchInsertResponse = "application/msgpack"),
+            ),
+        ),
+        (status = 400, description = "An item is not validly encoded", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_insert_batch(
+    Path(name): Path<String>,
+    Query(params): Query<EncodingParams>,
+    State(state): State<SharedState>,
+    format: Format,
+    partition: Option<Extension<Partition>>,
+    Payload(items): Payload<Vec<BatchItem>>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let count = items.len();
+    let items = params.encoding.decode_batch(items)?;
+    let new = match partition {
+        Some(Extension(partition)) => {
+            let insert = |items| state.insert_batch(&name, items);
+            partition.scatter("new", items, insert).await?
+        }
+        None => state.insert_batch(&name, items)?,
+    };
+    let inserted = new.iter().flatten().count();
+    Ok(format.respond(BatchInsertResponse {
+        inserted,
+        new,
+        message: format!("Inserted {inserted} of {count} items into filter '{name}'"),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/items/query",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), EncodingParams),
+    request_body(
+        content((Vec<String> = "application/json"), (Vec<String> = "application/msgpack")),
+        description = "The items, encoded as given by `encoding`; in MessagePack, also as bytes"
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Whether each item may have been inserted",
+            content(
+                (BatchLookupResponse = "application/json"),
+                (BatchLookupResponse = "application/msgpack"),
+            ),
+        ),
+        (status = 400, description = "An item is not validly encoded", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_lookup_batch(
+    Path(name): Path<String>,
+    Query(params): Query<EncodingParams>,
+    State(state): State<SharedState>,
+    format: Format,
+    partition: Option<Extension<Partition>>,
+    Payload(items): Payload<Vec<BatchItem>>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let count = items.len();
+    let items = params.encoding.decode_batch(items)?;
+    let contains = match partition {
+        Some(Extension(partition)) => {
+            let lookup = |items| state.contains_many(&name, items);
+            partition.scatter("contains", items, lookup).await?
+        }
+        None => state.contains_many(&name, items)?,
+    };
+    let found = contains.iter().filter(|&&contains| contains).count();
+    Ok(format.respond(BatchLookupResponse {
+        contains,
+        message: format!("{found} of {count} items may have been seen by filter '{name}'"),
+    }))
+}
+
+/// Maximum number of items a streaming ingest inserts under one lock of the filters.
+const STREAM_BATCH_ITEMS: usize = 10_000;
+/// Maximum length of a line of a streaming ingest (1 MiB).
+const MAX_STREAM_LINE_BYTES: usize = 1 << 20;
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/items/stream",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), StreamParams),
+    request_body(content = String, content_type = "text/plain", description = "The items, one per line, as given by `format`"),
+    responses(
+        (status = 200, description = "The items are inserted", body = IngestResponse),
+        (status = 400, description = "A line is not validly encoded, or too long", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_insert_stream(
+    Path(name): Path<String>,
+    Query(params): Query<StreamParams>,
+    State(state): State<SharedState>,
+    mut body: Body,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let mut ingest = LineIngest::new(state, name, params);
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|error| {
+            Error::InvalidParameters(format!("Failed to read the request body: {error}"))
+        })?;
+        if let Ok(data) = frame.into_data() {
+            ingest.feed(&data)?;
+        }
+    }
+    ingest.finish()?;
+
+    Ok(Json(IngestResponse {
+        message: format!(
+            "Inserted {} items into filter '{}'",
+            ingest.stats.items - ingest.stats.rejected,
+            ingest.name
+        ),
+        stats: ingest.stats,
+    }))
+}
+
+/// Splits the bytes of a streaming ingest into lines, and inserts their items into a filter in
+/// batches, releasing the lock of the filter between batches so that other requests are not held
+/// up.
+pub(crate) struct LineIngest {
+    state: SharedState,
+    name: String,
+    params: StreamParams,
+    started: Instant,
+    pub(crate) stats: IngestStats,
+    /// The items of the current batch.
+    batch: Vec<Vec<u8>>,
+    /// The start of a line continued in the next chunk.
+    partial: Vec<u8>,
+}
+
+impl LineIngest {
+    pub(crate) fn new(state: SharedState, name: String, params: StreamParams) -> Self {
+        LineIngest {
+            state,
+            name,
+            params,
+            started: Instant::now(),
+            stats: IngestStats::default(),
+            batch: Vec::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    /// Returns the name of the filter the items are inserted into.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Inserts the items of the lines completed by the next chunk of bytes.
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.stats.bytes += data.len();
+        self.partial.extend_from_slice(data);
+
+        let mut start = 0;
+        while let Some(end) = self.partial[start..].iter().position(|&b| b == b'\n') {
+            let line = &self.partial[start..start + end];
+            if let Some(item) = stream_item(line, &self.params, &self.stats)? {
+                self.batch.push(item);
+                self.stats.items += 1;
+            }
+            start += end + 1;
+            if self.batch.len() >= STREAM_BATCH_ITEMS {
+                self.insert()?;
+            }
+        }
+        self.partial.drain(..start);
+        if self.partial.len() > MAX_STREAM_LINE_BYTES {
+            return Err(Error::InvalidParameters(format!(
+                "Item {} is longer than {MAX_STREAM_LINE_BYTES} bytes",
+                self.stats.items + 1
+            )));
+        }
+        self.insert()
+    }
+
+    /// Inserts the item of the last line, which has no line feed.
+    pub(crate) fn finish(&mut self) -> Result<(), Error> {
+        let last = std::mem::take(&mut self.partial);
+        if let Some(item) = stream_item(&last, &self.params, &self.stats)? {
+            self.batch.push(item);
+            self.stats.items += 1;
+        }
+        self.insert()
+    }
+
+    /// Inserts the items of the current batch, if any.
+    fn insert(&mut self) -> Result<(), Error> {
+        if !self.batch.is_empty() {
+            for new in self.state.insert_batch(&self.name, self.batch.iter())? {
+                match new {
+                    Some(new) => self.stats.new += usize::from(new),
+                    None => self.stats.rejected += 1,
+                }
+            }
+            self.stats.batches += 1;
+            self.batch.clear();
+        }
+        self.stats.elapsed_seconds = self.started.elapsed().as_secs_f64();
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct LoadRequest {
+    /// A `file://` URL of a file of the server, or an `http(s)://` URL.
+    source: String,
+    #[serde(default)]
+    format: StreamFormat,
+    #[serde(default)]
+    encoding: ItemEncoding,
+}
+
+/// Starts loading the lines of a file or URL into a filter, as a streaming ingest would, on the
+/// side of the server; the progress is polled at `GET /jobs/:id`.
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/load",
+    tag = "jobs",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body = LoadRequest,
+    responses(
+        (status = 202, description = "The job loading the items is started", body = JobInfo),
+        (status = 400, description = "The source is invalid, or not allowed", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_load(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    Extension(config): Extension<AppConfig>,
+    Json(request): Json<LoadRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    // Fails before opening the source if the filter is missing
+    state.describe(&name)?;
+    let params = StreamParams {
+        format: request.format,
+        encoding: request.encoding,
+    };
+    let ingest = LineIngest::new(state.clone(), name, params);
+    let job = start_load(state, ingest, &request.source, &config.load_sources).await?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Id of the job")),
+    responses(
+        (status = 200, description = "The progress of the job", body = JobInfo),
+        (status = 404, description = "The job does not exist", body = ErrorBody),
+    )
+)]
+async fn job_get(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    Ok(Json(state.jobs.get(&id)?))
+}
+
+/// Decodes a line of a streaming ingest; `None` for empty lines.
+fn stream_item(
+    line: &[u8],
+    params: &StreamParams,
+    stats: &IngestStats,
+) -> Result<Option<Vec<u8>>, Error> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let item = match params.format {
+        StreamFormat::Lines => return params.encoding.decode(line, stats.items).map(Some),
+        StreamFormat::Ndjson => serde_json::from_slice::<String>(line).map_err(|_| {
+            Error::InvalidParameters(format!("Item {} is not a JSON string", stats.items + 1))
+        })?,
+    };
+    params
+        .encoding
+        .decode(item.as_bytes(), stats.items)
+        .map(Some)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/filters/{name}/items",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "The item is removed, if it was in the filter", body = RemoveResponse),
+        (status = 400, description = "The filter cannot remove items", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_remove(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let removed = state.remove(&name, &item)?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(RemoveResponse {
+        removed,
+        message: if removed {
+            format!("Item '{item}' removed from filter '{name}'")
+        } else {
+            format!("Item '{item}' not removed, it is not in filter '{name}'")
+        },
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/items/if-absent",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), InsertIfAbsentParams),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "The item is inserted, unless a filter may have seen it", body = InsertIfAbsentResponse),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_insert_if_absent(
+    Path(name): Path<String>,
+    Query(params): Query<InsertIfAbsentParams>,
+    State(state): State<SharedState>,
+    access: Option<Extension<Access>>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let check: Vec<String> = params
+        .check
+        .split(',')
+        .filter(|c| !c.is_empty())
+        .map(|c| state.filter_name(c))
+        .collect();
+    // The answer tells whether the filters checked may have seen the item
+    check_named_filters(access, &check)?;
+    let found_in = state.insert_if_absent(&name, &item, &check)?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(InsertIfAbsentResponse {
+        inserted: found_in.is_none(),
+        message: match &found_in {
+            None => format!("Item '{item}' inserted into filter '{name}'"),
+            Some(filter) => {
+                format!("Item '{item}' not inserted, it may have been seen by filter '{filter}'")
+            }
+        },
+        found_in,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/items",
+    tag = "filters",
+    params(
+        ("name" = String, Path, description = "Name or id of the filter"),
+        ("item" = Option<String>, Query, description = "The item to look up, instead of the request body"),
+        ("encoding" = Option<ItemEncoding>, Query, description = "Encoding of `item`"),
+        DebugParams,
+    ),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body, unless given by `item`"),
+    responses(
+        (status = 200, description = "Whether the item may have been inserted", body = LookupResponse),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_lookup(
+    Path(name): Path<String>,
+    Query(params): Query<LookupParams>,
+    State(state): State<SharedState>,
+    Extension(config): Extension<AppConfig>,
+    body: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let item = match &params.item {
+        Some(item) => params.encoding.decode(item.as_bytes(), 0)?,
+        None => body.to_vec(),
+    };
+    lookup_response(&state, &config, &params.debug, &name, &item)
+}
+
+/// Tests for an item given by the last segment of the path. The names of the other endpoints
+/// under `items/` route to those instead, so items named so are looked up with the `item` query
+/// parameter of [`filter_lookup`].
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/items/{item}",
+    tag = "filters",
+    params(
+        ("name" = String, Path, description = "Name or id of the filter"),
+        ("item" = String, Path, description = "The item to look up; items named `watch`, `stream`, `batch`, `query`, `if-absent` or `check-insert` are looked up with `GET /filters/{name}/items?item=<item>` instead"),
+        DebugParams,
+    ),
+    responses(
+        (status = 200, description = "Whether the item may have been inserted", body = LookupResponse),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_lookup_path(
+    Path((name, item)): Path<(String, String)>,
+    Query(params): Query<DebugParams>,
+    State(state): State<SharedState>,
+    Extension(config): Extension<AppConfig>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    lookup_response(&state, &config, &params, &name, item.as_bytes())
+}
+
+/// Looks an item up, for the lookups by body, query parameter and path.
+fn lookup_response(
+    state: &SharedState,
+    config: &AppConfig,
+    params: &DebugParams,
+    name: &str,
+    item: &[u8],
+) -> Result<Json<LookupResponse>, Error> {
+    let contains = state.contains(name, item)?;
+    let debug = debug_trace(state, config, params, name, item)?;
+    let item = String::from_utf8_lossy(item);
+    Ok(Json(LookupResponse {
+        contains,
+        message: if contains {
+            format!("Item '{item}' may have been seen by filter '{name}'")
+        } else {
+            format!("Item '{item}' cannot have been seen by filter '{name}'")
+        },
+        debug,
+    }))
+}
+
+/// Default and maximum time a watch request waits for an item to appear.
+const DEFAULT_WATCH_TIMEOUT: u64 = 30;
+const MAX_WATCH_TIMEOUT: u64 = 300;
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/items/watch",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), WatchParams),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "Whether the item appeared before the timeout", body = WatchResponse),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_watch(
+    Path(name): Path<String>,
+    Query(params): Query<WatchParams>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let timeout = params
+        .timeout_seconds
+        .unwrap_or(DEFAULT_WATCH_TIMEOUT)
+        .min(MAX_WATCH_TIMEOUT);
+
+    let contains = state
+        .watch(&name, &item, Duration::from_secs(timeout))
+        .await?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(WatchResponse {
+        contains,
+        timed_out: !contains,
+        message: if contains {
+            format!("Item '{item}' may have been seen by filter '{name}'")
+        } else {
+            format!("Item '{item}' has not been seen by filter '{name}' within {timeout} seconds")
+        },
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/ws",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), EncodingParams),
+    responses(
+        (status = 101, description = "The connection is upgraded to a WebSocket, over which items are inserted and looked up"),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_socket(
+    Path(name): Path<String>,
+    Query(params): Query<EncodingParams>,
+    State(state): State<SharedState>,
+    Extension(config): Extension<AppConfig>,
+    access: Option<Extension<Access>>,
+    trail: Option<Extension<AuditTrail>>,
+    upgrade: WebSocketUpgrade,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    // Fails before upgrading if the filter is missing
+    state.describe(&name)?;
+    // The upgrade is a lookup, and each insert needs a key allowing inserts
+    let writable = access
+        .as_ref()
+        .is_none_or(|Extension(access)| access.scope.allows(KeyScope::ReadWrite));
+    let clustered = config.is_clustered();
+    let audit = trail.map(|Extension(trail)| {
+        let caller = access.map(|Extension(access)| access.caller);
+        (trail, caller)
+    });
+    Ok(upgrade
+        .max_message_size(config.limits.max_upload_bytes)
+        .on_upgrade(move |socket| {
+            serve_socket(
+                socket,
+                state,
+                name,
+                params.encoding,
+                writable,
+                clustered,
+                audit,
+            )
+        }))
+}
+
+/// Answers the requests sent over the WebSocket of a filter, in order, until it is closed.
+///
+/// Text frames carry a JSON [request](SocketRequest), answered by a JSON text frame. Binary
+/// frames carry an opcode (1 to insert, 2 to look up) followed by the items, each prefixed with
+/// its length as a big-endian `u32`; they are answered by a binary frame of the opcode followed
+/// by a byte per item: 1 if it is definitely new (or may have been seen, for lookups), 0 if not,
+/// and 2 if it is rejected by the transform of the filter. Failures are answered by a JSON text
+/// frame, leaving the socket open. Each batch of inserts is recorded in the audit log of `audit`,
+/// if any, as requested by its caller.
+async fn serve_socket(
+    mut socket: WebSocket,
+    state: SharedState,
+    name: String,
+    encoding: ItemEncoding,
+    writable: bool,
+    clustered: bool,
+    audit: Option<(AuditTrail, Option<String>)>,
+) {
+    let run = |op: SocketOperation, items: &[Vec<u8>]| -> Result<Vec<Option<bool>>, Error> {
+        match op {
+            SocketOperation::Insert if !writable => Err(Error::Forbidden(
+                "The credentials do not allow inserts, which require the 'read_write' scope"
+                    .to_string(),
+            )),
+            SocketOperation::Insert
+                if state.read_only.load(std::sync::atomic::Ordering::Relaxed) =>
+            {
+                Err(replica_error())
+            }
+            SocketOperation::Insert
+                if state
+                    .read_only_mode
+                    .load(std::sync::atomic::Ordering::Relaxed) =>
+            {
+                Err(read_only_error())
+            }
+            SocketOperation::Insert if clustered => Err(cluster_error()),
+            SocketOperation::Insert => state.insert_batch(&name, items),
+            SocketOperation::Contains => Ok(state
+                .contains_many(&name, items)?
+                .into_iter()
+                .map(Some)
+                .collect()),
+        }
+    };
+    // Every batch of inserts is audited, made or refused
+    let apply = |op: SocketOperation, items: &[Vec<u8>]| {
+        let results = run(op, items);
+        if let (SocketOperation::Insert, Some((trail, caller))) = (op, &audit) {
+            let filters = state.written_filters(&[name.as_str()]);
+            trail.record(caller.as_deref(), filters, results.as_ref().map(|_| ()));
+        }
+        results
+    };
+    let respond =
+        |response: SocketResponse| Message::Text(serde_json::to_string(&response).unwrap());
+    let failure = |id: serde_json::Value, error: Error| {
+        respond(SocketResponse {
+            error: Some(ErrorBody::from(&error)),
+            ..SocketResponse::new(id)
+        })
+    };
+
+    while let Some(Ok(message)) = socket.recv().await {
+        let response = match message {
+            Message::Text(text) => match serde_json::from_str::<SocketRequest>(&text) {
+                Ok(request) => {
+                    let results = encoding
+                        .decode_all(&request.items)
+                        .and_then(|items| apply(request.op, &items));
+                    match (results, request.op) {
+                        (Ok(new), SocketOperation::Insert) => respond(SocketResponse {
+                            new: Some(new),
+                            ..SocketResponse::new(request.id)
+                        }),
+                        (Ok(contains), SocketOperation::Contains) => respond(SocketResponse {
+                            contains: Some(contains),
+                            ..SocketResponse::new(request.id)
+                        }),
+                        (Err(error), _) => failure(request.id, error),
+                    }
+                }
+                Err(error) => failure(
+                    serde_json::Value::Null,
+                    Error::InvalidParameters(format!("Invalid request: {error}")),
+                ),
+            },
+            Message::Binary(frame) => {
+                let results = decode_socket_frame(&frame)
+                    .and_then(|(op, items)| Ok((frame[0], apply(op, &items)?)));
+                match results {
+                    Ok((opcode, results)) => {
+                        let mut response = Vec::with_capacity(results.len() + 1);
+                        response.push(opcode);
+                        response.extend(results.into_iter().map(|result| match result {
+                            Some(result) => u8::from(result),
+                            None => 2,
+                        }));
+                        Message::Binary(response)
+                    }
+                    Err(error) => failure(serde_json::Value::Null, error),
+                }
+            }
+            Message::Close(_) => break,
+            // Pings are answered by the socket itself
+            Message::Ping(_) | Message::Pong(_) => continue,
+        };
+        if socket.send(response).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Decodes a binary frame of a WebSocket: an opcode, and items prefixed with their length.
+fn decode_socket_frame(frame: &[u8]) -> Result<(SocketOperation, Vec<Vec<u8>>), Error> {
+    let invalid =
+        |reason: &str| Error::InvalidParameters(format!("Invalid binary frame: {reason}"));
+    let (&opcode, mut rest) = frame.split_first().ok_or_else(|| invalid("it is empty"))?;
+    let op = SocketOperation::from_opcode(opcode).ok_or_else(|| invalid("unknown opcode"))?;
+    let mut items = Vec::new();
+    while !rest.is_empty() {
+        let (length, tail) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| invalid("truncated item length"))?;
+        let length = u32::from_be_bytes(*length) as usize;
+        if tail.len() < length {
+            return Err(invalid("truncated item"));
+        }
+        let (item, tail) = tail.split_at(length);
+        items.push(item.to_vec());
+        rest = tail;
+    }
+    Ok((op, items))
+}
+
+#[utoipa::path(
+    put,
+    path = "/filters/{name}/clear",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    responses(
+        (status = 200, description = "The filter is cleared", body = MessageResponse),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+        (status = 412, description = "The filter changed since it was read", body = ErrorBody),
+    )
+)]
+async fn filter_clear(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    state.clear_if_match(&name, if_match(&headers)?.as_deref())?;
+    Ok(message(format!("Filter '{name}' has been cleared")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/merge",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body = FilterMergeRequest,
+    responses(
+        (status = 200, description = "The filters are merged into the filter", body = FilterResponse),
+        (status = 400, description = "The filters are not compatible", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+        (status = 412, description = "The filter changed since it was read", body = ErrorBody),
+    )
+)]
+async fn filter_merge(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(request): Json<FilterMergeRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let expected = if_match(&headers)?;
+    let info = state.merge_if_match(&name, &request.sources, expected.as_deref())?;
+    Ok(Json(FilterResponse {
+        id: info.id,
+        message: format!(
+            "Merged {} filters into filter '{name}'",
+            request.sources.len()
+        ),
+        name: info.name,
+        bit_count: info.bit_count,
+        hash_count: info.hash_count,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/rebuild",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), EncodingParams),
+    request_body = FilterRebuildRequest,
+    responses(
+        (status = 200, description = "The filter is rebuilt from the items", body = FilterResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+        (status = 412, description = "The filter changed since it was read", body = ErrorBody),
+    )
+)]
+async fn filter_rebuild(
+    Path(name): Path<String>,
+    Query(params): Query<EncodingParams>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(request): Json<FilterRebuildRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let creation_mode = rebuild_mode(request.false_positive_rate, request.hash_count)?;
+    let items = params.encoding.decode_all(&request.items)?;
+    let expected = if_match(&headers)?;
+    let info = state.rebuild_if_match(
+        &name,
+        request.item_count,
+        creation_mode,
+        &items,
+        expected.as_deref(),
+    )?;
+    Ok(Json(FilterResponse {
+        id: info.id,
+        message: format!(
+            "Rebuilt filter '{name}' for {} items, from {} items",
+            info.capacity,
+            items.len()
+        ),
+        name: info.name,
+        bit_count: info.bit_count,
+        hash_count: info.hash_count,
+    }))
+}
+
+/// Starts rebuilding a filter from the journal of its items, on the side of the server, swapping
+/// the rebuilt filter in once done; the progress is polled at `GET /jobs/:id`.
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/rebuild/journal",
+    tag = "jobs",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body = FilterRebuildJobRequest,
+    responses(
+        (status = 202, description = "The job rebuilding the filter is started", body = JobInfo),
+        (status = 400, description = "The parameters are invalid, or the filter keeps no journal", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_rebuild_job(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    Json(request): Json<FilterRebuildJobRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let creation_mode = rebuild_mode(request.false_positive_rate, request.hash_count)?;
+    let job = start_rebuild(state, name, request.item_count, creation_mode)?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Resolves the creation mode of a rebuilt filter, if another is given.
+fn rebuild_mode(
+    false_positive_rate: Option<f64>,
+    hash_count: Option<u32>,
+) -> Result<Option<CreationMode>, Error> {
+    match (false_positive_rate, hash_count) {
+        (Some(rate), None) => Ok(Some(CreationMode::FalsePositiveRate(rate))),
+        (None, Some(count)) => Ok(Some(CreationMode::HashCount(count))),
+        (None, None) => Ok(None),
+        (Some(_), Some(_)) => Err(Error::InvalidParameters(
+            "Must provide either false_positive_rate or hash_count, not both".to_string(),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/intersect",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body = FilterIntersectRequest,
+    responses(
+        (status = 200, description = "The filter is intersected with the filters", body = FilterResponse),
+        (status = 201, description = "A filter is created from the intersection", body = FilterResponse),
+        (status = 400, description = "The filters are not compatible", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+        (status = 412, description = "The filter changed since it was read", body = ErrorBody),
+    )
+)]
+async fn filter_intersect(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(request): Json<FilterIntersectRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let expected = if_match(&headers)?;
+    let into = request.into.as_deref();
+    let info = state.intersect_if_match(&name, &request.sources, into, expected.as_deref())?;
+    let (status, message) = match &request.into {
+        Some(into) => (
+            StatusCode::CREATED,
+            format!("Filter '{into}' created from the intersection of filter '{name}'"),
+        ),
+        None => (StatusCode::OK, format!("Filter '{name}' intersected")),
+    };
+    let sources = request.sources.len();
+    Ok((
+        status,
+        Json(FilterResponse {
+            id: info.id,
+            message: format!("{message} with {sources} filters"),
+            name: info.name,
+            bit_count: info.bit_count,
+            hash_count: info.hash_count,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/filters/{name}/bits",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), UploadParams),
+    request_body(content = String, content_type = "application/octet-stream", description = "The filter file"),
+    responses(
+        (status = 200, description = "The filter is created, replaced or merged from the bits", body = FilterResponse),
+        (status = 400, description = "The file is invalid", body = ErrorBody),
+        (status = 409, description = "A filter of the name exists", body = ErrorBody),
+        (status = 412, description = "The filter changed since it was read", body = ErrorBody),
+    )
+)]
+async fn filter_upload(
+    Path(name): Path<String>,
+    Query(params): Query<UploadParams>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let snapshot = match params.format {
+        BitsFormat::Native => FilterSnapshot::decode(&body)?,
+        BitsFormat::Guava => FilterSnapshot::from_guava(&body)?,
+        BitsFormat::Pybloom => FilterSnapshot::from_pybloom(&body)?,
+    };
+    let expected = if_match(&headers)?;
+    let info = state.upload_if_match(&name, snapshot, params.mode, expected.as_deref())?;
+    let action = match params.mode {
+        UploadMode::Create => "created from the uploaded bits",
+        UploadMode::Replace => "replaced by the uploaded bits",
+        UploadMode::Merge => "merged with the uploaded bits",
+    };
+    Ok(Json(FilterResponse {
+        id: info.id,
+        message: format!("Filter '{name}' {action}"),
+        name: info.name,
+        bit_count: info.bit_count,
+        hash_count: info.hash_count,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/bits",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), DownloadParams),
+    responses(
+        (status = 200, description = "The filter file", content_type = "application/octet-stream"),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_download(
+    Path(name): Path<String>,
+    Query(params): Query<DownloadParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let snapshot = state.snapshot(&name)?;
+    let bytes = match params.format {
+        BitsFormat::Native => snapshot.encode(),
+        BitsFormat::Guava => snapshot.to_guava()?,
+        BitsFormat::Pybloom => snapshot.to_pybloom()?,
+    };
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], bytes))
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/dump",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), DumpParams),
+    responses(
+        (
+            status = 200,
+            description = "The filter and its parameters, as given by `format`",
+            content((FilterDump = "application/json"), (String = "application/octet-stream")),
+        ),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_dump(
+    Path(name): Path<String>,
+    Query(params): Query<DumpParams>,
+    State(state): State<SharedState>,
+) -> Result<axum::response::Response, Error> {
+    let name = state.filter_name(&name);
+    let snapshot = state.snapshot(&name)?;
+    Ok(match params.format {
+        DumpFormat::Json => Json(FilterDump::new(name, snapshot)).into_response(),
+        DumpFormat::Binary => (
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            snapshot.encode(),
+        )
+            .into_response(),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/import",
+    tag = "filters",
+    params(ImportParams),
+    request_body(
+        content((FilterDump = "application/json"), (String = "application/octet-stream")),
+        description = "A dump of a filter, in JSON or as a filter file"
+    ),
+    responses(
+        (status = 201, description = "The filter is imported", body = FilterResponse),
+        (status = 400, description = "The dump is invalid, or the name is missing", body = ErrorBody),
+        (status = 409, description = "A filter of the name exists", body = ErrorBody),
+    )
+)]
+async fn filters_import(
+    Query(params): Query<ImportParams>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let (name, snapshot) = if is_json {
+        let dump: FilterDump = serde_json::from_slice(&body)
+            .map_err(|e| Error::InvalidParameters(format!("Invalid filter dump: {e}")))?;
+        (
+            params.name.unwrap_or_else(|| dump.name.clone()),
+            dump.into_snapshot()?,
+        )
+    } else {
+        let name = params.name.ok_or_else(|| {
+            Error::InvalidParameters("Binary dumps require the 'name' parameter".to_string())
+        })?;
+        (name, FilterSnapshot::decode(&body)?)
+    };
+
+    let info = state.upload(&name, snapshot, UploadMode::Create)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(FilterResponse {
+            id: info.id,
+            message: format!("Filter '{}' imported", info.name),
+            name: info.name,
+            bit_count: info.bit_count,
+            hash_count: info.hash_count,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters/{name}/bits/diff",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body(content = String, content_type = "application/octet-stream", description = "The filter file"),
+    responses(
+        (status = 200, description = "The changes of the filter since the state of the file", body = FilterDiff),
+        (status = 400, description = "The file is invalid, or of another filter", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_diff(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    body: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let snapshot = FilterSnapshot::decode(&body)?;
+    Ok(Json(state.diff(&name, &snapshot)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/compare/{other}",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter"), ("other" = String, Path, description = "Name or id of the other filter")),
+    responses(
+        (status = 200, description = "The estimated overlap of the items of the filters", body = FilterOverlap),
+        (status = 400, description = "The filters are not compatible", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_compare(
+    Path((name, other)): Path<(String, String)>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let other = state.filter_name(&other);
+    Ok(Json(state.compare(&name, &other)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/stats",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    responses(
+        (
+            status = 200,
+            description = "The saturation and usage of the filter",
+            content(
+                (StatsResponse = "application/json"),
+                (StatsResponse = "application/msgpack"),
+            ),
+        ),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_stats(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    format: Format,
+) -> Result<impl IntoResponse, Error> {
+    let (info, stats) = state.describe(&name)?;
+    let etag = [(header::ETAG, info.etag())];
+    Ok((
+        etag,
+        format.respond(StatsResponse {
+            stats,
+            memory_usage_bytes: info.memory_usage_bytes,
+            mapped_bytes: info.mapped_bytes,
+            usage: info.usage,
+        }),
+    ))
+}
+
+// --- Transform Handlers ---
+
+#[cfg(feature = "wasm")]
+#[utoipa::path(
+    put,
+    path = "/filters/{name}/transform",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body(content = String, content_type = "application/wasm", description = "The WASM module"),
+    responses(
+        (status = 200, description = "The transform is set", body = MessageResponse),
+        (status = 400, description = "The module is invalid", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_transform_set(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    wasm: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    state.set_transform(&name, &wasm)?;
+    Ok(message(format!(
+        "Items of filter '{name}' are transformed by a {} byte module",
+        wasm.len()
+    )))
+}
+
+#[cfg(feature = "wasm")]
+#[utoipa::path(
+    delete,
+    path = "/filters/{name}/transform",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    responses(
+        (status = 200, description = "The transform is removed", body = MessageResponse),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_transform_delete(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    state.remove_transform(&name)?;
+    Ok(message(format!(
+        "Items of filter '{name}' are no longer transformed"
+    )))
+}
+
+// --- Shadow Handlers ---
+
+#[utoipa::path(
+    put,
+    path = "/filters/{name}/shadow",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    request_body = ShadowRequest,
+    responses(
+        (status = 200, description = "The shadow filter is set", body = MessageResponse),
+        (status = 400, description = "The shadow filter is invalid", body = ErrorBody),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_shadow_set(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    access: Option<Extension<Access>>,
+    Json(payload): Json<ShadowRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let shadow = state.filter_name(&payload.filter);
+    // Writes to the filter, and its clears, change the shadow filter too
+    check_named_filters(access, std::slice::from_ref(&shadow))?;
+    state.set_shadow(&name, &shadow)?;
+    Ok(message(format!(
+        "Writes to filter '{name}' are mirrored to filter '{shadow}'"
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/shadow",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    responses(
+        (status = 200, description = "The answers of the filter compared with those of its shadow filter", body = ShadowReport),
+        (status = 404, description = "The filter does not exist, or has no shadow filter", body = ErrorBody),
+    )
+)]
+async fn filter_shadow_get(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    Ok(Json(state.shadow_report(&name)?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/filters/{name}/shadow",
+    tag = "filters",
+    params(("name" = String, Path, description = "Name or id of the filter")),
+    responses(
+        (status = 200, description = "The shadow filter is no longer written to", body = MessageResponse),
+        (status = 404, description = "The filter does not exist", body = ErrorBody),
+    )
+)]
+async fn filter_shadow_delete(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    let name = state.filter_name(&name);
+    let shadow = state.remove_shadow(&name)?;
+    Ok(message(format!(
+        "Writes to filter '{name}' are no longer mirrored to filter '{shadow}'"
+    )))
+}
+
+// --- Group Handlers ---
+
+#[utoipa::path(
+    post,
+    path = "/groups",
+    tag = "groups",
+    request_body = GroupSpec,
+    responses(
+        (status = 201, description = "The group is created", body = MessageResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 409, description = "A group of the name exists", body = ErrorBody),
+    )
+)]
+async fn groups_create(
+    State(state): State<SharedState>,
+    Json(spec): Json<GroupSpec>,
+) -> Result<impl IntoResponse, Error> {
+    let name = spec.name.clone();
+    state.create_group(spec)?;
+    Ok((
+        StatusCode::CREATED,
+        message(format!("Group '{name}' created")),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/groups",
+    tag = "groups",
+    responses((status = 200, description = "The groups", body = Vec<GroupListItem>))
+)]
+async fn groups_list(State(state): State<SharedState>) -> impl IntoResponse {
+    let list: Vec<GroupListItem> = state
+        .list_groups()
+        .into_iter()
+        .map(|g| GroupListItem {
+            name: g.name,
+            filters: g.filters,
+        })
+        .collect();
+    Json(list)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/groups/{name}",
+    tag = "groups",
+    params(("name" = String, Path, description = "Name of the group"), GroupDeleteParams),
+    responses(
+        (status = 200, description = "The group is deleted, with its filters unless kept", body = MessageResponse),
+        (status = 404, description = "The group does not exist", body = ErrorBody),
+    )
+)]
+async fn groups_delete(
+    Path(name): Path<String>,
+    Query(params): Query<GroupDeleteParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    let count = state.delete_group(&name, params.keep_filters)?;
+    Ok(message(if params.keep_filters {
+        format!("Group '{name}' has been deleted")
+    } else {
+        format!("Group '{name}' and its {count} filters have been deleted")
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/groups/{name}/items",
+    tag = "groups",
+    params(("name" = String, Path, description = "Name of the group")),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "The item is inserted into every filter of the group", body = MessageResponse),
+        (status = 404, description = "The group does not exist", body = ErrorBody),
+    )
+)]
+async fn group_insert(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let count = state.group_insert(&name, &item)?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(message(format!(
+        "Item '{item}' inserted into {count} filters of group '{name}'"
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/groups/{name}/items",
+    tag = "groups",
+    params(("name" = String, Path, description = "Name of the group"), GroupLookupParams),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "Whether the filters of the group may have seen the item", body = GroupLookupResponse),
+        (status = 404, description = "The group does not exist", body = ErrorBody),
+    )
+)]
+async fn group_lookup(
+    Path(name): Path<String>,
+    Query(params): Query<GroupLookupParams>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let lookup = state.group_contains(&name, &item, params.mode)?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(GroupLookupResponse {
+        contains: lookup.contains,
+        filters: lookup.filters,
+        message: if lookup.contains {
+            format!("Item '{item}' may have been seen by group '{name}'")
+        } else {
+            format!("Item '{item}' cannot have been seen by group '{name}'")
+        },
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/groups/{name}/clear",
+    tag = "groups",
+    params(("name" = String, Path, description = "Name of the group")),
+    responses(
+        (status = 200, description = "The filters of the group are cleared", body = MessageResponse),
+        (status = 404, description = "The group does not exist", body = ErrorBody),
+    )
+)]
+async fn group_clear(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    state.group_clear(&name)?;
+    Ok(message(format!(
+        "Filters of group '{name}' have been cleared"
+    )))
+}
+
+// --- Limiter Handlers ---
+
+#[utoipa::path(
+    post,
+    path = "/limiters",
+    tag = "limiters",
+    request_body = LimiterSpec,
+    responses(
+        (status = 201, description = "The limiter is created", body = MessageResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 409, description = "A limiter of the name exists", body = ErrorBody),
+    )
+)]
+async fn limiters_create(
+    State(state): State<SharedState>,
+    Json(spec): Json<LimiterSpec>,
+) -> Result<impl IntoResponse, Error> {
+    let name = spec.name.clone();
+    state.create_limiter(spec)?;
+    Ok((
+        StatusCode::CREATED,
+        message(format!("Limiter '{name}' created")),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/limiters",
+    tag = "limiters",
+    responses((status = 200, description = "The limiters", body = Vec<LimiterInfo>))
+)]
+async fn limiters_list(State(state): State<SharedState>) -> impl IntoResponse {
+    Json(state.list_limiters())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/limiters/{name}",
+    tag = "limiters",
+    params(("name" = String, Path, description = "Name of the limiter")),
+    responses(
+        (status = 200, description = "The limiter is deleted", body = MessageResponse),
+        (status = 404, description = "The limiter does not exist", body = ErrorBody),
+    )
+)]
+async fn limiters_delete(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    state.delete_limiter(&name)?;
+    Ok(message(format!("Limiter '{name}' has been deleted")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/limiters/{name}/check",
+    tag = "limiters",
+    params(("name" = String, Path, description = "Name of the limiter"), LimiterCheckParams),
+    request_body(content = String, content_type = "application/octet-stream", description = "The key, as the bytes of the body"),
+    responses(
+        (status = 200, description = "Whether the key is allowed, and counted", body = LimiterDecision),
+        (status = 404, description = "The limiter does not exist", body = ErrorBody),
+    )
+)]
+async fn limiter_check(
+    Path(name): Path<String>,
+    Query(params): Query<LimiterCheckParams>,
+    State(state): State<SharedState>,
+    key: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    Ok(Json(state.check_limiter(
+        &name,
+        &key,
+        params.cost.unwrap_or(1),
+    )?))
+}
+
+// --- Recent Filter Handlers ---
+
+#[utoipa::path(
+    post,
+    path = "/recent",
+    tag = "recent",
+    request_body = RecentSpec,
+    responses(
+        (status = 201, description = "The recent filter is created", body = MessageResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 409, description = "A recent filter of the name exists", body = ErrorBody),
+    )
+)]
+async fn recent_create(
+    State(state): State<SharedState>,
+    Json(spec): Json<RecentSpec>,
+) -> Result<impl IntoResponse, Error> {
+    let name = spec.name.clone();
+    state.create_recent(spec)?;
+    Ok((
+        StatusCode::CREATED,
+        message(format!("Recent filter '{name}' created")),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/recent",
+    tag = "recent",
+    responses((status = 200, description = "The recent filters", body = Vec<RecentInfo>))
+)]
+async fn recent_list(State(state): State<SharedState>) -> impl IntoResponse {
+    Json(state.list_recent())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/recent/{name}",
+    tag = "recent",
+    params(("name" = String, Path, description = "Name of the recent filter")),
+    responses(
+        (status = 200, description = "The recent filter is deleted", body = MessageResponse),
+        (status = 404, description = "The recent filter does not exist", body = ErrorBody),
+    )
+)]
+async fn recent_delete(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    state.delete_recent(&name)?;
+    Ok(message(format!("Recent filter '{name}' has been deleted")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/recent/{name}/seen",
+    tag = "recent",
+    params(("name" = String, Path, description = "Name of the recent filter")),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "Whether the item was seen within the window; it is recorded as seen now", body = SeenResponse),
+        (status = 404, description = "The recent filter does not exist", body = ErrorBody),
+    )
+)]
+async fn recent_seen(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let seen = state.recent_seen(&name, &item)?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(SeenResponse {
+        seen,
+        message: if seen {
+            format!("Item '{item}' may have been seen recently by '{name}'")
+        } else {
+            format!("Item '{item}' has not been seen recently by '{name}', and is now recorded")
+        },
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/recent/{name}/seen",
+    tag = "recent",
+    params(("name" = String, Path, description = "Name of the recent filter")),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "Whether the item was seen within the window", body = SeenResponse),
+        (status = 404, description = "The recent filter does not exist", body = ErrorBody),
+    )
+)]
+async fn recent_lookup(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let seen = state.recent_contains(&name, &item)?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(SeenResponse {
+        seen,
+        message: if seen {
+            format!("Item '{item}' may have been seen recently by '{name}'")
+        } else {
+            format!("Item '{item}' has not been seen recently by '{name}'")
+        },
+    }))
+}
+
+// --- Sketch Handlers ---
+
+#[utoipa::path(
+    post,
+    path = "/sketches",
+    tag = "sketches",
+    request_body = SketchSpec,
+    responses(
+        (status = 201, description = "The sketch is created", body = MessageResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 409, description = "A sketch of the name exists", body = ErrorBody),
+    )
+)]
+async fn sketches_create(
+    State(state): State<SharedState>,
+    Json(spec): Json<SketchSpec>,
+) -> Result<impl IntoResponse, Error> {
+    let name = spec.name.clone();
+    state.create_sketch(spec)?;
+    Ok((
+        StatusCode::CREATED,
+        message(format!("Sketch '{name}' created")),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sketches",
+    tag = "sketches",
+    responses((status = 200, description = "The sketchs", body = Vec<SketchInfo>))
+)]
+async fn sketches_list(State(state): State<SharedState>) -> impl IntoResponse {
+    Json(state.list_sketches())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/sketches/{name}",
+    tag = "sketches",
+    params(("name" = String, Path, description = "Name of the sketch")),
+    responses(
+        (status = 200, description = "The sketch is deleted", body = MessageResponse),
+        (status = 404, description = "The sketch does not exist", body = ErrorBody),
+    )
+)]
+async fn sketches_delete(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    state.delete_sketch(&name)?;
+    Ok(message(format!("Sketch '{name}' has been deleted")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/sketches/{name}/items",
+    tag = "sketches",
+    params(("name" = String, Path, description = "Name of the sketch"), SketchIncrementParams),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "The item is counted; the estimate of its count", body = EstimateResponse),
+        (status = 404, description = "The sketch does not exist", body = ErrorBody),
+    )
+)]
+async fn sketch_increment(
+    Path(name): Path<String>,
+    Query(params): Query<SketchIncrementParams>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let estimate = state.increment_sketch(&name, &item, params.count.unwrap_or(1))?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(EstimateResponse {
+        estimate,
+        message: format!("Item '{item}' counted by sketch '{name}'"),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sketches/{name}/items",
+    tag = "sketches",
+    params(("name" = String, Path, description = "Name of the sketch")),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "The estimate of the count of the item", body = EstimateResponse),
+        (status = 404, description = "The sketch does not exist", body = ErrorBody),
+    )
+)]
+async fn sketch_estimate(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let estimate = state.estimate_sketch(&name, &item)?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(EstimateResponse {
+        estimate,
+        message: format!("Item '{item}' was counted about {estimate} times by sketch '{name}'"),
+    }))
+}
+
+// --- Cardinality Handlers ---
+
+#[utoipa::path(
+    post,
+    path = "/cardinality",
+    tag = "cardinality",
+    request_body = CardinalitySpec,
+    responses(
+        (status = 201, description = "The cardinality is created", body = MessageResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 409, description = "A cardinality of the name exists", body = ErrorBody),
+    )
+)]
+async fn cardinality_create(
+    State(state): State<SharedState>,
+    Json(spec): Json<CardinalitySpec>,
+) -> Result<impl IntoResponse, Error> {
+    let name = spec.name.clone();
+    state.create_cardinality(spec)?;
+    Ok((
+        StatusCode::CREATED,
+        message(format!("Cardinality '{name}' created")),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/cardinality",
+    tag = "cardinality",
+    responses((status = 200, description = "The cardinalitys", body = Vec<CardinalityInfo>))
+)]
+async fn cardinality_list(State(state): State<SharedState>) -> impl IntoResponse {
+    Json(state.list_cardinality())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/cardinality/{name}",
+    tag = "cardinality",
+    params(("name" = String, Path, description = "Name of the cardinality")),
+    responses(
+        (status = 200, description = "The cardinality is deleted", body = MessageResponse),
+        (status = 404, description = "The cardinality does not exist", body = ErrorBody),
+    )
+)]
+async fn cardinality_delete(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    state.delete_cardinality(&name)?;
+    Ok(message(format!("Cardinality '{name}' has been deleted")))
+}
+
+#[utoipa::path(
+    post,
+    path = "/cardinality/{name}/items",
+    tag = "cardinality",
+    params(("name" = String, Path, description = "Name of the cardinality")),
+    request_body(content = String, content_type = "application/octet-stream", description = "The item, as the bytes of the body"),
+    responses(
+        (status = 200, description = "The item is inserted", body = CardinalityInsertResponse),
+        (status = 404, description = "The cardinality does not exist", body = ErrorBody),
+    )
+)]
+async fn cardinality_insert(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    item: Bytes,
+) -> Result<impl IntoResponse, Error> {
+    let new = state.insert_cardinality(&name, &item)?;
+    let item = String::from_utf8_lossy(&item);
+    Ok(Json(CardinalityInsertResponse {
+        new,
+        message: if new {
+            format!("Item '{item}' inserted into cardinality '{name}'")
+        } else {
+            format!("Item '{item}' may already have been inserted into cardinality '{name}'")
+        },
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/cardinality/{name}/estimate",
+    tag = "cardinality",
+    params(("name" = String, Path, description = "Name of the cardinality")),
+    responses(
+        (status = 200, description = "The estimate of the number of distinct items", body = EstimateResponse),
+        (status = 404, description = "The cardinality does not exist", body = ErrorBody),
+    )
+)]
+async fn cardinality_estimate(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, Error> {
+    let estimate = state.estimate_cardinality(&name)?;
+    Ok(Json(EstimateResponse {
+        estimate,
+        message: format!("Cardinality '{name}' holds about {estimate} distinct items"),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/cardinality/{name}/merge",
+    tag = "cardinality",
+    params(("name" = String, Path, description = "Name of the cardinality")),
+    request_body = CardinalityMergeRequest,
+    responses(
+        (status = 200, description = "The cardinalities are merged into the cardinality", body = EstimateResponse),
+        (status = 400, description = "The cardinalities are not compatible", body = ErrorBody),
+        (status = 404, description = "The cardinality does not exist", body = ErrorBody),
+    )
+)]
+async fn cardinality_merge(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    Json(request): Json<CardinalityMergeRequest>,
+) -> Result<impl IntoResponse, Error> {
+    let estimate = state.merge_cardinality(&name, &request.sources)?;
+    Ok(Json(EstimateResponse {
+        estimate,
+        message: format!(
+            "Merged {} cardinalities into '{name}', which holds about {estimate} distinct items",
+            request.sources.len()
+        ),
+    }))
+}
+
+// --- Namespace Handlers ---
+
+/// Routers of the namespaces, built on their first request.
+#[derive(Clone, Default)]
+struct NamespaceRouters(Arc<Mutex<HashMap<String, (SharedState, Router)>>>);
+
+impl NamespaceRouters {
+    /// Returns the router of a namespace, building it again if the namespace was re-created.
+    fn get(&self, name: &str, namespace: &SharedState, config: &AppConfig) -> Router {
+        let mut routers = self.0.lock();
+        match routers.get(name) {
+            Some((store, router)) if Arc::ptr_eq(store, namespace) => router.clone(),
+            _ => {
+                let router = store_routes(config)
+                    .layer(Extension(config.clone()))
+                    .with_state(namespace.clone());
+                routers.insert(name.to_string(), (namespace.clone(), router.clone()));
+                router
+            }
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/namespaces",
+    tag = "namespaces",
+    request_body = NamespaceRequest,
+    responses(
+        (status = 201, description = "The namespace is created", body = MessageResponse),
+        (status = 400, description = "The name is invalid", body = ErrorBody),
+        (status = 409, description = "The namespace exists", body = ErrorBody),
+    )
+)]
+async fn namespaces_create(
+    State(state): State<SharedState>,
+    Json(request): Json<NamespaceRequest>,
+) -> Result<impl IntoResponse, Error> {
+    state.create_namespace(&request.name)?;
+    Ok((
+        StatusCode::CREATED,
+        message(format!("Namespace '{}' created", request.name)),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/namespaces",
+    tag = "namespaces",
+    responses((status = 200, description = "The namespaces", body = Vec<NamespaceInfo>))
+)]
+async fn namespaces_list(State(state): State<SharedState>) -> impl IntoResponse {
+    Json(state.list_namespaces())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/namespaces/{namespace}",
+    tag = "namespaces",
+    params(("namespace" = String, Path, description = "Name of the namespace")),
+    responses(
+        (status = 200, description = "The namespace and everything in it are deleted", body = MessageResponse),
+        (status = 404, description = "The namespace does not exist", body = ErrorBody),
+    )
+)]
+async fn namespaces_delete(
+    Path(name): Path<String>,
+    State(state): State<SharedState>,
+    Extension(routers): Extension<NamespaceRouters>,
+) -> Result<impl IntoResponse, Error> {
+    state.delete_namespace(&name)?;
+    routers.0.lock().remove(&name);
+    Ok(message(format!("Namespace '{name}' has been deleted")))
+}
+
+/// Serves a request under `/namespaces/<namespace>` with the routes of the namespace, e.g.
+/// `/namespaces/payments/filters` as `/filters` of the namespace `payments`.
+async fn namespace_route(
+    Path((name, path)): Path<(String, String)>,
+    State(state): State<SharedState>,
+    Extension(config): Extension<AppConfig>,
+    Extension(routers): Extension<NamespaceRouters>,
+    mut request: Request,
+) -> Result<Response, Error> {
+    if path == "namespaces" || path.starts_with("namespaces/") {
+        return Err(Error::InvalidParameters(
+            "Namespaces cannot be nested".to_string(),
+        ));
+    }
+    let namespace = state.namespace(&name)?;
+    let prefix = format!("/namespaces/{name}");
+    let uri = request
+        .uri()
+        .path_and_query()
+        .and_then(|uri| uri.as_str().strip_prefix(&prefix))
+        .and_then(|uri| uri.parse().ok())
+        .ok_or_else(|| Error::NamespaceNotFound(name.clone()))?;
+    *request.uri_mut() = uri;
+    // Otherwise the path parameters of this route would precede those of the namespace route;
+    // the credentials are kept, for the handlers checking the filters named by the request, and
+    // so is where the inserts over a WebSocket are audited
+    let access = request.extensions_mut().remove::<Access>();
+    let trail = request.extensions_mut().remove::<AuditTrail>();
+    request.extensions_mut().clear();
+    if let Some(access) = access {
+        request.extensions_mut().insert(access);
+    }
+    if let Some(trail) = trail {
+        request.extensions_mut().insert(trail);
+    }
+    let router = routers.get(&name, &namespace, &config);
+    Ok(router
+        .oneshot(request)
+        .await
+        .unwrap_or_else(|never| match never {}))
+}
+
+// --- Development Handlers ---
+
+/// Maximum number of synthetic items a development filter can be populated with.
+const MAX_FIXTURE_ITEMS: usize = 10_000_000;
+
+/// Number of the generated items returned in the response.
+const FIXTURE_SAMPLE_SIZE: usize = 10;
+
+/// Generates `count` pseudo-random items from a seed, as 16 digit hexadecimal strings.
+///
+/// The same seed always yields the same items (SplitMix64).
+fn synthetic_items(seed: u64, count: usize) -> impl Iterator<Item = String> {
+    let mut state = seed;
+    std::iter::repeat_with(move || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        format!("{:016x}", z ^ (z >> 31))
+    })
+    .take(count)
+}
+
+#[utoipa::path(
+    post,
+    path = "/dev/filters",
+    tag = "dev",
+    request_body = FixtureRequest,
+    responses(
+        (status = 201, description = "The filter is created with synthetic items", body = FixtureResponse),
+        (status = 400, description = "The parameters are invalid", body = ErrorBody),
+        (status = 409, description = "A filter of the name exists", body = ErrorBody),
+    )
+)]
+async fn dev_filters_create(
+    State(state): State<SharedState>,
+    Json(payload): Json<FixtureRequest>,
+) -> Result<impl IntoResponse, Error> {
+    if payload.items > MAX_FIXTURE_ITEMS {
+        return Err(Error::InvalidParameters(format!(
+            "items must be at most {MAX_FIXTURE_ITEMS}"
+        )));
+    }
+
+    let name = payload.filter.name.clone();
+    let id = state.create(payload.filter)?.id;
+    let items = state.insert_many(&name, synthetic_items(payload.seed, payload.items))?;
+    Ok((
+        StatusCode::CREATED,
+        Json(FixtureResponse {
+            id,
+            message: format!("Filter '{name}' created with {items} synthetic items"),
+            name,
+            items,
+            seed: payload.seed,
+            sample: synthetic_items(payload.seed, payload.items.min(FIXTURE_SAMPLE_SIZE)).collect(),
+        }),
+    ))
+}
+
+// --- Admin Handlers ---
+
+#[utoipa::path(
+    post,
+    path = "/admin/apply",
+    tag = "admin",
+    params(ApplyOptions),
+    request_body = Manifest,
+    responses(
+        (status = 200, description = "The filters and groups are reconciled with the manifest", body = ApplyReport),
+        (status = 400, description = "The manifest is invalid", body = ErrorBody),
+    )
+)]
+async fn admin_apply(
+    Query(options): Query<ApplyOptions>,
+    State(state): State<SharedState>,
+    Json(manifest): Json<Manifest>,
+) -> Result<impl IntoResponse, Error> {
+    Ok(Json(state.apply(manifest, options)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "filters",
+    responses((
+        status = 200,
+        description = "The memory used by the filters, and the evictions of the memory budget",
+        content((MemoryStats = "application/json"), (MemoryStats = "application/msgpack")),
+    ))
+)]
+async fn stats(State(state): State<SharedState>, format: Format) -> impl IntoResponse {
+    format.respond(state.memory_stats())
+}
+
+/// Reports the memory of every filter, of every namespace, and of the whole service.
+#[utoipa::path(
+    get,
+    path = "/admin/memory",
+    tag = "admin",
+    responses((
+        status = 200,
+        description = "The memory of every filter, of every namespace, and of the service",
+        content((MemoryReport = "application/json"), (MemoryReport = "application/msgpack")),
+    ))
+)]
+async fn admin_memory(State(state): State<SharedState>, format: Format) -> impl IntoResponse {
+    format.respond(state.memory_report())
+}
@@ -0,0 +1,594 @@
+#[cfg(feature = "server")]
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{header, request::Parts, HeaderMap, HeaderName},
+    response::Response,
+};
+#[cfg(feature = "server")]
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+#[cfg(feature = "server")]
+use futures_util::StreamExt;
+#[cfg(feature = "server")]
+use parking_lot::Mutex;
+#[cfg(feature = "server")]
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeSet;
+#[cfg(feature = "server")]
+use std::{io, sync::Arc, time::Duration};
+#[cfg(feature = "server")]
+use tokio::sync::mpsc;
+
+use crate::{Error, FilterSpec};
+#[cfg(feature = "server")]
+use crate::{SharedConfig, SharedState};
+
+/// Points of each node on the ring: the more, the more evenly the filters are spread.
+const POINTS_PER_NODE: u32 = 128;
+
+/// Header marking the requests proxied by a node of the ring, which their receiver serves itself
+/// whoever owns their filter, so that nodes given different rings never proxy them in circles.
+#[cfg(feature = "server")]
+pub(crate) const FORWARDED_HEADER: &str = "x-bloomsrv-forwarded";
+
+/// A consistent-hash ring over the nodes of a fleet, each owning a share of the filters of the
+/// default namespace by their names, and proxying the requests for the others to their owners.
+///
+/// Every node must be given the same nodes, itself included or not; a node joining or leaving the
+/// ring changes the owners of about the share of the filters it owns.
+///
+/// A [partitioned](ShardRing::with_partitioned) filter is rather held by every node, each owning
+/// the share of its items the ring assigns to it by their hashes, so that the filter holds more
+/// items than fit on one machine.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::ShardRing;
+///
+/// let nodes = ["http://node-2:3000".to_string(), "http://node-3:3000".to_string()];
+/// let ring = ShardRing::new("http://node-1:3000", &nodes).unwrap();
+///
+/// assert_eq!(ring.nodes().len(), 3);
+/// assert!(ring.nodes().contains(&ring.owner("login_attempts").to_string()));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ShardRing {
+    address: String,
+    nodes: Vec<String>,
+    /// The points of the nodes on the ring, in order, with the index of their node.
+    points: Vec<(u64, usize)>,
+    /// The names of the filters partitioned over the nodes by the hashes of their items.
+    partitioned: BTreeSet<String>,
+    #[cfg(feature = "server")]
+    client: reqwest::Client,
+}
+
+/// The nodes of a ring, and the owner of a filter, as served at `GET /admin/shards`.
+#[cfg(feature = "server")]
+#[derive(Clone, Debug, Serialize, utoipa::ToSchema)]
+pub(crate) struct ShardStatus {
+    address: String,
+    nodes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    partitioned: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+}
+
+impl ShardRing {
+    /// Creates the ring of the node reached at `address` and the other `nodes`, given by their
+    /// URLs, e.g. `http://node-1:3000`.
+    pub fn new(address: &str, nodes: &[String]) -> Result<Self, Error> {
+        let mut all = Vec::with_capacity(nodes.len() + 1);
+        for node in std::iter::once(address).chain(nodes.iter().map(String::as_str)) {
+            let node = node.trim_end_matches('/');
+            if !node.starts_with("http://") && !node.starts_with("https://") {
+                return Err(Error::InvalidParameters(format!(
+                    "Invalid address '{node}': expected an http:// URL"
+                )));
+            }
+            all.push(node.to_string());
+        }
+        let address = all[0].clone();
+        all.sort();
+        all.dedup();
+
+        let mut points = Vec::with_capacity(all.len() * POINTS_PER_NODE as usize);
+        for (index, node) in all.iter().enumerate() {
+            for point in 0..POINTS_PER_NODE {
+                points.push((hash(format!("{node}#{point}").as_bytes()), index));
+            }
+        }
+        points.sort_unstable();
+        Ok(ShardRing {
+            address,
+            nodes: all,
+            points,
+            partitioned: BTreeSet::new(),
+            #[cfg(feature = "server")]
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// Partitions the filters `names` over the nodes by the hashes of their items, rather than
+    /// having one node own each; every node must be given the same names.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::ShardRing;
+    ///
+    /// let nodes = ["http://node-2:3000".to_string()];
+    /// let ring = ShardRing::new("http://node-1:3000", &nodes)
+    ///     .unwrap()
+    ///     .with_partitioned(&["seen_events".to_string()]);
+    ///
+    /// assert!(ring.is_partitioned("seen_events"));
+    /// assert_eq!(ring.partition_item_count(1_000_001), 500_001);
+    /// assert!(ring.nodes().contains(&ring.item_owner(b"event-1").to_string()));
+    /// ```
+    pub fn with_partitioned(mut self, names: &[String]) -> Self {
+        self.partitioned.extend(names.iter().cloned());
+        self
+    }
+
+    /// Returns the URL of this node.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Returns the URLs of the nodes of the ring, this node included, in order.
+    pub fn nodes(&self) -> &[String] {
+        &self.nodes
+    }
+
+    /// Returns the URL of the node owning the filter `name`: the node of the first point on the
+    /// ring at or after the hash of the name.
+    pub fn owner(&self, name: &str) -> &str {
+        self.node_at(hash(name.as_bytes()))
+    }
+
+    /// Returns `true` if this node owns the filter `name`.
+    pub fn owns(&self, name: &str) -> bool {
+        self.owner(name) == self.address
+    }
+
+    /// Returns `true` if the filter `name` is partitioned over the nodes.
+    pub fn is_partitioned(&self, name: &str) -> bool {
+        self.partitioned.contains(name)
+    }
+
+    /// Returns the URL of the node owning `item` in a partitioned filter: the node of the first
+    /// point on the ring at or after the hash of the item.
+    pub fn item_owner(&self, item: &[u8]) -> &str {
+        self.node_at(hash(item))
+    }
+
+    /// Returns the number of items the partition of each node is sized for, for a partitioned
+    /// filter sized for `item_count` items.
+    pub fn partition_item_count(&self, item_count: usize) -> usize {
+        item_count.div_ceil(self.nodes.len())
+    }
+
+    /// Returns the specifications of the `filters` this node owns, and of its partitions of the
+    /// partitioned filters, e.g. to create those of the configuration file.
+    pub fn owned_filters(&self, filters: &[FilterSpec]) -> Vec<FilterSpec> {
+        filters
+            .iter()
+            .filter_map(|spec| match self.is_partitioned(&spec.name) {
+                true => Some(FilterSpec {
+                    item_count: self.partition_item_count(spec.item_count),
+                    ..spec.clone()
+                }),
+                false => self.owns(&spec.name).then(|| spec.clone()),
+            })
+            .collect()
+    }
+
+    /// Returns the node of the first point on the ring at or after `key`.
+    fn node_at(&self, key: u64) -> &str {
+        let index = self.points.partition_point(|&(point, _)| point < key);
+        let (_, node) = self.points[index % self.points.len()];
+        &self.nodes[node]
+    }
+}
+
+#[cfg(feature = "server")]
+impl ShardRing {
+    /// Describes the ring, and the owner of the filter `name`, if given; a partitioned filter is
+    /// owned by no node alone.
+    pub(crate) fn status(&self, name: Option<&str>) -> ShardStatus {
+        ShardStatus {
+            address: self.address.clone(),
+            nodes: self.nodes.clone(),
+            partitioned: self.partitioned.iter().cloned().collect(),
+            owner: name
+                .filter(|name| !self.is_partitioned(name))
+                .map(|name| self.owner(name).to_string()),
+        }
+    }
+
+    /// Proxies a request to the node `owner`, streaming the bodies of the request and of its
+    /// response.
+    pub(crate) async fn forward(&self, owner: &str, request: Request) -> Result<Response, Error> {
+        let (parts, body) = request.into_parts();
+        let uri = parts
+            .uri
+            .path_and_query()
+            .map_or_else(|| parts.uri.path(), |uri| uri.as_str());
+        // The framing of the body is kept, as a body of unknown length would not be sent with a GET
+        let length = parts.headers.get(header::CONTENT_LENGTH).cloned();
+        let chunked = parts.headers.contains_key(header::TRANSFER_ENCODING);
+        let mut headers = without_hop_headers(parts.headers);
+        headers.remove(header::HOST);
+        headers.insert(FORWARDED_HEADER, header::HeaderValue::from_static("1"));
+        match length {
+            Some(length) => {
+                headers.insert(header::CONTENT_LENGTH, length);
+            }
+            None if chunked => {
+                headers.insert(
+                    header::TRANSFER_ENCODING,
+                    header::HeaderValue::from_static("chunked"),
+                );
+            }
+            None => {}
+        }
+        let unreachable = |e: reqwest::Error| {
+            Error::Unavailable(format!(
+                "Cannot reach the node {owner} owning the filter: {e}"
+            ))
+        };
+        let response = self
+            .client
+            .request(parts.method, format!("{owner}{uri}"))
+            .headers(headers)
+            .body(stream_body(body))
+            .send()
+            .await
+            .map_err(unreachable)?;
+
+        let mut proxied = Response::builder().status(response.status());
+        if let Some(headers) = proxied.headers_mut() {
+            *headers = without_hop_headers(response.headers().clone());
+        }
+        let body = response
+            .bytes_stream()
+            .map(|chunk| chunk.map_err(io::Error::other));
+        proxied
+            .body(Body::from_stream(body))
+            .map_err(|e| Error::Unavailable(e.to_string()))
+    }
+
+    /// Sends a copy of a request, its body read whole, to every other node, returning the first
+    /// response failing, if any.
+    pub(crate) async fn broadcast(
+        &self,
+        parts: &Parts,
+        body: &Bytes,
+    ) -> Result<Option<Response>, Error> {
+        let copies = self
+            .nodes
+            .iter()
+            .filter(|node| **node != self.address)
+            .map(|node| {
+                let mut request = Request::new(Body::from(body.clone()));
+                *request.method_mut() = parts.method.clone();
+                *request.uri_mut() = parts.uri.clone();
+                *request.headers_mut() = parts.headers.clone();
+                request
+                    .headers_mut()
+                    .insert(header::CONTENT_LENGTH, body.len().into());
+                self.forward(node, request)
+            });
+        for response in futures_util::future::join_all(copies).await {
+            let response = response?;
+            if !response.status().is_success() {
+                return Ok(Some(response));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Splits the items of a batch of a partitioned filter by their owners: those of this node
+    /// are given to `local`, and those of the others sent to them at `path`, with the credentials
+    /// of `headers`. Returns the `field` of the responses for each item, in the order of the
+    /// items.
+    pub(crate) async fn scatter<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        headers: &HeaderMap,
+        field: &str,
+        items: Vec<Vec<u8>>,
+        local: impl FnOnce(Vec<Vec<u8>>) -> Result<Vec<T>, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let mut shares: Vec<(Vec<usize>, Vec<Vec<u8>>)> =
+            vec![Default::default(); self.nodes.len()];
+        for (index, item) in items.into_iter().enumerate() {
+            let owner = self.item_owner(&item);
+            let node = self
+                .nodes
+                .iter()
+                .position(|node| node == owner)
+                .unwrap_or(0);
+            shares[node].0.push(index);
+            shares[node].1.push(item);
+        }
+        let count = shares.iter().map(|(indices, _)| indices.len()).sum();
+        let mut results: Vec<Option<T>> = std::iter::repeat_with(|| None).take(count).collect();
+        let mut place = |indices: &[usize], values: Vec<T>| -> Result<(), Error> {
+            if values.len() != indices.len() {
+                return Err(Error::Unavailable(
+                    "A node answered for another number of items than sent".to_string(),
+                ));
+            }
+            for (&index, value) in indices.iter().zip(values) {
+                results[index] = Some(value);
+            }
+            Ok(())
+        };
+
+        let own = self.nodes.iter().position(|node| *node == self.address);
+        if let Some(own) = own {
+            let (indices, items) = std::mem::take(&mut shares[own]);
+            place(&indices, local(items)?)?;
+        }
+        let sent = shares
+            .iter()
+            .enumerate()
+            .filter(|(_, (indices, _))| !indices.is_empty())
+            .map(|(node, (indices, items))| async move {
+                let values = self
+                    .send_share(&self.nodes[node], path, headers, items)
+                    .await;
+                (indices, values.map(|mut values| values[field].take()))
+            });
+        for (indices, values) in futures_util::future::join_all(sent).await {
+            let values = serde_json::from_value(values?).map_err(|e| {
+                Error::Unavailable(format!("A node answered with an unexpected body: {e}"))
+            })?;
+            place(indices, values)?;
+        }
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// Sends the share of the items of a batch owned by `node`, encoded in Base64, and reads the
+    /// JSON body of its response.
+    async fn send_share(
+        &self,
+        node: &str,
+        path: &str,
+        headers: &HeaderMap,
+        items: &[Vec<u8>],
+    ) -> Result<serde_json::Value, Error> {
+        let items: Vec<String> = items.iter().map(|item| BASE64.encode(item)).collect();
+        let mut request = self
+            .client
+            .post(format!("{node}/v1{path}"))
+            .query(&[("encoding", "base64")])
+            .header(FORWARDED_HEADER, "1")
+            .json(&items);
+        for name in [header::AUTHORIZATION, HeaderName::from_static("x-api-key")] {
+            if let Some(value) = headers.get(&name) {
+                request = request.header(name, value);
+            }
+        }
+        let failed = |e: reqwest::Error| {
+            Error::Unavailable(format!(
+                "Cannot reach the node {node} of the partition: {e}"
+            ))
+        };
+        let response = request.send().await.map_err(failed)?;
+        let status = response.status();
+        let body: serde_json::Value = response.json().await.map_err(failed)?;
+        if !status.is_success() {
+            let message = body["error"].as_str().unwrap_or_default();
+            return Err(Error::Unavailable(format!(
+                "The node {node} failed the items of its partition ({status}): {message}"
+            )));
+        }
+        Ok(body)
+    }
+}
+
+/// The nodes of the shard ring of a service: those given, e.g. by the configuration file, and those
+/// discovered by resolving a DNS name, e.g. of a Kubernetes headless service. The ring of the
+/// service is replaced as they change, for the requests arriving from then on.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{AppConfig, ShardPeers, SharedConfig};
+///
+/// # async fn run() -> Result<(), bloomsrv::Error> {
+/// let config = SharedConfig::new(AppConfig::default());
+/// let peers = ShardPeers::new(config.clone(), "http://10.0.0.1:3000", &[], &[])?;
+/// peers.discover("bloomsrv.default.svc.cluster.local:3000").await?;
+///
+/// let ring = config.get().shards.unwrap();
+/// assert!(ring.nodes().contains(&"http://10.0.0.1:3000".to_string()));
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "server")]
+pub struct ShardPeers {
+    config: SharedConfig,
+    address: String,
+    partitioned: Vec<String>,
+    seeds: Mutex<Vec<String>>,
+    discovered: Mutex<Vec<String>>,
+    /// The store whose partitioned filters keep the nodes of the ring, once
+    /// [protected](ShardPeers::protect).
+    store: Mutex<Option<SharedState>>,
+}
+
+#[cfg(feature = "server")]
+impl ShardPeers {
+    /// Sets the ring of the service of `config` to the node reached at `address` and the `seeds`,
+    /// with the `partitioned` filters.
+    ///
+    /// The address of the node should be the one the others discover it at, e.g. the URL of the
+    /// IP address of its pod, so that it is not taken for another node.
+    pub fn new(
+        config: SharedConfig,
+        address: &str,
+        seeds: &[String],
+        partitioned: &[String],
+    ) -> Result<Self, Error> {
+        let peers = ShardPeers {
+            config,
+            address: address.to_string(),
+            partitioned: partitioned.to_vec(),
+            seeds: Mutex::new(seeds.to_vec()),
+            discovered: Mutex::default(),
+            store: Mutex::default(),
+        };
+        peers.update()?;
+        Ok(peers)
+    }
+
+    /// Keeps the nodes of the ring from then on while `store` holds one of the partitioned
+    /// filters: the items of its partitions would otherwise be looked up on other nodes than those
+    /// they were inserted into. Called once the nodes are first discovered, before the partitions
+    /// are created.
+    pub fn protect(&self, store: SharedState) {
+        *self.store.lock() = Some(store);
+    }
+
+    /// Replaces the nodes given, e.g. as the configuration file is reloaded.
+    pub fn set_seeds(&self, seeds: &[String]) -> Result<(), Error> {
+        let previous = std::mem::replace(&mut *self.seeds.lock(), seeds.to_vec());
+        self.update().inspect_err(|_| *self.seeds.lock() = previous)
+    }
+
+    /// Resolves `name`, a host name and a port (e.g. `bloomsrv.default.svc.cluster.local:3000`),
+    /// into the nodes of the ring, reached at the addresses it resolves to with the scheme of this
+    /// node; returns how many it resolves to.
+    pub async fn discover(&self, name: &str) -> Result<usize, Error> {
+        let unresolved =
+            |reason: String| Error::Unavailable(format!("Cannot resolve '{name}': {reason}"));
+        let scheme = self.address.split("://").next().unwrap_or("http");
+        let mut nodes: Vec<String> = tokio::net::lookup_host(name)
+            .await
+            .map_err(|e| unresolved(e.to_string()))?
+            .map(|address| format!("{scheme}://{address}"))
+            .collect();
+        if nodes.is_empty() {
+            return Err(unresolved("no addresses".to_string()));
+        }
+        nodes.sort();
+        nodes.dedup();
+        let count = nodes.len();
+        let previous = std::mem::replace(&mut *self.discovered.lock(), nodes);
+        self.update()
+            .inspect_err(|_| *self.discovered.lock() = previous)?;
+        Ok(count)
+    }
+
+    /// Resolves `name` every `interval`, replacing the ring as the nodes it resolves to change; a
+    /// name failing to resolve, or nodes that cannot replace those of the ring, leave the ring as
+    /// it is.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn_discovery(
+        self: &Arc<Self>,
+        name: String,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let peers = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(error) = peers.discover(&name).await {
+                    tracing::warn!(%error, "Cannot discover the nodes of the shard ring");
+                }
+            }
+        })
+    }
+
+    /// Builds the ring of the nodes given and discovered, and replaces that of the service if its
+    /// nodes differ; fails if they do while the partitioned filters of a
+    /// [protected](ShardPeers::protect) store exist.
+    fn update(&self) -> Result<(), Error> {
+        let nodes: Vec<String> = {
+            let (seeds, discovered) = (self.seeds.lock(), self.discovered.lock());
+            seeds.iter().chain(discovered.iter()).cloned().collect()
+        };
+        let ring = ShardRing::new(&self.address, &nodes)?.with_partitioned(&self.partitioned);
+        let mut config = self.config.get();
+        if config
+            .shards
+            .as_ref()
+            .is_some_and(|current| current.nodes() == ring.nodes())
+        {
+            return Ok(());
+        }
+        if let Some(store) = self.store.lock().as_ref() {
+            let partitioned: Vec<String> = store
+                .list()
+                .into_iter()
+                .map(|info| info.name)
+                .filter(|name| ring.is_partitioned(name))
+                .collect();
+            if !partitioned.is_empty() {
+                return Err(Error::InvalidParameters(format!(
+                    "The nodes of the shard ring cannot change while the filters partitioned over \
+                     them exist: {}",
+                    partitioned.join(", ")
+                )));
+            }
+        }
+        tracing::info!(nodes = ?ring.nodes(), "Updated the nodes of the shard ring");
+        config.shards = Some(Arc::new(ring));
+        self.config.replace(config);
+        Ok(())
+    }
+}
+
+/// Removes the headers describing a connection rather than the message, which are not proxied.
+#[cfg(feature = "server")]
+fn without_hop_headers(mut headers: HeaderMap) -> HeaderMap {
+    for name in [
+        header::CONNECTION,
+        header::CONTENT_LENGTH,
+        header::TRANSFER_ENCODING,
+        header::UPGRADE,
+    ] {
+        headers.remove(name);
+    }
+    headers
+}
+
+/// Streams the body of a request into the body of a proxied one, read as the owner reads it.
+#[cfg(feature = "server")]
+fn stream_body(body: Body) -> reqwest::Body {
+    let (sender, received) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut chunks = body.into_data_stream();
+        while let Some(chunk) = chunks.next().await {
+            let failed = chunk.is_err();
+            if sender.send(chunk.map_err(io::Error::other)).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+    let chunks = futures_util::stream::unfold(received, |mut received| async move {
+        let chunk = received.recv().await?;
+        Some((chunk, received))
+    });
+    reqwest::Body::wrap_stream(chunks)
+}
+
+/// Hashes a name or a point of the ring: FNV-1a, mixed by the finalizer of SplitMix64 so that
+/// similar names land far apart.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for &byte in bytes {
+        hash = (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{filter::fnv1a, Error};
+
+/// Default number of counters per row of a sketch.
+const DEFAULT_WIDTH: usize = 2048;
+/// Default number of rows (hash functions) of a sketch.
+const DEFAULT_DEPTH: usize = 4;
+/// Identifies a sketch file.
+const SKETCH_MAGIC: &[u8; 8] = b"BLOOMCMS";
+/// Version of the sketch file format.
+const SKETCH_VERSION: u32 = 1;
+/// Size of the header of a sketch file, in bytes.
+const SKETCH_HEADER_LEN: usize = 32;
+
+/// Parameters of a Count-Min sketch to be created.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::SketchSpec;
+///
+/// let spec = SketchSpec {
+///     name: "page_views".to_string(),
+///     width: Some(4096),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Parameters of a Count-Min sketch to be created.")
+)]
+pub struct SketchSpec {
+    pub name: String,
+    /// Counters per row (default 2048); wider sketches overcount less.
+    pub width: Option<usize>,
+    /// Rows (default 4); deeper sketches overcount less often.
+    pub depth: Option<usize>,
+}
+
+/// Summary of a sketch, as listed by [`FilterStore::list_sketches`](crate::FilterStore::list_sketches).
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Summary of a sketch.")
+)]
+pub struct SketchInfo {
+    pub name: String,
+    pub width: usize,
+    pub depth: usize,
+    /// Sum of all increments.
+    pub total: u64,
+    pub memory_usage_bytes: usize,
+}
+
+/// A Count-Min sketch, estimating how often each item was counted.
+///
+/// Each row counts every item in one of its counters; the estimate of an item is the smallest of
+/// its counters. Estimates never undercount, and overcount by at most `e / width` of the total
+/// count with a probability of `1 - e^-depth`. Positions are derived from a stable hash of the
+/// item, so sketches can be saved and loaded by other builds of the service.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{CountMinSketch, SketchSpec};
+///
+/// let spec = SketchSpec { name: "page_views".to_string(), ..Default::default() };
+/// let mut sketch = CountMinSketch::new(&spec).unwrap();
+/// sketch.increment(b"/home", 3);
+/// sketch.increment(b"/home", 1);
+/// assert_eq!(sketch.estimate(b"/home"), 4);
+/// assert_eq!(sketch.estimate(b"/about"), 0);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountMinSketch {
+    pub name: String,
+    pub width: usize,
+    pub depth: usize,
+    total: u64,
+    counters: Vec<u64>,
+}
+
+impl CountMinSketch {
+    /// Validates a specification and creates the corresponding, empty sketch.
+    pub fn new(spec: &SketchSpec) -> Result<Self, Error> {
+        let width = spec.width.unwrap_or(DEFAULT_WIDTH);
+        let depth = spec.depth.unwrap_or(DEFAULT_DEPTH);
+        check_dimensions(width, depth)?;
+        Ok(CountMinSketch {
+            name: spec.name.clone(),
+            width,
+            depth,
+            total: 0,
+            counters: vec![0; width * depth],
+        })
+    }
+
+    /// Returns the sum of all increments.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the memory used by the counters, in bytes.
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.counters.len() * std::mem::size_of::<u64>()
+    }
+
+    /// Counts an item `count` times, returning its new estimate.
+    pub fn increment(&mut self, item: &[u8], count: u64) -> u64 {
+        self.total = self.total.saturating_add(count);
+        let mut estimate = u64::MAX;
+        for cell in self.cells(item) {
+            let counter = &mut self.counters[cell];
+            *counter = counter.saturating_add(count);
+            estimate = estimate.min(*counter);
+        }
+        estimate
+    }
+
+    /// Estimates how often an item was counted.
+    pub fn estimate(&self, item: &[u8]) -> u64 {
+        self.cells(item)
+            .map(|cell| self.counters[cell])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Returns the index of the counter of an item in each row.
+    fn cells(&self, item: &[u8]) -> impl Iterator<Item = usize> {
+        let hash = fnv1a(item);
+        // Double hashing: the rows use h1 + i * h2 for independent-enough positions
+        let (h1, h2) = (hash as u32 as u64, (hash >> 32) | 1);
+        let width = self.width;
+        (0..self.depth).map(move |row| {
+            let column = h1.wrapping_add((row as u64).wrapping_mul(h2)) % width as u64;
+            row * width + column as usize
+        })
+    }
+
+    /// Encodes the sketch as a sketch file.
+    ///
+    /// The binary format is little-endian: a 32-byte header (the magic `BLOOMCMS`, the format
+    /// version, the depth, the width and the total count), the counters row by row in 64 bits
+    /// each, and a 64-bit FNV-1a checksum of everything before it.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SKETCH_HEADER_LEN + self.memory_usage_bytes() + 8);
+        bytes.extend_from_slice(SKETCH_MAGIC);
+        bytes.extend_from_slice(&SKETCH_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.depth as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.width as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.total.to_le_bytes());
+        for counter in &self.counters {
+            bytes.extend_from_slice(&counter.to_le_bytes());
+        }
+        let checksum = fnv1a(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes a sketch file as the sketch `name`, verifying its checksum and size.
+    pub fn decode(name: &str, bytes: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Err(Error::InvalidParameters(reason.to_string()));
+        if bytes.len() < SKETCH_HEADER_LEN + 8 || &bytes[..8] != SKETCH_MAGIC {
+            return invalid("Not a sketch file");
+        }
+        let (content, checksum) = bytes.split_at(bytes.len() - 8);
+        if fnv1a(content) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+            return invalid("The checksum of the sketch file does not match");
+        }
+
+        let u32_at = |at: usize| u32::from_le_bytes(content[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(content[at..at + 8].try_into().unwrap());
+        if u32_at(8) != SKETCH_VERSION {
+            return invalid("Unsupported sketch file version");
+        }
+        let depth = u32_at(12) as usize;
+        let Ok(width) = usize::try_from(u64_at(16)) else {
+            return invalid("Invalid sketch width");
+        };
+        check_dimensions(width, depth)?;
+        let counters = &content[SKETCH_HEADER_LEN..];
+        if counters.len() != width * depth * 8 {
+            return invalid("The counters of the sketch do not match its declared size");
+        }
+        Ok(CountMinSketch {
+            name: name.to_string(),
+            width,
+            depth,
+            total: u64_at(24),
+            counters: counters
+                .chunks_exact(8)
+                .map(|counter| u64::from_le_bytes(counter.try_into().unwrap()))
+                .collect(),
+        })
+    }
+}
+
+fn check_dimensions(width: usize, depth: usize) -> Result<(), Error> {
+    let invalid = |reason: &str| Err(Error::InvalidParameters(reason.to_string()));
+    if !(1..=1 << 24).contains(&width) {
+        return invalid("width must be between 1 and 16777216");
+    }
+    if !(1..=16).contains(&depth) {
+        return invalid("depth must be between 1 and 16");
+    }
+    Ok(())
+}
@@ -0,0 +1,3315 @@
+use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+#[cfg(feature = "wasm")]
+use crate::Transform;
+use crate::{
+    auth::ApiKey,
+    budget::{check_outgrown, Evictions, Outgrown, Quotas},
+    events::{Change, Events},
+    journal::{journal_path, ItemChange, ItemJournal},
+    persist::mapped_path,
+    wal::{Journal, Operation},
+    BloomFilter, CardinalityInfo, CardinalitySpec, CountMinSketch, Error, FilterDiff, FilterEvent,
+    FilterKind, FilterOverlap, FilterSnapshot, FilterStats, HashScheme, HashTrace, HyperLogLog,
+    LimiterDecision, LimiterSpec, MemoryBudget, NamePolicy, RateLimiter, RecentFilter, RecentInfo,
+    RecentSpec, SharedState, SketchInfo, SketchSpec, StableInfo, StableParams, Storage,
+};
+
+// --- Data Structures ---
+
+/// Container holding the filter and its configuration.
+///
+/// This struct is used to store the state of a specific bloom filter
+/// inside the store, behind a lock of its own.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{FilterContainer, CreationMode};
+/// // Note: Constructed by the store (see `FilterStore::create`)
+/// // This is just a structural example.
+/// ```
+pub struct FilterContainer {
+    pub id: String,
+    pub name: String,
+    pub filter: BloomFilter,
+    pub capacity: usize,
+    pub creation_mode: CreationMode,
+    /// When the filter was created in (or uploaded to) this service, in seconds since the Unix
+    /// epoch; kept as the filter is saved and loaded again.
+    pub created_at: u64,
+    /// Number of the generation of the bits, starting at 1 and increased whenever they are cleared
+    /// or replaced (e.g. rebuilt, merged into, or uploaded), but not by inserts.
+    pub generation: u64,
+    pub expiry: Option<Expiry>,
+    pub idle_timeout: Option<Duration>,
+    pub last_accessed: Mutex<Instant>,
+    pub shadow: Option<Shadow>,
+    pub labels: BTreeMap<String, String>,
+    /// How the filter has been used since it was created in (or loaded into) this service.
+    pub usage: UsageCounters,
+    /// WASM module applied to items before they are inserted or looked up.
+    #[cfg(feature = "wasm")]
+    pub transform: Option<Transform>,
+    /// Journal of the items of the filter, if it keeps one, to rebuild it from.
+    pub(crate) journal: Option<Arc<ItemJournal>>,
+}
+
+impl FilterContainer {
+    /// Builds the container of a filter installed as `name`, with none of its metadata.
+    pub(crate) fn of(name: &str, snapshot: FilterSnapshot) -> Result<Self, Error> {
+        let (false_positive_rate, hash_count) = match snapshot.creation_mode {
+            CreationMode::FalsePositiveRate(rate) => (Some(rate), None),
+            CreationMode::HashCount(count) => (None, Some(count)),
+        };
+        let mut container = FilterSpec {
+            name: name.to_string(),
+            item_count: snapshot.item_count,
+            false_positive_rate,
+            hash_count,
+            ..Default::default()
+        }
+        .build(None)?;
+        container.filter = snapshot.filter;
+        Ok(container)
+    }
+
+    /// Prepares an item for the filter, applying its transform if one is loaded.
+    pub fn prepare<'a>(&self, item: &'a [u8]) -> Result<Cow<'a, [u8]>, Error> {
+        #[cfg(feature = "wasm")]
+        if let Some(transform) = &self.transform {
+            return match transform.apply(item) {
+                Ok(Some(item)) => Ok(Cow::Owned(item)),
+                Ok(None) => Err(Error::ItemRejected(self.name.clone())),
+                Err(reason) => Err(Error::TransformFailed {
+                    filter: self.name.clone(),
+                    reason,
+                }),
+            };
+        }
+        Ok(Cow::Borrowed(item))
+    }
+
+    /// Records an item inserted into, or removed from, the filter in its item journal, if any.
+    fn journal_item(&self, change: ItemChange, item: &[u8]) -> Result<(), Error> {
+        self.journal
+            .as_ref()
+            .map_or(Ok(()), |journal| journal.record(change, item))
+    }
+
+    /// Empties the item journal of the filter, if any, as the filter is cleared.
+    fn clear_journal(&self) -> Result<(), Error> {
+        self.journal
+            .as_ref()
+            .map_or(Ok(()), |journal| journal.clear())
+    }
+
+    /// Fails with [`Error::PreconditionFailed`] unless the filter has one of the `expected`
+    /// [entity tags](FilterInfo::etag), if any.
+    pub(crate) fn check_expected(&self, expected: Option<&[String]>) -> Result<(), Error> {
+        let etag = entity_tag(&self.id, self.generation);
+        match expected {
+            Some(tags) if !tags.contains(&etag) => {
+                Err(Error::PreconditionFailed(self.name.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records that the filter has just been used.
+    pub fn touch(&self) {
+        *self.last_accessed.lock() = Instant::now();
+        self.usage
+            .last_accessed_at
+            .store(unix_seconds(SystemTime::now()), Ordering::Relaxed);
+    }
+
+    /// Returns the summary of the filter at `now`.
+    pub fn info(&self, now: Instant) -> FilterInfo {
+        FilterInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            capacity: self.capacity,
+            creation_mode: self.creation_mode,
+            created_at: self.created_at,
+            generation: self.generation,
+            bit_count: self.filter.bit_count(),
+            hash_count: self.filter.hash_count(),
+            storage: self.filter.storage(),
+            kind: self.filter.kind(),
+            scheme: self.filter.scheme(),
+            seed: self.filter.seed(),
+            slice_count: self.filter.slice_count(),
+            stable: self.filter.stable_info(),
+            on_full: FullAction::of(&self.filter),
+            rotations: self.filter.rotations(),
+            memory_usage_bytes: self.filter.memory_usage_bytes(),
+            mapped_bytes: self.filter.mapped_bytes(),
+            ttl_remaining_seconds: self.expiry.map(|e| e.remaining_seconds(now)),
+            labels: self.labels.clone(),
+            usage: self.usage.snapshot(),
+            evicted: false,
+        }
+    }
+
+    /// Returns `true` if the filter has an idle timeout and has not been used for that long at `now`.
+    pub fn is_idle(&self, now: Instant) -> bool {
+        self.idle_timeout.is_some_and(|timeout| {
+            now.saturating_duration_since(*self.last_accessed.lock()) >= timeout
+        })
+    }
+}
+
+/// Defines how the Bloom Filter was calculated during creation.
+///
+/// This is stored so that if we need to "clear" (re-create) the filter,
+/// we know which parameters to use.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::CreationMode;
+///
+/// let mode_rate = CreationMode::FalsePositiveRate(0.01);
+/// let mode_hash = CreationMode::HashCount(5);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CreationMode {
+    FalsePositiveRate(f64),
+    HashCount(u32),
+}
+
+/// A shadow filter receiving a copy of every write to another (primary) filter.
+///
+/// Lookups on the primary filter are also answered by the shadow filter, and the
+/// answers are compared, so that new filter parameters can be evaluated against
+/// live traffic before cutting over.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::Shadow;
+///
+/// let shadow = Shadow::new("login_attempts_v2");
+/// assert_eq!(shadow.filter, "login_attempts_v2");
+/// ```
+#[derive(Debug)]
+pub struct Shadow {
+    pub filter: String,
+    pub stats: ShadowStats,
+}
+
+impl Shadow {
+    /// Creates a shadow configuration mirroring writes to the named filter.
+    pub fn new(filter: impl Into<String>) -> Self {
+        Shadow {
+            filter: filter.into(),
+            stats: ShadowStats::default(),
+        }
+    }
+}
+
+/// Counts of how the answers of a primary filter and its shadow filter compare.
+#[derive(Debug, Default)]
+pub struct ShadowStats {
+    /// Lookups answered by both filters.
+    pub compared: AtomicU64,
+    /// Lookups both filters answered the same way.
+    pub agreed: AtomicU64,
+    /// Lookups only the primary filter answered positively.
+    pub primary_only: AtomicU64,
+    /// Lookups only the shadow filter answered positively.
+    pub shadow_only: AtomicU64,
+}
+
+impl ShadowStats {
+    /// Records the answers of the primary and the shadow filter to the same lookup.
+    pub fn record(&self, primary: bool, shadow: bool) {
+        self.compared.fetch_add(1, Ordering::Relaxed);
+        let outcome = match (primary, shadow) {
+            (true, false) => &self.primary_only,
+            (false, true) => &self.shadow_only,
+            _ => &self.agreed,
+        };
+        outcome.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts of the operations on a filter, updated as it is used.
+#[derive(Debug, Default)]
+pub struct UsageCounters {
+    /// Items inserted, including those mirrored from a primary filter.
+    pub inserts: AtomicU64,
+    /// Items looked up.
+    pub lookups: AtomicU64,
+    /// Lookups answered positively.
+    pub positive_lookups: AtomicU64,
+    /// When the filter was last used (or created), in seconds since the Unix epoch.
+    pub last_accessed_at: AtomicU64,
+}
+
+impl UsageCounters {
+    /// Creates counters for a filter created at `created_at`, in seconds since the Unix epoch.
+    pub fn new(created_at: u64) -> Self {
+        UsageCounters {
+            last_accessed_at: AtomicU64::new(created_at),
+            ..Self::default()
+        }
+    }
+
+    /// Records an item inserted.
+    pub fn record_insert(&self) {
+        self.inserts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the answer to a lookup.
+    pub fn record_lookup(&self, positive: bool) {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        if positive {
+            self.positive_lookups.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the current counts.
+    pub fn snapshot(&self) -> FilterUsage {
+        let lookups = self.lookups.load(Ordering::Relaxed);
+        let positive_lookups = self.positive_lookups.load(Ordering::Relaxed);
+        FilterUsage {
+            inserts: self.inserts.load(Ordering::Relaxed),
+            lookups,
+            positive_lookups,
+            negative_lookups: lookups.saturating_sub(positive_lookups),
+            last_accessed_at: self.last_accessed_at.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// How a filter has been used, as counted by [`UsageCounters`].
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{FilterSpec, FilterStore};
+///
+/// let store = FilterStore::default();
+/// store
+///     .create(FilterSpec {
+///         name: "sessions".to_string(),
+///         item_count: 1000,
+///         false_positive_rate: Some(0.01),
+///         ..Default::default()
+///     })
+///     .unwrap();
+/// store.insert("sessions", "alice").unwrap();
+/// store.contains("sessions", "alice").unwrap();
+/// store.contains("sessions", "bob").unwrap();
+///
+/// let (info, _) = store.describe("sessions").unwrap();
+/// assert_eq!(info.usage.inserts, 1);
+/// assert_eq!(info.usage.lookups, 2);
+/// assert_eq!(info.usage.positive_lookups, 1);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "How a filter has been used.")
+)]
+pub struct FilterUsage {
+    pub inserts: u64,
+    pub lookups: u64,
+    pub positive_lookups: u64,
+    pub negative_lookups: u64,
+    /// When the filter was last used (or created), in seconds since the Unix epoch.
+    pub last_accessed_at: u64,
+}
+
+/// What happens to a filter once its time-to-live has elapsed.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::ExpiryAction;
+///
+/// assert_eq!(ExpiryAction::default(), ExpiryAction::Delete);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "What happens to a filter once its time-to-live has elapsed.")
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpiryAction {
+    /// Remove the filter from the state entirely.
+    #[default]
+    Delete,
+    /// Reset all bits and start a new time-to-live period.
+    Clear,
+}
+
+/// What a filter does once its bits fill up (with as many items as it was created for),
+/// instead of answering with a growing false positive rate.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{FilterKind, FilterSpec, FilterStore, FullAction};
+///
+/// let store = FilterStore::default();
+/// let info = store
+///     .create(FilterSpec {
+///         name: "sessions".to_string(),
+///         item_count: 1000,
+///         false_positive_rate: Some(0.01),
+///         on_full: Some(FullAction::Rotate),
+///         ..Default::default()
+///     })
+///     .unwrap();
+/// assert_eq!(info.kind, FilterKind::Standard);
+/// assert_eq!(info.on_full, Some(FullAction::Rotate));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "What a filter does once its bits fill up.")
+)]
+#[serde(rename_all = "lowercase")]
+pub enum FullAction {
+    /// Start over with fresh bits, keeping the full ones for lookups until the next rotation.
+    Rotate,
+    /// Grow, by adding larger slices, as [scalable](FilterKind::Scalable) filters do.
+    Scale,
+}
+
+impl FullAction {
+    /// Returns what a filter does once full, if anything.
+    pub(crate) fn of(filter: &BloomFilter) -> Option<Self> {
+        if filter.rotations().is_some() {
+            Some(FullAction::Rotate)
+        } else if filter.kind() == FilterKind::Scalable {
+            Some(FullAction::Scale)
+        } else {
+            None
+        }
+    }
+}
+
+/// Time-to-live configuration of a filter.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{Expiry, ExpiryAction};
+/// use std::time::{Duration, Instant};
+///
+/// let now = Instant::now();
+/// let expiry = Expiry::new(Duration::from_secs(60), ExpiryAction::Clear, now).unwrap();
+///
+/// assert!(!expiry.is_expired(now));
+/// assert!(expiry.is_expired(now + Duration::from_secs(60)));
+/// assert!(Expiry::new(Duration::MAX, ExpiryAction::Clear, now).is_err());
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Expiry {
+    pub ttl: Duration,
+    pub action: ExpiryAction,
+    pub expires_at: Instant,
+}
+
+impl Expiry {
+    /// Creates an expiry that elapses `ttl` after `now`, failing if that is beyond the times the
+    /// clock can tell.
+    pub fn new(ttl: Duration, action: ExpiryAction, now: Instant) -> Result<Self, Error> {
+        let expires_at = now.checked_add(ttl).ok_or_else(|| {
+            Error::InvalidParameters(format!("ttl_seconds of {} is too large", ttl.as_secs()))
+        })?;
+        Ok(Expiry {
+            ttl,
+            action,
+            expires_at,
+        })
+    }
+
+    /// Returns `true` if the time-to-live has elapsed at `now`.
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now >= self.expires_at
+    }
+
+    /// Returns the time left until expiry, rounded up to whole seconds.
+    pub fn remaining_seconds(&self, now: Instant) -> u64 {
+        let remaining = self.expires_at.saturating_duration_since(now);
+        remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)
+    }
+}
+
+/// A named set of filters operated on together.
+///
+/// Groups refer to their member filters by name. Deleting a filter
+/// removes it from every group it belongs to.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::FilterGroup;
+///
+/// let group = FilterGroup {
+///     name: "dedup".to_string(),
+///     filters: vec!["hourly".to_string(), "daily".to_string()],
+/// };
+/// ```
+#[derive(Clone, Debug)]
+pub struct FilterGroup {
+    pub name: String,
+    pub filters: Vec<String>,
+}
+
+// --- Specifications ---
+
+/// Parameters of a filter to be created.
+///
+/// Exactly one of `false_positive_rate` and `hash_count` must be given.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::FilterSpec;
+///
+/// let spec = FilterSpec {
+///     name: "login_attempts".to_string(),
+///     item_count: 1000,
+///     false_positive_rate: Some(0.01),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Parameters of a filter to be created; exactly one of \
+        `false_positive_rate` and `hash_count` must be given.")
+)]
+pub struct FilterSpec {
+    /// May be omitted where the name is given otherwise, e.g. in the path of a request.
+    #[serde(default)]
+    pub name: String,
+    pub item_count: usize,
+    pub hash_count: Option<u32>,
+    pub false_positive_rate: Option<f64>,
+    pub ttl_seconds: Option<u64>,
+    #[serde(default)]
+    pub on_expire: ExpiryAction,
+    pub expire_after_idle_seconds: Option<u64>,
+    /// Where the bits are kept; memory-mapped filters need the store to have a data directory.
+    #[serde(default)]
+    pub storage: Storage,
+    /// Counting filters can also remove items.
+    #[serde(default)]
+    pub kind: FilterKind,
+    /// How a stable filter forgets items; defaults apply if absent.
+    pub stable: Option<StableParams>,
+    /// What a standard filter does once full: rotate, or scale (making it a scalable filter);
+    /// nothing by default.
+    pub on_full: Option<FullAction>,
+    /// How the positions of items are derived from their bytes, e.g. to lay out the bits like
+    /// the filters built by another tool, or on another node, so that they can be merged.
+    #[serde(default)]
+    pub scheme: HashScheme,
+    /// Seed of the hashes of the scheme (0 by default).
+    #[serde(default)]
+    pub seed: u64,
+    /// Arbitrary key/value metadata, e.g. the team owning the filter.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Keeps a journal of the items inserted, in the data directory of the store, for the filter
+    /// to be [rebuilt](FilterStore::rebuild_from_journal) from with other parameters.
+    #[serde(default)]
+    pub journal: bool,
+}
+
+impl FilterSpec {
+    /// Resolves the requested creation mode.
+    fn creation_mode(&self) -> Result<CreationMode, Error> {
+        match (self.false_positive_rate, self.hash_count) {
+            (Some(false_positive_rate), None) => {
+                Ok(CreationMode::FalsePositiveRate(false_positive_rate))
+            }
+            (None, Some(hash_count)) => Ok(CreationMode::HashCount(hash_count)),
+            (Some(_), Some(_)) => Err(Error::InvalidParameters(
+                "Must provide either false_positive_rate or hash_count, not both".to_string(),
+            )),
+            (None, None) => Err(Error::InvalidParameters(
+                "Must provide either false_positive_rate or hash_count".to_string(),
+            )),
+        }
+    }
+
+    /// Resolves the kind of the filter: scalable, for standard filters scaling once full.
+    fn resolved_kind(&self) -> Result<FilterKind, Error> {
+        match (self.on_full, self.kind) {
+            (None, kind) => Ok(kind),
+            (Some(FullAction::Scale), FilterKind::Standard | FilterKind::Scalable) => {
+                Ok(FilterKind::Scalable)
+            }
+            (Some(FullAction::Rotate), FilterKind::Standard) => Ok(FilterKind::Standard),
+            (Some(_), _) => Err(Error::InvalidParameters(
+                "Only standard filters take an on_full action".to_string(),
+            )),
+        }
+    }
+
+    /// Returns the bytes the filter would take on the heap once created, without creating it; a
+    /// rotating filter counts with its previous generation.
+    pub(crate) fn memory_usage_bytes(&self) -> Result<usize, Error> {
+        if self.storage == Storage::Mmap {
+            return Ok(0);
+        }
+        let bytes = BloomFilter::heap_bytes(
+            self.item_count,
+            self.creation_mode()?,
+            self.resolved_kind()?,
+        )?;
+        Ok(match self.on_full {
+            Some(FullAction::Rotate) => bytes.saturating_mul(2),
+            _ => bytes,
+        })
+    }
+
+    /// Validates the time-to-live and idle settings.
+    fn validate_expiry(&self) -> Result<(), Error> {
+        if self.ttl_seconds == Some(0) {
+            return Err(Error::InvalidParameters(
+                "ttl_seconds must be greater than 0".to_string(),
+            ));
+        }
+
+        if self.expire_after_idle_seconds == Some(0) {
+            return Err(Error::InvalidParameters(
+                "expire_after_idle_seconds must be greater than 0".to_string(),
+            ));
+        }
+        self.expiry(Instant::now())?;
+
+        Ok(())
+    }
+
+    /// Returns whether an existing filter was created with the parameters of this specification,
+    /// i.e. the same expected number of items, creation mode, kind, action once full, hash
+    /// scheme and seed, and stable parameters.
+    fn matches(&self, existing: &FilterContainer) -> Result<bool, Error> {
+        let rotates = self.on_full == Some(FullAction::Rotate);
+        let filter = &existing.filter;
+        let seed = self
+            .scheme
+            .resolve_seed(self.seed, filter.bit_count(), filter.hash_count());
+        Ok(self.creation_mode()? == existing.creation_mode
+            && self.item_count == existing.capacity
+            && self.resolved_kind()? == existing.filter.kind()
+            && rotates == existing.filter.rotations().is_some()
+            && (self.scheme, seed) == (filter.scheme(), filter.seed())
+            && stable_matches(self.stable, existing.filter.stable_params()))
+    }
+
+    /// Returns the expiry configured by this specification, starting at `now`.
+    fn expiry(&self, now: Instant) -> Result<Option<Expiry>, Error> {
+        self.ttl_seconds
+            .map(|ttl| Expiry::new(Duration::from_secs(ttl), self.on_expire, now))
+            .transpose()
+    }
+
+    /// Validates the specification and builds the corresponding, empty filter container.
+    ///
+    /// A memory-mapped filter gets a new file in `data_dir`, replacing any file of the same name;
+    /// so does a filter keeping a journal of its items.
+    pub(crate) fn build(&self, data_dir: Option<&Path>) -> Result<FilterContainer, Error> {
+        self.validate_expiry()?;
+        validate_labels(&self.labels)?;
+
+        let creation_mode = self.creation_mode()?;
+        if self.stable.is_some() && self.kind != FilterKind::Stable {
+            return Err(Error::InvalidParameters(
+                "Only stable filters take stable parameters".to_string(),
+            ));
+        }
+        self.scheme.check_seed(self.seed)?;
+        let filter = match (self.storage, self.resolved_kind()?) {
+            (Storage::Memory, FilterKind::Standard) => {
+                BloomFilter::new(self.item_count, creation_mode)?
+            }
+            (Storage::Memory, FilterKind::Counting) => {
+                BloomFilter::counting(self.item_count, creation_mode)?
+            }
+            (Storage::Memory, FilterKind::Scalable) => {
+                BloomFilter::scalable(self.item_count, creation_mode)?
+            }
+            (Storage::Memory, FilterKind::Stable) => BloomFilter::stable(
+                self.item_count,
+                creation_mode,
+                self.stable.unwrap_or_default(),
+            )?,
+            (Storage::Atomic, FilterKind::Standard) => {
+                BloomFilter::new(self.item_count, creation_mode)?.into_atomic()?
+            }
+            (Storage::Atomic, FilterKind::Counting | FilterKind::Scalable | FilterKind::Stable) => {
+                return Err(Error::InvalidParameters(
+                    "Only standard filters can have atomic storage".to_string(),
+                ))
+            }
+            (Storage::Mmap, FilterKind::Counting | FilterKind::Scalable | FilterKind::Stable) => {
+                return Err(Error::InvalidParameters(
+                    "Only standard filters can be memory-mapped".to_string(),
+                ))
+            }
+            (Storage::Mmap, FilterKind::Standard) => {
+                let dir = data_dir.ok_or_else(|| {
+                    Error::InvalidParameters(
+                        "Memory-mapped filters require a data directory".to_string(),
+                    )
+                })?;
+                let path = mapped_path(dir, &self.name);
+                let hashing = (self.scheme, self.seed);
+                FilterSnapshot::create_mapped(&path, self.item_count, creation_mode, hashing)?
+                    .filter
+            }
+        };
+        let mut filter = filter.with_hashing(self.scheme, self.seed)?;
+        if self.on_full == Some(FullAction::Rotate) {
+            filter = filter.with_rotation()?;
+        }
+        let journal = match (self.journal, data_dir) {
+            (false, _) => None,
+            (true, _) if self.storage == Storage::Mmap => {
+                return Err(Error::InvalidParameters(
+                    "Memory-mapped filters cannot keep a journal of their items, as they cannot be rebuilt"
+                        .to_string(),
+                ))
+            }
+            (true, Some(dir)) => {
+                let journal = ItemJournal::create(&journal_path(dir, &self.name))
+                    .map_err(|e| Error::Storage(e.to_string()))?;
+                Some(Arc::new(journal))
+            }
+            (true, None) => {
+                return Err(Error::InvalidParameters(
+                    "Filters keeping a journal of their items require a data directory".to_string(),
+                ))
+            }
+        };
+
+        let created_at = unix_seconds(SystemTime::now());
+        Ok(FilterContainer {
+            id: new_id().to_string(),
+            name: self.name.clone(),
+            filter,
+            capacity: self.item_count,
+            creation_mode,
+            created_at,
+            generation: 1,
+            expiry: self.expiry(Instant::now())?,
+            idle_timeout: self.expire_after_idle_seconds.map(Duration::from_secs),
+            last_accessed: Mutex::new(Instant::now()),
+            shadow: None,
+            labels: self.labels.clone(),
+            usage: UsageCounters::new(created_at),
+            #[cfg(feature = "wasm")]
+            transform: None,
+            journal,
+        })
+    }
+}
+
+/// Members of a filter group to be created.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Members of a filter group to be created.")
+)]
+pub struct GroupSpec {
+    pub name: String,
+    pub filters: Vec<String>,
+}
+
+/// How the answers of the member filters of a group are combined.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "How the answers of the member filters of a group are combined.")
+)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupLookupMode {
+    /// The item may have been seen if any member filter may have seen it.
+    #[default]
+    Any,
+    /// The item may have been seen only if all member filters may have seen it.
+    All,
+}
+
+/// The desired set of filters and groups, reconciled by [`FilterStore::apply`].
+#[derive(Clone, Debug, Default, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "The desired set of filters and groups of a store.")
+)]
+pub struct Manifest {
+    #[serde(default)]
+    pub filters: Vec<FilterSpec>,
+    #[serde(default)]
+    pub groups: Vec<GroupSpec>,
+}
+
+/// Options of [`FilterStore::apply`].
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct ApplyOptions {
+    /// Delete filters and groups that are not in the manifest.
+    #[serde(default)]
+    pub prune: bool,
+    /// Report the changes without applying them.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// How [`FilterStore::upload`] installs a prebuilt filter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "How a prebuilt filter is installed.")
+)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadMode {
+    /// Create a new filter, failing if the name is already in use.
+    #[default]
+    Create,
+    /// Create a new filter, or replace the bits and parameters of an existing one.
+    Replace,
+    /// Add the items of the prebuilt filter to an existing filter with the same parameters.
+    Merge,
+}
+
+// --- Reports ---
+
+/// Summary of a filter, as listed by [`FilterStore::list`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilterInfo {
+    pub id: String,
+    pub name: String,
+    pub capacity: usize,
+    pub creation_mode: CreationMode,
+    /// When the filter was created in (or uploaded to) this service, in seconds since the Unix
+    /// epoch; kept as the filter is saved and loaded again.
+    pub created_at: u64,
+    /// Generation of the bits, increased whenever they are cleared or replaced.
+    pub generation: u64,
+    /// Size of the filter, in bits, as resolved from the creation parameters.
+    pub bit_count: u64,
+    /// Number of hash functions, as resolved from the creation parameters.
+    pub hash_count: u32,
+    pub storage: Storage,
+    pub kind: FilterKind,
+    pub scheme: HashScheme,
+    pub seed: u64,
+    /// Number of slices, more than one once a scalable filter has grown.
+    pub slice_count: usize,
+    /// The parameters and guarantees of a stable filter.
+    pub stable: Option<StableInfo>,
+    pub on_full: Option<FullAction>,
+    /// Number of times a rotating filter rotated.
+    pub rotations: Option<u64>,
+    pub memory_usage_bytes: usize,
+    /// Size of the memory-mapped bits, which do not count towards `memory_usage_bytes`.
+    pub mapped_bytes: usize,
+    pub ttl_remaining_seconds: Option<u64>,
+    pub labels: BTreeMap<String, String>,
+    pub usage: FilterUsage,
+    /// Whether the filter is evicted to disk by the [memory budget](MemoryBudget), as it was
+    /// then, using no memory until it is reloaded as it is next used.
+    pub evicted: bool,
+}
+
+impl FilterInfo {
+    /// Returns the entity tag of the filter, as sent in the `ETag` header of its reads: its id and
+    /// generation, quoted. Changes conditional on it fail once the filter was cleared, replaced or
+    /// created again since.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterSpec, FilterStore};
+    ///
+    /// let store = FilterStore::new();
+    /// let spec = FilterSpec {
+    ///     name: "login_attempts".to_string(),
+    ///     item_count: 1000,
+    ///     false_positive_rate: Some(0.01),
+    ///     ..Default::default()
+    /// };
+    /// let created = store.create(spec).unwrap();
+    /// assert_eq!(created.generation, 1);
+    ///
+    /// store.insert("login_attempts", "user@example.com").unwrap();
+    /// store.clear("login_attempts").unwrap();
+    /// let (cleared, _) = store.describe("login_attempts").unwrap();
+    /// assert_eq!(cleared.generation, 2);
+    /// assert_ne!(cleared.etag(), created.etag());
+    /// ```
+    pub fn etag(&self) -> String {
+        entity_tag(&self.id, self.generation)
+    }
+}
+
+/// Summary of a rate limiter, as listed by [`FilterStore::list_limiters`].
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Summary of a rate limiter.")
+)]
+pub struct LimiterInfo {
+    pub name: String,
+    pub limit: u64,
+    pub window_seconds: u64,
+    pub width: usize,
+    pub depth: usize,
+    pub slots: usize,
+    pub memory_usage_bytes: usize,
+}
+
+/// Answers of the member filters of a group to a lookup.
+#[derive(Clone, Debug)]
+pub struct GroupLookup {
+    /// The combined answer.
+    pub contains: bool,
+    /// The answer of each member filter.
+    pub filters: BTreeMap<String, bool>,
+}
+
+/// Comparison of the answers of a filter and its shadow filter.
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Comparison of the answers of a filter and its shadow filter.")
+)]
+pub struct ShadowReport {
+    pub filter: String,
+    pub shadow: String,
+    pub compared: u64,
+    pub agreed: u64,
+    pub primary_only: u64,
+    pub shadow_only: u64,
+    pub agreement_rate: Option<f64>,
+}
+
+/// Names of the filters (or groups) affected by [`FilterStore::apply`].
+#[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Names of the filters (or groups) affected by applying a manifest.")
+)]
+pub struct ChangeSet {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+/// Outcome of [`FilterStore::apply`].
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(
+    feature = "server",
+    derive(utoipa::ToSchema),
+    schema(description = "Outcome of applying a manifest.")
+)]
+pub struct ApplyReport {
+    pub dry_run: bool,
+    pub filters: ChangeSet,
+    pub groups: ChangeSet,
+}
+
+/// Outcome of [`FilterStore::bootstrap`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BootstrapReport {
+    /// Filters created.
+    pub created: Vec<String>,
+    /// Filters existing with the same parameters, left as they are.
+    pub existing: Vec<String>,
+    /// Filters existing with other parameters, left as they are, as changing them would lose
+    /// their items.
+    pub conflicting: Vec<String>,
+}
+
+// --- The Filter Store ---
+
+/// The filters and groups managed by the service, with typed operations on them.
+///
+/// The store is independent of HTTP: applications can embed it in-process,
+/// and the `server` feature exposes it over a REST API (see `create_app`).
+///
+/// Filters and groups are guarded by separate locks. Code that needs both
+/// must acquire the `groups` lock before the `filters` lock.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{FilterSpec, FilterStore};
+///
+/// let store = FilterStore::default();
+/// store
+///     .create(FilterSpec {
+///         name: "login_attempts".to_string(),
+///         item_count: 1000,
+///         false_positive_rate: Some(0.01),
+///         ..Default::default()
+///     })
+///     .unwrap();
+///
+/// store.insert("login_attempts", "user@example.com").unwrap();
+/// assert!(store.contains("login_attempts", "user@example.com").unwrap());
+/// ```
+#[derive(Default)]
+pub struct FilterStore {
+    /// The filters, each locked on its own: the map itself is only locked to look filters up, or
+    /// to add or remove them, so that operations on different filters never contend.
+    pub filters: RwLock<HashMap<String, FilterEntry>>,
+    pub groups: RwLock<HashMap<String, FilterGroup>>,
+    /// Rate limiters, independent of the filters and groups.
+    pub limiters: RwLock<HashMap<String, RateLimiter>>,
+    /// Sliding-window "seen recently" filters, independent of the filters and groups.
+    pub recent: RwLock<HashMap<String, RecentFilter>>,
+    /// Count-Min sketches, independent of the filters and groups, and saved and logged with them.
+    pub sketches: RwLock<HashMap<String, CountMinSketch>>,
+    /// HyperLogLogs counting distinct items, independent of the filters and groups, and saved and
+    /// logged with them.
+    pub cardinality: RwLock<HashMap<String, HyperLogLog>>,
+    /// Counter bumped after every insert, waking up clients watching for items.
+    pub inserts: tokio::sync::watch::Sender<u64>,
+    /// Rules for the names of everything created in the store.
+    pub name_policy: NamePolicy,
+    /// Stores of their own, isolating the names of several applications sharing the service.
+    pub namespaces: RwLock<BTreeMap<String, SharedState>>,
+    /// Set while the saved filters are being restored, during which the service is not ready.
+    pub restoring: AtomicBool,
+    /// Set while the store replicates a primary, whose changes are the only ones it applies;
+    /// cleared on promotion.
+    pub read_only: AtomicBool,
+    /// Set while the store is in read-only mode, e.g. for a maintenance window: changes are
+    /// rejected (though a replica still applies those of its primary), and nothing expires.
+    pub read_only_mode: AtomicBool,
+    /// Set once a replica has synced with its primary.
+    pub(crate) replica_synced: AtomicBool,
+    /// Set while the store is kept by the node of a cluster, whose leader commits the expiries and
+    /// evictions of the filters instead.
+    pub(crate) clustered: AtomicBool,
+    /// API keys, by id, saved and logged with the filters.
+    pub(crate) api_keys: RwLock<BTreeMap<String, ApiKey>>,
+    /// Write-ahead log of the changes to the filters, if started.
+    pub(crate) journal: Journal,
+    /// Directory holding the files of memory-mapped filters, which cannot be created without one.
+    pub data_dir: Option<PathBuf>,
+    /// Limit on the memory used by the filters, enforced by evicting the least recently used.
+    pub memory_budget: Option<MemoryBudget>,
+    pub(crate) evictions: Mutex<Evictions>,
+    /// Held while an evicted filter is reloaded, so that it is reloaded once.
+    pub(crate) reloading: Mutex<()>,
+    /// Limits on the filters clients may create, enforced by rejecting the changes exceeding them.
+    pub(crate) quotas: RwLock<Quotas>,
+    /// Quotas of the namespaces having their own, instead of those of the store.
+    pub(crate) namespace_quotas: RwLock<BTreeMap<String, Quotas>>,
+    /// Lifecycle events of the filters, shared with the namespaces.
+    pub(crate) events: Events,
+    /// Jobs loading filters on the side of the service.
+    #[cfg(feature = "server")]
+    pub(crate) jobs: crate::jobs::Jobs,
+}
+
+impl FilterStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty store enforcing the given rules for names.
+    pub fn with_name_policy(name_policy: NamePolicy) -> Self {
+        FilterStore {
+            name_policy,
+            ..Self::default()
+        }
+    }
+
+    /// Wakes up all clients watching for items to appear.
+    pub fn notify_inserted(&self) {
+        self.inserts
+            .send_modify(|count| *count = count.wrapping_add(1));
+    }
+
+    /// Subscribes to the [events](FilterEvent) of the filters of the store and of all its
+    /// namespaces, from now on.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<FilterEvent> {
+        self.events.subscribe()
+    }
+
+    /// Reports `ratio` of the inserts into the filters of the store and of its namespaces to the
+    /// [subscribers](FilterStore::subscribe), between 0 (none, the default) and 1 (all), e.g.
+    /// to mirror them into replicas.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterEvent, FilterSpec, FilterStore};
+    ///
+    /// let store = FilterStore::new();
+    /// let spec = FilterSpec {
+    ///     name: "emails".to_string(),
+    ///     item_count: 1000,
+    ///     false_positive_rate: Some(0.01),
+    ///     ..Default::default()
+    /// };
+    /// store.create(spec).unwrap();
+    /// let mut events = store.subscribe();
+    /// store.sample_insert_events(0.5);
+    /// for i in 0..10 {
+    ///     store.insert("emails", format!("user{i}@example.com")).unwrap();
+    /// }
+    ///
+    /// let mut inserted = Vec::new();
+    /// while let Ok(FilterEvent::Inserted { item, .. }) = events.try_recv() {
+    ///     inserted.push(String::from_utf8(item).unwrap());
+    /// }
+    /// assert_eq!(inserted.len(), 5);
+    /// assert_eq!(inserted[0], "user1@example.com");
+    /// ```
+    pub fn sample_insert_events(&self, ratio: f64) {
+        self.events.sample_inserts(ratio);
+    }
+
+    /// Broadcasts an event to the subscribers of the store (and of the store it is a namespace
+    /// of), e.g. a [saturated](FilterEvent::Saturated) filter.
+    pub fn publish(&self, event: FilterEvent) {
+        self.events.publish(event);
+    }
+
+    // --- Filters ---
+
+    /// Creates an empty filter, returning its summary (including its id and resolved parameters).
+    pub fn create(&self, spec: FilterSpec) -> Result<FilterInfo, Error> {
+        self.name_policy.check(&spec.name)?;
+        // An evicted filter still exists, and is not created again
+        self.reload_evicted(&spec.name);
+        let mut db = self.filters.write();
+        if db.contains_key(&spec.name) {
+            return Err(Error::FilterExists(spec.name));
+        }
+
+        let bytes = spec.memory_usage_bytes()?;
+        self.check_quotas(&db, &[(&spec.name, spec.item_count, bytes)], &[])?;
+        let container = spec.build(self.data_dir.as_deref())?;
+        self.check_budget(&container.filter)?;
+        self.journal.record(Operation::Create {
+            name: &spec.name,
+            item_count: container.capacity,
+            creation_mode: container.creation_mode,
+            storage: spec.storage,
+            kind: container.filter.kind(),
+            rotating: container.filter.rotations().is_some(),
+            stable: container.filter.stable_params(),
+            scheme: spec.scheme,
+            seed: spec.seed,
+        })?;
+        if !container.labels.is_empty() {
+            self.journal.record(Operation::Label {
+                name: &spec.name,
+                labels: container.labels.clone(),
+            })?;
+        }
+        if !spec.journal {
+            self.discard_journal(&spec.name);
+        }
+        let info = container.info(Instant::now());
+        db.insert(spec.name, Arc::new(RwLock::new(container)));
+        drop(db);
+        self.events.changed(Change::Created, &info.name);
+        self.enforce_memory_budget();
+        Ok(info)
+    }
+
+    /// Creates a filter unless one of the same name already exists, returning its summary and
+    /// whether it was created.
+    ///
+    /// An existing filter is returned as is if it was created with the same parameters (the
+    /// expected number of items, creation mode, kind and stable parameters), and is a
+    /// [conflict](Error::ParameterConflict) otherwise; so concurrent clients can all ensure a filter
+    /// exists without racing each other.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterSpec, FilterStore};
+    ///
+    /// let store = FilterStore::default();
+    /// let spec = FilterSpec {
+    ///     name: "sessions".to_string(),
+    ///     item_count: 1000,
+    ///     false_positive_rate: Some(0.01),
+    ///     ..Default::default()
+    /// };
+    /// let (created, is_new) = store.get_or_create(spec.clone()).unwrap();
+    /// assert!(is_new);
+    /// let (existing, is_new) = store.get_or_create(spec.clone()).unwrap();
+    /// assert!(!is_new);
+    /// assert_eq!(existing.id, created.id);
+    ///
+    /// let larger = FilterSpec { item_count: 2000, ..spec };
+    /// assert!(store.get_or_create(larger).is_err());
+    /// ```
+    pub fn get_or_create(&self, spec: FilterSpec) -> Result<(FilterInfo, bool), Error> {
+        if let Ok(existing) = self.entry(&spec.name) {
+            let existing = existing.read();
+            if !spec.matches(&existing)? {
+                return Err(Error::ParameterConflict(vec![spec.name]));
+            }
+            return Ok((existing.info(Instant::now()), false));
+        }
+        match self.create(spec.clone()) {
+            Ok(info) => Ok((info, true)),
+            // Created concurrently since checked
+            Err(Error::FilterExists(_)) => self.get_or_create(spec),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Creates the `filters` that do not exist yet, e.g. those declared by the configuration of a
+    /// deployment on startup, leaving the existing ones as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::{FilterSpec, FilterStore};
+    ///
+    /// let store = FilterStore::new();
+    /// let spec = FilterSpec {
+    ///     name: "emails".to_string(),
+    ///     item_count: 1000,
+    ///     false_positive_rate: Some(0.01),
+    ///     ..Default::default()
+    /// };
+    /// let report = store.bootstrap([spec.clone()]).unwrap();
+    /// assert_eq!(report.created, ["emails"]);
+    /// let report = store.bootstrap([spec]).unwrap();
+    /// assert_eq!(report.existing, ["emails"]);
+    /// ```
+    pub fn bootstrap(
+        &self,
+        filters: impl IntoIterator<Item = FilterSpec>,
+    ) -> Result<BootstrapReport, Error> {
+        let mut report = BootstrapReport::default();
+        for spec in filters {
+            match self.get_or_create(spec.clone()) {
+                Ok((_, true)) => report.created.push(spec.name),
+                Ok((_, false)) => report.existing.push(spec.name),
+                Err(Error::ParameterConflict(_)) => report.conflicting.push(spec.name),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Deletes a filter given its name or id, returning its name.
+    pub fn delete(&self, id_or_name: &str) -> Result<String, Error> {
+        self.delete_if_match(id_or_name, None)
+    }
+
+    /// Deletes a filter given its name or id, as [`FilterStore::delete`], unless it no longer has
+    /// one of the `expected` [entity tags](FilterInfo::etag), if any.
+    pub fn delete_if_match(
+        &self,
+        id_or_name: &str,
+        expected: Option<&[String]>,
+    ) -> Result<String, Error> {
+        let name = self.resolve(id_or_name)?.read().name.clone();
+        let mut groups = self.groups.write();
+        let entries: Vec<FilterEntry> = {
+            let mut db = self.filters.write();
+            // Deleted concurrently since resolved
+            if !db.contains_key(&name) {
+                return Err(Error::FilterNotFound(id_or_name.to_string()));
+            }
+            db[&name].read().check_expected(expected)?;
+            self.journal.record(Operation::Delete { name: &name })?;
+            if let Some(journal) = db.remove(&name).and_then(|e| e.read().journal.clone()) {
+                journal.delete();
+            }
+            db.values().cloned().collect()
+        };
+        forget_filter(&mut groups, &entries, &name);
+        self.events.changed(Change::Deleted, &name);
+        Ok(name)
+    }
+
+    /// Changes the labels of a filter, returning its summary: labels mapped to a value are set,
+    /// and those mapped to `None` are removed.
+    pub fn update_labels(
+        &self,
+        name: &str,
+        changes: BTreeMap<String, Option<String>>,
+    ) -> Result<FilterInfo, Error> {
+        let entry = self.entry(name)?;
+        let mut container = entry.write();
+        let mut labels = container.labels.clone();
+        for (key, value) in changes {
+            match value {
+                Some(value) => labels.insert(key, value),
+                None => labels.remove(&key),
+            };
+        }
+        validate_labels(&labels)?;
+        self.journal.record(Operation::Label {
+            name,
+            labels: labels.clone(),
+        })?;
+        container.labels = labels;
+        Ok(container.info(Instant::now()))
+    }
+
+    /// Returns the name of the filter with the id `id_or_name`, or `id_or_name` itself if no filter
+    /// has that id (e.g. as it is a name already).
+    ///
+    /// The other methods taking the name of a filter can thus be given the id returned at creation.
+    pub fn filter_name(&self, id_or_name: &str) -> String {
+        self.resolve(id_or_name).map_or_else(
+            |_| id_or_name.to_string(),
+            |entry| entry.read().name.clone(),
+        )
+    }
+
+    /// Describes a filter given its name or id, with how saturated it is.
+    pub fn describe(&self, id_or_name: &str) -> Result<(FilterInfo, FilterStats), Error> {
+        let entry = self.resolve(id_or_name)?;
+        let container = entry.read();
+        Ok((container.info(Instant::now()), container.filter.stats()))
+    }
+
+    /// Lists all filters, those [evicted](FilterInfo::evicted) to disk included.
+    pub fn list(&self) -> Vec<FilterInfo> {
+        let now = Instant::now();
+        let mut filters: Vec<FilterInfo> = self
+            .all_entries()
+            .iter()
+            .map(|entry| entry.read().info(now))
+            .collect();
+        filters.extend(self.evicted_filters());
+        filters
+    }
+
+    /// Inserts an item into a filter and, if configured, into its shadow filter.
+    ///
+    /// Items are arbitrary bytes; strings are inserted as their UTF-8 encoding.
+    ///
+    /// Returns `true` if the item is definitely new, i.e. the insert set at least one bit of the
+    /// filter, and `false` if the item may have been inserted before. Fails if the filter is
+    /// scalable, and has outgrown the [quotas](Quotas).
+    pub fn insert(&self, name: &str, item: impl AsRef<[u8]>) -> Result<bool, Error> {
+        let entries = self.with_shadows(&[name]);
+        let outgrown = self.outgrown(&entries);
+        let new = insert_item(
+            &self.journal,
+            &self.events,
+            &outgrown,
+            &mut lock_inserting(&entries, true),
+            name,
+            item.as_ref(),
+        )?;
+        self.notify_inserted();
+        Ok(new)
+    }
+
+    /// Inserts several items into a filter (and its shadow filter) under a single lock.
+    ///
+    /// Returns the number of items inserted; items rejected by the transform of the filter
+    /// are skipped. Fails up front if the filter is scalable, and has outgrown the
+    /// [quotas](Quotas).
+    pub fn insert_many<I>(&self, name: &str, items: I) -> Result<usize, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let entries = self.with_shadows(&[name]);
+        let outgrown = self.outgrown(&entries);
+        check_outgrown(&outgrown, name)?;
+        let mut db = lock_inserting(&entries, true);
+        if !db.contains_key(name) {
+            return Err(Error::FilterNotFound(name.to_string()));
+        }
+
+        let count = items
+            .into_iter()
+            .filter(|item| {
+                insert_item(
+                    &self.journal,
+                    &self.events,
+                    &outgrown,
+                    &mut db,
+                    name,
+                    item.as_ref(),
+                )
+                .is_ok()
+            })
+            .count();
+        drop(db);
+        self.notify_inserted();
+        Ok(count)
+    }
+
+    /// Checks whether an item may have been inserted into a filter, and inserts it, atomically.
+    ///
+    /// Unlike the `new` flag of [`FilterStore::insert`], the answer is that of a lookup before the
+    /// insert, even for stable filters, where the insert itself may forget bits of the item.
+    pub fn check_and_insert(&self, name: &str, item: impl AsRef<[u8]>) -> Result<bool, Error> {
+        let item = item.as_ref();
+        let entries = self.with_shadows(&[name]);
+        let outgrown = self.outgrown(&entries);
+        let mut db = lock_inserting(&entries, false);
+        let container = db
+            .get(name)
+            .ok_or_else(|| Error::FilterNotFound(name.to_string()))?;
+        let was_present = container.filter.contains(&container.prepare(item)?);
+        container.usage.record_lookup(was_present);
+        insert_item(&self.journal, &self.events, &outgrown, &mut db, name, item)?;
+        drop(db);
+        self.notify_inserted();
+        Ok(was_present)
+    }
+
+    /// Inserts a batch of items into a filter (and its shadow filter) under a single lock.
+    ///
+    /// Returns, for each item, whether it is definitely new (as [`FilterStore::insert`] does), or
+    /// `None` if it was rejected by the transform of the filter. Any other failure stops the
+    /// batch; the items before it stay inserted. Fails up front if the filter is scalable, and
+    /// has outgrown the [quotas](Quotas).
+    pub fn insert_batch<I>(&self, name: &str, items: I) -> Result<Vec<Option<bool>>, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let entries = self.with_shadows(&[name]);
+        let outgrown = self.outgrown(&entries);
+        let mut db = lock_inserting(&entries, true);
+        if !db.contains_key(name) {
+            return Err(Error::FilterNotFound(name.to_string()));
+        }
+
+        let mut results = Vec::new();
+        for item in items {
+            match insert_item(
+                &self.journal,
+                &self.events,
+                &outgrown,
+                &mut db,
+                name,
+                item.as_ref(),
+            ) {
+                Ok(new) => results.push(Some(new)),
+                Err(Error::ItemRejected(_)) => results.push(None),
+                Err(error) => {
+                    drop(db);
+                    self.notify_inserted();
+                    return Err(error);
+                }
+            }
+        }
+        drop(db);
+        self.notify_inserted();
+        Ok(results)
+    }
+
+    /// Inserts an item into a filter only if it is absent from the filter and from all the `check`
+    /// filters, atomically: no other insert can happen between the lookups and the insert.
+    ///
+    /// Returns the first of these filters that may contain the item, or `None` if it was absent
+    /// from all of them and has been inserted.
+    pub fn insert_if_absent<S: AsRef<str>>(
+        &self,
+        name: &str,
+        item: impl AsRef<[u8]>,
+        check: &[S],
+    ) -> Result<Option<String>, Error> {
+        let item = item.as_ref();
+        let names: Vec<&str> = std::iter::once(name)
+            .chain(check.iter().map(|c| c.as_ref()))
+            .collect();
+        let entries = self.with_shadows(&names);
+        let outgrown = self.outgrown(&entries);
+        let mut db = lock_inserting(&entries, false);
+        if let Some(missing) = names.iter().find(|n| !db.contains_key(**n)) {
+            return Err(Error::FilterNotFound(missing.to_string()));
+        }
+
+        for checked in names {
+            if lookup_item(&db, checked, item)? {
+                return Ok(Some(checked.to_string()));
+            }
+        }
+        insert_item(&self.journal, &self.events, &outgrown, &mut db, name, item)?;
+        drop(db);
+        self.notify_inserted();
+        Ok(None)
+    }
+
+    /// Checks whether an item may have been inserted into a filter.
+    ///
+    /// If the filter has a shadow filter, the answers of both are compared.
+    pub fn contains(&self, name: &str, item: impl AsRef<[u8]>) -> Result<bool, Error> {
+        lookup_item(&read_all(&self.with_shadows(&[name])), name, item.as_ref())
+    }
+
+    /// Checks whether each of a batch of items may have been inserted into a filter, under a
+    /// single lock.
+    ///
+    /// Items rejected by the transform of the filter cannot have been inserted, and are reported
+    /// as absent.
+    pub fn contains_many<I>(&self, name: &str, items: I) -> Result<Vec<bool>, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let entries = self.with_shadows(&[name]);
+        let db = read_all(&entries);
+        items
+            .into_iter()
+            .map(|item| match lookup_item(&db, name, item.as_ref()) {
+                Err(Error::ItemRejected(_)) => Ok(false),
+                result => result,
+            })
+            .collect()
+    }
+
+    /// Returns the hashes and bit positions of an item in a filter, after its transform.
+    pub fn trace(&self, name: &str, item: impl AsRef<[u8]>) -> Result<HashTrace, Error> {
+        let entry = self.entry(name)?;
+        let container = entry.read();
+        Ok(container.filter.trace(&container.prepare(item.as_ref())?))
+    }
+
+    /// Waits until an item may have been inserted into a filter, or until `timeout` elapses.
+    ///
+    /// Returns `true` if the item may have been inserted, and `false` on timeout.
+    pub async fn watch(
+        &self,
+        name: &str,
+        item: impl AsRef<[u8]>,
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        let item = item.as_ref();
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        // Subscribe before the first check, so that no insert can be missed in between
+        let mut inserts = self.inserts.subscribe();
+        loop {
+            let contains = self.entry(name).ok().map(|entry| {
+                let container = entry.read();
+                container.touch();
+                container.filter.contains(item)
+            });
+            match contains {
+                None => return Err(Error::FilterNotFound(name.to_string())),
+                Some(true) => return Ok(true),
+                Some(false) => {}
+            }
+            match tokio::time::timeout_at(deadline, inserts.changed()).await {
+                Ok(Ok(())) => continue,
+                _ => return Ok(false),
+            }
+        }
+    }
+
+    /// Removes an item from a [counting](FilterKind::Counting) filter and, if configured, from
+    /// its shadow filter (if that is a counting filter too).
+    ///
+    /// Returns `true` if the item was removed, and `false` if it definitely was not in the filter.
+    /// Fails for filters of other kinds, which cannot remove items.
+    pub fn remove(&self, name: &str, item: impl AsRef<[u8]>) -> Result<bool, Error> {
+        let entries = self.with_shadows(&[name]);
+        let mut db = write_all(&entries);
+        let container = db
+            .get_mut(name)
+            .ok_or_else(|| Error::FilterNotFound(name.to_string()))?;
+        if container.filter.kind() != FilterKind::Counting {
+            return Err(Error::RemovalUnsupported(name.to_string()));
+        }
+        let prepared = container.prepare(item.as_ref())?;
+        container.touch();
+        if !container.filter.contains(&prepared) {
+            return Ok(false);
+        }
+        self.journal.record(Operation::Remove {
+            name,
+            item: &prepared,
+        })?;
+        container.journal_item(ItemChange::Remove, &prepared)?;
+        container.filter.remove(&prepared);
+
+        let shadow = container.shadow.as_ref().map(|s| s.filter.clone());
+        let shadow = shadow
+            .and_then(|s| db.get_mut(s.as_str()))
+            .filter(|s| s.filter.kind() == FilterKind::Counting);
+        if let Some(shadow) = shadow {
+            if let Ok(prepared) = shadow.prepare(item.as_ref()) {
+                let logged = self
+                    .journal
+                    .record(Operation::Remove {
+                        name: &shadow.name,
+                        item: &prepared,
+                    })
+                    .and_then(|()| shadow.journal_item(ItemChange::Remove, &prepared));
+                if logged.is_ok() {
+                    shadow.touch();
+                    shadow.filter.remove(&prepared);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Clears a filter and, if configured, its shadow filter.
+    pub fn clear(&self, name: &str) -> Result<(), Error> {
+        self.clear_if_match(name, None)
+    }
+
+    /// Clears a filter, as [`FilterStore::clear`], unless it no longer has one of the `expected`
+    /// [entity tags](FilterInfo::etag), if any.
+    pub fn clear_if_match(&self, name: &str, expected: Option<&[String]>) -> Result<(), Error> {
+        clear_filter(
+            &self.journal,
+            &mut write_all(&self.with_shadows(&[name])),
+            name,
+            expected,
+        )?;
+        self.events.changed(Change::Cleared, name);
+        Ok(())
+    }
+
+    /// Replaces a filter with one of the same kind sized for `item_count` items, or created with
+    /// `creation_mode`, holding `items`, returning the summary of the new filter.
+    ///
+    /// A Bloom filter does not keep its items, so the replacement only holds those passed in (as
+    /// prepared by the transform of the filter; rejected items are skipped). Parameters not given
+    /// are those of the filter. The filter is swapped atomically, keeping its id, expiry and
+    /// shadow. Memory-mapped filters cannot be rebuilt, as their file is sized at creation.
+    pub fn rebuild<I>(
+        &self,
+        name: &str,
+        item_count: Option<usize>,
+        creation_mode: Option<CreationMode>,
+        items: I,
+    ) -> Result<FilterInfo, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.rebuild_if_match(name, item_count, creation_mode, items, None)
+    }
+
+    /// Rebuilds a filter, as [`FilterStore::rebuild`], unless it no longer has one of the
+    /// `expected` [entity tags](FilterInfo::etag), if any.
+    pub fn rebuild_if_match<I>(
+        &self,
+        name: &str,
+        item_count: Option<usize>,
+        creation_mode: Option<CreationMode>,
+        items: I,
+        expected: Option<&[String]>,
+    ) -> Result<FilterInfo, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let entry = self.entry(name)?;
+        // Checked before locking the filter for writing, as the quotas look up the other filters
+        self.check_rebuild(name, &entry.read(), item_count, creation_mode)?;
+
+        let mut container = entry.write();
+        container.check_expected(expected)?;
+        let mut rebuilt = rebuild_spec(&container, item_count, creation_mode).build(None)?;
+        let mut prepared = Vec::new();
+        for item in items {
+            match container.prepare(item.as_ref()) {
+                Ok(item) => {
+                    rebuilt.filter.insert(&item);
+                    if container.journal.is_some() {
+                        prepared.push(item.into_owned());
+                    }
+                }
+                Err(Error::ItemRejected(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        if let Some(journal) = &container.journal {
+            journal.replace(prepared.iter().map(Vec::as_slice))?;
+        }
+        let info = self.assign_rebuilt(name, &mut container, rebuilt)?;
+        drop(container);
+        self.notify_inserted();
+        self.enforce_memory_budget();
+        Ok(info)
+    }
+
+    /// Fails unless the filter `name` can be rebuilt with the parameters given, within the quotas.
+    pub(crate) fn check_rebuild(
+        &self,
+        name: &str,
+        container: &FilterContainer,
+        item_count: Option<usize>,
+        creation_mode: Option<CreationMode>,
+    ) -> Result<(), Error> {
+        if container.filter.storage() == Storage::Mmap {
+            return Err(Error::InvalidParameters(
+                "Memory-mapped filters cannot be rebuilt".to_string(),
+            ));
+        }
+        let estimate = rebuild_spec(container, item_count, creation_mode);
+        let bytes = estimate.memory_usage_bytes()?;
+        self.check_quotas(
+            &self.filters.read(),
+            &[(name, estimate.item_count, bytes)],
+            &[],
+        )
+    }
+
+    /// Swaps the bits of the locked filter `name` for those of the `rebuilt` one, returning the
+    /// summary of the filter.
+    pub(crate) fn assign_rebuilt(
+        &self,
+        name: &str,
+        container: &mut FilterContainer,
+        rebuilt: FilterContainer,
+    ) -> Result<FilterInfo, Error> {
+        self.check_budget(&rebuilt.filter)?;
+        let snapshot = FilterSnapshot {
+            item_count: rebuilt.capacity,
+            creation_mode: rebuilt.creation_mode,
+            filter: rebuilt.filter,
+        };
+        self.journal.record(Operation::Put {
+            name,
+            snapshot: snapshot.encode(),
+        })?;
+        container.capacity = snapshot.item_count;
+        container.creation_mode = snapshot.creation_mode;
+        container.filter.assign(snapshot.filter)?;
+        container.generation += 1;
+        container.touch();
+        Ok(container.info(Instant::now()))
+    }
+
+    /// Merges the items of the filters `sources`, given by name or id, into the filter `name`
+    /// (e.g. to combine per-shard filters into an aggregate one), returning the summary of the
+    /// merged filter.
+    ///
+    /// The sources must have the same parameters as the target, and are left unchanged. All of
+    /// them are checked before the target changes.
+    pub fn merge(&self, name: &str, sources: &[String]) -> Result<FilterInfo, Error> {
+        self.merge_if_match(name, sources, None)
+    }
+
+    /// Merges filters into the filter `name`, as [`FilterStore::merge`], unless it no longer has
+    /// one of the `expected` [entity tags](FilterInfo::etag), if any.
+    pub fn merge_if_match(
+        &self,
+        name: &str,
+        sources: &[String],
+        expected: Option<&[String]>,
+    ) -> Result<FilterInfo, Error> {
+        let (entries, sources) = self.combined(name, sources);
+        let mut db = write_all(&entries);
+        check_expected(&db, name, expected)?;
+        let merged = combine(&db, name, &sources, BloomFilter::union)?;
+        let info = self.assign_combined(&mut db, name, merged)?;
+        drop(db);
+        self.notify_inserted();
+        // Scalable filters take the slices they lack from the sources
+        self.enforce_memory_budget();
+        Ok(info)
+    }
+
+    /// Intersects the filter `name` with the filters `sources`, given by name or id, keeping
+    /// (approximately) the items inserted into all of them, returning the summary of the result.
+    ///
+    /// The result replaces the filter `name`, or, with `into`, is created as a new filter of that
+    /// name, leaving `name` unchanged. The sources must have the same parameters as the target,
+    /// and are left unchanged.
+    pub fn intersect(
+        &self,
+        name: &str,
+        sources: &[String],
+        into: Option<&str>,
+    ) -> Result<FilterInfo, Error> {
+        self.intersect_if_match(name, sources, into, None)
+    }
+
+    /// Intersects the filter `name` with other filters, as [`FilterStore::intersect`], unless it
+    /// no longer has one of the `expected` [entity tags](FilterInfo::etag), if any.
+    pub fn intersect_if_match(
+        &self,
+        name: &str,
+        sources: &[String],
+        into: Option<&str>,
+        expected: Option<&[String]>,
+    ) -> Result<FilterInfo, Error> {
+        let (entries, sources) = self.combined(name, sources);
+        match into {
+            Some(into) => {
+                let db = read_all(&entries);
+                check_expected(&db, name, expected)?;
+                let intersection = combine(&db, name, &sources, BloomFilter::intersect)?;
+                drop(db);
+                self.upload(into, intersection, UploadMode::Create)
+            }
+            None => {
+                let mut db = write_all(&entries);
+                check_expected(&db, name, expected)?;
+                let intersection = combine(&db, name, &sources, BloomFilter::intersect)?;
+                self.assign_combined(&mut db, name, intersection)
+            }
+        }
+    }
+
+    /// Returns the filter `name` and the filters `sources` it is combined with, along with the
+    /// names of the sources, given by name or id.
+    fn combined(
+        &self,
+        name: &str,
+        sources: &[String],
+    ) -> (BTreeMap<String, FilterEntry>, Vec<String>) {
+        let sources: Vec<String> = sources.iter().map(|s| self.filter_name(s)).collect();
+        let names: Vec<&str> = std::iter::once(name)
+            .chain(sources.iter().map(String::as_str))
+            .collect();
+        (self.entries(&names), sources)
+    }
+
+    /// Sets the bits of the filter `name` to those of a combination of it with other filters.
+    fn assign_combined<G: DerefMut<Target = FilterContainer>>(
+        &self,
+        db: &mut Locked<'_, G>,
+        name: &str,
+        combined: FilterSnapshot,
+    ) -> Result<FilterInfo, Error> {
+        // Logged as the combined filter, so that replaying it twice has no effect
+        self.journal.record(Operation::Put {
+            name,
+            snapshot: combined.encode(),
+        })?;
+        let container = db
+            .get_mut(name)
+            .ok_or_else(|| Error::FilterNotFound(name.to_string()))?;
+        container.filter.assign(combined.filter)?;
+        container.generation += 1;
+        container.touch();
+        Ok(container.info(Instant::now()))
+    }
+
+    /// Installs a prebuilt filter (e.g. from the offline builder, or another service), returning
+    /// the summary of the resulting filter.
+    ///
+    /// The snapshot has already been verified by [`FilterSnapshot::decode`]. Its items were
+    /// inserted as they are, so a transform of an existing filter is not applied to them. Fails
+    /// if the filter created or replaced does not fit in the [quotas](Quotas).
+    pub fn upload(
+        &self,
+        name: &str,
+        snapshot: FilterSnapshot,
+        mode: UploadMode,
+    ) -> Result<FilterInfo, Error> {
+        self.upload_if_match(name, snapshot, mode, None)
+    }
+
+    /// Installs a prebuilt filter, as [`FilterStore::upload`], unless the filter `name` no longer
+    /// has one of the `expected` [entity tags](FilterInfo::etag), if any (or does not exist).
+    pub fn upload_if_match(
+        &self,
+        name: &str,
+        snapshot: FilterSnapshot,
+        mode: UploadMode,
+        expected: Option<&[String]>,
+    ) -> Result<FilterInfo, Error> {
+        // An evicted filter still exists, and is replaced or merged rather than created again
+        self.reload_evicted(name);
+        self.install(name, snapshot, mode, expected, true)
+    }
+
+    /// Installs a filter restored from a snapshot or replicated from a primary, as
+    /// [`FilterStore::upload`] in the replace mode, without checking the quotas.
+    pub(crate) fn restore(
+        &self,
+        name: &str,
+        snapshot: FilterSnapshot,
+    ) -> Result<FilterInfo, Error> {
+        self.install(name, snapshot, UploadMode::Replace, None, false)
+    }
+
+    /// Installs a prebuilt filter, checking the quotas if `quotas` is set.
+    fn install(
+        &self,
+        name: &str,
+        snapshot: FilterSnapshot,
+        mode: UploadMode,
+        expected: Option<&[String]>,
+        quotas: bool,
+    ) -> Result<FilterInfo, Error> {
+        let entry = self.filters.read().get(name).cloned();
+        let added = [(
+            name,
+            snapshot.item_count,
+            snapshot.filter.memory_usage_bytes(),
+        )];
+        let info = match (entry, mode) {
+            (Some(_), UploadMode::Create) => return Err(Error::FilterExists(name.to_string())),
+            (None, UploadMode::Merge) => return Err(Error::FilterNotFound(name.to_string())),
+            (None, _) if expected.is_some() => {
+                return Err(Error::PreconditionFailed(name.to_string()))
+            }
+            (Some(entry), UploadMode::Replace) => {
+                // Checked before locking the filter, as the quotas look up the other filters
+                if quotas && entry.read().filter.storage() != Storage::Mmap {
+                    self.check_quotas(&self.filters.read(), &added, &[])?;
+                }
+                let mut container = entry.write();
+                container.check_expected(expected)?;
+                // The parameters of a memory-mapped filter are also in the header of its file
+                if container.filter.storage() == Storage::Mmap
+                    && (container.capacity != snapshot.item_count
+                        || container.creation_mode != snapshot.creation_mode)
+                {
+                    return Err(Error::InvalidParameters(
+                        "A memory-mapped filter can only be replaced by a filter with the same parameters"
+                            .to_string(),
+                    ));
+                }
+                if container.filter.storage() == Storage::Atomic
+                    && snapshot.filter.kind() != FilterKind::Standard
+                {
+                    return Err(Error::InvalidParameters(
+                        "A filter with atomic storage can only be replaced by a standard filter"
+                            .to_string(),
+                    ));
+                }
+                if container.filter.storage() != Storage::Mmap {
+                    self.check_budget(&snapshot.filter)?;
+                }
+                self.journal.record(Operation::Put {
+                    name,
+                    snapshot: snapshot.encode(),
+                })?;
+                container.capacity = snapshot.item_count;
+                container.creation_mode = snapshot.creation_mode;
+                container.filter.assign(snapshot.filter)?;
+                container.generation += 1;
+                container.touch();
+                container.info(Instant::now())
+            }
+            (Some(entry), UploadMode::Merge) => {
+                let mut container = entry.write();
+                container.check_expected(expected)?;
+                if container.capacity != snapshot.item_count
+                    || container.creation_mode != snapshot.creation_mode
+                {
+                    return Err(Error::ParameterConflict(vec![name.to_string()]));
+                }
+                // Logged as the merged filter, so that replaying it twice has no effect
+                let mut merged = container.filter.clone();
+                merged.union(&snapshot.filter)?;
+                let merged = FilterSnapshot {
+                    item_count: snapshot.item_count,
+                    creation_mode: snapshot.creation_mode,
+                    filter: merged,
+                };
+                self.journal.record(Operation::Put {
+                    name,
+                    snapshot: merged.encode(),
+                })?;
+                container.filter.union(&snapshot.filter)?;
+                container.generation += 1;
+                container.touch();
+                container.info(Instant::now())
+            }
+            (None, _) => {
+                let mut db = self.filters.write();
+                if db.contains_key(name) {
+                    // Created concurrently since looked up
+                    drop(db);
+                    return match mode {
+                        UploadMode::Create => Err(Error::FilterExists(name.to_string())),
+                        _ => self.install(name, snapshot, mode, expected, quotas),
+                    };
+                }
+                self.name_policy.check(name)?;
+                if quotas {
+                    self.check_quotas(&db, &added, &[])?;
+                }
+                self.check_budget(&snapshot.filter)?;
+                self.journal.record(Operation::Put {
+                    name,
+                    snapshot: snapshot.encode(),
+                })?;
+                let container = FilterContainer::of(name, snapshot)?;
+                let info = container.info(Instant::now());
+                db.insert(name.to_string(), Arc::new(RwLock::new(container)));
+                self.events.changed(Change::Created, name);
+                info
+            }
+        };
+        self.notify_inserted();
+        self.enforce_memory_budget();
+        Ok(info)
+    }
+
+    /// Returns a snapshot of a filter, e.g. to save it to a file.
+    pub fn snapshot(&self, name: &str) -> Result<FilterSnapshot, Error> {
+        let entry = self.entry(name)?;
+        let container = entry.read();
+        Ok(FilterSnapshot {
+            item_count: container.capacity,
+            creation_mode: container.creation_mode,
+            filter: container.filter.clone(),
+        })
+    }
+
+    /// Compares a snapshot of a filter (e.g. a backup) with its live state.
+    pub fn diff(&self, name: &str, snapshot: &FilterSnapshot) -> Result<FilterDiff, Error> {
+        let entry = self.entry(name)?;
+        let container = entry.read();
+        snapshot.filter.diff(&container.filter)
+    }
+
+    /// Estimates the overlap of the items of the filters `a` and `b`.
+    pub fn compare(&self, a: &str, b: &str) -> Result<FilterOverlap, Error> {
+        let entries = self.entries(&[a, b]);
+        let db = read_all(&entries);
+        let get = |name: &str| {
+            db.get(name)
+                .ok_or_else(|| Error::FilterNotFound(name.to_string()))
+        };
+        get(a)?.filter.overlap(&get(b)?.filter)
+    }
+
+    /// Reports how saturated a filter is.
+    pub fn filter_stats(&self, name: &str) -> Result<FilterStats, Error> {
+        Ok(self.entry(name)?.read().filter.stats())
+    }
+
+    // --- Transforms ---
+
+    /// Loads a WASM module transforming the items of a filter, replacing any previous one.
+    ///
+    /// See [`Transform`] for the interface the module must implement.
+    #[cfg(feature = "wasm")]
+    pub fn set_transform(&self, name: &str, wasm: &[u8]) -> Result<(), Error> {
+        let transform = Transform::new(wasm).map_err(|reason| {
+            Error::InvalidParameters(format!("Invalid transform module: {reason}"))
+        })?;
+        self.entry(name)?.write().transform = Some(transform);
+        Ok(())
+    }
+
+    /// Removes the transform of a filter.
+    #[cfg(feature = "wasm")]
+    pub fn remove_transform(&self, name: &str) -> Result<(), Error> {
+        self.entry(name)?
+            .write()
+            .transform
+            .take()
+            .map(|_| ())
+            .ok_or_else(|| Error::NoTransform(name.to_string()))
+    }
+
+    // --- Shadow Filters ---
+
+    /// Mirrors all writes to a filter into a shadow filter.
+    pub fn set_shadow(&self, name: &str, shadow: &str) -> Result<(), Error> {
+        self.entry(shadow)?;
+        if shadow == name {
+            return Err(Error::InvalidParameters(
+                "A filter cannot shadow itself".to_string(),
+            ));
+        }
+        self.entry(name)?.write().shadow = Some(Shadow::new(shadow));
+        Ok(())
+    }
+
+    /// Compares the answers of a filter and its shadow filter.
+    pub fn shadow_report(&self, name: &str) -> Result<ShadowReport, Error> {
+        let entry = self.entry(name)?;
+        let container = entry.read();
+        let shadow = container
+            .shadow
+            .as_ref()
+            .ok_or_else(|| Error::NoShadow(name.to_string()))?;
+
+        let stats = &shadow.stats;
+        let compared = stats.compared.load(Ordering::Relaxed);
+        let agreed = stats.agreed.load(Ordering::Relaxed);
+        Ok(ShadowReport {
+            filter: name.to_string(),
+            shadow: shadow.filter.clone(),
+            compared,
+            agreed,
+            primary_only: stats.primary_only.load(Ordering::Relaxed),
+            shadow_only: stats.shadow_only.load(Ordering::Relaxed),
+            agreement_rate: (compared > 0).then(|| agreed as f64 / compared as f64),
+        })
+    }
+
+    /// Stops mirroring the writes to a filter, returning the name of the former shadow filter.
+    pub fn remove_shadow(&self, name: &str) -> Result<String, Error> {
+        self.entry(name)?
+            .write()
+            .shadow
+            .take()
+            .map(|shadow| shadow.filter)
+            .ok_or_else(|| Error::NoShadow(name.to_string()))
+    }
+
+    // --- Groups ---
+
+    /// Creates a group of existing filters.
+    pub fn create_group(&self, spec: GroupSpec) -> Result<(), Error> {
+        self.name_policy.check(&spec.name)?;
+        self.entries(&spec.filters);
+        let mut groups = self.groups.write();
+        let db = self.filters.read();
+
+        if groups.contains_key(&spec.name) {
+            return Err(Error::GroupExists(spec.name));
+        }
+        if let Some(missing) = spec.filters.iter().find(|f| !db.contains_key(*f)) {
+            return Err(Error::FilterNotFound(missing.clone()));
+        }
+
+        groups.insert(
+            spec.name.clone(),
+            FilterGroup {
+                name: spec.name,
+                filters: dedup_members(spec.filters),
+            },
+        );
+        Ok(())
+    }
+
+    /// Lists all groups.
+    pub fn list_groups(&self) -> Vec<FilterGroup> {
+        self.groups.read().values().cloned().collect()
+    }
+
+    /// Deletes a group and, unless `keep_filters` is set, its member filters.
+    ///
+    /// Returns the number of deleted filters.
+    pub fn delete_group(&self, name: &str, keep_filters: bool) -> Result<usize, Error> {
+        let mut groups = self.groups.write();
+        let group = groups
+            .remove(name)
+            .ok_or_else(|| Error::GroupNotFound(name.to_string()))?;
+
+        if keep_filters {
+            return Ok(0);
+        }
+        for member in &group.filters {
+            let entries: Vec<FilterEntry> = {
+                let mut db = self.filters.write();
+                self.journal.record(Operation::Delete { name: member })?;
+                db.remove(member);
+                db.values().cloned().collect()
+            };
+            forget_filter(&mut groups, &entries, member);
+            self.events.changed(Change::Deleted, member);
+        }
+        Ok(group.filters.len())
+    }
+
+    /// Inserts an item into all member filters of a group.
+    ///
+    /// Returns the number of filters the item was inserted into; members whose transform
+    /// rejects the item are skipped.
+    pub fn group_insert(&self, name: &str, item: impl AsRef<[u8]>) -> Result<usize, Error> {
+        let item = item.as_ref();
+        let groups = self.groups.read();
+        let group = groups
+            .get(name)
+            .ok_or_else(|| Error::GroupNotFound(name.to_string()))?;
+        let entries = self.add_shadows(self.resident_entries(&group.filters));
+        let outgrown = self.outgrown(&entries);
+        let mut db = lock_inserting(&entries, true);
+
+        let count = group
+            .filters
+            .iter()
+            .filter(|member| {
+                insert_item(
+                    &self.journal,
+                    &self.events,
+                    &outgrown,
+                    &mut db,
+                    member,
+                    item,
+                )
+                .is_ok()
+            })
+            .count();
+        drop(db);
+        self.notify_inserted();
+        Ok(count)
+    }
+
+    /// Checks whether an item may have been inserted into the member filters of a group.
+    ///
+    /// Members whose transform rejects the item cannot have seen it.
+    pub fn group_contains(
+        &self,
+        name: &str,
+        item: impl AsRef<[u8]>,
+        mode: GroupLookupMode,
+    ) -> Result<GroupLookup, Error> {
+        let item = item.as_ref();
+        let groups = self.groups.read();
+        let group = groups
+            .get(name)
+            .ok_or_else(|| Error::GroupNotFound(name.to_string()))?;
+        let entries = self.add_shadows(self.resident_entries(&group.filters));
+        let db = read_all(&entries);
+
+        let filters: BTreeMap<String, bool> = group
+            .filters
+            .iter()
+            .filter(|member| db.contains_key(member.as_str()))
+            .map(|member| {
+                let seen = lookup_item(&db, member, item).unwrap_or(false);
+                (member.clone(), seen)
+            })
+            .collect();
+        let contains = match mode {
+            GroupLookupMode::Any => filters.values().any(|seen| *seen),
+            GroupLookupMode::All => !filters.is_empty() && filters.values().all(|seen| *seen),
+        };
+        Ok(GroupLookup { contains, filters })
+    }
+
+    /// Clears all member filters of a group.
+    pub fn group_clear(&self, name: &str) -> Result<(), Error> {
+        let groups = self.groups.read();
+        let group = groups
+            .get(name)
+            .ok_or_else(|| Error::GroupNotFound(name.to_string()))?;
+        let entries = self.add_shadows(self.resident_entries(&group.filters));
+        let mut db = write_all(&entries);
+
+        for member in &group.filters {
+            clear_filter(&self.journal, &mut db, member, None)?;
+            self.events.changed(Change::Cleared, member);
+        }
+        Ok(())
+    }
+
+    // --- Rate Limiters ---
+
+    /// Creates a rate limiter.
+    pub fn create_limiter(&self, spec: LimiterSpec) -> Result<(), Error> {
+        self.name_policy.check(&spec.name)?;
+        let mut limiters = self.limiters.write();
+        if limiters.contains_key(&spec.name) {
+            return Err(Error::LimiterExists(spec.name));
+        }
+
+        let limiter = RateLimiter::new(&spec)?;
+        limiters.insert(spec.name, limiter);
+        Ok(())
+    }
+
+    /// Lists all rate limiters.
+    pub fn list_limiters(&self) -> Vec<LimiterInfo> {
+        self.limiters
+            .read()
+            .values()
+            .map(|l| LimiterInfo {
+                name: l.name.clone(),
+                limit: l.limit,
+                window_seconds: l.window.as_secs(),
+                width: l.width,
+                depth: l.depth,
+                slots: l.slots,
+                memory_usage_bytes: l.memory_usage_bytes(),
+            })
+            .collect()
+    }
+
+    /// Deletes a rate limiter.
+    pub fn delete_limiter(&self, name: &str) -> Result<(), Error> {
+        self.limiters
+            .write()
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| Error::LimiterNotFound(name.to_string()))
+    }
+
+    /// Counts `cost` for a key, unless this would exceed the limit of the rate limiter.
+    pub fn check_limiter(
+        &self,
+        name: &str,
+        key: impl AsRef<[u8]>,
+        cost: u64,
+    ) -> Result<LimiterDecision, Error> {
+        let limiters = self.limiters.read();
+        let limiter = limiters
+            .get(name)
+            .ok_or_else(|| Error::LimiterNotFound(name.to_string()))?;
+
+        Ok(limiter.check_and_increment(key.as_ref(), cost, Instant::now()))
+    }
+
+    // --- Recent Filters ---
+
+    /// Creates a recent filter.
+    pub fn create_recent(&self, spec: RecentSpec) -> Result<(), Error> {
+        self.name_policy.check(&spec.name)?;
+        let mut recent = self.recent.write();
+        if recent.contains_key(&spec.name) {
+            return Err(Error::RecentExists(spec.name));
+        }
+
+        let filter = RecentFilter::new(&spec)?;
+        recent.insert(spec.name, filter);
+        Ok(())
+    }
+
+    /// Lists all recent filters.
+    pub fn list_recent(&self) -> Vec<RecentInfo> {
+        self.recent
+            .read()
+            .values()
+            .map(|r| RecentInfo {
+                name: r.name.clone(),
+                window_seconds: r.window.as_secs(),
+                item_count: r.item_count,
+                false_positive_rate: r.false_positive_rate,
+                slots: r.slots,
+                rotation_seconds: r.rotation().as_secs_f64(),
+            })
+            .collect()
+    }
+
+    /// Deletes a recent filter.
+    pub fn delete_recent(&self, name: &str) -> Result<(), Error> {
+        self.recent
+            .write()
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| Error::RecentNotFound(name.to_string()))
+    }
+
+    /// Records an item as seen by a recent filter, returning `true` if it may have been seen
+    /// within the window before.
+    pub fn recent_seen(&self, name: &str, item: impl AsRef<[u8]>) -> Result<bool, Error> {
+        let recent = self.recent.read();
+        let filter = recent
+            .get(name)
+            .ok_or_else(|| Error::RecentNotFound(name.to_string()))?;
+
+        Ok(filter.seen(item.as_ref(), Instant::now()))
+    }
+
+    /// Checks whether an item may have been seen by a recent filter within its window,
+    /// without recording it.
+    pub fn recent_contains(&self, name: &str, item: impl AsRef<[u8]>) -> Result<bool, Error> {
+        let recent = self.recent.read();
+        let filter = recent
+            .get(name)
+            .ok_or_else(|| Error::RecentNotFound(name.to_string()))?;
+
+        Ok(filter.contains(item.as_ref(), Instant::now()))
+    }
+
+    /// Rotates every recent filter at `now`, clearing the generations that have left their
+    /// window; returns the number of generations cleared.
+    pub fn rotate_recent(&self, now: Instant) -> usize {
+        self.recent
+            .read()
+            .values()
+            .map(|filter| filter.rotate(now))
+            .sum()
+    }
+
+    // --- Sketches ---
+
+    /// Creates a Count-Min sketch.
+    pub fn create_sketch(&self, spec: SketchSpec) -> Result<(), Error> {
+        self.name_policy.check(&spec.name)?;
+        let mut sketches = self.sketches.write();
+        if sketches.contains_key(&spec.name) {
+            return Err(Error::SketchExists(spec.name));
+        }
+
+        let sketch = CountMinSketch::new(&spec)?;
+        self.journal.record(Operation::CreateSketch {
+            name: &spec.name,
+            width: sketch.width,
+            depth: sketch.depth,
+        })?;
+        sketches.insert(spec.name, sketch);
+        Ok(())
+    }
+
+    /// Lists all sketches.
+    pub fn list_sketches(&self) -> Vec<SketchInfo> {
+        self.sketches
+            .read()
+            .values()
+            .map(|s| SketchInfo {
+                name: s.name.clone(),
+                width: s.width,
+                depth: s.depth,
+                total: s.total(),
+                memory_usage_bytes: s.memory_usage_bytes(),
+            })
+            .collect()
+    }
+
+    /// Deletes a sketch.
+    pub fn delete_sketch(&self, name: &str) -> Result<(), Error> {
+        let mut sketches = self.sketches.write();
+        if !sketches.contains_key(name) {
+            return Err(Error::SketchNotFound(name.to_string()));
+        }
+        self.journal.record(Operation::DeleteSketch { name })?;
+        sketches.remove(name);
+        Ok(())
+    }
+
+    /// Counts an item `count` times in a sketch, returning its new estimated frequency.
+    pub fn increment_sketch(
+        &self,
+        name: &str,
+        item: impl AsRef<[u8]>,
+        count: u64,
+    ) -> Result<u64, Error> {
+        let item = item.as_ref();
+        let mut sketches = self.sketches.write();
+        let sketch = sketches
+            .get_mut(name)
+            .ok_or_else(|| Error::SketchNotFound(name.to_string()))?;
+        self.journal
+            .record(Operation::Increment { name, item, count })?;
+        Ok(sketch.increment(item, count))
+    }
+
+    /// Estimates how often an item was counted in a sketch.
+    pub fn estimate_sketch(&self, name: &str, item: impl AsRef<[u8]>) -> Result<u64, Error> {
+        let sketches = self.sketches.read();
+        let sketch = sketches
+            .get(name)
+            .ok_or_else(|| Error::SketchNotFound(name.to_string()))?;
+
+        Ok(sketch.estimate(item.as_ref()))
+    }
+
+    // --- Cardinality ---
+
+    /// Creates a HyperLogLog.
+    pub fn create_cardinality(&self, spec: CardinalitySpec) -> Result<(), Error> {
+        self.name_policy.check(&spec.name)?;
+        let mut cardinality = self.cardinality.write();
+        if cardinality.contains_key(&spec.name) {
+            return Err(Error::CardinalityExists(spec.name));
+        }
+
+        let hll = HyperLogLog::new(&spec)?;
+        self.journal.record(Operation::CreateCardinality {
+            name: &spec.name,
+            precision: hll.precision,
+        })?;
+        cardinality.insert(spec.name, hll);
+        Ok(())
+    }
+
+    /// Lists all HyperLogLogs, with their estimates.
+    pub fn list_cardinality(&self) -> Vec<CardinalityInfo> {
+        self.cardinality
+            .read()
+            .values()
+            .map(|h| CardinalityInfo {
+                name: h.name.clone(),
+                precision: h.precision,
+                estimate: h.estimate(),
+                standard_error: h.standard_error(),
+                memory_usage_bytes: h.memory_usage_bytes(),
+            })
+            .collect()
+    }
+
+    /// Deletes a HyperLogLog.
+    pub fn delete_cardinality(&self, name: &str) -> Result<(), Error> {
+        let mut cardinality = self.cardinality.write();
+        if !cardinality.contains_key(name) {
+            return Err(Error::CardinalityNotFound(name.to_string()));
+        }
+        self.journal.record(Operation::DeleteCardinality { name })?;
+        cardinality.remove(name);
+        Ok(())
+    }
+
+    /// Inserts an item into a HyperLogLog, returning `true` if it is definitely new.
+    pub fn insert_cardinality(&self, name: &str, item: impl AsRef<[u8]>) -> Result<bool, Error> {
+        let item = item.as_ref();
+        let mut cardinality = self.cardinality.write();
+        let hll = cardinality
+            .get_mut(name)
+            .ok_or_else(|| Error::CardinalityNotFound(name.to_string()))?;
+        self.journal
+            .record(Operation::InsertCardinality { name, item })?;
+        Ok(hll.insert(item))
+    }
+
+    /// Estimates the number of distinct items inserted into a HyperLogLog.
+    pub fn estimate_cardinality(&self, name: &str) -> Result<u64, Error> {
+        let cardinality = self.cardinality.read();
+        let hll = cardinality
+            .get(name)
+            .ok_or_else(|| Error::CardinalityNotFound(name.to_string()))?;
+
+        Ok(hll.estimate())
+    }
+
+    /// Merges the items of the HyperLogLogs `sources` into `name`, returning its new estimate.
+    ///
+    /// All of them must have the same precision; the sources are left unchanged.
+    pub fn merge_cardinality(&self, name: &str, sources: &[String]) -> Result<u64, Error> {
+        let mut cardinality = self.cardinality.write();
+        let mut merged = cardinality
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::CardinalityNotFound(name.to_string()))?;
+        for source in sources {
+            let source = cardinality
+                .get(source)
+                .ok_or_else(|| Error::CardinalityNotFound(source.clone()))?;
+            merged.merge(source)?;
+        }
+
+        // Logged as the result, which does not depend on the later state of the sources
+        self.journal.record(Operation::PutCardinality {
+            name,
+            registers: merged.registers(),
+        })?;
+        let estimate = merged.estimate();
+        cardinality.insert(name.to_string(), merged);
+        Ok(estimate)
+    }
+
+    // --- Reconciliation ---
+
+    /// Reconciles the filters and groups towards the state described by a manifest.
+    ///
+    /// Missing filters and groups are created, the time-to-live and idle settings of existing
+    /// filters are updated, and, with `prune`, filters and groups absent from the manifest are
+    /// deleted. The parameters of an existing filter cannot be changed without losing its
+    /// content, so a manifest that requests this is rejected as a whole, before any change is made.
+    pub fn apply(&self, manifest: Manifest, options: ApplyOptions) -> Result<ApplyReport, Error> {
+        let mut groups = self.groups.write();
+        let mut db = self.filters.write();
+
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = manifest.filters.iter().find(|f| !seen.insert(&f.name)) {
+            return Err(Error::InvalidParameters(format!(
+                "Filter '{}' is listed more than once",
+                duplicate.name
+            )));
+        }
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = manifest.groups.iter().find(|g| !seen.insert(&g.name)) {
+            return Err(Error::InvalidParameters(format!(
+                "Group '{}' is listed more than once",
+                duplicate.name
+            )));
+        }
+
+        // Validate the whole manifest before changing anything
+        let invalid = |name: &str, error: Error| {
+            Error::InvalidParameters(format!("Filter '{name}': {error}"))
+        };
+        let pruned: Vec<&str> = db
+            .keys()
+            .filter(|name| options.prune && !manifest.filters.iter().any(|f| &f.name == *name))
+            .map(String::as_str)
+            .collect();
+        let mut added = Vec::new();
+        let mut new_filters = Vec::new();
+        let mut conflicts = Vec::new();
+        for spec in &manifest.filters {
+            match db.get(&spec.name) {
+                None => {
+                    self.name_policy.check(&spec.name)?;
+                    let bytes = spec
+                        .memory_usage_bytes()
+                        .map_err(|e| invalid(&spec.name, e))?;
+                    added.push((spec.name.as_str(), spec.item_count, bytes));
+                    self.check_quotas(&db, &added, &pruned)?;
+                    let container = spec
+                        .build(self.data_dir.as_deref())
+                        .map_err(|e| invalid(&spec.name, e))?;
+                    self.check_budget(&container.filter)
+                        .map_err(|e| invalid(&spec.name, e))?;
+                    new_filters.push((container, spec.storage));
+                }
+                Some(existing) => {
+                    spec.validate_expiry().map_err(|e| invalid(&spec.name, e))?;
+                    validate_labels(&spec.labels).map_err(|e| invalid(&spec.name, e))?;
+                    if !spec
+                        .matches(&existing.read())
+                        .map_err(|e| invalid(&spec.name, e))?
+                    {
+                        conflicts.push(spec.name.clone());
+                    }
+                }
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(Error::ParameterConflict(conflicts));
+        }
+
+        let retained = |name: &String| {
+            manifest.filters.iter().any(|f| &f.name == name)
+                || (!options.prune && db.contains_key(name))
+        };
+        for group in manifest
+            .groups
+            .iter()
+            .filter(|g| !groups.contains_key(&g.name))
+        {
+            self.name_policy.check(&group.name)?;
+        }
+        for group in &manifest.groups {
+            if let Some(missing) = group.filters.iter().find(|f| !retained(f)) {
+                return Err(Error::GroupMemberNotFound {
+                    group: group.name.clone(),
+                    filter: missing.clone(),
+                });
+            }
+        }
+
+        // Compute the changes
+        let mut report = ApplyReport {
+            dry_run: options.dry_run,
+            filters: ChangeSet::default(),
+            groups: ChangeSet::default(),
+        };
+        for spec in &manifest.filters {
+            match db.get(&spec.name).map(|existing| existing.read()) {
+                None => report.filters.created.push(spec.name.clone()),
+                Some(existing)
+                    if expiry_matches(&existing, spec) && existing.labels == spec.labels =>
+                {
+                    report.filters.unchanged.push(spec.name.clone())
+                }
+                Some(_) => report.filters.updated.push(spec.name.clone()),
+            }
+        }
+        if options.prune {
+            report.filters.deleted = db.keys().filter(|name| !retained(name)).cloned().collect();
+            report.groups.deleted = groups
+                .keys()
+                .filter(|name| !manifest.groups.iter().any(|g| &g.name == *name))
+                .cloned()
+                .collect();
+        }
+        for spec in &manifest.groups {
+            match groups.get(&spec.name) {
+                None => report.groups.created.push(spec.name.clone()),
+                Some(existing) if existing.filters == dedup_members(spec.filters.clone()) => {
+                    report.groups.unchanged.push(spec.name.clone())
+                }
+                Some(_) => report.groups.updated.push(spec.name.clone()),
+            }
+        }
+
+        if options.dry_run {
+            return Ok(report);
+        }
+
+        // Apply the changes
+        for name in &report.groups.deleted {
+            groups.remove(name);
+        }
+        for name in &report.filters.deleted {
+            self.journal.record(Operation::Delete { name })?;
+            db.remove(name);
+            forget_filter(&mut groups, db.values(), name);
+            self.events.changed(Change::Deleted, name);
+        }
+        for (container, storage) in new_filters {
+            self.journal.record(Operation::Create {
+                name: &container.name,
+                item_count: container.capacity,
+                creation_mode: container.creation_mode,
+                storage,
+                kind: container.filter.kind(),
+                rotating: container.filter.rotations().is_some(),
+                stable: container.filter.stable_params(),
+                scheme: container.filter.scheme(),
+                seed: container.filter.seed(),
+            })?;
+            if !container.labels.is_empty() {
+                self.journal.record(Operation::Label {
+                    name: &container.name,
+                    labels: container.labels.clone(),
+                })?;
+            }
+            if container.journal.is_none() {
+                self.discard_journal(&container.name);
+            }
+            self.events.changed(Change::Created, &container.name);
+            db.insert(container.name.clone(), Arc::new(RwLock::new(container)));
+        }
+        for name in &report.filters.updated {
+            let spec = manifest.filters.iter().find(|f| &f.name == name).unwrap();
+            let mut container = db[name].write();
+            if expiry_spec(&container) != (spec.ttl_seconds, spec.on_expire) {
+                container.expiry = spec.expiry(Instant::now())?;
+            }
+            container.idle_timeout = spec.expire_after_idle_seconds.map(Duration::from_secs);
+            if container.labels != spec.labels {
+                self.journal.record(Operation::Label {
+                    name,
+                    labels: spec.labels.clone(),
+                })?;
+                container.labels = spec.labels.clone();
+            }
+        }
+        for spec in manifest.groups {
+            groups.insert(
+                spec.name.clone(),
+                FilterGroup {
+                    name: spec.name,
+                    filters: dedup_members(spec.filters),
+                },
+            );
+        }
+        drop(db);
+        drop(groups);
+        self.enforce_memory_budget();
+
+        Ok(report)
+    }
+
+    // --- Expiry ---
+
+    /// Applies the expiry action to every filter whose time-to-live has elapsed at `now`,
+    /// and removes every filter that has been idle for longer than its idle timeout.
+    ///
+    /// Expired filters configured with [`ExpiryAction::Delete`] are removed, while those
+    /// configured with [`ExpiryAction::Clear`] are emptied and start a new time-to-live period.
+    /// Returns the number of filters that expired or were removed as idle.
+    ///
+    /// A no-op in the store of a cluster node, whose leader commits the expiries instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bloomsrv::FilterStore;
+    /// use std::time::Instant;
+    ///
+    /// let store = FilterStore::new();
+    /// assert_eq!(store.expire(Instant::now()), 0);
+    /// ```
+    pub fn expire(&self, now: Instant) -> usize {
+        if self.clustered.load(Ordering::Relaxed) {
+            return 0;
+        }
+        let (removed, cleared) = self.expiring(now);
+        self.expire_entries(removed, cleared, now)
+    }
+
+    /// Returns the filters due to expire at `now`: those to remove, and those to clear.
+    pub(crate) fn expiring(&self, now: Instant) -> (Vec<FilterEntry>, Vec<FilterEntry>) {
+        let (mut removed, mut cleared) = (Vec::new(), Vec::new());
+        for entry in self.all_entries() {
+            let container = entry.read();
+            match container.expiry {
+                _ if container.is_idle(now) => removed.push(entry.clone()),
+                Some(expiry) if expiry.is_expired(now) => match expiry.action {
+                    ExpiryAction::Delete => removed.push(entry.clone()),
+                    ExpiryAction::Clear => cleared.push(entry.clone()),
+                },
+                _ => {}
+            }
+        }
+        (removed, cleared)
+    }
+
+    /// Expires the filters `removed` and `cleared` at `now`, whether or not they are due to,
+    /// returning their number.
+    pub(crate) fn expire_entries(
+        &self,
+        removed: Vec<FilterEntry>,
+        cleared: Vec<FilterEntry>,
+        now: Instant,
+    ) -> usize {
+        let expired = removed.len() + cleared.len();
+        // Expiry cannot fail: if an operation is not logged, it is repeated after a recovery
+        let cleared: Vec<String> = cleared
+            .into_iter()
+            .map(|entry| {
+                let mut container = entry.write();
+                let _ = self.journal.record(Operation::Clear {
+                    name: &container.name,
+                });
+                let _ = container.clear_journal();
+                container.filter.clear();
+                container.generation += 1;
+                if let Some(expiry) = container.expiry {
+                    // Never expires again if the next expiry is beyond the clock
+                    container.expiry = Expiry::new(expiry.ttl, expiry.action, now).ok();
+                }
+                container.name.clone()
+            })
+            .collect();
+
+        let mut groups = self.groups.write();
+        for entry in removed {
+            let name = entry.read().name.clone();
+            let entries: Vec<FilterEntry> = {
+                let mut db = self.filters.write();
+                // Unless deleted (and maybe created again) since it expired
+                if !db.get(&name).is_some_and(|e| Arc::ptr_eq(e, &entry)) {
+                    continue;
+                }
+                let _ = self.journal.record(Operation::Delete { name: &name });
+                db.remove(&name);
+                db.values().cloned().collect()
+            };
+            if let Some(journal) = &entry.read().journal {
+                journal.delete();
+            }
+            forget_filter(&mut groups, &entries, &name);
+            self.events.changed(Change::Deleted, &name);
+        }
+        for name in cleared {
+            self.events.changed(Change::Cleared, &name);
+        }
+        expired
+    }
+
+    // --- Locking ---
+
+    /// Returns the filter `name`, locking the map of the filters only to look it up, and
+    /// reloading it if it was evicted to disk.
+    pub(crate) fn entry(&self, name: &str) -> Result<FilterEntry, Error> {
+        self.resident(name)
+            .or_else(|| self.reload_evicted(name).then(|| self.resident(name))?)
+            .ok_or_else(|| Error::FilterNotFound(name.to_string()))
+    }
+
+    /// Returns the filter `name` if it is in memory, without reloading it if it was evicted, e.g.
+    /// to save or replay what the store holds.
+    pub(crate) fn resident(&self, name: &str) -> Option<FilterEntry> {
+        self.filters.read().get(name).cloned()
+    }
+
+    /// Returns every filter.
+    pub(crate) fn all_entries(&self) -> Vec<FilterEntry> {
+        self.filters.read().values().cloned().collect()
+    }
+
+    /// Returns the filter of name or id `id_or_name`.
+    ///
+    /// Looking up an id only waits for the filters locked by other operations if none of the
+    /// others has it.
+    fn resolve(&self, id_or_name: &str) -> Result<FilterEntry, Error> {
+        if let Ok(entry) = self.entry(id_or_name) {
+            return Ok(entry);
+        }
+        let entries = self.all_entries();
+        let resident = entries
+            .iter()
+            .find(|e| e.try_read().is_some_and(|c| c.id == id_or_name))
+            .or_else(|| entries.iter().find(|e| e.read().id == id_or_name))
+            .cloned();
+        resident
+            .or_else(|| self.entry(&self.evicted_name(id_or_name)?).ok())
+            .ok_or_else(|| Error::FilterNotFound(id_or_name.to_string()))
+    }
+
+    /// Returns those of the filters `names` that exist, by name, reloading those evicted to disk.
+    pub(crate) fn entries<S: AsRef<str>>(&self, names: &[S]) -> BTreeMap<String, FilterEntry> {
+        let evicted: Vec<&str> = {
+            let db = self.filters.read();
+            names
+                .iter()
+                .map(AsRef::as_ref)
+                .filter(|name| !db.contains_key(*name))
+                .collect()
+        };
+        for name in evicted {
+            self.reload_evicted(name);
+        }
+        self.resident_entries(names)
+    }
+
+    /// Returns those of the filters `names` in memory, by name.
+    fn resident_entries<S: AsRef<str>>(&self, names: &[S]) -> BTreeMap<String, FilterEntry> {
+        let db = self.filters.read();
+        names
+            .iter()
+            .filter_map(|name| {
+                let name = name.as_ref();
+                db.get(name).map(|entry| (name.to_string(), entry.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the filters a change to `names` is written to: `names` themselves, followed by the
+    /// shadow filters of those of them that are resident. Nothing is reloaded.
+    #[cfg(feature = "server")]
+    pub(crate) fn written_filters<S: AsRef<str>>(&self, names: &[S]) -> Vec<String> {
+        let mut written: Vec<String> = names.iter().map(|n| n.as_ref().to_string()).collect();
+        for shadow in self.add_shadows(self.resident_entries(names)).into_keys() {
+            if !written.contains(&shadow) {
+                written.push(shadow);
+            }
+        }
+        written
+    }
+
+    /// Returns the member filters of the group `name`, none if it is missing.
+    #[cfg(feature = "server")]
+    pub(crate) fn group_filters(&self, name: &str) -> Vec<String> {
+        self.groups
+            .read()
+            .get(name)
+            .map(|group| group.filters.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns those of the filters `names` that exist, and their shadow filters, by name: the
+    /// filters written to by an insert into `names`.
+    fn with_shadows<S: AsRef<str>>(&self, names: &[S]) -> BTreeMap<String, FilterEntry> {
+        self.add_shadows(self.entries(names))
+    }
+
+    /// Adds the shadow filters of `entries` to them. Evicted filters are forgotten as shadows, so
+    /// none is reloaded, which the members of a locked group rely on too: reloading a filter may
+    /// evict others, which locks the groups.
+    fn add_shadows(
+        &self,
+        mut entries: BTreeMap<String, FilterEntry>,
+    ) -> BTreeMap<String, FilterEntry> {
+        let shadows: Vec<String> = entries
+            .values()
+            .filter_map(|entry| entry.read().shadow.as_ref().map(|s| s.filter.clone()))
+            .collect();
+        let missing: Vec<String> = shadows
+            .into_iter()
+            .filter(|shadow| !entries.contains_key(shadow))
+            .collect();
+        entries.extend(self.resident_entries(&missing));
+        entries
+    }
+}
+
+/// Returns the specification of the filter of `container` rebuilt for `item_count` items, or with
+/// `creation_mode`, if given.
+pub(crate) fn rebuild_spec(
+    container: &FilterContainer,
+    item_count: Option<usize>,
+    creation_mode: Option<CreationMode>,
+) -> FilterSpec {
+    let (false_positive_rate, hash_count) = match creation_mode.unwrap_or(container.creation_mode) {
+        CreationMode::FalsePositiveRate(rate) => (Some(rate), None),
+        CreationMode::HashCount(count) => (None, Some(count)),
+    };
+    FilterSpec {
+        name: container.name.clone(),
+        item_count: item_count.unwrap_or(container.capacity),
+        false_positive_rate,
+        hash_count,
+        kind: container.filter.kind(),
+        stable: container.filter.stable_params(),
+        on_full: FullAction::of(&container.filter),
+        scheme: container.filter.scheme(),
+        seed: container.filter.seed(),
+        ..Default::default()
+    }
+}
+
+/// Returns `true` unless the stable parameters of a specification (defaults if absent) differ from
+/// the resolved ones of an existing stable filter.
+fn stable_matches(spec: Option<StableParams>, existing: Option<StableParams>) -> bool {
+    let spec = spec.unwrap_or_default();
+    existing.is_none_or(|existing| {
+        spec.max == existing.max
+            && spec
+                .decrements
+                .is_none_or(|d| Some(d) == existing.decrements)
+    })
+}
+
+/// Spawns a background task that calls [`FilterStore::expire`] every `interval`, and then
+/// [`FilterStore::enforce_memory_budget`], as scalable filters grow when items are inserted, and
+/// [`FilterStore::rotate_recent`], in the store and each of its namespaces.
+///
+/// Nothing expires in read-only mode, nor on a replica, which follows the deletes and clears of
+/// its primary instead. Nor is anything expired or evicted by the node of a cluster, whose
+/// leader commits them instead: see `Cluster::spawn_expiry_task`.
+///
+/// Must be called from within a Tokio runtime.
+pub fn spawn_expiry_task(state: SharedState, interval: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let namespaces: Vec<SharedState> = state.namespaces.read().values().cloned().collect();
+            let frozen = state.read_only_mode.load(Ordering::Relaxed)
+                || state.read_only.load(Ordering::Relaxed);
+            for store in std::iter::once(&state).chain(&namespaces) {
+                if !frozen {
+                    store.expire(Instant::now());
+                    store.rotate_recent(Instant::now());
+                }
+                store.enforce_memory_budget();
+            }
+        }
+    })
+}
+
+// --- Filter Operations ---
+
+/// A filter of a store, locked on its own.
+pub(crate) type FilterEntry = Arc<RwLock<FilterContainer>>;
+
+/// The filters locked by an operation, by name.
+type Locked<'a, G> = BTreeMap<&'a str, G>;
+
+/// Locks filters for writing, in the order of their names, so that operations locking several
+/// filters cannot deadlock.
+///
+/// No other lock of the store is taken while filters are locked: the groups are locked before the
+/// map of the filters, and the map before the filters.
+fn write_all(
+    entries: &BTreeMap<String, FilterEntry>,
+) -> Locked<'_, RwLockWriteGuard<'_, FilterContainer>> {
+    entries
+        .iter()
+        .map(|(name, entry)| (name.as_str(), entry.write()))
+        .collect()
+}
+
+/// Locks filters for reading, in the order of their names.
+fn read_all(
+    entries: &BTreeMap<String, FilterEntry>,
+) -> Locked<'_, RwLockReadGuard<'_, FilterContainer>> {
+    entries
+        .iter()
+        .map(|(name, entry)| (name.as_str(), entry.read()))
+        .collect()
+}
+
+/// A filter locked by an insert: for reading if it has atomic storage and the insert does not
+/// depend on a lookup, so that such inserts run at once, and for writing otherwise.
+enum Inserting<'a> {
+    Shared(RwLockReadGuard<'a, FilterContainer>),
+    Exclusive(RwLockWriteGuard<'a, FilterContainer>),
+}
+
+impl Inserting<'_> {
+    /// Inserts an item into the filter, returning `true` if this changed any bits.
+    fn insert(&mut self, item: &[u8]) -> bool {
+        match self {
+            Inserting::Shared(container) => container
+                .filter
+                .insert_shared(item)
+                .expect("only filters with atomic storage are shared"),
+            Inserting::Exclusive(container) => container.filter.insert(item),
+        }
+    }
+}
+
+impl Deref for Inserting<'_> {
+    type Target = FilterContainer;
+
+    fn deref(&self) -> &FilterContainer {
+        match self {
+            Inserting::Shared(container) => container,
+            Inserting::Exclusive(container) => container,
+        }
+    }
+}
+
+/// Locks filters for inserts, in the order of their names: those with atomic storage for reading
+/// if `shared`, and the others for writing.
+fn lock_inserting(
+    entries: &BTreeMap<String, FilterEntry>,
+    shared: bool,
+) -> Locked<'_, Inserting<'_>> {
+    entries
+        .iter()
+        .map(|(name, entry)| {
+            let locked = match entry.read() {
+                container if shared && container.filter.storage() == Storage::Atomic => {
+                    Inserting::Shared(container)
+                }
+                container => {
+                    drop(container);
+                    Inserting::Exclusive(entry.write())
+                }
+            };
+            (name.as_str(), locked)
+        })
+        .collect()
+}
+
+/// Inserts an item into a filter and, if configured, into its shadow filter.
+///
+/// Each filter applies its own transform; a shadow filter rejecting the item does not record it.
+///
+/// Items are logged as they are inserted, i.e. after the transform, and published (if sampled) as
+/// they are given.
+fn insert_item(
+    journal: &Journal,
+    events: &Events,
+    outgrown: &Outgrown,
+    db: &mut Locked<'_, Inserting<'_>>,
+    name: &str,
+    item: &[u8],
+) -> Result<bool, Error> {
+    let container = db
+        .get_mut(name)
+        .ok_or_else(|| Error::FilterNotFound(name.to_string()))?;
+    check_outgrown(outgrown, name)?;
+    let prepared = container.prepare(item)?;
+    journal.record(Operation::Insert {
+        name,
+        item: &prepared,
+    })?;
+    container.journal_item(ItemChange::Insert, &prepared)?;
+    container.touch();
+    container.usage.record_insert();
+    let new = container.insert(&prepared);
+    events.inserted(name, item);
+
+    let shadow = container.shadow.as_ref().map(|s| s.filter.clone());
+    let shadow = shadow.filter(|s| !outgrown.contains_key(s));
+    if let Some(shadow) = shadow.and_then(|s| db.get_mut(s.as_str())) {
+        if let Ok(prepared) = shadow.prepare(item) {
+            let logged = journal
+                .record(Operation::Insert {
+                    name: &shadow.name,
+                    item: &prepared,
+                })
+                .and_then(|()| shadow.journal_item(ItemChange::Insert, &prepared));
+            if logged.is_ok() {
+                shadow.touch();
+                shadow.usage.record_insert();
+                shadow.insert(&prepared);
+            }
+        }
+    }
+    Ok(new)
+}
+
+/// Checks for an item in a filter and, if configured, compares the answer with its shadow filter.
+fn lookup_item<G: Deref<Target = FilterContainer>>(
+    db: &Locked<'_, G>,
+    name: &str,
+    item: &[u8],
+) -> Result<bool, Error> {
+    let container = db
+        .get(name)
+        .ok_or_else(|| Error::FilterNotFound(name.to_string()))?;
+    container.touch();
+    let contains = container.filter.contains(&container.prepare(item)?);
+    container.usage.record_lookup(contains);
+
+    if let Some(shadow) = &container.shadow {
+        if let Some(shadow_container) = db.get(shadow.filter.as_str()) {
+            let shadow_contains = shadow_container
+                .prepare(item)
+                .is_ok_and(|prepared| shadow_container.filter.contains(&prepared));
+            shadow.stats.record(contains, shadow_contains);
+        }
+    }
+    Ok(contains)
+}
+
+/// Generates the id of something created: random, but while a command of a cluster is served,
+/// when it is derived from the command for every node to generate the same.
+pub(crate) fn new_id() -> Uuid {
+    #[cfg(feature = "cluster")]
+    if let Some(id) = crate::cluster::seeded_id() {
+        return id;
+    }
+    Uuid::new_v4()
+}
+
+/// Formats the entity tag of the generation of a filter.
+fn entity_tag(id: &str, generation: u64) -> String {
+    format!("\"{id}-{generation}\"")
+}
+
+/// Returns a time in seconds since the Unix epoch.
+pub(crate) fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Checks that label keys are not empty, and can be queried as `key=value`.
+fn validate_labels(labels: &BTreeMap<String, String>) -> Result<(), Error> {
+    match labels
+        .keys()
+        .find(|key| key.is_empty() || key.contains('='))
+    {
+        Some(key) => Err(Error::InvalidParameters(format!(
+            "Invalid label '{key}': label keys must be non-empty and not contain '='"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Combines the filter `name` with the filters `sources`, checking that they all have the same
+/// parameters.
+fn combine<G: Deref<Target = FilterContainer>>(
+    db: &Locked<'_, G>,
+    name: &str,
+    sources: &[String],
+    op: fn(&mut BloomFilter, &BloomFilter) -> Result<(), Error>,
+) -> Result<FilterSnapshot, Error> {
+    let target = db
+        .get(name)
+        .ok_or_else(|| Error::FilterNotFound(name.to_string()))?;
+    let mut combined = target.filter.clone();
+    for source in sources {
+        let container = db
+            .get(source.as_str())
+            .ok_or_else(|| Error::FilterNotFound(source.clone()))?;
+        let source_name = &container.name;
+        if source_name == name {
+            return Err(Error::InvalidParameters(format!(
+                "Filter '{name}' cannot be combined with itself"
+            )));
+        }
+        if container.capacity != target.capacity || container.creation_mode != target.creation_mode
+        {
+            return Err(Error::ParameterConflict(vec![source_name.clone()]));
+        }
+        op(&mut combined, &container.filter)?;
+    }
+    Ok(FilterSnapshot {
+        item_count: target.capacity,
+        creation_mode: target.creation_mode,
+        filter: combined,
+    })
+}
+
+/// Fails unless the locked filter `name` has one of the `expected` entity tags, if any.
+fn check_expected<G: Deref<Target = FilterContainer>>(
+    db: &Locked<'_, G>,
+    name: &str,
+    expected: Option<&[String]>,
+) -> Result<(), Error> {
+    match db.get(name) {
+        Some(container) => container.check_expected(expected),
+        None => Err(Error::FilterNotFound(name.to_string())),
+    }
+}
+
+/// Clears a filter and, if configured, its shadow filter, unless the filter no longer has one of
+/// the `expected` entity tags.
+fn clear_filter<G: DerefMut<Target = FilterContainer>>(
+    journal: &Journal,
+    db: &mut Locked<'_, G>,
+    name: &str,
+    expected: Option<&[String]>,
+) -> Result<(), Error> {
+    let container = db
+        .get_mut(name)
+        .ok_or_else(|| Error::FilterNotFound(name.to_string()))?;
+    container.check_expected(expected)?;
+    journal.record(Operation::Clear { name })?;
+    container.clear_journal()?;
+    container.touch();
+    container.filter.clear();
+    container.generation += 1;
+
+    let shadow = container.shadow.as_ref().map(|s| s.filter.clone());
+    if let Some(shadow) = shadow.and_then(|s| db.get_mut(s.as_str())) {
+        journal.record(Operation::Clear { name: &shadow.name })?;
+        shadow.clear_journal()?;
+        shadow.filter.clear();
+        shadow.generation += 1;
+    }
+    Ok(())
+}
+
+/// Removes every reference to a deleted filter from the remaining `filters`: its group memberships,
+/// and its use as a shadow filter, waiting for the operations in progress on each.
+pub(crate) fn forget_filter<'a>(
+    groups: &mut HashMap<String, FilterGroup>,
+    filters: impl IntoIterator<Item = &'a FilterEntry>,
+    filter_name: &str,
+) {
+    for group in groups.values_mut() {
+        group.filters.retain(|member| member != filter_name);
+    }
+    for entry in filters {
+        let mut container = entry.write();
+        if container
+            .shadow
+            .as_ref()
+            .is_some_and(|s| s.filter == filter_name)
+        {
+            container.shadow = None;
+        }
+    }
+}
+
+/// Removes repeated filter names from a group's member list, keeping the first occurrence.
+fn dedup_members(filters: Vec<String>) -> Vec<String> {
+    let mut members = Vec::with_capacity(filters.len());
+    for filter in filters {
+        if !members.contains(&filter) {
+            members.push(filter);
+        }
+    }
+    members
+}
+
+/// Returns the time-to-live settings of a filter as they would be requested in a specification.
+fn expiry_spec(container: &FilterContainer) -> (Option<u64>, ExpiryAction) {
+    container
+        .expiry
+        .map_or((None, ExpiryAction::default()), |e| {
+            (Some(e.ttl.as_secs()), e.action)
+        })
+}
+
+/// Returns `true` if the time-to-live and idle settings of a filter match a specification.
+fn expiry_matches(container: &FilterContainer, spec: &FilterSpec) -> bool {
+    expiry_spec(container) == (spec.ttl_seconds, spec.on_expire)
+        && container.idle_timeout == spec.expire_after_idle_seconds.map(Duration::from_secs)
+}
@@ -0,0 +1,147 @@
+//! The integration of the service with systemd: the sockets it binds for the service (socket
+//! activation), and the notifications of the state of the service (readiness, watchdog pings and
+//! stopping) it waits for.
+
+use std::{
+    env, io,
+    mem::ManuallyDrop,
+    net::TcpListener,
+    os::unix::{io::FromRawFd, net::UnixDatagram},
+    time::Duration,
+};
+
+/// The first file descriptor passed by socket activation, after standard input, output and error.
+const LISTEN_FDS_START: i32 = 3;
+
+/// Takes the TCP sockets passed by systemd socket activation, with their names (as set by
+/// `FileDescriptorName=` in the socket unit, `unknown` otherwise), in the order of the socket
+/// unit; none if the process was not started by socket activation.
+///
+/// The sockets are bound and listening already. They are taken only once: the variables passing
+/// them are removed from the environment.
+pub fn activated_listeners() -> io::Result<Vec<(String, TcpListener)>> {
+    let pid: Option<u32> = env::var("LISTEN_PID").ok().and_then(|pid| pid.parse().ok());
+    let count = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse().ok());
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    for variable in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(variable);
+    }
+    let count: i32 = match (pid, count) {
+        (Some(pid), Some(count)) if pid == std::process::id() => count,
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut names = names.split(':');
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            let name = names.next().filter(|name| !name.is_empty());
+            let name = name.unwrap_or("unknown").to_string();
+            // SAFETY: systemd passes the descriptors from `LISTEN_FDS_START` on to the process of
+            // `LISTEN_PID`, for it to own, and the variables are removed so they are taken once
+            let listener = ManuallyDrop::new(unsafe {
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                TcpListener::from_raw_fd(fd)
+            });
+            // Left open if it is something else, e.g. a descriptor of the process itself when
+            // the variables are wrong
+            if listener.local_addr().is_err() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("the socket '{name}' (file descriptor {fd}) is not a TCP socket"),
+                ));
+            }
+            let listener = ManuallyDrop::into_inner(listener);
+            listener.set_nonblocking(true)?;
+            Ok((name, listener))
+        })
+        .collect()
+}
+
+/// Sends notifications of the state of the service to systemd, for units of `Type=notify`, e.g.
+/// `READY=1` once the service serves requests, or `WATCHDOG=1` to tell it still runs.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::SystemdNotifier;
+/// use std::os::unix::net::UnixDatagram;
+///
+/// let path = std::env::temp_dir().join(format!("bloomsrv-notify-{}", std::process::id()));
+/// let _ = std::fs::remove_file(&path);
+/// let systemd = UnixDatagram::bind(&path).unwrap();
+///
+/// SystemdNotifier::new(path.to_str().unwrap()).notify("READY=1").unwrap();
+/// let mut buffer = [0; 16];
+/// let len = systemd.recv(&mut buffer).unwrap();
+/// assert_eq!(&buffer[..len], b"READY=1");
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SystemdNotifier {
+    /// Path of the notification socket, or its name in the abstract namespace after a `@`.
+    socket: String,
+    watchdog: Option<Duration>,
+}
+
+impl SystemdNotifier {
+    /// Creates a notifier sending to the datagram socket at `socket` (in the abstract namespace,
+    /// Linux only, if it starts with `@`).
+    pub fn new(socket: &str) -> Self {
+        SystemdNotifier {
+            socket: socket.to_string(),
+            watchdog: None,
+        }
+    }
+
+    /// Returns the notifier of the service manager that started the process, as told by
+    /// `NOTIFY_SOCKET`, with its watchdog timeout, as told by `WATCHDOG_USEC` (and
+    /// `WATCHDOG_PID`, if set); `None` if it does not wait for notifications.
+    pub fn from_env() -> Option<Self> {
+        let socket = env::var("NOTIFY_SOCKET").ok().filter(|s| !s.is_empty())?;
+        let watched = env::var("WATCHDOG_PID")
+            .ok()
+            .and_then(|pid| pid.parse().ok())
+            .is_none_or(|pid: u32| pid == std::process::id());
+        let watchdog = env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse().ok())
+            .filter(|&usec| usec > 0 && watched)
+            .map(Duration::from_micros);
+        Some(SystemdNotifier {
+            watchdog,
+            ..SystemdNotifier::new(&socket)
+        })
+    }
+
+    /// Returns the watchdog timeout: systemd restarts the service unless it is sent `WATCHDOG=1`
+    /// within every timeout.
+    pub fn watchdog(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    /// Sends a notification, of one or more `VARIABLE=value` lines.
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        let socket = UnixDatagram::unbound()?;
+        match self.socket.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+                let address = SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &address)?;
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "abstract notification sockets are only supported on Linux",
+                ))
+            }
+            None => {
+                socket.send_to(state.as_bytes(), &self.socket)?;
+            }
+        }
+        Ok(())
+    }
+}
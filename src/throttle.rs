@@ -0,0 +1,84 @@
+use parking_lot::Mutex;
+use std::{collections::HashMap, time::Instant};
+
+use crate::Error;
+
+/// Number of clients tracked beyond which the buckets refilled to the burst are forgotten.
+const MAX_IDLE_CLIENTS: usize = 10_000;
+
+/// Limits the requests of each client to a rate, allowing bursts: a token bucket per client,
+/// holding up to `burst` requests and refilled at `rate` requests per second.
+///
+/// Unlike the [rate limiters](crate::RateLimiter) served to clients, this limits the requests
+/// made of the service itself, so that no client can starve the others.
+///
+/// # Examples
+///
+/// ```
+/// use bloomsrv::{Error, Throttle};
+///
+/// let throttle = Throttle::new(1.0, 2);
+/// assert!(throttle.acquire("loader").is_ok());
+/// assert!(throttle.acquire("loader").is_ok());
+/// assert_eq!(throttle.acquire("loader"), Err(Error::RateLimited { retry_after: 1 }));
+///
+/// // Other clients have buckets of their own
+/// assert!(throttle.acquire("dashboard").is_ok());
+/// ```
+#[derive(Debug)]
+pub struct Throttle {
+    /// Requests per second allowed to each client.
+    pub rate: f64,
+    /// Requests a client may make at once, after being idle.
+    pub burst: u32,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Throttle {
+    /// Creates a throttle allowing each client `rate` requests per second, and bursts of `burst`
+    /// requests.
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Throttle {
+            rate,
+            burst: burst.max(1),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a request of `client` from its bucket, failing with [`Error::RateLimited`] if the
+    /// bucket is empty, with the seconds until it holds a request again.
+    pub fn acquire(&self, client: &str) -> Result<(), Error> {
+        let now = Instant::now();
+        let burst = f64::from(self.burst);
+        let mut buckets = self.buckets.lock();
+        if buckets.len() >= MAX_IDLE_CLIENTS && !buckets.contains_key(client) {
+            let rate = self.rate;
+            buckets.retain(|_, bucket| {
+                let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+                bucket.tokens + elapsed * rate < burst
+            });
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: burst,
+            refilled_at: now,
+        });
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(burst);
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.rate;
+            Err(Error::RateLimited {
+                retry_after: wait.ceil().max(1.0) as u64,
+            })
+        }
+    }
+}
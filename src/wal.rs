@@ -0,0 +1,664 @@
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::broadcast;
+
+use crate::{
+    auth::decode_keys,
+    filter::{decode_creation_mode, encode_creation_mode, fnv1a},
+    CardinalitySpec, CountMinSketch, CreationMode, Error, FilterKind, FilterSnapshot, FilterSpec,
+    FilterStore, FullAction, HashScheme, HyperLogLog, SketchSpec, StableParams, Storage,
+};
+
+/// Identifies a segment of the write-ahead log.
+const SEGMENT_MAGIC: &[u8; 8] = b"BLOOMWAL";
+/// Version of the segment format.
+const SEGMENT_VERSION: u32 = 1;
+/// Size of the segment header, in bytes.
+const SEGMENT_HEADER_LEN: usize = 12;
+/// Number of operations kept for each replica; slower replicas miss the older ones, and sync again.
+const FEED_CAPACITY: usize = 1 << 16;
+
+/// A change to the filters of a store, as recorded in the write-ahead log.
+///
+/// Every operation sets the state of a single filter, or adds to it, so that replaying operations
+/// already contained in a snapshot does not change the result. Inserts into and removals from
+/// counting filters, inserts into stable filters, and increments of sketches are the exceptions,
+/// which is why [`FilterStore::save`] copies these at the exact point where it starts a new
+/// segment.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Operation<'a> {
+    /// An empty filter was created, replacing any filter of the same name.
+    Create {
+        name: &'a str,
+        item_count: usize,
+        creation_mode: CreationMode,
+        storage: Storage,
+        kind: FilterKind,
+        /// Whether a standard filter rotates once full.
+        rotating: bool,
+        /// The resolved parameters of a stable filter.
+        stable: Option<StableParams>,
+        scheme: HashScheme,
+        seed: u64,
+    },
+    /// An item, already transformed, was inserted.
+    Insert {
+        name: &'a str,
+        item: &'a [u8],
+    },
+    /// An item, already transformed, was removed from a counting filter.
+    Remove {
+        name: &'a str,
+        item: &'a [u8],
+    },
+    Clear {
+        name: &'a str,
+    },
+    Delete {
+        name: &'a str,
+    },
+    /// A filter was set to an uploaded (or merged) snapshot, encoded as a filter file.
+    Put {
+        name: &'a str,
+        snapshot: Vec<u8>,
+    },
+    /// An empty Count-Min sketch was created.
+    CreateSketch {
+        name: &'a str,
+        width: usize,
+        depth: usize,
+    },
+    /// An item was counted `count` times in a sketch.
+    Increment {
+        name: &'a str,
+        item: &'a [u8],
+        count: u64,
+    },
+    DeleteSketch {
+        name: &'a str,
+    },
+    /// An empty HyperLogLog was created.
+    CreateCardinality {
+        name: &'a str,
+        precision: u8,
+    },
+    /// An item was inserted into a HyperLogLog.
+    InsertCardinality {
+        name: &'a str,
+        item: &'a [u8],
+    },
+    /// A HyperLogLog was set to the result of a merge.
+    PutCardinality {
+        name: &'a str,
+        registers: &'a [u8],
+    },
+    DeleteCardinality {
+        name: &'a str,
+    },
+    /// The labels of a filter were set, replacing all previous ones.
+    Label {
+        name: &'a str,
+        labels: BTreeMap<String, String>,
+    },
+    /// An API key was created; the name is its id, and the key is [encoded](crate::auth::encode_keys).
+    CreateKey {
+        name: &'a str,
+        key: Vec<u8>,
+    },
+    /// An API key was revoked; the name is its id.
+    RevokeKey {
+        name: &'a str,
+    },
+    /// A sketch was set to a copy, encoded as a [sketch file](CountMinSketch::encode); only sent
+    /// to replicas, as they sync.
+    PutSketch {
+        name: &'a str,
+        sketch: Vec<u8>,
+    },
+}
+
+impl<'a> Operation<'a> {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let (kind, name) = match self {
+            Operation::Create { name, .. } => (0u8, name),
+            Operation::Insert { name, .. } => (1, name),
+            Operation::Clear { name } => (2, name),
+            Operation::Delete { name } => (3, name),
+            Operation::Put { name, .. } => (4, name),
+            Operation::Remove { name, .. } => (5, name),
+            Operation::CreateSketch { name, .. } => (6, name),
+            Operation::Increment { name, .. } => (7, name),
+            Operation::DeleteSketch { name } => (8, name),
+            Operation::CreateCardinality { name, .. } => (9, name),
+            Operation::InsertCardinality { name, .. } => (10, name),
+            Operation::PutCardinality { name, .. } => (11, name),
+            Operation::DeleteCardinality { name } => (12, name),
+            Operation::Label { name, .. } => (13, name),
+            Operation::CreateKey { name, .. } => (14, name),
+            Operation::RevokeKey { name } => (15, name),
+            Operation::PutSketch { name, .. } => (16, name),
+        };
+        let mut bytes = vec![kind];
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+        match self {
+            Operation::Create {
+                item_count,
+                creation_mode,
+                storage,
+                kind,
+                rotating,
+                stable,
+                scheme,
+                seed,
+                ..
+            } => {
+                let (mode, parameter) = encode_creation_mode(*creation_mode);
+                bytes.extend_from_slice(&(*item_count as u64).to_le_bytes());
+                bytes.extend_from_slice(&mode.to_le_bytes());
+                bytes.extend_from_slice(&parameter.to_le_bytes());
+                bytes.push(match storage {
+                    Storage::Memory => 0,
+                    Storage::Mmap => 1,
+                    Storage::Atomic => 2,
+                });
+                bytes.push(match kind {
+                    FilterKind::Standard if *rotating => 4,
+                    FilterKind::Standard => 0,
+                    FilterKind::Counting => 1,
+                    FilterKind::Scalable => 2,
+                    FilterKind::Stable => 3,
+                });
+                if let Some(stable) = stable {
+                    bytes.push(stable.max);
+                    bytes.extend_from_slice(&stable.decrements.unwrap_or(0).to_le_bytes());
+                }
+                // Filters hashed otherwise than by default are followed by their scheme and seed
+                if (*scheme, *seed) != (HashScheme::Native, 0) {
+                    bytes.push(scheme.code() as u8);
+                    bytes.extend_from_slice(&seed.to_le_bytes());
+                }
+            }
+            Operation::Insert { item, .. }
+            | Operation::Remove { item, .. }
+            | Operation::InsertCardinality { item, .. } => bytes.extend_from_slice(item),
+            Operation::CreateCardinality { precision, .. } => bytes.push(*precision),
+            Operation::PutCardinality { registers, .. } => bytes.extend_from_slice(registers),
+            Operation::Put { snapshot, .. }
+            | Operation::CreateKey { key: snapshot, .. }
+            | Operation::PutSketch {
+                sketch: snapshot, ..
+            } => bytes.extend_from_slice(snapshot),
+            Operation::Label { labels, .. } => bytes.extend_from_slice(&encode_labels(labels)),
+            Operation::CreateSketch { width, depth, .. } => {
+                bytes.extend_from_slice(&(*width as u64).to_le_bytes());
+                bytes.extend_from_slice(&(*depth as u32).to_le_bytes());
+            }
+            Operation::Increment { item, count, .. } => {
+                bytes.extend_from_slice(&count.to_le_bytes());
+                bytes.extend_from_slice(item);
+            }
+            Operation::Clear { .. }
+            | Operation::Delete { .. }
+            | Operation::DeleteSketch { .. }
+            | Operation::DeleteCardinality { .. }
+            | Operation::RevokeKey { .. } => {}
+        }
+        bytes
+    }
+
+    /// Decodes an operation encoded by [`Operation::encode`]; `None` if it is malformed.
+    fn decode(bytes: &'a [u8]) -> Option<Self> {
+        let (&kind, rest) = bytes.split_first()?;
+        let name_len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let name = std::str::from_utf8(rest.get(4..4 + name_len)?).ok()?;
+        let rest = &rest[4 + name_len..];
+        match kind {
+            0 if rest.len() >= 22 => {
+                let kind = match rest[21] {
+                    0 | 4 => FilterKind::Standard,
+                    1 => FilterKind::Counting,
+                    2 => FilterKind::Scalable,
+                    3 => FilterKind::Stable,
+                    _ => return None,
+                };
+                // Stable filters are followed by their max and decrements
+                let (stable, hashing) = match (kind, &rest[22..]) {
+                    (FilterKind::Stable, &[max, ref rest @ ..]) if rest.len() >= 4 => {
+                        let stable = StableParams {
+                            max,
+                            decrements: Some(u32::from_le_bytes(rest[..4].try_into().ok()?)),
+                        };
+                        (Some(stable), &rest[4..])
+                    }
+                    (FilterKind::Stable, _) => return None,
+                    (_, rest) => (None, rest),
+                };
+                let (scheme, seed) = match hashing {
+                    [] => (HashScheme::Native, 0),
+                    &[scheme, ref seed @ ..] if seed.len() == 8 => (
+                        HashScheme::from_code(u32::from(scheme))?,
+                        u64::from_le_bytes(seed.try_into().ok()?),
+                    ),
+                    _ => return None,
+                };
+                Some(Operation::Create {
+                    name,
+                    item_count: usize::try_from(u64::from_le_bytes(rest[..8].try_into().ok()?))
+                        .ok()?,
+                    creation_mode: decode_creation_mode(
+                        u32::from_le_bytes(rest[8..12].try_into().ok()?),
+                        u64::from_le_bytes(rest[12..20].try_into().ok()?),
+                    )
+                    .ok()?,
+                    storage: match rest[20] {
+                        0 => Storage::Memory,
+                        1 => Storage::Mmap,
+                        2 => Storage::Atomic,
+                        _ => return None,
+                    },
+                    kind,
+                    rotating: rest[21] == 4,
+                    stable,
+                    scheme,
+                    seed,
+                })
+            }
+            1 => Some(Operation::Insert { name, item: rest }),
+            2 if rest.is_empty() => Some(Operation::Clear { name }),
+            3 if rest.is_empty() => Some(Operation::Delete { name }),
+            4 => Some(Operation::Put {
+                name,
+                snapshot: rest.to_vec(),
+            }),
+            5 => Some(Operation::Remove { name, item: rest }),
+            6 if rest.len() == 12 => Some(Operation::CreateSketch {
+                name,
+                width: usize::try_from(u64::from_le_bytes(rest[..8].try_into().ok()?)).ok()?,
+                depth: u32::from_le_bytes(rest[8..].try_into().ok()?) as usize,
+            }),
+            7 if rest.len() >= 8 => Some(Operation::Increment {
+                name,
+                item: &rest[8..],
+                count: u64::from_le_bytes(rest[..8].try_into().ok()?),
+            }),
+            8 if rest.is_empty() => Some(Operation::DeleteSketch { name }),
+            9 if rest.len() == 1 => Some(Operation::CreateCardinality {
+                name,
+                precision: rest[0],
+            }),
+            10 => Some(Operation::InsertCardinality { name, item: rest }),
+            11 => Some(Operation::PutCardinality {
+                name,
+                registers: rest,
+            }),
+            12 if rest.is_empty() => Some(Operation::DeleteCardinality { name }),
+            13 => Some(Operation::Label {
+                name,
+                labels: decode_labels(rest).ok()?,
+            }),
+            14 => Some(Operation::CreateKey {
+                name,
+                key: rest.to_vec(),
+            }),
+            15 if rest.is_empty() => Some(Operation::RevokeKey { name }),
+            16 => Some(Operation::PutSketch {
+                name,
+                sketch: rest.to_vec(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The write-ahead log of a store, a no-op until [`FilterStore::start_log`] is called, and the
+/// feed of its operations to the replicas following it.
+///
+/// Operations are recorded while the filters are locked for writing, so the log has the order
+/// in which they were applied.
+pub(crate) struct Journal {
+    wal: Mutex<Option<Wal>>,
+    /// Operations, [encoded](Operation::encode), broadcast to the replicas.
+    feed: broadcast::Sender<Arc<[u8]>>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Journal {
+            wal: Mutex::default(),
+            feed: broadcast::channel(FEED_CAPACITY).0,
+        }
+    }
+}
+
+impl Journal {
+    /// Records an operation, before it is applied.
+    pub(crate) fn record(&self, operation: Operation<'_>) -> Result<(), Error> {
+        let mut wal = self.wal.lock();
+        let replicated = self.feed.receiver_count() > 0;
+        if wal.is_none() && !replicated {
+            return Ok(());
+        }
+        let encoded = operation.encode();
+        if replicated {
+            let _ = self.feed.send(encoded.as_slice().into());
+        }
+        match wal.as_mut() {
+            Some(wal) => wal
+                .append(&encoded)
+                .map_err(|e| Error::Storage(e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Subscribes to the operations recorded from now on, encoded.
+    #[cfg(feature = "server")]
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Arc<[u8]>> {
+        self.feed.subscribe()
+    }
+
+    /// Returns the directory and segment size of the log; `None` if the log is not started.
+    pub(crate) fn settings(&self) -> Option<(PathBuf, u64)> {
+        self.wal
+            .lock()
+            .as_ref()
+            .map(|wal| (wal.dir.clone(), wal.segment_bytes))
+    }
+
+    /// Stops recording operations, e.g. before the log is removed.
+    pub(crate) fn stop(&self) {
+        *self.wal.lock() = None;
+    }
+
+    /// Starts a new segment, returning its sequence number; `None` if the log is not started.
+    pub(crate) fn rotate(&self) -> io::Result<Option<u64>> {
+        match self.wal.lock().as_mut() {
+            Some(wal) => wal.rotate().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the segments older than `sequence`, whose operations are all in a snapshot.
+    pub(crate) fn compact(&self, sequence: u64) -> io::Result<()> {
+        if let Some(wal) = self.wal.lock().as_ref() {
+            for (older, path) in segments(&wal.dir)? {
+                if older < sequence {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The segment of the log currently appended to.
+struct Wal {
+    dir: PathBuf,
+    segment_bytes: u64,
+    sequence: u64,
+    file: File,
+    size: u64,
+}
+
+impl Wal {
+    fn open(dir: &Path, segment_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let sequence = segments(dir)?.last().map_or(1, |(last, _)| last + 1);
+        let (file, size) = create_segment(dir, sequence)?;
+        Ok(Wal {
+            dir: dir.to_path_buf(),
+            segment_bytes,
+            sequence,
+            file,
+            size,
+        })
+    }
+
+    /// Appends the [record](encode_record) of an operation.
+    fn append(&mut self, operation: &[u8]) -> io::Result<()> {
+        if self.size >= self.segment_bytes {
+            self.rotate()?;
+        }
+        let record = encode_record(operation);
+        // A single write, so that a crash leaves at most one torn record at the end
+        self.file.write_all(&record)?;
+        self.size += record.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<u64> {
+        let (file, size) = create_segment(&self.dir, self.sequence + 1)?;
+        self.sequence += 1;
+        self.file = file;
+        self.size = size;
+        Ok(self.sequence)
+    }
+}
+
+/// Encodes the record of an operation: its length, the operation, and its FNV-1a checksum.
+pub(crate) fn encode_record(operation: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(operation.len() + 12);
+    record.extend_from_slice(&(operation.len() as u32).to_le_bytes());
+    record.extend_from_slice(operation);
+    record.extend_from_slice(&fnv1a(operation).to_le_bytes());
+    record
+}
+
+/// Creates an empty segment, returning it and its size.
+fn create_segment(dir: &Path, sequence: u64) -> io::Result<(File, u64)> {
+    let mut file = OpenOptions::new()
+        .create_new(true)
+        .append(true)
+        .open(dir.join(format!("wal-{sequence:08}.log")))?;
+    file.write_all(SEGMENT_MAGIC)?;
+    file.write_all(&SEGMENT_VERSION.to_le_bytes())?;
+    Ok((file, SEGMENT_HEADER_LEN as u64))
+}
+
+/// Lists the segments of the log in a directory, oldest first.
+fn segments(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let sequence = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("wal-")?.strip_suffix(".log"))
+            .and_then(|sequence| sequence.parse().ok());
+        if let Some(sequence) = sequence {
+            segments.push((sequence, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+impl FilterStore {
+    /// Starts recording creates, inserts, removals, clears, deletes and uploads of filters, and the
+    /// changes to sketches and HyperLogLogs, in a write-ahead log in `dir`, in segments of about
+    /// `segment_bytes` each.
+    ///
+    /// Call this after [`FilterStore::load`], which replays the log of a previous run. Segments
+    /// are removed by [`FilterStore::save`] once a snapshot supersedes them. Each
+    /// [namespace](FilterStore::create_namespace) logs to its own subdirectory of `dir`.
+    pub fn start_log(&self, dir: &Path, segment_bytes: u64) -> io::Result<()> {
+        *self.journal.wal.lock() = Some(Wal::open(dir, segment_bytes)?);
+        self.start_namespace_logs(dir, segment_bytes)
+    }
+
+    /// Replays the segments of the log in `dir` on top of the loaded snapshots, returning the
+    /// number of operations replayed.
+    ///
+    /// A record that is truncated or fails its checksum ends its segment: it can only be the last
+    /// record written before a crash.
+    pub(crate) fn replay(&self, dir: &Path) -> io::Result<usize> {
+        let mut replayed = 0;
+        for (_, path) in segments(dir)? {
+            let bytes = fs::read(&path)?;
+            if bytes.len() < SEGMENT_HEADER_LEN || &bytes[..8] != SEGMENT_MAGIC {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: not a write-ahead log segment", path.display()),
+                ));
+            }
+            let mut records = &bytes[SEGMENT_HEADER_LEN..];
+            while let Some((operation, rest)) = next_record(records) {
+                self.apply_operation(operation);
+                replayed += 1;
+                records = rest;
+            }
+        }
+        Ok(replayed)
+    }
+
+    /// Applies a logged operation, skipping operations on filters that no longer exist.
+    pub(crate) fn apply_operation(&self, operation: Operation<'_>) {
+        match operation {
+            Operation::Create {
+                name,
+                item_count,
+                creation_mode,
+                storage,
+                kind,
+                rotating,
+                stable,
+                scheme,
+                seed,
+            } => {
+                let (false_positive_rate, hash_count) = match creation_mode {
+                    CreationMode::FalsePositiveRate(rate) => (Some(rate), None),
+                    CreationMode::HashCount(count) => (None, Some(count)),
+                };
+                let spec = FilterSpec {
+                    name: name.to_string(),
+                    item_count,
+                    false_positive_rate,
+                    hash_count,
+                    storage,
+                    kind,
+                    stable,
+                    on_full: rotating.then_some(FullAction::Rotate),
+                    scheme,
+                    seed,
+                    ..Default::default()
+                };
+                if let Ok(container) = spec.build(self.data_dir.as_deref()) {
+                    self.filters
+                        .write()
+                        .insert(name.to_string(), Arc::new(RwLock::new(container)));
+                }
+            }
+            Operation::Insert { name, item } => {
+                if let Some(entry) = self.resident(name) {
+                    entry.write().filter.insert(item);
+                }
+            }
+            Operation::Remove { name, item } => {
+                if let Some(entry) = self.resident(name) {
+                    entry.write().filter.remove(item);
+                }
+            }
+            Operation::Clear { name } => {
+                if let Some(entry) = self.resident(name) {
+                    let mut container = entry.write();
+                    container.filter.clear();
+                    container.generation += 1;
+                }
+            }
+            Operation::Delete { name } => {
+                let _ = self.delete(name);
+            }
+            Operation::Put { name, snapshot } => {
+                if let Ok(snapshot) = FilterSnapshot::decode(&snapshot) {
+                    let _ = self.restore(name, snapshot);
+                }
+            }
+            Operation::CreateSketch { name, width, depth } => {
+                let spec = SketchSpec {
+                    name: name.to_string(),
+                    width: Some(width),
+                    depth: Some(depth),
+                };
+                if let Ok(sketch) = CountMinSketch::new(&spec) {
+                    self.sketches.write().insert(name.to_string(), sketch);
+                }
+            }
+            Operation::Increment { name, item, count } => {
+                if let Some(sketch) = self.sketches.write().get_mut(name) {
+                    sketch.increment(item, count);
+                }
+            }
+            Operation::DeleteSketch { name } => {
+                self.sketches.write().remove(name);
+            }
+            Operation::CreateCardinality { name, precision } => {
+                let spec = CardinalitySpec {
+                    name: name.to_string(),
+                    precision: Some(precision),
+                };
+                if let Ok(hll) = HyperLogLog::new(&spec) {
+                    self.cardinality.write().insert(name.to_string(), hll);
+                }
+            }
+            Operation::InsertCardinality { name, item } => {
+                if let Some(hll) = self.cardinality.write().get_mut(name) {
+                    hll.insert(item);
+                }
+            }
+            Operation::PutCardinality { name, registers } => {
+                if let Ok(hll) = HyperLogLog::from_registers(name, registers.to_vec()) {
+                    self.cardinality.write().insert(name.to_string(), hll);
+                }
+            }
+            Operation::DeleteCardinality { name } => {
+                self.cardinality.write().remove(name);
+            }
+            Operation::Label { name, labels } => {
+                if let Some(entry) = self.resident(name) {
+                    entry.write().labels = labels;
+                }
+            }
+            Operation::CreateKey { key, .. } => {
+                for key in decode_keys(&key).unwrap_or_default() {
+                    self.api_keys.write().insert(key.id.clone(), key);
+                }
+            }
+            Operation::RevokeKey { name } => {
+                self.api_keys.write().remove(name);
+            }
+            Operation::PutSketch { name, sketch } => {
+                if let Ok(sketch) = CountMinSketch::decode(name, &sketch) {
+                    self.sketches.write().insert(name.to_string(), sketch);
+                }
+            }
+        }
+    }
+}
+
+/// Encodes the labels of a filter, as a JSON object.
+pub(crate) fn encode_labels(labels: &BTreeMap<String, String>) -> Vec<u8> {
+    serde_json::to_vec(labels).unwrap_or_default()
+}
+
+/// Decodes labels encoded by [`encode_labels`].
+pub(crate) fn decode_labels(bytes: &[u8]) -> Result<BTreeMap<String, String>, Error> {
+    serde_json::from_slice(bytes)
+        .map_err(|error| Error::InvalidParameters(format!("Invalid labels: {error}")))
+}
+
+/// Splits the next valid record off the records of a segment.
+pub(crate) fn next_record(records: &[u8]) -> Option<(Operation<'_>, &[u8])> {
+    let len = u32::from_le_bytes(records.get(..4)?.try_into().ok()?) as usize;
+    let operation = records.get(4..4 + len)?;
+    let checksum = u64::from_le_bytes(records.get(4 + len..12 + len)?.try_into().ok()?);
+    if fnv1a(operation) != checksum {
+        return None;
+    }
+    Some((Operation::decode(operation)?, &records[12 + len..]))
+}
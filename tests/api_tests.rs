@@ -7,7 +7,17 @@ use http_body_util::BodyExt; // specific dependency for reading bodies
 use tower::ServiceExt; // for `oneshot`
 
 // !!! IMPORTANT: Replace 'bloom_daemon' with the actual name of your package from Cargo.toml !!!
-use bloomsrv::{create_app, SharedState};
+use bloomsrv::{
+    create_app, create_app_with_config, create_app_with_shared_config, AppConfig, AuditLog,
+    BloomFilter, Compression, CorsPolicy, CreationMode, FilterSnapshot, FilterSpec, FilterStore,
+    JwtValidator, KeyScope, KeySpec, Quotas, Reloader, RequestLimits, ShardPeers, ShardRing,
+    SharedConfig, SharedState, Throttle,
+};
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 // --- Helper to convert response body to Serde Value ---
 async fn response_json(response: axum::response::Response) -> serde_json::Value {
@@ -21,7 +31,6 @@ async fn response_json(response: axum::response::Response) -> serde_json::Value
 #[tokio::test]
 async fn test_create_filter_validation() {
     let state = SharedState::default();
-    let app = create_app(state);
 
     // Case 1: Missing required params (neither hash_count nor fp_rate)
     let payload = serde_json::json!({
@@ -36,7 +45,25 @@ async fn test_create_filter_validation() {
         .body(Body::from(payload.to_string()))
         .unwrap();
 
-    let response = app.oneshot(req).await.unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Case 2: Conflicting params (both hash_count and fp_rate)
+    let payload = serde_json::json!({
+        "name": "bad_filter",
+        "item_count": 1000,
+        "hash_count": 5,
+        "false_positive_rate": 0.01
+    });
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+
+    let response = create_app(state).oneshot(req).await.unwrap();
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 }
 
@@ -55,6 +82,327 @@ async fn test_delete_non_existent() {
     assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn test_put_creates_filter_once() {
+    let state = SharedState::default();
+    let put = |payload: serde_json::Value| {
+        Request::builder()
+            .method("PUT")
+            .uri("/filters/sessions")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    };
+    let payload = serde_json::json!({ "item_count": 1000, "false_positive_rate": 0.01 });
+
+    // 1. CREATE the filter if absent
+    let response = create_app(state.clone())
+        .oneshot(put(payload.clone()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created = response_json(response).await;
+    assert_eq!(created["name"], "sessions");
+
+    // 2. RETURN the existing filter when the parameters match
+    let response = create_app(state.clone())
+        .oneshot(put(payload))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let existing = response_json(response).await;
+    assert_eq!(existing["id"], created["id"]);
+
+    // 3. CONFLICT when they do not
+    let payload = serde_json::json!({ "item_count": 2000, "false_positive_rate": 0.01 });
+    let response = create_app(state.clone())
+        .oneshot(put(payload))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let json = response_json(response).await;
+    assert_eq!(json["code"], "parameter_conflict");
+
+    // 4. REJECT a name in the body other than that of the path
+    let payload =
+        serde_json::json!({ "name": "other", "item_count": 1000, "false_positive_rate": 0.01 });
+    let response = create_app(state).oneshot(put(payload)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_list_pagination() {
+    let state = SharedState::default();
+    for name in ["users_c", "users_a", "orders", "users_b"] {
+        let payload = serde_json::json!({ "name": name, "item_count": 100, "hash_count": 3 });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters?name_prefix=users_&offset=1&limit=1")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.headers()["x-total-count"], "3");
+    let json = response_json(response).await;
+    let names: Vec<&str> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|filter| filter["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["users_b"]);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters?sort=created_at")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.headers()["x-total-count"], "4");
+    let json = response_json(response).await;
+    assert_eq!(json.as_array().unwrap().len(), 4);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters?sort=size")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_filter_labels() {
+    let state = SharedState::default();
+    for (name, team) in [
+        ("cards", "fraud"),
+        ("logins", "fraud"),
+        ("signups", "growth"),
+    ] {
+        let payload = serde_json::json!({
+            "name": name,
+            "item_count": 100,
+            "hash_count": 3,
+            "labels": { "team": team }
+        });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+    let names = |json: serde_json::Value| -> Vec<String> {
+        json.as_array()
+            .unwrap()
+            .iter()
+            .map(|filter| filter["name"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters?label=team=fraud")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json[0]["labels"]["team"], "fraud");
+    assert_eq!(names(json), ["cards", "logins"]);
+
+    let payload = serde_json::json!({ "labels": { "team": null, "owner": "alice" } });
+    let req = Request::builder()
+        .method("PATCH")
+        .uri("/filters/cards")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["labels"], serde_json::json!({ "owner": "alice" }));
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters?label=team")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(names(json), ["logins", "signups"]);
+
+    let payload = serde_json::json!({ "labels": { "a=b": "c" } });
+    let req = Request::builder()
+        .method("PATCH")
+        .uri("/filters/cards")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_namespaces() {
+    let state = SharedState::default();
+    let request = |method: &str, uri: &str, body: Option<serde_json::Value>| {
+        let builder = Request::builder().method(method).uri(uri);
+        let req = match body {
+            Some(body) => builder
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string())),
+            None => builder.body(Body::empty()),
+        }
+        .unwrap();
+        create_app(state.clone()).oneshot(req)
+    };
+
+    let response = request(
+        "POST",
+        "/namespaces",
+        Some(serde_json::json!({ "name": "payments" })),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let filter = serde_json::json!({ "name": "seen", "item_count": 1000, "hash_count": 4 });
+    for uri in ["/filters", "/namespaces/payments/filters"] {
+        let response = request("POST", uri, Some(filter.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/namespaces/payments/filters/seen/items")
+        .body(Body::from("card-1"))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+    let json = response_json(
+        request(
+            "GET",
+            "/namespaces/payments/filters/seen/items?item=card-1",
+            None,
+        )
+        .await
+        .unwrap(),
+    )
+    .await;
+    assert_eq!(json["contains"], true);
+    let json = response_json(
+        request("GET", "/filters/seen/items?item=card-1", None)
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(json["contains"], false);
+
+    let json = response_json(request("GET", "/namespaces", None).await.unwrap()).await;
+    assert_eq!(json[0]["name"], "payments");
+    assert_eq!(json[0]["filters"], 1);
+
+    let response = request("GET", "/namespaces/search/filters", None)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = request("GET", "/namespaces/payments/namespaces", None)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = request("DELETE", "/namespaces/payments", None)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = request("GET", "/namespaces/payments/filters", None)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(state.list().len(), 1);
+}
+
+#[tokio::test]
+async fn test_versioned_routes() {
+    let state = SharedState::default();
+    let config = AppConfig {
+        admin_key: Some("root-secret".to_string()),
+        ..Default::default()
+    };
+    let request = |method: &str, uri: &str, key: Option<&str>, body: Body| {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(key) = key {
+            req = req.header("authorization", format!("Bearer {key}"));
+        }
+        let app = create_app_with_config(state.clone(), config.clone());
+        app.oneshot(req.body(body).unwrap())
+    };
+    let json = |value: serde_json::Value| Body::from(value.to_string());
+
+    // 1. The routes are served under /v1, with the same middleware
+    let filter = serde_json::json!({ "name": "seen", "item_count": 1000, "hash_count": 4 });
+    let response = request("POST", "/v1/filters", None, json(filter.clone()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = request("POST", "/v1/filters", Some("root-secret"), json(filter))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let uri = "/v1/filters/seen/items";
+    let response = request("POST", uri, Some("root-secret"), Body::from("alice"))
+        .await
+        .unwrap();
+    assert_eq!(response_json(response).await["new"], true);
+
+    // 2. The unversioned paths are aliases of the first version
+    let uri = "/filters/seen/items?item=alice";
+    let response = request("GET", uri, Some("root-secret"), Body::empty())
+        .await
+        .unwrap();
+    assert_eq!(response_json(response).await["contains"], true);
+    let uri = "/v1/filters/seen/items/alice";
+    let response = request("GET", uri, Some("root-secret"), Body::empty())
+        .await
+        .unwrap();
+    assert_eq!(response_json(response).await["contains"], true);
+
+    // 3. Namespaces are versioned as well
+    let namespace = serde_json::json!({ "name": "payments" });
+    let response = request(
+        "POST",
+        "/v1/namespaces",
+        Some("root-secret"),
+        json(namespace),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = request(
+        "GET",
+        "/v1/namespaces/payments/filters",
+        Some("root-secret"),
+        Body::empty(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response_json(response).await, serde_json::json!([]));
+
+    // 4. The probes are not versioned
+    let response = request("GET", "/livez", None, Body::empty()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = request("GET", "/v1/livez", Some("root-secret"), Body::empty())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[tokio::test]
 async fn test_full_filter_lifecycle() {
     let state = SharedState::default();
@@ -79,6 +427,8 @@ async fn test_full_filter_lifecycle() {
     let json = response_json(response).await;
     let filter_id = json.get("id").unwrap().as_str().unwrap();
     assert!(!filter_id.is_empty());
+    assert_eq!(json["bit_count"], 9600);
+    assert_eq!(json["hash_count"], 7);
 
     // 2. LIST to verify it exists
     let req = Request::builder()
@@ -116,6 +466,19 @@ async fn test_full_filter_lifecycle() {
 
     let response = create_app(state.clone()).oneshot(req).await.unwrap();
     assert_eq!(response.status(), StatusCode::OK);
+    let json = response_json(response).await;
+    assert_eq!(json["new"], true);
+
+    // 4b. INSERT the same item again (No longer new)
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/login_attempts/items")
+        .body(Body::from("user_123"))
+        .unwrap();
+
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    let json = response_json(response).await;
+    assert_eq!(json["new"], false);
 
     // 5. LOOKUP (Should be true)
     let req = Request::builder()
@@ -171,3 +534,5683 @@ async fn test_full_filter_lifecycle() {
     let list = json.as_array().unwrap();
     assert_eq!(list.len(), 0);
 }
+
+#[tokio::test]
+async fn test_filter_ttl_expiry() {
+    let state = SharedState::default();
+
+    for (name, on_expire) in [("short_lived", "delete"), ("periodic", "clear")] {
+        let payload = serde_json::json!({
+            "name": name,
+            "item_count": 100,
+            "hash_count": 3,
+            "ttl_seconds": 60,
+            "on_expire": on_expire
+        });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        let response = create_app(state.clone()).oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/periodic/items")
+        .body(Body::from("user_123"))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    // The list reports the remaining time-to-live
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    for item in json.as_array().unwrap() {
+        assert_eq!(item["ttl_remaining_seconds"], 60);
+    }
+
+    // Nothing expires before the time-to-live elapses
+    assert_eq!(state.expire(Instant::now()), 0);
+
+    // Once elapsed, one filter is deleted and the other cleared
+    let later = Instant::now() + Duration::from_secs(61);
+    assert_eq!(state.expire(later), 2);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    let list = json.as_array().unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0]["name"], "periodic");
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/periodic/items")
+        .body(Body::from("user_123"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], false);
+}
+
+#[tokio::test]
+async fn test_filter_ttl_too_large() {
+    let state = SharedState::default();
+    let payload = serde_json::json!({
+        "name": "forever",
+        "item_count": 100,
+        "false_positive_rate": 0.01,
+        "ttl_seconds": u64::MAX
+    });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let json = response_json(response).await;
+    assert_eq!(json["code"], "invalid_parameters");
+    assert!(state.filters.read().is_empty());
+}
+
+#[tokio::test]
+async fn test_filter_idle_expiry() {
+    let state = SharedState::default();
+
+    let payloads = [
+        serde_json::json!({ "name": "idle", "item_count": 100, "hash_count": 3, "expire_after_idle_seconds": 60 }),
+        serde_json::json!({ "name": "busy", "item_count": 100, "hash_count": 3, "expire_after_idle_seconds": 120 }),
+        serde_json::json!({ "name": "forever", "item_count": 100, "hash_count": 3 }),
+    ];
+    for payload in payloads {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        let response = create_app(state.clone()).oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    // Only the filter idle for longer than its timeout is removed
+    let later = Instant::now() + Duration::from_secs(90);
+    assert_eq!(state.expire(later), 1);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    let mut names: Vec<_> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["name"].as_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["busy", "forever"]);
+}
+
+#[tokio::test]
+async fn test_filter_group_operations() {
+    let state = SharedState::default();
+
+    for name in ["hourly", "daily", "weekly"] {
+        let payload =
+            serde_json::json!({ "name": name, "item_count": 1000, "false_positive_rate": 0.01 });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+
+    // 1. CREATE a group over the tiered filters
+    let payload = serde_json::json!({ "name": "tiers", "filters": ["hourly", "daily", "weekly"] });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/groups")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // 2. INSERT into all members, and into one member only
+    let req = Request::builder()
+        .method("POST")
+        .uri("/groups/tiers/items")
+        .body(Body::from("user_123"))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/weekly/items")
+        .body(Body::from("user_456"))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    // 3. LOOKUP with any/all semantics
+    let req = Request::builder()
+        .method("GET")
+        .uri("/groups/tiers/items?mode=all")
+        .body(Body::from("user_123"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], true);
+    assert_eq!(json["filters"]["daily"], true);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/groups/tiers/items?mode=any")
+        .body(Body::from("user_456"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], true);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/groups/tiers/items?mode=all")
+        .body(Body::from("user_456"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], false);
+
+    // 4. DELETE a member filter, which leaves the group
+    let req = Request::builder()
+        .method("DELETE")
+        .uri("/filters/hourly")
+        .body(Body::empty())
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/groups")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json[0]["filters"], serde_json::json!(["daily", "weekly"]));
+
+    // 5. CLEAR the group
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/groups/tiers/clear")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/groups/tiers/items")
+        .body(Body::from("user_123"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], false);
+
+    // 6. DELETE the group together with its filters
+    let req = Request::builder()
+        .method("DELETE")
+        .uri("/groups/tiers")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json.as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn test_admin_apply_reconciliation() {
+    let state = SharedState::default();
+
+    // An extra filter, not part of the manifest
+    let payload = serde_json::json!({ "name": "legacy", "item_count": 100, "hash_count": 3 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    let apply = |manifest: serde_json::Value, query: &str| {
+        Request::builder()
+            .method("POST")
+            .uri(format!("/admin/apply{query}"))
+            .header("content-type", "application/json")
+            .body(Body::from(manifest.to_string()))
+            .unwrap()
+    };
+    let manifest = serde_json::json!({
+        "filters": [
+            { "name": "hourly", "item_count": 1000, "false_positive_rate": 0.01 },
+            { "name": "daily", "item_count": 10000, "false_positive_rate": 0.01 }
+        ],
+        "groups": [ { "name": "tiers", "filters": ["hourly", "daily"] } ]
+    });
+
+    // 1. DRY RUN reports the changes without applying them
+    let response = create_app(state.clone())
+        .oneshot(apply(manifest.clone(), "?dry_run=true&prune=true"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response_json(response).await;
+    assert_eq!(
+        json["filters"]["created"],
+        serde_json::json!(["hourly", "daily"])
+    );
+    assert_eq!(json["filters"]["deleted"], serde_json::json!(["legacy"]));
+    assert_eq!(state.filters.read().len(), 1);
+
+    // 2. APPLY with pruning
+    let response = create_app(state.clone())
+        .oneshot(apply(manifest.clone(), "?prune=true"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut names: Vec<_> = state.filters.read().keys().cloned().collect();
+    names.sort();
+    assert_eq!(names, vec!["daily", "hourly"]);
+    assert_eq!(
+        state.groups.read()["tiers"].filters,
+        vec!["hourly", "daily"]
+    );
+
+    // 3. RE-APPLY is a no-op, while metadata changes are updates
+    let mut updated = manifest.clone();
+    updated["filters"][0]["ttl_seconds"] = serde_json::json!(3600);
+    let json = response_json(
+        create_app(state.clone())
+            .oneshot(apply(updated, ""))
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(json["filters"]["updated"], serde_json::json!(["hourly"]));
+    assert_eq!(json["filters"]["unchanged"], serde_json::json!(["daily"]));
+    assert!(state.filters.read()["hourly"].read().expiry.is_some());
+
+    // 4. CONFLICTING parameters are rejected as a whole
+    let mut conflicting = manifest.clone();
+    conflicting["filters"][1]["item_count"] = serde_json::json!(5);
+    conflicting["filters"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::json!({ "name": "weekly", "item_count": 100, "hash_count": 3 }));
+    let response = create_app(state.clone())
+        .oneshot(apply(conflicting, ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert!(!state.filters.read().contains_key("weekly"));
+}
+
+#[tokio::test]
+async fn test_shadow_filter_mirroring() {
+    let state = SharedState::default();
+
+    for (name, count) in [("primary", 1000), ("candidate", 10)] {
+        let payload = serde_json::json!({ "name": name, "item_count": count, "hash_count": 1 });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+
+    // 1. CONFIGURE the shadow filter
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/filters/primary/shadow")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{ "filter": "candidate" }"#))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // 2. INSERT into the primary is mirrored to the shadow
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/primary/items")
+        .body(Body::from("user_123"))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/candidate/items")
+        .body(Body::from("user_123"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], true);
+
+    // 3. LOOKUPS on the primary are compared
+    for item in ["user_123", "user_123"] {
+        let req = Request::builder()
+            .method("GET")
+            .uri("/filters/primary/items")
+            .body(Body::from(item))
+            .unwrap();
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/primary/shadow")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["shadow"], "candidate");
+    assert_eq!(json["compared"], 2);
+    assert_eq!(json["agreed"], 2);
+    assert_eq!(json["agreement_rate"], 1.0);
+
+    // 4. DELETING the shadow filter stops the mirroring
+    let req = Request::builder()
+        .method("DELETE")
+        .uri("/filters/candidate")
+        .body(Body::empty())
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/primary/shadow")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_watch_for_item() {
+    let state = SharedState::default();
+
+    let payload =
+        serde_json::json!({ "name": "handoff", "item_count": 1000, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    // 1. WATCH times out when the item does not appear
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/handoff/items/watch?timeout_seconds=0")
+        .body(Body::from("job_42"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], false);
+    assert_eq!(json["timed_out"], true);
+
+    // 2. WATCH returns as soon as another client inserts the item
+    let watcher = tokio::spawn(
+        create_app(state.clone()).oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/filters/handoff/items/watch?timeout_seconds=10")
+                .body(Body::from("job_42"))
+                .unwrap(),
+        ),
+    );
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/handoff/items")
+        .body(Body::from("job_42"))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    let response = tokio::time::timeout(Duration::from_secs(5), watcher)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let json = response_json(response).await;
+    assert_eq!(json["contains"], true);
+    assert_eq!(json["timed_out"], false);
+}
+
+/// Lowercases ASCII items in place and rejects empty ones.
+#[cfg(feature = "wasm")]
+const LOWERCASE_TRANSFORM: &str = r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param i32) (result i32) i32.const 0)
+  (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+    (local $i i32) (local $c i32)
+    (if (i32.eqz (local.get $len)) (then (return (i64.const -1))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+        (local.set $c (i32.load8_u (i32.add (local.get $ptr) (local.get $i))))
+        (if (i32.and (i32.ge_u (local.get $c) (i32.const 65)) (i32.le_u (local.get $c) (i32.const 90)))
+          (then (i32.store8 (i32.add (local.get $ptr) (local.get $i)) (i32.add (local.get $c) (i32.const 32)))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+      (i64.extend_i32_u (local.get $len)))))"#;
+
+#[cfg(feature = "wasm")]
+#[tokio::test]
+async fn test_filter_transform() {
+    let state = SharedState::default();
+
+    let payload =
+        serde_json::json!({ "name": "emails", "item_count": 1000, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    // 1. LOAD a transform, rejecting invalid modules
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/filters/emails/transform")
+        .body(Body::from("not a module"))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/filters/emails/transform")
+        .body(Body::from(LOWERCASE_TRANSFORM))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // 2. INSERTS and LOOKUPS see the transformed item
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/emails/items")
+        .body(Body::from("Alice@Example.com"))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/emails/items")
+        .body(Body::from("ALICE@EXAMPLE.COM"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], true);
+
+    // 3. REJECTED items are not inserted
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/emails/items")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // 4. REMOVE the transform: items are used as given again
+    let req = Request::builder()
+        .method("DELETE")
+        .uri("/filters/emails/transform")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/emails/items")
+        .body(Body::from("ALICE@EXAMPLE.COM"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], false);
+
+    let req = Request::builder()
+        .method("DELETE")
+        .uri("/filters/emails/transform")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[cfg(feature = "wasm")]
+#[tokio::test]
+async fn test_filter_transform_out_of_fuel() {
+    let state = SharedState::default();
+
+    let payload = serde_json::json!({ "name": "loop", "item_count": 100, "hash_count": 2 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    let endless = r#"(module
+      (memory (export "memory") 1)
+      (func (export "alloc") (param i32) (result i32) i32.const 0)
+      (func (export "transform") (param i32 i32) (result i64)
+        (loop $forever (br $forever))
+        i64.const 0))"#;
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/filters/loop/transform")
+        .body(Body::from(endless))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    // A module that never returns fails the request instead of blocking the service
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/loop/items")
+        .body(Body::from("item"))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn test_error_responses_carry_codes() {
+    let state = SharedState::default();
+
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/groups/ghost/clear")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let json = response_json(response).await;
+    assert_eq!(json["error"], "Group 'ghost' not found");
+    assert_eq!(json["code"], "group_not_found");
+
+    let payload =
+        serde_json::json!({ "name": "f", "item_count": 10, "ttl_seconds": 0, "hash_count": 1 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let json = response_json(response).await;
+    assert_eq!(json["code"], "invalid_parameters");
+
+    let payload =
+        serde_json::json!({ "name": "a/b", "item_count": 10, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let json = response_json(response).await;
+    assert_eq!(json["code"], "invalid_name");
+}
+
+#[tokio::test]
+async fn test_request_ids() {
+    let state = SharedState::default();
+
+    // A request without an id gets one
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    let generated = response.headers()["x-request-id"].to_str().unwrap();
+    assert!(!generated.is_empty());
+
+    // The id of the client is kept, and quoted in error responses
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/ghost_filter")
+        .header("x-request-id", "req-42")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["x-request-id"], "req-42");
+    let json = response_json(response).await;
+    assert_eq!(json["request_id"], "req-42");
+    assert_eq!(json["code"], "filter_not_found");
+}
+
+#[tokio::test]
+async fn test_health_probes() {
+    let probe = |state: &SharedState, uri: &str| {
+        let req = Request::builder()
+            .method("GET")
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let app = create_app(state.clone());
+        async move { app.oneshot(req).await.unwrap() }
+    };
+    let state = SharedState::default();
+    for uri in ["/livez", "/readyz", "/healthz"] {
+        assert_eq!(probe(&state, uri).await.status(), StatusCode::OK);
+    }
+
+    // 1. NOT READY while the saved filters are restored, and other requests are rejected meanwhile
+    state.restoring.store(true, Ordering::Relaxed);
+    let response = probe(&state, "/readyz").await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let json = response_json(response).await;
+    assert_eq!(json["status"], "not_ready");
+    assert_eq!(json["reasons"][0], "The saved filters are being restored");
+    let response = probe(&state, "/filters").await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response_json(response).await["code"], "unavailable");
+    assert_eq!(probe(&state, "/livez").await.status(), StatusCode::OK);
+
+    // 2. NOT READY while the data directory cannot be written to
+    let file = std::env::temp_dir().join(format!("bloomsrv-readyz-{}", std::process::id()));
+    std::fs::write(&file, b"not a directory").unwrap();
+    let mut store = FilterStore::new();
+    store.data_dir = Some(file.clone());
+    let state = SharedState::new(store);
+    let response = probe(&state, "/readyz").await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let json = response_json(response).await;
+    assert!(json["reasons"][0]
+        .as_str()
+        .unwrap()
+        .contains("is not writable"));
+    std::fs::remove_file(file).unwrap();
+}
+
+#[tokio::test]
+async fn test_openapi_document() {
+    // The document and its browser are served without a key, like the probes
+    let config = AppConfig {
+        admin_key: Some("root-secret".to_string()),
+        ..Default::default()
+    };
+    let get = |uri: &str| {
+        let req = Request::builder()
+            .method("GET")
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let app = create_app_with_config(SharedState::default(), config.clone());
+        async move { app.oneshot(req).await.unwrap() }
+    };
+
+    // 1. The DOCUMENT describes the paths, their parameters and bodies, and how to authenticate
+    let response = get("/openapi.json").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response_json(response).await;
+    assert!(json["openapi"].as_str().unwrap().starts_with("3."));
+    assert_eq!(json["info"]["title"], "bloomsrv");
+    let insert = &json["paths"]["/v1/filters/{name}/items"]["post"];
+    assert_eq!(insert["parameters"][0]["name"], "name");
+    assert_eq!(
+        insert["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/InsertResponse"
+    );
+    let schemas = &json["components"]["schemas"];
+    assert_eq!(
+        schemas["FilterSpec"]["required"],
+        serde_json::json!(["item_count"])
+    );
+    assert!(schemas["FilterKind"]["enum"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!("counting")));
+    assert!(schemas["ErrorBody"]["properties"]["code"].is_object());
+    assert!(json["components"]["securitySchemes"]["bearer"].is_object());
+    assert!(json["paths"]["/livez"]["get"].is_object());
+
+    // 2. Every ROUTE of the application is in the document (those of the namespaces repeat those
+    // of the store), as read from the functions building the routes
+    let source = include_str!("../src/server.rs");
+    let start = source.find("pub fn create_app_with_shared_config").unwrap();
+    let end = source.find("fn command_routes").unwrap();
+    let routes = &source[start..end];
+    let mut checked = 0;
+    let mut missing = Vec::new();
+    let mut offset = 0;
+    while let Some(found) = routes[offset..].find(".route(") {
+        let at = offset + found;
+        offset = at + ".route(".len();
+        let call = &routes[offset..];
+        let call = [".route(", ";", "\n\n"]
+            .iter()
+            .filter_map(|end| call.find(end))
+            .fold(call, |call, end| &call[..end.min(call.len())]);
+        let path = call.split('"').nth(1).unwrap();
+        if path.contains('*') {
+            continue;
+        }
+        // Routes of the features the tests are not built with are not served either
+        let before = &routes[..at];
+        if let Some(cfg) = before.rfind("#[cfg(feature = \"") {
+            if !before[cfg..].contains(';') {
+                let feature = before[cfg..].split('"').nth(1).unwrap();
+                let enabled = match feature {
+                    "cluster" => cfg!(feature = "cluster"),
+                    "wasm" => cfg!(feature = "wasm"),
+                    other => panic!("Unknown feature {other}"),
+                };
+                if !enabled {
+                    continue;
+                }
+            }
+        }
+        let documented = path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(param) => format!("{{{param}}}"),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        for method in ["get", "post", "put", "patch", "delete"] {
+            let called = call.match_indices(&format!("{method}(")).any(|(i, _)| {
+                i == 0
+                    || !call.as_bytes()[i - 1].is_ascii_alphanumeric()
+                        && call.as_bytes()[i - 1] != b'_'
+            });
+            if !called {
+                continue;
+            }
+            checked += 1;
+            let paths = &json["paths"];
+            if !paths[&documented][method].is_object()
+                && !paths[format!("/v1{documented}")][method].is_object()
+            {
+                missing.push(format!("{} {path}", method.to_uppercase()));
+            }
+        }
+    }
+    assert!(checked > 80, "Only {checked} routes found");
+    assert!(
+        missing.is_empty(),
+        "Routes missing from the document: {missing:?}"
+    );
+
+    // 3. The SWAGGER UI browses the document
+    let response = get("/docs").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let page = String::from_utf8(body.to_vec()).unwrap();
+    assert!(page.contains("SwaggerUIBundle"));
+    assert!(page.contains(r#"url: "openapi.json""#));
+}
+
+#[tokio::test]
+async fn test_api_keys() {
+    let state = SharedState::default();
+    let config = AppConfig {
+        admin_key: Some("root-secret".to_string()),
+        ..Default::default()
+    };
+    let send = |method: &str, uri: &str, key: Option<&str>, body: serde_json::Value| {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(key) = key {
+            req = req.header("authorization", format!("Bearer {key}"));
+        }
+        let app = create_app_with_config(state.clone(), config.clone());
+        let req = req.body(Body::from(body.to_string())).unwrap();
+        async move { app.oneshot(req).await.unwrap() }
+    };
+
+    // 1. UNAUTHORIZED without a key, or with an unknown one; the probes are exempt
+    let response = send("GET", "/filters", None, serde_json::json!(null)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response_json(response).await["code"], "unauthorized");
+    let response = send("GET", "/filters", Some("guess"), serde_json::json!(null)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = send("GET", "/livez", None, serde_json::json!(null)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // 2. MINT a read-only key with the admin key
+    let payload = serde_json::json!({ "scope": "read", "description": "dashboards" });
+    let response = send("POST", "/admin/keys", Some("root-secret"), payload).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let json = response_json(response).await;
+    assert_eq!(json["scope"], "read");
+    let id = json["id"].as_str().unwrap().to_string();
+    let read_key = json["api_key"].as_str().unwrap().to_string();
+
+    // 3. The read-only key may list filters, but not create them, nor mint keys
+    let response = send("GET", "/filters", Some(&read_key), serde_json::json!(null)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let payload = serde_json::json!({ "name": "f", "item_count": 100, "hash_count": 3 });
+    let response = send("POST", "/filters", Some(&read_key), payload.clone()).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = send(
+        "GET",
+        "/admin/keys",
+        Some(&read_key),
+        serde_json::json!(null),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // 4. A read-write key, taken from X-Api-Key, may create filters
+    let response = send(
+        "POST",
+        "/admin/keys",
+        Some("root-secret"),
+        serde_json::json!({}),
+    )
+    .await;
+    let write_key = response_json(response).await["api_key"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .header("x-api-key", &write_key)
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app_with_config(state.clone(), config.clone())
+        .oneshot(req)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // 5. LIST the keys, without their secrets, and REVOKE one
+    let response = send(
+        "GET",
+        "/admin/keys",
+        Some("root-secret"),
+        serde_json::json!(null),
+    )
+    .await;
+    let json = response_json(response).await;
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert!(json[0].get("api_key").is_none());
+    let uri = format!("/admin/keys/{id}");
+    let response = send("DELETE", &uri, Some("root-secret"), serde_json::json!(null)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = send("GET", "/filters", Some(&read_key), serde_json::json!(null)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = send("DELETE", &uri, Some("root-secret"), serde_json::json!(null)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response_json(response).await["code"], "api_key_not_found");
+}
+
+#[tokio::test]
+async fn test_api_keys_limited_to_filters() {
+    let state = SharedState::default();
+    let config = AppConfig {
+        admin_key: Some("root-secret".to_string()),
+        ..Default::default()
+    };
+    let send = |method: &str, uri: &str, key: &str, body: serde_json::Value| {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .header("authorization", format!("Bearer {key}"))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let app = create_app_with_config(state.clone(), config.clone());
+        async move { app.oneshot(req).await.unwrap() }
+    };
+    let null = serde_json::json!(null);
+    send(
+        "POST",
+        "/namespaces",
+        "root-secret",
+        serde_json::json!({ "name": "payments" }),
+    )
+    .await;
+    let mut ids = Vec::new();
+    for name in ["cards_eu", "cards_us", "emails"] {
+        let payload = serde_json::json!({ "name": name, "item_count": 100, "hash_count": 3 });
+        let response = send(
+            "POST",
+            "/namespaces/payments/filters",
+            "root-secret",
+            payload,
+        )
+        .await;
+        ids.push(
+            response_json(response).await["id"]
+                .as_str()
+                .unwrap()
+                .to_string(),
+        );
+    }
+
+    // 1. MINT a key for inserting into the cards filters of the payments namespace
+    let payload = serde_json::json!({ "namespaces": ["payments"], "filters": ["cards_*"] });
+    let response = send("POST", "/admin/keys", "root-secret", payload).await;
+    let json = response_json(response).await;
+    assert_eq!(json["filters"], serde_json::json!(["cards_*"]));
+    let key = json["api_key"].as_str().unwrap().to_string();
+
+    // 2. Its filters, by name or id, are allowed
+    let response = send(
+        "POST",
+        "/namespaces/payments/filters/cards_eu/items",
+        &key,
+        null.clone(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let uri = format!("/namespaces/payments/filters/{}", ids[1]);
+    assert_eq!(
+        send("GET", &uri, &key, null.clone()).await.status(),
+        StatusCode::OK
+    );
+
+    // 3. Other filters, other namespaces, and requests on no given filter are not
+    for uri in [
+        "/namespaces/payments/filters/emails/items".to_string(),
+        format!("/namespaces/payments/filters/{}/items", ids[2]),
+        "/filters/cards_eu/items".to_string(),
+        "/namespaces/payments/filters".to_string(),
+        "/namespaces/payments/filters/cards_eu/compare/emails".to_string(),
+    ] {
+        let response = send("GET", &uri, &key, null.clone()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+    let response = send(
+        "POST",
+        "/namespaces/payments/filters/cards_eu/merge",
+        &key,
+        null.clone(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = send("GET", "/filters/cards_eu", &key, null.clone()).await;
+    assert_eq!(
+        response_json(response).await["error"],
+        "The credentials do not allow requests in the default namespace"
+    );
+
+    // 4. Nor are other filters named in the body or query: as the shadow of one of its filters,
+    // which its inserts and clears would change, or as filters checked before an insert
+    let secret = serde_json::json!("topsecret");
+    send(
+        "POST",
+        "/namespaces/payments/filters/emails/items",
+        "root-secret",
+        secret.clone(),
+    )
+    .await;
+    for (filter, status) in [
+        (ids[2].as_str(), StatusCode::FORBIDDEN),
+        ("cards_us", StatusCode::OK),
+    ] {
+        let response = send(
+            "PUT",
+            "/namespaces/payments/filters/cards_eu/shadow",
+            &key,
+            serde_json::json!({ "filter": filter }),
+        )
+        .await;
+        assert_eq!(response.status(), status, "{filter}");
+    }
+    let response = send(
+        "PUT",
+        "/namespaces/payments/filters/cards_eu/clear",
+        &key,
+        null.clone(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let payments = state.namespace("payments").unwrap();
+    assert!(payments.contains("emails", "\"topsecret\"").unwrap());
+    for check in ["emails", "cards_us,emails", ids[2].as_str()] {
+        let uri = format!("/namespaces/payments/filters/cards_eu/items/if-absent?check={check}");
+        let response = send("POST", &uri, &key, secret.clone()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{check}");
+    }
+    let uri = "/namespaces/payments/filters/cards_eu/items/if-absent?check=cards_us";
+    let response = send("POST", uri, &key, secret).await;
+    assert_eq!(response_json(response).await["inserted"], true);
+}
+
+#[tokio::test]
+async fn test_audit_log() {
+    let state = SharedState::default();
+    let path = std::env::temp_dir().join(format!("bloomsrv-audit-{}.log", std::process::id()));
+    let config = AppConfig {
+        admin_key: Some("root-secret".to_string()),
+        audit: Some(Arc::new(AuditLog::open(&path).unwrap())),
+        ..Default::default()
+    };
+    let send = |method: &str, uri: &str, key: &str, body: serde_json::Value| {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .header("authorization", format!("Bearer {key}"))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let app = create_app_with_config(state.clone(), config.clone());
+        async move { app.oneshot(req).await.unwrap() }
+    };
+    let null = serde_json::json!(null);
+    let payload = serde_json::json!({ "scope": "read" });
+    let response = send("POST", "/admin/keys", "root-secret", payload).await;
+    let json = response_json(response).await;
+    let (id, reader) = (
+        json["id"].as_str().unwrap(),
+        json["api_key"].as_str().unwrap(),
+    );
+
+    // 1. Changes are recorded, made or refused, with who requested them; lookups are not
+    let payload = serde_json::json!({ "name": "prod", "item_count": 100, "hash_count": 3 });
+    send("POST", "/filters", "root-secret", payload).await;
+    send("POST", "/filters/prod/items", "root-secret", null.clone()).await;
+    send("GET", "/filters/prod/items", "root-secret", null.clone()).await;
+    let response = send("PUT", "/filters/prod/clear", reader, null.clone()).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    send("PUT", "/filters/prod/clear", "root-secret", null.clone()).await;
+
+    // 2. LIST the changes of a filter, oldest first
+    let response = send(
+        "GET",
+        "/admin/audit?filter=prod",
+        "root-secret",
+        null.clone(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response_json(response).await;
+    let entries: Vec<_> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| {
+            (
+                e["method"].as_str().unwrap(),
+                e["path"].as_str().unwrap(),
+                e["status"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("POST", "/filters/prod/items", 200),
+            ("PUT", "/filters/prod/clear", 403),
+            ("PUT", "/filters/prod/clear", 200),
+        ]
+    );
+    assert_eq!(json[1]["caller"], format!("key:{id}"));
+    assert_eq!(json[2]["caller"], "admin");
+    assert!(!json[2]["request_id"].as_str().unwrap().is_empty());
+
+    // 3. Every change is also appended to the file
+    let uri = "/admin/audit?caller=admin&limit=1";
+    let json = response_json(send("GET", uri, "root-secret", null.clone()).await).await;
+    assert_eq!(json[0]["path"], "/filters/prod/clear");
+    let lines = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(lines.lines().count(), 5);
+    let last: serde_json::Value = serde_json::from_str(lines.lines().last().unwrap()).unwrap();
+    assert_eq!(last, json[0]);
+
+    // 4. Changes are recorded under every filter they are written to: shadows and group members
+    let payload = serde_json::json!({ "name": "staging", "item_count": 100, "hash_count": 3 });
+    send("POST", "/filters", "root-secret", payload).await;
+    let payload = serde_json::json!({ "filter": "staging" });
+    send("PUT", "/filters/prod/shadow", "root-secret", payload).await;
+    send("PUT", "/filters/prod/clear", "root-secret", null.clone()).await;
+    let payload = serde_json::json!({ "name": "all", "filters": ["prod"] });
+    send("POST", "/groups", "root-secret", payload).await;
+    send("POST", "/groups/all/items", "root-secret", null.clone()).await;
+    let uri = "/admin/audit?filter=staging";
+    let json = response_json(send("GET", uri, "root-secret", null.clone()).await).await;
+    let entries: Vec<_> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["path"].as_str().unwrap(), e["filters"].clone()))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            (
+                "/filters/prod/clear",
+                serde_json::json!(["prod", "staging"])
+            ),
+            ("/groups/all/items", serde_json::json!(["prod", "staging"])),
+        ]
+    );
+
+    // 5. Every batch inserted over a WebSocket is recorded, made or refused
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+    let app = create_app_with_config(state.clone(), config.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    for key in ["root-secret", reader] {
+        let mut request = format!("ws://{addr}/filters/prod/ws")
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert("x-api-key", key.parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        let request = serde_json::json!({ "id": 1, "op": "insert", "items": ["a", "b"] });
+        socket
+            .send(Message::Text(request.to_string()))
+            .await
+            .unwrap();
+        socket.next().await.unwrap().unwrap();
+    }
+    let uri = "/admin/audit?filter=staging&limit=2";
+    let json = response_json(send("GET", uri, "root-secret", null).await).await;
+    let entries: Vec<_> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| {
+            (
+                e["method"].as_str().unwrap(),
+                e["path"].as_str().unwrap(),
+                e["caller"].clone(),
+                e["status"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("WS", "/filters/prod/ws", serde_json::json!("admin"), 200),
+            (
+                "WS",
+                "/filters/prod/ws",
+                serde_json::json!(format!("key:{id}")),
+                403
+            ),
+        ]
+    );
+    assert_eq!(json[0]["filters"], serde_json::json!(["prod", "staging"]));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_clients_are_throttled() {
+    let state = SharedState::default();
+    let config = AppConfig {
+        admin_key: Some("root-secret".to_string()),
+        throttle: Some(Arc::new(Throttle::new(0.5, 2))),
+        ..Default::default()
+    };
+    let send = |uri: &str, key: &str| {
+        let req = Request::builder()
+            .method("GET")
+            .uri(uri)
+            .header("x-api-key", key)
+            .body(Body::empty())
+            .unwrap();
+        let app = create_app_with_config(state.clone(), config.clone());
+        async move { app.oneshot(req).await.unwrap() }
+    };
+
+    // 1. A burst of requests is allowed, and those beyond it are rejected until the bucket refills
+    for _ in 0..2 {
+        assert_eq!(
+            send("/filters", "root-secret").await.status(),
+            StatusCode::OK
+        );
+    }
+    let response = send("/filters", "root-secret").await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "2");
+    assert_eq!(response_json(response).await["code"], "rate_limited");
+
+    // 2. Other clients, and the probes, are not affected
+    let (_, secret) = state.create_api_key(KeySpec::default()).unwrap();
+    assert_eq!(send("/filters", &secret).await.status(), StatusCode::OK);
+    assert_eq!(send("/livez", "root-secret").await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_jwt_bearer_tokens() {
+    // Tokens of https://id.example.com for the audience bloomsrv, signed by the key of the JWKS
+    let tokens: serde_json::Value =
+        serde_json::from_str(include_str!("fixtures/tokens.json")).unwrap();
+    let mut validator = JwtValidator::new("https://id.example.com", "bloomsrv");
+    validator
+        .roles
+        .insert("ingest".to_string(), KeyScope::ReadWrite);
+    let keys = validator
+        .set_keys(include_bytes!("fixtures/jwks.json"))
+        .unwrap();
+    assert_eq!(keys, 1);
+    let config = AppConfig {
+        jwt: Some(Arc::new(validator)),
+        ..Default::default()
+    };
+    let state = SharedState::default();
+    let send = |method: &str, token: &str| {
+        let req = Request::builder()
+            .method(method)
+            .uri("/filters")
+            .header("authorization", format!("Bearer {token}"))
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "name": "f", "item_count": 100, "hash_count": 3 }).to_string(),
+            ))
+            .unwrap();
+        let app = create_app_with_config(state.clone(), config.clone());
+        async move { app.oneshot(req).await.unwrap() }
+    };
+    let token = |name: &str| tokens[name].as_str().unwrap().to_string();
+
+    // 1. The roles of a valid token give its scope
+    assert_eq!(send("GET", &token("read")).await.status(), StatusCode::OK);
+    let response = send("POST", &token("read")).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = send("POST", &token("ingest")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // 2. Expired, mis-audienced and tampered tokens are rejected
+    let response = send("GET", &token("expired")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response_json(response).await["error"],
+        "Invalid token: expired"
+    );
+    let response = send("GET", &token("audience")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response_json(response).await["error"],
+        "Invalid token: meant for another audience"
+    );
+    let read = token("read");
+    let mut parts: Vec<&str> = read.split('.').collect();
+    let ingest = token("ingest");
+    parts[1] = ingest.split('.').nth(1).unwrap();
+    let response = send("POST", &parts.join(".")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response_json(response).await["error"],
+        "Invalid token: bad signature"
+    );
+
+    // 3. The latest expiry does not overflow, and keys too short to be secure are refused
+    let response = send("GET", &token("never_expires")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let validator = JwtValidator::new("https://id.example.com", "bloomsrv");
+    // Moduli of 1, 50 and 255 bytes, in base64url
+    let moduli = [
+        "AQ".to_string(),
+        format!("{}xcU", "xcXF".repeat(16)),
+        "xcXF".repeat(85),
+    ];
+    for n in moduli {
+        let jwks = serde_json::json!({ "keys": [{ "kty": "RSA", "n": n, "e": "AQAB" }] });
+        let error = validator.set_keys(jwks.to_string().as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("Invalid JWKS"), "{error}");
+    }
+}
+
+#[tokio::test]
+async fn test_binary_items() {
+    let state = SharedState::default();
+
+    let payload =
+        serde_json::json!({ "name": "blobs", "item_count": 1000, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    // Items are raw bytes and need not be valid UTF-8
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/blobs/items")
+        .header("content-type", "application/octet-stream")
+        .body(Body::from(vec![0xff, 0x00, 0xfe]))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for (item, expected) in [(vec![0xff, 0x00, 0xfe], true), (vec![0xff, 0x00], false)] {
+        let req = Request::builder()
+            .method("GET")
+            .uri("/filters/blobs/items")
+            .body(Body::from(item))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["contains"], expected);
+    }
+
+    // In JSON payloads and query parameters, binary items can be base64-encoded
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/blobs/items/batch?encoding=base64")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"["AQI=", "/wD+"]"#))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["new"], serde_json::json!([true, false]));
+    assert!(state.contains("blobs", [0x01, 0x02]).unwrap());
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/blobs/items?item=AQI%3D&encoding=base64")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], true);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/blobs/items/query?encoding=base64")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"["AQI=", "not base64!"]"#))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response_json(response).await["error"],
+        "Item 2 is not valid base64"
+    );
+}
+
+#[tokio::test]
+async fn test_dev_synthetic_filter() {
+    let state = SharedState::default();
+    let payload = serde_json::json!({
+        "name": "load_test",
+        "item_count": 10000,
+        "false_positive_rate": 0.01,
+        "items": 5000,
+        "seed": 42
+    });
+    let request = || {
+        Request::builder()
+            .method("POST")
+            .uri("/dev/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    };
+
+    // 1. The development endpoints are disabled by default
+    let response = create_app(state.clone()).oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // 2. CREATE a pre-populated filter
+    let config = AppConfig {
+        dev_endpoints: true,
+        ..Default::default()
+    };
+    let response = create_app_with_config(state.clone(), config)
+        .oneshot(request())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let json = response_json(response).await;
+    assert_eq!(json["items"], 5000);
+    let sample = json["sample"].as_array().unwrap().clone();
+    assert_eq!(sample.len(), 10);
+
+    // 3. The sampled items are in the filter, and the same seed yields the same items
+    for item in &sample {
+        let req = Request::builder()
+            .method("GET")
+            .uri("/filters/load_test/items")
+            .body(Body::from(item.as_str().unwrap().to_string()))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["contains"], true);
+    }
+
+    let other = SharedState::default();
+    let config = AppConfig {
+        dev_endpoints: true,
+        ..Default::default()
+    };
+    let response = create_app_with_config(other, config)
+        .oneshot(request())
+        .await
+        .unwrap();
+    assert_eq!(
+        response_json(response).await["sample"],
+        serde_json::json!(sample)
+    );
+}
+
+#[tokio::test]
+async fn test_rate_limiter() {
+    let state = SharedState::default();
+
+    let payload = serde_json::json!({ "name": "api_calls", "limit": 3, "window_seconds": 60 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/limiters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // 1. CHECK a key up to its limit, then deny
+    let check = |key: &'static str, uri: &'static str| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::from(key))
+            .unwrap()
+    };
+    let checks = [
+        ("/limiters/api_calls/check?cost=2", true, 2),
+        ("/limiters/api_calls/check", true, 3),
+        ("/limiters/api_calls/check", false, 3),
+    ];
+    for (uri, allowed, count) in checks {
+        let json = response_json(
+            create_app(state.clone())
+                .oneshot(check("alice", uri))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(json["allowed"], allowed);
+        assert_eq!(json["count"], count);
+        assert_eq!(json["remaining"], 3 - count);
+    }
+
+    // 2. Other keys are counted separately
+    let req = check("bob", "/limiters/api_calls/check");
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["allowed"], true);
+    assert_eq!(json["count"], 1);
+
+    // 3. LIST and DELETE
+    let req = Request::builder()
+        .method("GET")
+        .uri("/limiters")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json[0]["name"], "api_calls");
+    assert_eq!(json[0]["window_seconds"], 60);
+
+    let req = Request::builder()
+        .method("DELETE")
+        .uri("/limiters/api_calls")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = create_app(state.clone())
+        .oneshot(check("alice", "/limiters/api_calls/check"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response_json(response).await["code"], "limiter_not_found");
+}
+
+#[tokio::test]
+async fn test_recent_seen() {
+    let state = SharedState::default();
+
+    let payload = serde_json::json!({ "name": "cards", "window_seconds": 600, "item_count": 1000 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/recent")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // 1. The first sighting records the item, the second one reports it
+    for expected in [false, true] {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/recent/cards/seen")
+            .body(Body::from("4111-1111"))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["seen"], expected);
+    }
+
+    // 2. Lookups do not record items
+    for _ in 0..2 {
+        let req = Request::builder()
+            .method("GET")
+            .uri("/recent/cards/seen")
+            .body(Body::from("5500-0000"))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["seen"], false);
+    }
+
+    let req = Request::builder()
+        .method("DELETE")
+        .uri("/recent/cards")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/recent/cards/seen")
+        .body(Body::from("4111-1111"))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_sketch_frequencies() {
+    let state = SharedState::default();
+
+    let payload = serde_json::json!({ "name": "page_views", "width": 1024, "depth": 4 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/sketches")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // 1. Increments return the new estimate, by one unless a count is given
+    for (uri, expected) in [
+        ("/sketches/page_views/items", 1),
+        ("/sketches/page_views/items?count=5", 6),
+    ] {
+        let req = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::from("/home"))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["estimate"], expected);
+    }
+
+    // 2. Lookups estimate without counting
+    for (item, expected) in [("/home", 6), ("/home", 6), ("/about", 0)] {
+        let req = Request::builder()
+            .method("GET")
+            .uri("/sketches/page_views/items")
+            .body(Body::from(item))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["estimate"], expected);
+    }
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/sketches")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json[0]["total"], 6);
+
+    let req = Request::builder()
+        .method("DELETE")
+        .uri("/sketches/page_views")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/sketches/page_views/items")
+        .body(Body::from("/home"))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response_json(response).await["code"], "sketch_not_found");
+}
+
+#[tokio::test]
+async fn test_cardinality_estimates() {
+    let state = SharedState::default();
+
+    for name in ["visitors_eu", "visitors_us"] {
+        let payload = serde_json::json!({ "name": name, "precision": 12 });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/cardinality")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        let response = create_app(state.clone()).oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    // 1. Inserting an item again does not raise the estimate
+    for (item, expected) in [("user-1", true), ("user-1", false)] {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/cardinality/visitors_eu/items")
+            .body(Body::from(item))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["new"], expected);
+    }
+    for i in 0..1000 {
+        state
+            .insert_cardinality("visitors_us", format!("user-{i}"))
+            .unwrap();
+    }
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/cardinality/visitors_eu/estimate")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["estimate"], 1);
+
+    // 2. Merging counts the items of both only once
+    let payload = serde_json::json!({ "sources": ["visitors_us"] });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/cardinality/visitors_eu/merge")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    let estimate = json["estimate"].as_u64().unwrap();
+    assert!((950..=1050).contains(&estimate), "estimate {estimate}");
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/cardinality")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json.as_array().unwrap().len(), 2);
+
+    // 3. Merging an unknown source is rejected
+    let payload = serde_json::json!({ "sources": ["visitors_asia"] });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/cardinality/visitors_eu/merge")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        response_json(response).await["code"],
+        "cardinality_not_found"
+    );
+
+    let req = Request::builder()
+        .method("DELETE")
+        .uri("/cardinality/visitors_eu")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+// --- Helper to build a serialized filter containing the given items ---
+fn snapshot_bytes(item_count: usize, items: &[&str]) -> Vec<u8> {
+    let creation_mode = CreationMode::FalsePositiveRate(0.01);
+    let mut filter = BloomFilter::new(item_count, creation_mode).unwrap();
+    for item in items {
+        filter.insert(item.as_bytes());
+    }
+    FilterSnapshot {
+        item_count,
+        creation_mode,
+        filter,
+    }
+    .encode()
+}
+
+#[tokio::test]
+async fn test_filter_upload() {
+    let state = SharedState::default();
+    let upload = |uri: &str, body: Vec<u8>| {
+        Request::builder()
+            .method("PUT")
+            .uri(uri)
+            .header("content-type", "application/octet-stream")
+            .body(Body::from(body))
+            .unwrap()
+    };
+    let lookup = |item: &'static str| {
+        Request::builder()
+            .method("GET")
+            .uri("/filters/prebuilt/items")
+            .body(Body::from(item))
+            .unwrap()
+    };
+
+    // 1. Create a filter from prebuilt bits
+    let response = create_app(state.clone())
+        .oneshot(upload(
+            "/filters/prebuilt/bits",
+            snapshot_bytes(1000, &["alice"]),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response_json(response).await["hash_count"], 7);
+    let json = response_json(
+        create_app(state.clone())
+            .oneshot(lookup("alice"))
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(json["contains"], true);
+
+    // 2. Creating it again fails, merging adds the items
+    let response = create_app(state.clone())
+        .oneshot(upload(
+            "/filters/prebuilt/bits",
+            snapshot_bytes(1000, &["bob"]),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = create_app(state.clone())
+        .oneshot(upload(
+            "/filters/prebuilt/bits?mode=merge",
+            snapshot_bytes(1000, &["bob"]),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    for item in ["alice", "bob"] {
+        let json = response_json(
+            create_app(state.clone())
+                .oneshot(lookup(item))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(json["contains"], true);
+    }
+
+    // 3. Merging requires the same parameters, replacing does not
+    let response = create_app(state.clone())
+        .oneshot(upload(
+            "/filters/prebuilt/bits?mode=merge",
+            snapshot_bytes(10, &[]),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(response_json(response).await["code"], "parameter_conflict");
+    let response = create_app(state.clone())
+        .oneshot(upload(
+            "/filters/prebuilt/bits?mode=replace",
+            snapshot_bytes(10, &[]),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response_json(response).await["bit_count"], 128);
+    let json = response_json(
+        create_app(state.clone())
+            .oneshot(lookup("alice"))
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(json["contains"], false);
+
+    // 4. Corrupted bits are rejected
+    let mut bytes = snapshot_bytes(1000, &["alice"]);
+    bytes[100] ^= 0xff;
+    let response = create_app(state.clone())
+        .oneshot(upload("/filters/corrupted/bits", bytes))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_insert_if_absent() {
+    let state = SharedState::default();
+    for name in ["hourly", "daily"] {
+        let payload =
+            serde_json::json!({ "name": name, "item_count": 1000, "false_positive_rate": 0.01 });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+    let insert_if_absent = |uri: &str, item: &'static str| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::from(item))
+            .unwrap()
+    };
+
+    // 1. The item is absent from both filters, so it is inserted into the target
+    let uri = "/filters/hourly/items/if-absent?check=daily";
+    let response = create_app(state.clone())
+        .oneshot(insert_if_absent(uri, "evt-1"))
+        .await
+        .unwrap();
+    let json = response_json(response).await;
+    assert_eq!(json["inserted"], true);
+    assert_eq!(json["found_in"], serde_json::Value::Null);
+
+    // 2. The second time, the target filter has seen it
+    let response = create_app(state.clone())
+        .oneshot(insert_if_absent(uri, "evt-1"))
+        .await
+        .unwrap();
+    let json = response_json(response).await;
+    assert_eq!(json["inserted"], false);
+    assert_eq!(json["found_in"], "hourly");
+
+    // 3. An item seen by a checked filter is not inserted
+    let req = insert_if_absent("/filters/daily/items", "evt-2");
+    create_app(state.clone()).oneshot(req).await.unwrap();
+    let response = create_app(state.clone())
+        .oneshot(insert_if_absent(uri, "evt-2"))
+        .await
+        .unwrap();
+    assert_eq!(response_json(response).await["found_in"], "daily");
+
+    // 4. Unknown checked filters are reported before anything is inserted
+    let uri = "/filters/hourly/items/if-absent?check=daily,weekly";
+    let response = create_app(state.clone())
+        .oneshot(insert_if_absent(uri, "evt-3"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/hourly/items")
+        .body(Body::from("evt-3"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], false);
+}
+
+#[tokio::test]
+async fn test_lookup_by_query_and_path() {
+    let state = SharedState::default();
+    let payload =
+        serde_json::json!({ "name": "emails", "item_count": 1000, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+    state.insert("emails", "user@example.com").unwrap();
+    state.insert("emails", "a b/c").unwrap();
+
+    // Items are percent-decoded, and the body is not needed
+    for (uri, expected) in [
+        ("/filters/emails/items?item=user%40example.com", true),
+        (
+            "/filters/emails/items?item=other%40example.com&debug=0",
+            false,
+        ),
+        ("/filters/emails/items/user@example.com", true),
+        ("/filters/emails/items/a%20b%2Fc", true),
+        ("/filters/emails/items/other@example.com", false),
+    ] {
+        let req = Request::builder()
+            .method("GET")
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["contains"], expected, "{uri}");
+    }
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/unknown/items/user@example.com")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Items named like the other endpoints under `items/` are looked up by query parameter
+    state.insert("emails", "batch").unwrap();
+    let lookup = |uri: &str| {
+        let req = Request::builder()
+            .method("GET")
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        create_app(state.clone()).oneshot(req)
+    };
+    let response = lookup("/filters/emails/items/batch").await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    let response = lookup("/filters/emails/items?item=batch").await.unwrap();
+    assert_eq!(response_json(response).await["contains"], true);
+}
+
+#[tokio::test]
+async fn test_check_and_insert() {
+    let state = SharedState::default();
+    let payload =
+        serde_json::json!({ "name": "events", "item_count": 1000, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    for expected in [false, true] {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters/events/items/check-insert")
+            .body(Body::from("evt-1"))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["was_present"], expected);
+    }
+    assert!(state.contains("events", "evt-1").unwrap());
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/unknown/items/check-insert")
+        .body(Body::from("evt-1"))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_batch_insert_and_lookup() {
+    let state = SharedState::default();
+    let payload =
+        serde_json::json!({ "name": "events", "item_count": 1000, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+    let batch = |uri: &str, items: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(items.to_string()))
+            .unwrap()
+    };
+
+    // 1. Every item is inserted, and duplicates within the batch are reported as such
+    let req = batch(
+        "/filters/events/items/batch",
+        serde_json::json!(["evt-1", "evt-2", "evt-1"]),
+    );
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["inserted"], 3);
+    assert_eq!(json["new"], serde_json::json!([true, true, false]));
+    assert!(state.contains("events", "evt-2").unwrap());
+
+    // 2. Batch lookups answer for each item, in order
+    let items = serde_json::json!(["evt-2", "evt-3", "evt-1"]);
+    let req = batch("/filters/events/items/query", items);
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], serde_json::json!([true, false, true]));
+
+    // 3. Streams insert one item per line, raw or as JSON strings
+    let raw = "evt-3\r\nevt-1\n\nevt-4";
+    let ndjson = "\"evt-5\"\n\"evt-3\"\n";
+    for (uri, body, items, new) in [
+        ("/filters/events/items/stream", raw, 3, 2),
+        ("/filters/events/items/stream?format=ndjson", ndjson, 2, 1),
+    ] {
+        let req = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::from(body))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["stats"]["items"], items);
+        assert_eq!(json["stats"]["new"], new);
+    }
+    assert!(state.contains("events", "evt-4").unwrap());
+    assert!(state.contains("events", "evt-5").unwrap());
+
+    // 4. Unknown filters are reported
+    let req = batch("/filters/unknown/items/batch", serde_json::json!(["evt-1"]));
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_msgpack_batches() {
+    let state = SharedState::default();
+    let payload = serde_json::json!({ "name": "events", "item_count": 1000, "hash_count": 4 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+    let send = |uri: &str, content_type: &str, accept: &str, body: Vec<u8>| {
+        let req = Request::builder()
+            .method(if body.is_empty() { "GET" } else { "POST" })
+            .uri(uri)
+            .header("content-type", content_type)
+            .header("accept", accept)
+            .body(Body::from(body))
+            .unwrap();
+        create_app(state.clone()).oneshot(req)
+    };
+    let msgpack = |response: axum::response::Response| async move {
+        assert_eq!(response.headers()["content-type"], "application/msgpack");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        rmp_serde::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+    // An array of the string "evt-1" and the bytes 0xff 0x00
+    let items = vec![
+        0x92, 0xa5, b'e', b'v', b't', b'-', b'1', 0xc4, 0x02, 0xff, 0x00,
+    ];
+
+    // 1. Batches are read from and answered in MessagePack, items as strings or bytes
+    let uri = "/filters/events/items/batch";
+    let response = send(
+        uri,
+        "application/msgpack",
+        "application/msgpack",
+        items.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = msgpack(response).await;
+    assert_eq!(json["inserted"], 2);
+    assert_eq!(json["new"], serde_json::json!([true, true]));
+    assert!(state.contains("events", [0xff, 0x00]).unwrap());
+
+    let uri = "/filters/events/items/query";
+    let accept = "application/json;q=0.5, application/x-msgpack";
+    let response = send(uri, "application/msgpack", accept, items.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        msgpack(response).await["contains"],
+        serde_json::json!([true, true])
+    );
+
+    // 2. JSON is answered unless MessagePack is accepted
+    for accept in ["application/json", "application/msgpack;q=0"] {
+        let response = send(uri, "application/msgpack", accept, items.clone())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "application/json");
+        let json = response_json(response).await;
+        assert_eq!(json["contains"], serde_json::json!([true, true]));
+    }
+    let body = serde_json::json!(["evt-1", "evt-2"])
+        .to_string()
+        .into_bytes();
+    let response = send(uri, "application/json", "application/msgpack", body)
+        .await
+        .unwrap();
+    assert_eq!(
+        msgpack(response).await["contains"],
+        serde_json::json!([true, false])
+    );
+
+    // 3. Statistics are answered in MessagePack
+    let accept = "application/msgpack";
+    let response = send("/filters/events/stats", "", accept, Vec::new())
+        .await
+        .unwrap();
+    assert_eq!(msgpack(response).await["usage"]["inserts"], 2);
+    let response = send("/stats", "", accept, Vec::new()).await.unwrap();
+    assert_eq!(msgpack(response).await["evictions"], 0);
+
+    // 4. Invalid bodies are rejected
+    let response = send(
+        uri,
+        "application/msgpack",
+        "application/msgpack",
+        vec![0x92, 0xc1],
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response_json(response).await["code"], "invalid_parameters");
+}
+
+#[tokio::test]
+async fn test_filter_load() {
+    let state = SharedState::default();
+    state
+        .create(FilterSpec {
+            name: "keys".to_string(),
+            item_count: 1000,
+            false_positive_rate: Some(0.01),
+            ..Default::default()
+        })
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!("bloomsrv-load-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let lines: String = (0..500).map(|i| format!("key-{i}\n")).collect();
+    std::fs::write(dir.join("keys.txt"), lines).unwrap();
+    let config = AppConfig {
+        load_sources: vec![format!("file://{}/", dir.display())],
+        ..Default::default()
+    };
+    let send = |method: &str, uri: &str, body: serde_json::Value| {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        create_app_with_config(state.clone(), config.clone()).oneshot(req)
+    };
+
+    // 1. The source is loaded in a job, whose progress is polled
+    let source = format!("file://{}", dir.join("keys.txt").display());
+    let body = serde_json::json!({ "source": source });
+    let response = send("POST", "/filters/keys/load", body).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let job = response_json(response).await;
+    assert_eq!(job["filter"], "keys");
+    assert_eq!(job["source"], source.as_str());
+    assert_eq!(job["total_bytes"], 3890);
+    let uri = format!("/jobs/{}", job["id"].as_str().unwrap());
+    let job = loop {
+        let json = response_json(send("GET", &uri, serde_json::json!({})).await.unwrap()).await;
+        if json["status"] != "running" {
+            break json;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_eq!(job["status"], "completed");
+    assert_eq!(job["progress"]["items"], 500);
+    assert_eq!(job["progress"]["bytes"], 3890);
+    assert!(job["finished_at"].is_u64());
+    assert!(state.contains("keys", "key-0").unwrap());
+    assert!(state.contains("keys", "key-499").unwrap());
+
+    // 2. Sources outside of the allowed prefixes are forbidden, even through '..'
+    let outside = format!("file://{}/../keys.txt", dir.display());
+    for source in [
+        "file:///etc/passwd",
+        "https://example.com/keys.txt",
+        &outside,
+    ] {
+        let body = serde_json::json!({ "source": source });
+        let response = send("POST", "/filters/keys/load", body).await.unwrap();
+        assert!(response.status().is_client_error(), "{source}");
+    }
+
+    // 3. Missing sources, filters and jobs are reported
+    let body = serde_json::json!({ "source": format!("file://{}/missing.txt", dir.display()) });
+    let response = send("POST", "/filters/keys/load", body).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = serde_json::json!({ "source": source });
+    let response = send("POST", "/filters/unknown/load", body).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = send("GET", "/jobs/unknown", serde_json::json!({}))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response_json(response).await["code"], "job_not_found");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_filter_rebuild_from_journal() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-journal-{}", std::process::id()));
+    let mut store = FilterStore::new();
+    store.data_dir = Some(dir.clone());
+    let state = SharedState::new(store);
+    for (name, journal) in [("keys", true), ("cards", false)] {
+        state
+            .create(FilterSpec {
+                name: name.to_string(),
+                item_count: 100,
+                false_positive_rate: Some(0.01),
+                journal,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    for i in 0..500 {
+        state.insert("keys", format!("key-{i}")).unwrap();
+    }
+    let send = |method: &str, uri: &str, body: serde_json::Value| {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req)
+    };
+
+    // 1. The filter is rebuilt from its journal in a job, whose progress is polled
+    let body = serde_json::json!({ "item_count": 10000 });
+    let response = send("POST", "/filters/keys/rebuild/journal", body)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let job = response_json(response).await;
+    assert_eq!(job["filter"], "keys");
+    assert_eq!(job["source"], "journal");
+    let uri = format!("/jobs/{}", job["id"].as_str().unwrap());
+    let job = loop {
+        let json = response_json(send("GET", &uri, serde_json::json!({})).await.unwrap()).await;
+        if json["status"] != "running" {
+            break json;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_eq!(job["status"], "completed");
+    assert_eq!(job["progress"]["items"], 500);
+    assert_eq!(job["progress"]["bytes"], job["total_bytes"]);
+    assert_eq!(state.describe("keys").unwrap().0.capacity, 10000);
+    assert!(state.contains("keys", "key-0").unwrap());
+    assert!(state.contains("keys", "key-499").unwrap());
+
+    // 2. Filters keeping no journal, and invalid parameters, are refused up front
+    let response = send(
+        "POST",
+        "/filters/cards/rebuild/journal",
+        serde_json::json!({}),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = serde_json::json!({ "false_positive_rate": 0.01, "hash_count": 3 });
+    let response = send("POST", "/filters/keys/rebuild/journal", body)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = send(
+        "POST",
+        "/filters/unknown/rebuild/journal",
+        serde_json::json!({}),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_filter_merge() {
+    let state = SharedState::default();
+    let mut ids = Vec::new();
+    for (name, item_count) in [
+        ("all", 1000),
+        ("shard_1", 1000),
+        ("shard_2", 1000),
+        ("small", 10),
+    ] {
+        let payload = serde_json::json!({ "name": name, "item_count": item_count, "false_positive_rate": 0.01 });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        ids.push(json["id"].as_str().unwrap().to_string());
+    }
+    state.insert("shard_1", "evt-1").unwrap();
+    state.insert("shard_2", "evt-2").unwrap();
+    let merge = |sources: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri("/filters/all/merge")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "sources": sources }).to_string(),
+            ))
+            .unwrap()
+    };
+
+    // 1. Sources are given by name or id, and left unchanged
+    let req = merge(serde_json::json!(["shard_1", ids[2]]));
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(state.contains("all", "evt-1").unwrap());
+    assert!(state.contains("all", "evt-2").unwrap());
+    assert!(!state.contains("shard_1", "evt-2").unwrap());
+
+    // 2. Filters of other parameters are rejected before anything is merged
+    state.insert("shard_1", "evt-3").unwrap();
+    let req = merge(serde_json::json!(["shard_1", "small"]));
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(response_json(response).await["code"], "parameter_conflict");
+    assert!(!state.contains("all", "evt-3").unwrap());
+
+    let req = merge(serde_json::json!(["shard_3"]));
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_filter_intersection() {
+    let state = SharedState::default();
+    for name in ["pipeline_a", "pipeline_b"] {
+        let payload =
+            serde_json::json!({ "name": name, "item_count": 1000, "false_positive_rate": 0.01 });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+    state.insert_many("pipeline_a", ["evt-1", "evt-2"]).unwrap();
+    state.insert_many("pipeline_b", ["evt-2", "evt-3"]).unwrap();
+    let intersect = |body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri("/filters/pipeline_a/intersect")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    // 1. Into a new filter, leaving both unchanged
+    let req = intersect(serde_json::json!({ "sources": ["pipeline_b"], "into": "both" }));
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(state.contains("both", "evt-2").unwrap());
+    assert!(!state.contains("both", "evt-1").unwrap());
+    assert!(!state.contains("both", "evt-3").unwrap());
+    assert!(state.contains("pipeline_a", "evt-1").unwrap());
+
+    // 2. In place
+    let req = intersect(serde_json::json!({ "sources": ["pipeline_b"] }));
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(state.contains("pipeline_a", "evt-2").unwrap());
+    assert!(!state.contains("pipeline_a", "evt-1").unwrap());
+
+    let req = intersect(serde_json::json!({ "sources": ["pipeline_b"], "into": "both" }));
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_filter_comparison() {
+    let state = SharedState::default();
+    for name in ["dataset_a", "dataset_b"] {
+        let payload =
+            serde_json::json!({ "name": name, "item_count": 10000, "false_positive_rate": 0.01 });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+    let rows = |range: std::ops::Range<u32>| range.map(|i| format!("row-{i}"));
+    state.insert_many("dataset_a", rows(0..1000)).unwrap();
+    state.insert_many("dataset_b", rows(500..2000)).unwrap();
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/dataset_a/compare/dataset_b")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    let intersection = json["estimated_intersection"].as_u64().unwrap();
+    assert!((450..=550).contains(&intersection), "{json}");
+    let jaccard = json["jaccard"].as_f64().unwrap();
+    assert!((jaccard - 0.25).abs() < 0.03, "{json}");
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/dataset_a/compare/dataset_c")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_filter_stats() {
+    let state = SharedState::default();
+    let payload =
+        serde_json::json!({ "name": "sessions", "item_count": 1000, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    let stats = |state: &SharedState| {
+        let req = Request::builder()
+            .method("GET")
+            .uri("/filters/sessions/stats")
+            .body(Body::empty())
+            .unwrap();
+        let app = create_app(state.clone());
+        async move { response_json(app.oneshot(req).await.unwrap()).await }
+    };
+    let json = stats(&state).await;
+    assert_eq!(json["set_bits"], 0);
+    assert_eq!(json["estimated_items"], 0);
+    assert_eq!(json["false_positive_rate"], 0.0);
+    assert_eq!(json["usage"]["inserts"], 0);
+    assert_eq!(json["usage"]["lookups"], 0);
+    assert_eq!(json["memory_usage_bytes"], 1200);
+    assert_eq!(json["mapped_bytes"], 0);
+
+    // Twice the expected number of items saturates the filter well beyond its configured rate
+    state
+        .insert_many("sessions", (0..2000).map(|i| format!("session-{i}")))
+        .unwrap();
+    let json = stats(&state).await;
+    let fill_ratio = json["fill_ratio"].as_f64().unwrap();
+    assert!((0.7..0.8).contains(&fill_ratio), "{json}");
+    let estimated = json["estimated_items"].as_u64().unwrap();
+    assert!((1900..=2100).contains(&estimated), "{json}");
+    let rate = json["false_positive_rate"].as_f64().unwrap();
+    assert!((0.08..0.2).contains(&rate), "{json}");
+    assert_eq!(json["usage"]["inserts"], 2000);
+
+    // Lookups are counted by their answer
+    state.contains("sessions", "session-1").unwrap();
+    let json = stats(&state).await;
+    assert_eq!(json["usage"]["lookups"], 1);
+    assert_eq!(json["usage"]["positive_lookups"], 1);
+    assert_eq!(json["usage"]["negative_lookups"], 0);
+    assert!(json["usage"]["last_accessed_at"].as_u64().unwrap() > 0);
+
+    // The memory of every filter is reported to administrators
+    let req = Request::builder()
+        .uri("/admin/memory")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["filters"][0]["name"], "sessions");
+    assert_eq!(json["filters"][0]["memory_usage_bytes"], 1200);
+    assert_eq!(json["filter_bytes"], 1200);
+}
+
+#[tokio::test]
+async fn test_filter_on_full() {
+    let source = SharedState::default();
+    let target = SharedState::default();
+    let send = |state: &SharedState, method: &str, uri: &str, body: String| {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        create_app(state.clone()).oneshot(req)
+    };
+    let payload = serde_json::json!({
+        "name": "sessions",
+        "item_count": 100,
+        "false_positive_rate": 0.01,
+        "on_full": "rotate",
+    });
+    let response = send(&source, "POST", "/filters", payload.to_string())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let items: Vec<String> = (0..500).map(|i| format!("session-{i}")).collect();
+    let body = serde_json::to_string(&items).unwrap();
+    send(&source, "POST", "/filters/sessions/items/batch", body)
+        .await
+        .unwrap();
+
+    // 1. The filter rotated instead of filling up
+    let response = send(&source, "GET", "/filters/sessions", String::new())
+        .await
+        .unwrap();
+    let json = response_json(response).await;
+    assert_eq!(json["kind"], "standard");
+    assert_eq!(json["on_full"], "rotate");
+    assert!(json["rotations"].as_u64().unwrap() >= 4, "{json}");
+    assert!(json["stats"]["fill_ratio"].as_f64().unwrap() <= 0.5);
+
+    // 2. Dumps carry the previous generation
+    let response = send(&source, "GET", "/filters/sessions/dump", String::new())
+        .await
+        .unwrap();
+    let dump = response_json(response).await;
+    assert!(dump["previous"].is_string());
+    let response = send(&target, "POST", "/filters/import", dump.to_string())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(
+        target.snapshot("sessions").unwrap(),
+        source.snapshot("sessions").unwrap()
+    );
+
+    // 3. Only standard filters act once full
+    let payload = serde_json::json!({
+        "name": "counted",
+        "item_count": 100,
+        "false_positive_rate": 0.01,
+        "kind": "counting",
+        "on_full": "scale",
+    });
+    let response = send(&source, "POST", "/filters", payload.to_string())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response_json(response).await["error"],
+        "Only standard filters take an on_full action"
+    );
+}
+
+#[tokio::test]
+async fn test_filter_if_match() {
+    let state = SharedState::default();
+    let send = |method: &str, uri: &str, if_match: Option<&str>, body: String| {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(if_match) = if_match {
+            req = req.header("if-match", if_match);
+        }
+        create_app(state.clone()).oneshot(req.body(Body::from(body)).unwrap())
+    };
+    let etag = |response: &axum::response::Response| {
+        response.headers()["etag"].to_str().unwrap().to_string()
+    };
+    let payload = serde_json::json!({
+        "name": "login_attempts",
+        "item_count": 1000,
+        "false_positive_rate": 0.01,
+    });
+    send("POST", "/filters", None, payload.to_string())
+        .await
+        .unwrap();
+
+    // 1. Reads tag the generation, which inserts do not change
+    let response = send("GET", "/filters/login_attempts", None, String::new())
+        .await
+        .unwrap();
+    let read = etag(&response);
+    let json = response_json(response).await;
+    assert_eq!(read, format!("\"{}-1\"", json["id"].as_str().unwrap()));
+    assert_eq!(json["generation"], 1);
+    send("POST", "/filters/login_attempts/items", None, "user".into())
+        .await
+        .unwrap();
+    let response = send("GET", "/filters/login_attempts/stats", None, String::new())
+        .await
+        .unwrap();
+    assert_eq!(etag(&response), read);
+
+    // 2. A rebuild moves the filter to its next generation
+    let rebuild = serde_json::json!({ "items": ["user"] }).to_string();
+    let response = send(
+        "POST",
+        "/filters/login_attempts/rebuild",
+        Some(&read),
+        rebuild.clone(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // 3. Changes conditional on the previous generation fail, leaving the filter unchanged
+    for (method, uri, body) in [
+        ("PUT", "/filters/login_attempts/clear", String::new()),
+        ("POST", "/filters/login_attempts/rebuild", rebuild),
+        ("DELETE", "/filters/login_attempts", String::new()),
+    ] {
+        let response = send(method, uri, Some(&read), body).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED, "{uri}");
+        let json = response_json(response).await;
+        assert_eq!(json["code"], "precondition_failed");
+        assert_eq!(
+            json["error"],
+            "Filter 'login_attempts' has changed since it was read"
+        );
+    }
+    let response = send("GET", "/filters/login_attempts", None, String::new())
+        .await
+        .unwrap();
+    let current = etag(&response);
+    assert_ne!(current, read);
+    assert_eq!(response_json(response).await["generation"], 2);
+    let response = send("GET", "/filters/login_attempts/items", None, "user".into())
+        .await
+        .unwrap();
+    assert_eq!(response_json(response).await["contains"], true);
+
+    // 4. Any of the listed tags, or any tag at all, matches
+    let listed = format!("\"other\", {current}");
+    let response = send(
+        "PUT",
+        "/filters/login_attempts/clear",
+        Some(&listed),
+        String::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = send(
+        "PUT",
+        "/filters/login_attempts/clear",
+        Some("*"),
+        String::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = send("GET", "/filters/login_attempts", None, String::new())
+        .await
+        .unwrap();
+    let current = etag(&response);
+    let response = send(
+        "DELETE",
+        "/filters/login_attempts",
+        Some(&current),
+        String::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_filter_quotas() {
+    let state = SharedState::default();
+    let quotas = Quotas {
+        max_filters: Some(2),
+        max_item_count: Some(10_000),
+        max_filter_bytes: Some(4096),
+        max_total_bytes: None,
+    };
+    let tenant = Quotas {
+        max_filters: Some(1),
+        ..quotas.clone()
+    };
+    state.set_quotas(quotas, [("tenant".to_string(), tenant)].into());
+    let request = |method: &str, uri: &str, body: serde_json::Value| {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req)
+    };
+    let filter = |name: &str, item_count: usize| {
+        serde_json::json!({
+            "name": name,
+            "item_count": item_count,
+            "false_positive_rate": 0.01,
+        })
+    };
+
+    // 1. Filters sized for too many items, or too large, are rejected
+    for item_count in [100_000, 10_000] {
+        let response = request("POST", "/filters", filter("huge", item_count))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let json = response_json(response).await;
+        assert_eq!(json["code"], "filter_too_large");
+    }
+
+    // 2. So is a filter beyond the number of filters, until another is deleted
+    for name in ["sessions", "logins"] {
+        let response = request("POST", "/filters", filter(name, 100))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+    let response = request("POST", "/filters", filter("emails", 100))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    let json = response_json(response).await;
+    assert_eq!(json["code"], "quota_exceeded");
+    let response = request("DELETE", "/filters/logins", serde_json::Value::Null)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = request("POST", "/filters", filter("emails", 100))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // 3. Namespaces have quotas of their own
+    let response = request(
+        "POST",
+        "/namespaces",
+        serde_json::json!({ "name": "tenant" }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let uri = "/namespaces/tenant/filters";
+    let response = request("POST", uri, filter("sessions", 100)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = request("POST", uri, filter("logins", 100)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+
+    // 4. A scalable filter takes no more batches once it has outgrown the quotas
+    request("DELETE", "/filters/sessions", serde_json::Value::Null)
+        .await
+        .unwrap();
+    let mut scalable = filter("events", 100);
+    scalable["kind"] = "scalable".into();
+    let response = request("POST", "/filters", scalable).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let mut batches = 0;
+    let status = loop {
+        let items: Vec<String> = (0..100).map(|i| format!("evt-{batches}-{i}")).collect();
+        let uri = "/filters/events/items/batch";
+        let response = request("POST", uri, serde_json::json!(items))
+            .await
+            .unwrap();
+        batches += 1;
+        if response.status() != StatusCode::OK || batches == 100 {
+            break response.status();
+        }
+    };
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    let json = response_json(
+        request("GET", "/filters/events/stats", serde_json::Value::Null)
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert!(json["memory_usage_bytes"].as_u64().unwrap() >= 4096);
+
+    // 5. Nor single items, once an insert has grown it beyond them
+    request("DELETE", "/filters/emails", serde_json::Value::Null)
+        .await
+        .unwrap();
+    let mut scalable = filter("clicks", 100);
+    scalable["kind"] = "scalable".into();
+    let response = request("POST", "/filters", scalable).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let mut inserts = 0;
+    let status = loop {
+        let item = serde_json::json!(format!("click-{inserts}"));
+        let response = request("POST", "/filters/clicks/items", item)
+            .await
+            .unwrap();
+        inserts += 1;
+        if response.status() != StatusCode::OK || inserts == 10_000 {
+            break response.status();
+        }
+    };
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    let json = response_json(
+        request("GET", "/filters/clicks/stats", serde_json::Value::Null)
+            .await
+            .unwrap(),
+    )
+    .await;
+    let bytes = json["memory_usage_bytes"].as_u64().unwrap();
+    assert!(bytes >= 4096);
+    let response = request("POST", "/filters/clicks/items", serde_json::json!("late"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    let json = response_json(
+        request("GET", "/filters/clicks/stats", serde_json::Value::Null)
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(json["memory_usage_bytes"].as_u64().unwrap(), bytes);
+}
+
+#[tokio::test]
+async fn test_oversized_filters_without_quotas() {
+    let state = SharedState::default();
+    let create = |payload: serde_json::Value| {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        create_app(state.clone()).oneshot(req)
+    };
+
+    // Rejected before anything is allocated, rather than aborting the process
+    let response = create(serde_json::json!({
+        "name": "many_hashes",
+        "item_count": 100,
+        "hash_count": 4_000_000_000u32,
+    }))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response_json(response).await["code"], "invalid_parameters");
+
+    let response = create(serde_json::json!({
+        "name": "many_items",
+        "item_count": 1_000_000_000_000_000u64,
+        "false_positive_rate": 0.01,
+    }))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response_json(response).await["code"], "filter_too_large");
+    assert!(state.filters.read().is_empty());
+}
+
+#[tokio::test]
+async fn test_dashboard() {
+    let config = AppConfig {
+        admin_key: Some("root-secret".to_string()),
+        ..Default::default()
+    };
+    let app = create_app_with_config(SharedState::default(), config);
+
+    // The page is served without a key, with everything it needs embedded
+    let req = Request::builder().uri("/ui").body(Body::empty()).unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let page = String::from_utf8(body.to_vec()).unwrap();
+    assert!(page.contains("v1/filters"));
+    assert!(page.contains("/stats"));
+    assert!(!page.contains("https://"));
+}
+
+#[tokio::test]
+async fn test_get_filter_by_name_or_id() {
+    let state = SharedState::default();
+    let payload = serde_json::json!({ "name": "accounts", "item_count": 1000, "hash_count": 4 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let created = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    state.insert("accounts", "alice").unwrap();
+
+    let id = created["id"].as_str().unwrap();
+    for key in ["accounts", id] {
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/filters/{key}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = create_app(state.clone()).oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let json = response_json(response).await;
+        assert_eq!(json["id"], id);
+        assert_eq!(json["name"], "accounts");
+        assert_eq!(json["item_count"], 1000);
+        assert_eq!(json["config"], "Hash count: 4");
+        assert!(json["created_at"].as_u64().unwrap() > 0);
+        assert_eq!(json["stats"]["set_bits"], 4);
+        assert_eq!(json["stats"]["estimated_items"], 1);
+    }
+
+    let req = Request::builder()
+        .method("GET")
+        .uri("/filters/unknown")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_routes_accept_filter_ids() {
+    let state = SharedState::default();
+    let payload =
+        serde_json::json!({ "name": "devices", "item_count": 1000, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let created = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    let id = created["id"].as_str().unwrap();
+
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/filters/{id}/items"))
+        .body(Body::from("phone"))
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(
+        json["response"],
+        "Item 'phone' inserted into filter 'devices'"
+    );
+    assert!(state.contains("devices", "phone").unwrap());
+
+    let req = Request::builder()
+        .method("GET")
+        .uri(format!("/filters/{id}/items?item=phone"))
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["contains"], true);
+
+    let req = Request::builder()
+        .method("PUT")
+        .uri(format!("/filters/{id}/clear"))
+        .body(Body::empty())
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!state.contains("devices", "phone").unwrap());
+}
+
+#[tokio::test]
+async fn test_filter_rebuild() {
+    let state = SharedState::default();
+    let payload =
+        serde_json::json!({ "name": "emails", "item_count": 100, "false_positive_rate": 0.01 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let created = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    state.insert("emails", "stale@example.com").unwrap();
+
+    let payload = serde_json::json!({ "item_count": 10000, "items": ["alice@example.com"] });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/emails/rebuild")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response_json(response).await;
+    assert_eq!(json["id"], created["id"]);
+    assert!(json["bit_count"].as_u64().unwrap() > created["bit_count"].as_u64().unwrap() * 50);
+    assert_eq!(json["hash_count"], created["hash_count"]);
+    assert!(state.contains("emails", "alice@example.com").unwrap());
+    assert!(!state.contains("emails", "stale@example.com").unwrap());
+    assert_eq!(state.list()[0].capacity, 10000);
+
+    let payload = serde_json::json!({ "false_positive_rate": 0.01, "hash_count": 3 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/emails/rebuild")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_filter_diff() {
+    let state = SharedState::default();
+    let backup = snapshot_bytes(1000, &["alice"]);
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/filters/audited/bits")
+        .body(Body::from(backup.clone()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    for i in 0..100 {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/filters/audited/items")
+            .body(Body::from(format!("item-{i}")))
+            .unwrap();
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+
+    // Compare the backup with the live filter
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/audited/bits/diff")
+        .body(Body::from(backup))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response_json(response).await;
+    assert_eq!(json["bits_set_before"], 7);
+    assert_eq!(json["bits_removed"], 0);
+    let added = json["estimated_items_added"].as_u64().unwrap();
+    assert!((90..=110).contains(&added), "estimated {added} items added");
+
+    // Filters of other sizes cannot be compared
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/audited/bits/diff")
+        .body(Body::from(snapshot_bytes(10, &[])))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+// A Guava `BloomFilter.create(Funnels.stringFunnel(UTF_8), 100, 0.01)` containing "alice" and "bob"
+const GUAVA_FILTER: &[&str] = &[
+    "01070000000f20000000002000000000000000000000100000000000000000000000000000000000",
+    "00002200000000402000000000200000000000000000000000000008000000010000000000000020",
+    "00000000200000000000000020000000040000000000000000000000000000000000000000000000",
+    "000000000000",
+];
+
+fn guava_filter_bytes() -> Vec<u8> {
+    let hex = GUAVA_FILTER.concat();
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_guava_interop() {
+    let state = SharedState::default();
+    let lookup = |filter: &str, item: &'static str| {
+        Request::builder()
+            .method("GET")
+            .uri(format!("/filters/{filter}/items"))
+            .body(Body::from(item))
+            .unwrap()
+    };
+    let download = |uri: &str| {
+        Request::builder()
+            .method("GET")
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // 1. Import a filter serialized by Guava, and answer lookups as Guava would
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/filters/jvm/bits?format=guava")
+        .body(Body::from(guava_filter_bytes()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response_json(response).await;
+    assert_eq!(
+        (json["bit_count"].as_u64(), json["hash_count"].as_u64()),
+        (Some(960), Some(7))
+    );
+    for (item, expected) in [("alice", true), ("bob", true), ("carol", false)] {
+        let json = response_json(
+            create_app(state.clone())
+                .oneshot(lookup("jvm", item))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(json["contains"], expected, "{item}");
+    }
+
+    // 2. Export it back to Guava, after inserting another item
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/jvm/items")
+        .body(Body::from("carol"))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+    let response = create_app(state.clone())
+        .oneshot(download("/filters/jvm/bits?format=guava"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let exported = response.into_body().collect().await.unwrap().to_bytes();
+    let snapshot = FilterSnapshot::from_guava(&exported).unwrap();
+    assert!(["alice", "bob", "carol"]
+        .iter()
+        .all(|item| snapshot.filter.contains(item.as_bytes())));
+
+    // 3. Native files keep the hash scheme, so the filter can move between services
+    let response = create_app(state.clone())
+        .oneshot(download("/filters/jvm/bits"))
+        .await
+        .unwrap();
+    let native = response.into_body().collect().await.unwrap().to_bytes();
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/filters/jvm_copy/bits")
+        .body(Body::from(native))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+    let json = response_json(
+        create_app(state.clone())
+            .oneshot(lookup("jvm_copy", "carol"))
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(json["contains"], true);
+
+    // 4. Native filters cannot be exported to Guava
+    let req = Request::builder()
+        .method("PUT")
+        .uri("/filters/native/bits")
+        .body(Body::from(snapshot_bytes(100, &["alice"])))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+    let response = create_app(state.clone())
+        .oneshot(download("/filters/native/bits?format=guava"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_pybloom_interop() {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+    // Filters written by pybloom_live's BloomFilter.tofile, covering each of its digests
+    let fixtures: Vec<serde_json::Value> =
+        serde_json::from_str(include_str!("fixtures/pybloom.json")).unwrap();
+    let state = SharedState::default();
+    let upload = |name: &str, bytes: Vec<u8>| {
+        Request::builder()
+            .method("PUT")
+            .uri(format!("/filters/{name}/bits?format=pybloom"))
+            .body(Body::from(bytes))
+            .unwrap()
+    };
+    let download = |name: &str| {
+        Request::builder()
+            .method("GET")
+            .uri(format!("/filters/{name}/bits?format=pybloom"))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    for (index, fixture) in fixtures.iter().enumerate() {
+        let file = BASE64.decode(fixture["file"].as_str().unwrap()).unwrap();
+        let added = fixture["added"].as_u64().unwrap();
+        let name = format!("python_{index}");
+
+        // 1. Import the filter, answering lookups as pybloom would
+        let response = create_app(state.clone())
+            .oneshot(upload(&name, file.clone()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{fixture}");
+        let described = state.describe(&name).unwrap().0;
+        assert_eq!(described.scheme, bloomsrv::HashScheme::Pybloom);
+        assert_eq!(
+            described.capacity as u64,
+            fixture["capacity"].as_u64().unwrap()
+        );
+        for i in 0..added {
+            assert!(
+                state.contains(&name, format!("item-{i}")).unwrap(),
+                "{name}"
+            );
+        }
+        let rate = fixture["error_rate"].as_f64().unwrap();
+        let false_positives = (added..added + 1000)
+            .filter(|i| state.contains(&name, format!("item-{i}")).unwrap())
+            .count();
+        assert!(
+            false_positives as f64 <= 1000.0 * rate * 3.0 + 5.0,
+            "{name}"
+        );
+
+        // 2. Export it back, only its count estimated
+        let response = create_app(state.clone())
+            .oneshot(download(&name))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let exported = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            (&exported[..32], &exported[40..]),
+            (&file[..32], &file[40..])
+        );
+        let count = u64::from_le_bytes(exported[32..40].try_into().unwrap());
+        assert!(count.abs_diff(added) <= added / 10, "{name}: {count}");
+
+        // 3. Inserting the items into an empty copy sets the same bits as pybloom
+        let mut empty = file[..40].to_vec();
+        empty.resize(file.len(), 0);
+        let copy = format!("{name}_copy");
+        create_app(state.clone())
+            .oneshot(upload(&copy, empty))
+            .await
+            .unwrap();
+        for i in 0..added {
+            state.insert(&copy, format!("item-{i}")).unwrap();
+        }
+        let response = create_app(state.clone())
+            .oneshot(download(&copy))
+            .await
+            .unwrap();
+        let exported = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&exported[40..], &file[40..], "{name}");
+    }
+
+    // 4. Filters created with the scheme are exported with a slice per hash function
+    let payload = serde_json::json!({
+        "name": "sliced", "item_count": 1000, "false_positive_rate": 0.01, "scheme": "pybloom",
+    });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    state.insert("sliced", "alice").unwrap();
+    let response = create_app(state.clone())
+        .oneshot(download("sliced"))
+        .await
+        .unwrap();
+    let exported = response.into_body().collect().await.unwrap().to_bytes();
+    let field = |index: usize| u64::from_le_bytes(exported[index..index + 8].try_into().unwrap());
+    let (bit_count, hash_count) = (9600, 7);
+    assert_eq!((field(8), field(16)), (hash_count, bit_count / hash_count));
+    assert_eq!((field(24), field(32)), (1000, 1));
+    let snapshot = FilterSnapshot::from_pybloom(&exported).unwrap();
+    assert!(snapshot.filter.contains(b"alice"));
+
+    // 5. Other filters cannot be exported to pybloom, nor truncated files imported
+    let response = create_app(state.clone())
+        .oneshot(upload("truncated", exported[..exported.len() - 1].to_vec()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    state
+        .create(FilterSpec {
+            name: "native".to_string(),
+            item_count: 1000,
+            false_positive_rate: Some(0.01),
+            ..Default::default()
+        })
+        .unwrap();
+    let response = create_app(state.clone())
+        .oneshot(download("native"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_debug_traces() {
+    let state = SharedState::default();
+    let config = AppConfig {
+        debug_traces: true,
+        ..Default::default()
+    };
+    let request = |method: &str, uri: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from("alice"))
+            .unwrap()
+    };
+
+    let payload = serde_json::json!({ "name": "traced", "item_count": 1000, "hash_count": 4 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(state.clone()).oneshot(req).await.unwrap();
+
+    // 1. Traces are refused unless the server enables them
+    let response = create_app(state.clone())
+        .oneshot(request("POST", "/filters/traced/items?debug=1"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(response_json(response).await["code"], "forbidden");
+
+    // 2. An insert reports the positions of the item, and the bits before the insert
+    let response = create_app_with_config(state.clone(), config.clone())
+        .oneshot(request("POST", "/filters/traced/items?debug=1"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response_json(response).await;
+    assert_eq!(json["new"], true);
+    let trace = &json["debug"];
+    assert_eq!(trace["scheme"], "native");
+    let indices = trace["bit_indices"].as_array().unwrap().clone();
+    assert_eq!(indices.len(), 4);
+    assert_eq!(
+        trace["bits_set"],
+        serde_json::json!([false, false, false, false])
+    );
+
+    // 3. A lookup reports the same positions, now set
+    let json = response_json(
+        create_app_with_config(state.clone(), config.clone())
+            .oneshot(request("GET", "/filters/traced/items?debug=true"))
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(json["contains"], true);
+    assert_eq!(json["debug"]["bit_indices"], serde_json::json!(indices));
+    assert_eq!(
+        json["debug"]["bits_set"],
+        serde_json::json!([true, true, true, true])
+    );
+
+    // 4. Without the flag, responses are unchanged
+    let json = response_json(
+        create_app_with_config(state.clone(), config)
+            .oneshot(request("GET", "/filters/traced/items"))
+            .await
+            .unwrap(),
+    )
+    .await;
+    assert!(json.get("debug").is_none());
+}
+
+#[tokio::test]
+async fn test_filter_dump_and_import() {
+    let source = SharedState::default();
+    let target = SharedState::default();
+    let get = |uri: &str| {
+        Request::builder()
+            .method("GET")
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let import = |uri: &str, content_type: &str, body: Vec<u8>| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", content_type)
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let payload = serde_json::json!({ "name": "logins", "item_count": 1000, "hash_count": 5 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    create_app(source.clone()).oneshot(req).await.unwrap();
+    let req = Request::builder()
+        .method("POST")
+        .uri("/filters/logins/items")
+        .body(Body::from("alice"))
+        .unwrap();
+    create_app(source.clone()).oneshot(req).await.unwrap();
+
+    // 1. A JSON dump carries the name and parameters of the filter
+    let response = create_app(source.clone())
+        .oneshot(get("/filters/logins/dump"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let dump = response_json(response).await;
+    assert_eq!(dump["name"], "logins");
+    assert_eq!(dump["item_count"], 1000);
+    assert_eq!(dump["hash_count"], 5);
+    assert!(dump.get("false_positive_rate").is_none());
+
+    // 2. Importing it into another service recreates the filter
+    let response = create_app(target.clone())
+        .oneshot(import(
+            "/filters/import",
+            "application/json",
+            dump.to_string().into_bytes(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response_json(response).await["name"], "logins");
+    let contains = |state: &SharedState, name: &str, item: &'static str| {
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/filters/{name}/items"))
+            .body(Body::from(item))
+            .unwrap();
+        let app = create_app(state.clone());
+        async move { response_json(app.oneshot(req).await.unwrap()).await["contains"].clone() }
+    };
+    assert_eq!(contains(&target, "logins", "alice").await, true);
+    assert_eq!(contains(&target, "logins", "bob").await, false);
+
+    // 3. Existing filters are not overwritten
+    let response = create_app(target.clone())
+        .oneshot(import(
+            "/filters/import",
+            "application/json",
+            dump.to_string().into_bytes(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // 4. Parameters that do not match the bits are rejected
+    for (field, value) in [
+        ("item_count", serde_json::json!(2000)),
+        ("hash_count", serde_json::json!(6)),
+        ("bits", serde_json::json!("AAAA")),
+        ("bits", serde_json::json!("not base64!")),
+    ] {
+        let mut tampered = dump.clone();
+        tampered[field] = value;
+        let response = create_app(target.clone())
+            .oneshot(import(
+                "/filters/import?name=tampered",
+                "application/json",
+                tampered.to_string().into_bytes(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{field}");
+    }
+
+    // 5. Binary dumps need a name
+    let response = create_app(source.clone())
+        .oneshot(get("/filters/logins/dump?format=binary"))
+        .await
+        .unwrap();
+    let binary = response.into_body().collect().await.unwrap().to_bytes();
+    let response = create_app(target.clone())
+        .oneshot(import(
+            "/filters/import",
+            "application/octet-stream",
+            binary.to_vec(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = create_app(target.clone())
+        .oneshot(import(
+            "/filters/import?name=logins_copy",
+            "application/octet-stream",
+            binary.to_vec(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(contains(&target, "logins_copy", "alice").await, true);
+}
+
+#[tokio::test]
+async fn test_counting_filter_removal() {
+    let state = SharedState::default();
+    let request = |method: &str, uri: &str, body: String| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    for (name, kind) in [("sessions", "counting"), ("logins", "standard")] {
+        let payload = serde_json::json!({
+            "name": name,
+            "item_count": 1000,
+            "false_positive_rate": 0.01,
+            "kind": kind
+        });
+        let req = request("POST", "/filters", payload.to_string());
+        let response = create_app(state.clone()).oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+    for item in ["alice", "bob"] {
+        let req = request("POST", "/filters/sessions/items", item.to_string());
+        create_app(state.clone()).oneshot(req).await.unwrap();
+    }
+
+    // Removing an item leaves the other items in the filter
+    let req = request("DELETE", "/filters/sessions/items", "alice".to_string());
+    let response = create_app(state.clone()).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = response_json(response).await;
+    assert_eq!(json["removed"], true);
+    assert_eq!(
+        json["message"],
+        "Item 'alice' removed from filter 'sessions'"
+    );
+
+    for (item, expected) in [("alice", false), ("bob", true)] {
+        let req = request("GET", "/filters/sessions/items", item.to_string());
+        let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+        assert_eq!(json["contains"], expected);
+    }
+
+    let req = request("DELETE", "/filters/sessions/items", "alice".to_string());
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    assert_eq!(json["removed"], false);
+
+    let req = request("GET", "/filters", String::new());
+    let json = response_json(create_app(state.clone()).oneshot(req).await.unwrap()).await;
+    let kinds: Vec<_> = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| (f["name"].as_str().unwrap(), f["kind"].as_str().unwrap()))
+        .collect();
+    assert!(kinds.contains(&("sessions", "counting")));
+    assert!(kinds.contains(&("logins", "standard")));
+
+    // Standard filters cannot remove items
+    let req = request("DELETE", "/filters/logins/items", "alice".to_string());
+    let response = create_app(state).oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response_json(response).await["code"], "removal_unsupported");
+}
+
+#[tokio::test]
+async fn test_request_limits() {
+    let state = SharedState::default();
+    state
+        .create(FilterSpec {
+            name: "emails".to_string(),
+            item_count: 1000,
+            false_positive_rate: Some(0.01),
+            ..Default::default()
+        })
+        .unwrap();
+    let config = AppConfig {
+        limits: RequestLimits {
+            max_body_bytes: 16,
+            max_upload_bytes: 64,
+            upload_timeout: Duration::from_millis(200),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let send = |uri: &str, body: String, length: bool| {
+        let mut req = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json");
+        if length {
+            req = req.header("content-length", body.len());
+        }
+        let app = create_app_with_config(state.clone(), config.clone());
+        let req = req.body(Body::from(body)).unwrap();
+        async move { app.oneshot(req).await.unwrap() }
+    };
+
+    // 1. Bodies beyond the limit are rejected, whether or not their length is given
+    let item = "a".repeat(32);
+    for length in [true, false] {
+        let response = send("/filters/emails/items", item.clone(), length).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let json = response_json(response).await;
+        assert_eq!(json["code"], "payload_too_large");
+        assert_eq!(
+            json["error"],
+            "The body of the request is larger than 16 bytes"
+        );
+    }
+
+    // 2. Batches have a limit of their own
+    let batch = serde_json::json!([item]).to_string();
+    let response = send("/filters/emails/items/batch", batch, true).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let batch = serde_json::json!([item, item]).to_string();
+    let response = send("/filters/emails/items/batch", batch, true).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // 3. A client stalling part way through its body is answered once the timeout elapses
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = create_app_with_config(state.clone(), config.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"POST /filters/emails/items/batch HTTP/1.1\r\nhost: localhost\r\n\
+              connection: close\r\ncontent-type: application/json\r\ncontent-length: 40\r\n\r\n[\"a\", ",
+        )
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .expect("no response before the timeout")
+        .unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 408 Request Timeout"),
+        "{response}"
+    );
+    assert!(response.contains("\"code\":\"timeout\""), "{response}");
+}
+
+#[tokio::test]
+async fn test_compression() {
+    use std::io::Write;
+
+    let state = SharedState::default();
+    state
+        .create(FilterSpec {
+            name: "events".to_string(),
+            item_count: 1000,
+            false_positive_rate: Some(0.01),
+            ..Default::default()
+        })
+        .unwrap();
+    let config = AppConfig {
+        compression: vec![Compression::Gzip, Compression::Zstd],
+        limits: RequestLimits {
+            max_body_bytes: 64,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let gzip = |body: &[u8]| {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    };
+    let send = |uri: &str, encoding: &str, body: Vec<u8>| {
+        let req = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json")
+            .header("content-encoding", encoding)
+            .header("content-length", body.len())
+            .body(Body::from(body))
+            .unwrap();
+        create_app_with_config(state.clone(), config.clone()).oneshot(req)
+    };
+
+    // 1. Batches and streams are decompressed as they are read
+    let batch = serde_json::json!(["evt-1", "evt-2"]).to_string();
+    let uri = "/filters/events/items/batch";
+    let response = send(uri, "gzip", gzip(batch.as_bytes())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response_json(response).await["inserted"], 2);
+    assert!(state.contains("events", "evt-2").unwrap());
+
+    let ndjson = "\"evt-3\"\n\"evt-1\"\n";
+    let body = zstd::encode_all(ndjson.as_bytes(), 0).unwrap();
+    let uri = "/filters/events/items/stream?format=ndjson";
+    let json = response_json(send(uri, "zstd", body).await.unwrap()).await;
+    assert_eq!(json["stats"]["items"], 2);
+    assert_eq!(json["stats"]["new"], 1);
+
+    // 2. The limits apply to the decompressed bodies
+    let item = "a".repeat(1000);
+    let response = send("/filters/events/items", "gzip", gzip(item.as_bytes()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(response_json(response).await["code"], "payload_too_large");
+
+    // 3. Other encodings are rejected rather than inserted as they are
+    let response = send("/filters/events/items", "br", b"evt-4".to_vec())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let json = response_json(response).await;
+    assert_eq!(json["code"], "unsupported_encoding");
+    assert_eq!(json["error"], "Unsupported content encoding 'br'");
+
+    // 4. Responses are compressed for the clients accepting it, and only when configured
+    let list = |config: AppConfig| {
+        let req = Request::builder()
+            .uri("/filters")
+            .header("accept-encoding", "zstd")
+            .body(Body::empty())
+            .unwrap();
+        create_app_with_config(state.clone(), config).oneshot(req)
+    };
+    let response = list(config.clone()).await.unwrap();
+    assert_eq!(response.headers()["content-encoding"], "zstd");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value =
+        serde_json::from_slice(&zstd::decode_all(&body[..]).unwrap()).unwrap();
+    assert_eq!(json[0]["name"], "events");
+
+    let response = list(AppConfig::default()).await.unwrap();
+    assert!(response.headers().get("content-encoding").is_none());
+    assert_eq!(response_json(response).await[0]["name"], "events");
+}
+
+#[tokio::test]
+async fn test_cors() {
+    let state = SharedState::default();
+    let config = AppConfig {
+        admin_key: Some("root-secret".to_string()),
+        cors: Some(CorsPolicy::new(vec![
+            "https://dashboard.example.com".to_string()
+        ])),
+        ..Default::default()
+    };
+    let send = |method: &str, origin: &str, preflight: bool| {
+        let mut req = Request::builder()
+            .method(method)
+            .uri("/filters")
+            .header("origin", origin);
+        if preflight {
+            req = req.header("access-control-request-method", "POST");
+        } else {
+            req = req.header("x-api-key", "root-secret");
+        }
+        let app = create_app_with_config(state.clone(), config.clone());
+        let req = req.body(Body::empty()).unwrap();
+        async move { app.oneshot(req).await.unwrap() }
+    };
+
+    // 1. Preflights of allowed origins are answered without credentials
+    let response = send("OPTIONS", "https://dashboard.example.com", true).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let headers = response.headers();
+    assert_eq!(
+        headers["access-control-allow-origin"],
+        "https://dashboard.example.com"
+    );
+    assert!(headers["access-control-allow-methods"]
+        .to_str()
+        .unwrap()
+        .contains("POST"));
+    assert!(headers["access-control-allow-headers"]
+        .to_str()
+        .unwrap()
+        .contains("authorization"));
+
+    // 2. The responses to their requests can be read
+    let response = send("GET", "https://dashboard.example.com", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["access-control-allow-origin"],
+        "https://dashboard.example.com"
+    );
+    assert_eq!(response.headers()["vary"], "origin");
+
+    // 3. Other origins are not allowed
+    let response = send("OPTIONS", "https://evil.example.com", true).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = send("GET", "https://evil.example.com", false).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response
+        .headers()
+        .contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn test_reload_configuration() {
+    let state = SharedState::default();
+    let send = |app: axum::Router, method: &str, uri: &str, key: &str| {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("x-api-key", key)
+            .body(Body::empty())
+            .unwrap();
+        async move { app.oneshot(req).await.unwrap() }
+    };
+
+    // 1. Without a reloader, reloading is disabled
+    let app = create_app_with_config(state.clone(), AppConfig::default());
+    let response = send(app, "POST", "/admin/reload", "").await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // 2. Reloading replaces the configuration of the running application, e.g. the admin key
+    let config = SharedConfig::new(AppConfig {
+        admin_key: Some("old-secret".to_string()),
+        ..Default::default()
+    });
+    let reloaded = config.clone();
+    let mut reloadable = config.get();
+    reloadable.reload = Some(Reloader::new(move || {
+        let mut changed = reloaded.get();
+        changed.admin_key = Some("new-secret".to_string());
+        reloaded.replace(changed);
+        Ok("Rotated the admin key".to_string())
+    }));
+    config.replace(reloadable);
+    let app = create_app_with_shared_config(state.clone(), config.clone());
+
+    let response = send(app.clone(), "POST", "/admin/reload", "old-secret").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response_json(response).await["message"],
+        "Rotated the admin key"
+    );
+    let response = send(app.clone(), "GET", "/filters", "old-secret").await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = send(app.clone(), "GET", "/filters", "new-secret").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // 3. A failed reload leaves the configuration unchanged
+    let mut failing = config.get();
+    failing.reload = Some(Reloader::new(|| {
+        Err(bloomsrv::Error::InvalidParameters(
+            "Invalid configuration file 'bloomsrv.toml'".to_string(),
+        ))
+    }));
+    config.replace(failing);
+    let response = send(app.clone(), "POST", "/admin/reload", "new-secret").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = send(app, "GET", "/filters", "new-secret").await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_resp_protocol() {
+    // Sends a command as an array of bulk strings, and reads the expected reply
+    async fn call(stream: &mut tokio::net::TcpStream, command: &[&str], expected: &str) {
+        let mut request = format!("*{}\r\n", command.len());
+        for argument in command {
+            request.push_str(&format!("${}\r\n{argument}\r\n", argument.len()));
+        }
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut reply = vec![0; expected.len()];
+        tokio::time::timeout(Duration::from_secs(5), stream.read_exact(&mut reply))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&reply), expected, "{command:?}");
+    }
+
+    let state = SharedState::default();
+    let (_, read_key) = state
+        .create_api_key(KeySpec {
+            scope: KeyScope::Read,
+            ..Default::default()
+        })
+        .unwrap();
+    let config = SharedConfig::new(AppConfig {
+        admin_key: Some("secret".to_string()),
+        ..Default::default()
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(bloomsrv::serve_resp(listener, state.clone(), config));
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+    // Credentials are required, as by the HTTP API
+    call(
+        &mut stream,
+        &["PING"],
+        "-NOAUTH Authentication required.\r\n",
+    )
+    .await;
+    call(
+        &mut stream,
+        &["AUTH", "wrong"],
+        "-WRONGPASS invalid username-password pair or user is disabled.\r\n",
+    )
+    .await;
+    call(&mut stream, &["AUTH", "default", "secret"], "+OK\r\n").await;
+    call(&mut stream, &["PING"], "+PONG\r\n").await;
+
+    // Adding to a missing filter creates it
+    call(&mut stream, &["BF.EXISTS", "emails", "a@x.io"], ":0\r\n").await;
+    call(&mut stream, &["BF.ADD", "emails", "a@x.io"], ":1\r\n").await;
+    call(&mut stream, &["BF.ADD", "emails", "a@x.io"], ":0\r\n").await;
+    assert!(state.contains("emails", "a@x.io").unwrap());
+    call(
+        &mut stream,
+        &["BF.MADD", "emails", "a@x.io", "b@x.io"],
+        "*2\r\n:0\r\n:1\r\n",
+    )
+    .await;
+    call(
+        &mut stream,
+        &["BF.MEXISTS", "emails", "b@x.io", "c@x.io"],
+        "*2\r\n:1\r\n:0\r\n",
+    )
+    .await;
+
+    // Reserving
+    call(
+        &mut stream,
+        &["BF.RESERVE", "cards", "0.001", "1000", "NONSCALING"],
+        "+OK\r\n",
+    )
+    .await;
+    assert_eq!(
+        state.describe("cards").unwrap().0.kind,
+        bloomsrv::FilterKind::Standard
+    );
+    call(
+        &mut stream,
+        &["BF.RESERVE", "cards", "0.001", "1000"],
+        "-ERR item exists\r\n",
+    )
+    .await;
+    call(
+        &mut stream,
+        &["BF.RESERVE", "cards", "2", "1000"],
+        "-ERR (0 < error rate range < 1)\r\n",
+    )
+    .await;
+    call(
+        &mut stream,
+        &["BF.ADD", "cards"],
+        "-ERR wrong number of arguments for 'bf.add' command\r\n",
+    )
+    .await;
+
+    // Pipelined commands, and inline commands
+    stream
+        .write_all(b"BF.EXISTS cards 4111\r\nBF.ADD cards 4111\r\nBF.EXISTS cards 4111\r\n")
+        .await
+        .unwrap();
+    let mut reply = [0; 12];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(&reply, b":0\r\n:1\r\n:1\r\n");
+
+    // Read-only keys can only look up
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    call(&mut stream, &["AUTH", &read_key], "+OK\r\n").await;
+    call(&mut stream, &["BF.EXISTS", "emails", "a@x.io"], ":1\r\n").await;
+    let mut request = String::new();
+    for argument in ["BF.ADD", "emails", "d@x.io"] {
+        request.push_str(&format!("${}\r\n{argument}\r\n", argument.len()));
+    }
+    stream
+        .write_all(format!("*3\r\n{request}").as_bytes())
+        .await
+        .unwrap();
+    let mut reply = [0; 7];
+    stream.read_exact(&mut reply).await.unwrap();
+    assert_eq!(&reply, b"-NOPERM");
+    assert!(!state.contains("emails", "d@x.io").unwrap());
+}
+
+#[tokio::test]
+async fn test_filter_websocket() {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+
+    let state = SharedState::default();
+    let spec = FilterSpec {
+        name: "edge_dedup".to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+    state.create(spec).unwrap();
+    let (_, read_key) = state
+        .create_api_key(KeySpec {
+            scope: KeyScope::Read,
+            ..Default::default()
+        })
+        .unwrap();
+    let config = AppConfig {
+        admin_key: Some("secret".to_string()),
+        ..Default::default()
+    };
+    let app = create_app_with_config(state.clone(), config);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    let connect = |path: &str, key: &str| {
+        let mut request = format!("ws://{addr}{path}").into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("x-api-key", key.parse().unwrap());
+        tokio_tungstenite::connect_async(request)
+    };
+
+    // Missing filters fail before upgrading
+    let error = connect("/filters/missing/ws", "secret").await.unwrap_err();
+    assert!(error.to_string().contains("404"), "{error}");
+
+    // Text frames, answered in order
+    let (mut socket, _) = connect("/filters/edge_dedup/ws", "secret").await.unwrap();
+    let requests = [
+        serde_json::json!({ "id": 1, "op": "insert", "items": ["a", "b"] }),
+        serde_json::json!({ "id": 2, "op": "insert", "items": ["a"] }),
+        serde_json::json!({ "id": 3, "op": "contains", "items": ["b", "c"] }),
+    ];
+    for request in &requests {
+        socket
+            .send(Message::Text(request.to_string()))
+            .await
+            .unwrap();
+    }
+    let mut responses = Vec::new();
+    for _ in &requests {
+        let message = socket.next().await.unwrap().unwrap();
+        responses
+            .push(serde_json::from_str::<serde_json::Value>(message.to_text().unwrap()).unwrap());
+    }
+    assert_eq!(
+        responses[0],
+        serde_json::json!({ "id": 1, "new": [true, true] })
+    );
+    assert_eq!(responses[1], serde_json::json!({ "id": 2, "new": [false] }));
+    assert_eq!(
+        responses[2],
+        serde_json::json!({ "id": 3, "contains": [true, false] })
+    );
+    assert!(state.contains("edge_dedup", "b").unwrap());
+
+    // Binary frames
+    let mut frame = vec![1];
+    for item in [&b"x"[..], b"a"] {
+        frame.extend_from_slice(&(item.len() as u32).to_be_bytes());
+        frame.extend_from_slice(item);
+    }
+    socket.send(Message::Binary(frame)).await.unwrap();
+    let message = socket.next().await.unwrap().unwrap();
+    assert_eq!(message, Message::Binary(vec![1, 1, 0]));
+
+    // Failures leave the socket open
+    socket.send(Message::Text("{}".to_string())).await.unwrap();
+    let message = socket.next().await.unwrap().unwrap();
+    let body: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+    assert_eq!(body["code"], "invalid_parameters");
+    socket.send(Message::Binary(vec![2, 0, 0])).await.unwrap();
+    let message = socket.next().await.unwrap().unwrap();
+    let body: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+    assert_eq!(body["error"], "Invalid binary frame: truncated item length");
+
+    // Read-only keys can look up, but not insert
+    let (mut socket, _) = connect("/filters/edge_dedup/ws", &read_key).await.unwrap();
+    let request = serde_json::json!({ "op": "insert", "items": ["z"] });
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+    let message = socket.next().await.unwrap().unwrap();
+    let body: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+    assert_eq!(body["code"], "forbidden");
+    assert!(!state.contains("edge_dedup", "z").unwrap());
+    let request = serde_json::json!({ "op": "contains", "items": ["x"] });
+    socket
+        .send(Message::Text(request.to_string()))
+        .await
+        .unwrap();
+    let message = socket.next().await.unwrap().unwrap();
+    let body: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+    assert_eq!(body, serde_json::json!({ "id": null, "contains": [true] }));
+}
+
+#[tokio::test]
+async fn test_events_stream() {
+    let state = SharedState::default();
+    let app = create_app(state.clone());
+    let response = app
+        .clone()
+        .oneshot(Request::get("/events").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut events = response.into_body();
+
+    let create = |uri: &str, name: &str| {
+        let payload =
+            serde_json::json!({ "name": name, "item_count": 1000, "false_positive_rate": 0.01 });
+        Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    };
+    let requests = [
+        create("/filters", "login_attempts"),
+        Request::put("/filters/login_attempts/clear")
+            .body(Body::empty())
+            .unwrap(),
+        Request::delete("/filters/login_attempts")
+            .body(Body::empty())
+            .unwrap(),
+        create("/namespaces", "payments"),
+        create("/namespaces/payments/filters", "cards"),
+    ];
+    for request in requests {
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert!(response.status().is_success(), "{}", response.status());
+    }
+
+    let mut received = String::new();
+    while received.matches("\n\n").count() < 4 {
+        let frame = tokio::time::timeout(Duration::from_secs(5), events.frame())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        received.push_str(std::str::from_utf8(&frame.into_data().unwrap()).unwrap());
+    }
+    let expected = [
+        r#"{"type":"created","namespace":null,"filter":"login_attempts"}"#,
+        r#"{"type":"cleared","namespace":null,"filter":"login_attempts"}"#,
+        r#"{"type":"deleted","namespace":null,"filter":"login_attempts"}"#,
+        r#"{"type":"created","namespace":"payments","filter":"cards"}"#,
+    ];
+    let events: Vec<&str> = received.split_terminator("\n\n").collect();
+    for (event, expected) in events.iter().zip(expected) {
+        let kind = expected.split('"').nth(3).unwrap();
+        assert_eq!(*event, format!("event: {kind}\ndata: {expected}"));
+    }
+}
+
+#[tokio::test]
+async fn test_publish_events() {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    // A NATS server, accepting the connections to read the messages published
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("nats://s3cr3t@{}", listener.local_addr().unwrap());
+    let accept = || async {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut socket = BufReader::new(socket);
+        socket
+            .write_all(b"INFO {\"server_id\":\"test\",\"auth_required\":true}\r\n")
+            .await
+            .unwrap();
+        let mut line = String::new();
+        socket.read_line(&mut line).await.unwrap();
+        assert!(line.starts_with("CONNECT {"), "{line}");
+        assert!(line.contains(r#""auth_token":"s3cr3t""#), "{line}");
+        line.clear();
+        socket.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "PING\r\n");
+        socket.write_all(b"PONG\r\n").await.unwrap();
+        socket
+    };
+    async fn read_message(socket: &mut BufReader<tokio::net::TcpStream>) -> String {
+        let mut line = String::new();
+        tokio::time::timeout(Duration::from_secs(5), socket.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        let (subject, length) = line
+            .trim_end()
+            .strip_prefix("PUB ")
+            .unwrap()
+            .split_once(' ')
+            .unwrap();
+        assert_eq!(subject, "bloomsrv.events");
+        let mut payload = vec![0; length.parse::<usize>().unwrap() + 2];
+        socket.read_exact(&mut payload).await.unwrap();
+        assert!(payload.ends_with(b"\r\n"));
+        payload.truncate(payload.len() - 2);
+        String::from_utf8(payload).unwrap()
+    }
+
+    let state = SharedState::default();
+    state.sample_insert_events(1.0);
+    let sink = bloomsrv::EventSink::parse(&url, "bloomsrv.events").unwrap();
+    let published = state.clone();
+    tokio::spawn(async move { sink.publish(published).await });
+    let mut socket = accept().await;
+
+    let spec = FilterSpec {
+        name: "login_attempts".to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+    state.create(spec).unwrap();
+    state.insert("login_attempts", "user1").unwrap();
+    assert_eq!(
+        read_message(&mut socket).await,
+        r#"{"type":"created","namespace":null,"filter":"login_attempts"}"#
+    );
+    assert_eq!(
+        read_message(&mut socket).await,
+        r#"{"type":"inserted","namespace":null,"filter":"login_attempts","item":"dXNlcjE="}"#
+    );
+
+    // Answers the keep-alives of the server
+    socket.write_all(b"PING\r\n").await.unwrap();
+    let mut line = String::new();
+    socket.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "PONG\r\n");
+
+    // Reconnects once the connection is lost, publishing the events made meanwhile
+    drop(socket);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    state.delete("login_attempts").unwrap();
+    let mut socket = tokio::time::timeout(Duration::from_secs(10), accept())
+        .await
+        .unwrap();
+    assert_eq!(
+        read_message(&mut socket).await,
+        r#"{"type":"deleted","namespace":null,"filter":"login_attempts"}"#
+    );
+}
+
+#[tokio::test]
+async fn test_replication() {
+    let primary = SharedState::default();
+    let spec = FilterSpec {
+        name: "login_attempts".to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+    primary.create(spec.clone()).unwrap();
+    primary.insert("login_attempts", "alice").unwrap();
+    let config = AppConfig {
+        admin_key: Some("secret".to_string()),
+        ..Default::default()
+    };
+    let app = create_app_with_config(primary.clone(), config);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let replica = SharedState::default();
+    replica.read_only.store(true, Ordering::Relaxed);
+    assert_eq!(
+        replica.readiness(),
+        ["The replica has not synced with its primary yet"]
+    );
+    let replication = bloomsrv::Replication {
+        primary: format!("http://{addr}"),
+        key: Some("secret".to_string()),
+    };
+    let following = replica.clone();
+    tokio::spawn(async move { replication.follow(following).await });
+    async fn until(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            if Instant::now() > deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        true
+    }
+    assert!(until(|| replica.readiness().is_empty()).await);
+    assert!(replica.contains("login_attempts", "alice").unwrap());
+
+    // Changes are applied as the primary makes them
+    primary.insert("login_attempts", "bob").unwrap();
+    primary
+        .create(FilterSpec {
+            name: "signups".to_string(),
+            ..spec.clone()
+        })
+        .unwrap();
+    assert!(until(|| replica.contains("signups", "carol").is_ok()).await);
+    assert!(replica.contains("login_attempts", "bob").unwrap());
+    primary.delete("login_attempts").unwrap();
+    assert!(until(|| replica.contains("login_attempts", "bob").is_err()).await);
+
+    // Replicas serve lookups only, until promoted
+    let app = create_app(replica.clone());
+    let create = || {
+        Request::post("/filters")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"name": "emails", "item_count": 1000, "false_positive_rate": 0.01}"#,
+            ))
+            .unwrap()
+    };
+    let response = app.clone().oneshot(create()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/filters/signups/items?value=carol")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/admin/replication")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let promote = || Request::post("/admin/promote").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(promote()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.clone().oneshot(create()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = app.oneshot(promote()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_replica_does_not_expire() {
+    let replica = SharedState::default();
+    replica
+        .create(FilterSpec {
+            name: "sessions".to_string(),
+            item_count: 1000,
+            false_positive_rate: Some(0.01),
+            ttl_seconds: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+    replica.read_only.store(true, Ordering::Relaxed);
+    let task = bloomsrv::spawn_expiry_task(replica.clone(), Duration::from_millis(50));
+
+    // The replica follows the expiry of its primary only
+    tokio::time::sleep(Duration::from_millis(1300)).await;
+    assert!(replica.contains("sessions", "alice").is_ok());
+
+    // Once promoted, it expires its filters itself
+    replica.read_only.store(false, Ordering::Relaxed);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(replica.contains("sessions", "alice").is_err());
+    task.abort();
+}
+
+#[tokio::test]
+async fn test_read_only_mode() {
+    let state = SharedState::default();
+    let spec = FilterSpec {
+        name: "login_attempts".to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+    state.create(spec).unwrap();
+    let app = create_app(state.clone());
+    let request = |method: &str, uri: &str, body: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let read_only = |enabled: bool| {
+        request(
+            "POST",
+            "/admin/readonly",
+            &format!(r#"{{"enabled": {enabled}}}"#),
+        )
+    };
+    let insert = || request("POST", "/filters/login_attempts/items", "alice");
+
+    let response = app.clone().oneshot(read_only(true)).await.unwrap();
+    assert_eq!(
+        response_json(response).await["message"],
+        "Read-only mode turned on"
+    );
+    assert!(state.read_only_mode.load(Ordering::Relaxed));
+
+    // Changes are rejected, while lookups and lists are served
+    let response = app.clone().oneshot(insert()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body = r#"{"name": "emails", "item_count": 1000, "false_positive_rate": 0.01}"#;
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/filters", body))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let uri = "/filters/login_attempts/items?item=alice";
+    let response = app.clone().oneshot(request("GET", uri, "")).await.unwrap();
+    assert_eq!(response_json(response).await["contains"], false);
+    let response = app
+        .clone()
+        .oneshot(request("GET", "/filters", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app.clone().oneshot(read_only(false)).await.unwrap();
+    assert_eq!(
+        response_json(response).await["message"],
+        "Read-only mode turned off"
+    );
+    let response = app.clone().oneshot(insert()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(state.contains("login_attempts", "alice"), Ok(true));
+}
+
+#[cfg(feature = "cluster")]
+#[tokio::test]
+async fn test_cluster() {
+    use bloomsrv::{Cluster, ClusterMember};
+
+    let dir = std::env::temp_dir().join(format!("bloomsrv-cluster-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut nodes = Vec::new();
+    for id in 1..=3 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let member = ClusterMember {
+            id,
+            address: format!("http://{}", listener.local_addr().unwrap()),
+        };
+        let state = SharedState::default();
+        let node_dir = dir.join(id.to_string());
+        let key = Some("secret".to_string());
+        let cluster = Cluster::start(state.clone(), member.clone(), &node_dir, key)
+            .await
+            .unwrap();
+        let config = AppConfig {
+            admin_key: Some("secret".to_string()),
+            cluster: Some(cluster.clone()),
+            ..Default::default()
+        };
+        let app = create_app_with_config(state.clone(), config);
+        let served = app.clone();
+        let server = tokio::spawn(async move { axum::serve(listener, served).await.unwrap() });
+        nodes.push((member, state, cluster, app, server));
+    }
+    let request = |method: &str, uri: &str, body: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("authorization", "Bearer secret")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    async fn until(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            if Instant::now() > deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        true
+    }
+
+    // A cluster of the first node, which the others are added to
+    let app = nodes[0].3.clone();
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/admin/cluster/init", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/admin/cluster/init", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    for (member, ..) in &nodes[1..] {
+        let body = serde_json::to_string(member).unwrap();
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/admin/cluster/nodes", &body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    assert!(
+        until(|| nodes.iter().all(|(_, _, cluster, ..)| {
+            let status = cluster.status();
+            status.leader == Some(1) && status.nodes.iter().filter(|n| n.voter).count() == 3
+        }))
+        .await
+    );
+    let response = nodes[2]
+        .3
+        .clone()
+        .oneshot(request("GET", "/admin/cluster", ""))
+        .await
+        .unwrap();
+    let status = response_json(response).await;
+    assert_eq!(status["id"], 3);
+    assert_eq!(status["role"], "follower");
+    assert_eq!(status["leader"], 1);
+
+    // Changes requested of any node are committed by all, with the same ids
+    let body = r#"{"name": "login_attempts", "item_count": 1000, "false_positive_rate": 0.01}"#;
+    let response = nodes[1]
+        .3
+        .clone()
+        .oneshot(request("POST", "/filters", body))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = nodes[2]
+        .3
+        .clone()
+        .oneshot(request("POST", "/filters/login_attempts/items", "alice"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        until(|| nodes
+            .iter()
+            .all(|(_, state, ..)| state.contains("login_attempts", "alice") == Ok(true)))
+        .await
+    );
+    let ids: Vec<String> = nodes
+        .iter()
+        .map(|(_, state, ..)| state.describe("login_attempts").unwrap().0.id)
+        .collect();
+    assert!(ids.iter().all(|id| *id == ids[0]));
+
+    // The secret of a key is generated by the node it is minted on, and only its hash committed
+    let response = nodes[1]
+        .3
+        .clone()
+        .oneshot(request("POST", "/admin/keys", r#"{"scope": "read"}"#))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let minted = response_json(response).await;
+    let secret = minted["api_key"].as_str().unwrap().to_string();
+    assert!(secret.starts_with("bsk_"));
+    assert!(
+        until(|| nodes
+            .iter()
+            .all(|(_, state, ..)| state.authenticate(&secret).is_some()))
+        .await
+    );
+    for id in 1..=3 {
+        let log = std::fs::read_to_string(dir.join(id.to_string()).join("log.jsonl")).unwrap();
+        assert!(log.contains("secret_hash"));
+        assert!(!log.contains(&secret));
+    }
+
+    // Filters expire on no node but as the leader commits it, and then on every node
+    let body = r#"{"name": "sessions", "item_count": 1000, "false_positive_rate": 0.01, "ttl_seconds": 1}"#;
+    let response = nodes[1]
+        .3
+        .clone()
+        .oneshot(request("POST", "/filters", body))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let tasks: Vec<_> = nodes
+        .iter()
+        .map(|(_, state, ..)| bloomsrv::spawn_expiry_task(state.clone(), Duration::from_millis(50)))
+        .collect();
+    tokio::time::sleep(Duration::from_millis(1300)).await;
+    assert!(nodes
+        .iter()
+        .all(|(_, state, ..)| state.contains("sessions", "alice").is_ok()));
+    let cluster_tasks: Vec<_> = nodes
+        .iter()
+        .map(|(_, state, cluster, ..)| {
+            cluster.spawn_expiry_task(state.clone(), Duration::from_millis(50))
+        })
+        .collect();
+    assert!(
+        until(|| nodes
+            .iter()
+            .all(|(_, state, ..)| state.contains("sessions", "alice").is_err()))
+        .await
+    );
+    assert!(nodes
+        .iter()
+        .all(|(_, state, ..)| state.contains("login_attempts", "alice") == Ok(true)));
+    for task in tasks.into_iter().chain(cluster_tasks) {
+        task.abort();
+    }
+
+    // The others elect a leader once the leader is lost, and go on committing changes
+    let (_, _, cluster, _, server) = nodes.remove(0);
+    cluster.shutdown().await;
+    server.abort();
+    assert!(
+        until(|| nodes
+            .iter()
+            .all(|(_, _, cluster, ..)| matches!(cluster.status().leader, Some(2 | 3))))
+        .await
+    );
+    let response = nodes[0]
+        .3
+        .clone()
+        .oneshot(request("POST", "/filters/login_attempts/items", "bob"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        until(|| nodes
+            .iter()
+            .all(|(_, state, ..)| state.contains("login_attempts", "bob") == Ok(true)))
+        .await
+    );
+    let response = nodes[1]
+        .3
+        .clone()
+        .oneshot(request("GET", "/filters/login_attempts/items?item=bob", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    for (_, _, cluster, ..) in &nodes {
+        cluster.shutdown().await;
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_shard_ring() {
+    let mut listeners = Vec::new();
+    for _ in 0..2 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        listeners.push((listener, address));
+    }
+    let addresses: Vec<String> = listeners
+        .iter()
+        .map(|(_, address)| address.clone())
+        .collect();
+    let mut nodes = Vec::new();
+    for (listener, address) in listeners {
+        let state = SharedState::default();
+        let ring = ShardRing::new(&address, &addresses).unwrap();
+        let config = AppConfig {
+            shards: Some(Arc::new(ring.clone())),
+            ..Default::default()
+        };
+        let app = create_app_with_config(state.clone(), config);
+        let served = app.clone();
+        tokio::spawn(async move { axum::serve(listener, served).await.unwrap() });
+        nodes.push((ring, state, app));
+    }
+    let request = |method: &str, uri: &str, body: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    // A filter owned by each node, both created through the first
+    let (ring, _, app) = &nodes[0];
+    let name_of = |own: bool| {
+        (0..)
+            .map(|i| format!("filter_{i}"))
+            .find(|name| ring.owns(name) == own)
+            .unwrap()
+    };
+    let (own, other) = (name_of(true), name_of(false));
+    for name in [&own, &other] {
+        let body =
+            format!(r#"{{"name": "{name}", "item_count": 1000, "false_positive_rate": 0.01}}"#);
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/filters", &body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+    assert!(nodes[0].1.describe(&own).is_ok());
+    assert!(nodes[0].1.describe(&other).is_err());
+    assert!(nodes[1].1.describe(&other).is_ok());
+    assert!(nodes[1].1.describe(&own).is_err());
+
+    // Items of the filter of the other node are inserted and looked up there
+    let uri = format!("/filters/{other}/items");
+    let response = app
+        .clone()
+        .oneshot(request("POST", &uri, "alice"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(nodes[1].1.contains(&other, "alice"), Ok(true));
+    for (item, contains) in [("alice", true), ("bob", false)] {
+        let uri = format!("/filters/{other}/items?item={item}");
+        let response = app.clone().oneshot(request("GET", &uri, "")).await.unwrap();
+        assert_eq!(response_json(response).await["contains"], contains);
+    }
+    // With the body of a lookup too, which is proxied with its length
+    for _ in 0..50 {
+        let lookup = Request::builder()
+            .method("GET")
+            .uri(format!("/filters/{other}/items"))
+            .header("content-length", "5")
+            .body(Body::from("alice"))
+            .unwrap();
+        let response = app.clone().oneshot(lookup).await.unwrap();
+        assert_eq!(response_json(response).await["contains"], true);
+    }
+
+    // The ring tells the owner of a filter
+    let uri = format!("/admin/shards?filter={other}");
+    let response = app.clone().oneshot(request("GET", &uri, "")).await.unwrap();
+    let status = response_json(response).await;
+    assert_eq!(status["address"], addresses[0]);
+    assert_eq!(status["nodes"].as_array().unwrap().len(), 2);
+    assert_eq!(status["owner"], addresses[1]);
+    let response = create_app(SharedState::default())
+        .oneshot(request("GET", "/admin/shards", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn test_shard_owned_filters() {
+    let addresses: Vec<String> = (1..=3).map(|i| format!("http://10.0.0.{i}:3000")).collect();
+    let spec = |name: &str| FilterSpec {
+        name: name.to_string(),
+        item_count: 3000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+    let filters: Vec<FilterSpec> = (0..30).map(|i| spec(&format!("filter-{i}"))).collect();
+    let filters = [filters, vec![spec("seen")]].concat();
+
+    // Each filter is created on its owner alone, and partitioned filters on every node, sized
+    // for their share of the items
+    let mut owners = std::collections::HashMap::new();
+    for address in &addresses {
+        let ring = ShardRing::new(address, &addresses)
+            .unwrap()
+            .with_partitioned(&["seen".to_string()]);
+        let state = SharedState::default();
+        let report = state.bootstrap(ring.owned_filters(&filters)).unwrap();
+        assert!(report.created.contains(&"seen".to_string()));
+        assert_eq!(state.describe("seen").unwrap().0.capacity, 1000);
+        for name in report.created.iter().filter(|name| *name != "seen") {
+            assert_eq!(ring.owner(name), address);
+            assert!(owners.insert(name.clone(), address).is_none());
+        }
+    }
+    assert_eq!(owners.len(), 30);
+}
+
+#[tokio::test]
+async fn test_shard_partition() {
+    let mut listeners = Vec::new();
+    for _ in 0..2 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        listeners.push((listener, address));
+    }
+    let addresses: Vec<String> = listeners
+        .iter()
+        .map(|(_, address)| address.clone())
+        .collect();
+    let mut nodes = Vec::new();
+    for (listener, address) in listeners {
+        let state = SharedState::default();
+        let ring = ShardRing::new(&address, &addresses)
+            .unwrap()
+            .with_partitioned(&["seen".to_string()]);
+        let config = AppConfig {
+            shards: Some(Arc::new(ring.clone())),
+            ..Default::default()
+        };
+        let app = create_app_with_config(state.clone(), config);
+        let served = app.clone();
+        tokio::spawn(async move { axum::serve(listener, served).await.unwrap() });
+        nodes.push((ring, state, app));
+    }
+    let request = |method: &str, uri: &str, body: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let (ring, _, app) = &nodes[0];
+    let owner = |item: &str| {
+        let owner = ring.item_owner(item.as_bytes());
+        &nodes[addresses.iter().position(|a| a == owner).unwrap()].1
+    };
+
+    // Every node holds a partition, sized for its share of the items
+    let body = r#"{"name": "seen", "item_count": 1000, "false_positive_rate": 0.01}"#;
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/filters", body))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    for (_, state, _) in &nodes {
+        assert_eq!(state.describe("seen").unwrap().0.capacity, 500);
+    }
+
+    // Items are inserted by their owners, one by one or in batches
+    let items: Vec<String> = (0..20).map(|i| format!("event-{i}")).collect();
+    for item in &items[..10] {
+        let response = app
+            .clone()
+            .oneshot(request("POST", "/filters/seen/items", item))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let batch = serde_json::to_string(&items[10..]).unwrap();
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/v1/filters/seen/items/batch", &batch))
+        .await
+        .unwrap();
+    assert_eq!(response_json(response).await["inserted"], 10);
+    for item in &items {
+        assert_eq!(owner(item).contains("seen", item), Ok(true));
+    }
+    assert!(nodes.iter().all(|(_, state, _)| {
+        let inserted = state.filter_stats("seen").unwrap().estimated_items;
+        inserted > 0 && inserted < 20
+    }));
+
+    // And looked up by their owners, through any node
+    let other = &nodes[1].2;
+    for item in ["event-3", "event-15", "unseen"] {
+        let uri = format!("/filters/seen/items/{item}");
+        let response = other
+            .clone()
+            .oneshot(request("GET", &uri, ""))
+            .await
+            .unwrap();
+        assert_eq!(response_json(response).await["contains"], item != "unseen");
+    }
+    let mut queried = items.clone();
+    queried.push("unseen".to_string());
+    let batch = serde_json::to_string(&queried).unwrap();
+    let response = other
+        .clone()
+        .oneshot(request("POST", "/filters/seen/items/query", &batch))
+        .await
+        .unwrap();
+    let contains = response_json(response).await["contains"].clone();
+    let mut expected = vec![true; 20];
+    expected.push(false);
+    assert_eq!(contains, serde_json::json!(expected));
+
+    // Changes of the filter are made by every node, others rejected
+    let response = other
+        .clone()
+        .oneshot(request("PUT", "/filters/seen/clear", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    for item in &items {
+        assert_eq!(owner(item).contains("seen", item), Ok(false));
+    }
+    let response = other
+        .clone()
+        .oneshot(request("POST", "/filters/seen/items/stream", "event-1"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let uri = "/admin/shards?filter=seen";
+    let response = app.clone().oneshot(request("GET", uri, "")).await.unwrap();
+    let status = response_json(response).await;
+    assert_eq!(status["partitioned"], serde_json::json!(["seen"]));
+    assert!(status.get("owner").is_none());
+    let response = app
+        .clone()
+        .oneshot(request("DELETE", "/filters/seen", ""))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(nodes
+        .iter()
+        .all(|(_, state, _)| state.describe("seen").is_err()));
+}
+
+#[tokio::test]
+async fn test_shard_peers() {
+    let config = SharedConfig::new(AppConfig::default());
+    let address = "http://127.0.0.1:3000".to_string();
+    let peers = ShardPeers::new(config.clone(), &address, &[], &["seen".to_string()]).unwrap();
+    let nodes = || config.get().shards.unwrap().nodes().to_vec();
+    assert_eq!(nodes(), vec![address.clone()]);
+    assert!(config.get().shards.unwrap().is_partitioned("seen"));
+
+    // The nodes discovered join the ring, this one listed once
+    let discovered = peers.discover("localhost:3000").await.unwrap();
+    assert!(discovered >= 1);
+    assert_eq!(nodes().iter().filter(|node| **node == address).count(), 1);
+    assert!(nodes().len() <= discovered + 1);
+
+    // The nodes given are replaced, invalid ones leaving the ring as it is
+    let seeds = ["http://10.0.0.2:3000".to_string()];
+    peers.set_seeds(&seeds).unwrap();
+    assert!(nodes().contains(&seeds[0]));
+    let ring = nodes();
+    assert!(matches!(
+        peers.set_seeds(&["10.0.0.3:3000".to_string()]),
+        Err(bloomsrv::Error::InvalidParameters(_))
+    ));
+    assert_eq!(nodes(), ring);
+    peers.set_seeds(&[]).unwrap();
+    assert!(!nodes().contains(&seeds[0]));
+
+    // A name failing to resolve leaves the ring as it is
+    let ring = nodes();
+    assert!(peers.discover("unresolved.invalid:3000").await.is_err());
+    assert_eq!(nodes(), ring);
+
+    // The nodes are kept while a partitioned filter exists, and only then
+    let store = SharedState::default();
+    peers.protect(store.clone());
+    peers.set_seeds(&seeds).unwrap();
+    let ring = nodes();
+    let seen = FilterSpec {
+        name: "seen".to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+    store.create(seen).unwrap();
+    assert!(matches!(
+        peers.set_seeds(&[]),
+        Err(bloomsrv::Error::InvalidParameters(_))
+    ));
+    assert!(peers.discover("127.0.0.1:3001").await.is_err());
+    assert_eq!(nodes(), ring);
+    store.delete("seen").unwrap();
+    peers.set_seeds(&[]).unwrap();
+    assert!(!nodes().contains(&seeds[0]));
+}
+
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn test_client() {
+    use bloomsrv::client::{BloomClient, ClientError};
+
+    let config = AppConfig {
+        admin_key: Some("secret".to_string()),
+        ..Default::default()
+    };
+    let app = create_app_with_config(SharedState::default(), config);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = BloomClient::new(&url).with_key("secret");
+    let spec = FilterSpec {
+        name: "login_attempts".to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+    let created = client.create_filter(&spec).await.unwrap();
+    assert_eq!(created.name, "login_attempts");
+    assert!(!client.ensure_filter(&spec).await.unwrap());
+    let error = client.create_filter(&spec).await.unwrap_err();
+    assert_eq!(error.code(), Some("filter_exists"));
+
+    // Items of any bytes, one at a time or in batches
+    assert!(client.insert("login_attempts", "alice").await.unwrap());
+    assert!(!client.insert("login_attempts", "alice").await.unwrap());
+    assert!(client.contains("login_attempts", "alice").await.unwrap());
+    assert!(!client.contains("login_attempts", "bob").await.unwrap());
+    let items: [&[u8]; 2] = [b"bob", &[0, 159, 146, 150]];
+    let new = client.insert_batch("login_attempts", &items).await.unwrap();
+    assert_eq!(new, [Some(true), Some(true)]);
+    let items: [&[u8]; 3] = [b"alice", &[0, 159, 146, 150], b"carol"];
+    let contains = client
+        .contains_many("login_attempts", &items)
+        .await
+        .unwrap();
+    assert_eq!(contains, [true, true, false]);
+    assert!(!client
+        .check_and_insert("login_attempts", "carol")
+        .await
+        .unwrap());
+    assert!(client
+        .check_and_insert("login_attempts", "carol")
+        .await
+        .unwrap());
+
+    let filters = client.list_filters().await.unwrap();
+    assert_eq!(filters.len(), 1);
+    assert_eq!(filters[0].id, created.id);
+    client.clear_filter("login_attempts").await.unwrap();
+    assert!(!client.contains("login_attempts", "alice").await.unwrap());
+    client.delete_filter("login_attempts").await.unwrap();
+    let error = client
+        .contains("login_attempts", "alice")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ClientError::Service { status: 404, .. }));
+    assert_eq!(error.code(), Some("filter_not_found"));
+
+    // Credentials, and namespaces
+    let error = BloomClient::new(&url).list_filters().await.unwrap_err();
+    assert_eq!(error.code(), Some("unauthorized"));
+    let error = client
+        .namespace("payments")
+        .list_filters()
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), Some("namespace_not_found"));
+}
+
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn test_client_topology() {
+    use bloomsrv::client::BloomClient;
+
+    async fn serve(app: axum::Router, listener: tokio::net::TcpListener) -> String {
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+    async fn listener() -> tokio::net::TcpListener {
+        tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap()
+    }
+    // An address nothing listens at
+    let gone = {
+        let listener = listener().await;
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let spec = |name: &str| FilterSpec {
+        name: name.to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+
+    // Lookups go to the replicas, and changes to the service
+    let (primary, replica) = (SharedState::default(), SharedState::default());
+    for state in [&primary, &replica] {
+        state.create(spec("seen")).unwrap();
+    }
+    replica.insert("seen", "alice").unwrap();
+    let primary_url = serve(create_app(primary.clone()), listener().await).await;
+    let replica_url = serve(create_app(replica.clone()), listener().await).await;
+    let client = BloomClient::new(&primary_url).with_replicas(std::slice::from_ref(&replica_url));
+    assert!(client.contains("seen", "alice").await.unwrap());
+    assert!(client.insert("seen", "bob").await.unwrap());
+    assert_eq!(primary.contains("seen", "bob"), Ok(true));
+    assert_eq!(replica.contains("seen", "bob"), Ok(false));
+
+    // The service serves the lookups of an unreachable replica, and a replica serves the
+    // changes while the service is unreachable
+    let client = BloomClient::new(&primary_url).with_replicas(std::slice::from_ref(&gone));
+    assert!(client.contains("seen", "bob").await.unwrap());
+    let client = BloomClient::new(&gone).with_replicas(&[replica_url]);
+    assert!(client.insert("seen", "carol").await.unwrap());
+    assert_eq!(replica.contains("seen", "carol"), Ok(true));
+
+    // With a ring, the requests go to the owners of the filters and items, even with the
+    // service unreachable
+    let listeners = [listener().await, listener().await];
+    let addresses: Vec<String> = listeners
+        .iter()
+        .map(|listener| format!("http://{}", listener.local_addr().unwrap()))
+        .collect();
+    let mut states = Vec::new();
+    for (listener, address) in listeners.into_iter().zip(&addresses) {
+        let state = SharedState::default();
+        let ring = ShardRing::new(address, &addresses)
+            .unwrap()
+            .with_partitioned(&["events".to_string()]);
+        let config = AppConfig {
+            shards: Some(Arc::new(ring)),
+            ..Default::default()
+        };
+        serve(create_app_with_config(state.clone(), config), listener).await;
+        states.push(state);
+    }
+    let ring = ShardRing::new(&addresses[0], &addresses[1..])
+        .unwrap()
+        .with_partitioned(&["events".to_string()]);
+    let client = BloomClient::new(&gone).with_ring(ring.clone());
+    let node = |owner: &str| &states[addresses.iter().position(|a| a == owner).unwrap()];
+    for name in ["filter_1", "filter_2", "filter_3"] {
+        client.create_filter(&spec(name)).await.unwrap();
+        assert!(client.insert(name, "alice").await.unwrap());
+        assert_eq!(node(ring.owner(name)).contains(name, "alice"), Ok(true));
+    }
+    client.create_filter(&spec("events")).await.unwrap();
+    for item in ["event-1", "event-2", "event-3"] {
+        assert!(client.insert("events", item).await.unwrap());
+        let owner = node(ring.item_owner(item.as_bytes()));
+        assert_eq!(owner.contains("events", item), Ok(true));
+    }
+    let contains = client
+        .contains_many("events", &["event-1", "event-3", "event-4"])
+        .await
+        .unwrap();
+    assert_eq!(contains, [true, true, false]);
+
+    // A node that left the ring is replaced by the others, which proxy to the owners they know
+    let stale = ShardRing::new(&gone, &addresses).unwrap();
+    let client = BloomClient::new(&addresses[0]).with_ring(stale.clone());
+    let name = (0..)
+        .map(|i| format!("moved_{i}"))
+        .find(|name| stale.owner(name) == gone)
+        .unwrap();
+    client.ensure_filter(&spec(&name)).await.unwrap();
+    assert!(client.insert(&name, "alice").await.unwrap());
+    assert!(client.contains(&name, "alice").await.unwrap());
+    assert_eq!(node(ring.owner(&name)).contains(&name, "alice"), Ok(true));
+}
+
+// --- Helpers to run the binary ---
+
+/// A service run by the binary, killed once dropped.
+struct Service {
+    child: std::process::Child,
+    url: String,
+}
+
+impl Drop for Service {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Service {
+    /// Asks the service to stop, as systemd and container runtimes do, returning whether it
+    /// exited successfully within `timeout`.
+    fn terminate(&mut self, timeout: Duration) -> bool {
+        // SAFETY: signals the process spawned by the test, which it waits for
+        unsafe { libc::kill(self.child.id() as i32, libc::SIGTERM) };
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status.success();
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+}
+
+/// Returns a free port of the loopback interface.
+fn free_port() -> u16 {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// Runs the binary with the arguments `args`, e.g. a subcommand calling a service.
+fn run_binary(args: &[&str]) -> std::process::Output {
+    std::process::Command::new(env!("CARGO_BIN_EXE_bloomsrv"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Runs the service with the options `args`, on a free port, once it serves requests.
+async fn spawn_service(args: &[&str]) -> Service {
+    spawn_service_with_env(args, &[]).await
+}
+
+/// Runs the service with the options `args` and the environment variables `envs`, on a free port,
+/// once it serves requests.
+async fn spawn_service_with_env(args: &[&str], envs: &[(&str, &str)]) -> Service {
+    let port = free_port();
+    let child = std::process::Command::new(env!("CARGO_BIN_EXE_bloomsrv"))
+        .args(["serve", "--host", "127.0.0.1", "--port", &port.to_string()])
+        .args(args)
+        .envs(envs.iter().copied())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let service = Service {
+        child,
+        url: format!("http://127.0.0.1:{port}"),
+    };
+    wait_until_serving(&service.url).await;
+    service
+}
+
+/// Waits for the service at `url` to serve requests.
+async fn wait_until_serving(url: &str) {
+    let client = reqwest::Client::new();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while Instant::now() < deadline {
+        let live = client.get(format!("{url}/livez")).send().await;
+        if live.is_ok_and(|response| response.status().is_success()) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("The service at {url} does not serve requests");
+}
+
+/// Returns a new empty directory, for the files of a test.
+fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_binary_drains_connections_and_saves_on_shutdown() {
+    let dir = test_dir("shutdown");
+    let data_dir = dir.to_str().unwrap();
+    let mut service = spawn_service(&["--data-dir", data_dir, "--shutdown-timeout", "10"]).await;
+    let client = bloomsrv::client::BloomClient::new(&service.url);
+    let spec = FilterSpec {
+        name: "emails".to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+    client.create_filter(&spec).await.unwrap();
+    client.insert("emails", "alice").await.unwrap();
+
+    // A request in flight as the service is asked to stop completes...
+    let watch = reqwest::Client::new()
+        .get(format!(
+            "{}/filters/emails/items/watch?timeout_seconds=1",
+            service.url
+        ))
+        .body("bob")
+        .send();
+    let watch = tokio::spawn(watch);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(service.terminate(Duration::from_secs(10)));
+    let response = watch.await.unwrap().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let json: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(json["timed_out"], true);
+
+    // ...and the items inserted since the last snapshot are saved
+    let restarted = FilterStore::default();
+    assert_eq!(restarted.load(&dir).unwrap(), 1);
+    assert!(restarted.contains("emails", "alice").unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_binary_creates_the_filters_of_the_configuration_file() {
+    let dir = test_dir("bootstrap");
+    let config = dir.join("bloomsrv.toml");
+    let declared = r#"
+        [[filters]]
+        name = "emails"
+        item_count = 1000
+        false_positive_rate = 0.01
+
+        [[filters]]
+        name = "sessions"
+        item_count = 500
+        hash_count = 3
+        ttl_seconds = 3600
+    "#;
+    std::fs::write(&config, declared).unwrap();
+    let data_dir = dir.join("data");
+
+    // The filters are created on startup, and left as they are on the next
+    let args = [
+        "--config",
+        config.to_str().unwrap(),
+        "--data-dir",
+        data_dir.to_str().unwrap(),
+    ];
+    let mut service = spawn_service(&args).await;
+    let client = bloomsrv::client::BloomClient::new(&service.url);
+    let mut names: Vec<String> = client
+        .list_filters()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|filter| filter.name)
+        .collect();
+    names.sort();
+    assert_eq!(names, ["emails", "sessions"]);
+    client.insert("emails", "alice").await.unwrap();
+    assert!(service.terminate(Duration::from_secs(10)));
+
+    let service = spawn_service(&args).await;
+    let client = bloomsrv::client::BloomClient::new(&service.url);
+    assert!(client.contains("emails", "alice").await.unwrap());
+    drop(service);
+
+    // Invalid configuration files fail the startup
+    std::fs::write(&config, format!("{declared}{declared}")).unwrap();
+    let output = run_binary(&["serve", "--config", config.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let error = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        error.contains("'emails' is listed more than once"),
+        "{error}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Serves `body` over HTTPS on a free port of 127.0.0.1, with a certificate of a new certificate
+/// authority, returning the port and the certificate of the authority, in PEM.
+async fn serve_https(body: String) -> (u16, String) {
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use tokio_rustls::rustls::{pki_types::PrivateKeyDer, ServerConfig};
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let key = KeyPair::generate().unwrap();
+    let cert = CertificateParams::new(vec!["127.0.0.1".to_string()])
+        .unwrap()
+        .signed_by(&key, &ca, &ca_key)
+        .unwrap();
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert.der().clone()],
+            PrivateKeyDer::Pkcs8(key.serialize_der().into()),
+        )
+        .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let (acceptor, body) = (acceptor.clone(), body.clone());
+            tokio::spawn(async move {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                // The request is read up to the end of its headers, and answered with the body
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(len) => request.extend_from_slice(&buffer[..len]),
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    (port, ca.pem())
+}
+
+#[tokio::test]
+async fn test_binary_loads_over_https() {
+    let dir = test_dir("https");
+    let lines: String = (0..100).map(|i| format!("key-{i}\n")).collect();
+    let (port, ca) = serve_https(lines).await;
+    let ca_file = dir.join("ca.pem");
+    std::fs::write(&ca_file, ca).unwrap();
+    let source = format!("https://127.0.0.1:{port}/");
+    let load = |service: &Service| {
+        let request = reqwest::Client::new()
+            .post(format!("{}/v1/filters/keys/load", service.url))
+            .json(&serde_json::json!({ "source": format!("{source}keys.txt") }));
+        async move { request.send().await.unwrap() }
+    };
+    let spec = FilterSpec {
+        name: "keys".to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+
+    // The certificate of the source is verified against those trusted by the service...
+    let envs = [("SSL_CERT_FILE", ca_file.to_str().unwrap())];
+    let service = spawn_service_with_env(&["--load-source", &source], &envs).await;
+    let client = bloomsrv::client::BloomClient::new(&service.url);
+    client.create_filter(&spec).await.unwrap();
+    let response = load(&service).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let job: serde_json::Value = response.json().await.unwrap();
+    let uri = format!("{}/v1/jobs/{}", service.url, job["id"].as_str().unwrap());
+    let job = loop {
+        let json: serde_json::Value = reqwest::get(&uri).await.unwrap().json().await.unwrap();
+        if json["status"] != "running" {
+            break json;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_eq!(job["status"], "completed", "{job}");
+    assert_eq!(job["progress"]["items"], 100);
+    assert!(client.contains("keys", "key-99").await.unwrap());
+    drop(service);
+
+    // ...and a source certified by an authority it does not trust is refused
+    let service = spawn_service(&["--load-source", &source]).await;
+    let client = bloomsrv::client::BloomClient::new(&service.url);
+    client.create_filter(&spec).await.unwrap();
+    let response = load(&service).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_binary_subcommands() {
+    let service = spawn_service(&[]).await;
+    let url = service.url.as_str();
+
+    let output = run_binary(&[
+        "create",
+        "emails",
+        "--item-count",
+        "1000",
+        "--fp",
+        "0.01",
+        "--url",
+        url,
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    let output = run_binary(&["insert", "emails", "alice", "bob", "--url", url]);
+    assert!(output.status.success(), "{output:?}");
+
+    // Checks exit with 0 if all items may have been inserted, 1 if not, and 2 on failures
+    let output = run_binary(&["check", "emails", "alice", "bob", "--url", url]);
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    let output = run_binary(&["check", "emails", "alice", "carol", "--url", url]);
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let output = run_binary(&["check", "unknown", "alice", "--url", url]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+
+    let output = run_binary(&["list", "--url", url]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "emails\n");
+
+    // Existing filters, and unreachable services, fail the subcommands
+    let output = run_binary(&[
+        "create",
+        "emails",
+        "--item-count",
+        "1000",
+        "--fp",
+        "0.01",
+        "--url",
+        url,
+    ]);
+    assert!(!output.status.success());
+    let unreachable = format!("http://127.0.0.1:{}", free_port());
+    let output = run_binary(&["list", "--url", &unreachable]);
+    assert!(!output.status.success());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_binary_socket_activation_and_notifications() {
+    use std::os::unix::{io::AsRawFd, net::UnixDatagram, process::CommandExt};
+
+    let dir = test_dir("systemd");
+    let saved = FilterStore::default();
+    saved
+        .create(FilterSpec {
+            name: "emails".to_string(),
+            item_count: 1000,
+            false_positive_rate: Some(0.01),
+            ..Default::default()
+        })
+        .unwrap();
+    saved.insert("emails", "alice").unwrap();
+    saved.save(&dir).unwrap();
+    let socket = dir.join("notify");
+    let systemd = UnixDatagram::bind(&socket).unwrap();
+    systemd
+        .set_read_timeout(Some(Duration::from_secs(30)))
+        .unwrap();
+    let notification = || {
+        let mut buffer = [0; 256];
+        let len = systemd.recv(&mut buffer).unwrap();
+        String::from_utf8_lossy(&buffer[..len]).into_owned()
+    };
+
+    // The socket is bound by the test, as systemd would, and passed as the descriptor 3 to the
+    // process of `LISTEN_PID`: that of the shell, which the binary replaces
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let fd = listener.as_raw_fd();
+    let mut command = std::process::Command::new("sh");
+    command
+        .args(["-c", r#"LISTEN_PID=$$ exec "$0" "$@""#])
+        .arg(env!("CARGO_BIN_EXE_bloomsrv"))
+        .args(["serve", "--port", &free_port().to_string()])
+        .args(["--data-dir", dir.to_str().unwrap()])
+        .env("LISTEN_FDS", "1")
+        .env("LISTEN_FDNAMES", "http")
+        .env("NOTIFY_SOCKET", &socket)
+        .env("WATCHDOG_USEC", "200000")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    // SAFETY: only calls async-signal-safe functions between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            let moved = match fd {
+                3 => libc::fcntl(3, libc::F_SETFD, 0),
+                _ => libc::dup2(fd, 3),
+            };
+            match moved {
+                -1 => Err(std::io::Error::last_os_error()),
+                _ => Ok(()),
+            }
+        });
+    }
+    let mut service = Service {
+        child: command.spawn().unwrap(),
+        url,
+    };
+    drop(listener);
+
+    // Ready once the saved filters are restored, on the socket passed, and pinging the watchdog
+    let mut notifications = Vec::new();
+    while !notifications.contains(&"READY=1".to_string()) {
+        notifications.push(notification());
+    }
+    let client = bloomsrv::client::BloomClient::new(&service.url);
+    assert!(client.contains("emails", "alice").await.unwrap());
+    while notification() != "WATCHDOG=1" {}
+
+    assert!(service.terminate(Duration::from_secs(10)));
+    while notification() != "STOPPING=1" {}
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "kafka")]
+#[test]
+fn test_kafka_ingest() {
+    use bloomsrv::KafkaIngest;
+
+    let state = SharedState::default();
+    let spec = |name: &str| FilterSpec {
+        name: name.to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    };
+    state.create(spec("emails")).unwrap();
+    state
+        .create_namespace("payments")
+        .unwrap()
+        .create(spec("cards"))
+        .unwrap();
+    let ingest = KafkaIngest {
+        brokers: vec!["localhost:9092".to_string()],
+        group: "bloomsrv".to_string(),
+        topics: vec![
+            "signups=emails".parse().unwrap(),
+            "charges=payments/cards".parse().unwrap(),
+            "logins=sessions".parse().unwrap(),
+        ],
+    };
+
+    // The values of the messages are inserted into the filters their topics are mapped to,
+    // skipping those empty
+    let messages: [&[u8]; 3] = [b"alice", b"", b"bob"];
+    assert_eq!(ingest.insert(&state, "signups", messages).unwrap(), 2);
+    assert!(state.contains("emails", "alice").unwrap());
+    assert!(state.contains("emails", "bob").unwrap());
+    let messages: [&[u8]; 1] = [b"4111"];
+    assert_eq!(ingest.insert(&state, "charges", messages).unwrap(), 1);
+    let cards = state.namespace("payments").unwrap();
+    assert!(cards.contains("cards", "4111").unwrap());
+    assert!(!state.contains("emails", "4111").unwrap());
+
+    // Batches for missing filters, or topics mapped to none, fail, so their offsets are not
+    // committed
+    let messages: [&[u8]; 1] = [b"carol"];
+    assert!(matches!(
+        ingest.insert(&state, "logins", messages),
+        Err(bloomsrv::Error::FilterNotFound(_))
+    ));
+    assert!(matches!(
+        ingest.insert(&state, "unknown", messages),
+        Err(bloomsrv::Error::InvalidParameters(_))
+    ));
+}
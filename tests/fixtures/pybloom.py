@@ -0,0 +1,128 @@
+"""Writes pybloom.json: filters serialized by `pybloom_live.BloomFilter.tofile`, with the number
+of items added to them, `item-0`, `item-1` and so on.
+
+The hashing, layout and file format are those of `pybloom_live` 4.0 (`make_hashfuncs`,
+`BloomFilter.add` and `BloomFilter.tofile`), reproduced with the standard library only, so that
+the fixtures can be written again without the package or `bitarray`:
+
+    python3 tests/fixtures/pybloom.py > tests/fixtures/pybloom.json
+"""
+
+import base64
+import hashlib
+import json
+import math
+from struct import pack, unpack
+
+
+def make_hashfuncs(num_slices, num_bits):
+    if num_bits >= (1 << 31):
+        fmt_code, chunk_size = "Q", 8
+    elif num_bits >= (1 << 15):
+        fmt_code, chunk_size = "I", 4
+    else:
+        fmt_code, chunk_size = "H", 2
+    total_hash_bits = 8 * num_slices * chunk_size
+    if total_hash_bits > 384:
+        hashfn = hashlib.sha512
+    elif total_hash_bits > 256:
+        hashfn = hashlib.sha384
+    elif total_hash_bits > 160:
+        hashfn = hashlib.sha256
+    elif total_hash_bits > 128:
+        hashfn = hashlib.sha1
+    else:
+        hashfn = hashlib.md5
+
+    fmt = fmt_code * (hashfn().digest_size // chunk_size)
+    num_salts, extra = divmod(num_slices, len(fmt))
+    if extra:
+        num_salts += 1
+    salts = tuple(hashfn(hashfn(pack("I", i)).digest()) for i in range(0, num_salts))
+
+    def _hash_maker(key):
+        if isinstance(key, str):
+            key = key.encode("utf-8")
+        else:
+            key = str(key).encode("utf-8")
+        i = 0
+        for salt in salts:
+            h = salt.copy()
+            h.update(key)
+            for uint in unpack(fmt, h.digest()):
+                yield uint % num_bits
+                i += 1
+                if i >= num_slices:
+                    return
+
+    return _hash_maker
+
+
+class BloomFilter:
+    FILE_FMT = "<dQQQQ"
+
+    def __init__(self, capacity, error_rate):
+        num_slices = int(math.ceil(math.log(1.0 / error_rate, 2)))
+        bits_per_slice = int(
+            math.ceil((capacity * abs(math.log(error_rate))) / (num_slices * (math.log(2) ** 2)))
+        )
+        self.error_rate = error_rate
+        self.num_slices = num_slices
+        self.bits_per_slice = bits_per_slice
+        self.capacity = capacity
+        self.num_bits = num_slices * bits_per_slice
+        self.count = 0
+        self.make_hashes = make_hashfuncs(self.num_slices, self.bits_per_slice)
+        # A little-endian bitarray: bit i is bit i % 8 of byte i // 8
+        self.bits = bytearray((self.num_bits + 7) // 8)
+
+    def add(self, key):
+        found_all_bits = True
+        offset = 0
+        for k in self.make_hashes(key):
+            bit = offset + k
+            if not self.bits[bit >> 3] & (1 << (bit & 7)):
+                found_all_bits = False
+            self.bits[bit >> 3] |= 1 << (bit & 7)
+            offset += self.bits_per_slice
+        if not found_all_bits:
+            self.count += 1
+        return found_all_bits
+
+    def tobytes(self):
+        header = pack(
+            self.FILE_FMT,
+            self.error_rate,
+            self.num_slices,
+            self.bits_per_slice,
+            self.capacity,
+            self.count,
+        )
+        return header + bytes(self.bits)
+
+
+# Parameters covering each digest and size of chunks of make_hashfuncs
+CASES = [
+    (1000, 0.01, 500),  # MD5, 16-bit chunks
+    (1000, 0.001, 500),  # SHA-1
+    (500, 1e-4, 250),  # SHA-256
+    (500, 1e-6, 250),  # SHA-384
+    (1000, 1e-10, 500),  # SHA-512, two salts
+    (23000, 0.5, 1000),  # MD5, 32-bit chunks
+]
+
+fixtures = []
+for capacity, error_rate, added in CASES:
+    bloom = BloomFilter(capacity, error_rate)
+    items = [f"item-{i}" for i in range(added)]
+    for item in items:
+        bloom.add(item)
+    fixtures.append(
+        {
+            "capacity": capacity,
+            "error_rate": error_rate,
+            "added": added,
+            "file": base64.b64encode(bloom.tobytes()).decode(),
+        }
+    )
+print(json.dumps(fixtures, indent=1))
@@ -0,0 +1,1589 @@
+use bloomsrv::{
+    AlertThresholds, ApplyOptions, BloomFilter, CardinalitySpec, CountMinSketch, CreationMode,
+    Error, FilterKind, FilterSnapshot, FilterSpec, FilterStore, FullAction, GroupLookupMode,
+    GroupSpec, HashScheme, HyperLogLog, KeyScope, KeySpec, LimiterSpec, Manifest, MemoryBudget,
+    NamePolicy, RateLimiter, RecentFilter, RecentSpec, SaturationMonitor, SketchSpec, StableParams,
+    Storage, UploadMode,
+};
+use siphasher::sip128::{Hasher128, SipHasher24};
+use std::{
+    hash::Hasher,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+// --- Helper to build a filter specification ---
+fn spec(name: &str) -> FilterSpec {
+    FilterSpec {
+        name: name.to_string(),
+        item_count: 1000,
+        false_positive_rate: Some(0.01),
+        ..Default::default()
+    }
+}
+
+// --- Embedded Store Tests ---
+// These exercise the FilterStore directly, without the HTTP layer
+
+#[test]
+fn test_store_filter_lifecycle() {
+    let store = FilterStore::new();
+
+    let id = store.create(spec("users")).unwrap().id;
+    assert_eq!(
+        store.create(spec("users")),
+        Err(Error::FilterExists("users".to_string()))
+    );
+    assert!(matches!(
+        store.create(FilterSpec {
+            hash_count: None,
+            false_positive_rate: None,
+            ..spec("bad")
+        }),
+        Err(Error::InvalidParameters(_))
+    ));
+    // A rate needing more hash functions than may be given
+    assert!(matches!(
+        store.create(FilterSpec {
+            item_count: 10,
+            false_positive_rate: Some(1e-320),
+            ..spec("bad")
+        }),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    assert!(store.insert("users", "alice").unwrap());
+    assert!(!store.insert("users", "alice").unwrap());
+    assert!(store.contains("users", "alice").unwrap());
+    assert!(!store.contains("users", "bob").unwrap());
+    assert_eq!(
+        store.insert("ghost", "alice"),
+        Err(Error::FilterNotFound("ghost".to_string()))
+    );
+
+    store.clear("users").unwrap();
+    assert!(!store.contains("users", "alice").unwrap());
+
+    let list = store.list();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].id, id);
+
+    // Filters can be deleted by id as well as by name
+    assert_eq!(store.delete(&id).unwrap(), "users");
+    assert!(store.list().is_empty());
+}
+
+#[test]
+fn test_store_groups_and_shadows() {
+    let store = FilterStore::new();
+    store.create(spec("hourly")).unwrap();
+    store.create(spec("daily")).unwrap();
+
+    store.set_shadow("hourly", "daily").unwrap();
+    store.insert("hourly", "alice").unwrap();
+    assert!(store.contains("daily", "alice").unwrap());
+    assert!(store.contains("hourly", "alice").unwrap());
+    let report = store.shadow_report("hourly").unwrap();
+    assert_eq!((report.compared, report.agreed), (1, 1));
+    assert_eq!(store.remove_shadow("hourly").unwrap(), "daily");
+
+    store
+        .create_group(GroupSpec {
+            name: "dedup".to_string(),
+            filters: vec!["hourly".to_string(), "daily".to_string()],
+        })
+        .unwrap();
+    assert_eq!(store.group_insert("dedup", "bob").unwrap(), 2);
+    store.clear("hourly").unwrap();
+
+    let any = store
+        .group_contains("dedup", "bob", GroupLookupMode::Any)
+        .unwrap();
+    assert!(any.contains);
+    assert!(!any.filters["hourly"]);
+    let all = store
+        .group_contains("dedup", "bob", GroupLookupMode::All)
+        .unwrap();
+    assert!(!all.contains);
+
+    assert_eq!(store.delete_group("dedup", false).unwrap(), 2);
+    assert!(store.list().is_empty());
+}
+
+#[test]
+fn test_store_per_filter_locking() {
+    let store = Arc::new(FilterStore::new());
+    store.create(spec("busy")).unwrap();
+    let id = store.create(spec("idle")).unwrap().id;
+
+    // Hold the lock of one filter, as a long batch insert does
+    let busy = store.filters.read()["busy"].clone();
+    let locked = busy.write();
+
+    // Other filters are neither read nor written behind it, nor are filters created
+    let other = store.clone();
+    let (sender, received) = mpsc::channel();
+    thread::spawn(move || {
+        other.insert("idle", "alice").unwrap();
+        let found = other.contains("idle", "alice").unwrap();
+        let (info, _) = other.describe(&id).unwrap();
+        other.create(spec("new")).unwrap();
+        sender.send(found && info.name == "idle").unwrap();
+    });
+    assert_eq!(received.recv_timeout(Duration::from_secs(5)), Ok(true));
+
+    // The locked filter waits for its lock
+    let waiting = store.clone();
+    let (sender, received) = mpsc::channel();
+    thread::spawn(move || sender.send(waiting.insert("busy", "bob").unwrap()).unwrap());
+    assert!(received.recv_timeout(Duration::from_millis(200)).is_err());
+    drop(locked);
+    assert_eq!(received.recv_timeout(Duration::from_secs(5)), Ok(true));
+
+    // Filters shadowing each other are locked together, in order
+    store.set_shadow("busy", "idle").unwrap();
+    store.set_shadow("idle", "busy").unwrap();
+    let writers: Vec<_> = ["busy", "idle"]
+        .into_iter()
+        .map(|name| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for i in 0..1000 {
+                    store.insert(name, format!("{name}{i}")).unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    assert!(store.contains("busy", "idle999").unwrap());
+    assert!(store.contains("idle", "busy999").unwrap());
+}
+
+#[test]
+fn test_store_atomic_filters() {
+    let store = Arc::new(FilterStore::new());
+    let atomic = |name: &str, kind| FilterSpec {
+        storage: Storage::Atomic,
+        kind,
+        ..spec(name)
+    };
+    store.create(atomic("hot", FilterKind::Standard)).unwrap();
+    let counting = store.create(atomic("counted", FilterKind::Counting));
+    assert!(matches!(counting, Err(Error::InvalidParameters(_))));
+
+    // Inserts set the bits while the filter is read, and alongside one another
+    let hot = store.filters.read()["hot"].clone();
+    let reading = hot.read();
+    let (sender, received) = mpsc::channel();
+    for writer in 0..4 {
+        let (store, sender) = (store.clone(), sender.clone());
+        thread::spawn(move || {
+            for i in 0..250 {
+                store.insert("hot", format!("{writer}-{i}")).unwrap();
+            }
+            sender.send(writer).unwrap();
+        });
+    }
+    for _ in 0..4 {
+        assert!(received.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+    drop(reading);
+    assert!((0..4)
+        .all(|writer| (0..250).all(|i| store.contains("hot", format!("{writer}-{i}")).unwrap())));
+    assert!(!store.check_and_insert("hot", "alice").unwrap());
+    assert!(store.check_and_insert("hot", "alice").unwrap());
+
+    // Snapshots keep the storage
+    let snapshot = store.snapshot("hot").unwrap();
+    let decoded = FilterSnapshot::decode(&snapshot.encode()).unwrap();
+    assert_eq!(decoded.filter.storage(), Storage::Atomic);
+    assert_eq!(decoded, snapshot);
+    let copy = FilterStore::new();
+    copy.upload("copy", decoded, UploadMode::Create).unwrap();
+    let (info, _) = copy.describe("copy").unwrap();
+    assert_eq!(info.storage, Storage::Atomic);
+    assert!(copy.contains("copy", "alice").unwrap());
+}
+
+#[test]
+fn test_store_apply() {
+    let store = FilterStore::new();
+    let manifest = Manifest {
+        filters: vec![spec("users")],
+        groups: vec![GroupSpec {
+            name: "all".to_string(),
+            filters: vec!["users".to_string()],
+        }],
+    };
+
+    let report = store
+        .apply(manifest.clone(), ApplyOptions::default())
+        .unwrap();
+    assert_eq!(report.filters.created, ["users"]);
+    assert_eq!(report.groups.created, ["all"]);
+
+    let report = store.apply(manifest, ApplyOptions::default()).unwrap();
+    assert_eq!(report.filters.unchanged, ["users"]);
+
+    let conflicting = Manifest {
+        filters: vec![FilterSpec {
+            item_count: 10,
+            ..spec("users")
+        }],
+        ..Default::default()
+    };
+    assert_eq!(
+        store
+            .apply(conflicting, ApplyOptions::default())
+            .unwrap_err(),
+        Error::ParameterConflict(vec!["users".to_string()])
+    );
+}
+
+#[test]
+fn test_store_bootstrap() {
+    let store = FilterStore::new();
+    store.create(spec("users")).unwrap();
+    store.insert("users", "alice").unwrap();
+    store
+        .create(FilterSpec {
+            item_count: 10,
+            ..spec("cards")
+        })
+        .unwrap();
+
+    // Missing filters are created, and existing ones left as they are, with their items
+    let report = store
+        .bootstrap([spec("users"), spec("cards"), spec("emails")])
+        .unwrap();
+    assert_eq!(report.created, ["emails"]);
+    assert_eq!(report.existing, ["users"]);
+    assert_eq!(report.conflicting, ["cards"]);
+    assert!(store.contains("users", "alice").unwrap());
+    assert_eq!(store.describe("cards").unwrap().0.capacity, 10);
+
+    // Invalid filters fail the bootstrap
+    let invalid = FilterSpec {
+        hash_count: Some(3),
+        ..spec("logins")
+    };
+    assert!(matches!(
+        store.bootstrap([invalid]),
+        Err(Error::InvalidParameters(_))
+    ));
+    let report = store.bootstrap([spec("emails")]).unwrap();
+    assert_eq!(report.existing, ["emails"]);
+    assert!(report.created.is_empty());
+}
+
+#[tokio::test]
+async fn test_store_watch() {
+    let store = std::sync::Arc::new(FilterStore::new());
+    store.create(spec("events")).unwrap();
+
+    assert!(!store
+        .watch("events", "done", Duration::from_millis(10))
+        .await
+        .unwrap());
+
+    let writer = store.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        writer.insert("events", "done").unwrap();
+    });
+    assert!(store
+        .watch("events", "done", Duration::from_secs(5))
+        .await
+        .unwrap());
+}
+
+#[test]
+fn test_rate_limiter_window_slides() {
+    let limiter = RateLimiter::new(&LimiterSpec {
+        name: "api_calls".to_string(),
+        limit: 2,
+        window_seconds: 10,
+        slots: Some(10),
+        ..Default::default()
+    })
+    .unwrap();
+    let start = Instant::now();
+
+    assert!(limiter.check_and_increment(b"alice", 1, start).allowed);
+    let later = start + Duration::from_secs(5);
+    assert!(limiter.check_and_increment(b"alice", 1, later).allowed);
+    assert!(!limiter.check_and_increment(b"alice", 1, later).allowed);
+
+    // The first request leaves the window, the second one is still in it
+    let decision = limiter.check_and_increment(b"alice", 1, start + Duration::from_secs(11));
+    assert!(decision.allowed);
+    assert_eq!(decision.count, 2);
+    assert!(
+        !limiter
+            .check_and_increment(b"alice", 1, start + Duration::from_secs(12))
+            .allowed
+    );
+
+    // A request costing more than the limit is never allowed
+    assert!(!limiter.check_and_increment(b"bob", 3, start).allowed);
+}
+
+#[test]
+fn test_recent_filter_forgets_old_items() {
+    let recent = RecentFilter::new(&RecentSpec {
+        name: "cards".to_string(),
+        window_seconds: 10,
+        item_count: 1000,
+        slots: Some(5),
+        ..Default::default()
+    })
+    .unwrap();
+    let start = Instant::now();
+
+    assert!(!recent.seen(b"4111", start));
+    assert!(!recent.seen(b"5500", start + Duration::from_secs(6)));
+
+    // Items are remembered for the whole window...
+    let later = start + Duration::from_secs(10);
+    assert!(recent.contains(b"4111", later));
+    assert!(recent.contains(b"5500", later));
+
+    // ...and forgotten at most one generation after leaving it
+    let much_later = start + Duration::from_secs(13);
+    assert!(!recent.contains(b"4111", much_later));
+    assert!(recent.contains(b"5500", much_later));
+    assert!(RecentFilter::new(&RecentSpec {
+        name: "bad".to_string(),
+        window_seconds: 10,
+        ..Default::default()
+    })
+    .is_err());
+}
+
+#[test]
+fn test_recent_filter_rotates_generations() {
+    let store = FilterStore::default();
+    store
+        .create_recent(RecentSpec {
+            name: "events".to_string(),
+            rotation_seconds: Some(60),
+            item_count: 1000,
+            slots: Some(24),
+            ..Default::default()
+        })
+        .unwrap();
+    let info = &store.list_recent()[0];
+    assert_eq!(info.window_seconds, 24 * 60);
+    assert_eq!(info.rotation_seconds, 60.0);
+
+    assert!(!store.recent_seen("events", "event-1").unwrap());
+    assert!(store.recent_contains("events", "event-1").unwrap());
+
+    // Rotating within the window keeps the item...
+    let now = Instant::now();
+    assert_eq!(store.rotate_recent(now + Duration::from_secs(60)), 0);
+    assert!(store.recent_contains("events", "event-1").unwrap());
+    // ...and clears its generation once it has left the window
+    assert_eq!(store.rotate_recent(now + Duration::from_secs(27 * 60)), 1);
+    assert!(!store.recent_contains("events", "event-1").unwrap());
+
+    assert!(RecentFilter::new(&RecentSpec {
+        name: "bad".to_string(),
+        window_seconds: 600,
+        rotation_seconds: Some(60),
+        item_count: 1000,
+        slots: Some(5),
+        ..Default::default()
+    })
+    .is_err());
+}
+
+#[test]
+fn test_store_name_policy() {
+    let store = FilterStore::with_name_policy(NamePolicy {
+        max_length: 8,
+        reserved_prefixes: vec!["sys_".to_string()],
+        ..Default::default()
+    });
+
+    store.create(spec("users.eu")).unwrap();
+    for name in ["", "toolong_name", "../etc", "a b", "sys_users"] {
+        assert!(
+            matches!(store.create(spec(name)), Err(Error::InvalidName { .. })),
+            "{name:?} should be rejected"
+        );
+    }
+    assert!(matches!(
+        store.create_group(GroupSpec {
+            name: "a?b".to_string(),
+            filters: vec!["users.eu".to_string()],
+        }),
+        Err(Error::InvalidName { .. })
+    ));
+}
+
+#[test]
+fn test_filter_snapshot_round_trip() {
+    let creation_mode = CreationMode::HashCount(5);
+    let mut filter = BloomFilter::new(100, creation_mode).unwrap();
+    filter.insert(b"alice");
+    let snapshot = FilterSnapshot {
+        item_count: 100,
+        creation_mode,
+        filter,
+    };
+
+    let mut bytes = snapshot.encode();
+    let loaded = FilterSnapshot::decode(&bytes).unwrap();
+    assert_eq!(loaded, snapshot);
+    assert!(loaded.filter.contains(b"alice"));
+
+    // Corrupted and truncated snapshots are rejected
+    bytes[60] ^= 1;
+    assert!(FilterSnapshot::decode(&bytes).is_err());
+    assert!(FilterSnapshot::decode(&bytes[..bytes.len() - 16]).is_err());
+    assert!(FilterSnapshot::decode(b"not a snapshot").is_err());
+}
+
+#[test]
+fn test_store_save_and_load() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-test-{}", std::process::id()));
+    let store = FilterStore::default();
+    store.create(spec("logins")).unwrap();
+    store.create(spec("tier-1.eu")).unwrap();
+    store.insert("logins", "alice").unwrap();
+    assert_eq!(store.save(&dir).unwrap(), 2);
+
+    // A restarted store gets the filters back, with their parameters
+    let restarted = FilterStore::default();
+    assert_eq!(restarted.load(&dir).unwrap(), 2);
+    assert!(restarted.contains("logins", "alice").unwrap());
+    assert!(!restarted.contains("logins", "bob").unwrap());
+    let info = restarted
+        .list()
+        .into_iter()
+        .find(|info| info.name == "tier-1.eu")
+        .unwrap();
+    assert_eq!(info.capacity, 1000);
+    assert_eq!(info.creation_mode, CreationMode::FalsePositiveRate(0.01));
+
+    // Deleted filters are removed from the directory at the next save
+    store.delete("tier-1.eu").unwrap();
+    assert_eq!(store.save(&dir).unwrap(), 1);
+    assert_eq!(FilterStore::default().load(&dir).unwrap(), 1);
+
+    // A damaged file fails the load instead of being dropped
+    std::fs::write(dir.join("logins.bloom"), b"garbage").unwrap();
+    assert!(FilterStore::default().load(&dir).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(FilterStore::default().load(&dir).unwrap(), 0);
+}
+
+#[test]
+fn test_store_write_ahead_log() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-wal-test-{}", std::process::id()));
+    let segments = || {
+        std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_str().unwrap().starts_with("wal-")
+            })
+            .count()
+    };
+
+    // Small segments, so that the log rotates
+    let store = FilterStore::default();
+    store.start_log(&dir, 64).unwrap();
+    store.create(spec("logins")).unwrap();
+    store.create(spec("scratch")).unwrap();
+    store.insert("logins", "alice").unwrap();
+    store.insert("scratch", "bob").unwrap();
+    store.clear("scratch").unwrap();
+    store.create(spec("gone")).unwrap();
+    store.delete("gone").unwrap();
+    assert!(segments() > 1);
+
+    // Without any snapshot, a restarted store replays the log
+    let recovered = FilterStore::default();
+    assert_eq!(recovered.load(&dir).unwrap(), 2);
+    assert!(recovered.contains("logins", "alice").unwrap());
+    assert!(!recovered.contains("scratch", "bob").unwrap());
+
+    // A snapshot supersedes the segments written so far
+    store.save(&dir).unwrap();
+    assert_eq!(segments(), 1);
+    store.insert("logins", "carol").unwrap();
+
+    // A record torn by a crash is ignored
+    let last = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "log"))
+        .max()
+        .unwrap();
+    let mut bytes = std::fs::read(&last).unwrap();
+    bytes.extend_from_slice(&[200, 0, 0, 0, 1]);
+    std::fs::write(&last, bytes).unwrap();
+
+    let recovered = FilterStore::default();
+    assert_eq!(recovered.load(&dir).unwrap(), 2);
+    assert!(recovered.contains("logins", "alice").unwrap());
+    assert!(recovered.contains("logins", "carol").unwrap());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_memory_mapped_filters() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-mmap-test-{}", std::process::id()));
+    let mapped = |name: &str| FilterSpec {
+        storage: Storage::Mmap,
+        ..spec(name)
+    };
+
+    // Memory-mapped filters live in the data directory
+    assert!(matches!(
+        FilterStore::default().create(mapped("logins")),
+        Err(Error::InvalidParameters(_))
+    ));
+    let mut store = FilterStore::default();
+    store.data_dir = Some(dir.clone());
+    std::fs::create_dir_all(&dir).unwrap();
+    let info = store.create(mapped("logins")).unwrap();
+    assert_eq!(info.storage, Storage::Mmap);
+    store.create(spec("plain")).unwrap();
+    store.insert("logins", "alice").unwrap();
+    assert!(store.contains("logins", "alice").unwrap());
+    assert_eq!(store.save(&dir).unwrap(), 2);
+    assert!(dir.join("logins.mmap").exists());
+    assert!(!dir.join("logins.bloom").exists());
+
+    // Inserts after the save are in the file already
+    store.insert("logins", "bob").unwrap();
+    drop(store);
+    let mut restarted = FilterStore::default();
+    restarted.data_dir = Some(dir.clone());
+    assert_eq!(restarted.load(&dir).unwrap(), 2);
+    assert!(restarted.contains("logins", "alice").unwrap());
+    assert!(restarted.contains("logins", "bob").unwrap());
+    assert!(!restarted.contains("logins", "carol").unwrap());
+
+    // Replacing keeps the filter mapped, and only with the same parameters
+    let snapshot = restarted.snapshot("plain").unwrap();
+    assert!(restarted
+        .upload("logins", snapshot.clone(), UploadMode::Replace)
+        .is_ok());
+    assert!(!restarted.contains("logins", "alice").unwrap());
+    let other = FilterSnapshot {
+        item_count: 10,
+        ..snapshot
+    };
+    assert!(matches!(
+        restarted.upload("logins", other, UploadMode::Replace),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    // The file of a deleted filter is removed at the next save
+    restarted.delete("logins").unwrap();
+    restarted.save(&dir).unwrap();
+    assert!(!dir.join("logins.mmap").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_memory_budget() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-evicted-test-{}", std::process::id()));
+    // Room for two filters of 9600 bits
+    let mut store = FilterStore::default();
+    store.memory_budget = Some(MemoryBudget {
+        max_bytes: 3000,
+        persist_dir: Some(dir.clone()),
+    });
+    store.create(spec("a")).unwrap();
+    let b = store
+        .create(FilterSpec {
+            ttl_seconds: Some(3600),
+            labels: [("team".to_string(), "fraud".to_string())].into(),
+            ..spec("b")
+        })
+        .unwrap();
+    store
+        .create_group(GroupSpec {
+            name: "both".to_string(),
+            filters: vec!["a".to_string(), "b".to_string()],
+        })
+        .unwrap();
+    store.insert("b", "alice").unwrap();
+    store.contains("a", "alice").unwrap();
+    assert_eq!(store.memory_stats().memory_usage_bytes, 2400);
+
+    // The least recently used filter makes room for a new one
+    store.create(spec("c")).unwrap();
+    let names = |store: &FilterStore| -> Vec<String> {
+        let mut names: Vec<String> = store
+            .list()
+            .into_iter()
+            .filter(|f| !f.evicted)
+            .map(|f| f.name)
+            .collect();
+        names.sort();
+        names
+    };
+    assert_eq!(names(&store), vec!["a", "c"]);
+    assert_eq!(store.list_groups()[0].filters, vec!["a".to_string()]);
+
+    // But is still listed, as evicted, until reloaded
+    let evicted: Vec<_> = store.list().into_iter().filter(|f| f.evicted).collect();
+    assert_eq!(evicted.len(), 1);
+    assert_eq!((&evicted[0].id, evicted[0].memory_usage_bytes), (&b.id, 0));
+    assert_eq!(evicted[0].labels, b.labels);
+    assert!(evicted[0].ttl_remaining_seconds.is_some());
+    let stats = store.memory_stats();
+    assert_eq!(stats.memory_usage_bytes, 2400);
+    assert_eq!(stats.max_memory_bytes, Some(3000));
+    assert_eq!(stats.evictions, 1);
+    assert_eq!(stats.recent_evictions[0].name, "b");
+    assert!(stats.recent_evictions[0].persisted);
+
+    // The evicted filter was saved first
+    let bytes = std::fs::read(dir.join("b.bloom")).unwrap();
+    let snapshot = FilterSnapshot::decode(&bytes).unwrap();
+    assert!(snapshot.filter.contains(b"alice"));
+
+    assert!(dir.join("b.meta").exists());
+
+    // And is reloaded when next used, even by its id, making room in turn, with its metadata and
+    // in its groups
+    let reloaded = store.describe(&b.id).unwrap().0;
+    assert_eq!(store.contains("b", "alice"), Ok(true));
+    assert_eq!(names(&store), vec!["b", "c"]);
+    assert!(!dir.join("b.bloom").exists());
+    assert!(!dir.join("b.meta").exists());
+    assert!(dir.join("a.bloom").exists());
+    assert_eq!(store.list_groups()[0].filters, vec!["b".to_string()]);
+    assert_eq!((&reloaded.id, reloaded.created_at), (&b.id, b.created_at));
+    assert_eq!(reloaded.labels, b.labels);
+    assert!(reloaded.ttl_remaining_seconds.is_some());
+    assert!(!reloaded.evicted);
+    let stats = store.memory_stats();
+    assert_eq!((stats.evictions, stats.reloads), (2, 1));
+    assert!(matches!(
+        store.create(spec("a")),
+        Err(Error::FilterExists(_))
+    ));
+    assert_eq!(names(&store), vec!["a", "b"]);
+
+    // Evicted filters are listed again once the store is loaded
+    let empty = dir.join("empty");
+    std::fs::create_dir_all(&empty).unwrap();
+    let mut restarted = FilterStore::default();
+    restarted.memory_budget = store.memory_budget.clone();
+    restarted.load(&empty).unwrap();
+    let listed: Vec<_> = restarted
+        .list()
+        .into_iter()
+        .map(|f| (f.name, f.evicted))
+        .collect();
+    assert_eq!(listed, vec![("c".to_string(), true)]);
+
+    // Deleting an evicted filter deletes its file too
+    store.delete("c").unwrap();
+    assert!(!dir.join("c.bloom").exists());
+    assert!(matches!(
+        store.contains("c", "alice"),
+        Err(Error::FilterNotFound(_))
+    ));
+
+    // A filter larger than the whole budget is refused
+    let evictions = store.memory_stats().evictions;
+    let large = FilterSpec {
+        item_count: 10_000,
+        ..spec("large")
+    };
+    assert!(matches!(
+        store.create(large),
+        Err(Error::InvalidParameters(_))
+    ));
+    assert_eq!(store.memory_stats().evictions, evictions);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_memory_report() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-report-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut store = FilterStore::default();
+    store.data_dir = Some(dir.clone());
+    store.create(spec("plain")).unwrap();
+    store
+        .create(FilterSpec {
+            storage: Storage::Mmap,
+            ..spec("mapped")
+        })
+        .unwrap();
+    store
+        .create_namespace("payments")
+        .unwrap()
+        .create(FilterSpec {
+            item_count: 2000,
+            ..spec("plain")
+        })
+        .unwrap();
+
+    // Every filter is reported, the largest first, with heap and mapped bytes apart
+    let report = store.memory_report();
+    let filters: Vec<_> = report
+        .filters
+        .iter()
+        .map(|f| (f.namespace.as_deref(), f.name.as_str(), f.storage))
+        .collect();
+    assert_eq!(
+        filters,
+        vec![
+            (Some("payments"), "plain", Storage::Memory),
+            (None, "mapped", Storage::Mmap),
+            (None, "plain", Storage::Memory),
+        ]
+    );
+    assert_eq!(report.filters[1].memory_usage_bytes, 0);
+    assert_eq!(report.filters[1].mapped_bytes, 1200);
+    assert_eq!(report.filters[2].memory_usage_bytes, 1200);
+    assert_eq!(report.filters[2].mapped_bytes, 0);
+    assert_eq!(
+        report.filter_bytes,
+        report.filters[0].memory_usage_bytes + 1200
+    );
+    assert_eq!(report.mapped_bytes, 1200);
+    assert_eq!(report.structure_bytes, 0);
+    assert_eq!(store.describe("mapped").unwrap().0.mapped_bytes, 1200);
+
+    // The other structures are counted apart from the filters
+    store
+        .create_limiter(LimiterSpec {
+            name: "api_calls".to_string(),
+            limit: 2,
+            window_seconds: 10,
+            slots: Some(10),
+            ..Default::default()
+        })
+        .unwrap();
+    let limiter = store.list_limiters()[0].memory_usage_bytes;
+    assert!(limiter > 0);
+    assert_eq!(store.memory_report().structure_bytes, limiter);
+    if cfg!(target_os = "linux") {
+        assert!(report.resident_bytes.unwrap() > 0);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_counting_filters() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-counting-test-{}", std::process::id()));
+    let counting = |name: &str| FilterSpec {
+        kind: FilterKind::Counting,
+        ..spec(name)
+    };
+
+    let store = FilterStore::default();
+    store.start_log(&dir, 1 << 20).unwrap();
+    store.create(counting("sessions")).unwrap();
+    store.insert("sessions", "alice").unwrap();
+    store.insert("sessions", "alice").unwrap();
+    store.insert("sessions", "bob").unwrap();
+    store.save(&dir).unwrap();
+
+    // An item inserted twice stays until it is removed twice
+    assert!(store.remove("sessions", "alice").unwrap());
+    assert!(store.contains("sessions", "alice").unwrap());
+    assert!(store.remove("sessions", "alice").unwrap());
+    assert!(!store.contains("sessions", "alice").unwrap());
+    assert!(!store.remove("sessions", "alice").unwrap());
+
+    // The snapshot keeps the counters, and the log replays the removals exactly once
+    let recovered = FilterStore::default();
+    recovered.load(&dir).unwrap();
+    assert!(!recovered.contains("sessions", "alice").unwrap());
+    assert!(recovered.remove("sessions", "bob").unwrap());
+    assert!(!recovered.contains("sessions", "bob").unwrap());
+
+    let snapshot = store.snapshot("sessions").unwrap();
+    let decoded = FilterSnapshot::decode(&snapshot.encode()).unwrap();
+    assert_eq!(decoded.filter.kind(), FilterKind::Counting);
+    assert_eq!(decoded, snapshot);
+
+    store.create(spec("logins")).unwrap();
+    assert_eq!(
+        store.remove("logins", "alice"),
+        Err(Error::RemovalUnsupported("logins".to_string()))
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_scalable_filters() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-scalable-test-{}", std::process::id()));
+    let scalable = |name: &str| FilterSpec {
+        item_count: 100,
+        kind: FilterKind::Scalable,
+        ..spec(name)
+    };
+    let slices = |store: &FilterStore| store.list()[0].slice_count;
+
+    let store = FilterStore::default();
+    store.create(scalable("urls")).unwrap();
+    assert_eq!(slices(&store), 1);
+    for i in 0..2000 {
+        store.insert("urls", format!("url-{i}")).unwrap();
+    }
+
+    // The filter grew instead of filling up, and keeps about its false positive rate
+    assert!(slices(&store) > 1);
+    assert!((0..2000).all(|i| store.contains("urls", format!("url-{i}")).unwrap()));
+    let false_positives = (0..10_000)
+        .filter(|i| store.contains("urls", format!("other-{i}")).unwrap())
+        .count();
+    assert!(false_positives < 200, "{false_positives} false positives");
+
+    // The slices are saved and loaded with the filter
+    let snapshot = store.snapshot("urls").unwrap();
+    let decoded = FilterSnapshot::decode(&snapshot.encode()).unwrap();
+    assert_eq!(decoded.filter.kind(), FilterKind::Scalable);
+    assert_eq!(decoded, snapshot);
+    store.save(&dir).unwrap();
+    let recovered = FilterStore::default();
+    recovered.load(&dir).unwrap();
+    assert_eq!(slices(&recovered), slices(&store));
+    assert!(recovered.contains("urls", "url-1999").unwrap());
+
+    store.clear("urls").unwrap();
+    assert_eq!(slices(&store), 1);
+    assert!(!store.contains("urls", "url-0").unwrap());
+
+    let fixed = FilterSpec {
+        false_positive_rate: None,
+        hash_count: Some(7),
+        ..scalable("fixed")
+    };
+    assert!(matches!(
+        store.create(fixed),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_filters_acting_once_full() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-full-test-{}", std::process::id()));
+    let on_full = |name: &str, action| FilterSpec {
+        item_count: 100,
+        on_full: Some(action),
+        ..spec(name)
+    };
+
+    let store = FilterStore::default();
+    store.start_log(&dir, 1 << 20).unwrap();
+    let info = store
+        .create(on_full("sessions", FullAction::Rotate))
+        .unwrap();
+    assert_eq!(info.on_full, Some(FullAction::Rotate));
+    assert_eq!(info.rotations, Some(0));
+    for i in 0..1000 {
+        store.insert("sessions", format!("session-{i}")).unwrap();
+    }
+
+    // Rotating filters start over once full, still finding the items of the last generations,
+    // and keep their false positive rate
+    let (info, stats) = store.describe("sessions").unwrap();
+    assert!(info.rotations.unwrap() >= 8, "{:?}", info.rotations);
+    assert!((980..1000).all(|i| store.contains("sessions", format!("session-{i}")).unwrap()));
+    assert!(!(0..50).all(|i| store.contains("sessions", format!("session-{i}")).unwrap()));
+    assert!(stats.fill_ratio <= 0.5);
+    let false_positives = (0..10_000)
+        .filter(|i| store.contains("sessions", format!("other-{i}")).unwrap())
+        .count();
+    assert!(false_positives < 400, "{false_positives} false positives");
+
+    // The generations are saved, and their rotations replayed from the log
+    let snapshot = store.snapshot("sessions").unwrap();
+    assert_eq!(
+        FilterSnapshot::decode(&snapshot.encode()).unwrap(),
+        snapshot
+    );
+    store.save(&dir).unwrap();
+    for i in 1000..1100 {
+        store.insert("sessions", format!("session-{i}")).unwrap();
+    }
+    let recovered = FilterStore::default();
+    recovered.load(&dir).unwrap();
+    assert_eq!(
+        recovered.snapshot("sessions").unwrap(),
+        store.snapshot("sessions").unwrap()
+    );
+
+    // Clearing forgets the previous generation too
+    store.clear("sessions").unwrap();
+    assert!(!store.contains("sessions", "session-1099").unwrap());
+
+    // Scaling filters are scalable ones, and only standard filters act once full
+    let info = store.create(on_full("urls", FullAction::Scale)).unwrap();
+    assert_eq!(info.kind, FilterKind::Scalable);
+    assert_eq!(info.on_full, Some(FullAction::Scale));
+    assert!(store
+        .get_or_create(on_full("urls", FullAction::Scale))
+        .is_ok());
+    assert!(matches!(
+        store.get_or_create(on_full("urls", FullAction::Rotate)),
+        Err(Error::ParameterConflict(_))
+    ));
+    let counting = FilterSpec {
+        kind: FilterKind::Counting,
+        ..on_full("counted", FullAction::Rotate)
+    };
+    let atomic = FilterSpec {
+        storage: Storage::Atomic,
+        ..on_full("hot", FullAction::Rotate)
+    };
+    for spec in [counting, atomic] {
+        assert!(matches!(
+            store.create(spec),
+            Err(Error::InvalidParameters(_))
+        ));
+    }
+    let plain = FilterSpec {
+        item_count: 100,
+        ..spec("plain")
+    };
+    store.create(plain).unwrap();
+    assert!(matches!(
+        store.merge("plain", &["sessions".to_string()]),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_stable_filters() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-stable-test-{}", std::process::id()));
+    let stable = FilterSpec {
+        kind: FilterKind::Stable,
+        ..spec("events")
+    };
+
+    let store = FilterStore::default();
+    store.start_log(&dir, 1 << 20).unwrap();
+    store.create(stable.clone()).unwrap();
+    let info = store.list()[0].stable.unwrap();
+    assert_eq!(info.max, 3);
+    assert!(info.false_positive_rate <= 0.01);
+
+    // However long the stream, the false positive rate settles, and recent items are kept
+    for i in 0..20_000 {
+        store.insert("events", format!("event-{i}")).unwrap();
+    }
+    assert!(store.contains("events", "event-19999").unwrap());
+    let false_positives = (0..10_000)
+        .filter(|i| store.contains("events", format!("other-{i}")).unwrap())
+        .count();
+    assert!(false_positives < 200, "{false_positives} false positives");
+    assert!(!store.contains("events", "event-0").unwrap());
+
+    // The decrements are replayed exactly from the log
+    store.save(&dir).unwrap();
+    for i in 0..500 {
+        store.insert("events", format!("late-{i}")).unwrap();
+    }
+    let recovered = FilterStore::default();
+    recovered.load(&dir).unwrap();
+    assert_eq!(
+        recovered.snapshot("events").unwrap(),
+        store.snapshot("events").unwrap()
+    );
+    let snapshot = store.snapshot("events").unwrap();
+    assert_eq!(
+        FilterSnapshot::decode(&snapshot.encode()).unwrap(),
+        snapshot
+    );
+
+    assert!(matches!(
+        store.remove("events", "late-0"),
+        Err(Error::RemovalUnsupported(_))
+    ));
+    let misplaced = FilterSpec {
+        stable: Some(StableParams::default()),
+        ..spec("plain")
+    };
+    assert!(matches!(
+        store.create(misplaced),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_hash_schemes() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-scheme-test-{}", std::process::id()));
+    let seeded = |name: &str, scheme: HashScheme, seed: u64| FilterSpec {
+        scheme,
+        seed,
+        ..spec(name)
+    };
+
+    // Filters of the same scheme and seed, on different nodes, have the same bits
+    let (node_1, node_2) = (FilterStore::default(), FilterStore::default());
+    for scheme in [HashScheme::Murmur3, HashScheme::Xxhash, HashScheme::Siphash] {
+        let name = format!("{scheme:?}").to_lowercase();
+        node_1.create(seeded(&name, scheme, 42)).unwrap();
+        node_2.create(seeded(&name, scheme, 42)).unwrap();
+        node_1.insert(&name, "alice").unwrap();
+        node_2.insert(&name, "alice").unwrap();
+        let snapshot = node_2.snapshot(&name).unwrap();
+        assert_eq!(snapshot, node_1.snapshot(&name).unwrap());
+        assert_eq!(snapshot.filter.scheme(), scheme);
+
+        node_2.insert(&name, "bob").unwrap();
+        let snapshot = node_2.snapshot(&name).unwrap();
+        node_1.upload(&name, snapshot, UploadMode::Merge).unwrap();
+        assert!(node_1.contains(&name, "bob").unwrap());
+    }
+
+    // Seeds change the bits, and filters of different seeds cannot be merged
+    node_2
+        .create(seeded("other", HashScheme::Xxhash, 7))
+        .unwrap();
+    node_2.insert("other", "alice").unwrap();
+    let other = node_2.snapshot("other").unwrap();
+    assert_ne!(
+        other.filter.bits(),
+        node_1.snapshot("xxhash").unwrap().filter.bits()
+    );
+    assert!(matches!(
+        node_1.upload("xxhash", other, UploadMode::Merge),
+        Err(Error::InvalidParameters(_))
+    ));
+
+    // Schemes that cannot take the seed are rejected
+    for (scheme, seed) in [(HashScheme::Guava, 1), (HashScheme::Murmur3, 1 << 32)] {
+        assert!(matches!(
+            node_1.create(seeded("bad", scheme, seed)),
+            Err(Error::InvalidParameters(_))
+        ));
+    }
+
+    // Seeded filters are saved, logged and mapped with their scheme and seed
+    let mut store = FilterStore::default();
+    store.data_dir = Some(dir.clone());
+    std::fs::create_dir_all(&dir).unwrap();
+    store.start_log(&dir, 1 << 20).unwrap();
+    let scalable = FilterSpec {
+        kind: FilterKind::Scalable,
+        item_count: 10,
+        ..seeded("scalable", HashScheme::Siphash, 3)
+    };
+    store.create(scalable).unwrap();
+    let mapped = FilterSpec {
+        storage: Storage::Mmap,
+        ..seeded("mapped", HashScheme::Native, 5)
+    };
+    store.create(mapped).unwrap();
+    for i in 0..100 {
+        store.insert("scalable", format!("user-{i}")).unwrap();
+    }
+    store.insert("mapped", "alice").unwrap();
+    let snapshot = store.snapshot("scalable").unwrap();
+    assert_eq!(
+        FilterSnapshot::decode(&snapshot.encode()).unwrap(),
+        snapshot
+    );
+    assert_eq!(snapshot.filter.seed(), 3);
+
+    for saved in [false, true] {
+        if saved {
+            store.save(&dir).unwrap();
+        }
+        let mut recovered = FilterStore::default();
+        recovered.data_dir = Some(dir.clone());
+        recovered.load(&dir).unwrap();
+        let hashing = |name: &str| {
+            let list = recovered.list();
+            let info = list.iter().find(|info| info.name == name).unwrap();
+            (info.scheme, info.seed)
+        };
+        assert_eq!(hashing("scalable"), (HashScheme::Siphash, 3));
+        assert_eq!(recovered.snapshot("scalable").unwrap(), snapshot);
+        assert_eq!(hashing("mapped"), (HashScheme::Native, 5));
+        assert!(recovered.contains("mapped", "alice").unwrap());
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_hash_scheme_vectors() {
+    let trace = |scheme: HashScheme, seed: u64, item: &[u8]| {
+        let filter = BloomFilter::new(1000, CreationMode::HashCount(3))
+            .unwrap()
+            .with_hashing(scheme, seed)
+            .unwrap();
+        let trace = filter.trace(item);
+        (trace.hash1, trace.hash2)
+    };
+
+    // MurmurHash3 x64 128, the vectors of Guava's Murmur3_128HashFunctionTest
+    for (seed, item, hash1, hash2) in [
+        (0, "hell", 0x6299_4269_3e10_f867, 0x92db_0b82_baeb_5347),
+        (1, "hello", 0xa78d_dff5_adae_8d10, 0x1289_00ef_2090_0135),
+        (2, "hello ", 0x8a48_6b23_f422_e826, 0xf962_a2c5_8947_765f),
+        (3, "hello w", 0x2ea5_9f46_6f6b_ed8c, 0xc610_990a_cc42_8a17),
+        (4, "hello wo", 0x79f6_305a_386c_572c, 0x4630_5aed_3483_b94e),
+        (5, "hello wor", 0xc221_9d21_3ec1_f1b5, 0xa1d8_e2e0_a527_85bd),
+        (
+            0,
+            "The quick brown fox jumps over the lazy dog",
+            0xe34b_bc7b_bc07_1b6c,
+            0x7a43_3ca9_c49a_9347,
+        ),
+        (
+            0,
+            "The quick brown fox jumps over the lazy cog",
+            0x658c_a970_ff85_269a,
+            0x43fe_e3ea_a68e_5c3e,
+        ),
+    ] {
+        assert_eq!(
+            trace(HashScheme::Murmur3, seed, item.as_bytes()),
+            (hash1, hash2),
+            "murmur3 of {item:?} with seed {seed}"
+        );
+    }
+    // Guava's scheme is Murmur3 with seed 0
+    assert_eq!(
+        trace(HashScheme::Guava, 0, b"hell"),
+        (0x6299_4269_3e10_f867, 0x92db_0b82_baeb_5347)
+    );
+
+    // XXH3 128, the vectors of xxHash's sanity tests: the low half, then the high half
+    let mut buffer = Vec::new();
+    let mut byte = 2_654_435_761u64;
+    for _ in 0..6 {
+        buffer.push((byte >> 56) as u8);
+        byte = byte.wrapping_mul(11_400_714_785_074_694_797);
+    }
+    for (len, hash1, hash2) in [
+        (0, 0x6001_c324_468d_497f, 0x99aa_06d3_0147_98d8),
+        (1, 0xc44b_dff4_074e_ecdb, 0xa6cd_5e93_9200_0f6a),
+        (6, 0x3e70_39bd_da43_cfc6, 0x082a_fe0b_8162_d12a),
+    ] {
+        assert_eq!(
+            trace(HashScheme::Xxhash, 0, &buffer[..len]),
+            (hash1, hash2),
+            "xxh3 of {len} bytes"
+        );
+    }
+
+    // SipHash-2-4 128, the first vector of the reference implementation, keyed 00..0f
+    let mut hasher = SipHasher24::new_with_keys(0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+    hasher.write(b"");
+    assert_eq!(
+        hasher.finish128().as_bytes(),
+        [
+            0xa3, 0x81, 0x7f, 0x04, 0xba, 0x25, 0xa8, 0xe6, 0x6d, 0xf6, 0x72, 0x14, 0xc7, 0x55,
+            0x02, 0x93
+        ]
+    );
+    // The scheme keys SipHash with the seed and zero, and splits it into its halves
+    for (seed, item) in [(0, &b""[..]), (42, b"alice"), (u64::MAX, b"bob")] {
+        let mut hasher = SipHasher24::new_with_keys(seed, 0);
+        hasher.write(item);
+        let hash = hasher.finish128();
+        assert_eq!(trace(HashScheme::Siphash, seed, item), (hash.h1, hash.h2));
+    }
+    assert_eq!(
+        trace(HashScheme::Siphash, 0, b""),
+        (0x7de0_a380_47d7_4950, 0xf4f2_ced4_47ab_0242)
+    );
+}
+
+#[test]
+fn test_store_sketches_are_saved_and_logged() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-sketch-test-{}", std::process::id()));
+    let store = FilterStore::default();
+    store.start_log(&dir, 1 << 20).unwrap();
+    store
+        .create_sketch(SketchSpec {
+            name: "page_views".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+    store.increment_sketch("page_views", "/home", 3).unwrap();
+    store.save(&dir).unwrap();
+    assert_eq!(store.increment_sketch("page_views", "/home", 2).unwrap(), 5);
+
+    // The increments since the snapshot are replayed exactly once
+    let recovered = FilterStore::default();
+    recovered.load(&dir).unwrap();
+    assert_eq!(recovered.estimate_sketch("page_views", "/home").unwrap(), 5);
+    assert_eq!(recovered.list_sketches()[0].total, 5);
+
+    store.delete_sketch("page_views").unwrap();
+    assert_eq!(
+        store.estimate_sketch("page_views", "/home"),
+        Err(Error::SketchNotFound("page_views".to_string()))
+    );
+    assert!(CountMinSketch::new(&SketchSpec {
+        name: "bad".to_string(),
+        depth: Some(0),
+        ..Default::default()
+    })
+    .is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_cardinality_is_saved_and_logged() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-hll-test-{}", std::process::id()));
+    let store = FilterStore::default();
+    store.start_log(&dir, 1 << 20).unwrap();
+    for name in ["visitors_eu", "visitors_us"] {
+        store
+            .create_cardinality(CardinalitySpec {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    for i in 0..100 {
+        store
+            .insert_cardinality("visitors_eu", format!("user-{i}"))
+            .unwrap();
+    }
+    store.save(&dir).unwrap();
+    for i in 50..150 {
+        store
+            .insert_cardinality("visitors_us", format!("user-{i}"))
+            .unwrap();
+    }
+    let merged = store
+        .merge_cardinality("visitors_eu", &["visitors_us".to_string()])
+        .unwrap();
+    assert!((140..=160).contains(&merged), "estimate {merged}");
+    // The merge is replayed as its result, whatever happens to the source afterwards
+    store.delete_cardinality("visitors_us").unwrap();
+
+    let recovered = FilterStore::default();
+    recovered.load(&dir).unwrap();
+    assert_eq!(
+        recovered.estimate_cardinality("visitors_eu").unwrap(),
+        merged
+    );
+    assert_eq!(
+        recovered.estimate_cardinality("visitors_us"),
+        Err(Error::CardinalityNotFound("visitors_us".to_string()))
+    );
+    assert!(HyperLogLog::new(&CardinalitySpec {
+        name: "bad".to_string(),
+        precision: Some(30),
+    })
+    .is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_labels_are_saved_and_logged() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-labels-test-{}", std::process::id()));
+    let store = FilterStore::default();
+    store.start_log(&dir, 1 << 20).unwrap();
+    let labels = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    };
+    store
+        .create(FilterSpec {
+            labels: labels(&[("team", "fraud"), ("env", "prod")]),
+            ..spec("cards")
+        })
+        .unwrap();
+    store.create(spec("emails")).unwrap();
+    store.save(&dir).unwrap();
+
+    // Changed after the save, so only the log has the change
+    let changes = [
+        ("env".to_string(), None),
+        ("tier".to_string(), Some("gold".to_string())),
+    ];
+    let info = store
+        .update_labels("cards", changes.into_iter().collect())
+        .unwrap();
+    assert_eq!(info.labels, labels(&[("team", "fraud"), ("tier", "gold")]));
+    assert!(store
+        .update_labels("emails", [("".to_string(), Some("x".to_string()))].into())
+        .is_err());
+
+    let recovered = FilterStore::default();
+    recovered.load(&dir).unwrap();
+    let cards = recovered.describe("cards").unwrap().0;
+    assert_eq!(cards.labels, labels(&[("team", "fraud"), ("tier", "gold")]));
+    assert!(recovered.describe("emails").unwrap().0.labels.is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_metadata_is_saved() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-metadata-test-{}", std::process::id()));
+    let store = FilterStore::default();
+    store
+        .create(FilterSpec {
+            ttl_seconds: Some(3600),
+            expire_after_idle_seconds: Some(600),
+            ..spec("sessions")
+        })
+        .unwrap();
+    store.create(spec("candidate")).unwrap();
+    store.set_shadow("sessions", "candidate").unwrap();
+    store.clear("sessions").unwrap();
+    store
+        .create_group(GroupSpec {
+            name: "all".to_string(),
+            filters: vec!["sessions".to_string(), "candidate".to_string()],
+        })
+        .unwrap();
+    // Rejects every item
+    #[cfg(feature = "wasm")]
+    store
+        .set_transform(
+            "candidate",
+            br#"(module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) i32.const 0)
+                (func (export "transform") (param i32 i32) (result i64) i64.const -1))"#,
+        )
+        .unwrap();
+    let saved = store.describe("sessions").unwrap().0;
+    store.save(&dir).unwrap();
+
+    // A restarted store gets the filters back as they were, found by their ids
+    let restarted = FilterStore::default();
+    restarted.load(&dir).unwrap();
+    let loaded = restarted.describe(&saved.id).unwrap().0;
+    assert_eq!(loaded.name, "sessions");
+    assert_eq!(loaded.etag(), saved.etag());
+    assert_eq!(loaded.created_at, saved.created_at);
+    assert!(loaded.ttl_remaining_seconds.unwrap() > 3500);
+    let report = restarted.shadow_report("sessions").unwrap();
+    assert_eq!(report.shadow, "candidate");
+    let groups = restarted.list_groups();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].filters, ["sessions", "candidate"]);
+    #[cfg(feature = "wasm")]
+    assert!(matches!(
+        restarted.insert("candidate", "x"),
+        Err(Error::ItemRejected(_))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_namespaces_are_saved_and_logged() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-ns-test-{}", std::process::id()));
+    let store = FilterStore::default();
+    store.start_log(&dir, 1 << 20).unwrap();
+    let payments = store.create_namespace("payments").unwrap();
+    let search = store.create_namespace("search").unwrap();
+    assert!(matches!(
+        store.create_namespace("payments"),
+        Err(Error::NamespaceExists(_))
+    ));
+    for namespace in [&payments, &search] {
+        namespace.create(spec("seen")).unwrap();
+    }
+    store.create(spec("seen")).unwrap();
+    payments.insert("seen", "card-1").unwrap();
+    store.save(&dir).unwrap();
+
+    // Logged after the save, in the log of the namespace
+    payments.insert("seen", "card-2").unwrap();
+    store.delete_namespace("search").unwrap();
+
+    let recovered = FilterStore::default();
+    assert_eq!(recovered.load(&dir).unwrap(), 2);
+    let payments = recovered.namespace("payments").unwrap();
+    assert!(payments.contains("seen", "card-1").unwrap());
+    assert!(payments.contains("seen", "card-2").unwrap());
+    assert!(!recovered.contains("seen", "card-1").unwrap());
+    assert_eq!(
+        recovered.namespace("search").err(),
+        Some(Error::NamespaceNotFound("search".to_string()))
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_api_keys_are_saved_and_logged() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-keys-test-{}", std::process::id()));
+    let store = FilterStore::default();
+    store.start_log(&dir, 1 << 20).unwrap();
+    let (reader, read_secret) = store
+        .create_api_key(KeySpec {
+            scope: KeyScope::Read,
+            ..Default::default()
+        })
+        .unwrap();
+    store.save(&dir).unwrap();
+
+    // Changed after the save, so only the log has the changes
+    let (_, write_secret) = store
+        .create_api_key(KeySpec {
+            description: Some("ingest".to_string()),
+            filters: vec!["events_*".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+    store.revoke_api_key(&reader.id).unwrap();
+    assert_eq!(
+        store.revoke_api_key(&reader.id),
+        Err(Error::ApiKeyNotFound(reader.id.clone()))
+    );
+
+    let recovered = FilterStore::default();
+    recovered.load(&dir).unwrap();
+    assert_eq!(recovered.authenticate(&read_secret), None);
+    let access = recovered.authenticate(&write_secret).unwrap();
+    assert_eq!(access.scope, KeyScope::ReadWrite);
+    assert_eq!(access.filters, ["events_*"]);
+    let keys = recovered.list_api_keys();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].description.as_deref(), Some("ingest"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_store_saturation_alerts() {
+    let store = FilterStore::new();
+    store.create(spec("full")).unwrap();
+    store.create(spec("empty")).unwrap();
+    store.create_namespace("tenant").unwrap();
+    let tenant = store.namespace("tenant").unwrap();
+    tenant.create(spec("full")).unwrap();
+    for i in 0..5000 {
+        let item = format!("user{i}");
+        store.insert("full", &item).unwrap();
+        tenant.insert("full", &item).unwrap();
+    }
+
+    let thresholds = AlertThresholds {
+        fill_ratio: None,
+        false_positive_rate: Some(0.05),
+    };
+    let monitor = SaturationMonitor::new(thresholds, Duration::from_secs(60));
+    let now = Instant::now();
+
+    // 1. Filters beyond the thresholds are reported, in every namespace
+    let alerts = monitor.check(&store, now);
+    let reported: Vec<_> = alerts
+        .iter()
+        .map(|alert| (alert.namespace.as_deref(), alert.filter.as_str()))
+        .collect();
+    assert_eq!(reported, [(None, "full"), (Some("tenant"), "full")]);
+    assert!(alerts[0].false_positive_rate > 0.05);
+    assert_eq!(alerts[0].thresholds, thresholds);
+
+    // 2. Once per cooldown
+    assert!(monitor
+        .check(&store, now + Duration::from_secs(30))
+        .is_empty());
+    assert_eq!(
+        monitor.check(&store, now + Duration::from_secs(61)).len(),
+        2
+    );
+
+    // 3. Against the thresholds of the moment
+    monitor.set_thresholds(AlertThresholds::default());
+    assert!(monitor
+        .check(&store, now + Duration::from_secs(200))
+        .is_empty());
+}
+
+#[test]
+fn test_store_rebuild_from_journal() {
+    let dir = std::env::temp_dir().join(format!("bloomsrv-journal-test-{}", std::process::id()));
+    let mut store = FilterStore::default();
+    store.data_dir = Some(dir.clone());
+    let journaled = |name: &str| FilterSpec {
+        journal: true,
+        ..spec(name)
+    };
+    store.create(journaled("emails")).unwrap();
+    store.create(spec("cards")).unwrap();
+    store.insert("emails", "alice").unwrap();
+    store.insert("emails", "bob").unwrap();
+    store.save(&dir).unwrap();
+
+    // The journal survives a restart, and is appended to afterwards
+    let mut restarted = FilterStore::default();
+    restarted.data_dir = Some(dir.clone());
+    restarted.load(&dir).unwrap();
+    restarted.insert("emails", "carol").unwrap();
+    let mut reports = Vec::new();
+    let info = restarted
+        .rebuild_from_journal("emails", Some(100_000), None, |progress| {
+            reports.push(*progress)
+        })
+        .unwrap();
+    assert_eq!(info.capacity, 100_000);
+    let last = reports.last().unwrap();
+    assert_eq!((last.items, last.new), (3, 3));
+    assert_eq!(last.bytes, last.total_bytes);
+    for item in ["alice", "bob", "carol"] {
+        assert!(restarted.contains("emails", item).unwrap());
+    }
+
+    // Clearing the filter empties its journal
+    restarted.clear("emails").unwrap();
+    restarted.insert("emails", "dave").unwrap();
+    restarted
+        .rebuild_from_journal("emails", Some(1000), None, |_| {})
+        .unwrap();
+    assert!(restarted.contains("emails", "dave").unwrap());
+    assert!(!restarted.contains("emails", "alice").unwrap());
+
+    // Only journaled filters, of a store with a data directory, can be rebuilt so
+    assert!(matches!(
+        restarted.rebuild_from_journal("cards", None, None, |_| {}),
+        Err(Error::InvalidParameters(_))
+    ));
+    assert!(matches!(
+        restarted.rebuild_from_journal("missing", None, None, |_| {}),
+        Err(Error::FilterNotFound(_))
+    ));
+    assert!(restarted
+        .create(FilterSpec {
+            storage: Storage::Mmap,
+            ..journaled("mapped")
+        })
+        .is_err());
+    assert!(FilterStore::default().create(journaled("emails")).is_err());
+
+    restarted.delete("emails").unwrap();
+    assert!(!dir.join("emails.items").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}